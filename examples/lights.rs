@@ -40,13 +40,14 @@ async fn main() -> anyhow::Result<()> {
         .await
         .expect("Unable to fetch adapter list.")
         .into_iter()
-        .nth(0)
+        .next()
         .expect("Unable to find adapters.");
 
     // start scanning for devices
     central.start_scan(ScanFilter::default()).await?;
-    // instead of waiting, you can use central.events() to get a stream which will
-    // notify you of new devices, for an example of that see examples/event_driven_discovery.rs
+    // instead of waiting, you can use central.discover() to get a stream of the devices as they
+    // are found, or central.events() for everything the adapter sees, for an example of that see
+    // examples/event_driven_discovery.rs
     time::sleep(Duration::from_secs(2)).await;
 
     // find the device we're interested in
@@ -78,7 +79,7 @@ async fn main() -> anyhow::Result<()> {
             0xAA,
         ];
         light
-            .write(&cmd_char, &color_cmd, WriteType::WithoutResponse)
+            .write(cmd_char, &color_cmd, WriteType::WithoutResponse)
             .await?;
         time::sleep(Duration::from_millis(200)).await;
     }
@@ -0,0 +1,158 @@
+/*
+ * btleplug Source Code File
+ *
+ * Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+ *
+ * Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+ * for full license information.
+ *
+ * The C interface to btleplug, which is enabled with the `ffi` feature. See the documentation of
+ * the `btleplug::ffi` module for the conventions it follows.
+ */
+
+#ifndef BTLEPLUG_H
+#define BTLEPLUG_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum btleplug_status {
+    BTLEPLUG_OK = 0,
+    BTLEPLUG_INVALID_ARGUMENT = 1,
+    BTLEPLUG_PERMISSION_DENIED = 2,
+    BTLEPLUG_DEVICE_NOT_FOUND = 3,
+    BTLEPLUG_NOT_CONNECTED = 4,
+    BTLEPLUG_NO_SUCH_CHARACTERISTIC = 5,
+    BTLEPLUG_NOT_SUPPORTED = 6,
+    BTLEPLUG_TIMED_OUT = 7,
+    BTLEPLUG_ATT = 8,
+    BTLEPLUG_PANIC = 9,
+    BTLEPLUG_OTHER = 10,
+} btleplug_status;
+
+typedef enum btleplug_event_kind {
+    BTLEPLUG_EVENT_DEVICE_DISCOVERED = 0,
+    BTLEPLUG_EVENT_DEVICE_UPDATED = 1,
+    BTLEPLUG_EVENT_DEVICE_CONNECTED = 2,
+    BTLEPLUG_EVENT_DEVICE_DISCONNECTED = 3,
+    BTLEPLUG_EVENT_MANUFACTURER_DATA_ADVERTISEMENT = 4,
+    BTLEPLUG_EVENT_SERVICE_DATA_ADVERTISEMENT = 5,
+    BTLEPLUG_EVENT_SERVICES_ADVERTISEMENT = 6,
+    BTLEPLUG_EVENT_STATE_UPDATE = 7,
+    BTLEPLUG_EVENT_SERVICES_CHANGED = 8,
+    BTLEPLUG_EVENT_MTU_CHANGED = 9,
+    BTLEPLUG_EVENT_DIRECTED_ADVERTISEMENT = 10,
+} btleplug_event_kind;
+
+/* The values of btleplug_event.state. */
+typedef enum btleplug_adapter_state {
+    BTLEPLUG_STATE_UNKNOWN = 0,
+    BTLEPLUG_STATE_POWERED_ON = 1,
+    BTLEPLUG_STATE_POWERED_OFF = 2,
+    BTLEPLUG_STATE_UNAUTHORIZED = 3,
+    BTLEPLUG_STATE_RESETTING = 4,
+    BTLEPLUG_STATE_UNSUPPORTED = 5,
+} btleplug_adapter_state;
+
+/* An event from an adapter, which is only valid during the callback it is passed to. */
+typedef struct btleplug_event {
+    btleplug_event_kind kind;
+    /* The ID of the peripheral the event is about, or NULL for BTLEPLUG_EVENT_STATE_UPDATE. */
+    const char *id;
+    /* A btleplug_adapter_state, for BTLEPLUG_EVENT_STATE_UPDATE. */
+    int32_t state;
+    /* The new MTU, for BTLEPLUG_EVENT_MTU_CHANGED. */
+    uint16_t mtu;
+} btleplug_event;
+
+typedef struct btleplug_manager btleplug_manager;
+typedef struct btleplug_adapter btleplug_adapter;
+typedef struct btleplug_adapter_list btleplug_adapter_list;
+typedef struct btleplug_peripheral btleplug_peripheral;
+typedef struct btleplug_peripheral_list btleplug_peripheral_list;
+typedef struct btleplug_subscription btleplug_subscription;
+
+/* Callbacks are called on a thread of btleplug's own. */
+typedef void (*btleplug_event_callback)(const btleplug_event *event, void *user_data);
+typedef void (*btleplug_notification_callback)(const uint8_t service_uuid[16],
+                                               const uint8_t characteristic_uuid[16],
+                                               const uint8_t *value, size_t length,
+                                               void *user_data);
+
+/* Errors and memory. */
+char *btleplug_last_error_message(void);
+void btleplug_string_free(char *string);
+void btleplug_bytes_free(uint8_t *data, size_t length);
+
+/* Managers. */
+btleplug_status btleplug_manager_new(btleplug_manager **out_manager);
+void btleplug_manager_free(btleplug_manager *manager);
+btleplug_status btleplug_manager_adapters(const btleplug_manager *manager,
+                                          btleplug_adapter_list **out_adapters);
+
+/* Adapters. */
+size_t btleplug_adapter_list_len(const btleplug_adapter_list *list);
+btleplug_adapter *btleplug_adapter_list_get(const btleplug_adapter_list *list, size_t index);
+void btleplug_adapter_list_free(btleplug_adapter_list *list);
+void btleplug_adapter_free(btleplug_adapter *adapter);
+btleplug_status btleplug_adapter_start_scan(const btleplug_adapter *adapter,
+                                            const uint8_t (*services)[16], size_t count);
+btleplug_status btleplug_adapter_stop_scan(const btleplug_adapter *adapter);
+btleplug_status btleplug_adapter_on_event(const btleplug_adapter *adapter,
+                                          btleplug_event_callback callback, void *user_data,
+                                          btleplug_subscription **out_subscription);
+btleplug_status btleplug_adapter_peripherals(const btleplug_adapter *adapter,
+                                             btleplug_peripheral_list **out_peripherals);
+btleplug_status btleplug_adapter_peripheral(const btleplug_adapter *adapter, const char *id,
+                                            btleplug_peripheral **out_peripheral);
+
+/* Peripherals. */
+size_t btleplug_peripheral_list_len(const btleplug_peripheral_list *list);
+btleplug_peripheral *btleplug_peripheral_list_get(const btleplug_peripheral_list *list,
+                                                  size_t index);
+void btleplug_peripheral_list_free(btleplug_peripheral_list *list);
+void btleplug_peripheral_free(btleplug_peripheral *peripheral);
+char *btleplug_peripheral_id(const btleplug_peripheral *peripheral);
+btleplug_status btleplug_peripheral_address(const btleplug_peripheral *peripheral,
+                                            uint8_t out_address[6]);
+btleplug_status btleplug_peripheral_local_name(const btleplug_peripheral *peripheral,
+                                               char **out_name);
+btleplug_status btleplug_peripheral_is_connected(const btleplug_peripheral *peripheral,
+                                                 bool *out_connected);
+btleplug_status btleplug_peripheral_connect(const btleplug_peripheral *peripheral);
+btleplug_status btleplug_peripheral_disconnect(const btleplug_peripheral *peripheral);
+btleplug_status btleplug_peripheral_discover_services(const btleplug_peripheral *peripheral);
+btleplug_status btleplug_peripheral_read(const btleplug_peripheral *peripheral,
+                                         const uint8_t service_uuid[16],
+                                         const uint8_t characteristic_uuid[16],
+                                         uint8_t **out_value, size_t *out_length);
+btleplug_status btleplug_peripheral_write(const btleplug_peripheral *peripheral,
+                                          const uint8_t service_uuid[16],
+                                          const uint8_t characteristic_uuid[16],
+                                          const uint8_t *value, size_t length,
+                                          bool with_response);
+btleplug_status btleplug_peripheral_subscribe(const btleplug_peripheral *peripheral,
+                                              const uint8_t service_uuid[16],
+                                              const uint8_t characteristic_uuid[16]);
+btleplug_status btleplug_peripheral_unsubscribe(const btleplug_peripheral *peripheral,
+                                                const uint8_t service_uuid[16],
+                                                const uint8_t characteristic_uuid[16]);
+btleplug_status btleplug_peripheral_on_notification(const btleplug_peripheral *peripheral,
+                                                    btleplug_notification_callback callback,
+                                                    void *user_data,
+                                                    btleplug_subscription **out_subscription);
+
+/* Subscriptions. Once this returns the callback won't be called again. Called from within the
+ * callback, it isn't called again once the callback returns. */
+void btleplug_subscription_free(btleplug_subscription *subscription);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* BTLEPLUG_H */
@@ -0,0 +1,381 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Advertising from the adapter itself, for a system acting as a beacon.
+//!
+//! An [`Advertiser`] says what to advertise, and [`Central::start_advertising`] broadcasts it
+//! until the [`Advertising`] it returns is dropped. Advertisements are broadcast, so other devices
+//! can't connect through them.
+//!
+//! ```no_run
+//! use btleplug::api::advertiser::Advertiser;
+//! use btleplug::api::Central;
+//! use uuid::Uuid;
+//!
+//! async fn beacon(central: &impl Central) -> btleplug::Result<()> {
+//!     let uuid = Uuid::parse_str("e2c56db5-dffb-48d2-b060-d0f5a71096e0").unwrap();
+//!     let _advertising = central
+//!         .start_advertising(Advertiser::ibeacon(uuid, 1, 2, -59))
+//!         .await?;
+//!     // Advertise for as long as `_advertising` is around.
+//!     futures::future::pending::<()>().await;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Platforms restrict what may be advertised. With BlueZ and the `hci` backend, anything which
+//! fits in a legacy advertisement can be, including iBeacons and Eddystone frames. Windows doesn't let applications advertise a local name.
+//! CoreBluetooth only advertises a local name and service UUIDs, and refuses Apple's manufacturer
+//! data, so iBeacons have to be advertised with Core Location's `CLBeaconRegion` there instead;
+//! btleplug doesn't advertise on macOS and iOS at all.
+//!
+//! [`Central::start_advertising`]: super::Central::start_advertising
+
+use super::beacon::{
+    Eddystone, EddystoneUid, EddystoneUrl, IBeacon, APPLE_COMPANY_ID, EDDYSTONE_SERVICE_UUID,
+};
+use super::bleuuid::BleUuid;
+use std::collections::BTreeMap;
+use std::fmt::{self, Debug, Formatter};
+use thiserror::Error;
+use uuid::Uuid;
+
+const AD_COMPLETE_16_BIT_SERVICES: u8 = 0x03;
+const AD_COMPLETE_128_BIT_SERVICES: u8 = 0x07;
+const AD_COMPLETE_LOCAL_NAME: u8 = 0x09;
+const AD_SERVICE_DATA_16_BIT: u8 = 0x16;
+const AD_SERVICE_DATA_128_BIT: u8 = 0x21;
+const AD_MANUFACTURER_DATA: u8 = 0xff;
+
+/// The most bytes of AD structures a legacy advertisement holds.
+const MAX_LEGACY_LEN: usize = 31;
+
+/// The length of the flags AD structure, which the platform adds to advertisements itself.
+const FLAGS_LEN: usize = 3;
+
+/// Why an advertisement couldn't be started, returned within [`Error::Other`](crate::Error::Other).
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AdvertisingError {
+    /// The advertisement doesn't fit in a legacy advertisement, along with the flags the platform
+    /// adds to it.
+    #[error("Advertisement is {len} bytes long, but at most {max} fit")]
+    TooLong { len: usize, max: usize },
+    /// The TX power of an Eddystone frame is outside the -100 to 20 dBm the specification allows.
+    #[error("Eddystone TX power {0} dBm is out of range")]
+    TxPowerOutOfRange(i8),
+    /// The URL doesn't start with a scheme Eddystone-URL supports, contains characters other than
+    /// printable ASCII, or is too long once encoded.
+    #[error("URL {0:?} can't be encoded as an Eddystone-URL frame")]
+    InvalidUrl(String),
+}
+
+impl From<AdvertisingError> for crate::Error {
+    fn from(error: AdvertisingError) -> Self {
+        crate::Error::Other(Box::new(error))
+    }
+}
+
+/// What [`Central::start_advertising`](super::Central::start_advertising) advertises.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Advertiser {
+    /// The UUIDs of the services to advertise, as a complete list.
+    pub services: Vec<Uuid>,
+    /// Service data, by service UUID.
+    pub service_data: BTreeMap<Uuid, Vec<u8>>,
+    /// Manufacturer data, by company identifier.
+    pub manufacturer_data: BTreeMap<u16, Vec<u8>>,
+    pub local_name: Option<String>,
+}
+
+impl Advertiser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Advertises an [iBeacon](IBeacon), as Apple's manufacturer data.
+    pub fn ibeacon(uuid: Uuid, major: u16, minor: u16, measured_power: i8) -> Self {
+        let ibeacon = IBeacon {
+            uuid,
+            major,
+            minor,
+            measured_power,
+        };
+        Self::new().with_manufacturer_data(APPLE_COMPANY_ID, ibeacon.to_manufacturer_data())
+    }
+
+    /// Advertises an [Eddystone-UID](EddystoneUid) frame. `tx_power` is the calibrated TX power
+    /// at 0 metres, in dBm.
+    pub fn eddystone_uid(
+        namespace: [u8; 10],
+        instance: [u8; 6],
+        tx_power: i8,
+    ) -> crate::Result<Self> {
+        Self::eddystone(&Eddystone::Uid(EddystoneUid {
+            tx_power,
+            namespace,
+            instance,
+        }))
+    }
+
+    /// Advertises an [Eddystone-URL](EddystoneUrl) frame. `tx_power` is the calibrated TX power
+    /// at 0 metres, in dBm.
+    pub fn eddystone_url(url: &str, tx_power: i8) -> crate::Result<Self> {
+        Self::eddystone(&Eddystone::Url(EddystoneUrl {
+            tx_power,
+            url: url.to_string(),
+        }))
+    }
+
+    /// Advertises an Eddystone frame as service data for [`EDDYSTONE_SERVICE_UUID`], along with
+    /// the service UUID itself, as the Eddystone specification requires. Fails if the frame's TX
+    /// power is out of range, or it is a URL which can't be encoded.
+    pub fn eddystone(frame: &Eddystone) -> crate::Result<Self> {
+        let tx_power = match frame {
+            Eddystone::Uid(uid) => Some(uid.tx_power),
+            Eddystone::Url(url) => Some(url.tx_power),
+            Eddystone::Eid(eid) => Some(eid.tx_power),
+            Eddystone::Tlm(_) => None,
+        };
+        if let Some(tx_power) = tx_power.filter(|tx_power| !(-100..=20).contains(tx_power)) {
+            return Err(AdvertisingError::TxPowerOutOfRange(tx_power).into());
+        }
+        let data = frame.to_service_data().ok_or_else(|| {
+            // Only URLs can fail to encode.
+            let url = match frame {
+                Eddystone::Url(url) => url.url.clone(),
+                _ => String::new(),
+            };
+            AdvertisingError::InvalidUrl(url)
+        })?;
+        Ok(Self::new()
+            .with_service(EDDYSTONE_SERVICE_UUID)
+            .with_service_data(EDDYSTONE_SERVICE_UUID, data))
+    }
+
+    pub fn with_service(mut self, uuid: Uuid) -> Self {
+        self.services.push(uuid);
+        self
+    }
+
+    pub fn with_service_data(mut self, uuid: Uuid, data: Vec<u8>) -> Self {
+        self.service_data.insert(uuid, data);
+        self
+    }
+
+    pub fn with_manufacturer_data(mut self, company_id: u16, data: Vec<u8>) -> Self {
+        self.manufacturer_data.insert(company_id, data);
+        self
+    }
+
+    pub fn with_local_name(mut self, name: impl Into<String>) -> Self {
+        self.local_name = Some(name.into());
+        self
+    }
+
+    /// Encodes the advertisement as AD structures, without the flags, which the platform adds
+    /// itself. Fails if they don't fit in a legacy advertisement along with the flags.
+    // Only used by some backends, so turn off deadcode so we don't get warnings on the other platforms.
+    #[allow(dead_code)]
+    pub(crate) fn to_ad_structures(&self) -> Result<Vec<u8>, AdvertisingError> {
+        let mut data = Vec::new();
+        let mut push = |ad_type: u8, parts: &[&[u8]]| {
+            let len: usize = parts.iter().map(|part| part.len()).sum();
+            data.push((len + 1) as u8);
+            data.push(ad_type);
+            for part in parts {
+                data.extend_from_slice(part);
+            }
+        };
+        let (short, long): (Vec<&Uuid>, Vec<&Uuid>) = self
+            .services
+            .iter()
+            .partition(|uuid| uuid.to_ble_u16().is_some());
+        if !short.is_empty() {
+            let uuids: Vec<u8> = short
+                .iter()
+                .flat_map(|uuid| uuid.to_ble_u16().unwrap().to_le_bytes())
+                .collect();
+            push(AD_COMPLETE_16_BIT_SERVICES, &[&uuids]);
+        }
+        if !long.is_empty() {
+            let uuids: Vec<u8> = long.iter().flat_map(|uuid| uuid_bytes(uuid)).collect();
+            push(AD_COMPLETE_128_BIT_SERVICES, &[&uuids]);
+        }
+        for (uuid, service_data) in &self.service_data {
+            match uuid.to_ble_u16() {
+                Some(short) => push(
+                    AD_SERVICE_DATA_16_BIT,
+                    &[&short.to_le_bytes(), service_data],
+                ),
+                None => push(AD_SERVICE_DATA_128_BIT, &[&uuid_bytes(uuid), service_data]),
+            }
+        }
+        for (company_id, manufacturer_data) in &self.manufacturer_data {
+            push(
+                AD_MANUFACTURER_DATA,
+                &[&company_id.to_le_bytes(), manufacturer_data],
+            );
+        }
+        if let Some(name) = &self.local_name {
+            push(AD_COMPLETE_LOCAL_NAME, &[name.as_bytes()]);
+        }
+        let max = MAX_LEGACY_LEN - FLAGS_LEN;
+        if data.len() > max {
+            return Err(AdvertisingError::TooLong {
+                len: data.len(),
+                max,
+            });
+        }
+        Ok(data)
+    }
+}
+
+/// The bytes of a 128-bit UUID in the little-endian order of AD structures.
+fn uuid_bytes(uuid: &Uuid) -> [u8; 16] {
+    let mut bytes = *uuid.as_bytes();
+    bytes.reverse();
+    bytes
+}
+
+/// An advertisement started with
+/// [`Central::start_advertising`](super::Central::start_advertising), which stops when this is
+/// dropped.
+#[must_use = "advertising stops when this is dropped"]
+pub struct Advertising {
+    stop: Option<Box<dyn FnOnce() + Send + Sync>>,
+}
+
+impl Advertising {
+    /// Returns an advertisement which calls `stop` when it is dropped.
+    #[allow(dead_code)]
+    pub(crate) fn new(stop: impl FnOnce() + Send + Sync + 'static) -> Self {
+        Advertising {
+            stop: Some(Box::new(stop)),
+        }
+    }
+}
+
+impl Debug for Advertising {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Advertising").finish_non_exhaustive()
+    }
+}
+
+impl Drop for Advertising {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            stop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::bleuuid::uuid_from_u16;
+
+    #[test]
+    fn ibeacon() {
+        let uuid = Uuid::parse_str("e2c56db5-dffb-48d2-b060-d0f5a71096e0").unwrap();
+        let data = Advertiser::ibeacon(uuid, 1, 2, -59)
+            .to_ad_structures()
+            .unwrap();
+        let ibeacon = IBeacon {
+            uuid,
+            major: 1,
+            minor: 2,
+            measured_power: -59,
+        };
+        // The iBeacon's own advertising data, less the flags.
+        assert_eq!(data, ibeacon.to_advertising_data()[FLAGS_LEN..]);
+    }
+
+    #[test]
+    fn eddystone() {
+        let uid = EddystoneUid {
+            tx_power: -20,
+            namespace: [0x01; 10],
+            instance: [0x02; 6],
+        };
+        let data = Advertiser::eddystone_uid(uid.namespace, uid.instance, uid.tx_power)
+            .unwrap()
+            .to_ad_structures()
+            .unwrap();
+        // The frame's own advertising data, less the flags.
+        let expected = Eddystone::Uid(uid).to_advertising_data().unwrap();
+        assert_eq!(data, expected[FLAGS_LEN..]);
+
+        let data = Advertiser::eddystone_url("https://example.com/", -20)
+            .unwrap()
+            .to_ad_structures()
+            .unwrap();
+        let expected = Eddystone::Url(EddystoneUrl {
+            tx_power: -20,
+            url: "https://example.com/".to_string(),
+        })
+        .to_advertising_data()
+        .unwrap();
+        assert_eq!(data, expected[FLAGS_LEN..]);
+    }
+
+    #[test]
+    fn invalid_eddystone() {
+        let error = |result: crate::Result<Advertiser>| match result {
+            Err(crate::Error::Other(error)) => error.downcast_ref::<AdvertisingError>().cloned(),
+            _ => None,
+        };
+        assert_eq!(
+            error(Advertiser::eddystone_url("ftp://example.com/", -20)),
+            Some(AdvertisingError::InvalidUrl(
+                "ftp://example.com/".to_string()
+            ))
+        );
+        assert_eq!(
+            error(Advertiser::eddystone_url(
+                "https://a-very-long-host-name.example/",
+                -20
+            )),
+            Some(AdvertisingError::InvalidUrl(
+                "https://a-very-long-host-name.example/".to_string()
+            ))
+        );
+        assert_eq!(
+            error(Advertiser::eddystone_uid([0; 10], [0; 6], 21)),
+            Some(AdvertisingError::TxPowerOutOfRange(21))
+        );
+    }
+
+    #[test]
+    fn ad_structures() {
+        let long = Uuid::parse_str("6e400001-b5a3-f393-e0a9-e50e24dcca9e").unwrap();
+        let advertiser = Advertiser::new()
+            .with_service(uuid_from_u16(0x180f))
+            .with_service(long)
+            .with_service_data(uuid_from_u16(0x180f), vec![0x64]);
+        let mut expected = vec![0x03, 0x03, 0x0f, 0x18, 0x11, 0x07];
+        expected.extend_from_slice(&uuid_bytes(&long));
+        expected.extend_from_slice(&[0x04, 0x16, 0x0f, 0x18, 0x64]);
+        assert_eq!(advertiser.to_ad_structures().unwrap(), expected);
+
+        let advertiser = Advertiser::new().with_local_name("Tag");
+        assert_eq!(
+            advertiser.to_ad_structures().unwrap(),
+            [0x04, 0x09, b'T', b'a', b'g']
+        );
+    }
+
+    #[test]
+    fn too_long() {
+        let advertiser = Advertiser::new().with_local_name("A name far too long to advertise");
+        assert_eq!(
+            advertiser.to_ad_structures(),
+            Err(AdvertisingError::TooLong { len: 34, max: 28 })
+        );
+    }
+}
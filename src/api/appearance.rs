@@ -0,0 +1,287 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Decoding of the GAP Appearance value, which describes the external appearance of a device.
+//!
+//! Values are taken from section 2.6 of the Bluetooth
+//! [Assigned Numbers](https://www.bluetooth.com/specifications/assigned-numbers/) document. An
+//! appearance is made up of a 10-bit category and a 6-bit subcategory.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use serde_cr as serde;
+
+macro_rules! appearances {
+    ($($(#[$meta:meta])* $name:ident = $value:expr,)*) => {
+        /// The appearance of a device, as advertised in its GAP Appearance AD type or
+        /// characteristic.
+        ///
+        /// Values which aren't one of the named variants are kept in [`Appearance::Other`], so the
+        /// raw value is always available through [`Appearance::raw`].
+        #[cfg_attr(
+            feature = "serde",
+            derive(Serialize, Deserialize),
+            serde(crate = "serde_cr")
+        )]
+        #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+        pub enum Appearance {
+            $($(#[$meta])* $name,)*
+            /// An appearance value without a named variant.
+            Other(u16),
+        }
+
+        impl Appearance {
+            /// Returns the raw 16-bit appearance value.
+            pub fn raw(&self) -> u16 {
+                match self {
+                    $(Appearance::$name => $value,)*
+                    Appearance::Other(value) => *value,
+                }
+            }
+        }
+
+        impl From<u16> for Appearance {
+            fn from(value: u16) -> Self {
+                match value {
+                    $($value => Appearance::$name,)*
+                    _ => Appearance::Other(value),
+                }
+            }
+        }
+    };
+}
+
+appearances! {
+    Unknown = 0x0000,
+    GenericPhone = 0x0040,
+    GenericComputer = 0x0080,
+    GenericWatch = 0x00C0,
+    SportsWatch = 0x00C1,
+    GenericClock = 0x0100,
+    GenericDisplay = 0x0140,
+    GenericRemoteControl = 0x0180,
+    GenericEyeGlasses = 0x01C0,
+    GenericTag = 0x0200,
+    GenericKeyring = 0x0240,
+    GenericMediaPlayer = 0x0280,
+    GenericBarcodeScanner = 0x02C0,
+    GenericThermometer = 0x0300,
+    EarThermometer = 0x0301,
+    GenericHeartRateSensor = 0x0340,
+    HeartRateBelt = 0x0341,
+    GenericBloodPressure = 0x0380,
+    ArmBloodPressure = 0x0381,
+    WristBloodPressure = 0x0382,
+    GenericHumanInterfaceDevice = 0x03C0,
+    Keyboard = 0x03C1,
+    Mouse = 0x03C2,
+    Joystick = 0x03C3,
+    Gamepad = 0x03C4,
+    DigitizerTablet = 0x03C5,
+    CardReader = 0x03C6,
+    DigitalPen = 0x03C7,
+    HidBarcodeScanner = 0x03C8,
+    GenericGlucoseMeter = 0x0400,
+    GenericRunningWalkingSensor = 0x0440,
+    InShoeRunningWalkingSensor = 0x0441,
+    OnShoeRunningWalkingSensor = 0x0442,
+    OnHipRunningWalkingSensor = 0x0443,
+    GenericCycling = 0x0480,
+    CyclingComputer = 0x0481,
+    CyclingSpeedSensor = 0x0482,
+    CyclingCadenceSensor = 0x0483,
+    CyclingPowerSensor = 0x0484,
+    CyclingSpeedAndCadenceSensor = 0x0485,
+    GenericPulseOximeter = 0x0C40,
+    FingertipPulseOximeter = 0x0C41,
+    WristWornPulseOximeter = 0x0C42,
+    GenericWeightScale = 0x0C80,
+    GenericOutdoorSportsActivity = 0x1440,
+    LocationDisplay = 0x1441,
+    LocationAndNavigationDisplay = 0x1442,
+    LocationPod = 0x1443,
+    LocationAndNavigationPod = 0x1444,
+}
+
+impl From<Appearance> for u16 {
+    fn from(appearance: Appearance) -> Self {
+        appearance.raw()
+    }
+}
+
+impl Appearance {
+    /// Returns the category of this appearance.
+    pub fn category(&self) -> AppearanceCategory {
+        AppearanceCategory::from_u16(self.raw() >> 6)
+    }
+
+    /// Returns the 6-bit subcategory of this appearance. 0 is the generic subcategory.
+    pub fn subcategory(&self) -> u8 {
+        (self.raw() & 0x3F) as u8
+    }
+}
+
+/// The category of a GAP Appearance value.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum AppearanceCategory {
+    Unknown,
+    Phone,
+    Computer,
+    Watch,
+    Clock,
+    Display,
+    RemoteControl,
+    EyeGlasses,
+    Tag,
+    Keyring,
+    MediaPlayer,
+    BarcodeScanner,
+    Thermometer,
+    HeartRateSensor,
+    BloodPressure,
+    HumanInterfaceDevice,
+    GlucoseMeter,
+    RunningWalkingSensor,
+    Cycling,
+    ControlDevice,
+    NetworkDevice,
+    Sensor,
+    LightFixtures,
+    Fan,
+    Hvac,
+    AirConditioning,
+    Humidifier,
+    Heating,
+    AccessControl,
+    MotorizedDevice,
+    PowerDevice,
+    LightSource,
+    WindowCovering,
+    AudioSink,
+    AudioSource,
+    MotorizedVehicle,
+    DomesticAppliance,
+    WearableAudioDevice,
+    Aircraft,
+    AvEquipment,
+    DisplayEquipment,
+    HearingAid,
+    Gaming,
+    Signage,
+    PulseOximeter,
+    WeightScale,
+    PersonalMobilityDevice,
+    ContinuousGlucoseMonitor,
+    InsulinPump,
+    MedicationDelivery,
+    Spirometer,
+    OutdoorSportsActivity,
+    /// A category not known to this version of btleplug. Contains the 10-bit category number.
+    Other(u16),
+}
+
+impl AppearanceCategory {
+    fn from_u16(category: u16) -> Self {
+        use AppearanceCategory::*;
+        match category {
+            0x000 => Unknown,
+            0x001 => Phone,
+            0x002 => Computer,
+            0x003 => Watch,
+            0x004 => Clock,
+            0x005 => Display,
+            0x006 => RemoteControl,
+            0x007 => EyeGlasses,
+            0x008 => Tag,
+            0x009 => Keyring,
+            0x00A => MediaPlayer,
+            0x00B => BarcodeScanner,
+            0x00C => Thermometer,
+            0x00D => HeartRateSensor,
+            0x00E => BloodPressure,
+            0x00F => HumanInterfaceDevice,
+            0x010 => GlucoseMeter,
+            0x011 => RunningWalkingSensor,
+            0x012 => Cycling,
+            0x013 => ControlDevice,
+            0x014 => NetworkDevice,
+            0x015 => Sensor,
+            0x016 => LightFixtures,
+            0x017 => Fan,
+            0x018 => Hvac,
+            0x019 => AirConditioning,
+            0x01A => Humidifier,
+            0x01B => Heating,
+            0x01C => AccessControl,
+            0x01D => MotorizedDevice,
+            0x01E => PowerDevice,
+            0x01F => LightSource,
+            0x020 => WindowCovering,
+            0x021 => AudioSink,
+            0x022 => AudioSource,
+            0x023 => MotorizedVehicle,
+            0x024 => DomesticAppliance,
+            0x025 => WearableAudioDevice,
+            0x026 => Aircraft,
+            0x027 => AvEquipment,
+            0x028 => DisplayEquipment,
+            0x029 => HearingAid,
+            0x02A => Gaming,
+            0x02B => Signage,
+            0x031 => PulseOximeter,
+            0x032 => WeightScale,
+            0x033 => PersonalMobilityDevice,
+            0x034 => ContinuousGlucoseMonitor,
+            0x035 => InsulinPump,
+            0x036 => MedicationDelivery,
+            0x037 => Spirometer,
+            0x051 => OutdoorSportsActivity,
+            other => Other(other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_appearance() {
+        let appearance = Appearance::from(0x03C1);
+        assert_eq!(appearance, Appearance::Keyboard);
+        assert_eq!(
+            appearance.category(),
+            AppearanceCategory::HumanInterfaceDevice
+        );
+        assert_eq!(appearance.subcategory(), 1);
+        assert_eq!(appearance.raw(), 0x03C1);
+    }
+
+    #[test]
+    fn unnamed_appearance_keeps_raw_value() {
+        // Light Source / LED lamp.
+        let appearance = Appearance::from(0x07C2);
+        assert_eq!(appearance, Appearance::Other(0x07C2));
+        assert_eq!(appearance.category(), AppearanceCategory::LightSource);
+        assert_eq!(appearance.subcategory(), 2);
+        assert_eq!(u16::from(appearance), 0x07C2);
+    }
+
+    #[test]
+    fn unknown_category() {
+        assert_eq!(
+            Appearance::from(0xFFC0).category(),
+            AppearanceCategory::Other(0x3FF)
+        );
+    }
+}
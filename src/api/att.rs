@@ -0,0 +1,168 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+/// An error code returned by a device in an ATT Error Response, as defined in the Core
+/// Specification, Vol 3, Part F, 3.4.1.1, and the Core Specification Supplement, Part B.
+#[derive(Debug, thiserror::Error, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AttError {
+    #[error("Invalid handle")]
+    InvalidHandle,
+    #[error("Read not permitted")]
+    ReadNotPermitted,
+    #[error("Write not permitted")]
+    WriteNotPermitted,
+    #[error("Invalid PDU")]
+    InvalidPdu,
+    /// The attribute requires an authenticated link, so the device must be paired first.
+    #[error("Insufficient authentication")]
+    InsufficientAuthentication,
+    #[error("Request not supported")]
+    RequestNotSupported,
+    #[error("Invalid offset")]
+    InvalidOffset,
+    #[error("Insufficient authorization")]
+    InsufficientAuthorization,
+    #[error("Prepare queue full")]
+    PrepareQueueFull,
+    #[error("Attribute not found")]
+    AttributeNotFound,
+    #[error("Attribute not long")]
+    AttributeNotLong,
+    #[error("Insufficient encryption key size")]
+    InsufficientEncryptionKeySize,
+    #[error("Invalid attribute value length")]
+    InvalidAttributeValueLength,
+    #[error("Unlikely error")]
+    UnlikelyError,
+    /// The attribute requires an encrypted link, so the device must be paired first.
+    #[error("Insufficient encryption")]
+    InsufficientEncryption,
+    #[error("Unsupported group type")]
+    UnsupportedGroupType,
+    #[error("Insufficient resources")]
+    InsufficientResources,
+    #[error("Database out of sync")]
+    DatabaseOutOfSync,
+    #[error("Value not allowed")]
+    ValueNotAllowed,
+    /// An error defined by the application on the device, in the range 0x80 to 0x9F.
+    #[error("Application error {0:#04x}")]
+    Application(u8),
+    #[error("Write request rejected")]
+    WriteRequestRejected,
+    #[error("Client characteristic configuration descriptor improperly configured")]
+    CccdImproperlyConfigured,
+    #[error("Procedure already in progress")]
+    ProcedureAlreadyInProgress,
+    #[error("Out of range")]
+    OutOfRange,
+    /// A reserved or profile-specific error code.
+    #[error("ATT error {0:#04x}")]
+    Other(u8),
+}
+
+impl AttError {
+    /// Returns the error code which is sent over the air.
+    pub fn code(&self) -> u8 {
+        match self {
+            AttError::InvalidHandle => 0x01,
+            AttError::ReadNotPermitted => 0x02,
+            AttError::WriteNotPermitted => 0x03,
+            AttError::InvalidPdu => 0x04,
+            AttError::InsufficientAuthentication => 0x05,
+            AttError::RequestNotSupported => 0x06,
+            AttError::InvalidOffset => 0x07,
+            AttError::InsufficientAuthorization => 0x08,
+            AttError::PrepareQueueFull => 0x09,
+            AttError::AttributeNotFound => 0x0A,
+            AttError::AttributeNotLong => 0x0B,
+            AttError::InsufficientEncryptionKeySize => 0x0C,
+            AttError::InvalidAttributeValueLength => 0x0D,
+            AttError::UnlikelyError => 0x0E,
+            AttError::InsufficientEncryption => 0x0F,
+            AttError::UnsupportedGroupType => 0x10,
+            AttError::InsufficientResources => 0x11,
+            AttError::DatabaseOutOfSync => 0x12,
+            AttError::ValueNotAllowed => 0x13,
+            AttError::WriteRequestRejected => 0xFC,
+            AttError::CccdImproperlyConfigured => 0xFD,
+            AttError::ProcedureAlreadyInProgress => 0xFE,
+            AttError::OutOfRange => 0xFF,
+            AttError::Application(code) | AttError::Other(code) => *code,
+        }
+    }
+
+    /// Returns whether the operation may succeed once the device is paired, because the link
+    /// isn't authenticated or encrypted enough.
+    pub fn requires_pairing(&self) -> bool {
+        matches!(
+            self,
+            AttError::InsufficientAuthentication
+                | AttError::InsufficientEncryption
+                | AttError::InsufficientEncryptionKeySize
+        )
+    }
+}
+
+impl From<u8> for AttError {
+    fn from(code: u8) -> Self {
+        match code {
+            0x01 => AttError::InvalidHandle,
+            0x02 => AttError::ReadNotPermitted,
+            0x03 => AttError::WriteNotPermitted,
+            0x04 => AttError::InvalidPdu,
+            0x05 => AttError::InsufficientAuthentication,
+            0x06 => AttError::RequestNotSupported,
+            0x07 => AttError::InvalidOffset,
+            0x08 => AttError::InsufficientAuthorization,
+            0x09 => AttError::PrepareQueueFull,
+            0x0A => AttError::AttributeNotFound,
+            0x0B => AttError::AttributeNotLong,
+            0x0C => AttError::InsufficientEncryptionKeySize,
+            0x0D => AttError::InvalidAttributeValueLength,
+            0x0E => AttError::UnlikelyError,
+            0x0F => AttError::InsufficientEncryption,
+            0x10 => AttError::UnsupportedGroupType,
+            0x11 => AttError::InsufficientResources,
+            0x12 => AttError::DatabaseOutOfSync,
+            0x13 => AttError::ValueNotAllowed,
+            0x80..=0x9F => AttError::Application(code),
+            0xFC => AttError::WriteRequestRejected,
+            0xFD => AttError::CccdImproperlyConfigured,
+            0xFE => AttError::ProcedureAlreadyInProgress,
+            0xFF => AttError::OutOfRange,
+            _ => AttError::Other(code),
+        }
+    }
+}
+
+impl From<AttError> for u8 {
+    fn from(error: AttError) -> Self {
+        error.code()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_round_trip() {
+        for code in 0..=u8::MAX {
+            assert_eq!(AttError::from(code).code(), code);
+        }
+    }
+
+    #[test]
+    fn known_codes() {
+        assert_eq!(AttError::from(0x05), AttError::InsufficientAuthentication);
+        assert_eq!(AttError::from(0x0F), AttError::InsufficientEncryption);
+        assert_eq!(AttError::from(0x85), AttError::Application(0x85));
+        assert_eq!(AttError::from(0xA0), AttError::Other(0xA0));
+        assert_eq!(AttError::from(0x00), AttError::Other(0x00));
+    }
+}
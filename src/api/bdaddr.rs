@@ -4,6 +4,8 @@ use std::convert::{TryFrom, TryInto};
 use std::fmt::{self, Debug, Display, Formatter, LowerHex, UpperHex};
 use std::str::FromStr;
 
+use super::RandomAddressKind;
+
 /// Stores the 6 byte address used to identify Bluetooth devices.
 #[derive(Copy, Clone, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct BDAddr {
@@ -133,9 +135,22 @@ impl BDAddr {
         self.address
     }
 
-    /// Check if this address is a randomly generated.
+    /// Check if this address is a random static address. This only makes sense for addresses
+    /// with an [`AddressType::Random`](super::AddressType::Random) address type.
     pub fn is_random_static(&self) -> bool {
-        self.address[5] & 0b11 == 0b11
+        self.random_address_kind() == Some(RandomAddressKind::Static)
+    }
+
+    /// Returns what kind of random address this is, going by its two most significant bits. This
+    /// only makes sense for addresses with an [`AddressType::Random`](super::AddressType::Random) address type. Returns `None`
+    /// if the bits have the reserved value.
+    pub fn random_address_kind(&self) -> Option<RandomAddressKind> {
+        match self.address[0] >> 6 {
+            0b11 => Some(RandomAddressKind::Static),
+            0b01 => Some(RandomAddressKind::Resolvable),
+            0b00 => Some(RandomAddressKind::NonResolvable),
+            _ => None,
+        }
     }
 
     /// Parses a Bluetooth address with colons `:` as delimiters.
@@ -428,6 +443,26 @@ mod tests {
     /// A u64 with the same value as `ADDR`.
     const HEX: u64 = 0x00_00_1f_2a_00_cc_22_f1;
 
+    #[test]
+    fn random_address_kind() {
+        let addr = BDAddr::from([0xc1, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(addr.random_address_kind(), Some(RandomAddressKind::Static));
+        assert!(addr.is_random_static());
+        let addr = BDAddr::from([0x4f, 0x00, 0x00, 0x00, 0x00, 0x03]);
+        assert_eq!(
+            addr.random_address_kind(),
+            Some(RandomAddressKind::Resolvable)
+        );
+        assert!(!addr.is_random_static());
+        let addr = BDAddr::from([0x3f, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(
+            addr.random_address_kind(),
+            Some(RandomAddressKind::NonResolvable)
+        );
+        let addr = BDAddr::from([0x80, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(addr.random_address_kind(), None);
+    }
+
     #[test]
     fn parse_addr() {
         let addr = BDAddr::from([0x2a, 0x00, 0xaa, 0xbb, 0xcc, 0xdd]);
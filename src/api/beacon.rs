@@ -0,0 +1,603 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Parsing of common beacon formats from advertisement data, and encoding them to be advertised.
+//!
+//! Supported formats are Apple's [iBeacon](https://developer.apple.com/ibeacon/), Google's
+//! [Eddystone](https://github.com/google/eddystone) (UID, URL, TLM and EID frames) and
+//! [AltBeacon](https://github.com/AltBeacon/spec).
+//!
+//! The encoders build the bytes of an advertisement. To advertise an iBeacon or an Eddystone
+//! frame from the adapter, use [`Advertiser`](super::advertiser::Advertiser), which encodes them
+//! with these.
+//!
+//! ```
+//! use btleplug::api::beacon::Beacon;
+//! # use btleplug::api::PeripheralProperties;
+//! # fn print_beacons(properties: &PeripheralProperties) {
+//! for beacon in properties.beacons() {
+//!     if let Beacon::IBeacon(ibeacon) = beacon {
+//!         println!("{} {}/{}", ibeacon.uuid, ibeacon.major, ibeacon.minor);
+//!     }
+//! }
+//! # }
+//! ```
+
+use super::{bleuuid::uuid_from_u16, PeripheralProperties};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use serde_cr as serde;
+use std::convert::TryInto;
+use uuid::Uuid;
+
+/// The company identifier of Apple, Inc., under which iBeacons are advertised.
+pub const APPLE_COMPANY_ID: u16 = 0x004C;
+
+/// The 16-bit service UUID under which Eddystone frames are advertised.
+pub const EDDYSTONE_SERVICE_UUID: Uuid = uuid_from_u16(0xFEAA);
+
+/// A beacon frame found in a peripheral's advertisement data.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Beacon {
+    IBeacon(IBeacon),
+    AltBeacon(AltBeacon),
+    Eddystone(Eddystone),
+}
+
+/// An iBeacon advertisement.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct IBeacon {
+    /// The proximity UUID, which usually identifies the beacon's owner.
+    pub uuid: Uuid,
+    pub major: u16,
+    pub minor: u16,
+    /// The calibrated RSSI at 1 metre, in dBm.
+    pub measured_power: i8,
+}
+
+impl IBeacon {
+    /// Parses an iBeacon from a manufacturer data entry, as found in
+    /// [`PeripheralProperties::manufacturer_data`].
+    pub fn from_manufacturer_data(company_id: u16, data: &[u8]) -> Option<Self> {
+        if company_id != APPLE_COMPANY_ID {
+            return None;
+        }
+        match data {
+            [0x02, 0x15, uuid @ .., major_high, major_low, minor_high, minor_low, measured_power]
+                if uuid.len() == 16 =>
+            {
+                Some(IBeacon {
+                    uuid: Uuid::from_slice(uuid).ok()?,
+                    major: u16::from_be_bytes([*major_high, *major_low]),
+                    minor: u16::from_be_bytes([*minor_high, *minor_low]),
+                    measured_power: *measured_power as i8,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Encodes the iBeacon as the manufacturer data to advertise under [`APPLE_COMPANY_ID`], for
+    /// a system acting as a beacon. This is the reverse of
+    /// [`from_manufacturer_data`](Self::from_manufacturer_data). To advertise it, use
+    /// [`Advertiser::ibeacon`](super::advertiser::Advertiser::ibeacon).
+    pub fn to_manufacturer_data(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(23);
+        data.extend_from_slice(&[0x02, 0x15]);
+        data.extend_from_slice(self.uuid.as_bytes());
+        data.extend_from_slice(&self.major.to_be_bytes());
+        data.extend_from_slice(&self.minor.to_be_bytes());
+        data.push(self.measured_power as u8);
+        data
+    }
+
+    /// Encodes the whole advertising payload of the iBeacon, as AD structures: the flags for a
+    /// general discoverable, LE only device, followed by the manufacturer data. At 30 bytes, it
+    /// fits in a legacy advertisement, leaving no room for a name, which goes in the scan
+    /// response instead.
+    ///
+    /// This is for platforms which take raw advertising data, such as BlueZ's
+    /// `LEAdvertisement1` with `Data` or the kernel's management interface. CoreBluetooth refuses
+    /// to advertise Apple's manufacturer data, so on macOS and iOS beacons have to be advertised
+    /// with Core Location's `CLBeaconRegion` instead.
+    pub fn to_advertising_data(&self) -> Vec<u8> {
+        let manufacturer_data = self.to_manufacturer_data();
+        let mut data = Vec::with_capacity(30);
+        // Flags: LE General Discoverable Mode, BR/EDR Not Supported.
+        data.extend_from_slice(&[0x02, 0x01, 0x06]);
+        data.push(3 + manufacturer_data.len() as u8);
+        data.push(0xFF);
+        data.extend_from_slice(&APPLE_COMPANY_ID.to_le_bytes());
+        data.extend_from_slice(&manufacturer_data);
+        data
+    }
+}
+
+/// An AltBeacon advertisement.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct AltBeacon {
+    /// The company identifier of the beacon's manufacturer.
+    pub manufacturer_id: u16,
+    /// The beacon identifier. By convention the first 16 bytes are an organisational UUID and the
+    /// remaining 4 bytes are split into two 16-bit values, like an iBeacon's major and minor.
+    pub beacon_id: [u8; 20],
+    /// The average RSSI at 1 metre, in dBm.
+    pub reference_rssi: i8,
+    /// Reserved for use by the manufacturer.
+    pub manufacturer_reserved: u8,
+}
+
+impl AltBeacon {
+    /// Parses an AltBeacon from a manufacturer data entry, as found in
+    /// [`PeripheralProperties::manufacturer_data`].
+    pub fn from_manufacturer_data(manufacturer_id: u16, data: &[u8]) -> Option<Self> {
+        match data {
+            [0xBE, 0xAC, beacon_id @ .., reference_rssi, manufacturer_reserved] => {
+                Some(AltBeacon {
+                    manufacturer_id,
+                    beacon_id: beacon_id.try_into().ok()?,
+                    reference_rssi: *reference_rssi as i8,
+                    manufacturer_reserved: *manufacturer_reserved,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the first 16 bytes of the beacon identifier as a UUID.
+    pub fn uuid(&self) -> Uuid {
+        Uuid::from_slice(&self.beacon_id[..16]).unwrap()
+    }
+}
+
+/// An Eddystone frame.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Eddystone {
+    Uid(EddystoneUid),
+    Url(EddystoneUrl),
+    Tlm(EddystoneTlm),
+    Eid(EddystoneEid),
+}
+
+impl Eddystone {
+    /// Parses an Eddystone frame from the service data advertised for
+    /// [`EDDYSTONE_SERVICE_UUID`].
+    pub fn from_service_data(data: &[u8]) -> Option<Self> {
+        match data {
+            [0x00, tx_power, id @ ..] if id.len() >= 16 => Some(Eddystone::Uid(EddystoneUid {
+                tx_power: *tx_power as i8,
+                namespace: id[..10].try_into().unwrap(),
+                instance: id[10..16].try_into().unwrap(),
+            })),
+            [0x10, tx_power, scheme, encoded @ ..] => Some(Eddystone::Url(EddystoneUrl {
+                tx_power: *tx_power as i8,
+                url: decode_url(*scheme, encoded)?,
+            })),
+            [0x20, 0x00, battery_high, battery_low, temperature_high, temperature_low, counts @ ..]
+                if counts.len() == 8 =>
+            {
+                let temperature = i16::from_be_bytes([*temperature_high, *temperature_low]);
+                Some(Eddystone::Tlm(EddystoneTlm {
+                    battery_voltage: u16::from_be_bytes([*battery_high, *battery_low]),
+                    // 0x8000 indicates that temperature isn't supported.
+                    temperature: (temperature != i16::MIN).then(|| temperature as f32 / 256.0),
+                    advertisement_count: u32::from_be_bytes(counts[..4].try_into().unwrap()),
+                    uptime: u32::from_be_bytes(counts[4..].try_into().unwrap()),
+                }))
+            }
+            [0x30, tx_power, eid @ ..] if eid.len() == 8 => Some(Eddystone::Eid(EddystoneEid {
+                tx_power: *tx_power as i8,
+                eid: eid.try_into().unwrap(),
+            })),
+            _ => None,
+        }
+    }
+
+    /// Encodes the frame as the service data to advertise for [`EDDYSTONE_SERVICE_UUID`], for a
+    /// system acting as a beacon. This is the reverse of
+    /// [`from_service_data`](Self::from_service_data). Returns `None` if the frame can't be
+    /// encoded, which is the case for a URL which doesn't start with one of the schemes
+    /// Eddystone supports, contains characters other than printable ASCII, or is longer than
+    /// 17 bytes once encoded.
+    ///
+    /// To advertise the frame, use
+    /// [`Advertiser::eddystone`](super::advertiser::Advertiser::eddystone).
+    pub fn to_service_data(&self) -> Option<Vec<u8>> {
+        let mut data = Vec::with_capacity(20);
+        match self {
+            Eddystone::Uid(uid) => {
+                data.extend_from_slice(&[0x00, uid.tx_power as u8]);
+                data.extend_from_slice(&uid.namespace);
+                data.extend_from_slice(&uid.instance);
+                // Reserved for future use.
+                data.extend_from_slice(&[0x00, 0x00]);
+            }
+            Eddystone::Url(url) => {
+                let (scheme, encoded) = encode_url(&url.url)?;
+                data.extend_from_slice(&[0x10, url.tx_power as u8, scheme]);
+                data.extend_from_slice(&encoded);
+            }
+            Eddystone::Tlm(tlm) => {
+                let temperature = match tlm.temperature {
+                    Some(temperature) => (temperature * 256.0) as i16,
+                    None => i16::MIN,
+                };
+                data.extend_from_slice(&[0x20, 0x00]);
+                data.extend_from_slice(&tlm.battery_voltage.to_be_bytes());
+                data.extend_from_slice(&temperature.to_be_bytes());
+                data.extend_from_slice(&tlm.advertisement_count.to_be_bytes());
+                data.extend_from_slice(&tlm.uptime.to_be_bytes());
+            }
+            Eddystone::Eid(eid) => {
+                data.extend_from_slice(&[0x30, eid.tx_power as u8]);
+                data.extend_from_slice(&eid.eid);
+            }
+        }
+        Some(data)
+    }
+
+    /// Encodes the whole advertising payload of the frame, as AD structures: the flags for a
+    /// general discoverable, LE only device, the Eddystone service UUID, and the frame as service
+    /// data for it, as the Eddystone specification requires. Returns `None` if the frame can't be
+    /// encoded, as for [`to_service_data`](Self::to_service_data).
+    pub fn to_advertising_data(&self) -> Option<Vec<u8>> {
+        let service_data = self.to_service_data()?;
+        let uuid = [0xAA, 0xFE];
+        let mut data = Vec::with_capacity(31);
+        // Flags: LE General Discoverable Mode, BR/EDR Not Supported.
+        data.extend_from_slice(&[0x02, 0x01, 0x06]);
+        // Complete List of 16-bit Service UUIDs.
+        data.extend_from_slice(&[0x03, 0x03]);
+        data.extend_from_slice(&uuid);
+        // Service Data - 16-bit UUID.
+        data.extend_from_slice(&[3 + service_data.len() as u8, 0x16]);
+        data.extend_from_slice(&uuid);
+        data.extend_from_slice(&service_data);
+        Some(data)
+    }
+}
+
+/// An Eddystone-UID frame, which broadcasts a static beacon ID.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct EddystoneUid {
+    /// The calibrated TX power at 0 metres, in dBm.
+    pub tx_power: i8,
+    pub namespace: [u8; 10],
+    pub instance: [u8; 6],
+}
+
+/// An Eddystone-URL frame, which broadcasts a URL.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct EddystoneUrl {
+    /// The calibrated TX power at 0 metres, in dBm.
+    pub tx_power: i8,
+    /// The decoded URL.
+    pub url: String,
+}
+
+/// An unencrypted Eddystone-TLM frame, which broadcasts telemetry about the beacon itself.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EddystoneTlm {
+    /// The battery voltage in millivolts, or 0 if the beacon isn't battery powered.
+    pub battery_voltage: u16,
+    /// The beacon temperature in degrees Celsius, if supported.
+    pub temperature: Option<f32>,
+    /// The number of advertising frames sent since the beacon was powered on.
+    pub advertisement_count: u32,
+    /// The time since the beacon was powered on, in units of 0.1 seconds.
+    pub uptime: u32,
+}
+
+/// An Eddystone-EID frame, which broadcasts a rotating encrypted identifier.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct EddystoneEid {
+    /// The calibrated TX power at 0 metres, in dBm.
+    pub tx_power: i8,
+    /// The current ephemeral identifier.
+    pub eid: [u8; 8],
+}
+
+/// The URL schemes of Eddystone-URL frames, in the order of their codes.
+const URL_SCHEMES: [&str; 4] = ["http://www.", "https://www.", "http://", "https://"];
+
+/// Expansions of common parts of URLs, in the order of their codes. Those with a trailing slash
+/// come first, so that they are preferred when encoding.
+const URL_EXPANSIONS: [&str; 14] = [
+    ".com/", ".org/", ".edu/", ".net/", ".info/", ".biz/", ".gov/", ".com", ".org", ".edu", ".net",
+    ".info", ".biz", ".gov",
+];
+
+/// The most bytes an Eddystone-URL frame has room for after its scheme.
+const MAX_ENCODED_URL_LEN: usize = 17;
+
+fn decode_url(scheme: u8, encoded: &[u8]) -> Option<String> {
+    let mut url = URL_SCHEMES.get(scheme as usize)?.to_string();
+    for &byte in encoded {
+        match byte {
+            0x00..=0x0D => url.push_str(URL_EXPANSIONS[byte as usize]),
+            0x21..=0x7E => url.push(byte as char),
+            _ => return None,
+        }
+    }
+    Some(url)
+}
+
+fn encode_url(url: &str) -> Option<(u8, Vec<u8>)> {
+    // The schemes with "www." come first, so they are preferred when they match.
+    let (scheme, prefix) = URL_SCHEMES
+        .iter()
+        .enumerate()
+        .find(|(_, prefix)| url.starts_with(*prefix))?;
+    let mut rest = &url[prefix.len()..];
+    let mut encoded = Vec::new();
+    while let Some(byte) = rest.bytes().next() {
+        if let Some((code, expansion)) = URL_EXPANSIONS
+            .iter()
+            .enumerate()
+            .find(|(_, expansion)| rest.starts_with(*expansion))
+        {
+            encoded.push(code as u8);
+            rest = &rest[expansion.len()..];
+        } else if (0x21..=0x7E).contains(&byte) {
+            encoded.push(byte);
+            rest = &rest[1..];
+        } else {
+            return None;
+        }
+    }
+    (encoded.len() <= MAX_ENCODED_URL_LEN).then_some((scheme as u8, encoded))
+}
+
+impl PeripheralProperties {
+    /// Returns all of the beacon frames found in this peripheral's manufacturer and service data.
+    pub fn beacons(&self) -> Vec<Beacon> {
+        let mut beacons = Vec::new();
+        for (&company_id, data) in &self.manufacturer_data {
+            if let Some(ibeacon) = IBeacon::from_manufacturer_data(company_id, data) {
+                beacons.push(Beacon::IBeacon(ibeacon));
+            } else if let Some(altbeacon) = AltBeacon::from_manufacturer_data(company_id, data) {
+                beacons.push(Beacon::AltBeacon(altbeacon));
+            }
+        }
+        if let Some(data) = self.service_data.get(&EDDYSTONE_SERVICE_UUID) {
+            if let Some(eddystone) = Eddystone::from_service_data(data) {
+                beacons.push(Beacon::Eddystone(eddystone));
+            }
+        }
+        beacons
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ibeacon() {
+        let data = [
+            0x02, 0x15, 0xE2, 0xC5, 0x6D, 0xB5, 0xDF, 0xFB, 0x48, 0xD2, 0xB0, 0x60, 0xD0, 0xF5,
+            0xA7, 0x10, 0x96, 0xE0, 0x00, 0x01, 0x00, 0x02, 0xC5,
+        ];
+        assert_eq!(
+            IBeacon::from_manufacturer_data(APPLE_COMPANY_ID, &data),
+            Some(IBeacon {
+                uuid: Uuid::parse_str("e2c56db5-dffb-48d2-b060-d0f5a71096e0").unwrap(),
+                major: 1,
+                minor: 2,
+                measured_power: -59,
+            })
+        );
+        assert_eq!(IBeacon::from_manufacturer_data(0x0059, &data), None);
+        assert_eq!(
+            IBeacon::from_manufacturer_data(APPLE_COMPANY_ID, &data[..22]),
+            None
+        );
+    }
+
+    #[test]
+    fn encode_ibeacon() {
+        let ibeacon = IBeacon {
+            uuid: Uuid::parse_str("e2c56db5-dffb-48d2-b060-d0f5a71096e0").unwrap(),
+            major: 1,
+            minor: 0x1234,
+            measured_power: -59,
+        };
+        let data = ibeacon.to_manufacturer_data();
+        assert_eq!(
+            IBeacon::from_manufacturer_data(APPLE_COMPANY_ID, &data),
+            Some(ibeacon)
+        );
+        let advertising_data = ibeacon.to_advertising_data();
+        assert_eq!(advertising_data.len(), 30);
+        assert_eq!(
+            advertising_data[..7],
+            [0x02, 0x01, 0x06, 0x1A, 0xFF, 0x4C, 0x00]
+        );
+        assert_eq!(advertising_data[7..], data[..]);
+    }
+
+    #[test]
+    fn parse_altbeacon() {
+        let mut data = vec![0xBE, 0xAC];
+        data.extend(1..=20);
+        data.extend([0xC5, 0x00]);
+        let altbeacon = AltBeacon::from_manufacturer_data(0x0118, &data).unwrap();
+        assert_eq!(altbeacon.manufacturer_id, 0x0118);
+        assert_eq!(altbeacon.beacon_id[0], 1);
+        assert_eq!(altbeacon.beacon_id[19], 20);
+        assert_eq!(altbeacon.reference_rssi, -59);
+        assert_eq!(AltBeacon::from_manufacturer_data(0x0118, &data[..23]), None);
+    }
+
+    #[test]
+    fn parse_eddystone_uid() {
+        let mut data = vec![0x00, 0xEE];
+        data.extend(0..16);
+        data.extend([0x00, 0x00]);
+        assert_eq!(
+            Eddystone::from_service_data(&data),
+            Some(Eddystone::Uid(EddystoneUid {
+                tx_power: -18,
+                namespace: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+                instance: [10, 11, 12, 13, 14, 15],
+            }))
+        );
+    }
+
+    #[test]
+    fn parse_eddystone_url() {
+        let data = [
+            0x10, 0xF8, 0x03, b'g', b'o', b'o', b'.', b'g', b'l', b'/', b'a', b'b',
+        ];
+        assert_eq!(
+            Eddystone::from_service_data(&data),
+            Some(Eddystone::Url(EddystoneUrl {
+                tx_power: -8,
+                url: "https://goo.gl/ab".to_string(),
+            }))
+        );
+        let data = [
+            0x10, 0x00, 0x00, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x07,
+        ];
+        match Eddystone::from_service_data(&data) {
+            Some(Eddystone::Url(url)) => assert_eq!(url.url, "http://www.example.com"),
+            other => panic!("Unexpected frame {:?}", other),
+        }
+    }
+
+    #[test]
+    fn encode_eddystone_uid() {
+        let uid = Eddystone::Uid(EddystoneUid {
+            tx_power: -18,
+            namespace: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+            instance: [10, 11, 12, 13, 14, 15],
+        });
+        let data = uid.to_service_data().unwrap();
+        assert_eq!(data.len(), 20);
+        assert_eq!(Eddystone::from_service_data(&data), Some(uid.clone()));
+        let advertising_data = uid.to_advertising_data().unwrap();
+        assert_eq!(
+            advertising_data[..11],
+            [0x02, 0x01, 0x06, 0x03, 0x03, 0xAA, 0xFE, 0x17, 0x16, 0xAA, 0xFE]
+        );
+        assert_eq!(advertising_data[11..], data[..]);
+    }
+
+    #[test]
+    fn encode_eddystone_url() {
+        let url = |url: &str| {
+            Eddystone::Url(EddystoneUrl {
+                tx_power: -8,
+                url: url.to_string(),
+            })
+        };
+        assert_eq!(
+            url("https://www.example.com/ab").to_service_data(),
+            Some(vec![
+                0x10, 0xF8, 0x01, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x00, b'a', b'b'
+            ])
+        );
+        for valid in [
+            "https://goo.gl/ab",
+            "http://example.org",
+            "https://a.info/b.gov",
+        ] {
+            let frame = url(valid);
+            let data = frame.to_service_data().unwrap();
+            assert_eq!(Eddystone::from_service_data(&data), Some(frame));
+        }
+        assert_eq!(url("ftp://example.com").to_service_data(), None);
+        assert_eq!(url("https://exa mple.com").to_service_data(), None);
+        assert_eq!(url("https://example.com/ünïcode").to_service_data(), None);
+        assert_eq!(
+            url("https://a-very-long-domain.example.com").to_service_data(),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_eddystone_tlm() {
+        let data = [
+            0x20, 0x00, 0x0B, 0xB8, 0x15, 0x80, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x0A,
+        ];
+        assert_eq!(
+            Eddystone::from_service_data(&data),
+            Some(Eddystone::Tlm(EddystoneTlm {
+                battery_voltage: 3000,
+                temperature: Some(21.5),
+                advertisement_count: 256,
+                uptime: 10,
+            }))
+        );
+    }
+
+    #[test]
+    fn parse_eddystone_eid() {
+        let data = [0x30, 0x00, 1, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(
+            Eddystone::from_service_data(&data),
+            Some(Eddystone::Eid(EddystoneEid {
+                tx_power: 0,
+                eid: [1, 2, 3, 4, 5, 6, 7, 8],
+            }))
+        );
+    }
+
+    #[test]
+    fn beacons_from_properties() {
+        let mut properties = PeripheralProperties::default();
+        properties.service_data.insert(
+            EDDYSTONE_SERVICE_UUID,
+            vec![0x30, 0x00, 1, 2, 3, 4, 5, 6, 7, 8].into(),
+        );
+        properties
+            .manufacturer_data
+            .insert(0x0059, vec![1, 2, 3].into());
+        assert_eq!(properties.beacons().len(), 1);
+    }
+}
@@ -2,6 +2,9 @@
 
 use uuid::Uuid;
 
+#[cfg(feature = "uuid-names")]
+mod names;
+
 const BLUETOOTH_BASE_UUID: u128 = 0x00000000_0000_1000_8000_00805f9b34fb;
 const BLUETOOTH_BASE_MASK: u128 = 0x00000000_ffff_ffff_ffff_ffffffffffff;
 const BLUETOOTH_BASE_MASK_16: u128 = 0xffff0000_ffff_ffff_ffff_ffffffffffff;
@@ -19,6 +22,25 @@ pub const fn uuid_from_u16(short: u16) -> Uuid {
     uuid_from_u32(short as u32)
 }
 
+/// Returns the name of a well-known service, characteristic or descriptor UUID, such as
+/// "Battery Level" for `0x2a19`. This covers the UUIDs assigned by the Bluetooth SIG as well as
+/// some widely used vendor-specific ones.
+///
+/// Only available with the `uuid-names` feature.
+///
+/// ```
+/// use btleplug::api::bleuuid::{name_of, uuid_from_u16};
+///
+/// assert_eq!(name_of(uuid_from_u16(0x2a19)), Some("Battery Level"));
+/// ```
+#[cfg(feature = "uuid-names")]
+pub fn name_of(uuid: Uuid) -> Option<&'static str> {
+    names::UUID_NAMES
+        .binary_search_by_key(&uuid.as_u128(), |&(uuid, _)| uuid)
+        .ok()
+        .map(|index| names::UUID_NAMES[index].1)
+}
+
 /// An extension trait for `Uuid` which provides BLE-specific methods.
 pub trait BleUuid {
     /// If the UUID is a valid BLE short UUID then return its short form, otherwise return `None`.
@@ -140,4 +162,20 @@ mod tests {
         let uuid = Uuid::parse_str(uuid_str).unwrap();
         assert_eq!(uuid.to_short_string(), uuid_str);
     }
+
+    #[cfg(feature = "uuid-names")]
+    #[test]
+    fn name_of_test() {
+        assert!(names::UUID_NAMES.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(name_of(uuid_from_u16(0x180f)), Some("Battery Service"));
+        assert_eq!(
+            name_of(uuid_from_u16(0x2902)),
+            Some("Client Characteristic Configuration")
+        );
+        assert_eq!(
+            name_of(Uuid::parse_str("6e400001-b5a3-f393-e0a9-e50e24dcca9e").unwrap()),
+            Some("Nordic UART Service")
+        );
+        assert_eq!(name_of(uuid_from_u16(0x0000)), None);
+    }
 }
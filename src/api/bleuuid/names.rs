@@ -0,0 +1,593 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+//
+// Generated from the service, characteristic and descriptor tables in the Nordic Semiconductor
+// bluetooth-numbers-database (https://github.com/NordicSemiconductor/bluetooth-numbers-database),
+// using a BSD 3-Clause license under the following copyright:
+//
+// Copyright (c) 2019 - 2020, Nordic Semiconductor ASA
+
+/// Well-known UUIDs and their names, sorted by UUID.
+#[rustfmt::skip]
+pub(super) const UUID_NAMES: &[(u128, &str)] = &[
+    (0x00001000_0000_1000_8000_00805f9b34fb, "SDP Server"),
+    (0x00001001_0000_1000_8000_00805f9b34fb, "Browse Group Descriptor"),
+    (0x00001002_0000_1000_8000_00805f9b34fb, "Public Browse Group"),
+    (0x00001101_0000_1000_8000_00805f9b34fb, "Serial Port"),
+    (0x00001102_0000_1000_8000_00805f9b34fb, "LAN Access Using PPP"),
+    (0x00001103_0000_1000_8000_00805f9b34fb, "Dialup Networking"),
+    (0x00001104_0000_1000_8000_00805f9b34fb, "IrMC Sync"),
+    (0x00001105_0000_1000_8000_00805f9b34fb, "OBEX Object Push"),
+    (0x00001106_0000_1000_8000_00805f9b34fb, "OBEX File Transfer"),
+    (0x00001107_0000_1000_8000_00805f9b34fb, "IrMC Sync Command"),
+    (0x00001108_0000_1000_8000_00805f9b34fb, "Headset"),
+    (0x00001109_0000_1000_8000_00805f9b34fb, "Cordless Telephony"),
+    (0x0000110a_0000_1000_8000_00805f9b34fb, "Audio Source"),
+    (0x0000110b_0000_1000_8000_00805f9b34fb, "Audio Sink"),
+    (0x0000110c_0000_1000_8000_00805f9b34fb, "AV Remote Target"),
+    (0x0000110d_0000_1000_8000_00805f9b34fb, "Advanced Audio"),
+    (0x0000110e_0000_1000_8000_00805f9b34fb, "AV Remote"),
+    (0x0000110f_0000_1000_8000_00805f9b34fb, "AV Remote Controller"),
+    (0x00001110_0000_1000_8000_00805f9b34fb, "Intercom"),
+    (0x00001111_0000_1000_8000_00805f9b34fb, "Fax"),
+    (0x00001112_0000_1000_8000_00805f9b34fb, "Headset Audio Gateway"),
+    (0x00001113_0000_1000_8000_00805f9b34fb, "WAP"),
+    (0x00001114_0000_1000_8000_00805f9b34fb, "WAP Client"),
+    (0x00001115_0000_1000_8000_00805f9b34fb, "PAN User"),
+    (0x00001116_0000_1000_8000_00805f9b34fb, "Network Access Point"),
+    (0x00001117_0000_1000_8000_00805f9b34fb, "PAN Group Network"),
+    (0x00001118_0000_1000_8000_00805f9b34fb, "Direct Printing"),
+    (0x00001119_0000_1000_8000_00805f9b34fb, "Reference Printing"),
+    (0x0000111a_0000_1000_8000_00805f9b34fb, "Imaging"),
+    (0x0000111b_0000_1000_8000_00805f9b34fb, "Imaging Responder"),
+    (0x0000111c_0000_1000_8000_00805f9b34fb, "Imaging Automatic Archive"),
+    (0x0000111d_0000_1000_8000_00805f9b34fb, "Imaging Referenced Objects"),
+    (0x0000111e_0000_1000_8000_00805f9b34fb, "Handsfree"),
+    (0x0000111f_0000_1000_8000_00805f9b34fb, "Handsfree Audio Gateway"),
+    (0x00001120_0000_1000_8000_00805f9b34fb, "Direct Printing Ref. Objects"),
+    (0x00001121_0000_1000_8000_00805f9b34fb, "Reflected UI"),
+    (0x00001122_0000_1000_8000_00805f9b34fb, "Basic Printing"),
+    (0x00001123_0000_1000_8000_00805f9b34fb, "Printing Status"),
+    (0x00001124_0000_1000_8000_00805f9b34fb, "Human Interface Device"),
+    (0x00001125_0000_1000_8000_00805f9b34fb, "Hardcopy Cable Replacement"),
+    (0x00001126_0000_1000_8000_00805f9b34fb, "HCR Print"),
+    (0x00001127_0000_1000_8000_00805f9b34fb, "HCR Scan"),
+    (0x00001128_0000_1000_8000_00805f9b34fb, "Common ISDN Access"),
+    (0x00001129_0000_1000_8000_00805f9b34fb, "Video Conferencing Gateway"),
+    (0x0000112a_0000_1000_8000_00805f9b34fb, "UDI MT"),
+    (0x0000112b_0000_1000_8000_00805f9b34fb, "UDI TA"),
+    (0x0000112c_0000_1000_8000_00805f9b34fb, "Audio/Video"),
+    (0x0000112d_0000_1000_8000_00805f9b34fb, "SIM Access"),
+    (0x0000112e_0000_1000_8000_00805f9b34fb, "Phonebook Access - PCE"),
+    (0x0000112f_0000_1000_8000_00805f9b34fb, "Phonebook Access - PSE"),
+    (0x00001130_0000_1000_8000_00805f9b34fb, "Phonebook Access"),
+    (0x00001132_0000_1000_8000_00805f9b34fb, "Message Access - MAS"),
+    (0x00001133_0000_1000_8000_00805f9b34fb, "Message Access - MNS"),
+    (0x00001134_0000_1000_8000_00805f9b34fb, "Message Access"),
+    (0x00001135_0000_1000_8000_00805f9b34fb, "GNSS"),
+    (0x00001136_0000_1000_8000_00805f9b34fb, "GNSS Server"),
+    (0x0000113a_0000_1000_8000_00805f9b34fb, "MPS Profile"),
+    (0x0000113b_0000_1000_8000_00805f9b34fb, "MPS SC"),
+    (0x00001200_0000_1000_8000_00805f9b34fb, "PnP Information"),
+    (0x00001201_0000_1000_8000_00805f9b34fb, "Generic Networking"),
+    (0x00001202_0000_1000_8000_00805f9b34fb, "Generic File Transfer"),
+    (0x00001203_0000_1000_8000_00805f9b34fb, "Generic Audio"),
+    (0x00001204_0000_1000_8000_00805f9b34fb, "Generic Telephony"),
+    (0x00001205_0000_1000_8000_00805f9b34fb, "UPnP"),
+    (0x00001206_0000_1000_8000_00805f9b34fb, "UPnP IP"),
+    (0x00001233_0000_1000_8000_00805f9b34fb, "Deprecated Fast Pair Model ID"),
+    (0x00001234_0000_1000_8000_00805f9b34fb, "Deprecated Fast Pair Key-based Pairing"),
+    (0x00001235_0000_1000_8000_00805f9b34fb, "Deprecated Fast Pair Passkey"),
+    (0x00001236_0000_1000_8000_00805f9b34fb, "Deprecated Fast Pair Account Key"),
+    (0x00001237_0000_1000_8000_00805f9b34fb, "Deprecated Fast Pair Data"),
+    (0x00001300_0000_1000_8000_00805f9b34fb, "UPnP PAN"),
+    (0x00001301_0000_1000_8000_00805f9b34fb, "UPnP LAP"),
+    (0x00001302_0000_1000_8000_00805f9b34fb, "UPnP L2CAP"),
+    (0x00001303_0000_1000_8000_00805f9b34fb, "Video Source"),
+    (0x00001304_0000_1000_8000_00805f9b34fb, "Video Sink"),
+    (0x00001305_0000_1000_8000_00805f9b34fb, "Video Distribution"),
+    (0x00001400_0000_1000_8000_00805f9b34fb, "HDP"),
+    (0x00001401_0000_1000_8000_00805f9b34fb, "HDP Source"),
+    (0x00001402_0000_1000_8000_00805f9b34fb, "HDP Sink"),
+    (0x00001523_1212_efde_1523_785feabcd123, "Nordic LED and Button Service"),
+    (0x00001524_1212_efde_1523_785feabcd123, "Blinky Button State"),
+    (0x00001525_1212_efde_1523_785feabcd123, "Blinky LED State"),
+    (0x00001530_1212_efde_1523_785feabcd123, "Legacy DFU Service"),
+    (0x00001531_1212_efde_1523_785feabcd123, "Legacy DFU Control Point"),
+    (0x00001532_1212_efde_1523_785feabcd123, "Legacy DFU Packet"),
+    (0x00001534_1212_efde_1523_785feabcd123, "Legacy DFU Version"),
+    (0x00001623_1212_efde_1623_785feabcd123, "LEGO® Wireless Protocol v3 Hub Service"),
+    (0x00001624_1212_efde_1623_785feabcd123, "LEGO® Wireless Protocol v3 Hub Characteristic"),
+    (0x00001625_1212_efde_1623_785feabcd123, "LEGO® Wireless Protocol v3 Bootloader Service"),
+    (0x00001626_1212_efde_1623_785feabcd123, "LEGO® Wireless Protocol v3 Bootloader Characteristic"),
+    (0x00001800_0000_1000_8000_00805f9b34fb, "Generic Access"),
+    (0x00001801_0000_1000_8000_00805f9b34fb, "Generic Attribute"),
+    (0x00001802_0000_1000_8000_00805f9b34fb, "Immediate Alert"),
+    (0x00001803_0000_1000_8000_00805f9b34fb, "Link Loss"),
+    (0x00001804_0000_1000_8000_00805f9b34fb, "Tx Power"),
+    (0x00001805_0000_1000_8000_00805f9b34fb, "Current Time Service"),
+    (0x00001806_0000_1000_8000_00805f9b34fb, "Reference Time Update Service"),
+    (0x00001807_0000_1000_8000_00805f9b34fb, "Next DST Change Service"),
+    (0x00001808_0000_1000_8000_00805f9b34fb, "Glucose"),
+    (0x00001809_0000_1000_8000_00805f9b34fb, "Health Thermometer"),
+    (0x0000180a_0000_1000_8000_00805f9b34fb, "Device Information"),
+    (0x0000180d_0000_1000_8000_00805f9b34fb, "Heart Rate"),
+    (0x0000180e_0000_1000_8000_00805f9b34fb, "Phone Alert Status Service"),
+    (0x0000180f_0000_1000_8000_00805f9b34fb, "Battery Service"),
+    (0x00001810_0000_1000_8000_00805f9b34fb, "Blood Pressure"),
+    (0x00001811_0000_1000_8000_00805f9b34fb, "Alert Notification Service"),
+    (0x00001812_0000_1000_8000_00805f9b34fb, "Human Interface Device"),
+    (0x00001813_0000_1000_8000_00805f9b34fb, "Scan Parameters"),
+    (0x00001814_0000_1000_8000_00805f9b34fb, "Running Speed and Cadence"),
+    (0x00001815_0000_1000_8000_00805f9b34fb, "Automation IO"),
+    (0x00001816_0000_1000_8000_00805f9b34fb, "Cycling Speed and Cadence"),
+    (0x00001818_0000_1000_8000_00805f9b34fb, "Cycling Power"),
+    (0x00001819_0000_1000_8000_00805f9b34fb, "Location and Navigation"),
+    (0x0000181a_0000_1000_8000_00805f9b34fb, "Environmental Sensing"),
+    (0x0000181b_0000_1000_8000_00805f9b34fb, "Body Composition"),
+    (0x0000181c_0000_1000_8000_00805f9b34fb, "User Data"),
+    (0x0000181d_0000_1000_8000_00805f9b34fb, "Weight Scale"),
+    (0x0000181e_0000_1000_8000_00805f9b34fb, "Bond Management Service"),
+    (0x0000181f_0000_1000_8000_00805f9b34fb, "Continuous Glucose Monitoring"),
+    (0x00001820_0000_1000_8000_00805f9b34fb, "Internet Protocol Support Service"),
+    (0x00001821_0000_1000_8000_00805f9b34fb, "Indoor Positioning"),
+    (0x00001822_0000_1000_8000_00805f9b34fb, "Pulse Oximeter Service"),
+    (0x00001823_0000_1000_8000_00805f9b34fb, "HTTP Proxy"),
+    (0x00001824_0000_1000_8000_00805f9b34fb, "Transport Discovery"),
+    (0x00001825_0000_1000_8000_00805f9b34fb, "Object Transfer Service"),
+    (0x00001826_0000_1000_8000_00805f9b34fb, "Fitness Machine"),
+    (0x00001827_0000_1000_8000_00805f9b34fb, "Mesh Provisioning Service"),
+    (0x00001828_0000_1000_8000_00805f9b34fb, "Mesh Proxy Service"),
+    (0x00001829_0000_1000_8000_00805f9b34fb, "Reconnection Configuration"),
+    (0x0000183a_0000_1000_8000_00805f9b34fb, "Insulin Delivery"),
+    (0x00002112_0000_1000_8000_00805f9b34fb, "Apple Agent"),
+    (0x00002900_0000_1000_8000_00805f9b34fb, "Characteristic Extended Properties"),
+    (0x00002901_0000_1000_8000_00805f9b34fb, "Characteristic User Descriptor"),
+    (0x00002902_0000_1000_8000_00805f9b34fb, "Client Characteristic Configuration"),
+    (0x00002903_0000_1000_8000_00805f9b34fb, "Server Characteristic Configuration"),
+    (0x00002904_0000_1000_8000_00805f9b34fb, "Characteristic Presentation Format"),
+    (0x00002905_0000_1000_8000_00805f9b34fb, "Characteristic Aggregate Format"),
+    (0x00002906_0000_1000_8000_00805f9b34fb, "Valid Range"),
+    (0x00002907_0000_1000_8000_00805f9b34fb, "External Report Reference"),
+    (0x00002908_0000_1000_8000_00805f9b34fb, "Report Reference"),
+    (0x00002909_0000_1000_8000_00805f9b34fb, "Number of Digitals"),
+    (0x0000290a_0000_1000_8000_00805f9b34fb, "Value Trigger Setting"),
+    (0x0000290b_0000_1000_8000_00805f9b34fb, "Environmental Sensing Configuration"),
+    (0x0000290c_0000_1000_8000_00805f9b34fb, "Environmental Sensing Measurement"),
+    (0x0000290d_0000_1000_8000_00805f9b34fb, "Environmental Sensing Trigger Setting"),
+    (0x0000290e_0000_1000_8000_00805f9b34fb, "Time Trigger Setting"),
+    (0x00002a00_0000_1000_8000_00805f9b34fb, "Device Name"),
+    (0x00002a01_0000_1000_8000_00805f9b34fb, "Appearance"),
+    (0x00002a02_0000_1000_8000_00805f9b34fb, "Peripheral Privacy Flag"),
+    (0x00002a03_0000_1000_8000_00805f9b34fb, "Reconnection Address"),
+    (0x00002a04_0000_1000_8000_00805f9b34fb, "Peripheral Preferred Connection Parameters"),
+    (0x00002a05_0000_1000_8000_00805f9b34fb, "Service Changed"),
+    (0x00002a06_0000_1000_8000_00805f9b34fb, "Alert Level"),
+    (0x00002a07_0000_1000_8000_00805f9b34fb, "Tx Power Level"),
+    (0x00002a08_0000_1000_8000_00805f9b34fb, "Date Time"),
+    (0x00002a09_0000_1000_8000_00805f9b34fb, "Day of Week"),
+    (0x00002a0a_0000_1000_8000_00805f9b34fb, "Day Date Time"),
+    (0x00002a0b_0000_1000_8000_00805f9b34fb, "Exact Time 100"),
+    (0x00002a0c_0000_1000_8000_00805f9b34fb, "Exact Time 256"),
+    (0x00002a0d_0000_1000_8000_00805f9b34fb, "DST Offset"),
+    (0x00002a0e_0000_1000_8000_00805f9b34fb, "Time Zone"),
+    (0x00002a0f_0000_1000_8000_00805f9b34fb, "Local Time Information"),
+    (0x00002a10_0000_1000_8000_00805f9b34fb, "Secondary Time Zone"),
+    (0x00002a11_0000_1000_8000_00805f9b34fb, "Time with DST"),
+    (0x00002a12_0000_1000_8000_00805f9b34fb, "Time Accuracy"),
+    (0x00002a13_0000_1000_8000_00805f9b34fb, "Time Source"),
+    (0x00002a14_0000_1000_8000_00805f9b34fb, "Reference Time Information"),
+    (0x00002a15_0000_1000_8000_00805f9b34fb, "Time Broadcast"),
+    (0x00002a16_0000_1000_8000_00805f9b34fb, "Time Update Control Point"),
+    (0x00002a17_0000_1000_8000_00805f9b34fb, "Time Update State"),
+    (0x00002a18_0000_1000_8000_00805f9b34fb, "Glucose Measurement"),
+    (0x00002a19_0000_1000_8000_00805f9b34fb, "Battery Level"),
+    (0x00002a1a_0000_1000_8000_00805f9b34fb, "Battery Power State"),
+    (0x00002a1b_0000_1000_8000_00805f9b34fb, "Battery Level State"),
+    (0x00002a1c_0000_1000_8000_00805f9b34fb, "Temperature Measurement"),
+    (0x00002a1d_0000_1000_8000_00805f9b34fb, "Temperature Type"),
+    (0x00002a1e_0000_1000_8000_00805f9b34fb, "Intermediate Temperature"),
+    (0x00002a1f_0000_1000_8000_00805f9b34fb, "Temperature Celsius"),
+    (0x00002a20_0000_1000_8000_00805f9b34fb, "Temperature Fahrenheit"),
+    (0x00002a21_0000_1000_8000_00805f9b34fb, "Measurement Interval"),
+    (0x00002a22_0000_1000_8000_00805f9b34fb, "Boot Keyboard Input Report"),
+    (0x00002a23_0000_1000_8000_00805f9b34fb, "System ID"),
+    (0x00002a24_0000_1000_8000_00805f9b34fb, "Model Number String"),
+    (0x00002a25_0000_1000_8000_00805f9b34fb, "Serial Number String"),
+    (0x00002a26_0000_1000_8000_00805f9b34fb, "Firmware Revision String"),
+    (0x00002a27_0000_1000_8000_00805f9b34fb, "Hardware Revision String"),
+    (0x00002a28_0000_1000_8000_00805f9b34fb, "Software Revision String"),
+    (0x00002a29_0000_1000_8000_00805f9b34fb, "Manufacturer Name String"),
+    (0x00002a2a_0000_1000_8000_00805f9b34fb, "IEEE 11073-20601 Regulatory Certification Data List"),
+    (0x00002a2b_0000_1000_8000_00805f9b34fb, "Current Time"),
+    (0x00002a2c_0000_1000_8000_00805f9b34fb, "Magnetic Declination"),
+    (0x00002a2f_0000_1000_8000_00805f9b34fb, "Position 2D"),
+    (0x00002a30_0000_1000_8000_00805f9b34fb, "Position 3D"),
+    (0x00002a31_0000_1000_8000_00805f9b34fb, "Scan Refresh"),
+    (0x00002a32_0000_1000_8000_00805f9b34fb, "Boot Keyboard Output Report"),
+    (0x00002a33_0000_1000_8000_00805f9b34fb, "Boot Mouse Input Report"),
+    (0x00002a34_0000_1000_8000_00805f9b34fb, "Glucose Measurement Context"),
+    (0x00002a35_0000_1000_8000_00805f9b34fb, "Blood Pressure Measurement"),
+    (0x00002a36_0000_1000_8000_00805f9b34fb, "Intermediate Cuff Pressure"),
+    (0x00002a37_0000_1000_8000_00805f9b34fb, "Heart Rate Measurement"),
+    (0x00002a38_0000_1000_8000_00805f9b34fb, "Body Sensor Location"),
+    (0x00002a39_0000_1000_8000_00805f9b34fb, "Heart Rate Control Point"),
+    (0x00002a3a_0000_1000_8000_00805f9b34fb, "Removable"),
+    (0x00002a3b_0000_1000_8000_00805f9b34fb, "Service Required"),
+    (0x00002a3c_0000_1000_8000_00805f9b34fb, "Scientific Temperature Celsius"),
+    (0x00002a3d_0000_1000_8000_00805f9b34fb, "String"),
+    (0x00002a3e_0000_1000_8000_00805f9b34fb, "Network Availability"),
+    (0x00002a3f_0000_1000_8000_00805f9b34fb, "Alert Status"),
+    (0x00002a40_0000_1000_8000_00805f9b34fb, "Ringer Control point"),
+    (0x00002a41_0000_1000_8000_00805f9b34fb, "Ringer Setting"),
+    (0x00002a42_0000_1000_8000_00805f9b34fb, "Alert Category ID Bit Mask"),
+    (0x00002a43_0000_1000_8000_00805f9b34fb, "Alert Category ID"),
+    (0x00002a44_0000_1000_8000_00805f9b34fb, "Alert Notification Control Point"),
+    (0x00002a45_0000_1000_8000_00805f9b34fb, "Unread Alert Status"),
+    (0x00002a46_0000_1000_8000_00805f9b34fb, "New Alert"),
+    (0x00002a47_0000_1000_8000_00805f9b34fb, "Supported New Alert Category"),
+    (0x00002a48_0000_1000_8000_00805f9b34fb, "Supported Unread Alert Category"),
+    (0x00002a49_0000_1000_8000_00805f9b34fb, "Blood Pressure Feature"),
+    (0x00002a4a_0000_1000_8000_00805f9b34fb, "HID Information"),
+    (0x00002a4b_0000_1000_8000_00805f9b34fb, "Report Map"),
+    (0x00002a4c_0000_1000_8000_00805f9b34fb, "HID Control Point"),
+    (0x00002a4d_0000_1000_8000_00805f9b34fb, "Report"),
+    (0x00002a4e_0000_1000_8000_00805f9b34fb, "Protocol Mode"),
+    (0x00002a4f_0000_1000_8000_00805f9b34fb, "Scan Interval Window"),
+    (0x00002a50_0000_1000_8000_00805f9b34fb, "PnP ID"),
+    (0x00002a51_0000_1000_8000_00805f9b34fb, "Glucose Feature"),
+    (0x00002a52_0000_1000_8000_00805f9b34fb, "Record Access Control Point"),
+    (0x00002a53_0000_1000_8000_00805f9b34fb, "RSC Measurement"),
+    (0x00002a54_0000_1000_8000_00805f9b34fb, "RSC Feature"),
+    (0x00002a55_0000_1000_8000_00805f9b34fb, "SC Control Point"),
+    (0x00002a56_0000_1000_8000_00805f9b34fb, "Digital"),
+    (0x00002a57_0000_1000_8000_00805f9b34fb, "Digital Output"),
+    (0x00002a58_0000_1000_8000_00805f9b34fb, "Analog"),
+    (0x00002a59_0000_1000_8000_00805f9b34fb, "Analog Output"),
+    (0x00002a5a_0000_1000_8000_00805f9b34fb, "Aggregate"),
+    (0x00002a5b_0000_1000_8000_00805f9b34fb, "CSC Measurement"),
+    (0x00002a5c_0000_1000_8000_00805f9b34fb, "CSC Feature"),
+    (0x00002a5d_0000_1000_8000_00805f9b34fb, "Sensor Location"),
+    (0x00002a5e_0000_1000_8000_00805f9b34fb, "PLX Spot-Check Measurement"),
+    (0x00002a5f_0000_1000_8000_00805f9b34fb, "PLX Continuous Measurement Characteristic"),
+    (0x00002a60_0000_1000_8000_00805f9b34fb, "PLX Features"),
+    (0x00002a62_0000_1000_8000_00805f9b34fb, "Pulse Oximetry Control Point"),
+    (0x00002a63_0000_1000_8000_00805f9b34fb, "Cycling Power Measurement"),
+    (0x00002a64_0000_1000_8000_00805f9b34fb, "Cycling Power Vector"),
+    (0x00002a65_0000_1000_8000_00805f9b34fb, "Cycling Power Feature"),
+    (0x00002a66_0000_1000_8000_00805f9b34fb, "Cycling Power Control Point"),
+    (0x00002a67_0000_1000_8000_00805f9b34fb, "Location and Speed Characteristic"),
+    (0x00002a68_0000_1000_8000_00805f9b34fb, "Navigation"),
+    (0x00002a69_0000_1000_8000_00805f9b34fb, "Position Quality"),
+    (0x00002a6a_0000_1000_8000_00805f9b34fb, "LN Feature"),
+    (0x00002a6b_0000_1000_8000_00805f9b34fb, "LN Control Point"),
+    (0x00002a6c_0000_1000_8000_00805f9b34fb, "Elevation"),
+    (0x00002a6d_0000_1000_8000_00805f9b34fb, "Pressure"),
+    (0x00002a6e_0000_1000_8000_00805f9b34fb, "Temperature"),
+    (0x00002a6f_0000_1000_8000_00805f9b34fb, "Humidity"),
+    (0x00002a70_0000_1000_8000_00805f9b34fb, "True Wind Speed"),
+    (0x00002a71_0000_1000_8000_00805f9b34fb, "True Wind Direction"),
+    (0x00002a72_0000_1000_8000_00805f9b34fb, "Apparent Wind Speed"),
+    (0x00002a73_0000_1000_8000_00805f9b34fb, "Apparent Wind Direction"),
+    (0x00002a74_0000_1000_8000_00805f9b34fb, "Gust Factor"),
+    (0x00002a75_0000_1000_8000_00805f9b34fb, "Pollen Concentration"),
+    (0x00002a76_0000_1000_8000_00805f9b34fb, "UV Index"),
+    (0x00002a77_0000_1000_8000_00805f9b34fb, "Irradiance"),
+    (0x00002a78_0000_1000_8000_00805f9b34fb, "Rainfall"),
+    (0x00002a79_0000_1000_8000_00805f9b34fb, "Wind Chill"),
+    (0x00002a7a_0000_1000_8000_00805f9b34fb, "Heat Index"),
+    (0x00002a7b_0000_1000_8000_00805f9b34fb, "Dew Point"),
+    (0x00002a7d_0000_1000_8000_00805f9b34fb, "Descriptor Value Changed"),
+    (0x00002a7e_0000_1000_8000_00805f9b34fb, "Aerobic Heart Rate Lower Limit"),
+    (0x00002a7f_0000_1000_8000_00805f9b34fb, "Aerobic Threshold"),
+    (0x00002a80_0000_1000_8000_00805f9b34fb, "Age"),
+    (0x00002a81_0000_1000_8000_00805f9b34fb, "Anaerobic Heart Rate Lower Limit"),
+    (0x00002a82_0000_1000_8000_00805f9b34fb, "Anaerobic Heart Rate Upper Limit"),
+    (0x00002a83_0000_1000_8000_00805f9b34fb, "Anaerobic Threshold"),
+    (0x00002a84_0000_1000_8000_00805f9b34fb, "Aerobic Heart Rate Upper Limit"),
+    (0x00002a85_0000_1000_8000_00805f9b34fb, "Date of Birth"),
+    (0x00002a86_0000_1000_8000_00805f9b34fb, "Date of Threshold Assessment"),
+    (0x00002a87_0000_1000_8000_00805f9b34fb, "Email Address"),
+    (0x00002a88_0000_1000_8000_00805f9b34fb, "Fat Burn Heart Rate Lower Limit"),
+    (0x00002a89_0000_1000_8000_00805f9b34fb, "Fat Burn Heart Rate Upper Limit"),
+    (0x00002a8a_0000_1000_8000_00805f9b34fb, "First Name"),
+    (0x00002a8b_0000_1000_8000_00805f9b34fb, "Five Zone Heart Rate Limits"),
+    (0x00002a8c_0000_1000_8000_00805f9b34fb, "Gender"),
+    (0x00002a8d_0000_1000_8000_00805f9b34fb, "Heart Rate Max"),
+    (0x00002a8e_0000_1000_8000_00805f9b34fb, "Height"),
+    (0x00002a8f_0000_1000_8000_00805f9b34fb, "Hip Circumference"),
+    (0x00002a90_0000_1000_8000_00805f9b34fb, "Last Name"),
+    (0x00002a91_0000_1000_8000_00805f9b34fb, "Maximum Recommended Heart Rate"),
+    (0x00002a92_0000_1000_8000_00805f9b34fb, "Resting Heart Rate"),
+    (0x00002a93_0000_1000_8000_00805f9b34fb, "Sport Type for Aerobic and Anaerobic Thresholds"),
+    (0x00002a94_0000_1000_8000_00805f9b34fb, "Three Zone Heart Rate Limits"),
+    (0x00002a95_0000_1000_8000_00805f9b34fb, "Two Zone Heart Rate Limit"),
+    (0x00002a96_0000_1000_8000_00805f9b34fb, "VO2 Max"),
+    (0x00002a97_0000_1000_8000_00805f9b34fb, "Waist Circumference"),
+    (0x00002a98_0000_1000_8000_00805f9b34fb, "Weight"),
+    (0x00002a99_0000_1000_8000_00805f9b34fb, "Database Change Increment"),
+    (0x00002a9a_0000_1000_8000_00805f9b34fb, "User Index"),
+    (0x00002a9b_0000_1000_8000_00805f9b34fb, "Body Composition Feature"),
+    (0x00002a9c_0000_1000_8000_00805f9b34fb, "Body Composition Measurement"),
+    (0x00002a9d_0000_1000_8000_00805f9b34fb, "Weight Measurement"),
+    (0x00002a9e_0000_1000_8000_00805f9b34fb, "Weight Scale Feature"),
+    (0x00002a9f_0000_1000_8000_00805f9b34fb, "User Control Point"),
+    (0x00002aa0_0000_1000_8000_00805f9b34fb, "Magnetic Flux Density - 2D"),
+    (0x00002aa1_0000_1000_8000_00805f9b34fb, "Magnetic Flux Density - 3D"),
+    (0x00002aa2_0000_1000_8000_00805f9b34fb, "Language"),
+    (0x00002aa3_0000_1000_8000_00805f9b34fb, "Barometric Pressure Trend"),
+    (0x00002aa4_0000_1000_8000_00805f9b34fb, "Bond Management Control Point"),
+    (0x00002aa5_0000_1000_8000_00805f9b34fb, "Bond Management Features"),
+    (0x00002aa6_0000_1000_8000_00805f9b34fb, "Central Address Resolution"),
+    (0x00002aa7_0000_1000_8000_00805f9b34fb, "CGM Measurement"),
+    (0x00002aa8_0000_1000_8000_00805f9b34fb, "CGM Feature"),
+    (0x00002aa9_0000_1000_8000_00805f9b34fb, "CGM Status"),
+    (0x00002aaa_0000_1000_8000_00805f9b34fb, "CGM Session Start Time"),
+    (0x00002aab_0000_1000_8000_00805f9b34fb, "CGM Session Run Time"),
+    (0x00002aac_0000_1000_8000_00805f9b34fb, "CGM Specific Ops Control Point"),
+    (0x00002aad_0000_1000_8000_00805f9b34fb, "Indoor Positioning Configuration"),
+    (0x00002aae_0000_1000_8000_00805f9b34fb, "Latitude"),
+    (0x00002aaf_0000_1000_8000_00805f9b34fb, "Longitude"),
+    (0x00002ab0_0000_1000_8000_00805f9b34fb, "Local North Coordinate"),
+    (0x00002ab1_0000_1000_8000_00805f9b34fb, "Local East Coordinate"),
+    (0x00002ab2_0000_1000_8000_00805f9b34fb, "Floor Number"),
+    (0x00002ab3_0000_1000_8000_00805f9b34fb, "Altitude"),
+    (0x00002ab4_0000_1000_8000_00805f9b34fb, "Uncertainty"),
+    (0x00002ab5_0000_1000_8000_00805f9b34fb, "Location Name"),
+    (0x00002ab6_0000_1000_8000_00805f9b34fb, "URI"),
+    (0x00002ab7_0000_1000_8000_00805f9b34fb, "HTTP Headers"),
+    (0x00002ab8_0000_1000_8000_00805f9b34fb, "HTTP Status Code"),
+    (0x00002ab9_0000_1000_8000_00805f9b34fb, "HTTP Entity Body"),
+    (0x00002aba_0000_1000_8000_00805f9b34fb, "HTTP Control Point"),
+    (0x00002abb_0000_1000_8000_00805f9b34fb, "HTTPS Security"),
+    (0x00002abc_0000_1000_8000_00805f9b34fb, "TDS Control Point"),
+    (0x00002abd_0000_1000_8000_00805f9b34fb, "OTS Feature"),
+    (0x00002abe_0000_1000_8000_00805f9b34fb, "Object Name"),
+    (0x00002abf_0000_1000_8000_00805f9b34fb, "Object Type"),
+    (0x00002ac0_0000_1000_8000_00805f9b34fb, "Object Size"),
+    (0x00002ac1_0000_1000_8000_00805f9b34fb, "Object First-Created"),
+    (0x00002ac2_0000_1000_8000_00805f9b34fb, "Object Last-Modified"),
+    (0x00002ac3_0000_1000_8000_00805f9b34fb, "Object ID"),
+    (0x00002ac4_0000_1000_8000_00805f9b34fb, "Object Properties"),
+    (0x00002ac5_0000_1000_8000_00805f9b34fb, "Object Action Control Point"),
+    (0x00002ac6_0000_1000_8000_00805f9b34fb, "Object List Control Point"),
+    (0x00002ac7_0000_1000_8000_00805f9b34fb, "Object List Filter"),
+    (0x00002ac8_0000_1000_8000_00805f9b34fb, "Object Changed"),
+    (0x00002ac9_0000_1000_8000_00805f9b34fb, "Resolvable Private Address Only"),
+    (0x00002acc_0000_1000_8000_00805f9b34fb, "Fitness Machine Feature"),
+    (0x00002acd_0000_1000_8000_00805f9b34fb, "Treadmill Data"),
+    (0x00002ace_0000_1000_8000_00805f9b34fb, "Cross Trainer Data"),
+    (0x00002acf_0000_1000_8000_00805f9b34fb, "Step Climber Data"),
+    (0x00002ad0_0000_1000_8000_00805f9b34fb, "Stair Climber Data"),
+    (0x00002ad1_0000_1000_8000_00805f9b34fb, "Rower Data"),
+    (0x00002ad2_0000_1000_8000_00805f9b34fb, "Indoor Bike Data"),
+    (0x00002ad3_0000_1000_8000_00805f9b34fb, "Training Status"),
+    (0x00002ad4_0000_1000_8000_00805f9b34fb, "Supported Speed Range"),
+    (0x00002ad5_0000_1000_8000_00805f9b34fb, "Supported Inclination Range"),
+    (0x00002ad6_0000_1000_8000_00805f9b34fb, "Supported Resistance Level Range"),
+    (0x00002ad7_0000_1000_8000_00805f9b34fb, "Supported Heart Rate Range"),
+    (0x00002ad8_0000_1000_8000_00805f9b34fb, "Supported Power Range"),
+    (0x00002ad9_0000_1000_8000_00805f9b34fb, "Fitness Machine Control Point"),
+    (0x00002ada_0000_1000_8000_00805f9b34fb, "Fitness Machine Status"),
+    (0x00002adb_0000_1000_8000_00805f9b34fb, "Mesh Provisioning Data In"),
+    (0x00002adc_0000_1000_8000_00805f9b34fb, "Mesh Provisioning Data Out"),
+    (0x00002add_0000_1000_8000_00805f9b34fb, "Mesh Proxy Data In"),
+    (0x00002ade_0000_1000_8000_00805f9b34fb, "Mesh Proxy Data Out"),
+    (0x00002aed_0000_1000_8000_00805f9b34fb, "Date UTC"),
+    (0x00002b1d_0000_1000_8000_00805f9b34fb, "RC Feature"),
+    (0x00002b1e_0000_1000_8000_00805f9b34fb, "RC Settings"),
+    (0x00002b1f_0000_1000_8000_00805f9b34fb, "Reconnection Configuration Control Point"),
+    (0x00002b20_0000_1000_8000_00805f9b34fb, "IDD Status Changed"),
+    (0x00002b21_0000_1000_8000_00805f9b34fb, "IDD Status"),
+    (0x00002b22_0000_1000_8000_00805f9b34fb, "IDD Annunciation Status"),
+    (0x00002b23_0000_1000_8000_00805f9b34fb, "IDD Features"),
+    (0x00002b24_0000_1000_8000_00805f9b34fb, "IDD Status Reader Control Point"),
+    (0x00002b25_0000_1000_8000_00805f9b34fb, "IDD Command Control Point"),
+    (0x00002b26_0000_1000_8000_00805f9b34fb, "IDD Command Data"),
+    (0x00002b27_0000_1000_8000_00805f9b34fb, "IDD Record Access Control Point"),
+    (0x00002b28_0000_1000_8000_00805f9b34fb, "IDD History Data"),
+    (0x00002b29_0000_1000_8000_00805f9b34fb, "Client Supported Features"),
+    (0x00002b2a_0000_1000_8000_00805f9b34fb, "Database Hash"),
+    (0x0000fd6f_0000_1000_8000_00805f9b34fb, "Exposure Notification Service"),
+    (0x0000fe0f_0000_1000_8000_00805f9b34fb, "Signify Netherlands B.V. (formerly Phillips Lighting) Service"),
+    (0x0000fe2c_0000_1000_8000_00805f9b34fb, "Fast Pair Service"),
+    (0x0000fe59_0000_1000_8000_00805f9b34fb, "Secure DFU Service"),
+    (0x0000feaa_0000_1000_8000_00805f9b34fb, "Eddystone"),
+    (0x0000febb_0000_1000_8000_00805f9b34fb, "File Transfer Service by Adafruit"),
+    (0x22eac6e9_24d6_4bb5_be44_b36ace7c7bfb, "Apple Data Source"),
+    (0x2f7cabce_808d_411f_9a0c_bb92ba96c102, "Apple Entity Update"),
+    (0x69d1d8f3_45e1_49a8_9821_9bbdfdaad9d9, "Apple Control Point"),
+    (0x6e400001_b5a3_f393_e0a9_e50e24dcca9e, "Nordic UART Service"),
+    (0x6e400002_b5a3_f393_e0a9_e50e24dcca9e, "UART RX Characteristic"),
+    (0x6e400003_b5a3_f393_e0a9_e50e24dcca9e, "UART TX Characteristic"),
+    (0x7905f431_b5ce_4e99_a40f_4b1e122d00d0, "Apple Notification Center Service"),
+    (0x7dfc6000_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Service"),
+    (0x7dfc6001_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc6002_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc6003_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc6004_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc6005_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc6101_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc6102_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc6103_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc6104_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc6105_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc6106_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc6107_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc6108_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc6201_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc6202_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc6203_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc7000_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Service"),
+    (0x7dfc7004_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc7005_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc7006_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc7007_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc7008_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc7009_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc700a_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc700b_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc700c_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc7103_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc7104_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc7105_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc7106_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc7107_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc7108_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc7109_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc710b_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc710c_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc710d_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc8000_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Service"),
+    (0x7dfc8003_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc8004_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x7dfc9000_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Service"),
+    (0x7dfc9001_7d1c_4951_86aa_8d9728f8d66c, "Apple Reserved Characteristic"),
+    (0x89d3502b_0f36_433a_8ef4_c502ad55f8dc, "Apple Media Service"),
+    (0x8d53dc1d_1db7_4cd3_868b_8a527460aa84, "SMP Service"),
+    (0x8e400001_f315_4f60_9fb8_838830daea50, "Experimental Buttonless DFU Service"),
+    (0x8ec90001_f315_4f60_9fb8_838830daea50, "DFU Control Point"),
+    (0x8ec90002_f315_4f60_9fb8_838830daea50, "DFU Packet"),
+    (0x8ec90003_f315_4f60_9fb8_838830daea50, "Buttonless DFU Without Bonds"),
+    (0x8ec90004_f315_4f60_9fb8_838830daea50, "Buttonless DFU With Bonds"),
+    (0x932c32bd_0000_47a2_835a_a8d455b859dd, "Phillips Hue Light Control Service"),
+    (0x932c32bd_0002_47a2_835a_a8d455b859dd, "Phillips Hue Light On/Off Toggle"),
+    (0x932c32bd_0003_47a2_835a_a8d455b859dd, "Phillips Hue Light Brightness Level"),
+    (0x932c32bd_0005_47a2_835a_a8d455b859dd, "Phillips Hue Light Color"),
+    (0x9b3c81d8_57b1_4a8a_b8df_0e56f7ca51c2, "Apple Remote Command"),
+    (0x9fbf120d_6301_42d9_8c58_25e699a21dbd, "Apple Notification Source"),
+    (0xa3c87500_8ed3_4bdf_8a39_a01bebede295, "Eddystone Configuration Service"),
+    (0xa3c87501_8ed3_4bdf_8a39_a01bebede295, "Eddystone Capabilities"),
+    (0xa3c87502_8ed3_4bdf_8a39_a01bebede295, "Eddystone Active Slot"),
+    (0xa3c87503_8ed3_4bdf_8a39_a01bebede295, "Eddystone Advertising Interval"),
+    (0xa3c87504_8ed3_4bdf_8a39_a01bebede295, "Eddystone Radio Tx Power"),
+    (0xa3c87505_8ed3_4bdf_8a39_a01bebede295, "Eddystone (Advanced) Advertised Tx Power"),
+    (0xa3c87506_8ed3_4bdf_8a39_a01bebede295, "Eddystone Lock State"),
+    (0xa3c87507_8ed3_4bdf_8a39_a01bebede295, "Eddystone Unlock"),
+    (0xa3c87508_8ed3_4bdf_8a39_a01bebede295, "Eddystone Public ECDH Key"),
+    (0xa3c87509_8ed3_4bdf_8a39_a01bebede295, "Eddystone EID Identity Key"),
+    (0xa3c8750a_8ed3_4bdf_8a39_a01bebede295, "Eddystone ADV Slot Data"),
+    (0xa3c8750b_8ed3_4bdf_8a39_a01bebede295, "Eddystone Avanced Factory Reset"),
+    (0xa3c8750c_8ed3_4bdf_8a39_a01bebede295, "Eddystone (Advanced) Remain Connectable"),
+    (0xadaf0001_c332_42a8_93bd_25e905756cb8, "Adafruit Sensor Measurement Period"),
+    (0xadaf0002_c332_42a8_93bd_25e905756cb8, "Adafruit Sensor Service Version"),
+    (0xadaf0100_4669_6c65_5472_616e73666572, "Adafruit Version"),
+    (0xadaf0100_c332_42a8_93bd_25e905756cb8, "Adafruit Temperature Service"),
+    (0xadaf0101_c332_42a8_93bd_25e905756cb8, "Adafruit Temperature"),
+    (0xadaf0200_4669_6c65_5472_616e73666572, "Adafruit Raw TX/RX"),
+    (0xadaf0200_c332_42a8_93bd_25e905756cb8, "Adafruit Accelerometer Service"),
+    (0xadaf0201_c332_42a8_93bd_25e905756cb8, "Adafruit Acceleration"),
+    (0xadaf0300_c332_42a8_93bd_25e905756cb8, "Adafruit Light Service"),
+    (0xadaf0301_c332_42a8_93bd_25e905756cb8, "Adafruit Light Level"),
+    (0xadaf0400_c332_42a8_93bd_25e905756cb8, "Adafruit Gyroscope Service"),
+    (0xadaf0401_c332_42a8_93bd_25e905756cb8, "Adafruit Gyro"),
+    (0xadaf0500_c332_42a8_93bd_25e905756cb8, "Adafruit Magnetometer Service"),
+    (0xadaf0501_c332_42a8_93bd_25e905756cb8, "Adafruit Magnetic"),
+    (0xadaf0600_c332_42a8_93bd_25e905756cb8, "Adafruit Button Service"),
+    (0xadaf0601_c332_42a8_93bd_25e905756cb8, "Adafruit Pressed"),
+    (0xadaf0700_c332_42a8_93bd_25e905756cb8, "Adafruit Humidity Service"),
+    (0xadaf0701_c332_42a8_93bd_25e905756cb8, "Adafruit Humidity"),
+    (0xadaf0800_c332_42a8_93bd_25e905756cb8, "Adafruit Barometric Service"),
+    (0xadaf0801_c332_42a8_93bd_25e905756cb8, "Adafruit Pressure"),
+    (0xadaf0900_c332_42a8_93bd_25e905756cb8, "Adafruit Addressable Service"),
+    (0xadaf0901_c332_42a8_93bd_25e905756cb8, "Adafruit Pixel Pin"),
+    (0xadaf0902_c332_42a8_93bd_25e905756cb8, "Adafruit Pixel Pin Type"),
+    (0xadaf0903_c332_42a8_93bd_25e905756cb8, "Adafruit Pixel Data"),
+    (0xadaf0904_c332_42a8_93bd_25e905756cb8, "Adafruit Pixel Buffer Size"),
+    (0xadaf0a00_c332_42a8_93bd_25e905756cb8, "Adafruit Color Service"),
+    (0xadaf0a01_c332_42a8_93bd_25e905756cb8, "Adafruit Color"),
+    (0xadaf0b00_c332_42a8_93bd_25e905756cb8, "Adafruit Sound Service"),
+    (0xadaf0b01_c332_42a8_93bd_25e905756cb8, "Adafruit Sound Samples"),
+    (0xadaf0b02_c332_42a8_93bd_25e905756cb8, "Adafruit Number of Channels"),
+    (0xadaf0c00_c332_42a8_93bd_25e905756cb8, "Adafruit Tone Service"),
+    (0xadaf0c01_c332_42a8_93bd_25e905756cb8, "Adafruit Tone"),
+    (0xadaf0d00_c332_42a8_93bd_25e905756cb8, "Adafruit Quaternion Service"),
+    (0xadaf0d01_c332_42a8_93bd_25e905756cb8, "Adafruit Quaternions"),
+    (0xadaf0d02_c332_42a8_93bd_25e905756cb8, "Adafruit Calibration In"),
+    (0xadaf0d03_c332_42a8_93bd_25e905756cb8, "Adafruit Calibration Out"),
+    (0xadaf0e00_c332_42a8_93bd_25e905756cb8, "Adafruit Proximity Service"),
+    (0xadaf0e01_c332_42a8_93bd_25e905756cb8, "Adafruit Proximity"),
+    (0xb8843add_0000_4aa1_8794_c3f462030bda, "Phillips Hue Light Update Service"),
+    (0xc6b2f38c_23ab_46d8_a6ab_a3a870bbd5d7, "Apple Entity Attribute"),
+    (0xda2e7828_fbce_4e01_ae9e_261174997c48, "SMP Characteristic"),
+    (0xe2a00001_ec31_4ec3_a97a_1c34d87e9878, "Edge Impulse Remote Management Service"),
+    (0xe2a00002_ec31_4ec3_a97a_1c34d87e9878, "Edge Impulse Remote Management RX Characteristic"),
+    (0xe2a00003_ec31_4ec3_a97a_1c34d87e9878, "Edge Impulse Remote Management TX Characteristic"),
+    (0xe95d0753_251d_470a_a062_fa1922dfa9a8, "micro:bit Accelerometer Service"),
+    (0xe95d0d2d_251d_470a_a062_fa1922dfa9a8, "micro:bit Scrolling Delay"),
+    (0xe95d127b_251d_470a_a062_fa1922dfa9a8, "micro:bit IO Pin Service"),
+    (0xe95d1b25_251d_470a_a062_fa1922dfa9a8, "micro:bit Temperature Period"),
+    (0xe95d23c4_251d_470a_a062_fa1922dfa9a8, "micro:bit Client Requirements"),
+    (0xe95d386c_251d_470a_a062_fa1922dfa9a8, "micro:bit Magnetometer Period"),
+    (0xe95d5404_251d_470a_a062_fa1922dfa9a8, "micro:bit Client Event"),
+    (0xe95d5899_251d_470a_a062_fa1922dfa9a8, "micro:bit Pin AD Configuration"),
+    (0xe95d6100_251d_470a_a062_fa1922dfa9a8, "micro:bit Temperature Service"),
+    (0xe95d7b77_251d_470a_a062_fa1922dfa9a8, "micro:bit LED Matrix State"),
+    (0xe95d8d00_251d_470a_a062_fa1922dfa9a8, "micro:bit Pin Data"),
+    (0xe95d9250_251d_470a_a062_fa1922dfa9a8, "micro:bit Temperature"),
+    (0xe95d93af_251d_470a_a062_fa1922dfa9a8, "micro:bit Event Service"),
+    (0xe95d93b0_251d_470a_a062_fa1922dfa9a8, "micro:bit DFU Control Service"),
+    (0xe95d93b1_251d_470a_a062_fa1922dfa9a8, "micro:bit DFU Control"),
+    (0xe95d93ee_251d_470a_a062_fa1922dfa9a8, "micro:bit LED Text"),
+    (0xe95d9715_251d_470a_a062_fa1922dfa9a8, "micro:bit Magnetometer Bearing"),
+    (0xe95d9775_251d_470a_a062_fa1922dfa9a8, "micro:bit Event"),
+    (0xe95d9882_251d_470a_a062_fa1922dfa9a8, "micro:bit Button Service"),
+    (0xe95db84c_251d_470a_a062_fa1922dfa9a8, "micro:bit Requirements"),
+    (0xe95db9fe_251d_470a_a062_fa1922dfa9a8, "micro:bit Pin I/O Configuration"),
+    (0xe95dca4b_251d_470a_a062_fa1922dfa9a8, "micro:bit Accelerometer Data"),
+    (0xe95dd822_251d_470a_a062_fa1922dfa9a8, "micro:bit PWM Control"),
+    (0xe95dd91d_251d_470a_a062_fa1922dfa9a8, "micro:bit LED Service"),
+    (0xe95dda90_251d_470a_a062_fa1922dfa9a8, "micro:bit Button A State"),
+    (0xe95dda91_251d_470a_a062_fa1922dfa9a8, "micro:bit Button B State"),
+    (0xe95df2d8_251d_470a_a062_fa1922dfa9a8, "micro:bit Magnetometer Service"),
+    (0xe95dfb11_251d_470a_a062_fa1922dfa9a8, "micro:bit Magnetometer Data"),
+    (0xe95dfb24_251d_470a_a062_fa1922dfa9a8, "micro:bit Accelerometer Period"),
+    (0xef680100_9b35_4933_9b10_52ffa9740042, "Thingy Configuration Service"),
+    (0xef680101_9b35_4933_9b10_52ffa9740042, "Thingy Device Name"),
+    (0xef680102_9b35_4933_9b10_52ffa9740042, "Thingy Advertising Parameters"),
+    (0xef680104_9b35_4933_9b10_52ffa9740042, "Thingy Connection Parameters"),
+    (0xef680105_9b35_4933_9b10_52ffa9740042, "Thingy Eddystone URL"),
+    (0xef680106_9b35_4933_9b10_52ffa9740042, "Thingy Cloud Token"),
+    (0xef680107_9b35_4933_9b10_52ffa9740042, "Thingy FW Version"),
+    (0xef680108_9b35_4933_9b10_52ffa9740042, "Thingy MTU Request"),
+    (0xef680200_9b35_4933_9b10_52ffa9740042, "Thingy Weather Station Service"),
+    (0xef680201_9b35_4933_9b10_52ffa9740042, "Thingy Temperature"),
+    (0xef680202_9b35_4933_9b10_52ffa9740042, "Thingy Pressure"),
+    (0xef680203_9b35_4933_9b10_52ffa9740042, "Thingy Humidity"),
+    (0xef680204_9b35_4933_9b10_52ffa9740042, "Thingy Air Quality"),
+    (0xef680205_9b35_4933_9b10_52ffa9740042, "Thingy Color"),
+    (0xef680206_9b35_4933_9b10_52ffa9740042, "Thingy Configuration"),
+    (0xef680300_9b35_4933_9b10_52ffa9740042, "Thingy UI Service"),
+    (0xef680301_9b35_4933_9b10_52ffa9740042, "Thingy LED State"),
+    (0xef680302_9b35_4933_9b10_52ffa9740042, "Thingy Button State"),
+    (0xef680303_9b35_4933_9b10_52ffa9740042, "Thingy EXT Pin"),
+    (0xef680400_9b35_4933_9b10_52ffa9740042, "Thingy Motion Service"),
+    (0xef680401_9b35_4933_9b10_52ffa9740042, "Thingy Motion Config"),
+    (0xef680402_9b35_4933_9b10_52ffa9740042, "Thingy Tap"),
+    (0xef680403_9b35_4933_9b10_52ffa9740042, "Thingy Orientation"),
+    (0xef680404_9b35_4933_9b10_52ffa9740042, "Thingy Quaternion"),
+    (0xef680405_9b35_4933_9b10_52ffa9740042, "Thingy Pedometer"),
+    (0xef680406_9b35_4933_9b10_52ffa9740042, "Thingy Raw Data"),
+    (0xef680407_9b35_4933_9b10_52ffa9740042, "Thingy Euler"),
+    (0xef680408_9b35_4933_9b10_52ffa9740042, "Thingy Rotation Matrix"),
+    (0xef680409_9b35_4933_9b10_52ffa9740042, "Thingy Heading"),
+    (0xef68040a_9b35_4933_9b10_52ffa9740042, "Thingy Gravity Vector"),
+    (0xef680500_9b35_4933_9b10_52ffa9740042, "Thingy Sound Service"),
+    (0xef680501_9b35_4933_9b10_52ffa9740042, "Thingy Sound Config"),
+    (0xef680502_9b35_4933_9b10_52ffa9740042, "Thingy Speaker Data"),
+    (0xef680503_9b35_4933_9b10_52ffa9740042, "Thingy Speaker Status"),
+    (0xef680504_9b35_4933_9b10_52ffa9740042, "Thingy Microphone"),
+    (0xf000ffc0_0451_4000_b000_000000000000, "Texas Instruments Over-the-Air Download (OAD) Service"),
+    (0xf000ffc1_0451_4000_b000_000000000000, "Texas Instruments Image Identify"),
+    (0xf000ffc2_0451_4000_b000_000000000000, "Texas Instruments Image Block"),
+    (0xf000ffc5_0451_4000_b000_000000000000, "Texas Instruments OAD Control"),
+    (0xfe2c1233_8366_4814_8eb0_01de32100bea, "Fast Pair Model ID"),
+    (0xfe2c1234_8366_4814_8eb0_01de32100bea, "Fast Pair Key-based Pairing"),
+    (0xfe2c1235_8366_4814_8eb0_01de32100bea, "Fast Pair Passkey"),
+    (0xfe2c1236_8366_4814_8eb0_01de32100bea, "Fast Pair Account Key"),
+    (0xfe2c1237_8366_4814_8eb0_01de32100bea, "Fast Pair Data"),
+];
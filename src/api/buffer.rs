@@ -0,0 +1,230 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::ValueNotification;
+use crate::rt::{self, Task};
+use futures::stream::{Stream, StreamExt};
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+};
+use tokio::sync::Notify;
+
+/// What a [`NotificationBuffer`] does with a new notification when it is full.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum OverflowPolicy {
+    /// Drop the oldest buffered notification to make room for the new one.
+    DropOldest,
+    /// Drop the new notification, keeping the ones already buffered.
+    DropNewest,
+    /// Stop taking notifications from the platform until there is room. Nothing is dropped by the
+    /// buffer, but values then queue up in the platform's own buffer, which may drop them without
+    /// them being counted.
+    Block,
+}
+
+/// How the stream returned by
+/// [`Peripheral::notifications_buffered`](super::Peripheral::notifications_buffered) buffers
+/// notifications which haven't been read yet.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct NotificationBuffer {
+    /// The maximum number of notifications to hold. A capacity of 0 is treated as 1.
+    pub capacity: usize,
+    /// What to do when a notification arrives while the buffer is full.
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for NotificationBuffer {
+    fn default() -> Self {
+        NotificationBuffer {
+            capacity: 256,
+            overflow: OverflowPolicy::DropOldest,
+        }
+    }
+}
+
+struct Shared {
+    queue: Mutex<VecDeque<ValueNotification>>,
+    readable: Notify,
+    writable: Notify,
+    closed: AtomicBool,
+    dropped: AtomicU64,
+}
+
+/// A stream of notifications with its own bounded buffer, which counts how many notifications it
+/// has had to drop.
+///
+/// Notifications are taken from the platform as soon as they arrive, even while the stream isn't
+/// being polled, by a background task. With the `runtime-tokio` feature, this must be created
+/// within a Tokio runtime.
+pub struct BufferedNotifications {
+    shared: Arc<Shared>,
+    stream: Pin<Box<dyn Stream<Item = ValueNotification> + Send>>,
+    task: Task,
+}
+
+impl BufferedNotifications {
+    pub(crate) fn new(
+        mut source: Pin<Box<dyn Stream<Item = ValueNotification> + Send>>,
+        buffer: NotificationBuffer,
+    ) -> Self {
+        let capacity = buffer.capacity.max(1);
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(VecDeque::with_capacity(capacity)),
+            readable: Notify::new(),
+            writable: Notify::new(),
+            closed: AtomicBool::new(false),
+            dropped: AtomicU64::new(0),
+        });
+
+        let producer = shared.clone();
+        let task = rt::spawn(async move {
+            while let Some(notification) = source.next().await {
+                loop {
+                    {
+                        let mut queue = producer.queue.lock().unwrap();
+                        if queue.len() < capacity {
+                            queue.push_back(notification);
+                            break;
+                        }
+                        match buffer.overflow {
+                            OverflowPolicy::DropOldest => {
+                                queue.pop_front();
+                                queue.push_back(notification);
+                                producer.dropped.fetch_add(1, Ordering::Relaxed);
+                                break;
+                            }
+                            OverflowPolicy::DropNewest => {
+                                producer.dropped.fetch_add(1, Ordering::Relaxed);
+                                break;
+                            }
+                            OverflowPolicy::Block => {}
+                        }
+                    }
+                    producer.writable.notified().await;
+                }
+                producer.readable.notify_one();
+            }
+            producer.closed.store(true, Ordering::Release);
+            producer.readable.notify_one();
+        });
+
+        let consumer = shared.clone();
+        let stream = futures::stream::unfold(consumer, |consumer| async move {
+            loop {
+                let next = consumer.queue.lock().unwrap().pop_front();
+                if let Some(notification) = next {
+                    consumer.writable.notify_one();
+                    return Some((notification, consumer));
+                }
+                if consumer.closed.load(Ordering::Acquire) {
+                    // The producer may have pushed a last value before closing.
+                    let next = consumer.queue.lock().unwrap().pop_front();
+                    return next.map(|notification| (notification, consumer));
+                }
+                consumer.readable.notified().await;
+            }
+        });
+
+        BufferedNotifications {
+            shared,
+            stream: Box::pin(stream),
+            task,
+        }
+    }
+
+    /// Returns the number of notifications this stream has dropped because its buffer was full.
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Stream for BufferedNotifications {
+    type Item = ValueNotification;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.stream.as_mut().poll_next(cx)
+    }
+}
+
+impl Drop for BufferedNotifications {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::SubscriptionType;
+    use std::time::{Duration, Instant};
+    use uuid::Uuid;
+
+    fn source(count: u8) -> Pin<Box<dyn Stream<Item = ValueNotification> + Send>> {
+        Box::pin(futures::stream::iter((0..count).map(|i| {
+            ValueNotification {
+                uuid: Uuid::nil(),
+                service_uuid: Uuid::nil(),
+                handle: None,
+                subscription_type: SubscriptionType::Notify,
+                value: vec![i],
+                timestamp: Instant::now(),
+            }
+        })))
+    }
+
+    /// Waits for the background task to have taken everything from the source, which it does
+    /// concurrently with the test when it isn't run on the test's own Tokio runtime.
+    async fn filled(stream: &BufferedNotifications) {
+        while !stream.shared.closed.load(Ordering::Acquire) {
+            rt::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    async fn values(stream: &mut BufferedNotifications) -> Vec<u8> {
+        let mut values = vec![];
+        while let Some(notification) = stream.next().await {
+            values.extend(notification.value);
+        }
+        values
+    }
+
+    fn buffer(overflow: OverflowPolicy) -> NotificationBuffer {
+        NotificationBuffer {
+            capacity: 3,
+            overflow,
+        }
+    }
+
+    #[tokio::test]
+    async fn drop_oldest() {
+        let mut stream = BufferedNotifications::new(source(10), buffer(OverflowPolicy::DropOldest));
+        filled(&stream).await;
+        assert_eq!(values(&mut stream).await, vec![7, 8, 9]);
+        assert_eq!(stream.dropped(), 7);
+    }
+
+    #[tokio::test]
+    async fn drop_newest() {
+        let mut stream = BufferedNotifications::new(source(10), buffer(OverflowPolicy::DropNewest));
+        filled(&stream).await;
+        assert_eq!(values(&mut stream).await, vec![0, 1, 2]);
+        assert_eq!(stream.dropped(), 7);
+    }
+
+    #[tokio::test]
+    async fn block() {
+        let mut stream = BufferedNotifications::new(source(10), buffer(OverflowPolicy::Block));
+        assert_eq!(values(&mut stream).await, (0..10).collect::<Vec<_>>());
+        assert_eq!(stream.dropped(), 0);
+    }
+}
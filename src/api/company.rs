@@ -0,0 +1,90 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Names for the company identifiers assigned by the Bluetooth SIG, which are used as the keys of
+//! [`PeripheralProperties::manufacturer_data`].
+//!
+//! This module is only available with the `company-ids` feature, as the table adds to binary
+//! size.
+//!
+//! ```
+//! use btleplug::api::company::company_name;
+//!
+//! assert_eq!(company_name(0x004C), Some("Apple, Inc."));
+//! ```
+
+mod ids;
+
+use super::PeripheralProperties;
+
+/// Returns the name of the company with the given Bluetooth SIG company identifier, if it is
+/// known.
+pub fn company_name(company_id: u16) -> Option<&'static str> {
+    ids::COMPANY_IDS
+        .binary_search_by_key(&company_id, |&(id, _)| id)
+        .ok()
+        .map(|index| ids::COMPANY_IDS[index].1)
+}
+
+/// A single manufacturer specific data entry from a peripheral's advertisements.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct ManufacturerData<'a> {
+    /// The Bluetooth SIG company identifier of the manufacturer.
+    pub company_id: u16,
+    /// The manufacturer specific data.
+    pub data: &'a [u8],
+}
+
+impl ManufacturerData<'_> {
+    /// Returns the name of the manufacturer, if it is known.
+    pub fn company_name(&self) -> Option<&'static str> {
+        company_name(self.company_id)
+    }
+}
+
+impl PeripheralProperties {
+    /// Returns the manufacturer specific data entries of this peripheral, sorted by company
+    /// identifier.
+    pub fn manufacturer_data_entries(&self) -> Vec<ManufacturerData<'_>> {
+        let mut entries: Vec<_> = self
+            .manufacturer_data
+            .iter()
+            .map(|(&company_id, data)| ManufacturerData { company_id, data })
+            .collect();
+        entries.sort_by_key(|entry| entry.company_id);
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_is_sorted() {
+        assert!(ids::COMPANY_IDS.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn lookup() {
+        assert_eq!(company_name(0x0000), Some("Ericsson Technology Licensing"));
+        assert_eq!(company_name(0x0059), Some("Nordic Semiconductor ASA"));
+        assert_eq!(company_name(0xFFFE), None);
+    }
+
+    #[test]
+    fn manufacturer_data_entries() {
+        let mut properties = PeripheralProperties::default();
+        properties.manufacturer_data.insert(0x0059, vec![2].into());
+        properties.manufacturer_data.insert(0x004C, vec![1].into());
+        let entries = properties.manufacturer_data_entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].company_name(), Some("Apple, Inc."));
+        assert_eq!(entries[0].data, &[1]);
+        assert_eq!(entries[1].company_id, 0x0059);
+    }
+}
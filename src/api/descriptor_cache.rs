@@ -0,0 +1,66 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! The values of a peripheral's descriptors as last read or written, which backends fill in to
+//! the descriptors [`Peripheral::services`](super::Peripheral::services) returns.
+
+use super::{Characteristic, Descriptor, Service};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DescriptorCache {
+    // Descriptors are compared without their values, so this finds a descriptor whichever value it
+    // has.
+    values: Arc<Mutex<BTreeMap<Descriptor, Vec<u8>>>>,
+}
+
+impl DescriptorCache {
+    /// Remembers the value just read from or written to `descriptor`.
+    pub(crate) fn insert(&self, descriptor: &Descriptor, value: &[u8]) {
+        let mut descriptor = descriptor.clone();
+        descriptor.value = None;
+        self.values
+            .lock()
+            .unwrap()
+            .insert(descriptor, value.to_vec());
+    }
+
+    /// Fills in the values of the characteristic's descriptors.
+    pub(crate) fn fill_characteristic(&self, mut characteristic: Characteristic) -> Characteristic {
+        let values = self.values.lock().unwrap();
+        if values.is_empty() {
+            return characteristic;
+        }
+        characteristic.descriptors = characteristic
+            .descriptors
+            .into_iter()
+            .map(|mut descriptor| {
+                if let Some(value) = values.get(&descriptor) {
+                    descriptor.value = Some(value.clone());
+                }
+                descriptor
+            })
+            .collect();
+        characteristic
+    }
+
+    /// Fills in the values of the descriptors of every characteristic of the services.
+    pub(crate) fn fill(&self, services: BTreeSet<Service>) -> BTreeSet<Service> {
+        services
+            .into_iter()
+            .map(|mut service| {
+                service.characteristics = service
+                    .characteristics
+                    .into_iter()
+                    .map(|characteristic| self.fill_characteristic(characteristic))
+                    .collect();
+                service
+            })
+            .collect()
+    }
+}
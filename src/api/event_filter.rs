@@ -0,0 +1,118 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Picking out the events which match an [`EventFilter`], for
+//! [`Central::events_filtered`](super::Central::events_filtered).
+
+use super::{Central, CentralEvent, EventFilter, Peripheral};
+use futures::channel::mpsc;
+use futures::stream::{Stream, StreamExt};
+use log::trace;
+use std::collections::HashSet;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// How many events a filtered subscriber can fall behind by before they are lost.
+const CHANNEL_CAPACITY: usize = 256;
+
+/// The subscribers to an adapter's events which only want some of them. An event is only cloned
+/// for the subscribers whose filter its kind and peripheral match.
+#[derive(Debug, Default)]
+pub(crate) struct FilteredSubscribers {
+    subscribers: Mutex<Vec<(EventFilter, mpsc::Sender<CentralEvent>)>>,
+}
+
+// Only used by some backends, so turn off deadcode so we don't get warnings on the other platforms.
+#[allow(dead_code)]
+impl FilteredSubscribers {
+    /// Sends the event to the subscribers which want it. This is called for every event the
+    /// adapter emits.
+    pub(crate) fn send(&self, event: &CentralEvent) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain_mut(|(filter, sender)| {
+                if !filter.matches_kind_and_peripheral(event) {
+                    return !sender.is_closed();
+                }
+                match sender.try_send(event.clone()) {
+                    Ok(()) => true,
+                    Err(e) if e.is_full() => {
+                        trace!("Lost central event, while the subscriber was behind");
+                        true
+                    }
+                    Err(_) => false,
+                }
+            });
+    }
+
+    /// Returns whether there are any subscribers, though they may have gone away since.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.subscribers.lock().unwrap().is_empty()
+    }
+
+    /// Returns a stream of the events which match the filter's kinds and peripherals. Its services
+    /// are left to [`by_services`].
+    pub(crate) fn subscribe(
+        &self,
+        filter: &EventFilter,
+    ) -> Pin<Box<dyn Stream<Item = CentralEvent> + Send>> {
+        let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        self.subscribers
+            .lock()
+            .unwrap()
+            .push((filter.clone(), sender));
+        Box::pin(receiver)
+    }
+}
+
+/// Passes on the events about peripherals which have advertised at least one of the services, or
+/// all of them if there are no services, along with events which aren't about a peripheral.
+pub(crate) fn by_services<C: Central + 'static>(
+    central: &C,
+    events: Pin<Box<dyn Stream<Item = CentralEvent> + Send>>,
+    services: Vec<Uuid>,
+) -> Pin<Box<dyn Stream<Item = CentralEvent> + Send>> {
+    if services.is_empty() {
+        return events;
+    }
+    // Peripherals don't stop advertising a service, so each only needs to match once.
+    let matched = Arc::new(Mutex::new(HashSet::new()));
+    let central = central.clone();
+    Box::pin(events.filter_map(move |event| {
+        let central = central.clone();
+        let services = services.clone();
+        let matched = matched.clone();
+        async move {
+            let Some(id) = event.peripheral_id().cloned() else {
+                return Some(event);
+            };
+            if matched.lock().unwrap().contains(&id) {
+                return Some(event);
+            }
+            let advertised = match &event {
+                CentralEvent::ServicesAdvertisement { services, .. } => services.clone(),
+                _ => {
+                    central
+                        .peripheral(&id)
+                        .await
+                        .ok()?
+                        .properties()
+                        .await
+                        .ok()??
+                        .services
+                }
+            };
+            if !advertised.iter().any(|uuid| services.contains(uuid)) {
+                return None;
+            }
+            matched.lock().unwrap().insert(id);
+            Some(event)
+        }
+    }))
+}
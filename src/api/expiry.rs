@@ -0,0 +1,57 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use crate::time::{Instant, SystemTime};
+use std::{sync::Mutex, time::Duration};
+
+/// How often at most an adapter's peripherals are checked for any which have expired.
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The TTL set with [`Central::set_peripheral_ttl`](super::Central::set_peripheral_ttl), and when
+/// the adapter's peripherals were last checked against it.
+#[derive(Debug, Default)]
+pub(crate) struct PeripheralExpiry {
+    ttl: Mutex<Option<Duration>>,
+    last_check: Mutex<Option<Instant>>,
+}
+
+impl PeripheralExpiry {
+    pub(crate) fn set_ttl(&self, ttl: Option<Duration>) {
+        *self.ttl.lock().unwrap() = ttl;
+    }
+
+    /// Returns the time before which a disconnected peripheral must have last been seen for it to
+    /// be removed, if a TTL is set and it's time to check again. Checking goes through all of the
+    /// adapter's peripherals, so it's done at most once a second, or once per TTL if that's less.
+    pub(crate) fn cutoff(&self) -> Option<SystemTime> {
+        let ttl = (*self.ttl.lock().unwrap())?;
+        let mut last_check = self.last_check.lock().unwrap();
+        let now = Instant::now();
+        if last_check.is_some_and(|last| now - last < ttl.min(EXPIRY_INTERVAL)) {
+            return None;
+        }
+        *last_check = Some(now);
+        SystemTime::now().checked_sub(ttl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cutoff() {
+        let expiry = PeripheralExpiry::default();
+        assert_eq!(expiry.cutoff(), None);
+
+        expiry.set_ttl(Some(Duration::from_secs(60)));
+        let cutoff = expiry.cutoff().unwrap();
+        assert!(cutoff <= SystemTime::now() - Duration::from_secs(60));
+        // It isn't time to check again yet.
+        assert_eq!(expiry.cutoff(), None);
+    }
+}
@@ -0,0 +1,90 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Samples of how well the connection to a peripheral is doing, from
+//! [`Peripheral::link_events`], for example to tell the user to
+//! move closer before the connection drops.
+//!
+//! ```no_run
+//! use btleplug::api::Peripheral;
+//! use futures::stream::StreamExt;
+//! use std::time::Duration;
+//!
+//! async fn warn_when_weak(peripheral: &impl Peripheral) -> btleplug::Result<()> {
+//!     let mut samples = peripheral.link_events(Duration::from_secs(2)).await?;
+//!     while let Some(sample) = samples.next().await {
+//!         if sample.rssi.is_some_and(|rssi| rssi < -85)
+//!             || sample.since_last_response > Some(Duration::from_secs(3))
+//!         {
+//!             println!("Signal weak, move closer");
+//!         }
+//!     }
+//!     println!("Disconnected");
+//!     Ok(())
+//! }
+//! ```
+
+use super::operation::OperationContext;
+use super::Peripheral;
+use crate::time::Instant;
+use futures::stream::{self, Stream};
+use std::pin::Pin;
+use std::time::Duration;
+
+/// A sample of how well the connection to a peripheral is doing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LinkSample {
+    /// When the sample was taken.
+    pub timestamp: Instant,
+    /// The signal strength in dBm. CoreBluetooth and Android read it from the connection; with
+    /// other platforms it's from the last advertisement, if the peripheral still advertises while
+    /// connected.
+    pub rssi: Option<i16>,
+    /// How long the peripheral took to answer the most recent GATT operation, if there has been
+    /// one.
+    pub latency: Option<Duration>,
+    /// How long it's been since the peripheral last answered a GATT operation.
+    ///
+    /// No platform tells applications when a link's supervision timeout is about to expire, so
+    /// this growing while operations are outstanding is the closest warning there is.
+    pub since_last_response: Option<Duration>,
+}
+
+/// Returns a stream of samples of the connection to `peripheral`, whose operations go through
+/// `operation_context`, one every `interval` starting now, which ends once it disconnects.
+pub(crate) fn samples<P: Peripheral + 'static>(
+    peripheral: P,
+    operation_context: OperationContext,
+    interval: Duration,
+) -> Pin<Box<dyn Stream<Item = LinkSample> + Send>> {
+    Box::pin(stream::unfold(
+        (peripheral, operation_context, false),
+        move |(peripheral, operation_context, started)| async move {
+            if started {
+                crate::rt::sleep(interval).await;
+            }
+            if !peripheral.is_connected().await.unwrap_or(false) {
+                return None;
+            }
+            let rssi = peripheral
+                .refresh_properties()
+                .await
+                .ok()
+                .flatten()
+                .and_then(|properties| properties.rssi);
+            let last_response = operation_context.last_response();
+            let sample = LinkSample {
+                timestamp: Instant::now(),
+                rssi,
+                latency: last_response.map(|(_, latency)| latency),
+                since_last_response: last_response.map(|(at, _)| at.elapsed()),
+            };
+            Some((sample, (peripheral, operation_context, true)))
+        },
+    ))
+}
@@ -0,0 +1,372 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! The GATT bearer of Bluetooth Mesh, through which a device without a mesh radio of its own
+//! provisions nodes or talks to a mesh network via a proxy node.
+//!
+//! A [`ProxyBearer`] sends and receives proxy PDUs over the Mesh Provisioning Service of an
+//! unprovisioned device, or the Mesh Proxy Service of a proxy node. PDUs longer than a write are
+//! split into segments, and segments received are put back together. What's in the PDUs, such as
+//! encrypted network PDUs or provisioning messages, is up to the application.
+//!
+//! ```no_run
+//! use btleplug::api::mesh::{MeshService, PduType, ProxyBearer, ProxyPdu};
+//! use futures::StreamExt;
+//! # use btleplug::api::Peripheral;
+//! # async fn provision(peripheral: impl Peripheral + 'static) -> btleplug::Result<()> {
+//! peripheral.connect().await?;
+//! let mut bearer = ProxyBearer::new(peripheral, MeshService::Provisioning).await?;
+//! // A Provisioning Invite with an attention duration of 5 seconds.
+//! bearer
+//!     .send(&ProxyPdu::new(PduType::Provisioning, vec![0x00, 0x05]))
+//!     .await?;
+//! if let Some(capabilities) = bearer.next().await {
+//!     println!("{:?}", capabilities.data);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use super::{
+    bleuuid::uuid_from_u16, negotiate_write_len, standard::find_characteristic, Characteristic,
+    Peripheral, WriteType,
+};
+use crate::Result;
+use futures::stream::{Stream, StreamExt};
+use std::{
+    fmt::{self, Debug, Formatter},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+use uuid::Uuid;
+
+/// The UUID of the Mesh Provisioning Service, of devices waiting to be provisioned.
+pub const MESH_PROVISIONING_SERVICE_UUID: Uuid = uuid_from_u16(0x1827);
+pub const MESH_PROVISIONING_DATA_IN_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2ADB);
+pub const MESH_PROVISIONING_DATA_OUT_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2ADC);
+
+/// The UUID of the Mesh Proxy Service, of nodes which relay between GATT and the mesh network.
+pub const MESH_PROXY_SERVICE_UUID: Uuid = uuid_from_u16(0x1828);
+pub const MESH_PROXY_DATA_IN_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2ADD);
+pub const MESH_PROXY_DATA_OUT_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2ADE);
+
+/// The segmentation and reassembly field of a proxy PDU's header.
+const SAR_COMPLETE: u8 = 0b00;
+const SAR_FIRST: u8 = 0b01;
+const SAR_CONTINUATION: u8 = 0b10;
+const SAR_LAST: u8 = 0b11;
+
+/// Which service a [`ProxyBearer`] uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MeshService {
+    /// The Mesh Provisioning Service, for provisioning the device.
+    Provisioning,
+    /// The Mesh Proxy Service, for talking to the mesh network through the device.
+    Proxy,
+}
+
+impl MeshService {
+    /// The UUIDs of the service, its Data In characteristic and its Data Out characteristic.
+    fn uuids(self) -> (Uuid, Uuid, Uuid) {
+        match self {
+            MeshService::Provisioning => (
+                MESH_PROVISIONING_SERVICE_UUID,
+                MESH_PROVISIONING_DATA_IN_CHARACTERISTIC_UUID,
+                MESH_PROVISIONING_DATA_OUT_CHARACTERISTIC_UUID,
+            ),
+            MeshService::Proxy => (
+                MESH_PROXY_SERVICE_UUID,
+                MESH_PROXY_DATA_IN_CHARACTERISTIC_UUID,
+                MESH_PROXY_DATA_OUT_CHARACTERISTIC_UUID,
+            ),
+        }
+    }
+}
+
+/// What a proxy PDU carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PduType {
+    Network,
+    MeshBeacon,
+    ProxyConfiguration,
+    Provisioning,
+    /// A type which the specification reserves.
+    Other(u8),
+}
+
+impl From<u8> for PduType {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => PduType::Network,
+            0x01 => PduType::MeshBeacon,
+            0x02 => PduType::ProxyConfiguration,
+            0x03 => PduType::Provisioning,
+            other => PduType::Other(other),
+        }
+    }
+}
+
+impl From<PduType> for u8 {
+    fn from(pdu_type: PduType) -> Self {
+        match pdu_type {
+            PduType::Network => 0x00,
+            PduType::MeshBeacon => 0x01,
+            PduType::ProxyConfiguration => 0x02,
+            PduType::Provisioning => 0x03,
+            PduType::Other(other) => other & 0x3f,
+        }
+    }
+}
+
+/// A whole proxy PDU, after reassembly.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProxyPdu {
+    pub pdu_type: PduType,
+    pub data: Vec<u8>,
+}
+
+impl ProxyPdu {
+    pub fn new(pdu_type: PduType, data: Vec<u8>) -> Self {
+        ProxyPdu { pdu_type, data }
+    }
+
+    /// Splits the PDU into segments of at most `max_len` bytes, header included.
+    fn segments(&self, max_len: usize) -> Vec<Vec<u8>> {
+        let pdu_type = u8::from(self.pdu_type);
+        let capacity = max_len.max(2) - 1;
+        if self.data.len() <= capacity {
+            let mut segment = vec![SAR_COMPLETE << 6 | pdu_type];
+            segment.extend_from_slice(&self.data);
+            return vec![segment];
+        }
+        let chunks: Vec<&[u8]> = self.data.chunks(capacity).collect();
+        let last = chunks.len() - 1;
+        chunks
+            .into_iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let sar = match i {
+                    0 => SAR_FIRST,
+                    i if i == last => SAR_LAST,
+                    _ => SAR_CONTINUATION,
+                };
+                let mut segment = vec![sar << 6 | pdu_type];
+                segment.extend_from_slice(chunk);
+                segment
+            })
+            .collect()
+    }
+}
+
+/// Puts segmented proxy PDUs back together.
+#[derive(Debug, Default)]
+struct Reassembler {
+    partial: Option<ProxyPdu>,
+}
+
+impl Reassembler {
+    /// Adds a received segment, and returns the PDU if it's now complete. A segment which doesn't
+    /// continue the PDU being reassembled discards it, as the specification requires.
+    fn push(&mut self, segment: &[u8]) -> Option<ProxyPdu> {
+        let (&header, data) = segment.split_first()?;
+        let pdu_type = PduType::from(header & 0x3f);
+        match header >> 6 {
+            SAR_COMPLETE => {
+                self.partial = None;
+                Some(ProxyPdu::new(pdu_type, data.to_vec()))
+            }
+            SAR_FIRST => {
+                self.partial = Some(ProxyPdu::new(pdu_type, data.to_vec()));
+                None
+            }
+            sar => {
+                let mut partial = self.partial.take()?;
+                if partial.pdu_type != pdu_type {
+                    return None;
+                }
+                partial.data.extend_from_slice(data);
+                if sar == SAR_LAST {
+                    Some(partial)
+                } else {
+                    self.partial = Some(partial);
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// The GATT bearer to a connected mesh device. Received PDUs are read from it as a [`Stream`],
+/// which never ends, even if the device disconnects; sending then fails with
+/// [`Error::NotConnected`](crate::Error::NotConnected). Anything received before the stream is
+/// read is buffered.
+pub struct ProxyBearer<P> {
+    peripheral: P,
+    data_in: Characteristic,
+    max_write_len: usize,
+    incoming: Pin<Box<dyn Stream<Item = Vec<u8>> + Send>>,
+    reassembler: Reassembler,
+}
+
+impl<P: Peripheral + 'static> ProxyBearer<P> {
+    /// Finds the service's characteristics on a connected peripheral, discovering the service if
+    /// it hasn't been yet, and subscribes to its Data Out characteristic. The largest MTU the
+    /// platform allows is asked for where that's supported.
+    pub async fn new(peripheral: P, service: MeshService) -> Result<ProxyBearer<P>> {
+        let (service_uuid, data_in_uuid, data_out_uuid) = service.uuids();
+        let data_in = find_characteristic(&peripheral, service_uuid, data_in_uuid).await?;
+        let data_out = find_characteristic(&peripheral, service_uuid, data_out_uuid).await?;
+        let max_write_len = negotiate_write_len(&peripheral).await;
+        let incoming = peripheral.subscribe_stream(&data_out).await?;
+        Ok(ProxyBearer {
+            peripheral,
+            data_in,
+            max_write_len,
+            incoming,
+            reassembler: Reassembler::default(),
+        })
+    }
+
+    /// The peripheral the bearer is to.
+    pub fn peripheral(&self) -> &P {
+        &self.peripheral
+    }
+
+    /// Sends a PDU, in as many segments as it takes.
+    pub async fn send(&self, pdu: &ProxyPdu) -> Result<()> {
+        for segment in pdu.segments(self.max_write_len) {
+            self.peripheral
+                .write(&self.data_in, &segment, WriteType::WithoutResponse)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+// Nothing is pinned in place; the stream is boxed.
+impl<P> Unpin for ProxyBearer<P> {}
+
+impl<P> Stream for ProxyBearer<P> {
+    type Item = ProxyPdu;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ProxyPdu>> {
+        let this = self.get_mut();
+        loop {
+            let Some(segment) = ready!(this.incoming.poll_next_unpin(cx)) else {
+                return Poll::Ready(None);
+            };
+            if let Some(pdu) = this.reassembler.push(&segment) {
+                return Poll::Ready(Some(pdu));
+            }
+        }
+    }
+}
+
+impl<P: Debug> Debug for ProxyBearer<P> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("ProxyBearer")
+            .field("peripheral", &self.peripheral)
+            .field("data_in", &self.data_in.uuid)
+            .field("max_write_len", &self.max_write_len)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segmentation() {
+        let pdu = ProxyPdu::new(PduType::Provisioning, (0..10).collect());
+        let mut complete = vec![0x03];
+        complete.extend_from_slice(&pdu.data);
+        assert_eq!(pdu.segments(20), vec![complete]);
+        let segments = pdu.segments(5);
+        assert_eq!(
+            segments,
+            vec![
+                vec![0x43, 0, 1, 2, 3],
+                vec![0x83, 4, 5, 6, 7],
+                vec![0xc3, 8, 9],
+            ]
+        );
+
+        let mut reassembler = Reassembler::default();
+        assert_eq!(reassembler.push(&segments[0]), None);
+        assert_eq!(reassembler.push(&segments[1]), None);
+        assert_eq!(reassembler.push(&segments[2]), Some(pdu.clone()));
+        // A continuation without a first segment is dropped.
+        assert_eq!(reassembler.push(&segments[2]), None);
+        // As is one of another type.
+        assert_eq!(reassembler.push(&segments[0]), None);
+        assert_eq!(reassembler.push(&[0xc0, 8, 9]), None);
+        assert_eq!(
+            reassembler.push(&[0x01, 0xaa]),
+            Some(ProxyPdu::new(PduType::MeshBeacon, vec![0xaa]))
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn send_and_receive() -> Result<()> {
+        use crate::api::{
+            Central, CharPropFlags, Descriptor, ScanFilter, Service,
+            CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
+        };
+        use crate::mock::Manager;
+
+        let manager = Manager::new().await?;
+        let adapter = manager.add_adapter();
+        adapter.start_scan(ScanFilter::default()).await?;
+        let data_in = Characteristic {
+            uuid: MESH_PROXY_DATA_IN_CHARACTERISTIC_UUID,
+            service_uuid: MESH_PROXY_SERVICE_UUID,
+            properties: CharPropFlags::WRITE_WITHOUT_RESPONSE,
+            descriptors: Default::default(),
+            handle: None,
+            value_handle: None,
+        };
+        let data_out = Characteristic {
+            uuid: MESH_PROXY_DATA_OUT_CHARACTERISTIC_UUID,
+            service_uuid: MESH_PROXY_SERVICE_UUID,
+            properties: CharPropFlags::NOTIFY,
+            descriptors: [Descriptor {
+                uuid: CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
+                service_uuid: MESH_PROXY_SERVICE_UUID,
+                characteristic_uuid: MESH_PROXY_DATA_OUT_CHARACTERISTIC_UUID,
+                handle: None,
+                value: None,
+            }]
+            .into(),
+            handle: None,
+            value_handle: None,
+        };
+        let device = adapter.advertise(Default::default()).unwrap();
+        device.set_services([Service {
+            uuid: MESH_PROXY_SERVICE_UUID,
+            primary: true,
+            characteristics: [data_in.clone(), data_out.clone()].into(),
+            included_services: Default::default(),
+            handle: None,
+        }]);
+        device.connect().await?;
+
+        let mut bearer = ProxyBearer::new(device.clone(), MeshService::Proxy).await?;
+        let pdu = ProxyPdu::new(PduType::Network, vec![1, 2, 3]);
+        bearer.send(&pdu).await?;
+        assert_eq!(device.value(&data_in), Some(vec![0x00, 1, 2, 3]));
+
+        assert!(device.notify(&data_out, [0x42, 1, 2]));
+        assert!(device.notify(&data_out, [0xc2, 3]));
+        assert_eq!(
+            bearer.next().await,
+            Some(ProxyPdu::new(PduType::ProxyConfiguration, vec![1, 2, 3]))
+        );
+        Ok(())
+    }
+}
@@ -21,25 +21,65 @@
 //! use btleplug::platform::{Adapter, Manager, Peripheral};
 //! ```
 
+pub mod advertiser;
+pub mod appearance;
+pub(crate) mod att;
 pub(crate) mod bdaddr;
+pub mod beacon;
 pub mod bleuuid;
+pub(crate) mod buffer;
+#[cfg(feature = "company-ids")]
+pub mod company;
+pub(crate) mod descriptor_cache;
+pub(crate) mod event_filter;
+pub(crate) mod expiry;
+pub mod link;
+pub mod mesh;
+pub mod monitor;
+pub(crate) mod native_error;
+pub mod observer;
+pub(crate) mod operation;
+#[cfg(feature = "ota")]
+pub mod ota;
+pub mod pairing;
+pub(crate) mod peripheral_events;
+pub(crate) mod peripheral_store;
+#[cfg(feature = "profiles")]
+pub mod profiles;
+pub mod proximity;
+pub(crate) mod retry;
+pub mod rpa;
+pub mod rssi;
+pub(crate) mod scan_response;
+pub(crate) mod spans;
+pub mod standard;
+pub mod uart;
 
+use crate::time::{Instant, SystemTime};
 use crate::Result;
 use async_trait::async_trait;
 use bitflags::bitflags;
-use futures::stream::Stream;
+use futures::stream::{self, Stream, StreamExt};
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "serde")]
 use serde_cr as serde;
 use std::{
-    collections::{BTreeSet, HashMap},
+    collections::{BTreeSet, HashMap, HashSet},
     fmt::{self, Debug, Display, Formatter},
     pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 use uuid::Uuid;
 
+pub use self::att::AttError;
 pub use self::bdaddr::{BDAddr, ParseBDAddrError};
+pub use self::buffer::{BufferedNotifications, NotificationBuffer, OverflowPolicy};
+pub use self::native_error::NativeError;
+pub use self::retry::{Backoff, RetryPolicy, Retrying};
+
+use self::appearance::Appearance;
 
 use crate::platform::PeripheralId;
 
@@ -81,17 +121,100 @@ impl AddressType {
     }
 }
 
+/// The kind of a random Bluetooth address, as returned by [`BDAddr::random_address_kind`].
+///
+/// Devices using resolvable or non-resolvable private addresses change their address regularly,
+/// so they can't be reconnected to by address alone.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum RandomAddressKind {
+    /// A random address which stays the same at least until the device is power cycled.
+    Static,
+    /// A resolvable private address (RPA), which changes periodically but can be resolved to the
+    /// device's identity with its identity resolving key.
+    Resolvable,
+    /// A non-resolvable private address (NRPA), which changes periodically and can't be traced
+    /// back to the device.
+    NonResolvable,
+}
+
+/// The kind of advertising PDU most recently received from a peripheral, as reported by the
+/// operating system. This determines whether the device can be connected to or scanned.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum AdvertisementType {
+    /// Connectable and scannable undirected advertising (`ADV_IND`).
+    ConnectableUndirected,
+    /// Connectable directed advertising (`ADV_DIRECT_IND`).
+    ConnectableDirected,
+    /// Scannable but non-connectable undirected advertising (`ADV_SCAN_IND`).
+    ScannableUndirected,
+    /// Non-connectable, non-scannable undirected advertising (`ADV_NONCONN_IND`), as used by
+    /// broadcast-only devices such as beacons.
+    NonConnectableUndirected,
+    /// A scan response (`SCAN_RSP`) to an active scan request.
+    ScanResponse,
+}
+
+impl AdvertisementType {
+    /// Returns true if a central may connect in response to this kind of advertisement.
+    pub fn is_connectable(&self) -> bool {
+        matches!(
+            self,
+            AdvertisementType::ConnectableUndirected | AdvertisementType::ConnectableDirected
+        )
+    }
+
+    /// Returns true if the advertiser accepts scan requests for this kind of advertisement.
+    pub fn is_scannable(&self) -> bool {
+        matches!(
+            self,
+            AdvertisementType::ConnectableUndirected | AdvertisementType::ScannableUndirected
+        )
+    }
+
+    /// Returns true if the advertisement was directed at a specific central.
+    pub fn is_directed(&self) -> bool {
+        matches!(self, AdvertisementType::ConnectableDirected)
+    }
+}
+
 /// A notification sent from a peripheral due to a change in a value.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ValueNotification {
     /// UUID of the characteristic that fired the notification.
     pub uuid: Uuid,
+    /// UUID of the service containing the characteristic.
+    pub service_uuid: Uuid,
+    /// The handle of the characteristic's value attribute on the device, which tells apart
+    /// characteristics with the same UUID. This is the same as [`Characteristic::value_handle`],
+    /// and is `None` on CoreBluetooth and Web Bluetooth, which don't expose handles.
+    pub handle: Option<u16>,
+    /// Whether the value was sent as a notification or as an indication. Indications are
+    /// acknowledged to the device by the operating system before they are delivered here.
+    pub subscription_type: SubscriptionType,
     /// The new value of the characteristic.
     pub value: Vec<u8>,
+    /// When the notification was received, as close to the operating system delivering it as
+    /// btleplug can get.
+    pub timestamp: Instant,
 }
 
 bitflags! {
     /// A set of properties that indicate what operations are supported by a Characteristic.
+    #[cfg_attr(
+        feature = "serde",
+        derive(Serialize, Deserialize),
+        serde(crate = "serde_cr", transparent)
+    )]
     #[derive(Default, Debug, PartialEq, Eq, Ord, PartialOrd, Clone, Copy)]
     pub struct CharPropFlags: u8 {
         const BROADCAST = 0x01;
@@ -105,8 +228,33 @@ bitflags! {
     }
 }
 
+/// The UUID of the Client Characteristic Configuration Descriptor (CCCD), which controls whether
+/// a characteristic sends notifications or indications.
+pub const CLIENT_CHARACTERISTIC_CONFIGURATION_UUID: Uuid = bleuuid::uuid_from_u16(0x2902);
+
+/// The UUID of the Generic Access (GAP) service, which has the device's name and appearance.
+pub const GENERIC_ACCESS_SERVICE_UUID: Uuid = bleuuid::uuid_from_u16(0x1800);
+
+/// The UUID of the Device Name characteristic of the Generic Access service.
+pub const DEVICE_NAME_CHARACTERISTIC_UUID: Uuid = bleuuid::uuid_from_u16(0x2A00);
+
+bitflags! {
+    /// The value of a Client Characteristic Configuration Descriptor, which says whether the
+    /// device will send notifications or indications for a characteristic.
+    #[derive(Default, Debug, PartialEq, Eq, Hash, Clone, Copy)]
+    pub struct ClientConfiguration: u16 {
+        const NOTIFY = 0x0001;
+        const INDICATE = 0x0002;
+    }
+}
+
 /// A GATT service. Services are groups of characteristics, which may be standard or
 /// device-specific.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
 #[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Clone)]
 pub struct Service {
     /// The UUID for this service.
@@ -115,6 +263,13 @@ pub struct Service {
     pub primary: bool,
     /// The characteristics of this service.
     pub characteristics: BTreeSet<Characteristic>,
+    /// The UUIDs of the services this service includes. Included services are discovered along
+    /// with the services which include them, and are also listed in [`Peripheral::services`].
+    pub included_services: BTreeSet<Uuid>,
+    /// The attribute handle of the service declaration, which is the first handle of the service.
+    /// None of the platforms expose where a service ends. This is `None` on CoreBluetooth and Web
+    /// Bluetooth, which don't expose handles.
+    pub handle: Option<u16>,
 }
 
 /// A Bluetooth characteristic. Characteristics are the main way you will interact with other
@@ -125,6 +280,11 @@ pub struct Service {
 ///
 /// A characteristic may be interacted with in various ways depending on its properties. You may be
 /// able to write to it, read from it, set its notify or indicate status, or send a command to it.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
 #[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Clone)]
 pub struct Characteristic {
     /// The UUID for this characteristic. This uniquely identifies its behavior.
@@ -137,6 +297,23 @@ pub struct Characteristic {
     pub properties: CharPropFlags,
     /// The descriptors of this characteristic.
     pub descriptors: BTreeSet<Descriptor>,
+    /// The attribute handle of the characteristic declaration. This is `None` on CoreBluetooth and
+    /// Web Bluetooth, which don't expose handles.
+    pub handle: Option<u16>,
+    /// The attribute handle of the characteristic's value, which is the handle used to read, write
+    /// and notify it. This always directly follows the declaration, and is `None` on
+    /// CoreBluetooth and Web Bluetooth.
+    pub value_handle: Option<u16>,
+}
+
+impl Characteristic {
+    /// Returns the characteristic's descriptor with the given UUID, which is its type. A
+    /// characteristic has at most one descriptor of each of the types the specification defines.
+    pub fn descriptor(&self, uuid: Uuid) -> Option<&Descriptor> {
+        self.descriptors
+            .iter()
+            .find(|descriptor| descriptor.uuid == uuid)
+    }
 }
 
 impl Display for Characteristic {
@@ -150,7 +327,12 @@ impl Display for Characteristic {
 }
 
 /// Add doc
-#[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone)]
 pub struct Descriptor {
     /// The UUID for this descriptor. This uniquely identifies its behavior.
     pub uuid: Uuid,
@@ -158,6 +340,48 @@ pub struct Descriptor {
     pub service_uuid: Uuid,
     /// The UUID of the characteristic this descriptor belongs to.
     pub characteristic_uuid: Uuid,
+    /// The attribute handle of the descriptor. This is `None` on CoreBluetooth and Web Bluetooth,
+    /// which don't expose handles, and on Android, which doesn't expose them for descriptors.
+    pub handle: Option<u16>,
+    /// The value last read from or written to the descriptor, or `None` if it hasn't been yet.
+    /// [`Peripheral::read_descriptor_cached`] reads it only if it's `None`.
+    ///
+    /// Descriptors are compared without their values, so that they can be looked up in the
+    /// [`Peripheral::services`] of a peripheral whichever value they have.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub value: Option<Vec<u8>>,
+}
+
+impl Descriptor {
+    /// The fields descriptors are compared by, which identify the descriptor.
+    fn key(&self) -> (Uuid, Uuid, Uuid, Option<u16>) {
+        (
+            self.uuid,
+            self.service_uuid,
+            self.characteristic_uuid,
+            self.handle,
+        )
+    }
+}
+
+impl PartialEq for Descriptor {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Descriptor {}
+
+impl PartialOrd for Descriptor {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Descriptor {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
 }
 
 impl Display for Descriptor {
@@ -177,23 +401,164 @@ impl Display for Descriptor {
 pub struct PeripheralProperties {
     /// The address of this peripheral
     pub address: BDAddr,
-    /// The type of address (either random or public)
+    /// The type of address (either random or public). See
+    /// [`random_address_kind`](PeripheralProperties::random_address_kind) for the kind of random
+    /// address.
     pub address_type: Option<AddressType>,
     /// The local name. This is generally a human-readable string that identifies the type of device.
     pub local_name: Option<String>,
-    /// The transmission power level for the device
+    /// The transmission power level for the device, in dBm, as given in its advertisements. See
+    /// [`proximity`] for estimating distance from this and `rssi`.
     pub tx_power_level: Option<i16>,
-    /// The most recent Received Signal Strength Indicator for the device
+    /// The most recent Received Signal Strength Indicator for the device, in dBm
     pub rssi: Option<i16>,
     /// Advertisement data specific to the device manufacturer. The keys of this map are
-    /// 'manufacturer IDs', while the values are arbitrary data.
-    pub manufacturer_data: HashMap<u16, Vec<u8>>,
+    /// 'manufacturer IDs', while the values are arbitrary data. The data is shared, so cloning the
+    /// properties doesn't copy it.
+    pub manufacturer_data: HashMap<u16, Arc<[u8]>>,
     /// Advertisement data specific to a service. The keys of this map are
-    /// 'Service UUIDs', while the values are arbitrary data.
-    pub service_data: HashMap<Uuid, Vec<u8>>,
+    /// 'Service UUIDs', while the values are arbitrary data, shared like the manufacturer data.
+    pub service_data: HashMap<Uuid, Arc<[u8]>>,
     /// Advertised services for this device
     pub services: Vec<Uuid>,
     pub class: Option<u32>,
+    /// The type of the most recent advertisement received from the device. Only some platforms
+    /// report the exact type; see `connectable` for a more widely available indication.
+    pub advertisement_type: Option<AdvertisementType>,
+    /// Whether the device's advertisements indicate that it accepts connections. This is `None`
+    /// if the platform does not report it.
+    pub connectable: Option<bool>,
+    /// The time at which the most recent advertisement from the device was received. This can be
+    /// used to age out devices which are no longer in range.
+    pub last_seen: Option<SystemTime>,
+    /// The GAP appearance of the device, if it has been advertised. The raw value is available
+    /// through [`Appearance::raw`].
+    pub appearance: Option<Appearance>,
+    /// Which of these values came from the scan response of the most recent advertising event,
+    /// rather than the advertisement. On Windows, where the two arrive separately, a scannable
+    /// advertisement is held back until its scan response arrives, so that the properties show
+    /// both at once.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub scan_response: ScanResponseParts,
+    /// The advertising data of the most recent advertising event as it was received, followed by
+    /// that of its scan response if there was one, so that formats btleplug doesn't parse can be
+    /// decoded. It's a series of AD structures, each a length byte, the AD type and its data,
+    /// which [`ad_structures`](Self::ad_structures) splits up. This is `None` where the platform
+    /// doesn't provide it, which is on macOS and iOS. BlueZ only provides the AD structures which
+    /// it doesn't parse into other properties itself.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub advertising_data: Option<Arc<[u8]>>,
+}
+
+impl PeripheralProperties {
+    /// Returns the kind of random address the peripheral is using, or `None` if it has a public
+    /// address or the platform doesn't report the address type.
+    pub fn random_address_kind(&self) -> Option<RandomAddressKind> {
+        match self.address_type? {
+            AddressType::Random => self.address.random_address_kind(),
+            AddressType::Public => None,
+        }
+    }
+
+    /// Returns the AD type and data of each AD structure in the
+    /// [`advertising_data`](Self::advertising_data). A truncated structure, or the zero padding
+    /// some platforms add, ends the data.
+    pub fn ad_structures(&self) -> impl Iterator<Item = (u8, &[u8])> {
+        ad_structures(self.advertising_data.as_deref().unwrap_or_default())
+    }
+}
+
+/// Splits advertising data into the AD type and data of each AD structure.
+pub(crate) fn ad_structures(mut data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    std::iter::from_fn(move || {
+        let [length, rest @ ..] = data else {
+            return None;
+        };
+        let length = usize::from(*length);
+        if length == 0 || length > rest.len() {
+            return None;
+        }
+        let (structure, rest) = rest.split_at(length);
+        data = rest;
+        let (&ad_type, value) = structure.split_first().unwrap();
+        Some((ad_type, value))
+    })
+}
+
+/// The values in a peripheral's [`PeripheralProperties`] which came from the scan response of its
+/// most recent advertising event. Only Windows and the `mock` backend report this; other platforms
+/// merge scan responses into advertisements without saying what came from where, so it's empty,
+/// and [`received`](Self::received) is `None`.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ScanResponseParts {
+    /// Whether a scan response was received for the most recent advertising event, or `None` if
+    /// the platform doesn't say. A scan response may be received and yet add nothing, in which
+    /// case this is `Some(true)` but the parts are empty.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub received: Option<bool>,
+    pub local_name: bool,
+    pub tx_power_level: bool,
+    pub appearance: bool,
+    /// The company IDs of the manufacturer data in the scan response.
+    pub manufacturer_data: Vec<u16>,
+    /// The UUIDs of the services whose data was in the scan response.
+    pub service_data: Vec<Uuid>,
+    pub services: Vec<Uuid>,
+}
+
+impl ScanResponseParts {
+    /// Returns whether nothing came from a scan response, either because none was received or
+    /// because it added nothing.
+    pub fn is_empty(&self) -> bool {
+        *self
+            == Self {
+                received: self.received,
+                ..Self::default()
+            }
+    }
+}
+
+/// A Bluetooth Classic (BR/EDR) device found while scanning with [`ScanFilter::classic`], as
+/// reported by [`CentralEvent::ClassicDeviceDiscovered`].
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ClassicDevice {
+    pub address: BDAddr,
+    /// The 24-bit Class of Device, which holds the major and minor device class and the kinds of
+    /// service the device offers.
+    pub class: Option<u32>,
+    /// The device's name, if the inquiry found it.
+    pub name: Option<String>,
+    /// The signal strength of the inquiry response, in dBm.
+    pub rssi: Option<i16>,
+}
+
+impl ClassicDevice {
+    pub fn new(address: BDAddr) -> Self {
+        ClassicDevice {
+            address,
+            class: None,
+            name: None,
+            rssi: None,
+        }
+    }
+
+    /// Returns the major device class from the Class of Device, such as `0x04` for audio and
+    /// video devices.
+    pub fn major_class(&self) -> Option<u8> {
+        self.class.map(|class| ((class >> 8) & 0x1f) as u8)
+    }
 }
 
 #[cfg_attr(
@@ -207,6 +572,160 @@ pub struct ScanFilter {
     /// If the filter contains at least one service UUID, only devices supporting at least one of
     /// the given services will be available.
     pub services: Vec<Uuid>,
+    /// Also look for Bluetooth Classic (BR/EDR) devices, which are reported with
+    /// [`CentralEvent::ClassicDeviceDiscovered`] rather than as peripherals. The service filter
+    /// doesn't apply to them.
+    ///
+    /// Only BlueZ and Windows can; elsewhere this is ignored. BlueZ also reports the devices it
+    /// finds as peripherals, though only dual-mode ones can be connected to.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub classic: bool,
+}
+
+/// How much of the time the adapter listens for advertisements while scanning. These are Android's
+/// scan modes.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ScanMode {
+    /// Listens a tenth of the time, which is Android's default.
+    LowPower,
+    /// Listens a quarter of the time.
+    Balanced,
+    /// Listens all the time, which finds devices quickest but uses the most power, and leaves the
+    /// least airtime for Wi-Fi on adapters which share an antenna with it.
+    LowLatency,
+    /// Shows the browser's device chooser, on WebAssembly, and adds the device the user picks. The
+    /// scan must be started while handling a user gesture, such as a click, and returns
+    /// [`Error::DeviceNotFound`](crate::Error::DeviceNotFound) if the user closes the chooser
+    /// without picking one. Elsewhere this scans like [`Balanced`](ScanMode::Balanced).
+    Chooser,
+}
+
+impl ScanMode {
+    /// The scan interval and window Android uses for this mode.
+    pub fn timing(self) -> (Duration, Duration) {
+        let (interval, window) = match self {
+            ScanMode::LowPower => (5120, 512),
+            ScanMode::Balanced | ScanMode::Chooser => (4096, 1024),
+            ScanMode::LowLatency => (4096, 4096),
+        };
+        (
+            Duration::from_millis(interval),
+            Duration::from_millis(window),
+        )
+    }
+}
+
+/// How the adapter scans, for [`Central::start_scan_with_options`].
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub struct ScanOptions {
+    /// Android scans with this mode, or the one closest to the interval and window. Elsewhere it
+    /// gives the interval and window which aren't set.
+    pub mode: Option<ScanMode>,
+    /// How often the adapter starts listening, from 2.5ms to 10.24s.
+    pub interval: Option<Duration>,
+    /// How long the adapter listens each time, from 2.5ms to the interval.
+    pub window: Option<Duration>,
+}
+
+impl ScanOptions {
+    pub fn with_mode(mode: ScanMode) -> Self {
+        ScanOptions {
+            mode: Some(mode),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_timing(interval: Duration, window: Duration) -> Self {
+        ScanOptions {
+            mode: None,
+            interval: Some(interval),
+            window: Some(window),
+        }
+    }
+
+    /// The interval and window to scan with, or `None` to leave the platform's. Those which aren't
+    /// set come from the mode, or else from [`ScanMode::Balanced`]. Only the Linux and FreeBSD
+    /// backends use this.
+    #[allow(dead_code)]
+    pub(crate) fn timing(&self) -> Option<(Duration, Duration)> {
+        if *self == ScanOptions::default() {
+            return None;
+        }
+        let (interval, window) = self.mode.unwrap_or(ScanMode::Balanced).timing();
+        let interval = self
+            .interval
+            .unwrap_or_else(|| interval.max(self.window.unwrap_or_default()));
+        let window = self.window.unwrap_or_else(|| window.min(interval));
+        Some((interval, window))
+    }
+
+    /// The scan mode, or the one listening closest to as much of the time as the interval and
+    /// window do. Only Android uses this.
+    #[allow(dead_code)]
+    pub(crate) fn mode(&self) -> Option<ScanMode> {
+        if self.mode.is_some() {
+            return self.mode;
+        }
+        let (interval, window) = self.timing()?;
+        let duty_cycle = window.as_secs_f64() / interval.as_secs_f64();
+        Some(if duty_cycle >= 0.6 {
+            ScanMode::LowLatency
+        } else if duty_cycle >= 0.15 {
+            ScanMode::Balanced
+        } else {
+            ScanMode::LowPower
+        })
+    }
+}
+
+/// What [`Central::connect_when_available`] connects to.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ConnectTarget {
+    /// The peripheral with this ID.
+    Peripheral(PeripheralId),
+    /// The first peripheral found by scanning with this filter which can be connected to.
+    Filter(ScanFilter),
+}
+
+impl From<PeripheralId> for ConnectTarget {
+    fn from(id: PeripheralId) -> Self {
+        ConnectTarget::Peripheral(id)
+    }
+}
+
+impl From<ScanFilter> for ConnectTarget {
+    fn from(filter: ScanFilter) -> Self {
+        ConnectTarget::Filter(filter)
+    }
+}
+
+/// Which radio [`Peripheral::connect_with_transport`] connects over. Dual-mode devices can be
+/// connected to over both, and some only have their GATT database on BR/EDR.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Transport {
+    /// Whichever the platform chooses, as [`Peripheral::connect`] does.
+    #[default]
+    Auto,
+    /// Bluetooth Low Energy.
+    Le,
+    /// Bluetooth Classic, i.e. Basic Rate/Enhanced Data Rate.
+    BrEdr,
 }
 
 /// The type of write operation to use.
@@ -217,6 +736,169 @@ pub enum WriteType {
     WithResponse,
     /// A write-without-response, also known as a command.
     WithoutResponse,
+    /// A write-without-response signed with the Connection Signature Resolving Key (CSRK)
+    /// exchanged when bonding, for characteristics with
+    /// [`AUTHENTICATED_SIGNED_WRITES`](CharPropFlags::AUTHENTICATED_SIGNED_WRITES) which need
+    /// writes to be authenticated without encrypting the link.
+    ///
+    /// Only BlueZ and Android support these, and sign the write with the key from the bond
+    /// themselves; BlueZ sends a plain command instead if the link is already encrypted. Other
+    /// platforms, and characteristics without the property, return
+    /// [`Error::NotSupported`](crate::Error::NotSupported).
+    SignedWithoutResponse,
+}
+
+impl WriteType {
+    /// The characteristic property which allows writes of this type.
+    pub fn property(self) -> CharPropFlags {
+        match self {
+            WriteType::WithResponse => CharPropFlags::WRITE,
+            WriteType::WithoutResponse => CharPropFlags::WRITE_WITHOUT_RESPONSE,
+            WriteType::SignedWithoutResponse => CharPropFlags::AUTHENTICATED_SIGNED_WRITES,
+        }
+    }
+
+    /// Returns an error if this is a signed write, and the characteristic doesn't accept them.
+    #[cfg(any(all(target_os = "linux", feature = "bluez"), target_os = "android"))]
+    pub(crate) fn check_signed(self, characteristic: &Characteristic) -> Result<()> {
+        if self == WriteType::SignedWithoutResponse
+            && !characteristic.properties.contains(self.property())
+        {
+            return Err(crate::Error::NotSupported(
+                "Characteristic doesn't accept signed writes".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// How a connection trades latency and throughput against power, for
+/// [`Peripheral::request_connection_priority`].
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum ConnectionPriority {
+    /// The platform's default connection parameters.
+    #[default]
+    Balanced,
+    /// A short connection interval, for low latency and high throughput, such as when transferring
+    /// a firmware update.
+    High,
+    /// A long connection interval, which saves power.
+    LowPower,
+}
+
+/// The largest payload of the link layer packets sent on an LE connection, and the longest time
+/// sending one may take. Longer packets, from the LE Data Length Extension, carry more data per
+/// connection event than the 27 bytes every device supports.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct DataLength {
+    /// The payload size in bytes, from 27 to 251.
+    pub octets: u16,
+    /// The time in microseconds, from 328 to 17040.
+    pub time: u16,
+}
+
+impl DataLength {
+    /// The length every device supports, which is the default.
+    pub const MIN: DataLength = DataLength {
+        octets: 27,
+        time: 328,
+    };
+    /// The longest length the specification allows.
+    pub const MAX: DataLength = DataLength {
+        octets: 251,
+        time: 17040,
+    };
+
+    /// The longest time sending `octets` bytes may take on the 1M PHY.
+    pub fn with_octets(octets: u16) -> Self {
+        DataLength {
+            octets,
+            // A preamble, access address, header and CRC of 10 bytes, and the MIC of 4 bytes on
+            // an encrypted link, at 8µs per byte.
+            time: (octets + 14) * 8,
+        }
+    }
+}
+
+impl Default for DataLength {
+    fn default() -> Self {
+        DataLength::MIN
+    }
+}
+
+/// How well the link to a peripheral is protected, for [`Peripheral::security_level`] and
+/// [`Peripheral::require_security`]. Levels are ordered from least to most secure.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[non_exhaustive]
+pub enum SecurityLevel {
+    /// The link isn't encrypted.
+    #[default]
+    None,
+    /// The link is encrypted with a key from pairing which wasn't authenticated, such as "Just
+    /// Works" pairing, so it isn't protected against a man in the middle.
+    Encrypted,
+    /// The link is encrypted with a key from authenticated pairing, where a PIN was entered or
+    /// compared.
+    Authenticated,
+    /// The link is encrypted with a key from authenticated LE Secure Connections pairing, as
+    /// required by security mode 1 level 4.
+    AuthenticatedLESC,
+}
+
+/// Returns [`Error::InsufficientSecurity`](crate::Error::InsufficientSecurity) unless `actual` is
+/// at least `required`.
+pub(crate) fn check_security_level(required: SecurityLevel, actual: SecurityLevel) -> Result<()> {
+    if actual >= required {
+        Ok(())
+    } else {
+        Err(crate::Error::InsufficientSecurity { required, actual })
+    }
+}
+
+/// How a device sends value updates for a characteristic which has been subscribed to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum SubscriptionType {
+    /// A notification, which the device sends without waiting for an acknowledgement.
+    Notify,
+    /// An indication, which the client must acknowledge before the device sends the next one. Some
+    /// profiles, such as the Record Access Control Point used by health devices, require these.
+    Indicate,
+}
+
+impl SubscriptionType {
+    /// The type which [`Peripheral::subscribe`] uses on most platforms: notifications if the
+    /// characteristic supports them, otherwise indications.
+    pub(crate) fn preferred(properties: CharPropFlags) -> Option<Self> {
+        if properties.contains(CharPropFlags::NOTIFY) {
+            Some(SubscriptionType::Notify)
+        } else if properties.contains(CharPropFlags::INDICATE) {
+            Some(SubscriptionType::Indicate)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn property(&self) -> CharPropFlags {
+        match self {
+            SubscriptionType::Notify => CharPropFlags::NOTIFY,
+            SubscriptionType::Indicate => CharPropFlags::INDICATE,
+        }
+    }
 }
 
 /// Peripheral is the device that you would like to communicate with (the "server" of BLE). This
@@ -230,10 +912,86 @@ pub trait Peripheral: Send + Sync + Clone + Debug {
     /// Returns the MAC address of the peripheral.
     fn address(&self) -> BDAddr;
 
+    /// Returns the identity address of the peripheral. This is its address if it is a public or
+    /// static random address, or, if it is using a resolvable private address, the identity
+    /// address registered for it with `resolver`. Returns `None` if the identity isn't known.
+    ///
+    /// If the platform doesn't report the address type, the address is assumed to be an identity
+    /// address unless it resolves with a registered key.
+    async fn identity_address(&self, resolver: &rpa::Resolver) -> Result<Option<BDAddr>> {
+        let address = self.address();
+        let address_type = self
+            .properties()
+            .await?
+            .and_then(|properties| properties.address_type);
+        Ok(match address_type {
+            Some(AddressType::Public) => Some(address),
+            Some(AddressType::Random) => match address.random_address_kind() {
+                Some(RandomAddressKind::Static) => Some(address),
+                Some(RandomAddressKind::Resolvable) => resolver.resolve(address),
+                _ => None,
+            },
+            None => resolver
+                .resolve(address)
+                .or((address != BDAddr::default()).then_some(address)),
+        })
+    }
+
     /// Returns the set of properties associated with the peripheral. These may be updated over time
     /// as additional advertising reports are received.
+    ///
+    /// This returns what has been received, without communicating with the peripheral. Each
+    /// advertisement or scan response updates the values it contains, and the values it doesn't
+    /// contain, such as a name which is only in the scan response, are kept from before. Values
+    /// therefore aren't removed when the peripheral stops advertising them. See
+    /// [`properties_with_age`](Self::properties_with_age) for how old they are, and
+    /// [`refresh_properties`](Self::refresh_properties) to update what can be read from the
+    /// peripheral.
     async fn properties(&self) -> Result<Option<PeripheralProperties>>;
 
+    /// Returns the properties like [`properties`](Self::properties), with how long ago the most
+    /// recent advertisement from the peripheral was received, or `None` if none has been, e.g.
+    /// because the peripheral is only known from being paired.
+    async fn properties_with_age(
+        &self,
+    ) -> Result<Option<(PeripheralProperties, Option<Duration>)>> {
+        Ok(self.properties().await?.map(|properties| {
+            let age = properties
+                .last_seen
+                .and_then(|last_seen| last_seen.elapsed().ok());
+            (properties, age)
+        }))
+    }
+
+    /// Returns the smoothed RSSI of the peripheral's advertisements and the most recent samples,
+    /// or `None` if RSSI tracking isn't turned on with [`Central::set_rssi_tracking`] or none has
+    /// been received since it was.
+    fn rssi_stats(&self) -> Option<rssi::RssiStats> {
+        None
+    }
+
+    /// Updates what can be read from the peripheral directly, and returns the properties. With
+    /// CoreBluetooth and on Android this reads the RSSI of a connected peripheral. Otherwise the
+    /// properties only change as advertisements are received, and this returns the same as
+    /// [`properties`](Self::properties).
+    async fn refresh_properties(&self) -> Result<Option<PeripheralProperties>> {
+        self.properties().await
+    }
+
+    /// Returns the peripheral's name. If it hasn't advertised one, this reads the Device Name
+    /// characteristic of its Generic Access service, which needs the peripheral to be connected
+    /// with its services discovered. A name read this way becomes the `local_name` of its
+    /// [`properties`](Self::properties), and [`CentralEvent::DeviceUpdated`] is emitted.
+    ///
+    /// BlueZ, CoreBluetooth and WinRT read the Device Name themselves on connecting, so with them
+    /// this returns the name they have. Returns `None` if the peripheral has no name.
+    async fn resolve_name(&self) -> Result<Option<String>> {
+        match self.properties().await?.and_then(|p| p.local_name) {
+            Some(name) => Ok(Some(name)),
+            None => read_device_name(self).await,
+        }
+    }
+
     /// The set of services we've discovered for this device. This will be empty until
     /// `discover_services` is called.
     fn services(&self) -> BTreeSet<Service>;
@@ -255,14 +1013,187 @@ pub trait Peripheral: Send + Sync + Clone + Debug {
     /// attempt to communicate with a device will fail until it is connected.
     async fn connect(&self) -> Result<()>;
 
+    /// Connects to the device like [`connect`](Self::connect), over the given transport.
+    ///
+    /// Only BlueZ and Android can connect over [`Transport::BrEdr`]; elsewhere that returns
+    /// [`Error::NotSupported`](crate::Error::NotSupported), and the others connect over LE, the
+    /// only transport those platforms have GATT on. BlueZ needs version 5.81 or later to connect
+    /// over a particular transport.
+    async fn connect_with_transport(&self, transport: Transport) -> Result<()> {
+        match transport {
+            Transport::Auto | Transport::Le => self.connect().await,
+            Transport::BrEdr => Err(crate::Error::NotSupported(
+                "Connecting over BR/EDR isn't supported on this platform".to_string(),
+            )),
+        }
+    }
+
     /// Terminates a connection to the device.
     async fn disconnect(&self) -> Result<()>;
 
+    /// Returns a stream of the events of this peripheral, in the order they happened. This saves
+    /// picking out the peripheral's events from [`Central::events`], and carries more detail.
+    ///
+    /// Where the platform doesn't say why a device disconnected,
+    /// [`PeripheralEvent::Disconnected`] has [`DisconnectReason::Unknown`]. BlueZ doesn't report
+    /// [`PeripheralEvent::MtuChanged`], and [`PeripheralEvent::PairingStateChanged`] is only
+    /// reported for pairing through btleplug.
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = PeripheralEvent> + Send>>> {
+        Err(crate::Error::NotSupported(
+            "Peripheral events aren't supported by this peripheral".to_string(),
+        ))
+    }
+
+    /// Pairs with the device, if it isn't paired already, agreeing to pairing which needs no PIN.
+    /// Devices which need a PIN to be entered or compared fail to pair; use
+    /// [`pair_with_agent`](Self::pair_with_agent) for those.
+    ///
+    /// Some devices only allow their characteristics to be read or written once paired. Pairing is
+    /// only supported on Windows and with BlueZ; elsewhere this returns
+    /// [`Error::NotSupported`](crate::Error::NotSupported), and the platform pairs when a
+    /// characteristic needs it.
+    async fn pair(&self) -> Result<()> {
+        self.pair_with_agent(Arc::new(pairing::ConfirmOnly)).await
+    }
+
+    /// Pairs with the device like [`pair`](Self::pair), asking the given agent to confirm pairing
+    /// or to show or provide a PIN, as the platform needs.
+    async fn pair_with_agent(&self, agent: Arc<dyn pairing::PairingAgent>) -> Result<()> {
+        let _ = agent;
+        Err(crate::Error::NotSupported(
+            "Pairing isn't supported on this platform".to_string(),
+        ))
+    }
+
+    /// Pairs with the device using out-of-band data it shared, such as from an NFC tag or a QR
+    /// code, so that no PIN needs to be entered or compared.
+    ///
+    /// This is only supported with BlueZ, where it needs the `CAP_NET_ADMIN` capability to give
+    /// the data to the kernel; elsewhere this returns
+    /// [`Error::NotSupported`](crate::Error::NotSupported).
+    async fn pair_with_oob(&self, data: pairing::OobData) -> Result<()> {
+        let _ = data;
+        Err(crate::Error::NotSupported(
+            "Out-of-band pairing isn't supported on this platform".to_string(),
+        ))
+    }
+
+    /// Returns how well the current connection to the device is protected.
+    ///
+    /// Windows reports the protection level of the bond, which is at most
+    /// [`SecurityLevel::Authenticated`] as it doesn't say whether LE Secure Connections were used.
+    /// BlueZ reports [`SecurityLevel::Encrypted`] for a paired device, as it encrypts links to
+    /// bonded devices but doesn't say how they were paired. Other platforms return
+    /// [`Error::NotSupported`](crate::Error::NotSupported).
+    async fn security_level(&self) -> Result<SecurityLevel> {
+        Err(crate::Error::NotSupported(
+            "Reading the security level isn't supported on this platform".to_string(),
+        ))
+    }
+
+    /// Makes sure the connection to the device is protected at least as well as `level`, pairing
+    /// with the device if it isn't, like [`pair`](Self::pair). This can be called once after
+    /// connecting, or before each operation which needs it: it does nothing if the level is met
+    /// already.
+    ///
+    /// Returns [`Error::InsufficientSecurity`](crate::Error::InsufficientSecurity) if the link
+    /// is still less secure than `level` after pairing, and
+    /// [`Error::NotSupported`](crate::Error::NotSupported) if the platform can't report the
+    /// security level. Authenticated levels need pairing with a PIN, so need
+    /// [`require_security_with_agent`](Self::require_security_with_agent).
+    async fn require_security(&self, level: SecurityLevel) -> Result<()> {
+        self.require_security_with_agent(level, Arc::new(pairing::ConfirmOnly))
+            .await
+    }
+
+    /// Makes sure the connection is protected at least as well as `level` like
+    /// [`require_security`](Self::require_security), asking the given agent to confirm pairing or
+    /// to show or provide a PIN, as the platform needs.
+    async fn require_security_with_agent(
+        &self,
+        level: SecurityLevel,
+        agent: Arc<dyn pairing::PairingAgent>,
+    ) -> Result<()> {
+        if level == SecurityLevel::None || self.security_level().await? >= level {
+            return Ok(());
+        }
+        self.pair_with_agent(agent).await?;
+        check_security_level(level, self.security_level().await?)
+    }
+
+    /// Asks for the ATT MTU of the connection to be raised to `mtu`, and returns the MTU which was
+    /// agreed with the device, which may be smaller. A larger MTU lets more data be read, written
+    /// or notified at once; the default is 23 bytes, which leaves 20 for the value.
+    ///
+    /// This is only supported on Android. Other platforms negotiate the largest MTU they can by
+    /// themselves, and return [`Error::NotSupported`](crate::Error::NotSupported).
+    /// [`CentralEvent::MtuChanged`] is emitted when the MTU changes.
+    async fn request_mtu(&self, mtu: u16) -> Result<u16> {
+        let _ = mtu;
+        Err(crate::Error::NotSupported(
+            "Requesting the MTU isn't supported on this platform".to_string(),
+        ))
+    }
+
+    /// Asks for the connection parameters to be changed to suit the given priority. The device may
+    /// not agree to them.
+    ///
+    /// This is only supported on Android; elsewhere this returns
+    /// [`Error::NotSupported`](crate::Error::NotSupported).
+    async fn request_connection_priority(&self, priority: ConnectionPriority) -> Result<()> {
+        let _ = priority;
+        Err(crate::Error::NotSupported(
+            "Requesting the connection priority isn't supported on this platform".to_string(),
+        ))
+    }
+
+    /// Returns a stream of samples of how well the connection is doing, one every `interval`
+    /// starting now, which ends once the peripheral disconnects. Sampling reads the RSSI where
+    /// the platform can, as [`refresh_properties`](Self::refresh_properties) does.
+    async fn link_events(
+        &self,
+        interval: Duration,
+    ) -> Result<Pin<Box<dyn Stream<Item = link::LinkSample> + Send>>> {
+        let _ = interval;
+        Err(crate::Error::NotSupported(
+            "Link events aren't supported by this peripheral".to_string(),
+        ))
+    }
+
+    /// Asks the controller to use the given data length on the connection, which it negotiates
+    /// with the device. The device may agree to less.
+    ///
+    /// This is only supported with BlueZ and the `hci` backend, where it needs the `CAP_NET_RAW`
+    /// capability; elsewhere this returns [`Error::NotSupported`](crate::Error::NotSupported).
+    /// macOS, iOS, Windows and Android use the longest data length both devices support by
+    /// themselves.
+    async fn request_data_length(&self, length: DataLength) -> Result<()> {
+        let _ = length;
+        Err(crate::Error::NotSupported(
+            "Requesting the data length isn't supported on this platform".to_string(),
+        ))
+    }
+
     /// Discovers all services for the device, including their characteristics.
     async fn discover_services(&self) -> Result<()>;
 
+    /// Discovers only the services with the given UUIDs, along with their characteristics and
+    /// descriptors. This can be much quicker than [`discover_services`](Self::discover_services)
+    /// on devices with many services. Services discovered earlier are kept.
+    ///
+    /// Where the platform can't discover individual services, as on Android and CoreBluetooth,
+    /// this discovers all of them.
+    async fn discover_services_filtered(&self, uuids: &[Uuid]) -> Result<()> {
+        let _ = uuids;
+        self.discover_services().await
+    }
+
     /// Write some data to the characteristic. Returns an error if the write couldn't be sent or (in
     /// the case of a write-with-response) if the device returns an error.
+    ///
+    /// A write without response returns once the platform has room to send it, rather than
+    /// straight away, so writing in a loop is paced to what the link can carry instead of
+    /// overrunning the platform's buffers and losing writes.
     async fn write(
         &self,
         characteristic: &Characteristic,
@@ -274,18 +1205,171 @@ pub trait Peripheral: Send + Sync + Clone + Debug {
     /// or the response from the device.
     async fn read(&self, characteristic: &Characteristic) -> Result<Vec<u8>>;
 
+    /// Returns the characteristic with the given UUID in the given service. If it hasn't been
+    /// discovered yet, this first discovers just that service with
+    /// [`discover_services_filtered`](Self::discover_services_filtered).
+    ///
+    /// Returns [`Error::NoSuchCharacteristic`](crate::Error::NoSuchCharacteristic) if the device
+    /// doesn't have it.
+    async fn find_characteristic(
+        &self,
+        service_uuid: Uuid,
+        characteristic_uuid: Uuid,
+    ) -> Result<Characteristic> {
+        let find = |services: BTreeSet<Service>| {
+            services
+                .into_iter()
+                .filter(|service| service.uuid == service_uuid)
+                .flat_map(|service| service.characteristics)
+                .find(|characteristic| characteristic.uuid == characteristic_uuid)
+        };
+        if let Some(characteristic) = find(self.services()) {
+            return Ok(characteristic);
+        }
+        self.discover_services_filtered(&[service_uuid]).await?;
+        find(self.services()).ok_or(crate::Error::NoSuchCharacteristic)
+    }
+
+    /// Reads the characteristic with the given UUID in the given service, discovering it first if
+    /// necessary. This is a shortcut for one-off reads; see
+    /// [`find_characteristic`](Self::find_characteristic).
+    async fn read_by_uuid(&self, service_uuid: Uuid, characteristic_uuid: Uuid) -> Result<Vec<u8>> {
+        let characteristic = self
+            .find_characteristic(service_uuid, characteristic_uuid)
+            .await?;
+        self.read(&characteristic).await
+    }
+
+    /// Writes to the characteristic with the given UUID in the given service, discovering it first
+    /// if necessary. This is a shortcut for one-off writes; see
+    /// [`find_characteristic`](Self::find_characteristic).
+    async fn write_by_uuid(
+        &self,
+        service_uuid: Uuid,
+        characteristic_uuid: Uuid,
+        data: &[u8],
+        write_type: WriteType,
+    ) -> Result<()> {
+        let characteristic = self
+            .find_characteristic(service_uuid, characteristic_uuid)
+            .await?;
+        self.write(&characteristic, data, write_type).await
+    }
+
     /// Enables either notify or indicate (depending on support) for the specified characteristic.
     async fn subscribe(&self, characteristic: &Characteristic) -> Result<()>;
 
+    /// Enables the given type of value updates for the specified characteristic, rather than
+    /// leaving the choice to the platform as [`subscribe`](Self::subscribe) does.
+    ///
+    /// BlueZ, CoreBluetooth and Web Bluetooth always use notifications if a characteristic supports
+    /// them, so on those platforms this returns [`Error::NotSupported`](crate::Error::NotSupported)
+    /// when asked for indications from a characteristic which supports both.
+    async fn subscribe_with(
+        &self,
+        characteristic: &Characteristic,
+        subscription_type: SubscriptionType,
+    ) -> Result<()> {
+        if !characteristic
+            .properties
+            .contains(subscription_type.property())
+        {
+            return Err(crate::Error::NotSupported(format!(
+                "Characteristic doesn't support {:?}",
+                subscription_type
+            )));
+        }
+        if SubscriptionType::preferred(characteristic.properties) != Some(subscription_type) {
+            return Err(crate::Error::NotSupported(format!(
+                "Platform can't choose {:?} for this characteristic",
+                subscription_type
+            )));
+        }
+        self.subscribe(characteristic).await
+    }
+
     /// Disables either notify or indicate (depending on support) for the specified characteristic.
     async fn unsubscribe(&self, characteristic: &Characteristic) -> Result<()>;
 
+    /// Reads the Client Characteristic Configuration Descriptor of the given characteristic from
+    /// the device, to find out whether notifications or indications are enabled.
+    ///
+    /// Returns an error if the characteristic doesn't have one, i.e. doesn't support notify or
+    /// indicate.
+    async fn read_client_configuration(
+        &self,
+        characteristic: &Characteristic,
+    ) -> Result<ClientConfiguration> {
+        let descriptor = characteristic
+            .descriptors
+            .iter()
+            .find(|descriptor| descriptor.uuid == CLIENT_CHARACTERISTIC_CONFIGURATION_UUID)
+            .ok_or_else(|| {
+                crate::Error::NotSupported(
+                    "Characteristic has no client characteristic configuration descriptor".into(),
+                )
+            })?;
+        let value = self.read_descriptor(descriptor).await?;
+        let value = match value[..] {
+            [low] => u16::from(low),
+            [low, high, ..] => u16::from_le_bytes([low, high]),
+            [] => 0,
+        };
+        Ok(ClientConfiguration::from_bits_truncate(value))
+    }
+
+    /// Returns true iff the device currently has notifications or indications enabled for the
+    /// given characteristic.
+    async fn is_subscribed(&self, characteristic: &Characteristic) -> Result<bool> {
+        Ok(!self
+            .read_client_configuration(characteristic)
+            .await?
+            .is_empty())
+    }
+
+    /// Enables notify or indicate for the given characteristic like [`subscribe`](Self::subscribe),
+    /// and returns a stream of notifications from just that characteristic.
+    ///
+    /// Unlike filtering [`notifications`](Self::notifications) by UUID, this tells apart
+    /// characteristics with the same UUID in different services. Dropping the stream doesn't
+    /// unsubscribe; call [`unsubscribe`](Self::unsubscribe) for that.
+    async fn subscribe_stream(
+        &self,
+        characteristic: &Characteristic,
+    ) -> Result<Pin<Box<dyn Stream<Item = Vec<u8>> + Send>>> {
+        // Get the stream first so we don't miss a notification sent straight away.
+        let notifications = self.notifications().await?;
+        self.subscribe(characteristic).await?;
+        let uuid = characteristic.uuid;
+        let service_uuid = characteristic.service_uuid;
+        Ok(Box::pin(notifications.filter_map(move |notification| {
+            let matches = notification.uuid == uuid && notification.service_uuid == service_uuid;
+            async move { matches.then_some(notification.value) }
+        })))
+    }
+
     /// Returns a stream of notifications for characteristic value updates. The stream will receive
     /// a notification when a value notification or indication is received from the device.
     /// The stream will remain valid across connections and can be queried before any connection
     /// is made.
     async fn notifications(&self) -> Result<Pin<Box<dyn Stream<Item = ValueNotification> + Send>>>;
 
+    /// Returns a stream of notifications like [`notifications`](Self::notifications), but with a
+    /// buffer of the given size and [overflow policy](OverflowPolicy) between the platform and the
+    /// stream, which counts the notifications it drops.
+    ///
+    /// Without this, how many unread notifications are kept depends on the platform: some buffer
+    /// without limit, others silently drop old values.
+    async fn notifications_buffered(
+        &self,
+        buffer: NotificationBuffer,
+    ) -> Result<BufferedNotifications> {
+        Ok(BufferedNotifications::new(
+            self.notifications().await?,
+            buffer,
+        ))
+    }
+
     /// Write some data to the descriptor. Returns an error if the write couldn't be sent or (in
     /// the case of a write-with-response) if the device returns an error.
     async fn write_descriptor(&self, descriptor: &Descriptor, data: &[u8]) -> Result<()>;
@@ -293,6 +1377,116 @@ pub trait Peripheral: Send + Sync + Clone + Debug {
     /// Sends a read descriptor request to the device. Returns either an error if the request
     /// was not accepted or the response from the device.
     async fn read_descriptor(&self, descriptor: &Descriptor) -> Result<Vec<u8>>;
+
+    /// Returns the value of the descriptor as last read or written, reading it from the device
+    /// only if it hasn't been yet. This suits descriptors whose values don't change, such as
+    /// Report Reference or Characteristic Presentation Format.
+    async fn read_descriptor_cached(&self, descriptor: &Descriptor) -> Result<Vec<u8>> {
+        let cached = self
+            .services()
+            .into_iter()
+            .filter(|service| service.uuid == descriptor.service_uuid)
+            .flat_map(|service| service.characteristics)
+            .filter(|characteristic| characteristic.uuid == descriptor.characteristic_uuid)
+            .flat_map(|characteristic| characteristic.descriptors)
+            .find(|d| d == descriptor)
+            .and_then(|d| d.value);
+        match cached.or_else(|| descriptor.value.clone()) {
+            Some(value) => Ok(value),
+            None => self.read_descriptor(descriptor).await,
+        }
+    }
+
+    /// Reads the characteristic's descriptor of the given type, such as
+    /// [`CLIENT_CHARACTERISTIC_CONFIGURATION_UUID`]. Returns
+    /// [`Error::NoSuchDescriptor`](crate::Error::NoSuchDescriptor) if it doesn't have one.
+    async fn read_descriptor_by_uuid(
+        &self,
+        characteristic: &Characteristic,
+        uuid: Uuid,
+    ) -> Result<Vec<u8>> {
+        let descriptor = characteristic
+            .descriptor(uuid)
+            .ok_or(crate::Error::NoSuchDescriptor)?;
+        self.read_descriptor(descriptor).await
+    }
+
+    /// Returns a handle to this peripheral which retries operations that fail with a transient
+    /// error, such as a busy Bluetooth stack or a disconnection during the operation, according to
+    /// the given policy.
+    fn with_retry(&self, policy: RetryPolicy) -> Retrying<Self> {
+        Retrying::new(self.clone(), policy)
+    }
+}
+
+/// Information about a Bluetooth adapter, as returned by [`Central::adapter_info`].
+///
+/// Fields are `None` where the platform doesn't expose them.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AdapterInfo {
+    /// A platform-specific identifier for the adapter which is stable across reboots, such as
+    /// `hci0` on Linux. See [`Manager::adapter_by_id`].
+    pub id: String,
+    /// A platform-specific description of the adapter, such as its model or type. You should not
+    /// attempt to parse this, but it may be useful for debug logs. This is also what the
+    /// [`Display`] implementation prints.
+    pub description: String,
+    /// The Bluetooth address of the adapter.
+    pub address: Option<BDAddr>,
+    /// The system name of the adapter.
+    pub name: Option<String>,
+    /// The friendly name of the adapter, if it has been set separately from the system name.
+    pub alias: Option<String>,
+    /// What the adapter supports.
+    pub capabilities: AdapterCapabilities,
+    /// The version of the platform's Bluetooth stack, such as `5.72` for BlueZ, where it can be
+    /// told.
+    pub stack_version: Option<String>,
+    /// The UUIDs of the experimental features of the platform's Bluetooth stack which are enabled.
+    /// BlueZ enables them when started with `--experimental`, or as listed in `KernelExperimental`
+    /// in its `main.conf`.
+    pub experimental_features: Vec<Uuid>,
+}
+
+impl Display for AdapterInfo {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.description)
+    }
+}
+
+/// The capabilities of a Bluetooth adapter. Each is `None` if the platform doesn't report it.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AdapterCapabilities {
+    /// Whether the adapter supports Bluetooth 5 extended advertising.
+    pub extended_advertising: Option<bool>,
+    /// Whether the adapter supports the LE 2M PHY.
+    pub le_2m_phy: Option<bool>,
+    /// Whether the adapter supports the LE Coded PHY, used for long range.
+    pub le_coded_phy: Option<bool>,
+    /// Whether the adapter can act in the peripheral role, e.g. to advertise.
+    pub peripheral_role: Option<bool>,
+    /// Whether GATT operations can use Enhanced ATT bearers with peripherals which support them,
+    /// so that operations started at once, e.g. from separate tasks, run in parallel rather than
+    /// one after another. EATT needs an encrypted link, so the peripheral has to be paired.
+    ///
+    /// BlueZ uses EATT itself when the kernel supports it, unless `Channels` is 1 in the `[GATT]`
+    /// section of its `main.conf`, but doesn't say whether it does, so this is `None` there. The
+    /// `hci` backend doesn't use it. Android 13 and later may use it internally, but only lets an
+    /// application have one GATT operation in progress at a time.
+    pub eatt: Option<bool>,
+    /// Whether [`Central::monitor`] can hand advertisement monitors to the platform, which matches
+    /// advertisements while scanning passively, rather than scanning and matching them itself.
+    pub advertisement_monitor: Option<bool>,
 }
 
 #[cfg_attr(
@@ -303,9 +1497,16 @@ pub trait Peripheral: Send + Sync + Clone + Debug {
 /// The state of the Central
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum CentralState {
+    /// The state is not known yet, or the adapter is changing between states.
     Unknown = 0,
     PoweredOn = 1,
     PoweredOff = 2,
+    /// The application is not allowed to use Bluetooth.
+    Unauthorized = 3,
+    /// The connection to the system Bluetooth service was lost and is being re-established.
+    Resetting = 4,
+    /// The platform doesn't support Bluetooth LE, or there is no adapter.
+    Unsupported = 5,
 }
 
 #[cfg_attr(
@@ -322,19 +1523,225 @@ pub enum CentralEvent {
     /// Emitted when a Manufacturer Data advertisement has been received from a device
     ManufacturerDataAdvertisement {
         id: PeripheralId,
-        manufacturer_data: HashMap<u16, Vec<u8>>,
+        manufacturer_data: HashMap<u16, Arc<[u8]>>,
     },
     /// Emitted when a Service Data advertisement has been received from a device
     ServiceDataAdvertisement {
         id: PeripheralId,
-        service_data: HashMap<Uuid, Vec<u8>>,
+        service_data: HashMap<Uuid, Arc<[u8]>>,
     },
     /// Emitted when the advertised services for a device has been updated
     ServicesAdvertisement {
         id: PeripheralId,
         services: Vec<Uuid>,
     },
+    /// Emitted when the state of the adapter changes, for example when it is powered on or off.
     StateUpdate(CentralState),
+    /// Emitted when a connected device indicates that its GATT services have changed, for example
+    /// after a firmware update. btleplug rediscovers the services before emitting this, so
+    /// [`Peripheral::services`] is up to date and any [`Characteristic`]s from before should be
+    /// looked up again.
+    ServicesChanged(PeripheralId),
+    /// Emitted when the ATT MTU of a connection changes, whether because of
+    /// [`Peripheral::request_mtu`] or because the platform or the device asked for it.
+    MtuChanged {
+        id: PeripheralId,
+        mtu: u16,
+    },
+    /// Emitted when a Bluetooth Classic device is found while scanning with
+    /// [`ScanFilter::classic`].
+    ClassicDeviceDiscovered(ClassicDevice),
+    /// Emitted when a device sends a directed advertisement to this adapter, asking it to connect
+    /// right away, as a bonded device which sleeps between connections does to reconnect. It
+    /// follows the `DeviceDiscovered` or `DeviceUpdated` event for the advertisement. Only Windows
+    /// and the `mock` backend report this; elsewhere the system either doesn't pass directed
+    /// advertisements on or doesn't say which they are.
+    DirectedAdvertisement(PeripheralId),
+}
+
+impl CentralEvent {
+    /// Returns which variant this is.
+    pub fn kind(&self) -> CentralEventKind {
+        match self {
+            CentralEvent::DeviceDiscovered(_) => CentralEventKind::DeviceDiscovered,
+            CentralEvent::DeviceUpdated(_) => CentralEventKind::DeviceUpdated,
+            CentralEvent::DeviceConnected(_) => CentralEventKind::DeviceConnected,
+            CentralEvent::DeviceDisconnected(_) => CentralEventKind::DeviceDisconnected,
+            CentralEvent::ManufacturerDataAdvertisement { .. } => {
+                CentralEventKind::ManufacturerDataAdvertisement
+            }
+            CentralEvent::ServiceDataAdvertisement { .. } => {
+                CentralEventKind::ServiceDataAdvertisement
+            }
+            CentralEvent::ServicesAdvertisement { .. } => CentralEventKind::ServicesAdvertisement,
+            CentralEvent::StateUpdate(_) => CentralEventKind::StateUpdate,
+            CentralEvent::ServicesChanged(_) => CentralEventKind::ServicesChanged,
+            CentralEvent::MtuChanged { .. } => CentralEventKind::MtuChanged,
+            CentralEvent::ClassicDeviceDiscovered(_) => CentralEventKind::ClassicDeviceDiscovered,
+            CentralEvent::DirectedAdvertisement(_) => CentralEventKind::DirectedAdvertisement,
+        }
+    }
+
+    /// Returns the ID of the peripheral the event is about, if it's about one.
+    pub fn peripheral_id(&self) -> Option<&PeripheralId> {
+        match self {
+            CentralEvent::DeviceDiscovered(id)
+            | CentralEvent::DeviceUpdated(id)
+            | CentralEvent::DeviceConnected(id)
+            | CentralEvent::DeviceDisconnected(id)
+            | CentralEvent::ManufacturerDataAdvertisement { id, .. }
+            | CentralEvent::ServiceDataAdvertisement { id, .. }
+            | CentralEvent::ServicesAdvertisement { id, .. }
+            | CentralEvent::ServicesChanged(id)
+            | CentralEvent::MtuChanged { id, .. }
+            | CentralEvent::DirectedAdvertisement(id) => Some(id),
+            CentralEvent::StateUpdate(_) | CentralEvent::ClassicDeviceDiscovered(_) => None,
+        }
+    }
+}
+
+/// The variants of [`CentralEvent`], without their fields, for [`EventFilter`].
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CentralEventKind {
+    DeviceDiscovered,
+    DeviceUpdated,
+    DeviceConnected,
+    DeviceDisconnected,
+    ManufacturerDataAdvertisement,
+    ServiceDataAdvertisement,
+    ServicesAdvertisement,
+    StateUpdate,
+    ServicesChanged,
+    MtuChanged,
+    ClassicDeviceDiscovered,
+    DirectedAdvertisement,
+}
+
+/// Which events [`Central::events_filtered`] returns. Each part of the filter which isn't empty
+/// has to match.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct EventFilter {
+    /// Only events of these kinds are returned, if there are any.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub kinds: Vec<CentralEventKind>,
+    /// Only events about these peripherals are returned, if there are any. Events which aren't
+    /// about a peripheral, like [`CentralEvent::StateUpdate`], aren't held back by this or by
+    /// `services`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub peripherals: Vec<PeripheralId>,
+    /// Only events about peripherals which have advertised at least one of these services are
+    /// returned, if there are any.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub services: Vec<Uuid>,
+}
+
+impl EventFilter {
+    /// Returns whether the event is of one of the kinds and about one of the peripherals, which
+    /// doesn't need to know about the peripheral.
+    pub(crate) fn matches_kind_and_peripheral(&self, event: &CentralEvent) -> bool {
+        (self.kinds.is_empty() || self.kinds.contains(&event.kind()))
+            && (self.peripherals.is_empty()
+                || event
+                    .peripheral_id()
+                    .is_none_or(|id| self.peripherals.contains(id)))
+    }
+}
+
+/// A peripheral found or updated while scanning, from [`Central::discovery_events`], with the
+/// properties it had then.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct DiscoveryEvent {
+    pub id: PeripheralId,
+    pub properties: PeripheralProperties,
+    /// Whether this is the first time the peripheral was seen, for which
+    /// [`CentralEvent::DeviceDiscovered`] is emitted, rather than [`CentralEvent::DeviceUpdated`].
+    pub new: bool,
+}
+
+/// Why a peripheral disconnected.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DisconnectReason {
+    /// The application disconnected, with [`Peripheral::disconnect`].
+    Local,
+    /// The device ended the connection, or went out of range.
+    Remote,
+    /// The platform doesn't say why.
+    Unknown,
+}
+
+/// An event of a single peripheral, from [`Peripheral::events`].
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PeripheralEvent {
+    Connected,
+    Disconnected(DisconnectReason),
+    /// The peripheral's services have been discovered, or rediscovered after they changed, so
+    /// [`Peripheral::services`] is up to date.
+    ServicesResolved,
+    /// New advertisement data or a new RSSI has been received, which
+    /// [`Peripheral::properties`] returns.
+    PropertiesUpdated,
+    /// The ATT MTU of the connection has changed to the given value.
+    MtuChanged(u16),
+    /// The device has been paired, if this is `true`, or its bond removed.
+    PairingStateChanged(bool),
+    /// The device sent a directed advertisement to this adapter, asking it to connect. See
+    /// [`CentralEvent::DirectedAdvertisement`].
+    DirectedAdvertisement,
+}
+
+/// Reads the Device Name characteristic of the peripheral, if it has one.
+pub(crate) async fn read_device_name<P: Peripheral>(peripheral: &P) -> Result<Option<String>> {
+    let Some(characteristic) = peripheral
+        .characteristics()
+        .into_iter()
+        .find(|c| c.uuid == DEVICE_NAME_CHARACTERISTIC_UUID)
+    else {
+        return Ok(None);
+    };
+    let name = standard::decode_string(&peripheral.read(&characteristic).await?);
+    Ok((!name.is_empty()).then_some(name))
+}
+
+/// The ATT MTU every connection starts with, of which 3 bytes are taken by the header of a write.
+const DEFAULT_MTU: u16 = 23;
+
+/// The largest MTU to ask for on platforms which let the application choose.
+const MAX_MTU: u16 = 517;
+
+/// Asks for the largest MTU where the platform lets the application choose, and returns the most
+/// bytes which can then be written at once. Elsewhere this is the 20 bytes the default MTU allows,
+/// though the platform may have negotiated more.
+pub(crate) async fn negotiate_write_len<P: Peripheral>(peripheral: &P) -> usize {
+    let mtu = peripheral.request_mtu(MAX_MTU).await.unwrap_or(DEFAULT_MTU);
+    usize::from(mtu.max(DEFAULT_MTU) - 3)
 }
 
 /// Central is the "client" of BLE. It's able to scan for and establish connections to peripherals.
@@ -354,29 +1761,504 @@ pub trait Central: Send + Sync + Clone {
     /// ignore (parts of) the filter and make additional devices available, other implementations
     /// might require at least one filter for security reasons. Cross-platform code should provide
     /// a filter, but must be able to handle devices, which do not fit into the filter.
+    ///
+    /// On iOS, an app in the background doesn't find any devices unless the filter has services,
+    /// and iOS coalesces the advertisements of each device, so that it is reported much less often
+    /// than while the app is in the foreground.
     async fn start_scan(&self, filter: ScanFilter) -> Result<()>;
 
+    /// Starts a scan like [`start_scan`](Central::start_scan), with the given options, to trade
+    /// how quickly devices are found against power, and against interference with Wi-Fi on
+    /// adapters which share an antenna with it.
+    ///
+    /// Android scans with the options' [`ScanMode`]. BlueZ and the `hci` backend set the scan
+    /// interval and window the kernel discovers with, which needs the `CAP_NET_ADMIN` capability,
+    /// and changes them for every application using the adapter until they are set again. If the
+    /// adapter is already scanning, they take effect once its scan restarts. FreeBSD sets them on
+    /// the adapter when the scan starts. On WebAssembly, [`ScanMode::Chooser`] shows the browser's
+    /// device chooser instead of scanning. Other platforms ignore the options.
+    async fn start_scan_with_options(
+        &self,
+        filter: ScanFilter,
+        options: ScanOptions,
+    ) -> Result<()> {
+        let _ = options;
+        self.start_scan(filter).await
+    }
+
     /// Stops scanning for BLE devices.
     async fn stop_scan(&self) -> Result<()>;
 
+    /// Starts scanning with the given filter, and returns a stream of the peripherals found, each
+    /// only once. Peripherals which were found before this was called are included too.
+    ///
+    /// If the filter has services, only peripherals advertising at least one of them are returned,
+    /// even on platforms which let others through. A peripheral which hasn't advertised its
+    /// services yet is returned once it does. Scanning continues after the stream is dropped; call
+    /// [`stop_scan`](Central::stop_scan) to stop it.
+    async fn discover(
+        &self,
+        filter: ScanFilter,
+    ) -> Result<Pin<Box<dyn Stream<Item = Self::Peripheral> + Send>>>
+    where
+        Self: 'static,
+    {
+        // Get the stream first so we don't miss a peripheral found in between.
+        let events = self.events().await?;
+        self.start_scan(filter.clone()).await?;
+        let known = self.peripherals().await?;
+        let ids = stream::iter(known.into_iter().map(|peripheral| peripheral.id())).chain(
+            events.filter_map(|event| async move {
+                match event {
+                    CentralEvent::DeviceDiscovered(id) | CentralEvent::DeviceUpdated(id) => {
+                        Some(id)
+                    }
+                    _ => None,
+                }
+            }),
+        );
+
+        let seen = Arc::new(Mutex::new(HashSet::new()));
+        let adapter = self.clone();
+        let peripherals = ids.filter_map(move |id| {
+            let seen = seen.clone();
+            let adapter = adapter.clone();
+            let services = filter.services.clone();
+            async move {
+                if seen.lock().unwrap().contains(&id) {
+                    return None;
+                }
+                let peripheral = adapter.peripheral(&id).await.ok()?;
+                if !services.is_empty() {
+                    let properties = peripheral.properties().await.ok()??;
+                    if !properties
+                        .services
+                        .iter()
+                        .any(|uuid| services.contains(uuid))
+                    {
+                        return None;
+                    }
+                }
+                seen.lock().unwrap().insert(id).then_some(peripheral)
+            }
+        });
+        Ok(Box::pin(peripherals))
+    }
+
+    /// Returns a stream of the events which match `filter`, like [`events`](Central::events). On
+    /// Android, macOS, iOS and Windows, and with the `hci` and `mock` backends, events of other
+    /// kinds or about other peripherals are left out as they are emitted, so they cost nothing to
+    /// subscribers which don't want them; with BlueZ, events about other peripherals are left out
+    /// before BlueZ is asked for their details.
+    ///
+    /// The services a peripheral has advertised are looked up with [`Peripheral::properties`] for
+    /// the first event about it, and for later ones until it matches.
+    async fn events_filtered(
+        &self,
+        filter: EventFilter,
+    ) -> Result<Pin<Box<dyn Stream<Item = CentralEvent> + Send>>>
+    where
+        Self: 'static,
+    {
+        let events = self.events().await?;
+        let kinds_and_peripherals = filter.clone();
+        let events = events.filter(move |event| {
+            let matches = kinds_and_peripherals.matches_kind_and_peripheral(event);
+            async move { matches }
+        });
+        Ok(event_filter::by_services(
+            self,
+            Box::pin(events),
+            filter.services,
+        ))
+    }
+
+    /// Returns a stream of the peripherals found and updated, like the
+    /// [`DeviceDiscovered`](CentralEvent::DeviceDiscovered) and
+    /// [`DeviceUpdated`](CentralEvent::DeviceUpdated) events, but with the properties which came
+    /// with each. Unlike calling [`Peripheral::properties`] for each event, this doesn't miss
+    /// peripherals which are removed in the meantime, or see properties from a later
+    /// advertisement.
+    ///
+    /// On Android, and with the `hci` and `mock` backends, the properties are taken as each event is
+    /// emitted.
+    /// Elsewhere they are read when the event arrives, so events for peripherals already removed
+    /// are left out.
+    async fn discovery_events(&self) -> Result<Pin<Box<dyn Stream<Item = DiscoveryEvent> + Send>>>
+    where
+        Self: 'static,
+    {
+        let events = self.events().await?;
+        let adapter = self.clone();
+        Ok(Box::pin(events.filter_map(move |event| {
+            let adapter = adapter.clone();
+            async move {
+                let (id, new) = match event {
+                    CentralEvent::DeviceDiscovered(id) => (id, true),
+                    CentralEvent::DeviceUpdated(id) => (id, false),
+                    _ => return None,
+                };
+                let properties = adapter
+                    .peripheral(&id)
+                    .await
+                    .ok()?
+                    .properties()
+                    .await
+                    .ok()??;
+                Some(DiscoveryEvent {
+                    id,
+                    properties,
+                    new,
+                })
+            }
+        })))
+    }
+
+    /// Watches for the devices described by `monitor` coming into and going out of range, until
+    /// the stream is dropped. See [`monitor`] for details.
+    ///
+    /// With BlueZ 5.65 or later, advertisements are matched while scanning passively, by the
+    /// controller where it can, which saves power. Elsewhere, and for monitors without patterns,
+    /// this scans with [`start_scan`](Central::start_scan) and matches advertisements as they come
+    /// in, in which case the RSSI timeouts aren't applied.
+    async fn monitor(
+        &self,
+        monitor: monitor::AdvertisementMonitor,
+    ) -> Result<Pin<Box<dyn Stream<Item = monitor::MonitorEvent> + Send>>>
+    where
+        Self: 'static,
+    {
+        monitor::by_scanning(self, monitor).await
+    }
+
     /// Returns the list of [`Peripheral`]s that have been discovered so far. Note that this list
-    /// may contain peripherals that are no longer available.
+    /// may contain peripherals that are no longer available. On Windows, it also includes the LE
+    /// devices paired with the system, even if they haven't been seen advertising.
     async fn peripherals(&self) -> Result<Vec<Self::Peripheral>>;
 
-    /// Returns a particular [`Peripheral`] by its address if it has been discovered.
+    /// Returns a particular [`Peripheral`] by its ID if it has been discovered.
+    ///
+    /// On macOS and iOS, this also finds peripherals which CoreBluetooth knows from before, for
+    /// example by an ID the app stored when it last ran, without scanning for them. This works even
+    /// if the peripheral isn't advertising, and emits [`CentralEvent::DeviceDiscovered`] for it.
     async fn peripheral(&self, id: &PeripheralId) -> Result<Self::Peripheral>;
 
+    /// Returns a particular [`Peripheral`] by its Bluetooth address if it has been discovered.
+    ///
+    /// CoreBluetooth and Web Bluetooth don't expose peripheral addresses, so on macOS, iOS and
+    /// WebAssembly this always fails with
+    /// [`Error::AddressNotAvailable`](crate::Error::AddressNotAvailable); use
+    /// [`peripheral`](Central::peripheral) with the peripheral's ID there instead.
+    async fn peripheral_by_address(&self, address: BDAddr) -> Result<Self::Peripheral> {
+        self.peripherals()
+            .await?
+            .into_iter()
+            .find(|peripheral| peripheral.address() == address)
+            .ok_or(crate::Error::DeviceNotFound)
+    }
+
     /// Add a [`Peripheral`] from a MAC address without a scan result. Not supported on all Bluetooth systems.
     async fn add_peripheral(&self, address: &PeripheralId) -> Result<Self::Peripheral>;
 
-    /// Get information about the Bluetooth adapter being used, such as the model or type.
+    /// Removes a peripheral from the adapter, disconnecting from it first if it's connected, so
+    /// that what's kept about it is freed. If it advertises again while scanning it's found again,
+    /// as a new peripheral. Handles to the old peripheral may stop working.
+    ///
+    /// With BlueZ this removes the device from BlueZ, which also removes any pairing with it.
+    async fn remove_peripheral(&self, id: &PeripheralId) -> Result<()> {
+        let _ = id;
+        Err(crate::Error::NotSupported(
+            "Removing peripherals isn't supported by this adapter".to_string(),
+        ))
+    }
+
+    /// Returns the peripherals which are connected to the system and have at least one of the
+    /// given services, or any services if `services` is empty. These may have been connected by
+    /// the OS or another app, such as a watch or a paired keyboard, and so not be advertising.
+    /// They can be used like discovered peripherals once [`Peripheral::connect`] has been called,
+    /// which doesn't take long as the link is already up.
+    ///
+    /// This is supported with BlueZ, CoreBluetooth and WinRT. CoreBluetooth only finds peripherals
+    /// with one of the given services, so there an empty list finds none.
+    async fn connected_peripherals(&self, services: &[Uuid]) -> Result<Vec<Self::Peripheral>> {
+        let _ = services;
+        Err(crate::Error::NotSupported(
+            "Listing connected peripherals isn't supported on this platform".to_string(),
+        ))
+    }
+
+    /// Adds the peripheral to the controller's filter accept list, so that the controller connects
+    /// to it by itself whenever it advertises, and again each time it disconnects, without
+    /// scanning. [`CentralEvent::DeviceConnected`] is emitted each time. This suits gateways which
+    /// keep connections to many sensors that only advertise now and then.
     ///
-    /// The details of this are platform-specific andyou should not attempt to parse it, but it may
-    /// be useful for debug logs.
-    async fn adapter_info(&self) -> Result<String>;
+    /// The peripheral stays on the list until it's removed with
+    /// [`remove_from_accept_list`](Central::remove_from_accept_list), so it's connected to again
+    /// even after [`Peripheral::disconnect`]. This is supported with BlueZ and the `hci` backend,
+    /// where it needs the `CAP_NET_ADMIN` capability; elsewhere this returns
+    /// [`Error::NotSupported`](crate::Error::NotSupported).
+    async fn add_to_accept_list(&self, id: &PeripheralId) -> Result<()> {
+        let _ = id;
+        Err(crate::Error::NotSupported(
+            "The filter accept list isn't supported on this platform".to_string(),
+        ))
+    }
+
+    /// Waits until the target is available, connects to it, and returns it. This doesn't time out,
+    /// so it suits reconnecting to a wearable or tag whenever it comes back in range; drop the
+    /// future to give up.
+    ///
+    /// On macOS and iOS, a peripheral which has been discovered is connected to with a pending
+    /// connection request, which CoreBluetooth keeps until the device is in range even while the
+    /// app is in the background. On Android, a peripheral is connected to with `autoConnect`,
+    /// whether or not it has been discovered. Otherwise, and for a
+    /// [`ConnectTarget::Filter`] everywhere, this scans and tries to connect to each matching
+    /// peripheral as it advertises, until a connection succeeds. As with
+    /// [`discover`](Central::discover), scanning continues afterwards.
+    async fn connect_when_available(&self, target: ConnectTarget) -> Result<Self::Peripheral> {
+        connect_when_advertising(self, target).await
+    }
+
+    /// Removes the peripheral from the controller's filter accept list, so that it's no longer
+    /// connected to by itself. A connection which is up is left up.
+    async fn remove_from_accept_list(&self, id: &PeripheralId) -> Result<()> {
+        let _ = id;
+        Err(crate::Error::NotSupported(
+            "The filter accept list isn't supported on this platform".to_string(),
+        ))
+    }
+
+    /// Returns the longest data length the adapter can send with.
+    ///
+    /// This and the other data length methods are only supported with BlueZ and the `hci`
+    /// backend; elsewhere they return [`Error::NotSupported`](crate::Error::NotSupported).
+    async fn max_data_length(&self) -> Result<DataLength> {
+        Err(crate::Error::NotSupported(
+            "The data length isn't supported on this platform".to_string(),
+        ))
+    }
+
+    /// Returns the data length the adapter asks for on new connections.
+    async fn suggested_data_length(&self) -> Result<DataLength> {
+        Err(crate::Error::NotSupported(
+            "The data length isn't supported on this platform".to_string(),
+        ))
+    }
+
+    /// Changes the data length the adapter asks for on new connections, until it's reset. This
+    /// needs the `CAP_NET_RAW` capability, and changes it for every application using the adapter.
+    async fn set_suggested_data_length(&self, length: DataLength) -> Result<()> {
+        let _ = length;
+        Err(crate::Error::NotSupported(
+            "The data length isn't supported on this platform".to_string(),
+        ))
+    }
+
+    /// Get information about the Bluetooth adapter being used, such as its address, name and
+    /// capabilities.
+    ///
+    /// The [`description`](AdapterInfo::description) is platform-specific and you should not
+    /// attempt to parse it, but it may be useful for debug logs.
+    async fn adapter_info(&self) -> Result<AdapterInfo>;
 
     /// Get information about the Bluetooth adapter state.
     async fn adapter_state(&self) -> Result<CentralState>;
+
+    /// Returns true iff the adapter is currently powered on.
+    async fn is_powered(&self) -> Result<bool> {
+        Ok(self.adapter_state().await? == CentralState::PoweredOn)
+    }
+
+    /// Turns the adapter on or off. This is supported on Linux and Windows; on other platforms,
+    /// or if the system doesn't allow it, an error is returned. Watch for
+    /// [`CentralEvent::StateUpdate`] to find out when the change has taken effect.
+    async fn set_powered(&self, powered: bool) -> Result<()> {
+        let _ = powered;
+        Err(crate::Error::NotSupported(
+            "Turning the adapter on or off isn't supported on this platform".to_string(),
+        ))
+    }
+
+    /// Makes the adapter discoverable, so that other devices scanning find it, or stops it being
+    /// discoverable. This is for when the system acts as a peripheral, such as a GATT server which
+    /// phones have to find while it's being provisioned. With a `timeout`, it stops being
+    /// discoverable by itself after that long.
+    ///
+    /// This is supported with BlueZ and the `hci` backend, where it needs the `CAP_NET_ADMIN`
+    /// capability; elsewhere this returns [`Error::NotSupported`](crate::Error::NotSupported).
+    async fn set_discoverable(&self, discoverable: bool, timeout: Option<Duration>) -> Result<()> {
+        let _ = (discoverable, timeout);
+        Err(crate::Error::NotSupported(
+            "Making the adapter discoverable isn't supported on this platform".to_string(),
+        ))
+    }
+
+    /// Sets the name the adapter presents to other devices, such as phones scanning for a kiosk
+    /// which acts as a GATT server, in place of the system name. With BlueZ, an empty alias goes
+    /// back to the system name.
+    ///
+    /// This is supported with BlueZ, where it sets the adapter's `Alias`, and the `hci` backend,
+    /// where it sets the controller's local name and needs the `CAP_NET_ADMIN` capability;
+    /// elsewhere this returns [`Error::NotSupported`](crate::Error::NotSupported).
+    async fn set_alias(&self, alias: &str) -> Result<()> {
+        let _ = alias;
+        Err(crate::Error::NotSupported(
+            "Setting the adapter's name isn't supported on this platform".to_string(),
+        ))
+    }
+
+    /// Sets whether the adapter accepts requests to pair from other devices, so that devices
+    /// which connect to it can bond with it.
+    ///
+    /// This is supported with BlueZ and the `hci` backend, where it needs the `CAP_NET_ADMIN`
+    /// capability; elsewhere this returns [`Error::NotSupported`](crate::Error::NotSupported).
+    async fn set_pairable(&self, pairable: bool) -> Result<()> {
+        let _ = pairable;
+        Err(crate::Error::NotSupported(
+            "Setting whether the adapter is pairable isn't supported on this platform".to_string(),
+        ))
+    }
+
+    /// Starts broadcasting `advertiser` from the adapter, until the returned [`Advertising`] is
+    /// dropped. See [`advertiser`] for what each platform lets applications advertise.
+    ///
+    /// [`Advertising`]: advertiser::Advertising
+    ///
+    /// This is supported with BlueZ, the `hci` backend, where it needs the `CAP_NET_ADMIN`
+    /// capability, and Windows; elsewhere this returns
+    /// [`Error::NotSupported`](crate::Error::NotSupported).
+    async fn start_advertising(
+        &self,
+        advertiser: advertiser::Advertiser,
+    ) -> Result<advertiser::Advertising> {
+        let _ = advertiser;
+        Err(crate::Error::NotSupported(
+            "Advertising isn't supported on this platform".to_string(),
+        ))
+    }
+
+    /// Returns the timeout set with [`set_operation_timeout`](Central::set_operation_timeout), if
+    /// any.
+    fn operation_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Sets how long connecting to, discovering services on, reading from, writing to and
+    /// subscribing to this adapter's peripherals may take before failing with
+    /// [`Error::TimedOut`](crate::Error::TimedOut). This also applies to peripherals which have
+    /// already been found.
+    ///
+    /// The default is `None`, which waits as long as the platform does. Some platforms never give
+    /// up if their Bluetooth stack gets stuck. A timed out operation may still take effect on the
+    /// device. Adapters which don't support a timeout ignore it, and keep returning `None` from
+    /// [`operation_timeout`](Central::operation_timeout).
+    fn set_operation_timeout(&self, timeout: Option<Duration>) {
+        let _ = timeout;
+    }
+
+    /// Sets the [`Observer`](observer::Observer) told about advertisements, connections, GATT
+    /// operations and dropped notifications on this adapter and its peripherals, or removes it with
+    /// `None`. Like the operation timeout, this also applies to peripherals which have already been
+    /// found. Adapters which don't support observers ignore it.
+    fn set_observer(&self, observer: Option<Arc<dyn observer::Observer>>) {
+        let _ = observer;
+    }
+
+    /// Sets how long a peripheral may go without being seen before it's removed from the adapter,
+    /// as with [`remove_peripheral`](Central::remove_peripheral), so that an adapter which scans
+    /// for a long time among many devices doesn't keep all of them forever. Connected peripherals
+    /// are never removed. Peripherals are checked when a new one is found and when
+    /// [`peripherals`](Central::peripherals) is called, at most once a second.
+    ///
+    /// The default is `None`, which keeps peripherals until they are removed. BlueZ removes
+    /// devices it hasn't seen for a while itself; with a TTL, those not seen for the TTL are
+    /// removed sooner, unless they are paired. Adapters which don't support a TTL ignore it.
+    fn set_peripheral_ttl(&self, ttl: Option<Duration>) {
+        let _ = ttl;
+    }
+
+    /// Turns on tracking the RSSI of each advertisement from this adapter's peripherals, which
+    /// [`Peripheral::rssi_stats`] then smooths, or changes how it's tracked. `None` turns it off,
+    /// and forgets the samples. See [`rssi`] for more.
+    ///
+    /// The default is `None`. With BlueZ, samples come from BlueZ's updates to the RSSI, which it
+    /// makes less often than it receives advertisements if they don't change anything else.
+    /// Adapters which don't support RSSI tracking ignore it, and their peripherals' `rssi_stats`
+    /// stays `None`.
+    fn set_rssi_tracking(&self, tracking: Option<rssi::RssiTracking>) {
+        let _ = tracking;
+    }
+}
+
+/// Scans, and tries to connect to each peripheral matching the target as it advertises, until a
+/// connection succeeds. This is the default for [`Central::connect_when_available`].
+pub(crate) async fn connect_when_advertising<C: Central>(
+    central: &C,
+    target: ConnectTarget,
+) -> Result<C::Peripheral> {
+    let (wanted, filter) = match target {
+        ConnectTarget::Peripheral(id) => (Some(id), ScanFilter::default()),
+        ConnectTarget::Filter(filter) => (None, filter),
+    };
+    let mut events = central.events().await?;
+    central.start_scan(filter.clone()).await?;
+    // A peripheral which is already connected may not be advertising.
+    if let Some(id) = &wanted {
+        if let Ok(peripheral) = central.peripheral(id).await {
+            if peripheral.is_connected().await? {
+                return Ok(peripheral);
+            }
+        }
+    }
+    while let Some(event) = events.next().await {
+        let id = match event {
+            CentralEvent::DeviceDiscovered(id) | CentralEvent::DeviceUpdated(id) => id,
+            _ => continue,
+        };
+        if wanted.as_ref().is_some_and(|wanted| *wanted != id) {
+            continue;
+        }
+        let Ok(peripheral) = central.peripheral(&id).await else {
+            continue;
+        };
+        if !filter.services.is_empty() {
+            let advertised = peripheral.properties().await?.is_some_and(|properties| {
+                properties
+                    .services
+                    .iter()
+                    .any(|uuid| filter.services.contains(uuid))
+            });
+            if !advertised {
+                continue;
+            }
+        }
+        match peripheral.connect().await {
+            Ok(()) => return Ok(peripheral),
+            Err(e @ (crate::Error::PermissionDenied | crate::Error::NotSupported(_))) => {
+                return Err(e)
+            }
+            Err(e) => log::debug!("Failed to connect to {:?}, will try again: {}", id, e),
+        }
+    }
+    Err(crate::Error::DeviceNotFound)
+}
+
+/// Events emitted by a [`Manager`] when Bluetooth adapters are added to or removed from the system.
+#[derive(Debug, Clone)]
+pub enum ManagerEvent<A> {
+    /// An adapter has become available, for example because a USB dongle was plugged in.
+    AdapterAdded(A),
+    /// An adapter has gone away. It can no longer be used.
+    AdapterRemoved(A),
+}
+
+/// A [`CentralEvent`] tagged with the adapter it came from, as returned by [`Manager::scan_all`].
+#[derive(Debug, Clone)]
+pub struct AdapterEvent<A> {
+    /// The adapter which produced the event.
+    pub adapter: A,
+    /// The event itself. Any peripheral ID in it belongs to `adapter`.
+    pub event: CentralEvent,
 }
 
 /// The Manager is the entry point to the library, providing access to all the Bluetooth adapters on
@@ -404,4 +2286,116 @@ pub trait Manager {
 
     /// Get a list of all Bluetooth adapters on the system. Each adapter implements [`Central`].
     async fn adapters(&self) -> Result<Vec<Self::Adapter>>;
+
+    /// Get a stream of adapters being added to and removed from the system.
+    ///
+    /// An [`AdapterAdded`](ManagerEvent::AdapterAdded) event is emitted for each adapter which is
+    /// already present when the stream is created, so there is no need to call
+    /// [`adapters`](Manager::adapters) as well. On platforms with a single fixed adapter
+    /// (CoreBluetooth and Android) that is the only event. Managers which can't watch for adapters
+    /// being added or removed only emit those events.
+    async fn events(
+        &self,
+    ) -> Result<Pin<Box<dyn Stream<Item = ManagerEvent<Self::Adapter>> + Send>>>
+    where
+        Self::Adapter: 'static,
+    {
+        let adapters = self.adapters().await?;
+        Ok(Box::pin(stream::iter(
+            adapters.into_iter().map(ManagerEvent::AdapterAdded),
+        )))
+    }
+
+    /// Sets a handler which is given the [record](observer::OperationRecord) of every connection
+    /// attempt, GATT operation and other operation on the peripherals of this manager's adapters
+    /// as it finishes, including adapters found later, or removes it with `None`. Unlike an
+    /// [`Observer`](observer::Observer), this covers every adapter at once. Managers which don't
+    /// support trace handlers ignore it.
+    fn set_trace_handler(&self, handler: Option<Arc<dyn observer::TraceHandler>>) {
+        let _ = handler;
+    }
+
+    /// Starts scanning with the given filter on every adapter, and returns their events merged into
+    /// one stream.
+    ///
+    /// Each device is only reported as [discovered](CentralEvent::DeviceDiscovered) once, by the
+    /// first adapter to see it; all other events are passed through, so you still get updates and
+    /// advertisements from whichever adapter receives them. Call
+    /// [`stop_scan`](Central::stop_scan) on each adapter to stop.
+    async fn scan_all(
+        &self,
+        filter: ScanFilter,
+    ) -> Result<Pin<Box<dyn Stream<Item = AdapterEvent<Self::Adapter>> + Send>>>
+    where
+        Self: Sync,
+        Self::Adapter: 'static,
+    {
+        let mut streams = Vec::new();
+        for adapter in self.adapters().await? {
+            let events = adapter.events().await?;
+            adapter.start_scan(filter.clone()).await?;
+            streams.push(events.map(move |event| AdapterEvent {
+                adapter: adapter.clone(),
+                event,
+            }));
+        }
+
+        // Devices are matched by address where we have one. CoreBluetooth doesn't give us
+        // addresses, but it only has one adapter anyway, so fall back to the ID there.
+        let seen = Arc::new(Mutex::new((HashSet::new(), HashSet::new())));
+        let events = stream::select_all(streams).filter_map(move |event| {
+            let seen = seen.clone();
+            async move {
+                let AdapterEvent {
+                    adapter,
+                    event: CentralEvent::DeviceDiscovered(id),
+                } = &event
+                else {
+                    return Some(event);
+                };
+                let address = match adapter.peripheral(id).await {
+                    Ok(peripheral) => peripheral.address(),
+                    Err(_) => BDAddr::default(),
+                };
+                let mut seen = seen.lock().unwrap();
+                let (addresses, ids) = &mut *seen;
+                let new = if address == BDAddr::default() {
+                    ids.insert(id.clone())
+                } else {
+                    addresses.insert(address)
+                };
+                new.then_some(event)
+            }
+        });
+        Ok(Box::pin(events))
+    }
+
+    /// Returns the adapter with the given Bluetooth address, if there is one.
+    async fn adapter_by_address(&self, address: BDAddr) -> Result<Option<Self::Adapter>>
+    where
+        Self: Sync,
+    {
+        for adapter in self.adapters().await? {
+            if adapter.adapter_info().await?.address == Some(address) {
+                return Ok(Some(adapter));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the adapter with the given [`id`](AdapterInfo::id), if there is one.
+    ///
+    /// Unlike the order of [`adapters`](Manager::adapters), this is stable across reboots, so it can
+    /// be used to pin a service to a particular adapter.
+    async fn adapter_by_id(&self, id: &str) -> Result<Option<Self::Adapter>>
+    where
+        Self: Sync,
+    {
+        for adapter in self.adapters().await? {
+            if adapter.adapter_info().await?.id == id {
+                return Ok(Some(adapter));
+            }
+        }
+        Ok(None)
+    }
 }
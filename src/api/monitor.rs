@@ -0,0 +1,309 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Watching for particular devices coming into and going out of range, with as little power as
+//! the platform allows, for example for presence detection which runs all the time.
+//!
+//! An [`AdvertisementMonitor`] passed to [`Central::monitor`] says which
+//! advertisements to look for, and optionally how strong their signal has to be. With BlueZ's
+//! advertisement monitor API, advertisements are matched by the kernel or the controller while
+//! scanning passively; elsewhere, and with versions of BlueZ without it, btleplug scans as usual and
+//! matches them itself.
+//!
+//! ```no_run
+//! use btleplug::api::monitor::{AdvertisementMonitor, AdvertisementPattern, MonitorEvent};
+//! use btleplug::api::Central;
+//! use futures::StreamExt;
+//!
+//! async fn watch(central: &(impl Central + 'static)) -> btleplug::Result<()> {
+//!     // Apple's iBeacons.
+//!     let monitor =
+//!         AdvertisementMonitor::new(vec![AdvertisementPattern::manufacturer_data(0x004c, &[0x02])]);
+//!     let mut events = central.monitor(monitor).await?;
+//!     while let Some(event) = events.next().await {
+//!         match event {
+//!             MonitorEvent::DeviceFound(id) => println!("{} arrived", id),
+//!             MonitorEvent::DeviceLost(id) => println!("{} left", id),
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use super::{
+    bleuuid::BleUuid, Central, CentralEvent, Peripheral, PeripheralProperties, ScanFilter,
+};
+use crate::platform::PeripheralId;
+use crate::Result;
+use futures::stream::{Stream, StreamExt};
+use std::collections::HashSet;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const AD_INCOMPLETE_16_BIT_SERVICES: u8 = 0x02;
+const AD_COMPLETE_16_BIT_SERVICES: u8 = 0x03;
+const AD_SHORTENED_LOCAL_NAME: u8 = 0x08;
+const AD_COMPLETE_LOCAL_NAME: u8 = 0x09;
+const AD_TX_POWER_LEVEL: u8 = 0x0a;
+const AD_SERVICE_DATA_16_BIT: u8 = 0x16;
+const AD_APPEARANCE: u8 = 0x19;
+const AD_MANUFACTURER_DATA: u8 = 0xff;
+
+/// Bytes to look for in the AD structures of a given type in a device's advertisements.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AdvertisementPattern {
+    /// The AD type, such as `0xff` for manufacturer data.
+    pub ad_type: u8,
+    /// Where in the data of the AD structure the content starts.
+    pub offset: u8,
+    pub content: Vec<u8>,
+}
+
+impl AdvertisementPattern {
+    pub fn new(ad_type: u8, offset: u8, content: Vec<u8>) -> Self {
+        AdvertisementPattern {
+            ad_type,
+            offset,
+            content,
+        }
+    }
+
+    /// Matches manufacturer data from the company with the given ID which starts with `prefix`.
+    pub fn manufacturer_data(company_id: u16, prefix: &[u8]) -> Self {
+        let mut content = company_id.to_le_bytes().to_vec();
+        content.extend_from_slice(prefix);
+        Self::new(AD_MANUFACTURER_DATA, 0, content)
+    }
+
+    /// Matches data for the service with the given 16-bit UUID which starts with `prefix`.
+    pub fn service_data(uuid: u16, prefix: &[u8]) -> Self {
+        let mut content = uuid.to_le_bytes().to_vec();
+        content.extend_from_slice(prefix);
+        Self::new(AD_SERVICE_DATA_16_BIT, 0, content)
+    }
+
+    /// Returns whether the most recent advertisement in the given properties matches. The AD
+    /// structures are put back together from the properties, so only the types which btleplug
+    /// parses can match: manufacturer data, 16-bit service data and service UUIDs, the local name,
+    /// the transmission power level and the appearance.
+    pub fn matches(&self, properties: &PeripheralProperties) -> bool {
+        ad_structures(properties, self.ad_type).iter().any(|data| {
+            data.get(usize::from(self.offset)..)
+                .is_some_and(|data| data.starts_with(&self.content))
+        })
+    }
+}
+
+/// Returns the data of the AD structures of the given type which the properties were parsed from,
+/// as far as they can be put back together.
+fn ad_structures(properties: &PeripheralProperties, ad_type: u8) -> Vec<Vec<u8>> {
+    let with_prefix = |prefix: &[u8], data: &[u8]| [prefix, data].concat();
+    match ad_type {
+        AD_MANUFACTURER_DATA => properties
+            .manufacturer_data
+            .iter()
+            .map(|(company_id, data)| with_prefix(&company_id.to_le_bytes(), data))
+            .collect(),
+        AD_SERVICE_DATA_16_BIT => properties
+            .service_data
+            .iter()
+            .filter_map(|(uuid, data)| Some(with_prefix(&uuid.to_ble_u16()?.to_le_bytes(), data)))
+            .collect(),
+        AD_INCOMPLETE_16_BIT_SERVICES | AD_COMPLETE_16_BIT_SERVICES => vec![properties
+            .services
+            .iter()
+            .filter_map(|uuid| uuid.to_ble_u16())
+            .flat_map(u16::to_le_bytes)
+            .collect()],
+        AD_SHORTENED_LOCAL_NAME | AD_COMPLETE_LOCAL_NAME => properties
+            .local_name
+            .iter()
+            .map(|name| name.as_bytes().to_vec())
+            .collect(),
+        AD_TX_POWER_LEVEL => properties
+            .tx_power_level
+            .iter()
+            .map(|&level| vec![level as u8])
+            .collect(),
+        AD_APPEARANCE => properties
+            .appearance
+            .iter()
+            .map(|appearance| appearance.raw().to_le_bytes().to_vec())
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// When a monitored device counts as in range. A device is found once its signal has been at
+/// least `high` for `high_timeout`, and lost once it has been below `low` for `low_timeout`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RssiThresholds {
+    /// In dBm, from -127 to 20.
+    pub high: i16,
+    pub high_timeout: Duration,
+    /// In dBm, from -127 to 20.
+    pub low: i16,
+    pub low_timeout: Duration,
+}
+
+/// Which devices [`Central::monitor`] watches for.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct AdvertisementMonitor {
+    /// Devices whose advertisements match at least one of these are monitored. BlueZ only offloads
+    /// monitors with at least one pattern.
+    pub patterns: Vec<AdvertisementPattern>,
+    /// If this is `None`, a device is found as soon as it's seen.
+    pub rssi: Option<RssiThresholds>,
+}
+
+impl AdvertisementMonitor {
+    pub fn new(patterns: Vec<AdvertisementPattern>) -> Self {
+        AdvertisementMonitor {
+            patterns,
+            rssi: None,
+        }
+    }
+
+    pub fn with_rssi(mut self, rssi: RssiThresholds) -> Self {
+        self.rssi = Some(rssi);
+        self
+    }
+
+    fn matches(&self, properties: &PeripheralProperties) -> bool {
+        self.patterns.is_empty()
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| pattern.matches(properties))
+    }
+}
+
+/// A monitored device coming into or going out of range.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MonitorEvent {
+    DeviceFound(PeripheralId),
+    DeviceLost(PeripheralId),
+}
+
+/// Monitors by scanning and matching the advertisements which come in. The RSSI thresholds apply
+/// to each advertisement on its own, without the timeouts.
+pub(crate) async fn by_scanning<C: Central + 'static>(
+    central: &C,
+    monitor: AdvertisementMonitor,
+) -> Result<Pin<Box<dyn Stream<Item = MonitorEvent> + Send>>> {
+    let events = central.events().await?;
+    central.start_scan(ScanFilter::default()).await?;
+    let found = Arc::new(Mutex::new(HashSet::new()));
+    let central = central.clone();
+    Ok(Box::pin(events.filter_map(move |event| {
+        let central = central.clone();
+        let monitor = monitor.clone();
+        let found = found.clone();
+        async move {
+            let id = match event {
+                CentralEvent::DeviceDiscovered(id) | CentralEvent::DeviceUpdated(id) => id,
+                _ => return None,
+            };
+            let properties = central
+                .peripheral(&id)
+                .await
+                .ok()?
+                .properties()
+                .await
+                .ok()??;
+            if !monitor.matches(&properties) {
+                return None;
+            }
+            let mut found = found.lock().unwrap();
+            let (in_range, out_of_range) = match (monitor.rssi, properties.rssi) {
+                (Some(thresholds), Some(rssi)) => (rssi >= thresholds.high, rssi < thresholds.low),
+                _ => (true, false),
+            };
+            if in_range && found.insert(id.clone()) {
+                Some(MonitorEvent::DeviceFound(id))
+            } else if out_of_range && found.remove(&id) {
+                Some(MonitorEvent::DeviceLost(id))
+            } else {
+                None
+            }
+        }
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{bleuuid::uuid_from_u16, Appearance};
+
+    #[test]
+    fn patterns() {
+        let mut properties = PeripheralProperties {
+            local_name: Some("Tag 42".to_string()),
+            services: vec![uuid_from_u16(0x180f), uuid_from_u16(0xfeaa)],
+            appearance: Some(Appearance::from(0x0200)),
+            ..Default::default()
+        };
+        properties
+            .manufacturer_data
+            .insert(0x004c, vec![0x02, 0x15, 0xaa].into());
+        properties
+            .service_data
+            .insert(uuid_from_u16(0xfeaa), vec![0x10, 0x00].into());
+
+        assert!(
+            AdvertisementPattern::manufacturer_data(0x004c, &[0x02, 0x15]).matches(&properties)
+        );
+        assert!(!AdvertisementPattern::manufacturer_data(0x0059, &[]).matches(&properties));
+        assert!(AdvertisementPattern::service_data(0xfeaa, &[0x10]).matches(&properties));
+        assert!(AdvertisementPattern::new(0x09, 4, b"42".to_vec()).matches(&properties));
+        assert!(!AdvertisementPattern::new(0x09, 7, vec![]).matches(&properties));
+        assert!(AdvertisementPattern::new(0x03, 2, vec![0xaa, 0xfe]).matches(&properties));
+        assert!(AdvertisementPattern::new(0x19, 0, vec![0x00, 0x02]).matches(&properties));
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn monitor_by_scanning() -> Result<()> {
+        use crate::mock::Manager;
+
+        let manager = Manager::new().await?;
+        let adapter = manager.add_adapter();
+        let monitor =
+            AdvertisementMonitor::new(vec![AdvertisementPattern::manufacturer_data(0x004c, &[])])
+                .with_rssi(RssiThresholds {
+                    high: -70,
+                    high_timeout: Duration::from_secs(1),
+                    low: -90,
+                    low_timeout: Duration::from_secs(5),
+                });
+        let mut events = adapter.monitor(monitor).await?;
+        let mut properties = PeripheralProperties {
+            rssi: Some(-80),
+            ..Default::default()
+        };
+        // Doesn't match.
+        adapter.advertise(properties.clone()).unwrap();
+        properties.manufacturer_data.insert(0x004c, vec![].into());
+        // Too far away.
+        adapter.advertise(properties.clone()).unwrap();
+        properties.rssi = Some(-60);
+        let tag = adapter.advertise(properties.clone()).unwrap();
+        assert_eq!(
+            events.next().await,
+            Some(MonitorEvent::DeviceFound(tag.id()))
+        );
+        properties.rssi = Some(-95);
+        adapter.advertise(properties.clone()).unwrap();
+        assert_eq!(
+            events.next().await,
+            Some(MonitorEvent::DeviceLost(tag.id()))
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use std::fmt::{self, Display, Formatter};
+
+/// An error as reported by the platform's Bluetooth stack, for applications which need to
+/// recover from specific platform failures. Get it from an [`Error`](crate::Error) with
+/// [`Error::native_error`](crate::Error::native_error).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum NativeError {
+    /// A D-Bus error returned by BlueZ.
+    Dbus {
+        /// The name of the error, e.g. `org.bluez.Error.Failed`.
+        name: String,
+        /// The message which came with the error, if any.
+        message: Option<String>,
+    },
+    /// A WinRT GATT operation which finished with a `GattCommunicationStatus` other than
+    /// `Success`.
+    GattCommunicationStatus {
+        /// The value of the `GattCommunicationStatus`.
+        status: i32,
+        /// The ATT error code the device returned, if the status is `ProtocolError`.
+        protocol_error: Option<u8>,
+    },
+    /// A WinRT call which failed with an `HRESULT`.
+    HResult {
+        /// The `HRESULT` code.
+        code: i32,
+        /// The message for the code.
+        message: String,
+    },
+    /// A WinRT pairing which finished with a `DevicePairingResultStatus` other than `Paired` or
+    /// `AlreadyPaired`.
+    PairingStatus(i32),
+    /// An `NSError` from CoreBluetooth.
+    NSError {
+        /// The error domain, e.g. `CBErrorDomain` or `CBATTErrorDomain`.
+        domain: String,
+        /// The error code within the domain, e.g. a `CBError` or `CBATTError`.
+        code: isize,
+        /// The localized description of the error.
+        description: String,
+    },
+    /// A `BluetoothGatt` status from Android, such as `GATT_ERROR` (133).
+    GattStatus(i32),
+    /// A status returned by the Linux kernel's Bluetooth management interface to the
+    /// [hci](crate::hci) backend, such as Busy (`0x0a`).
+    MgmtStatus(u8),
+    /// A status returned by the Bluetooth controller to an HCI command on Linux or FreeBSD, such as
+    /// Unsupported Feature or Parameter Value (`0x11`).
+    HciStatus(u8),
+    /// An exception thrown by the browser's Web Bluetooth API, which is usually a `DOMException`.
+    DomException {
+        /// The name of the exception, e.g. `InvalidStateError`.
+        name: String,
+        /// The message which came with the exception.
+        message: String,
+    },
+}
+
+impl Display for NativeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            NativeError::Dbus {
+                name,
+                message: Some(message),
+            } => write!(f, "{}: {}", name, message),
+            NativeError::Dbus {
+                name,
+                message: None,
+            } => write!(f, "{}", name),
+            NativeError::GattCommunicationStatus {
+                status,
+                protocol_error: Some(protocol_error),
+            } => write!(
+                f,
+                "GATT communication status {} with protocol error {:#04x}",
+                status, protocol_error
+            ),
+            NativeError::GattCommunicationStatus {
+                status,
+                protocol_error: None,
+            } => write!(f, "GATT communication status {}", status),
+            NativeError::HResult { code, message } => {
+                write!(f, "HRESULT {:#010x}: {}", code, message)
+            }
+            NativeError::PairingStatus(status) => write!(f, "Pairing status {}", status),
+            NativeError::NSError {
+                domain,
+                code,
+                description,
+            } => write!(f, "{} {}: {}", domain, code, description),
+            NativeError::GattStatus(status) => write!(f, "GATT status {}", status),
+            NativeError::MgmtStatus(status) => write!(f, "Management status {:#04x}", status),
+            NativeError::HciStatus(status) => write!(f, "HCI status {:#04x}", status),
+            NativeError::DomException { name, message } => write!(f, "{}: {}", name, message),
+        }
+    }
+}
+
+impl std::error::Error for NativeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn native_error_from_other() {
+        let error = Error::Other(Box::new(NativeError::GattStatus(133)));
+        assert_eq!(error.native_error(), Some(&NativeError::GattStatus(133)));
+        assert_eq!(error.to_string(), "GATT status 133");
+
+        let error = Error::Other("Something else".into());
+        assert_eq!(error.native_error(), None);
+        assert_eq!(Error::NotConnected.native_error(), None);
+    }
+
+    #[test]
+    fn display() {
+        let error = NativeError::Dbus {
+            name: "org.bluez.Error.Failed".to_string(),
+            message: Some("Operation failed".to_string()),
+        };
+        assert_eq!(
+            error.to_string(),
+            "org.bluez.Error.Failed: Operation failed"
+        );
+        let error = NativeError::HResult {
+            code: 0x80070005u32 as i32,
+            message: "Access is denied.".to_string(),
+        };
+        assert_eq!(error.to_string(), "HRESULT 0x80070005: Access is denied.");
+    }
+}
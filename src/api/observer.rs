@@ -0,0 +1,143 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Hooks for measuring what an adapter and its peripherals do, for example to export them as
+//! metrics. An [`Observer`] set with [`Central::set_observer`](super::Central::set_observer) is
+//! told about advertisements, connections, GATT operations and dropped notifications as they
+//! happen.
+//!
+//! ```
+//! use btleplug::api::observer::{Observer, OperationRecord};
+//! use std::sync::atomic::{AtomicU64, Ordering};
+//!
+//! #[derive(Debug, Default)]
+//! struct Counters {
+//!     connects: AtomicU64,
+//!     failed_connects: AtomicU64,
+//! }
+//!
+//! impl Observer for Counters {
+//!     fn operation(&self, record: &OperationRecord) {
+//!         if record.operation == "connect" {
+//!             self.connects.fetch_add(1, Ordering::Relaxed);
+//!             if record.error.is_some() {
+//!                 self.failed_connects.fetch_add(1, Ordering::Relaxed);
+//!             }
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! For diagnostics rather than metrics, a [`TraceHandler`] set with
+//! [`Manager::set_trace_handler`](super::Manager::set_trace_handler) is given the record of every
+//! operation on the peripherals of all of a manager's adapters, without having to set an observer
+//! on each adapter or turn on logging.
+//!
+//! ```
+//! use btleplug::api::observer::OperationRecord;
+//! use btleplug::api::Manager as _;
+//! use std::sync::Arc;
+//!
+//! # async fn example(manager: btleplug::platform::Manager) {
+//! manager.set_trace_handler(Some(Arc::new(|record: &OperationRecord| {
+//!     println!(
+//!         "{} {} on {}: {:?} bytes in {:?}",
+//!         record.peripheral,
+//!         record.operation,
+//!         record.attribute.map_or_else(String::new, |uuid| uuid.to_string()),
+//!         record.payload_len,
+//!         record.duration
+//!     );
+//! })));
+//! # }
+//! ```
+
+use crate::platform::PeripheralId;
+use crate::Error;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Receives measurements of what an adapter and its peripherals do. Every method does nothing by
+/// default.
+///
+/// The methods are called on whichever thread or task did the work, so they should return quickly,
+/// e.g. by updating counters.
+pub trait Observer: Send + Sync + Debug {
+    /// An advertisement or scan response was received from the peripheral. Not reported by the
+    /// BlueZ backend, which only tells btleplug when what a peripheral advertises changes.
+    fn advertisement(&self, _id: &PeripheralId) {}
+
+    /// A connection attempt, GATT operation or other operation on a peripheral finished.
+    fn operation(&self, _record: &OperationRecord) {}
+
+    /// The peripheral was connected to, having been connected to before.
+    fn reconnect(&self, _id: &PeripheralId) {}
+
+    /// `count` notifications from the peripheral were dropped because a stream returned by
+    /// [`Peripheral::notifications`](super::Peripheral::notifications) wasn't read quickly enough.
+    fn notifications_dropped(&self, _id: &PeripheralId, _count: u64) {}
+}
+
+/// An operation which finished, as passed to [`Observer::operation`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct OperationRecord<'a> {
+    /// What the operation was, such as `"connect"`, `"discover services"`, `"read"` or
+    /// `"write descriptor"`.
+    pub operation: &'static str,
+    pub peripheral: &'a PeripheralId,
+    /// The UUID of the characteristic or descriptor the operation was on, if any.
+    pub attribute: Option<Uuid>,
+    /// How many bytes were written, or read if the read succeeded, for reads and writes of
+    /// characteristics and descriptors.
+    pub payload_len: Option<usize>,
+    /// How long the operation took, including any time spent waiting for other operations.
+    pub duration: Duration,
+    /// Why the operation failed, if it did.
+    pub error: Option<&'a Error>,
+}
+
+/// Receives the record of every operation on the peripherals of a manager's adapters, as set with
+/// [`Manager::set_trace_handler`](super::Manager::set_trace_handler). Closures taking an
+/// [`OperationRecord`] are trace handlers.
+///
+/// Like [`Observer`]'s methods, this is called on whichever thread or task did the work, so it
+/// should return quickly.
+pub trait TraceHandler: Send + Sync {
+    fn trace(&self, record: &OperationRecord);
+}
+
+impl<F: Fn(&OperationRecord) + Send + Sync> TraceHandler for F {
+    fn trace(&self, record: &OperationRecord) {
+        self(record)
+    }
+}
+
+/// The trace handler of a manager, shared with each adapter it creates so that setting it applies
+/// to adapters which already exist.
+#[derive(Clone, Default)]
+pub(crate) struct Tracer(Arc<RwLock<Option<Arc<dyn TraceHandler>>>>);
+
+impl Tracer {
+    pub(crate) fn set(&self, handler: Option<Arc<dyn TraceHandler>>) {
+        *self.0.write().unwrap() = handler;
+    }
+
+    pub(crate) fn trace(&self, record: &OperationRecord) {
+        if let Some(handler) = self.0.read().unwrap().as_ref() {
+            handler.trace(record);
+        }
+    }
+}
+
+impl Debug for Tracer {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("Tracer")
+    }
+}
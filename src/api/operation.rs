@@ -0,0 +1,365 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::expiry::PeripheralExpiry;
+use super::observer::{Observer, OperationRecord, Tracer};
+use super::rssi::RssiTrackers;
+use super::spans;
+use crate::platform::PeripheralId;
+use crate::time::Instant;
+use crate::{Error, Result};
+use futures::stream::{Stream, StreamExt};
+use log::{debug, log_enabled, Level};
+use std::{
+    fmt::Display,
+    future::{ready, Future},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use uuid::Uuid;
+
+/// The target of the log records of the operations run with [`OperationContext`].
+const LOG_TARGET: &str = "btleplug::operation";
+
+/// What an adapter shares with its peripherals.
+#[derive(Debug, Default)]
+struct Shared {
+    /// Set with [`Central::set_operation_timeout`](super::Central::set_operation_timeout).
+    timeout: Mutex<Option<Duration>>,
+    /// Set with [`Central::set_observer`](super::Central::set_observer).
+    observer: RwLock<Option<Arc<dyn Observer>>>,
+    expiry: PeripheralExpiry,
+    rssi: RssiTrackers,
+    tracer: Tracer,
+}
+
+#[derive(Debug)]
+struct PeripheralState {
+    id: PeripheralId,
+    /// Whether a connection has succeeded, so that the next one is a reconnection.
+    connected: AtomicBool,
+    /// When the peripheral last answered a GATT operation, and how long it took.
+    last_response: Mutex<Option<(Instant, Duration)>>,
+}
+
+/// What an adapter shares with its peripherals about running their operations and tracking them:
+/// the operation timeout, the [`Observer`] and the tracer, along with the peripheral TTL and the
+/// RSSI samples, so that changing any of them applies to peripherals which have already been
+/// found. A peripheral's copy also keeps what is particular to it.
+///
+/// Every GATT operation goes through this, so it is also where they are logged: each one logs a
+/// debug record to the `btleplug::operation` target when it finishes, with the peripheral,
+/// the attribute, how long it took and how it failed. With the `log-kv` feature these are also
+/// key-value fields of the record, for loggers which index them. With the `tracing` feature, each
+/// one also runs in a `tracing` span with the same fields.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct OperationContext {
+    shared: Arc<Shared>,
+    /// The peripheral whose operations these are.
+    peripheral: Option<Arc<PeripheralState>>,
+}
+
+impl OperationContext {
+    /// Creates the state of an adapter of a manager with the given tracer.
+    pub(crate) fn with_tracer(tracer: Tracer) -> Self {
+        OperationContext {
+            shared: Arc::new(Shared {
+                tracer,
+                ..Default::default()
+            }),
+            peripheral: None,
+        }
+    }
+
+    pub(crate) fn timeout(&self) -> Option<Duration> {
+        *self.shared.timeout.lock().unwrap()
+    }
+
+    pub(crate) fn set_timeout(&self, timeout: Option<Duration>) {
+        *self.shared.timeout.lock().unwrap() = timeout;
+    }
+
+    pub(crate) fn set_observer(&self, observer: Option<Arc<dyn Observer>>) {
+        *self.shared.observer.write().unwrap() = observer;
+    }
+
+    fn observer(&self) -> Option<Arc<dyn Observer>> {
+        self.shared.observer.read().unwrap().clone()
+    }
+
+    pub(crate) fn expiry(&self) -> &PeripheralExpiry {
+        &self.shared.expiry
+    }
+
+    pub(crate) fn rssi(&self) -> &RssiTrackers {
+        &self.shared.rssi
+    }
+
+    // Only used by some backends, so turn off deadcode so we don't get warnings on the other platforms.
+    #[allow(dead_code)]
+    pub(crate) fn tracer(&self) -> &Tracer {
+        &self.shared.tracer
+    }
+
+    /// Returns a copy sharing everything with the adapter's, for the operations of the given
+    /// peripheral.
+    pub(crate) fn for_peripheral(&self, id: PeripheralId) -> Self {
+        OperationContext {
+            shared: self.shared.clone(),
+            peripheral: Some(Arc::new(PeripheralState {
+                id,
+                connected: AtomicBool::new(false),
+                last_response: Mutex::new(None),
+            })),
+        }
+    }
+
+    /// Returns when the peripheral last answered a GATT operation, and how long it took.
+    pub(crate) fn last_response(&self) -> Option<(Instant, Duration)> {
+        *self.peripheral.as_ref()?.last_response.lock().unwrap()
+    }
+
+    /// Tells the observer about an event the adapter is emitting. The BlueZ backend doesn't emit
+    /// events itself, so doesn't use this.
+    #[allow(dead_code)]
+    pub(crate) fn observe_event(&self, event: &super::CentralEvent) {
+        use super::CentralEvent::{DeviceDiscovered, DeviceUpdated};
+        if let DeviceDiscovered(id) | DeviceUpdated(id) = event {
+            if let Some(observer) = self.observer() {
+                observer.advertisement(id);
+            }
+        }
+    }
+
+    /// Returns a stream of the notifications from a broadcast channel, telling the observer about
+    /// those dropped because the stream fell behind. Not every backend's notifications come through
+    /// a broadcast channel.
+    #[allow(dead_code)]
+    pub(crate) fn notifications_stream<T: Clone + Send + 'static>(
+        &self,
+        receiver: broadcast::Receiver<T>,
+    ) -> Pin<Box<dyn Stream<Item = T> + Send>> {
+        let this = self.clone();
+        Box::pin(BroadcastStream::new(receiver).filter_map(move |item| {
+            ready(match item {
+                Ok(item) => Some(item),
+                Err(BroadcastStreamRecvError::Lagged(count)) => {
+                    if let (Some(observer), Some(peripheral)) = (this.observer(), &this.peripheral)
+                    {
+                        observer.notifications_dropped(&peripheral.id, count);
+                    }
+                    None
+                }
+            })
+        }))
+    }
+
+    /// Runs `future`, failing with [`Error::TimedOut`] if it takes longer than the timeout.
+    pub(crate) async fn run<T>(
+        &self,
+        operation: &'static str,
+        future: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        self.run_inner(operation, None, |_| None, future).await
+    }
+
+    /// Like [`run`](Self::run), for an operation on the characteristic or descriptor with the
+    /// given UUID.
+    pub(crate) async fn run_on<T>(
+        &self,
+        operation: &'static str,
+        attribute: Uuid,
+        future: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        self.run_inner(operation, Some(attribute), |_| None, future)
+            .await
+    }
+
+    /// Like [`run_on`](Self::run_on), for a write of `data`.
+    pub(crate) async fn run_write<T>(
+        &self,
+        operation: &'static str,
+        attribute: Uuid,
+        data: &[u8],
+        future: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let len = data.len();
+        self.run_inner(operation, Some(attribute), move |_| Some(len), future)
+            .await
+    }
+
+    /// Like [`run_on`](Self::run_on), for a read.
+    pub(crate) async fn run_read(
+        &self,
+        operation: &'static str,
+        attribute: Uuid,
+        future: impl Future<Output = Result<Vec<u8>>>,
+    ) -> Result<Vec<u8>> {
+        self.run_inner(
+            operation,
+            Some(attribute),
+            |value: Option<&Vec<u8>>| value.map(Vec::len),
+            future,
+        )
+        .await
+    }
+
+    async fn run_inner<T>(
+        &self,
+        operation: &'static str,
+        attribute: Option<Uuid>,
+        payload_len: impl FnOnce(Option<&T>) -> Option<usize>,
+        future: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let span = spans::operation(
+            operation,
+            self.peripheral
+                .as_ref()
+                .map(|peripheral| &peripheral.id as &dyn Display),
+            attribute,
+        );
+        let start = Instant::now();
+        let result = span
+            .instrument(async {
+                match self.timeout() {
+                    Some(timeout) => crate::rt::timeout(timeout, future)
+                        .await
+                        .map_err(|_| Error::TimedOut { operation, timeout })
+                        .and_then(|result| result),
+                    None => future.await,
+                }
+            })
+            .await;
+        let duration = start.elapsed();
+        span.record_outcome(duration, result.as_ref().err());
+        // A GATT operation which the peripheral rejected was still answered.
+        if attribute.is_some() && matches!(result, Ok(_) | Err(Error::Att(_))) {
+            if let Some(peripheral) = &self.peripheral {
+                *peripheral.last_response.lock().unwrap() = Some((Instant::now(), duration));
+            }
+        }
+        if log_enabled!(target: LOG_TARGET, Level::Debug) {
+            self.log(operation, attribute, duration, result.as_ref().err());
+        }
+        if let Some(peripheral) = &self.peripheral {
+            self.observe(
+                peripheral,
+                operation,
+                attribute,
+                payload_len(result.as_ref().ok()),
+                duration,
+                result.as_ref().err(),
+            );
+        }
+        result
+    }
+
+    fn observe(
+        &self,
+        peripheral: &PeripheralState,
+        operation: &'static str,
+        attribute: Option<Uuid>,
+        payload_len: Option<usize>,
+        duration: Duration,
+        error: Option<&Error>,
+    ) {
+        let reconnected = operation == "connect"
+            && error.is_none()
+            && peripheral.connected.swap(true, Ordering::Relaxed);
+        let record = OperationRecord {
+            operation,
+            peripheral: &peripheral.id,
+            attribute,
+            payload_len,
+            duration,
+            error,
+        };
+        self.shared.tracer.trace(&record);
+        let Some(observer) = self.observer() else {
+            return;
+        };
+        observer.operation(&record);
+        if reconnected {
+            observer.reconnect(&peripheral.id);
+        }
+    }
+
+    fn log(
+        &self,
+        operation: &'static str,
+        attribute: Option<Uuid>,
+        elapsed: Duration,
+        error: Option<&Error>,
+    ) {
+        let peripheral = self
+            .peripheral
+            .as_ref()
+            .map_or_else(|| "-".to_string(), |peripheral| peripheral.id.to_string());
+        let peripheral = peripheral.as_str();
+        let attribute = attribute.map(|uuid| uuid.to_string());
+        let attribute = attribute.as_deref().unwrap_or("-");
+        let outcome = error.map_or_else(|| "ok".to_string(), |error| error.to_string());
+        let status = error
+            .and_then(Error::native_error)
+            .map(|status| status.to_string());
+        let status = status.as_deref().unwrap_or("-");
+        let duration_ms = elapsed.as_millis() as u64;
+        #[cfg(feature = "log-kv")]
+        debug!(
+            target: LOG_TARGET,
+            operation,
+            peripheral,
+            attribute,
+            duration_ms,
+            outcome:%,
+            status;
+            "{} on {} {}: {} after {}ms", operation, peripheral, attribute, outcome, duration_ms
+        );
+        #[cfg(not(feature = "log-kv"))]
+        debug!(
+            target: LOG_TARGET,
+            "{} on {} {}: {} after {}ms (status {})",
+            operation,
+            peripheral,
+            attribute,
+            outcome,
+            duration_ms,
+            status
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn times_out() {
+        let context = OperationContext::default();
+        assert_eq!(context.run("read", async { Ok(1) }).await.unwrap(), 1);
+
+        context.set_timeout(Some(Duration::from_millis(10)));
+        let clone = context.clone();
+        let result = clone
+            .run("read", futures::future::pending::<Result<()>>())
+            .await;
+        assert!(matches!(
+            result,
+            Err(Error::TimedOut {
+                operation: "read",
+                ..
+            })
+        ));
+        assert_eq!(result.unwrap_err().to_string(), "read timed out after 10ms");
+    }
+}
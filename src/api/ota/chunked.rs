@@ -0,0 +1,192 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::{crc32, OtaTransport};
+use crate::api::{
+    negotiate_write_len, standard::find_characteristic, CharPropFlags, Characteristic, Peripheral,
+    WriteType,
+};
+use crate::Result;
+use async_trait::async_trait;
+use uuid::Uuid;
+
+/// Which characteristics a [`ChunkedWrite`] uses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkedWriteConfig {
+    pub service_uuid: Uuid,
+    /// The characteristic the image is written to, a chunk at a time.
+    pub data_uuid: Uuid,
+    /// The characteristic for the start and end commands and for resuming, if the device has
+    /// one. See [`ChunkedWrite`] for the commands.
+    pub control_uuid: Option<Uuid>,
+    /// The most bytes to write at once. If this is `None`, the largest MTU the platform allows is
+    /// asked for where that's supported, and otherwise 20 bytes are written at once.
+    pub max_chunk_len: Option<usize>,
+    /// How to write chunks. If this is `None`, they're written without response if the data
+    /// characteristic allows it.
+    pub write_type: Option<WriteType>,
+}
+
+/// Sends an image as consecutive writes to a data characteristic, for devices with a simple
+/// bootloader of their own.
+///
+/// If a control characteristic is configured, it's used with these commands, whose numbers are
+/// little-endian:
+///
+/// - Before sending, the control characteristic is read. If its value is a `u32` offset and a
+///   `u32` CRC-32 which matches that much of the image, sending resumes from the offset.
+/// - Then `[0x01, size: u32, crc: u32, offset: u32]` is written to it, with the size and CRC-32
+///   of the whole image, and the offset sending starts from.
+/// - After the last chunk, `[0x02]` is written to it. The device should check the image and fail
+///   the write if it's bad.
+#[derive(Debug)]
+pub struct ChunkedWrite<P> {
+    peripheral: P,
+    data: Characteristic,
+    control: Option<Characteristic>,
+    max_chunk_len: usize,
+    write_type: WriteType,
+}
+
+impl<P: Peripheral> ChunkedWrite<P> {
+    /// Finds the configured characteristics on a connected peripheral, discovering the service if
+    /// it hasn't been yet.
+    pub async fn new(peripheral: P, config: ChunkedWriteConfig) -> Result<ChunkedWrite<P>> {
+        let data = find_characteristic(&peripheral, config.service_uuid, config.data_uuid).await?;
+        let control = match config.control_uuid {
+            Some(uuid) => Some(find_characteristic(&peripheral, config.service_uuid, uuid).await?),
+            None => None,
+        };
+        let write_type = config.write_type.unwrap_or(
+            if data
+                .properties
+                .contains(CharPropFlags::WRITE_WITHOUT_RESPONSE)
+            {
+                WriteType::WithoutResponse
+            } else {
+                WriteType::WithResponse
+            },
+        );
+        let max_chunk_len = match config.max_chunk_len {
+            Some(len) => len.max(1),
+            None => negotiate_write_len(&peripheral).await,
+        };
+        Ok(ChunkedWrite {
+            peripheral,
+            data,
+            control,
+            max_chunk_len,
+            write_type,
+        })
+    }
+}
+
+#[async_trait]
+impl<P: Peripheral> OtaTransport for ChunkedWrite<P> {
+    fn max_chunk_len(&self) -> usize {
+        self.max_chunk_len
+    }
+
+    async fn begin(&mut self, image: &[u8]) -> Result<usize> {
+        let Some(control) = &self.control else {
+            return Ok(0);
+        };
+        let resume_at = match self.peripheral.read(control).await?[..] {
+            [o0, o1, o2, o3, c0, c1, c2, c3, ..] => {
+                let offset = u32::from_le_bytes([o0, o1, o2, o3]) as usize;
+                let crc = u32::from_le_bytes([c0, c1, c2, c3]);
+                if offset <= image.len() && crc32(&image[..offset]) == crc {
+                    offset
+                } else {
+                    0
+                }
+            }
+            _ => 0,
+        };
+        let size = u32::try_from(image.len())
+            .map_err(|_| crate::Error::NotSupported("Images of 4 GiB or more".to_string()))?;
+        let mut command = vec![0x01];
+        command.extend_from_slice(&size.to_le_bytes());
+        command.extend_from_slice(&crc32(image).to_le_bytes());
+        command.extend_from_slice(&(resume_at as u32).to_le_bytes());
+        self.peripheral
+            .write(control, &command, WriteType::WithResponse)
+            .await?;
+        Ok(resume_at)
+    }
+
+    async fn write_chunk(&mut self, _offset: usize, chunk: &[u8]) -> Result<()> {
+        self.peripheral
+            .write(&self.data, chunk, self.write_type)
+            .await
+    }
+
+    async fn finish(&mut self, _image: &[u8]) -> Result<()> {
+        if let Some(control) = &self.control {
+            self.peripheral
+                .write(control, &[0x02], WriteType::WithResponse)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::api::{ota, Central, ScanFilter, Service};
+    use crate::mock::Manager;
+
+    #[tokio::test]
+    async fn resumes_from_control_characteristic() -> Result<()> {
+        let manager = Manager::new().await?;
+        let adapter = manager.add_adapter();
+        adapter.start_scan(ScanFilter::default()).await?;
+        let service_uuid = Uuid::from_u128(0x1000);
+        let characteristic = |uuid| Characteristic {
+            uuid,
+            service_uuid,
+            properties: CharPropFlags::READ | CharPropFlags::WRITE,
+            descriptors: Default::default(),
+            handle: None,
+            value_handle: None,
+        };
+        let data = characteristic(Uuid::from_u128(0x1001));
+        let control = characteristic(Uuid::from_u128(0x1002));
+        let device = adapter.advertise(Default::default()).unwrap();
+        device.set_services([Service {
+            uuid: service_uuid,
+            primary: true,
+            characteristics: [data.clone(), control.clone()].into(),
+            included_services: Default::default(),
+            handle: None,
+        }]);
+        let image = b"firmware image";
+        let mut resume = 4u32.to_le_bytes().to_vec();
+        resume.extend_from_slice(&crc32(b"firm").to_le_bytes());
+        device.set_value(&control, resume);
+        device.connect().await?;
+
+        let mut transport = ChunkedWrite::new(
+            device.clone(),
+            ChunkedWriteConfig {
+                service_uuid,
+                data_uuid: data.uuid,
+                control_uuid: Some(control.uuid),
+                max_chunk_len: Some(8),
+                write_type: None,
+            },
+        )
+        .await?;
+        let mut progress = vec![];
+        ota::update(&mut transport, image, |p| progress.push(p.sent)).await?;
+        assert_eq!(progress, vec![4, 12, 14]);
+        assert_eq!(device.value(&data), Some(b"ge".to_vec()));
+        assert_eq!(device.value(&control), Some(vec![0x02]));
+        Ok(())
+    }
+}
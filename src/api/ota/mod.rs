@@ -0,0 +1,215 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Firmware updates over the air. Enabled with the `ota` feature.
+//!
+//! An [`OtaTransport`] knows how to send a firmware image to one kind of device, and [`update`]
+//! drives one from start to finish, reporting its [`Progress`]. Transports which can resume an
+//! interrupted update pick up where the device says the last one stopped, so after a disconnection
+//! it's enough to reconnect and call [`update`] again with the same image.
+//!
+//! Two transports are included: [`NordicDfu`] for Nordic's Secure DFU bootloader, and
+//! [`ChunkedWrite`] for devices which take the image as plain writes to a characteristic.
+//!
+//! ```no_run
+//! use btleplug::api::ota::{self, NordicDfu};
+//! # use btleplug::api::Peripheral;
+//! # async fn flash(peripheral: impl Peripheral + 'static, init_packet: Vec<u8>, firmware: Vec<u8>) -> btleplug::Result<()> {
+//! peripheral.connect().await?;
+//! let mut dfu = NordicDfu::new(peripheral, init_packet).await?;
+//! ota::update(&mut dfu, &firmware, |progress| {
+//!     println!("{}/{} bytes", progress.sent, progress.total);
+//! })
+//! .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod chunked;
+mod nordic;
+
+pub use self::{
+    chunked::{ChunkedWrite, ChunkedWriteConfig},
+    nordic::NordicDfu,
+};
+
+use crate::Result;
+use async_trait::async_trait;
+use thiserror::Error;
+
+/// Sends firmware images to a device. Most code only needs to pass one to [`update`], which calls
+/// these methods in order.
+#[async_trait]
+pub trait OtaTransport: Send {
+    /// The most bytes of the image to pass to [`write_chunk`](Self::write_chunk) at once.
+    fn max_chunk_len(&self) -> usize;
+
+    /// Gets the device ready to receive the image, and returns how much of it the device already
+    /// has from an earlier, interrupted update, which is 0 to start from the beginning.
+    async fn begin(&mut self, image: &[u8]) -> Result<usize>;
+
+    /// Sends the part of the image which starts at `offset`.
+    async fn write_chunk(&mut self, offset: usize, chunk: &[u8]) -> Result<()>;
+
+    /// Tells the device that the whole image has been sent, so it can check and apply it.
+    async fn finish(&mut self, image: &[u8]) -> Result<()>;
+}
+
+/// How far an update has got, as passed to the callback of [`update`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// How many bytes of the image the device has, including any it had before the update.
+    pub sent: usize,
+    /// The size of the image.
+    pub total: usize,
+}
+
+/// Why a device rejected an update, returned within [`Error::Other`](crate::Error::Other).
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OtaError {
+    /// The device answered a request with an error code.
+    #[error("Device rejected request {request:#04x} with result {result:#04x}")]
+    Rejected { request: u8, result: u8 },
+    /// The device's checksum of what it received doesn't match what was sent.
+    #[error("Device has {device_len} bytes with CRC {device_crc:#010x}, but {len} bytes with CRC {crc:#010x} were sent")]
+    ChecksumMismatch {
+        len: usize,
+        crc: u32,
+        device_len: usize,
+        device_crc: u32,
+    },
+    /// The device sent a response which couldn't be understood.
+    #[error("Unexpected response from device: {0:02x?}")]
+    UnexpectedResponse(Vec<u8>),
+}
+
+impl From<OtaError> for crate::Error {
+    fn from(error: OtaError) -> Self {
+        crate::Error::Other(Box::new(error))
+    }
+}
+
+/// Sends a firmware image through the transport, resuming an earlier update where the transport
+/// supports it, and calls `progress` as the device receives it.
+pub async fn update<T: OtaTransport + ?Sized>(
+    transport: &mut T,
+    image: &[u8],
+    mut progress: impl FnMut(Progress) + Send,
+) -> Result<()> {
+    let total = image.len();
+    let mut sent = transport.begin(image).await?.min(total);
+    progress(Progress { sent, total });
+    let chunk_len = transport.max_chunk_len().max(1);
+    while sent < total {
+        let end = (sent + chunk_len).min(total);
+        transport.write_chunk(sent, &image[sent..end]).await?;
+        sent = end;
+        progress(Progress { sent, total });
+    }
+    transport.finish(image).await
+}
+
+/// The CRC-32 used by zlib and Ethernet, computed as data arrives.
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32(u32);
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Crc32(!0)
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 ^= u32::from(byte);
+            for _ in 0..8 {
+                let mask = (self.0 & 1).wrapping_neg();
+                self.0 = (self.0 >> 1) ^ (0xEDB8_8320 & mask);
+            }
+        }
+    }
+
+    pub fn value(&self) -> u32 {
+        !self.0
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the CRC-32 of `data`, as used by zlib and Ethernet.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.value()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.value(), 0xCBF4_3926);
+    }
+
+    #[derive(Default)]
+    struct Recorded {
+        resume_at: usize,
+        chunks: Vec<(usize, Vec<u8>)>,
+        finished: bool,
+    }
+
+    #[async_trait]
+    impl OtaTransport for Recorded {
+        fn max_chunk_len(&self) -> usize {
+            4
+        }
+
+        async fn begin(&mut self, _image: &[u8]) -> Result<usize> {
+            Ok(self.resume_at)
+        }
+
+        async fn write_chunk(&mut self, offset: usize, chunk: &[u8]) -> Result<()> {
+            self.chunks.push((offset, chunk.to_vec()));
+            Ok(())
+        }
+
+        async fn finish(&mut self, _image: &[u8]) -> Result<()> {
+            self.finished = true;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn resumes() -> Result<()> {
+        let mut transport = Recorded {
+            resume_at: 3,
+            ..Default::default()
+        };
+        let mut reported = vec![];
+        update(&mut transport, b"0123456789", |progress| {
+            reported.push(progress.sent)
+        })
+        .await?;
+        assert_eq!(
+            transport.chunks,
+            vec![(3, b"3456".to_vec()), (7, b"789".to_vec())]
+        );
+        assert!(transport.finished);
+        assert_eq!(reported, vec![3, 7, 10]);
+        Ok(())
+    }
+}
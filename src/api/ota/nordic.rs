@@ -0,0 +1,329 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::{crc32, Crc32, OtaError, OtaTransport};
+use crate::api::{
+    bleuuid::uuid_from_u16, negotiate_write_len, standard::find_characteristic, Characteristic,
+    Peripheral, WriteType,
+};
+use crate::{rt, Error, Result};
+use async_trait::async_trait;
+use futures::stream::{Stream, StreamExt};
+use log::debug;
+use std::{pin::Pin, time::Duration};
+use uuid::Uuid;
+
+/// The UUID of the Secure DFU service.
+pub const SECURE_DFU_SERVICE_UUID: Uuid = uuid_from_u16(0xFE59);
+
+/// The UUID of the DFU Control Point characteristic.
+pub const DFU_CONTROL_POINT_UUID: Uuid = Uuid::from_u128(0x8ec90001_f315_4f60_9fb8_838830daea50);
+
+/// The UUID of the DFU Packet characteristic.
+pub const DFU_PACKET_UUID: Uuid = Uuid::from_u128(0x8ec90002_f315_4f60_9fb8_838830daea50);
+
+/// How long to wait for the bootloader to answer a request. Executing an object can take a while,
+/// as it may erase or write flash.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(20);
+
+const OP_CREATE: u8 = 0x01;
+const OP_SET_PRN: u8 = 0x02;
+const OP_CALCULATE_CHECKSUM: u8 = 0x03;
+const OP_EXECUTE: u8 = 0x04;
+const OP_SELECT: u8 = 0x06;
+const OP_RESPONSE: u8 = 0x60;
+
+const OBJECT_COMMAND: u8 = 0x01;
+const OBJECT_DATA: u8 = 0x02;
+
+const RESULT_SUCCESS: u8 = 0x01;
+const RESULT_OPERATION_NOT_PERMITTED: u8 = 0x08;
+
+/// Nordic's Secure DFU, as used by the bootloaders of the nRF5 SDK and nRF Connect SDK.
+///
+/// The image is the firmware binary, and the init packet is the signed description of it which
+/// comes with it, e.g. the `.bin` and `.dat` files of a DFU zip. The peripheral must already be
+/// connected in bootloader mode; devices with buttonless DFU have to be told to restart into it
+/// first, after which they advertise with a different address.
+///
+/// Packet receipt notifications are turned off, and each object is checked with its CRC-32 once
+/// it has been sent instead. An interrupted update resumes if the device still has the same init
+/// packet.
+pub struct NordicDfu<P> {
+    peripheral: P,
+    control_point: Characteristic,
+    packet: Characteristic,
+    responses: Pin<Box<dyn Stream<Item = Vec<u8>> + Send>>,
+    init_packet: Vec<u8>,
+    max_write_len: usize,
+    max_object_size: usize,
+    image_len: usize,
+    /// Where the data object being sent ends. When everything up to here has been sent, a new
+    /// object has to be created.
+    object_end: usize,
+    crc: Crc32,
+}
+
+/// What the bootloader said about an object type in answer to Select.
+struct Selected {
+    max_size: usize,
+    offset: usize,
+    crc: u32,
+}
+
+impl<P: Peripheral> NordicDfu<P> {
+    /// Finds the Secure DFU service on a connected peripheral, discovering it if it hasn't been
+    /// yet, and subscribes to its control point.
+    pub async fn new(peripheral: P, init_packet: Vec<u8>) -> Result<NordicDfu<P>> {
+        let control_point =
+            find_characteristic(&peripheral, SECURE_DFU_SERVICE_UUID, DFU_CONTROL_POINT_UUID)
+                .await?;
+        let packet =
+            find_characteristic(&peripheral, SECURE_DFU_SERVICE_UUID, DFU_PACKET_UUID).await?;
+        let responses = peripheral.subscribe_stream(&control_point).await?;
+        let max_write_len = negotiate_write_len(&peripheral).await;
+        Ok(NordicDfu {
+            peripheral,
+            control_point,
+            packet,
+            responses,
+            init_packet,
+            max_write_len,
+            max_object_size: 0,
+            image_len: 0,
+            object_end: 0,
+            crc: Crc32::new(),
+        })
+    }
+
+    /// Writes a request to the control point and waits for the bootloader's answer, returning what
+    /// follows the result code.
+    async fn request(&mut self, request: &[u8]) -> Result<Vec<u8>> {
+        self.peripheral
+            .write(&self.control_point, request, WriteType::WithResponse)
+            .await?;
+        let response = rt::timeout(RESPONSE_TIMEOUT, self.responses.next())
+            .await
+            .map_err(|_| Error::TimedOut {
+                operation: "DFU request",
+                timeout: RESPONSE_TIMEOUT,
+            })?
+            .ok_or(Error::NotConnected)?;
+        parse_response(request[0], response)
+    }
+
+    async fn select(&mut self, object_type: u8) -> Result<Selected> {
+        let response = self.request(&[OP_SELECT, object_type]).await?;
+        match response[..] {
+            [m0, m1, m2, m3, o0, o1, o2, o3, c0, c1, c2, c3, ..] => Ok(Selected {
+                max_size: u32::from_le_bytes([m0, m1, m2, m3]) as usize,
+                offset: u32::from_le_bytes([o0, o1, o2, o3]) as usize,
+                crc: u32::from_le_bytes([c0, c1, c2, c3]),
+            }),
+            _ => Err(OtaError::UnexpectedResponse(response).into()),
+        }
+    }
+
+    async fn create(&mut self, object_type: u8, size: usize) -> Result<()> {
+        let mut request = vec![OP_CREATE, object_type];
+        request.extend_from_slice(&(size as u32).to_le_bytes());
+        self.request(&request).await?;
+        Ok(())
+    }
+
+    /// Executes the object which has just been sent. If it has already been executed the
+    /// bootloader says the operation isn't permitted, which is fine when resuming.
+    async fn execute(&mut self, resuming: bool) -> Result<()> {
+        match self.request(&[OP_EXECUTE]).await {
+            Err(Error::Other(e))
+                if resuming
+                    && matches!(
+                        e.downcast_ref(),
+                        Some(OtaError::Rejected {
+                            result: RESULT_OPERATION_NOT_PERMITTED,
+                            ..
+                        })
+                    ) =>
+            {
+                Ok(())
+            }
+            result => result.map(|_| ()),
+        }
+    }
+
+    /// Checks that the bootloader has received `len` bytes of the current object type with the
+    /// given CRC-32.
+    async fn verify(&mut self, len: usize, crc: u32) -> Result<()> {
+        let response = self.request(&[OP_CALCULATE_CHECKSUM]).await?;
+        let (device_len, device_crc) = match response[..] {
+            [o0, o1, o2, o3, c0, c1, c2, c3, ..] => (
+                u32::from_le_bytes([o0, o1, o2, o3]) as usize,
+                u32::from_le_bytes([c0, c1, c2, c3]),
+            ),
+            _ => return Err(OtaError::UnexpectedResponse(response).into()),
+        };
+        if device_len != len || device_crc != crc {
+            return Err(OtaError::ChecksumMismatch {
+                len,
+                crc,
+                device_len,
+                device_crc,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    async fn write_packets(&mut self, data: &[u8]) -> Result<()> {
+        for packet in data.chunks(self.max_write_len) {
+            self.peripheral
+                .write(&self.packet, packet, WriteType::WithoutResponse)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Sends the init packet, unless the bootloader already has it. Returns whether it did, in
+    /// which case the firmware sent before can be resumed.
+    async fn send_init_packet(&mut self) -> Result<bool> {
+        let selected = self.select(OBJECT_COMMAND).await?;
+        let init_packet = std::mem::take(&mut self.init_packet);
+        let result = async {
+            if selected.offset == init_packet.len() && selected.crc == crc32(&init_packet) {
+                self.execute(true).await?;
+                return Ok(true);
+            }
+            if init_packet.len() > selected.max_size {
+                return Err(Error::NotSupported(format!(
+                    "Init packet of {} bytes, when the bootloader takes at most {}",
+                    init_packet.len(),
+                    selected.max_size
+                )));
+            }
+            self.create(OBJECT_COMMAND, init_packet.len()).await?;
+            self.write_packets(&init_packet).await?;
+            self.verify(init_packet.len(), crc32(&init_packet)).await?;
+            self.execute(false).await?;
+            Ok(false)
+        }
+        .await;
+        self.init_packet = init_packet;
+        result
+    }
+}
+
+/// Checks a control point response to the given request, and returns what follows its result
+/// code.
+fn parse_response(request: u8, response: Vec<u8>) -> Result<Vec<u8>> {
+    match response[..] {
+        [OP_RESPONSE, opcode, RESULT_SUCCESS, ..] if opcode == request => {
+            Ok(response[3..].to_vec())
+        }
+        [OP_RESPONSE, opcode, result, ..] if opcode == request => {
+            Err(OtaError::Rejected { request, result }.into())
+        }
+        _ => Err(OtaError::UnexpectedResponse(response).into()),
+    }
+}
+
+#[async_trait]
+impl<P: Peripheral> OtaTransport for NordicDfu<P> {
+    fn max_chunk_len(&self) -> usize {
+        self.max_write_len
+    }
+
+    async fn begin(&mut self, image: &[u8]) -> Result<usize> {
+        self.request(&[OP_SET_PRN, 0, 0]).await?;
+        let had_init_packet = self.send_init_packet().await?;
+        let selected = self.select(OBJECT_DATA).await?;
+        if selected.max_size == 0 {
+            return Err(OtaError::UnexpectedResponse(vec![]).into());
+        }
+        self.max_object_size = selected.max_size;
+        self.image_len = image.len();
+        let mut resume_at = 0;
+        if had_init_packet && selected.offset > 0 && selected.offset <= image.len() {
+            let object_start = (selected.offset - 1) / selected.max_size * selected.max_size;
+            if crc32(&image[..selected.offset]) == selected.crc {
+                resume_at = selected.offset;
+                if resume_at % selected.max_size == 0 {
+                    // The last object was sent in full, but may not have been executed.
+                    self.execute(true).await?;
+                    self.object_end = resume_at;
+                } else {
+                    self.object_end = (object_start + selected.max_size).min(image.len());
+                }
+            } else {
+                // The object being sent when the update stopped is bad, but the ones before it
+                // were executed, so it only needs to be created again.
+                resume_at = object_start;
+                self.object_end = resume_at;
+            }
+        } else {
+            self.object_end = 0;
+        }
+        debug!("Resuming DFU at {} of {} bytes", resume_at, image.len());
+        self.crc = Crc32::new();
+        self.crc.update(&image[..resume_at]);
+        Ok(resume_at)
+    }
+
+    async fn write_chunk(&mut self, mut offset: usize, mut chunk: &[u8]) -> Result<()> {
+        while !chunk.is_empty() {
+            if offset == self.object_end {
+                let size = self.max_object_size.min(self.image_len - offset);
+                self.create(OBJECT_DATA, size).await?;
+                self.object_end = offset + size;
+            }
+            let len = chunk.len().min(self.object_end - offset);
+            self.write_packets(&chunk[..len]).await?;
+            self.crc.update(&chunk[..len]);
+            offset += len;
+            chunk = &chunk[len..];
+            if offset == self.object_end {
+                self.verify(offset, self.crc.value()).await?;
+                self.execute(false).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn finish(&mut self, _image: &[u8]) -> Result<()> {
+        // Each object is executed as soon as it has been sent, and executing the last one starts
+        // the new firmware.
+        Ok(())
+    }
+}
+
+impl<P: std::fmt::Debug> std::fmt::Debug for NordicDfu<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("NordicDfu")
+            .field("peripheral", &self.peripheral)
+            .field("max_write_len", &self.max_write_len)
+            .field("max_object_size", &self.max_object_size)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn responses() {
+        assert_eq!(
+            parse_response(OP_CALCULATE_CHECKSUM, vec![0x60, 0x03, 0x01, 0x10, 0, 0, 0]).unwrap(),
+            vec![0x10, 0, 0, 0]
+        );
+        let rejected = parse_response(OP_CREATE, vec![0x60, 0x01, 0x04]).unwrap_err();
+        assert!(matches!(
+            rejected,
+            Error::Other(e) if e.downcast_ref() == Some(&OtaError::Rejected { request: OP_CREATE, result: 0x04 })
+        ));
+        assert!(parse_response(OP_CREATE, vec![0x60, 0x04, 0x01]).is_err());
+    }
+}
@@ -0,0 +1,214 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Types for pairing with a peripheral from the application, with
+//! [`Peripheral::pair`](super::Peripheral::pair) and
+//! [`Peripheral::pair_with_agent`](super::Peripheral::pair_with_agent).
+//!
+//! How pairing is confirmed depends on what input and output each device has. A device with no
+//! display or keyboard pairs without a PIN, and the user only has to agree to it; others show a PIN
+//! to be entered on the other device, or show the same PIN on both to be compared. A
+//! [`PairingAgent`] is asked to handle whichever of these the platform chooses, for example by
+//! asking the user.
+//!
+//! Alternatively, a device can share [`OobData`] some other way than over Bluetooth, such as with
+//! an NFC tag or a QR code, for [`Peripheral::pair_with_oob`](super::Peripheral::pair_with_oob)
+//! to pair without a PIN.
+//!
+//! ```no_run
+//! use async_trait::async_trait;
+//! use btleplug::api::pairing::{PairingAgent, PairingRequest, PairingResponse};
+//! use btleplug::platform::PeripheralId;
+//!
+//! #[derive(Debug)]
+//! struct FixedPin(String);
+//!
+//! #[async_trait]
+//! impl PairingAgent for FixedPin {
+//!     async fn request(&self, _id: &PeripheralId, request: PairingRequest) -> PairingResponse {
+//!         match request {
+//!             PairingRequest::Confirm => PairingResponse::Accept,
+//!             PairingRequest::ProvidePin => PairingResponse::AcceptWithPin(self.0.clone()),
+//!             _ => PairingResponse::Reject,
+//!         }
+//!     }
+//! }
+//! ```
+
+use super::{AddressType, BDAddr};
+use crate::platform::PeripheralId;
+use async_trait::async_trait;
+use std::fmt::Debug;
+
+/// The AD types of the EIR data which carries out-of-band pairing data.
+const AD_LE_DEVICE_ADDRESS: u8 = 0x1b;
+const AD_LE_SC_CONFIRMATION_VALUE: u8 = 0x22;
+const AD_LE_SC_RANDOM_VALUE: u8 = 0x23;
+
+/// What the platform needs from the user to pair with a device.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PairingRequest {
+    /// Pairing needs no PIN, and only has to be agreed to ("Just Works").
+    Confirm,
+    /// Both devices show this PIN, and the user has to confirm that they match (numeric
+    /// comparison).
+    ConfirmPin(String),
+    /// The user has to enter this PIN on the device (passkey display).
+    DisplayPin(String),
+    /// The device shows a PIN, which the user has to enter here (passkey entry).
+    ProvidePin,
+}
+
+/// The answer to a [`PairingRequest`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PairingResponse {
+    /// Go ahead with pairing. For [`PairingRequest::DisplayPin`], this means the PIN has been shown.
+    Accept,
+    /// Go ahead with pairing using the given PIN, in answer to [`PairingRequest::ProvidePin`].
+    AcceptWithPin(String),
+    /// Don't pair.
+    Reject,
+}
+
+/// Handles the requests for user input which come up while pairing with a device.
+#[async_trait]
+pub trait PairingAgent: Send + Sync + Debug {
+    /// Answers a request to pair with the peripheral with the given ID. Pairing waits until this
+    /// returns, though the platform may give up on it after a while.
+    async fn request(&self, id: &PeripheralId, request: PairingRequest) -> PairingResponse;
+}
+
+/// LE Secure Connections pairing data which a device shares out of band, i.e. some other way than
+/// over Bluetooth, such as with an NFC tag or a QR code. Pairing with it needs no PIN, and is
+/// protected against a man in the middle as long as the way it was shared is.
+///
+/// The values are in the byte order they are sent in, least significant byte first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct OobData {
+    /// The device's address and its type, if the data includes it.
+    pub address: Option<(BDAddr, AddressType)>,
+    /// The LE Secure Connections Confirmation Value.
+    pub confirm: [u8; 16],
+    /// The LE Secure Connections Random Value.
+    pub random: [u8; 16],
+}
+
+impl OobData {
+    pub fn new(confirm: [u8; 16], random: [u8; 16]) -> Self {
+        OobData {
+            address: None,
+            confirm,
+            random,
+        }
+    }
+
+    /// Parses the data from a sequence of AD structures, as in NFC Forum and Bluetooth SIG LE
+    /// out-of-band records. Returns `None` unless both the confirmation and random values are
+    /// there; other structures, such as the device's name, are skipped.
+    pub fn from_ad_structures(data: &[u8]) -> Option<Self> {
+        let mut address = None;
+        let mut confirm = None;
+        let mut random = None;
+        let mut rest = data;
+        while let [length, tail @ ..] = rest {
+            let length = usize::from(*length);
+            if length == 0 || tail.len() < length {
+                break;
+            }
+            let (structure, tail) = tail.split_at(length);
+            match (structure[0], &structure[1..]) {
+                (AD_LE_DEVICE_ADDRESS, [value @ .., flags]) if value.len() == 6 => {
+                    let mut bytes: [u8; 6] = value.try_into().unwrap();
+                    bytes.reverse();
+                    let address_type = if flags & 1 == 0 {
+                        AddressType::Public
+                    } else {
+                        AddressType::Random
+                    };
+                    address = Some((BDAddr::from(bytes), address_type));
+                }
+                (AD_LE_SC_CONFIRMATION_VALUE, value) => confirm = value.try_into().ok(),
+                (AD_LE_SC_RANDOM_VALUE, value) => random = value.try_into().ok(),
+                _ => {}
+            }
+            rest = tail;
+        }
+        Some(OobData {
+            address,
+            confirm: confirm?,
+            random: random?,
+        })
+    }
+}
+
+/// The agent [`Peripheral::pair`](super::Peripheral::pair) uses, which agrees to pairing which
+/// needs no PIN and rejects anything else.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConfirmOnly;
+
+#[async_trait]
+impl PairingAgent for ConfirmOnly {
+    async fn request(&self, _id: &PeripheralId, request: PairingRequest) -> PairingResponse {
+        match request {
+            PairingRequest::Confirm => PairingResponse::Accept,
+            _ => PairingResponse::Reject,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oob_data_from_ad_structures() {
+        let confirm: [u8; 16] = std::array::from_fn(|i| i as u8);
+        let random: [u8; 16] = std::array::from_fn(|i| 0x80 | i as u8);
+        let mut data = vec![8, 0x1b, 6, 5, 4, 3, 2, 1, 1];
+        // A name, which is skipped.
+        data.extend_from_slice(&[3, 0x09, b'h', b'i']);
+        data.extend_from_slice(&[17, 0x22]);
+        data.extend_from_slice(&confirm);
+        data.extend_from_slice(&[17, 0x23]);
+        data.extend_from_slice(&random);
+
+        let oob = OobData::from_ad_structures(&data).unwrap();
+        assert_eq!(
+            oob.address,
+            Some((BDAddr::from([1, 2, 3, 4, 5, 6]), AddressType::Random))
+        );
+        assert_eq!(oob.confirm, confirm);
+        assert_eq!(oob.random, random);
+
+        // Both values are needed.
+        assert_eq!(OobData::from_ad_structures(&data[..data.len() - 18]), None);
+        assert_eq!(
+            OobData::from_ad_structures(&data[13..]),
+            Some(OobData::new(confirm, random))
+        );
+    }
+
+    #[tokio::test]
+    async fn confirm_only() {
+        let id = PeripheralId::synthetic("hci0", BDAddr::from([1, 2, 3, 4, 5, 6]));
+        let answer = |request| ConfirmOnly.request(&id, request);
+        assert_eq!(
+            answer(PairingRequest::Confirm).await,
+            PairingResponse::Accept
+        );
+        assert_eq!(
+            answer(PairingRequest::ConfirmPin("123456".to_string())).await,
+            PairingResponse::Reject
+        );
+        assert_eq!(
+            answer(PairingRequest::ProvidePin).await,
+            PairingResponse::Reject
+        );
+    }
+}
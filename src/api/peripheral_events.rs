@@ -0,0 +1,162 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! The events of an adapter's peripherals, which backends send alongside the adapter's
+//! [`CentralEvent`]s for [`Peripheral::events`](super::Peripheral::events) and
+//! [`Central::discovery_events`](super::Central::discovery_events). As every central event comes
+//! through here, this is also where they are sent to
+//! [`Central::events_filtered`](super::Central::events_filtered).
+
+use super::event_filter::FilteredSubscribers;
+use super::{
+    CentralEvent, DisconnectReason, DiscoveryEvent, EventFilter, PeripheralEvent,
+    PeripheralProperties,
+};
+use crate::platform::PeripheralId;
+use futures::stream::{Stream, StreamExt};
+use log::trace;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+
+// Only used by some backends, so turn off deadcode so we don't get warnings on the other platforms.
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub(crate) struct PeripheralEventSender {
+    // The events of all the adapter's peripherals go through one channel, so a peripheral's events
+    // arrive in the order they were sent.
+    channel: broadcast::Sender<(PeripheralId, PeripheralEvent)>,
+    discoveries: broadcast::Sender<DiscoveryEvent>,
+    /// Why each peripheral which is disconnecting is, if the backend knows.
+    disconnect_reasons: Arc<Mutex<HashMap<PeripheralId, DisconnectReason>>>,
+    filtered: Arc<FilteredSubscribers>,
+}
+
+impl Default for PeripheralEventSender {
+    fn default() -> Self {
+        let (channel, _) = broadcast::channel(16);
+        let (discoveries, _) = broadcast::channel(16);
+        PeripheralEventSender {
+            channel,
+            discoveries,
+            disconnect_reasons: Default::default(),
+            filtered: Default::default(),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl PeripheralEventSender {
+    pub(crate) fn send(&self, id: &PeripheralId, event: PeripheralEvent) {
+        if let Err(lost) = self.channel.send((id.clone(), event)) {
+            trace!(
+                "Lost peripheral event, while nothing subscribed: {:?}",
+                lost
+            );
+        }
+    }
+
+    /// Sends the central event to the filtered subscribers which want it, and the peripheral event
+    /// which it stands for, if there is one. This is called for every central event the adapter
+    /// emits.
+    pub(crate) fn send_central(&self, event: &CentralEvent) {
+        self.filtered.send(event);
+        let (id, event) = match event {
+            CentralEvent::DeviceConnected(id) => {
+                self.disconnect_reasons.lock().unwrap().remove(id);
+                (id, PeripheralEvent::Connected)
+            }
+            CentralEvent::DeviceDisconnected(id) => {
+                let reason = self
+                    .disconnect_reasons
+                    .lock()
+                    .unwrap()
+                    .remove(id)
+                    .unwrap_or(DisconnectReason::Unknown);
+                (id, PeripheralEvent::Disconnected(reason))
+            }
+            CentralEvent::DeviceDiscovered(id) | CentralEvent::DeviceUpdated(id) => {
+                (id, PeripheralEvent::PropertiesUpdated)
+            }
+            CentralEvent::ServicesChanged(id) => (id, PeripheralEvent::ServicesResolved),
+            CentralEvent::MtuChanged { id, mtu } => (id, PeripheralEvent::MtuChanged(*mtu)),
+            CentralEvent::DirectedAdvertisement(id) => (id, PeripheralEvent::DirectedAdvertisement),
+            _ => return,
+        };
+        self.send(id, event);
+    }
+
+    /// Gives the reason for the next [`CentralEvent::DeviceDisconnected`] of the peripheral, which
+    /// is otherwise [`DisconnectReason::Unknown`].
+    pub(crate) fn set_disconnect_reason(&self, id: &PeripheralId, reason: DisconnectReason) {
+        self.disconnect_reasons
+            .lock()
+            .unwrap()
+            .insert(id.clone(), reason);
+    }
+
+    /// Sends the properties with which a peripheral was just discovered, if `new`, or updated. The
+    /// backend emits the matching [`CentralEvent`] itself.
+    pub(crate) fn send_discovery(
+        &self,
+        id: &PeripheralId,
+        properties: PeripheralProperties,
+        new: bool,
+    ) {
+        let _ = self.discoveries.send(DiscoveryEvent {
+            id: id.clone(),
+            properties,
+            new,
+        });
+    }
+
+    /// Returns whether anything is listening for discovery events, so that the properties only
+    /// need copying for them if so.
+    // Only used by some backends, so turn off deadcode so we don't get warnings on the other platforms.
+    #[allow(dead_code)]
+    pub(crate) fn has_discovery_subscribers(&self) -> bool {
+        self.discoveries.receiver_count() > 0
+    }
+
+    /// Returns whether anything is listening for filtered central events.
+    #[allow(dead_code)]
+    pub(crate) fn has_filtered_subscribers(&self) -> bool {
+        !self.filtered.is_empty()
+    }
+
+    pub(crate) fn subscribe_discoveries(
+        &self,
+    ) -> Pin<Box<dyn Stream<Item = DiscoveryEvent> + Send>> {
+        let receiver = self.discoveries.subscribe();
+        Box::pin(BroadcastStream::new(receiver).filter_map(|event| async move { event.ok() }))
+    }
+
+    /// Returns a stream of the central events which match the filter's kinds and peripherals.
+    pub(crate) fn subscribe_filtered(
+        &self,
+        filter: &EventFilter,
+    ) -> Pin<Box<dyn Stream<Item = CentralEvent> + Send>> {
+        self.filtered.subscribe(filter)
+    }
+
+    /// Returns a stream of the events of the peripheral with the given ID.
+    pub(crate) fn subscribe(
+        &self,
+        id: PeripheralId,
+    ) -> Pin<Box<dyn Stream<Item = PeripheralEvent> + Send>> {
+        let receiver = self.channel.subscribe();
+        Box::pin(BroadcastStream::new(receiver).filter_map(move |event| {
+            let event = match event {
+                Ok((event_id, event)) if event_id == id => Some(event),
+                _ => None,
+            };
+            async move { event }
+        }))
+    }
+}
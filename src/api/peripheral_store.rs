@@ -0,0 +1,185 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! The peripherals an adapter has found, for backends which keep track of them themselves.
+//!
+//! A busy site can have thousands of devices in range, each advertising several times a second,
+//! so the store is built to stay fast as it grows:
+//!
+//! - Looking a peripheral up by its ID or by its address, adding one and removing one take
+//!   constant time on average. They only lock the shard of the map the peripheral is in, so the
+//!   advertisements of different devices don't wait on each other, or on a caller listing the
+//!   peripherals.
+//! - Listing the peripherals and removing those which have expired take time linear in how many
+//!   there are. Backends check for expired peripherals at most once a second, however many
+//!   devices they find, as limited by
+//!   [`PeripheralExpiry::cutoff`](super::expiry::PeripheralExpiry::cutoff).
+//! - Peripherals are handles to state they share, so looking one up or listing them copies none
+//!   of their properties. The hci backend keeps each peripheral's properties in an
+//!   [`Arc`](std::sync::Arc) which is only copied when it's changed while a caller is cloning it,
+//!   so reading them holds up advertisements only for as long as it takes to clone the `Arc`.
+//!
+//! Addresses are only indexed when they're known: CoreBluetooth doesn't expose them.
+
+use super::{BDAddr, Peripheral};
+use crate::platform::PeripheralId;
+use dashmap::{mapref::entry::Entry, mapref::one::RefMut, DashMap};
+
+#[derive(Debug)]
+pub(crate) struct PeripheralStore<P> {
+    peripherals: DashMap<PeripheralId, P>,
+    /// The ID of the peripheral with each known address.
+    addresses: DashMap<BDAddr, PeripheralId>,
+}
+
+impl<P> Default for PeripheralStore<P> {
+    fn default() -> Self {
+        PeripheralStore {
+            peripherals: DashMap::new(),
+            addresses: DashMap::new(),
+        }
+    }
+}
+
+// Only used by some backends, so turn off deadcode so we don't get warnings on the other platforms.
+#[allow(dead_code)]
+impl<P: Peripheral> PeripheralStore<P> {
+    pub(crate) fn get(&self, id: &PeripheralId) -> Option<P> {
+        self.peripherals.get(id).map(|entry| entry.value().clone())
+    }
+
+    pub(crate) fn get_mut(&self, id: &PeripheralId) -> Option<RefMut<'_, PeripheralId, P>> {
+        self.peripherals.get_mut(id)
+    }
+
+    pub(crate) fn contains(&self, id: &PeripheralId) -> bool {
+        self.peripherals.contains_key(id)
+    }
+
+    pub(crate) fn by_address(&self, address: BDAddr) -> Option<P> {
+        // Let go of the index's shard before looking in the map.
+        let id = self.addresses.get(&address)?.value().clone();
+        self.get(&id)
+    }
+
+    /// Adds the peripheral, and returns the one with the same ID which it replaced, if any.
+    pub(crate) fn insert(&self, peripheral: P) -> Option<P> {
+        self.index(&peripheral);
+        self.peripherals.insert(peripheral.id(), peripheral)
+    }
+
+    /// Returns the peripheral with the given ID, adding the one `create` makes if there's none,
+    /// and whether it was added. Finding the same new device twice at once only adds it once.
+    pub(crate) fn get_or_insert_with(
+        &self,
+        id: PeripheralId,
+        create: impl FnOnce() -> P,
+    ) -> (P, bool) {
+        match self.peripherals.entry(id) {
+            Entry::Occupied(entry) => (entry.get().clone(), false),
+            Entry::Vacant(entry) => {
+                let peripheral = create();
+                self.index(&peripheral);
+                entry.insert(peripheral.clone());
+                (peripheral, true)
+            }
+        }
+    }
+
+    fn index(&self, peripheral: &P) {
+        let address = peripheral.address();
+        if address != BDAddr::default() {
+            self.addresses.insert(address, peripheral.id());
+        }
+    }
+
+    pub(crate) fn remove(&self, id: &PeripheralId) -> Option<P> {
+        let (_, peripheral) = self.peripherals.remove(id)?;
+        self.unindex(id, &peripheral);
+        Some(peripheral)
+    }
+
+    fn unindex(&self, id: &PeripheralId, peripheral: &P) {
+        self.addresses
+            .remove_if(&peripheral.address(), |_, indexed| indexed == id);
+    }
+
+    /// Removes the peripherals for which `expired` returns true, and returns them. A peripheral
+    /// is checked again just before it's removed, in case it was seen in the meantime.
+    pub(crate) fn remove_expired(&self, expired: impl Fn(&P) -> bool) -> Vec<P> {
+        let ids: Vec<_> = self
+            .peripherals
+            .iter()
+            .filter(|entry| expired(entry.value()))
+            .map(|entry| entry.key().clone())
+            .collect();
+        ids.iter()
+            .filter_map(|id| {
+                let (_, peripheral) = self
+                    .peripherals
+                    .remove_if(id, |_, peripheral| expired(peripheral))?;
+                self.unindex(id, &peripheral);
+                Some(peripheral)
+            })
+            .collect()
+    }
+
+    /// Returns all the peripherals, in no particular order.
+    pub(crate) fn peripherals(&self) -> Vec<P> {
+        self.peripherals
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect()
+    }
+}
+
+// Peripherals can only be made up with the mock backend.
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::api::{Central, PeripheralProperties, ScanFilter};
+    use crate::mock::Manager;
+
+    #[tokio::test]
+    async fn store() -> crate::Result<()> {
+        let manager = Manager::new().await?;
+        let adapter = manager.add_adapter();
+        adapter.start_scan(ScanFilter::default()).await?;
+        let store = PeripheralStore::default();
+        let mut devices = Vec::new();
+        for i in 1..=4u8 {
+            let device = adapter
+                .advertise(PeripheralProperties {
+                    address: [i, 0, 0, 0, 0, 0].into(),
+                    ..Default::default()
+                })
+                .unwrap();
+            assert!(store.insert(device.clone()).is_none());
+            devices.push(device);
+        }
+
+        let address = devices[1].address();
+        assert_eq!(store.by_address(address).unwrap().id(), devices[1].id());
+        let (found, added) = store.get_or_insert_with(devices[1].id(), || unreachable!());
+        assert_eq!((found.id(), added), (devices[1].id(), false));
+
+        assert!(store.remove(&devices[1].id()).is_some());
+        assert!(store.by_address(address).is_none());
+        assert!(!store.contains(&devices[1].id()));
+
+        let removed = store.remove_expired(|device| device.address().into_inner()[0] > 2);
+        assert_eq!(removed.len(), 2);
+        let left: Vec<_> = store
+            .peripherals()
+            .iter()
+            .map(|device| device.id())
+            .collect();
+        assert_eq!(left, vec![devices[0].id()]);
+        assert!(store.get(&devices[0].id()).is_some());
+        Ok(())
+    }
+}
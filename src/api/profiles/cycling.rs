@@ -0,0 +1,349 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! The Cycling Speed and Cadence and Cycling Power profiles, used by bike sensors and power
+//! meters.
+//!
+//! Both report cumulative revolution counts with the time of the last revolution, rather than
+//! speed or cadence directly. These are turned into a rate by comparing two measurements with
+//! [`WheelRevolutions::rpm_since`] or [`CrankRevolutions::rpm_since`]; a wheel's speed is its rate
+//! times its circumference.
+
+use super::{measurements, Fields};
+use crate::api::{bleuuid::uuid_from_u16, Peripheral};
+use crate::Result;
+use async_trait::async_trait;
+use futures::stream::Stream;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use serde_cr as serde;
+use std::pin::Pin;
+use uuid::Uuid;
+
+/// The UUID of the Cycling Speed and Cadence service.
+pub const CYCLING_SPEED_AND_CADENCE_SERVICE_UUID: Uuid = uuid_from_u16(0x1816);
+
+/// The UUID of the CSC Measurement characteristic, which notifies [`CscMeasurement`]s.
+pub const CSC_MEASUREMENT_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2A5B);
+
+/// The UUID of the Cycling Power service.
+pub const CYCLING_POWER_SERVICE_UUID: Uuid = uuid_from_u16(0x1818);
+
+/// The UUID of the Cycling Power Measurement characteristic, which notifies
+/// [`CyclingPowerMeasurement`]s.
+pub const CYCLING_POWER_MEASUREMENT_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2A63);
+
+/// Returns the revolutions per minute between two readings of a revolution count and the time of
+/// the last revolution, both of which wrap around.
+fn rpm(revolutions: u32, ticks: u16, ticks_per_second: u16) -> Option<f64> {
+    if ticks == 0 {
+        return None;
+    }
+    Some(f64::from(revolutions) * 60.0 * f64::from(ticks_per_second) / f64::from(ticks))
+}
+
+/// How many times a wheel has turned.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WheelRevolutions {
+    /// The number of revolutions, which wraps around.
+    pub cumulative: u32,
+    /// When the last revolution was, in units of 1/`ticks_per_second` seconds, which wraps around.
+    pub last_event_time: u16,
+    /// 1024 for the Cycling Speed and Cadence profile, and 2048 for the Cycling Power profile.
+    pub ticks_per_second: u16,
+}
+
+impl WheelRevolutions {
+    /// The wheel's revolutions per minute between an earlier measurement and this one, or `None`
+    /// if it hasn't turned since.
+    pub fn rpm_since(&self, earlier: &WheelRevolutions) -> Option<f64> {
+        rpm(
+            self.cumulative.wrapping_sub(earlier.cumulative),
+            self.last_event_time.wrapping_sub(earlier.last_event_time),
+            self.ticks_per_second,
+        )
+    }
+}
+
+/// How many times the cranks have turned.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrankRevolutions {
+    /// The number of revolutions, which wraps around.
+    pub cumulative: u16,
+    /// When the last revolution was, in units of 1/1024 seconds, which wraps around.
+    pub last_event_time: u16,
+}
+
+impl CrankRevolutions {
+    /// The cadence in revolutions per minute between an earlier measurement and this one, or
+    /// `None` if the cranks haven't turned since.
+    pub fn rpm_since(&self, earlier: &CrankRevolutions) -> Option<f64> {
+        rpm(
+            u32::from(self.cumulative.wrapping_sub(earlier.cumulative)),
+            self.last_event_time.wrapping_sub(earlier.last_event_time),
+            1024,
+        )
+    }
+}
+
+/// A value of the CSC Measurement characteristic.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CscMeasurement {
+    pub wheel: Option<WheelRevolutions>,
+    pub crank: Option<CrankRevolutions>,
+}
+
+impl CscMeasurement {
+    /// Parses a value of the CSC Measurement characteristic, or returns `None` if it's too short.
+    pub fn from_bytes(value: &[u8]) -> Option<CscMeasurement> {
+        let mut fields = Fields(value);
+        let flags = fields.u8()?;
+        let wheel = if flags & 0x01 != 0 {
+            Some(WheelRevolutions {
+                cumulative: fields.u32()?,
+                last_event_time: fields.u16()?,
+                ticks_per_second: 1024,
+            })
+        } else {
+            None
+        };
+        let crank = if flags & 0x02 != 0 {
+            Some(CrankRevolutions {
+                cumulative: fields.u16()?,
+                last_event_time: fields.u16()?,
+            })
+        } else {
+            None
+        };
+        Some(CscMeasurement { wheel, crank })
+    }
+}
+
+/// A value of the Cycling Power Measurement characteristic. Which of the optional fields are
+/// present depends on the power meter.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CyclingPowerMeasurement {
+    /// The power, in watts.
+    pub instantaneous_power: i16,
+    /// The percentage of the power from one pedal, which is the left one if
+    /// `pedal_power_balance_left` is true.
+    pub pedal_power_balance: Option<f32>,
+    pub pedal_power_balance_left: bool,
+    /// The torque accumulated since it was last reset, in newton metres.
+    pub accumulated_torque: Option<f32>,
+    pub wheel: Option<WheelRevolutions>,
+    pub crank: Option<CrankRevolutions>,
+    /// The largest and smallest force on the cranks during the last revolution, in newtons.
+    pub extreme_force_magnitudes: Option<(i16, i16)>,
+    /// The largest and smallest torque on the cranks during the last revolution, in newton metres.
+    pub extreme_torque_magnitudes: Option<(f32, f32)>,
+    /// The crank angles at which the largest and smallest force or torque was measured, in
+    /// degrees.
+    pub extreme_angles: Option<(u16, u16)>,
+    /// The crank angle, in degrees, at which the power first became positive in the last
+    /// revolution.
+    pub top_dead_spot_angle: Option<u16>,
+    /// The crank angle, in degrees, at which the power stopped being positive in the last
+    /// revolution.
+    pub bottom_dead_spot_angle: Option<u16>,
+    /// The energy accumulated since it was last reset, in kilojoules.
+    pub accumulated_energy: Option<u16>,
+}
+
+impl CyclingPowerMeasurement {
+    /// Parses a value of the Cycling Power Measurement characteristic, or returns `None` if it's
+    /// too short.
+    pub fn from_bytes(value: &[u8]) -> Option<CyclingPowerMeasurement> {
+        let mut fields = Fields(value);
+        let flags = fields.u16()?;
+        let present = |bit: u16| flags & (1 << bit) != 0;
+        let instantaneous_power = fields.i16()?;
+        let pedal_power_balance = if present(0) {
+            Some(f32::from(fields.u8()?) / 2.0)
+        } else {
+            None
+        };
+        let accumulated_torque = if present(2) {
+            Some(f32::from(fields.u16()?) / 32.0)
+        } else {
+            None
+        };
+        let wheel = if present(4) {
+            Some(WheelRevolutions {
+                cumulative: fields.u32()?,
+                last_event_time: fields.u16()?,
+                ticks_per_second: 2048,
+            })
+        } else {
+            None
+        };
+        let crank = if present(5) {
+            Some(CrankRevolutions {
+                cumulative: fields.u16()?,
+                last_event_time: fields.u16()?,
+            })
+        } else {
+            None
+        };
+        let extreme_force_magnitudes = if present(6) {
+            Some((fields.i16()?, fields.i16()?))
+        } else {
+            None
+        };
+        let extreme_torque_magnitudes = if present(7) {
+            Some((
+                f32::from(fields.i16()?) / 32.0,
+                f32::from(fields.i16()?) / 32.0,
+            ))
+        } else {
+            None
+        };
+        let extreme_angles = if present(8) {
+            // Two 12-bit angles packed into three bytes, the largest first.
+            let angles = fields.u24()?;
+            Some(((angles & 0xFFF) as u16, (angles >> 12) as u16))
+        } else {
+            None
+        };
+        let top_dead_spot_angle = if present(9) {
+            Some(fields.u16()?)
+        } else {
+            None
+        };
+        let bottom_dead_spot_angle = if present(10) {
+            Some(fields.u16()?)
+        } else {
+            None
+        };
+        let accumulated_energy = if present(11) {
+            Some(fields.u16()?)
+        } else {
+            None
+        };
+        Some(CyclingPowerMeasurement {
+            instantaneous_power,
+            pedal_power_balance,
+            pedal_power_balance_left: present(1),
+            accumulated_torque,
+            wheel,
+            crank,
+            extreme_force_magnitudes,
+            extreme_torque_magnitudes,
+            extreme_angles,
+            top_dead_spot_angle,
+            bottom_dead_spot_angle,
+            accumulated_energy,
+        })
+    }
+}
+
+/// Streams measurements from a connected cycling sensor or power meter.
+#[async_trait]
+pub trait Cycling: Peripheral {
+    /// Subscribes to the CSC Measurement characteristic, discovering the Cycling Speed and Cadence
+    /// service if it hasn't been yet, and returns a stream of its measurements. Dropping the
+    /// stream doesn't unsubscribe.
+    async fn csc_measurements(&self) -> Result<Pin<Box<dyn Stream<Item = CscMeasurement> + Send>>> {
+        measurements(
+            self,
+            CYCLING_SPEED_AND_CADENCE_SERVICE_UUID,
+            CSC_MEASUREMENT_CHARACTERISTIC_UUID,
+            CscMeasurement::from_bytes,
+        )
+        .await
+    }
+
+    /// Subscribes to the Cycling Power Measurement characteristic, discovering the Cycling Power
+    /// service if it hasn't been yet, and returns a stream of its measurements. Dropping the
+    /// stream doesn't unsubscribe.
+    async fn cycling_power_measurements(
+        &self,
+    ) -> Result<Pin<Box<dyn Stream<Item = CyclingPowerMeasurement> + Send>>> {
+        measurements(
+            self,
+            CYCLING_POWER_SERVICE_UUID,
+            CYCLING_POWER_MEASUREMENT_CHARACTERISTIC_UUID,
+            CyclingPowerMeasurement::from_bytes,
+        )
+        .await
+    }
+}
+
+impl<P: Peripheral> Cycling for P {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csc() {
+        let earlier = CscMeasurement::from_bytes(&[
+            0x03, 0x10, 0x00, 0x00, 0x00, 0x00, 0x04, 0xff, 0xff, 0x00, 0xfc,
+        ])
+        .unwrap();
+        assert_eq!(
+            earlier.wheel,
+            Some(WheelRevolutions {
+                cumulative: 16,
+                last_event_time: 1024,
+                ticks_per_second: 1024,
+            })
+        );
+        // Both counts wrap around between the measurements.
+        let later = CscMeasurement::from_bytes(&[0x02, 0x01, 0x00, 0x00, 0x02]).unwrap();
+        assert_eq!(later.wheel, None);
+        let cadence = later.crank.unwrap().rpm_since(&earlier.crank.unwrap());
+        assert_eq!(cadence, Some(80.0));
+        assert_eq!(CscMeasurement::from_bytes(&[0x01, 0x10]), None);
+    }
+
+    #[test]
+    fn cycling_power() {
+        let measurement = CyclingPowerMeasurement::from_bytes(&[
+            0x23, 0x08, 0xfa, 0x00, 0x64, 0x0a, 0x00, 0x03, 0x00, 0x00, 0x20,
+        ])
+        .unwrap();
+        assert_eq!(measurement.instantaneous_power, 250);
+        assert_eq!(measurement.pedal_power_balance, Some(50.0));
+        assert!(measurement.pedal_power_balance_left);
+        assert_eq!(
+            measurement.crank,
+            Some(CrankRevolutions {
+                cumulative: 10,
+                last_event_time: 3,
+            })
+        );
+        assert_eq!(measurement.wheel, None);
+        assert_eq!(measurement.accumulated_energy, Some(0x2000));
+        assert_eq!(measurement.accumulated_torque, None);
+        assert_eq!(
+            CyclingPowerMeasurement::from_bytes(&[0x00, 0x00, 0xfa]),
+            None
+        );
+    }
+}
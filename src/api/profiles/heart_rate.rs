@@ -0,0 +1,129 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! The Heart Rate profile, used by chest straps and other heart rate monitors.
+
+use super::{measurements, Fields};
+use crate::api::{bleuuid::uuid_from_u16, Peripheral};
+use crate::Result;
+use async_trait::async_trait;
+use futures::stream::Stream;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use serde_cr as serde;
+use std::{pin::Pin, time::Duration};
+use uuid::Uuid;
+
+/// The UUID of the Heart Rate service.
+pub const HEART_RATE_SERVICE_UUID: Uuid = uuid_from_u16(0x180D);
+
+/// The UUID of the Heart Rate Measurement characteristic, which notifies [`HeartRateMeasurement`]s.
+pub const HEART_RATE_MEASUREMENT_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2A37);
+
+/// A value of the Heart Rate Measurement characteristic.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeartRateMeasurement {
+    /// The heart rate, in beats per minute.
+    pub bpm: u16,
+    /// Whether the sensor is in contact with the skin, if it can tell.
+    pub sensor_contact: Option<bool>,
+    /// The energy expended since it was last reset, in kilojoules.
+    pub energy_expended: Option<u16>,
+    /// The times between successive beats since the last measurement, oldest first.
+    pub rr_intervals: Vec<Duration>,
+}
+
+impl HeartRateMeasurement {
+    /// Parses a value of the Heart Rate Measurement characteristic, or returns `None` if it's too
+    /// short.
+    pub fn from_bytes(value: &[u8]) -> Option<HeartRateMeasurement> {
+        let mut fields = Fields(value);
+        let flags = fields.u8()?;
+        let bpm = if flags & 0x01 != 0 {
+            fields.u16()?
+        } else {
+            u16::from(fields.u8()?)
+        };
+        let sensor_contact = (flags & 0x04 != 0).then_some(flags & 0x02 != 0);
+        let energy_expended = if flags & 0x08 != 0 {
+            Some(fields.u16()?)
+        } else {
+            None
+        };
+        let mut rr_intervals = Vec::new();
+        if flags & 0x10 != 0 {
+            while fields.remaining() >= 2 {
+                // In units of 1/1024 seconds.
+                let interval = u64::from(fields.u16()?);
+                rr_intervals.push(Duration::from_micros(interval * 1_000_000 / 1024));
+            }
+        }
+        Some(HeartRateMeasurement {
+            bpm,
+            sensor_contact,
+            energy_expended,
+            rr_intervals,
+        })
+    }
+}
+
+/// Streams heart rate measurements from a connected peripheral.
+#[async_trait]
+pub trait HeartRate: Peripheral {
+    /// Subscribes to the Heart Rate Measurement characteristic, discovering the Heart Rate service
+    /// if it hasn't been yet, and returns a stream of its measurements. Dropping the stream doesn't
+    /// unsubscribe.
+    async fn heart_rate_measurements(
+        &self,
+    ) -> Result<Pin<Box<dyn Stream<Item = HeartRateMeasurement> + Send>>> {
+        measurements(
+            self,
+            HEART_RATE_SERVICE_UUID,
+            HEART_RATE_MEASUREMENT_CHARACTERISTIC_UUID,
+            HeartRateMeasurement::from_bytes,
+        )
+        .await
+    }
+}
+
+impl<P: Peripheral> HeartRate for P {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            HeartRateMeasurement::from_bytes(&[0x06, 72]),
+            Some(HeartRateMeasurement {
+                bpm: 72,
+                sensor_contact: Some(true),
+                energy_expended: None,
+                rr_intervals: vec![],
+            })
+        );
+        assert_eq!(
+            HeartRateMeasurement::from_bytes(&[
+                0x19, 0x2c, 0x01, 0x10, 0x00, 0x00, 0x04, 0x00, 0x02
+            ]),
+            Some(HeartRateMeasurement {
+                bpm: 300,
+                sensor_contact: None,
+                energy_expended: Some(16),
+                rr_intervals: vec![Duration::from_secs(1), Duration::from_millis(500)],
+            })
+        );
+        assert_eq!(HeartRateMeasurement::from_bytes(&[0x01, 72]), None);
+    }
+}
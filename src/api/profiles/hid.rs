@@ -0,0 +1,594 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! A client for the HID over GATT profile (HOGP), used by Bluetooth LE keyboards, mice, game
+//! controllers and remotes.
+//!
+//! A [`HidDevice`] discovers the HID service of a connected peripheral, reads its report map,
+//! which describes the reports in the format of the USB HID specification, and works out which
+//! characteristic carries which report from their Report Reference descriptors.
+//!
+//! Most operating systems handle HID devices themselves: CoreBluetooth and WinRT don't let
+//! applications use the HID service, and BlueZ's input plugin claims it unless it's disabled. This
+//! is mostly useful on Android and with the `hci` backend.
+//!
+//! ```no_run
+//! use btleplug::api::profiles::hid::HidDevice;
+//! use futures::StreamExt;
+//! # use btleplug::api::Peripheral;
+//! # async fn watch(peripheral: impl Peripheral + 'static) -> btleplug::Result<()> {
+//! peripheral.connect().await?;
+//! let hid = HidDevice::new(peripheral).await?;
+//! println!("Report map: {:02x?}", hid.report_map());
+//! let mut reports = hid.input_reports().await?;
+//! while let Some(report) = reports.next().await {
+//!     println!("Report {}: {:02x?}", report.report_id, report.data);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use super::Fields;
+use crate::api::{
+    bleuuid::uuid_from_u16, standard::service_characteristics, CharPropFlags, Characteristic,
+    Peripheral, WriteType,
+};
+use crate::{Error, Result};
+use futures::stream::{Stream, StreamExt};
+use log::trace;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use serde_cr as serde;
+use std::pin::Pin;
+use uuid::Uuid;
+
+/// The UUID of the Human Interface Device service.
+pub const HID_SERVICE_UUID: Uuid = uuid_from_u16(0x1812);
+
+pub const HID_INFORMATION_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2A4A);
+pub const REPORT_MAP_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2A4B);
+pub const HID_CONTROL_POINT_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2A4C);
+pub const REPORT_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2A4D);
+pub const PROTOCOL_MODE_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2A4E);
+pub const BOOT_KEYBOARD_INPUT_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2A22);
+pub const BOOT_KEYBOARD_OUTPUT_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2A32);
+pub const BOOT_MOUSE_INPUT_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2A33);
+
+/// The UUID of the Report Reference descriptor, which gives the ID and type of a report.
+pub const REPORT_REFERENCE_DESCRIPTOR_UUID: Uuid = uuid_from_u16(0x2908);
+
+/// Which way a report goes.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReportType {
+    /// Sent by the device, e.g. key presses.
+    Input,
+    /// Sent to the device, e.g. to set keyboard LEDs.
+    Output,
+    /// Read from and written to the device, e.g. for settings.
+    Feature,
+}
+
+/// A report of a [`HidDevice`], and the characteristic which carries it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// The report ID, as used in the report map. This is 0 if the device only has one report of
+    /// this type and doesn't use IDs.
+    pub id: u8,
+    pub report_type: ReportType,
+    pub characteristic: Characteristic,
+}
+
+/// A report received from a device's input report characteristic.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputReport {
+    pub report_id: u8,
+    /// The report, without its ID.
+    pub data: Vec<u8>,
+}
+
+/// Whether a device sends the reports described by its report map, or the fixed boot protocol
+/// reports for keyboards and mice.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProtocolMode {
+    Boot,
+    Report,
+}
+
+/// The contents of the HID Information characteristic.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HidInformation {
+    /// The version of the HID specification the device implements, in binary-coded decimal, e.g.
+    /// `0x0111` for 1.11.
+    pub hid_version: u16,
+    /// The country the hardware is localised for, or 0 if it isn't.
+    pub country_code: u8,
+    /// Whether the device can wake the host up.
+    pub remote_wake: bool,
+    /// Whether the device advertises when it isn't connected, so it can be reconnected to.
+    pub normally_connectable: bool,
+}
+
+impl HidInformation {
+    /// Parses a value of the HID Information characteristic, or returns `None` if it's too short.
+    pub fn from_bytes(value: &[u8]) -> Option<HidInformation> {
+        let mut fields = Fields(value);
+        let hid_version = fields.u16()?;
+        let country_code = fields.u8()?;
+        let flags = fields.u8()?;
+        Some(HidInformation {
+            hid_version,
+            country_code,
+            remote_wake: flags & 0x01 != 0,
+            normally_connectable: flags & 0x02 != 0,
+        })
+    }
+}
+
+/// A boot protocol keyboard input report.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BootKeyboardReport {
+    /// The modifier keys which are down, one bit each from left control (bit 0) to right GUI
+    /// (bit 7).
+    pub modifiers: u8,
+    /// The usage IDs of up to six other keys which are down, with 0 for no key.
+    pub keys: [u8; 6],
+}
+
+impl BootKeyboardReport {
+    /// Parses a boot keyboard input report, or returns `None` if it's too short.
+    pub fn from_bytes(value: &[u8]) -> Option<BootKeyboardReport> {
+        let mut fields = Fields(value);
+        let modifiers = fields.u8()?;
+        let _reserved = fields.u8()?;
+        Some(BootKeyboardReport {
+            modifiers,
+            keys: fields.bytes()?,
+        })
+    }
+}
+
+/// A boot protocol mouse input report.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BootMouseReport {
+    /// The buttons which are down, one bit each starting with the left button.
+    pub buttons: u8,
+    pub x: i8,
+    pub y: i8,
+    /// How far the wheel turned, if the mouse sends it.
+    pub wheel: Option<i8>,
+}
+
+impl BootMouseReport {
+    /// Parses a boot mouse input report, or returns `None` if it's too short.
+    pub fn from_bytes(value: &[u8]) -> Option<BootMouseReport> {
+        let mut fields = Fields(value);
+        Some(BootMouseReport {
+            buttons: fields.u8()?,
+            x: fields.u8()? as i8,
+            y: fields.u8()? as i8,
+            wheel: fields.u8().map(|wheel| wheel as i8),
+        })
+    }
+}
+
+/// A boot protocol input report, as streamed by [`HidDevice::boot_input_reports`].
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BootInputReport {
+    Keyboard(BootKeyboardReport),
+    Mouse(BootMouseReport),
+}
+
+/// The HID service of a connected peripheral.
+#[derive(Debug, Clone)]
+pub struct HidDevice<P> {
+    peripheral: P,
+    characteristics: Vec<Characteristic>,
+    report_map: Vec<u8>,
+    information: Option<HidInformation>,
+    reports: Vec<Report>,
+}
+
+impl<P: Peripheral + 'static> HidDevice<P> {
+    /// Discovers the HID service of a connected peripheral if it hasn't been yet, and reads its
+    /// report map, HID information and report references. Fails with
+    /// [`Error::NoSuchCharacteristic`] if the peripheral doesn't have a HID service.
+    pub async fn new(peripheral: P) -> Result<HidDevice<P>> {
+        let characteristics = service_characteristics(&peripheral, HID_SERVICE_UUID).await?;
+        let mut report_map = Vec::new();
+        let mut information = None;
+        let mut reports = Vec::new();
+        for characteristic in &characteristics {
+            match characteristic.uuid {
+                REPORT_MAP_CHARACTERISTIC_UUID => {
+                    report_map = peripheral.read(characteristic).await?;
+                }
+                HID_INFORMATION_CHARACTERISTIC_UUID => {
+                    information =
+                        HidInformation::from_bytes(&peripheral.read(characteristic).await?);
+                }
+                REPORT_CHARACTERISTIC_UUID => {
+                    reports.push(read_report_reference(&peripheral, characteristic).await?);
+                }
+                _ => {}
+            }
+        }
+        Ok(HidDevice {
+            peripheral,
+            characteristics,
+            report_map,
+            information,
+            reports,
+        })
+    }
+
+    /// The peripheral this is the HID service of.
+    pub fn peripheral(&self) -> &P {
+        &self.peripheral
+    }
+
+    /// The report map, which describes the format of each report as a USB HID report descriptor.
+    pub fn report_map(&self) -> &[u8] {
+        &self.report_map
+    }
+
+    /// The contents of the HID Information characteristic, if it could be read.
+    pub fn information(&self) -> Option<HidInformation> {
+        self.information
+    }
+
+    /// The device's reports, in the order of their characteristics.
+    pub fn reports(&self) -> &[Report] {
+        &self.reports
+    }
+
+    /// Returns the report with the given type and ID, if the device has one.
+    pub fn report(&self, report_type: ReportType, id: u8) -> Option<&Report> {
+        self.reports
+            .iter()
+            .find(|report| report.report_type == report_type && report.id == id)
+    }
+
+    fn characteristic(&self, uuid: Uuid) -> Result<&Characteristic> {
+        self.characteristics
+            .iter()
+            .find(|characteristic| characteristic.uuid == uuid)
+            .ok_or(Error::NoSuchCharacteristic)
+    }
+
+    /// Reads whether the device is using the boot or report protocol. Devices which don't
+    /// support the boot protocol don't have the characteristic for this, and always use reports.
+    pub async fn protocol_mode(&self) -> Result<ProtocolMode> {
+        let characteristic = match self.characteristic(PROTOCOL_MODE_CHARACTERISTIC_UUID) {
+            Ok(characteristic) => characteristic,
+            Err(_) => return Ok(ProtocolMode::Report),
+        };
+        match self.peripheral.read(characteristic).await?[..] {
+            [0, ..] => Ok(ProtocolMode::Boot),
+            _ => Ok(ProtocolMode::Report),
+        }
+    }
+
+    /// Switches the device to the boot or report protocol. Devices start in report mode when
+    /// connected to.
+    pub async fn set_protocol_mode(&self, mode: ProtocolMode) -> Result<()> {
+        let characteristic = self.characteristic(PROTOCOL_MODE_CHARACTERISTIC_UUID)?;
+        let value = match mode {
+            ProtocolMode::Boot => 0,
+            ProtocolMode::Report => 1,
+        };
+        self.peripheral
+            .write(characteristic, &[value], WriteType::WithoutResponse)
+            .await
+    }
+
+    /// Tells the device that the host is suspending or has resumed, so it can save power.
+    pub async fn set_suspended(&self, suspended: bool) -> Result<()> {
+        let characteristic = self.characteristic(HID_CONTROL_POINT_CHARACTERISTIC_UUID)?;
+        self.peripheral
+            .write(
+                characteristic,
+                &[if suspended { 0 } else { 1 }],
+                WriteType::WithoutResponse,
+            )
+            .await
+    }
+
+    /// Reads the current value of a report.
+    pub async fn read_report(&self, report: &Report) -> Result<Vec<u8>> {
+        self.peripheral.read(&report.characteristic).await
+    }
+
+    /// Sends an output or feature report to the device. Output reports are written without
+    /// response if the device allows it.
+    pub async fn write_report(&self, report: &Report, data: &[u8]) -> Result<()> {
+        let write_type = if report.report_type == ReportType::Output
+            && report
+                .characteristic
+                .properties
+                .contains(CharPropFlags::WRITE_WITHOUT_RESPONSE)
+        {
+            WriteType::WithoutResponse
+        } else {
+            WriteType::WithResponse
+        };
+        self.peripheral
+            .write(&report.characteristic, data, write_type)
+            .await
+    }
+
+    /// Subscribes to every input report, and returns a stream of the reports the device sends.
+    /// Dropping the stream doesn't unsubscribe.
+    ///
+    /// Reports are told apart by the handle of their characteristic. CoreBluetooth doesn't give
+    /// handles, so there only a device with a single input report can be used.
+    pub async fn input_reports(&self) -> Result<Pin<Box<dyn Stream<Item = InputReport> + Send>>> {
+        let inputs: Vec<Report> = self
+            .reports
+            .iter()
+            .filter(|report| report.report_type == ReportType::Input)
+            .cloned()
+            .collect();
+        // Get the stream first so we don't miss a report sent straight away.
+        let notifications = self.peripheral.notifications().await?;
+        for report in &inputs {
+            self.peripheral.subscribe(&report.characteristic).await?;
+        }
+        Ok(Box::pin(notifications.filter_map(move |notification| {
+            let report = if notification.service_uuid != HID_SERVICE_UUID
+                || notification.uuid != REPORT_CHARACTERISTIC_UUID
+            {
+                None
+            } else if notification.handle.is_none() && inputs.len() == 1 {
+                inputs.first()
+            } else {
+                inputs.iter().find(|report| {
+                    report.characteristic.value_handle.is_some()
+                        && report.characteristic.value_handle == notification.handle
+                })
+            };
+            let report = report.map(|report| InputReport {
+                report_id: report.id,
+                data: notification.value,
+            });
+            if report.is_none() {
+                trace!(
+                    "Ignoring notification from {} which isn't an input report",
+                    notification.uuid
+                );
+            }
+            async move { report }
+        })))
+    }
+
+    /// Subscribes to the boot keyboard and mouse input reports the device has, and returns a
+    /// stream of them. The device only sends these after being switched to
+    /// [`ProtocolMode::Boot`] with [`set_protocol_mode`](Self::set_protocol_mode).
+    pub async fn boot_input_reports(
+        &self,
+    ) -> Result<Pin<Box<dyn Stream<Item = BootInputReport> + Send>>> {
+        let notifications = self.peripheral.notifications().await?;
+        let mut found = false;
+        for uuid in [
+            BOOT_KEYBOARD_INPUT_CHARACTERISTIC_UUID,
+            BOOT_MOUSE_INPUT_CHARACTERISTIC_UUID,
+        ] {
+            if let Ok(characteristic) = self.characteristic(uuid) {
+                self.peripheral.subscribe(characteristic).await?;
+                found = true;
+            }
+        }
+        if !found {
+            return Err(Error::NoSuchCharacteristic);
+        }
+        Ok(Box::pin(notifications.filter_map(|notification| {
+            let report = match notification.uuid {
+                _ if notification.service_uuid != HID_SERVICE_UUID => None,
+                BOOT_KEYBOARD_INPUT_CHARACTERISTIC_UUID => {
+                    BootKeyboardReport::from_bytes(&notification.value)
+                        .map(BootInputReport::Keyboard)
+                }
+                BOOT_MOUSE_INPUT_CHARACTERISTIC_UUID => {
+                    BootMouseReport::from_bytes(&notification.value).map(BootInputReport::Mouse)
+                }
+                _ => None,
+            };
+            async move { report }
+        })))
+    }
+}
+
+/// Reads the Report Reference descriptor of a Report characteristic. Without one, the
+/// characteristic is taken to be the device's only report of its type.
+async fn read_report_reference<P: Peripheral>(
+    peripheral: &P,
+    characteristic: &Characteristic,
+) -> Result<Report> {
+    let descriptor = characteristic.descriptor(REPORT_REFERENCE_DESCRIPTOR_UUID);
+    let (id, report_type) = match descriptor {
+        // Report references don't change, so there's no need to read them again.
+        Some(descriptor) => match peripheral.read_descriptor_cached(descriptor).await?[..] {
+            [id, 2, ..] => (id, ReportType::Output),
+            [id, 3, ..] => (id, ReportType::Feature),
+            [id, ..] => (id, ReportType::Input),
+            [] => (0, ReportType::Input),
+        },
+        None if characteristic.properties.contains(CharPropFlags::NOTIFY) => (0, ReportType::Input),
+        None => (0, ReportType::Feature),
+    };
+    Ok(Report {
+        id,
+        report_type,
+        characteristic: characteristic.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boot_reports() {
+        assert_eq!(
+            BootKeyboardReport::from_bytes(&[0x02, 0x00, 0x04, 0, 0, 0, 0, 0]),
+            Some(BootKeyboardReport {
+                modifiers: 0x02,
+                keys: [0x04, 0, 0, 0, 0, 0],
+            })
+        );
+        assert_eq!(
+            BootMouseReport::from_bytes(&[0x01, 0xff, 0x02]),
+            Some(BootMouseReport {
+                buttons: 0x01,
+                x: -1,
+                y: 2,
+                wheel: None,
+            })
+        );
+        assert_eq!(
+            HidInformation::from_bytes(&[0x11, 0x01, 0x00, 0x02]),
+            Some(HidInformation {
+                hid_version: 0x0111,
+                country_code: 0,
+                remote_wake: false,
+                normally_connectable: true,
+            })
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn input_reports() -> Result<()> {
+        use crate::api::{
+            Central, Descriptor, ScanFilter, Service, CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
+        };
+        use crate::mock::Manager;
+
+        let manager = Manager::new().await?;
+        let adapter = manager.add_adapter();
+        adapter.start_scan(ScanFilter::default()).await?;
+        let descriptor = |uuid, handle| Descriptor {
+            uuid,
+            service_uuid: HID_SERVICE_UUID,
+            characteristic_uuid: REPORT_CHARACTERISTIC_UUID,
+            handle: Some(handle),
+            value: None,
+        };
+        let report = |handle, properties, descriptors: Vec<Descriptor>| Characteristic {
+            uuid: REPORT_CHARACTERISTIC_UUID,
+            service_uuid: HID_SERVICE_UUID,
+            properties,
+            descriptors: descriptors.into_iter().collect(),
+            handle: Some(handle - 1),
+            value_handle: Some(handle),
+        };
+        let keys = report(
+            0x11,
+            CharPropFlags::READ | CharPropFlags::NOTIFY,
+            vec![
+                descriptor(CLIENT_CHARACTERISTIC_CONFIGURATION_UUID, 0x12),
+                descriptor(REPORT_REFERENCE_DESCRIPTOR_UUID, 0x13),
+            ],
+        );
+        let media = report(
+            0x15,
+            CharPropFlags::READ | CharPropFlags::NOTIFY,
+            vec![
+                descriptor(CLIENT_CHARACTERISTIC_CONFIGURATION_UUID, 0x16),
+                descriptor(REPORT_REFERENCE_DESCRIPTOR_UUID, 0x17),
+            ],
+        );
+        let leds = report(
+            0x19,
+            CharPropFlags::READ | CharPropFlags::WRITE | CharPropFlags::WRITE_WITHOUT_RESPONSE,
+            vec![descriptor(REPORT_REFERENCE_DESCRIPTOR_UUID, 0x1a)],
+        );
+        let report_map = Characteristic {
+            uuid: REPORT_MAP_CHARACTERISTIC_UUID,
+            service_uuid: HID_SERVICE_UUID,
+            properties: CharPropFlags::READ,
+            descriptors: Default::default(),
+            handle: Some(0x0c),
+            value_handle: Some(0x0d),
+        };
+        let device = adapter.advertise(Default::default()).unwrap();
+        device.set_services([Service {
+            uuid: HID_SERVICE_UUID,
+            primary: true,
+            characteristics: [
+                report_map.clone(),
+                keys.clone(),
+                media.clone(),
+                leds.clone(),
+            ]
+            .into(),
+            included_services: Default::default(),
+            handle: Some(0x0b),
+        }]);
+        device.set_value(&report_map, [0x05, 0x01, 0x09, 0x06]);
+        device.set_descriptor_value(&descriptor(REPORT_REFERENCE_DESCRIPTOR_UUID, 0x13), [1, 1]);
+        device.set_descriptor_value(&descriptor(REPORT_REFERENCE_DESCRIPTOR_UUID, 0x17), [2, 1]);
+        device.set_descriptor_value(&descriptor(REPORT_REFERENCE_DESCRIPTOR_UUID, 0x1a), [1, 2]);
+        device.connect().await?;
+
+        let hid = HidDevice::new(device.clone()).await?;
+        assert_eq!(hid.report_map(), [0x05, 0x01, 0x09, 0x06]);
+        assert_eq!(hid.reports().len(), 3);
+        let output = hid.report(ReportType::Output, 1).unwrap();
+        assert_eq!(output.characteristic, leds);
+        hid.write_report(output, &[0x02]).await?;
+        assert_eq!(device.value(&leds), Some(vec![0x02]));
+
+        let mut reports = hid.input_reports().await?;
+        assert!(device.notify(&media, [0xe9, 0x00]));
+        assert!(device.notify(&keys, [0, 0, 0x04, 0, 0, 0, 0, 0]));
+        assert_eq!(
+            reports.next().await,
+            Some(InputReport {
+                report_id: 2,
+                data: vec![0xe9, 0x00],
+            })
+        );
+        assert_eq!(reports.next().await.unwrap().report_id, 1);
+        Ok(())
+    }
+}
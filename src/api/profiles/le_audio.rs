@@ -0,0 +1,414 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Finding LE Audio devices and reading what audio they can handle.
+//!
+//! Devices which accept audio streams advertise the Audio Stream Control Service, with a
+//! [`StreamingAnnouncement`] in its service data, and broadcast sources advertise a
+//! [`BroadcastAnnouncement`]. Once connected, the Published Audio Capabilities Service says which
+//! codecs and configurations a device supports:
+//!
+//! ```no_run
+//! use btleplug::api::profiles::le_audio::LeAudio as _;
+//! # use btleplug::api::Peripheral;
+//! # async fn print(peripheral: impl Peripheral) -> btleplug::Result<()> {
+//! peripheral.connect().await?;
+//! let capabilities = peripheral.audio_capabilities().await?;
+//! for record in &capabilities.sink_pac {
+//!     println!("Accepts {:?}", record.codec_id);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Audio itself goes over isochronous channels, which no platform lets applications set up yet:
+//! BlueZ does that for audio servers such as PipeWire, and the other platforms for their own audio
+//! stacks.
+
+use super::Fields;
+use crate::api::{
+    bleuuid::uuid_from_u16, standard::service_characteristics, Peripheral, PeripheralProperties,
+};
+use crate::Result;
+use async_trait::async_trait;
+use bitflags::bitflags;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use serde_cr as serde;
+use uuid::Uuid;
+
+/// The UUID of the Audio Stream Control Service, of devices which accept unicast audio streams.
+pub const AUDIO_STREAM_CONTROL_SERVICE_UUID: Uuid = uuid_from_u16(0x184E);
+/// The UUID of the Broadcast Audio Scan Service, of devices which can be told which broadcasts to
+/// listen to.
+pub const BROADCAST_AUDIO_SCAN_SERVICE_UUID: Uuid = uuid_from_u16(0x184F);
+/// The UUID of the Published Audio Capabilities Service.
+pub const PUBLISHED_AUDIO_CAPABILITIES_SERVICE_UUID: Uuid = uuid_from_u16(0x1850);
+/// The UUID of the service data of a [`BroadcastAnnouncement`].
+pub const BROADCAST_AUDIO_ANNOUNCEMENT_SERVICE_UUID: Uuid = uuid_from_u16(0x1852);
+/// The UUID of the Common Audio Service, of devices which are part of a set, such as a pair of
+/// earbuds.
+pub const COMMON_AUDIO_SERVICE_UUID: Uuid = uuid_from_u16(0x1853);
+/// The UUID of the service data of a Public Broadcast Announcement, of broadcasts which follow the
+/// Public Broadcast Profile.
+pub const PUBLIC_BROADCAST_ANNOUNCEMENT_SERVICE_UUID: Uuid = uuid_from_u16(0x1856);
+
+pub const SINK_PAC_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2BC9);
+pub const SINK_AUDIO_LOCATIONS_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2BCA);
+pub const SOURCE_PAC_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2BCB);
+pub const SOURCE_AUDIO_LOCATIONS_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2BCC);
+pub const AVAILABLE_AUDIO_CONTEXTS_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2BCD);
+pub const SUPPORTED_AUDIO_CONTEXTS_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2BCE);
+
+/// The coding format of LC3, the codec every LE Audio device supports.
+pub const LC3_CODING_FORMAT: u8 = 0x06;
+
+bitflags! {
+    /// What kinds of audio a device is for, or will accept at the moment.
+    #[cfg_attr(
+        feature = "serde",
+        derive(Serialize, Deserialize),
+        serde(crate = "serde_cr", transparent)
+    )]
+    #[derive(Default, Debug, PartialEq, Eq, Hash, Clone, Copy)]
+    pub struct AudioContexts: u16 {
+        const UNSPECIFIED = 0x0001;
+        const CONVERSATIONAL = 0x0002;
+        const MEDIA = 0x0004;
+        const GAME = 0x0008;
+        const INSTRUCTIONAL = 0x0010;
+        const VOICE_ASSISTANTS = 0x0020;
+        const LIVE = 0x0040;
+        const SOUND_EFFECTS = 0x0080;
+        const NOTIFICATIONS = 0x0100;
+        const RINGTONE = 0x0200;
+        const ALERTS = 0x0400;
+        const EMERGENCY_ALARM = 0x0800;
+    }
+}
+
+/// Audio contexts for audio to the device, which it plays, and from it, such as from a microphone.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SinkSourceContexts {
+    pub sink: AudioContexts,
+    pub source: AudioContexts,
+}
+
+impl SinkSourceContexts {
+    /// Parses the value of the Available or Supported Audio Contexts characteristic, or returns
+    /// `None` if it's too short.
+    pub fn from_bytes(value: &[u8]) -> Option<SinkSourceContexts> {
+        let mut fields = Fields(value);
+        Some(SinkSourceContexts {
+            sink: AudioContexts::from_bits_retain(fields.u16()?),
+            source: AudioContexts::from_bits_retain(fields.u16()?),
+        })
+    }
+}
+
+/// What a device accepting unicast audio streams says in the service data of its Audio Stream
+/// Control Service.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StreamingAnnouncement {
+    /// Whether the device is asking a particular central to connect, rather than any.
+    pub targeted: bool,
+    /// The kinds of audio the device will accept now.
+    pub available_contexts: SinkSourceContexts,
+}
+
+impl StreamingAnnouncement {
+    /// Returns the announcement in the properties of a peripheral, if it's advertising one.
+    pub fn from_properties(properties: &PeripheralProperties) -> Option<StreamingAnnouncement> {
+        let mut fields = Fields(
+            properties
+                .service_data
+                .get(&AUDIO_STREAM_CONTROL_SERVICE_UUID)?,
+        );
+        let targeted = fields.u8()? == 0x01;
+        let available_contexts = SinkSourceContexts::from_bytes(&fields.bytes::<4>()?)?;
+        Some(StreamingAnnouncement {
+            targeted,
+            available_contexts,
+        })
+    }
+}
+
+/// What a broadcast source says in its advertisements about the broadcast.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct BroadcastAnnouncement {
+    /// The 24-bit ID of the broadcast, which stays the same while the broadcast lasts.
+    pub broadcast_id: u32,
+    /// Whether the broadcast follows the Public Broadcast Profile, so that any device can
+    /// play it.
+    pub public: bool,
+}
+
+impl BroadcastAnnouncement {
+    /// Returns the announcement in the properties of a peripheral, if it's advertising one.
+    pub fn from_properties(properties: &PeripheralProperties) -> Option<BroadcastAnnouncement> {
+        let mut fields = Fields(
+            properties
+                .service_data
+                .get(&BROADCAST_AUDIO_ANNOUNCEMENT_SERVICE_UUID)?,
+        );
+        Some(BroadcastAnnouncement {
+            broadcast_id: fields.u24()?,
+            public: properties
+                .service_data
+                .contains_key(&PUBLIC_BROADCAST_ANNOUNCEMENT_SERVICE_UUID),
+        })
+    }
+}
+
+/// Identifies a codec: a coding format assigned by the Bluetooth SIG, such as
+/// [`LC3_CODING_FORMAT`], or a vendor's own codec.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CodecId {
+    pub coding_format: u8,
+    /// The company ID of the vendor of a vendor-specific codec, which has coding format `0xff`.
+    pub company_id: u16,
+    pub vendor_codec_id: u16,
+}
+
+/// A type-length-value structure, as codec capabilities and metadata are made of.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Ltv {
+    pub kind: u8,
+    pub value: Vec<u8>,
+}
+
+/// Parses a sequence of LTV structures, or returns `None` if one runs past the end.
+fn parse_ltvs(data: &[u8]) -> Option<Vec<Ltv>> {
+    let mut ltvs = Vec::new();
+    let mut rest = data;
+    while let [length, tail @ ..] = rest {
+        let length = usize::from(*length);
+        if length == 0 || tail.len() < length {
+            return None;
+        }
+        let (ltv, tail) = tail.split_at(length);
+        ltvs.push(Ltv {
+            kind: ltv[0],
+            value: ltv[1..].to_vec(),
+        });
+        rest = tail;
+    }
+    Some(ltvs)
+}
+
+/// A Published Audio Capability record: a codec which a device supports, and what it supports it
+/// with.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PacRecord {
+    pub codec_id: CodecId,
+    /// The codec-specific capabilities, such as the sampling frequencies and frame durations of
+    /// LC3.
+    pub capabilities: Vec<Ltv>,
+    pub metadata: Vec<Ltv>,
+}
+
+impl PacRecord {
+    /// Parses the records in the value of a Sink PAC or Source PAC characteristic, or returns
+    /// `None` if it's malformed.
+    pub fn from_bytes(value: &[u8]) -> Option<Vec<PacRecord>> {
+        let mut fields = Fields(value);
+        let count = fields.u8()?;
+        let mut records = Vec::with_capacity(usize::from(count));
+        for _ in 0..count {
+            let codec_id = CodecId {
+                coding_format: fields.u8()?,
+                company_id: fields.u16()?,
+                vendor_codec_id: fields.u16()?,
+            };
+            let capabilities = fields.u8()?;
+            let capabilities = parse_ltvs(fields.take(usize::from(capabilities))?)?;
+            let metadata = fields.u8()?;
+            let metadata = parse_ltvs(fields.take(usize::from(metadata))?)?;
+            records.push(PacRecord {
+                codec_id,
+                capabilities,
+                metadata,
+            });
+        }
+        Some(records)
+    }
+}
+
+/// What the Published Audio Capabilities Service of a peripheral says about it. A device which
+/// only plays audio has no source characteristics, and one which only records has no sink ones.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AudioCapabilities {
+    /// The codecs the device accepts audio to play with.
+    pub sink_pac: Vec<PacRecord>,
+    /// The codecs the device can send audio with.
+    pub source_pac: Vec<PacRecord>,
+    /// The speaker locations, such as front left, which the device plays, as a bitmask of the
+    /// Bluetooth SIG's audio locations.
+    pub sink_audio_locations: Option<u32>,
+    pub source_audio_locations: Option<u32>,
+    /// The kinds of audio the device will accept now.
+    pub available_contexts: Option<SinkSourceContexts>,
+    /// The kinds of audio the device can handle at all.
+    pub supported_contexts: Option<SinkSourceContexts>,
+}
+
+/// Reads the audio capabilities of a connected LE Audio peripheral.
+#[async_trait]
+pub trait LeAudio: Peripheral {
+    /// Reads every characteristic of the Published Audio Capabilities Service the peripheral has.
+    /// The service is discovered if it hasn't been yet, and
+    /// [`Error::NoSuchCharacteristic`](crate::Error::NoSuchCharacteristic) is returned if the
+    /// peripheral doesn't have one. Values which can't be parsed are left out.
+    async fn audio_capabilities(&self) -> Result<AudioCapabilities> {
+        let mut capabilities = AudioCapabilities::default();
+        for characteristic in
+            service_characteristics(self, PUBLISHED_AUDIO_CAPABILITIES_SERVICE_UUID).await?
+        {
+            // A device may have more than one PAC characteristic in each direction.
+            let parse: fn(&mut AudioCapabilities, &[u8]) = match characteristic.uuid {
+                SINK_PAC_CHARACTERISTIC_UUID => |capabilities, value| {
+                    let records = PacRecord::from_bytes(value).unwrap_or_default();
+                    capabilities.sink_pac.extend(records)
+                },
+                SOURCE_PAC_CHARACTERISTIC_UUID => |capabilities, value| {
+                    let records = PacRecord::from_bytes(value).unwrap_or_default();
+                    capabilities.source_pac.extend(records)
+                },
+                SINK_AUDIO_LOCATIONS_CHARACTERISTIC_UUID => {
+                    |capabilities, value| capabilities.sink_audio_locations = Fields(value).u32()
+                }
+                SOURCE_AUDIO_LOCATIONS_CHARACTERISTIC_UUID => {
+                    |capabilities, value| capabilities.source_audio_locations = Fields(value).u32()
+                }
+                AVAILABLE_AUDIO_CONTEXTS_CHARACTERISTIC_UUID => |capabilities, value| {
+                    capabilities.available_contexts = SinkSourceContexts::from_bytes(value)
+                },
+                SUPPORTED_AUDIO_CONTEXTS_CHARACTERISTIC_UUID => |capabilities, value| {
+                    capabilities.supported_contexts = SinkSourceContexts::from_bytes(value)
+                },
+                _ => continue,
+            };
+            parse(&mut capabilities, &self.read(&characteristic).await?);
+        }
+        Ok(capabilities)
+    }
+}
+
+impl<P: Peripheral> LeAudio for P {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pac_records() {
+        // One LC3 record supporting 16 and 48 kHz with 10 ms frames, preferably for media.
+        let value = [
+            1, // Records
+            0x06, 0x00, 0x00, 0x00, 0x00, // LC3
+            7, 3, 0x01, 0x84, 0x00, 2, 0x02, 0x02, // Capabilities
+            4, 3, 0x01, 0x04, 0x00, // Metadata
+        ];
+        let records = PacRecord::from_bytes(&value).unwrap();
+        assert_eq!(
+            records,
+            vec![PacRecord {
+                codec_id: CodecId {
+                    coding_format: LC3_CODING_FORMAT,
+                    company_id: 0,
+                    vendor_codec_id: 0,
+                },
+                capabilities: vec![
+                    Ltv {
+                        kind: 0x01,
+                        value: vec![0x84, 0x00],
+                    },
+                    Ltv {
+                        kind: 0x02,
+                        value: vec![0x02],
+                    },
+                ],
+                metadata: vec![Ltv {
+                    kind: 0x01,
+                    value: vec![0x04, 0x00],
+                }],
+            }]
+        );
+        // The metadata runs past the end.
+        assert_eq!(PacRecord::from_bytes(&value[..value.len() - 1]), None);
+        assert_eq!(PacRecord::from_bytes(&[0]), Some(vec![]));
+    }
+
+    #[test]
+    fn announcements() {
+        let mut properties = PeripheralProperties::default();
+        assert_eq!(StreamingAnnouncement::from_properties(&properties), None);
+        properties.service_data.insert(
+            AUDIO_STREAM_CONTROL_SERVICE_UUID,
+            vec![0x01, 0x06, 0x00, 0x02, 0x00, 0x00].into(),
+        );
+        properties.service_data.insert(
+            BROADCAST_AUDIO_ANNOUNCEMENT_SERVICE_UUID,
+            vec![0x56, 0x34, 0x12].into(),
+        );
+        assert_eq!(
+            StreamingAnnouncement::from_properties(&properties),
+            Some(StreamingAnnouncement {
+                targeted: true,
+                available_contexts: SinkSourceContexts {
+                    sink: AudioContexts::CONVERSATIONAL | AudioContexts::MEDIA,
+                    source: AudioContexts::CONVERSATIONAL,
+                },
+            })
+        );
+        assert_eq!(
+            BroadcastAnnouncement::from_properties(&properties),
+            Some(BroadcastAnnouncement {
+                broadcast_id: 0x123456,
+                public: false,
+            })
+        );
+    }
+}
@@ -0,0 +1,99 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Parsers for the measurements of common Bluetooth SIG sensor profiles, extension traits for
+//! peripherals which subscribe to them, a client for HID devices, and discovery of LE Audio
+//! devices. Enabled with the `profiles` feature.
+//!
+//! Each measurement type can be parsed from a characteristic value with `from_bytes`, e.g. from a
+//! [`ValueNotification`](super::ValueNotification), or streamed from a connected peripheral:
+//!
+//! ```no_run
+//! use btleplug::api::profiles::heart_rate::HeartRate as _;
+//! use futures::StreamExt;
+//! # use btleplug::api::Peripheral;
+//! # async fn watch(peripheral: impl Peripheral) -> btleplug::Result<()> {
+//! peripheral.connect().await?;
+//! let mut measurements = peripheral.heart_rate_measurements().await?;
+//! while let Some(measurement) = measurements.next().await {
+//!     println!("{} bpm", measurement.bpm);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod cycling;
+pub mod heart_rate;
+pub mod hid;
+pub mod le_audio;
+pub mod thermometer;
+
+use super::{standard::find_characteristic, Peripheral};
+use crate::Result;
+use futures::stream::{Stream, StreamExt};
+use std::pin::Pin;
+use uuid::Uuid;
+
+/// Subscribes to the given characteristic, discovering its service first if needed, and returns a
+/// stream of its values parsed with `parse`. Values which can't be parsed are skipped.
+async fn measurements<P: Peripheral, T: Send + 'static>(
+    peripheral: &P,
+    service_uuid: Uuid,
+    characteristic_uuid: Uuid,
+    parse: fn(&[u8]) -> Option<T>,
+) -> Result<Pin<Box<dyn Stream<Item = T> + Send>>> {
+    let characteristic = find_characteristic(peripheral, service_uuid, characteristic_uuid).await?;
+    let values = peripheral.subscribe_stream(&characteristic).await?;
+    Ok(Box::pin(
+        values.filter_map(move |value| async move { parse(&value) }),
+    ))
+}
+
+/// Reads the little-endian fields of a characteristic value in order.
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    fn bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (field, rest) = self.0.split_first_chunk::<N>()?;
+        self.0 = rest;
+        Some(*field)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes::<1>().map(|[value]| value)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes().map(u16::from_le_bytes)
+    }
+
+    fn i16(&mut self) -> Option<i16> {
+        self.bytes().map(i16::from_le_bytes)
+    }
+
+    fn u24(&mut self) -> Option<u32> {
+        self.bytes::<3>()
+            .map(|[low, middle, high]| u32::from_le_bytes([low, middle, high, 0]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes().map(u32::from_le_bytes)
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (field, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(field)
+    }
+
+    fn remaining(&self) -> usize {
+        self.0.len()
+    }
+}
@@ -0,0 +1,208 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! The Health Thermometer profile.
+
+use super::{measurements, Fields};
+use crate::api::{bleuuid::uuid_from_u16, standard::presentation::ieee11073_float, Peripheral};
+use crate::Result;
+use async_trait::async_trait;
+use futures::stream::Stream;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use serde_cr as serde;
+use std::pin::Pin;
+use uuid::Uuid;
+
+/// The UUID of the Health Thermometer service.
+pub const HEALTH_THERMOMETER_SERVICE_UUID: Uuid = uuid_from_u16(0x1809);
+
+/// The UUID of the Temperature Measurement characteristic, which indicates
+/// [`TemperatureMeasurement`]s.
+pub const TEMPERATURE_MEASUREMENT_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2A1C);
+
+/// The unit of a [`TemperatureMeasurement`].
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TemperatureUnit {
+    Celsius,
+    Fahrenheit,
+}
+
+/// Where on the body a temperature was measured.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TemperatureType {
+    Armpit,
+    Body,
+    Ear,
+    Finger,
+    GastrointestinalTract,
+    Mouth,
+    Rectum,
+    Toe,
+    Tympanum,
+    /// A value which the specification reserves.
+    Other(u8),
+}
+
+impl From<u8> for TemperatureType {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => TemperatureType::Armpit,
+            2 => TemperatureType::Body,
+            3 => TemperatureType::Ear,
+            4 => TemperatureType::Finger,
+            5 => TemperatureType::GastrointestinalTract,
+            6 => TemperatureType::Mouth,
+            7 => TemperatureType::Rectum,
+            8 => TemperatureType::Toe,
+            9 => TemperatureType::Tympanum,
+            other => TemperatureType::Other(other),
+        }
+    }
+}
+
+/// The date and time a [`TemperatureMeasurement`] was taken, in the thermometer's local time.
+/// Fields the thermometer doesn't know are 0.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+}
+
+/// A value of the Temperature Measurement characteristic.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TemperatureMeasurement {
+    /// The temperature, in `unit`. This is NaN if the thermometer couldn't measure it.
+    pub temperature: f64,
+    pub unit: TemperatureUnit,
+    pub timestamp: Option<DateTime>,
+    pub temperature_type: Option<TemperatureType>,
+}
+
+impl TemperatureMeasurement {
+    /// Parses a value of the Temperature Measurement characteristic, or returns `None` if it's
+    /// too short.
+    pub fn from_bytes(value: &[u8]) -> Option<TemperatureMeasurement> {
+        let mut fields = Fields(value);
+        let flags = fields.u8()?;
+        let temperature = ieee11073_float(fields.u32()?);
+        let unit = if flags & 0x01 != 0 {
+            TemperatureUnit::Fahrenheit
+        } else {
+            TemperatureUnit::Celsius
+        };
+        let timestamp = if flags & 0x02 != 0 {
+            Some(DateTime {
+                year: fields.u16()?,
+                month: fields.u8()?,
+                day: fields.u8()?,
+                hours: fields.u8()?,
+                minutes: fields.u8()?,
+                seconds: fields.u8()?,
+            })
+        } else {
+            None
+        };
+        let temperature_type = if flags & 0x04 != 0 {
+            Some(fields.u8()?.into())
+        } else {
+            None
+        };
+        Some(TemperatureMeasurement {
+            temperature,
+            unit,
+            timestamp,
+            temperature_type,
+        })
+    }
+
+    /// The temperature in degrees Celsius, whichever unit it was measured in.
+    pub fn celsius(&self) -> f64 {
+        match self.unit {
+            TemperatureUnit::Celsius => self.temperature,
+            TemperatureUnit::Fahrenheit => (self.temperature - 32.0) * 5.0 / 9.0,
+        }
+    }
+}
+
+/// Streams temperature measurements from a connected thermometer.
+#[async_trait]
+pub trait Thermometer: Peripheral {
+    /// Subscribes to the Temperature Measurement characteristic, discovering the Health
+    /// Thermometer service if it hasn't been yet, and returns a stream of its measurements.
+    /// Dropping the stream doesn't unsubscribe.
+    async fn temperature_measurements(
+        &self,
+    ) -> Result<Pin<Box<dyn Stream<Item = TemperatureMeasurement> + Send>>> {
+        measurements(
+            self,
+            HEALTH_THERMOMETER_SERVICE_UUID,
+            TEMPERATURE_MEASUREMENT_CHARACTERISTIC_UUID,
+            TemperatureMeasurement::from_bytes,
+        )
+        .await
+    }
+}
+
+impl<P: Peripheral> Thermometer for P {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        // 36.6 °C, taken in the mouth on 2024-03-05 at 12:30:00.
+        let measurement = TemperatureMeasurement::from_bytes(&[
+            0x06, 0x6e, 0x01, 0x00, 0xff, 0xe8, 0x07, 0x03, 0x05, 0x0c, 0x1e, 0x00, 0x06,
+        ])
+        .unwrap();
+        assert!((measurement.temperature - 36.6).abs() < 1e-9);
+        assert_eq!(measurement.unit, TemperatureUnit::Celsius);
+        assert_eq!(measurement.timestamp.unwrap().year, 2024);
+        assert_eq!(measurement.temperature_type, Some(TemperatureType::Mouth));
+
+        // -4 °F, with a negative mantissa.
+        let measurement =
+            TemperatureMeasurement::from_bytes(&[0x01, 0xfc, 0xff, 0xff, 0x00]).unwrap();
+        assert_eq!(measurement.temperature, -4.0);
+        assert!((measurement.celsius() + 20.0).abs() < 1e-9);
+
+        let measurement =
+            TemperatureMeasurement::from_bytes(&[0x00, 0xff, 0xff, 0x7f, 0x00]).unwrap();
+        assert!(measurement.temperature.is_nan());
+        assert_eq!(
+            TemperatureMeasurement::from_bytes(&[0x02, 0x6e, 0x01, 0x00, 0xff]),
+            None
+        );
+    }
+}
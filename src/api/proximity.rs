@@ -0,0 +1,499 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Helpers for estimating how far away a peripheral is from its advertised TX power and the RSSI
+//! we received it with.
+//!
+//! These use the log-distance path loss model, so they only give a rough idea of distance. Results
+//! vary a lot with antennas, orientation and obstacles; if you need better accuracy, calibrate a
+//! [`PathLossModel`] for your environment.
+//!
+//! ```
+//! use btleplug::api::proximity::{estimate_distance, PathLossModel};
+//!
+//! // A device advertising 0 dBm, received at -61 dBm.
+//! let metres = estimate_distance(0, -61);
+//! assert!((metres - 10.0).abs() < 0.01);
+//!
+//! let indoor = PathLossModel::INDOOR.estimate_distance(0, -71);
+//! assert!((indoor - 10.0).abs() < 0.01);
+//! ```
+//!
+//! For presence detection, a [`ProximityMonitor`] sorts peripherals into [`ProximityZone`]s from
+//! those estimates, and reports when they come into range, move between zones and go out of range.
+//! It smooths the RSSI and only moves a peripheral to another zone once it's well past the
+//! boundary, so that a device sitting near a boundary doesn't flap between zones.
+//!
+//! ```no_run
+//! use btleplug::api::proximity::{ProximityEvent, ProximityMonitor, ZoneThresholds};
+//! use btleplug::api::Central;
+//! use futures::StreamExt;
+//!
+//! async fn watch(central: &(impl Central + 'static)) -> btleplug::Result<()> {
+//!     let mut events = ProximityMonitor::new(ZoneThresholds::default())
+//!         .watch(central)
+//!         .await?;
+//!     while let Some(event) = events.next().await {
+//!         match event {
+//!             ProximityEvent::Entered { id, zone } => println!("{} arrived, {:?}", id, zone),
+//!             ProximityEvent::ZoneChanged { id, to, .. } => println!("{} is now {:?}", id, to),
+//!             ProximityEvent::Exited(id) => println!("{} left", id),
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use super::{Central, CentralEvent, Peripheral, PeripheralProperties, ScanFilter};
+use crate::platform::PeripheralId;
+use crate::time::Instant;
+use crate::{rt, Result};
+use futures::stream::{self, Stream, StreamExt};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Parameters of the log-distance path loss model.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathLossModel {
+    /// The path loss in dB at the reference distance of 1 metre.
+    pub reference_loss: f64,
+    /// The path loss exponent, which describes how quickly the signal falls off with distance. 2.0
+    /// is free space; indoor environments are typically between 2.5 and 4.0.
+    pub exponent: f64,
+}
+
+impl PathLossModel {
+    /// Free-space propagation at 2.4 GHz.
+    pub const FREE_SPACE: PathLossModel = PathLossModel {
+        reference_loss: 41.0,
+        exponent: 2.0,
+    };
+
+    /// A typical indoor environment, such as an office or home with some obstructions.
+    pub const INDOOR: PathLossModel = PathLossModel {
+        reference_loss: 41.0,
+        exponent: 3.0,
+    };
+
+    /// Creates a model from a calibrated 1 metre reading: the RSSI received from a device placed 1
+    /// metre away, and the TX power level it advertises.
+    pub fn calibrated(tx_power_level: i16, rssi_at_one_metre: i16, exponent: f64) -> Self {
+        PathLossModel {
+            reference_loss: path_loss(tx_power_level, rssi_at_one_metre) as f64,
+            exponent,
+        }
+    }
+
+    /// Estimates the distance in metres to a device advertising `tx_power_level` dBm which was
+    /// received at `rssi` dBm.
+    pub fn estimate_distance(&self, tx_power_level: i16, rssi: i16) -> f64 {
+        let loss = path_loss(tx_power_level, rssi) as f64;
+        10f64.powf((loss - self.reference_loss) / (10.0 * self.exponent))
+    }
+}
+
+impl Default for PathLossModel {
+    fn default() -> Self {
+        PathLossModel::FREE_SPACE
+    }
+}
+
+/// Returns the path loss in dB between a device advertising `tx_power_level` dBm and the
+/// received `rssi` in dBm.
+pub fn path_loss(tx_power_level: i16, rssi: i16) -> i16 {
+    tx_power_level.saturating_sub(rssi)
+}
+
+/// Estimates the distance in metres to a device using the [free-space](PathLossModel::FREE_SPACE)
+/// model.
+pub fn estimate_distance(tx_power_level: i16, rssi: i16) -> f64 {
+    PathLossModel::default().estimate_distance(tx_power_level, rssi)
+}
+
+impl PeripheralProperties {
+    /// Returns the path loss in dB for the most recent advertisement, if the device advertised its
+    /// TX power level.
+    pub fn path_loss(&self) -> Option<i16> {
+        Some(path_loss(self.tx_power_level?, self.rssi?))
+    }
+
+    /// Estimates the distance in metres to the device with the given model, if the device
+    /// advertised its TX power level.
+    pub fn estimate_distance(&self, model: &PathLossModel) -> Option<f64> {
+        Some(model.estimate_distance(self.tx_power_level?, self.rssi?))
+    }
+}
+
+/// How close a peripheral is, from closest to furthest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ProximityZone {
+    /// Practically touching, e.g. a tag held against the receiver.
+    Immediate,
+    /// In the same room.
+    Near,
+    /// Still in range, but further away.
+    Far,
+    /// Not heard from for a while.
+    Gone,
+}
+
+/// Where the boundaries between [`ProximityZone`]s are, and how a [`ProximityMonitor`] settles on
+/// a peripheral's zone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZoneThresholds {
+    /// Estimates distances from the RSSI.
+    pub model: PathLossModel,
+    /// The TX power level in dBm assumed for peripherals which don't advertise theirs.
+    pub tx_power_level: i16,
+    /// Peripherals closer than this many metres are [`Immediate`](ProximityZone::Immediate).
+    pub immediate: f64,
+    /// Peripherals closer than this many metres are [`Near`](ProximityZone::Near), and ones
+    /// further away [`Far`](ProximityZone::Far).
+    pub near: f64,
+    /// How far past a boundary a peripheral has to be to move to the zone on the other side, as a
+    /// fraction of the boundary's distance.
+    pub hysteresis: f64,
+    /// How much each advertisement's RSSI counts towards the moving average distances are
+    /// estimated from, from 0 to 1. Higher values follow movement more quickly, but smooth less.
+    pub smoothing: f64,
+    /// Peripherals which haven't advertised for this long are [`Gone`](ProximityZone::Gone).
+    pub timeout: Duration,
+}
+
+impl Default for ZoneThresholds {
+    fn default() -> Self {
+        ZoneThresholds {
+            model: PathLossModel::INDOOR,
+            tx_power_level: 0,
+            immediate: 0.5,
+            near: 3.0,
+            hysteresis: 0.2,
+            smoothing: 0.3,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl ZoneThresholds {
+    /// Returns the zone at `distance` metres of a peripheral which was in `current`.
+    fn classify(&self, distance: f64, current: ProximityZone) -> ProximityZone {
+        // Boundaries move away from the zone the peripheral's already in.
+        let boundary = |metres: f64, closer: bool| match (current, closer) {
+            (ProximityZone::Gone, _) => metres,
+            (_, true) => metres * (1.0 + self.hysteresis),
+            (_, false) => metres * (1.0 - self.hysteresis),
+        };
+        if distance < boundary(self.immediate, current == ProximityZone::Immediate) {
+            ProximityZone::Immediate
+        } else if distance < boundary(self.near, current <= ProximityZone::Near) {
+            ProximityZone::Near
+        } else {
+            ProximityZone::Far
+        }
+    }
+}
+
+/// A peripheral coming into range, moving to another zone or going out of range.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ProximityEvent {
+    Entered {
+        id: PeripheralId,
+        zone: ProximityZone,
+    },
+    ZoneChanged {
+        id: PeripheralId,
+        from: ProximityZone,
+        to: ProximityZone,
+    },
+    /// The peripheral is [`Gone`](ProximityZone::Gone).
+    Exited(PeripheralId),
+}
+
+#[derive(Debug)]
+struct Tracked {
+    zone: ProximityZone,
+    rssi: f64,
+    last_seen: Instant,
+}
+
+/// Sorts peripherals into [`ProximityZone`]s from their advertisements.
+///
+/// [`watch`](Self::watch) does so for the advertisements an adapter receives. Otherwise, pass each
+/// advertisement to [`update`](Self::update), and call [`expire`](Self::expire) now and then to
+/// find out which peripherals have gone.
+#[derive(Debug)]
+pub struct ProximityMonitor {
+    thresholds: ZoneThresholds,
+    peripherals: HashMap<PeripheralId, Tracked>,
+}
+
+impl ProximityMonitor {
+    pub fn new(thresholds: ZoneThresholds) -> Self {
+        ProximityMonitor {
+            thresholds,
+            peripherals: HashMap::new(),
+        }
+    }
+
+    /// Returns the zone the peripheral is in.
+    pub fn zone(&self, id: &PeripheralId) -> ProximityZone {
+        self.peripherals
+            .get(id)
+            .map_or(ProximityZone::Gone, |tracked| tracked.zone)
+    }
+
+    /// Takes the properties of a peripheral as of an advertisement received at `now`, and returns
+    /// the event it causes, if any. Properties without an RSSI are ignored.
+    pub fn update(
+        &mut self,
+        id: &PeripheralId,
+        properties: &PeripheralProperties,
+        now: Instant,
+    ) -> Option<ProximityEvent> {
+        let rssi = f64::from(properties.rssi?);
+        let thresholds = self.thresholds;
+        let tx_power_level = properties
+            .tx_power_level
+            .unwrap_or(thresholds.tx_power_level);
+        let tracked = self.peripherals.entry(id.clone()).or_insert(Tracked {
+            zone: ProximityZone::Gone,
+            rssi,
+            last_seen: now,
+        });
+        tracked.rssi += thresholds.smoothing * (rssi - tracked.rssi);
+        tracked.last_seen = now;
+        let loss = f64::from(tx_power_level) - tracked.rssi;
+        let distance = 10f64
+            .powf((loss - thresholds.model.reference_loss) / (10.0 * thresholds.model.exponent));
+        let from = tracked.zone;
+        let to = thresholds.classify(distance, from);
+        tracked.zone = to;
+        match (from, to) {
+            (ProximityZone::Gone, zone) => Some(ProximityEvent::Entered {
+                id: id.clone(),
+                zone,
+            }),
+            (from, to) if from != to => Some(ProximityEvent::ZoneChanged {
+                id: id.clone(),
+                from,
+                to,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Forgets the peripherals which haven't advertised since the timeout before `now`, and returns
+    /// an [`Exited`](ProximityEvent::Exited) event for each.
+    pub fn expire(&mut self, now: Instant) -> Vec<ProximityEvent> {
+        let timeout = self.thresholds.timeout;
+        let mut exited = vec![];
+        self.peripherals.retain(|id, tracked| {
+            let keep = now.saturating_duration_since(tracked.last_seen) < timeout;
+            if !keep {
+                exited.push(ProximityEvent::Exited(id.clone()));
+            }
+            keep
+        });
+        exited
+    }
+
+    /// Starts scanning, and returns the events for the advertisements the central receives.
+    /// Whether peripherals have gone is checked four times per timeout.
+    pub async fn watch<C: Central + 'static>(
+        self,
+        central: &C,
+    ) -> Result<Pin<Box<dyn Stream<Item = ProximityEvent> + Send>>> {
+        let events = central.events().await?;
+        central.start_scan(ScanFilter::default()).await?;
+        let period = self.thresholds.timeout / 4;
+        let ticks = stream::unfold((), move |()| async move {
+            rt::sleep(period).await;
+            Some((None, ()))
+        });
+        let monitor = Arc::new(Mutex::new(self));
+        let central = central.clone();
+        let inputs = stream::select(events.map(Some), ticks);
+        Ok(Box::pin(
+            inputs
+                .then(move |event| {
+                    let central = central.clone();
+                    let monitor = monitor.clone();
+                    async move {
+                        let id = match event {
+                            None => return monitor.lock().unwrap().expire(Instant::now()),
+                            Some(CentralEvent::DeviceDiscovered(id))
+                            | Some(CentralEvent::DeviceUpdated(id)) => id,
+                            Some(_) => return vec![],
+                        };
+                        let properties = match central.peripheral(&id).await {
+                            Ok(peripheral) => peripheral.properties().await.ok().flatten(),
+                            Err(_) => None,
+                        };
+                        properties
+                            .and_then(|properties| {
+                                monitor
+                                    .lock()
+                                    .unwrap()
+                                    .update(&id, &properties, Instant::now())
+                            })
+                            .into_iter()
+                            .collect()
+                    }
+                })
+                .flat_map(stream::iter),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reference_distance() {
+        assert!((estimate_distance(4, -37) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn calibrated_model() {
+        let model = PathLossModel::calibrated(-12, -59, 2.0);
+        assert_eq!(model.reference_loss, 47.0);
+        assert!((model.estimate_distance(-12, -79) - 10.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn properties_without_tx_power() {
+        let properties = PeripheralProperties {
+            rssi: Some(-60),
+            ..Default::default()
+        };
+        assert_eq!(properties.path_loss(), None);
+        assert_eq!(properties.estimate_distance(&PathLossModel::INDOOR), None);
+
+        let properties = PeripheralProperties {
+            tx_power_level: Some(0),
+            ..properties
+        };
+        assert_eq!(properties.path_loss(), Some(60));
+    }
+
+    #[test]
+    fn classify() {
+        let thresholds = ZoneThresholds::default();
+        assert_eq!(
+            thresholds.classify(0.55, ProximityZone::Gone),
+            ProximityZone::Near
+        );
+        assert_eq!(
+            thresholds.classify(0.55, ProximityZone::Immediate),
+            ProximityZone::Immediate
+        );
+        assert_eq!(
+            thresholds.classify(0.45, ProximityZone::Near),
+            ProximityZone::Near
+        );
+        assert_eq!(
+            thresholds.classify(0.35, ProximityZone::Far),
+            ProximityZone::Immediate
+        );
+        assert_eq!(
+            thresholds.classify(3.3, ProximityZone::Near),
+            ProximityZone::Near
+        );
+        assert_eq!(
+            thresholds.classify(2.7, ProximityZone::Far),
+            ProximityZone::Far
+        );
+        assert_eq!(
+            thresholds.classify(2.3, ProximityZone::Far),
+            ProximityZone::Near
+        );
+    }
+
+    // Peripheral IDs can only be made up with the mock backend.
+    #[cfg(feature = "mock")]
+    #[test]
+    fn proximity_monitor() {
+        let id = PeripheralId::synthetic("hci0", [1, 2, 3, 4, 5, 6].into());
+        let mut monitor = ProximityMonitor::new(ZoneThresholds {
+            model: PathLossModel::FREE_SPACE,
+            smoothing: 1.0,
+            ..Default::default()
+        });
+        let start = Instant::now();
+        // 1 metre, then 10.
+        let at = |rssi| PeripheralProperties {
+            rssi: Some(rssi),
+            ..Default::default()
+        };
+        assert_eq!(
+            monitor.update(&id, &at(-41), start),
+            Some(ProximityEvent::Entered {
+                id: id.clone(),
+                zone: ProximityZone::Near
+            })
+        );
+        assert_eq!(monitor.update(&id, &at(-42), start), None);
+        assert_eq!(
+            monitor.update(&id, &at(-61), start),
+            Some(ProximityEvent::ZoneChanged {
+                id: id.clone(),
+                from: ProximityZone::Near,
+                to: ProximityZone::Far
+            })
+        );
+        assert_eq!(monitor.zone(&id), ProximityZone::Far);
+
+        assert_eq!(monitor.expire(start + Duration::from_secs(5)), vec![]);
+        assert_eq!(
+            monitor.expire(start + Duration::from_secs(10)),
+            vec![ProximityEvent::Exited(id.clone())]
+        );
+        assert_eq!(monitor.zone(&id), ProximityZone::Gone);
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn watch() -> Result<()> {
+        use crate::mock::Manager;
+
+        let manager = Manager::new().await?;
+        let adapter = manager.add_adapter();
+        let mut events = ProximityMonitor::new(ZoneThresholds {
+            smoothing: 1.0,
+            ..Default::default()
+        })
+        .watch(&adapter)
+        .await?;
+        let mut properties = PeripheralProperties {
+            tx_power_level: Some(0),
+            rssi: Some(-45),
+            ..Default::default()
+        };
+        let tag = adapter.advertise(properties.clone()).unwrap();
+        assert_eq!(
+            events.next().await,
+            Some(ProximityEvent::Entered {
+                id: tag.id(),
+                zone: ProximityZone::Near
+            })
+        );
+        properties.rssi = Some(-90);
+        adapter.advertise(properties).unwrap();
+        assert_eq!(
+            events.next().await,
+            Some(ProximityEvent::ZoneChanged {
+                id: tag.id(),
+                from: ProximityZone::Near,
+                to: ProximityZone::Far
+            })
+        );
+        Ok(())
+    }
+}
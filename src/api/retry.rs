@@ -0,0 +1,504 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::{
+    link::LinkSample,
+    pairing::{OobData, PairingAgent},
+    rpa,
+    rssi::RssiStats,
+    AttError, BDAddr, BufferedNotifications, Characteristic, ClientConfiguration,
+    ConnectionPriority, DataLength, Descriptor, NativeError, NotificationBuffer, Peripheral,
+    PeripheralEvent, PeripheralProperties, SecurityLevel, Service, SubscriptionType, Transport,
+    ValueNotification, WriteType,
+};
+use crate::{platform::PeripheralId, Error, Result};
+use async_trait::async_trait;
+use futures::stream::Stream;
+use std::{collections::BTreeSet, future::Future, pin::Pin, sync::Arc, time::Duration};
+use uuid::Uuid;
+
+/// How long a [`Retrying`] peripheral waits before trying an operation again.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Backoff {
+    /// Wait the same time before every retry.
+    Fixed(Duration),
+    /// Wait `initial` before the first retry, and twice as long before each one after that, up to
+    /// `max`.
+    Exponential { initial: Duration, max: Duration },
+}
+
+impl Backoff {
+    /// Returns how long to wait before the given retry, counting from 1.
+    pub fn delay(&self, retry: u32) -> Duration {
+        match *self {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, max } => {
+                let factor = 1u32
+                    .checked_shl(retry.saturating_sub(1))
+                    .unwrap_or(u32::MAX);
+                initial.saturating_mul(factor).min(max)
+            }
+        }
+    }
+}
+
+/// When and how often a [`Retrying`] peripheral retries a failed operation.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// The maximum number of times to try an operation, including the first. 0 is treated as 1.
+    pub attempts: u32,
+    /// How long to wait between attempts.
+    pub backoff: Backoff,
+    /// Returns whether an operation which failed with the given error should be tried again. The
+    /// default is [`Error::is_transient`].
+    pub retry_on: fn(&Error) -> bool,
+    /// Whether to reconnect before retrying if the peripheral was disconnected during the
+    /// operation. Without this, an operation interrupted by a disconnection can't succeed on a
+    /// later attempt.
+    pub reconnect: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 3,
+            backoff: Backoff::Exponential {
+                initial: Duration::from_millis(100),
+                max: Duration::from_secs(2),
+            },
+            retry_on: Error::is_transient,
+            reconnect: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Runs `operation` until it succeeds, fails with an error which shouldn't be retried, or has
+    /// been tried `attempts` times. `prepare` is run before each retry, and an error from it counts
+    /// as a failed attempt.
+    async fn run<T, F, Fut, P, PFut>(&self, mut operation: F, mut prepare: P) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+        P: FnMut() -> PFut,
+        PFut: Future<Output = Result<()>>,
+    {
+        let attempts = self.attempts.max(1);
+        let mut attempt = 1;
+        loop {
+            let result = if attempt == 1 {
+                operation().await
+            } else {
+                match prepare().await {
+                    Ok(()) => operation().await,
+                    Err(error) => Err(error),
+                }
+            };
+            match result {
+                Err(error) if attempt < attempts && (self.retry_on)(&error) => {
+                    log::debug!("Retrying after attempt {} failed: {}", attempt, error);
+                    crate::rt::sleep(self.backoff.delay(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl Error {
+    /// Returns whether the operation which failed with this error may succeed if it is tried again,
+    /// because the failure was caused by a busy Bluetooth stack, a passing radio problem or the
+    /// device disconnecting during the operation, rather than by what was asked for.
+    ///
+    /// This is the default [`RetryPolicy::retry_on`].
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::NotConnected | Error::TimedOut { .. } => true,
+            Error::Att(error) => matches!(
+                error,
+                AttError::PrepareQueueFull
+                    | AttError::InsufficientResources
+                    | AttError::ProcedureAlreadyInProgress
+            ),
+            // Android reports a request which the busy stack wouldn't start as "Unable to ...", and
+            // a disconnection during the operation as "Disconnected while ...".
+            Error::RuntimeError(message) if cfg!(target_os = "android") => {
+                message.starts_with("Unable to") || message.starts_with("Disconnected while")
+            }
+            Error::Other(_) => self.native_error().is_some_and(NativeError::is_transient),
+            _ => false,
+        }
+    }
+}
+
+impl NativeError {
+    fn is_transient(&self) -> bool {
+        match self {
+            NativeError::Dbus { name, message } => match name.as_str() {
+                "org.bluez.Error.InProgress"
+                | "org.bluez.Error.NotReady"
+                | "org.bluez.Error.NotConnected"
+                | "org.freedesktop.DBus.Error.NoReply" => true,
+                "org.bluez.Error.Failed" => message.as_deref().is_some_and(|message| {
+                    [
+                        "le-connection-abort-by-local",
+                        "Software caused connection abort",
+                        "Not connected",
+                        "Operation already in progress",
+                    ]
+                    .iter()
+                    .any(|transient| message.contains(transient))
+                }),
+                _ => false,
+            },
+            // GattCommunicationStatus::Unreachable
+            NativeError::GattCommunicationStatus { status, .. } => *status == 1,
+            // ERROR_GEN_FAILURE and ERROR_DEVICE_NOT_AVAILABLE, which Windows returns while the
+            // device is briefly out of reach.
+            NativeError::HResult { code, .. } => {
+                *code == 0x8007001Fu32 as i32 || *code == 0x800710DFu32 as i32
+            }
+            // Failed pairings are better left to the application or user to retry.
+            NativeError::PairingStatus(_) => false,
+            // CBErrorNotConnected, CBErrorConnectionTimeout, CBErrorPeripheralDisconnected and
+            // CBErrorConnectionFailed.
+            NativeError::NSError { domain, code, .. } => {
+                domain == "CBErrorDomain" && matches!(code, 3 | 6 | 7 | 10)
+            }
+            // GATT_ERROR, which Android returns for most stack and link failures, and
+            // GATT_CONNECTION_CONGESTED.
+            NativeError::GattStatus(status) => matches!(status, 133 | 143),
+            // MGMT_STATUS_BUSY, which the kernel returns while another command is in progress.
+            NativeError::MgmtStatus(status) => *status == 0x0a,
+            // Command Disallowed, while another procedure on the link is in progress, and
+            // Controller Busy.
+            NativeError::HciStatus(status) => matches!(status, 0x0c | 0x3a),
+            // Which browsers throw when the link drops during an operation, or another operation
+            // is still in progress.
+            NativeError::DomException { name, .. } => name == "NetworkError",
+        }
+    }
+}
+
+/// A peripheral which retries GATT operations that fail with a transient error, as decided by its
+/// [`RetryPolicy`]. Create one with [`Peripheral::with_retry`].
+///
+/// Connecting, discovering services, reads, writes, subscribing and unsubscribing are retried;
+/// everything else is passed straight through. Retried writes may be received by the device more
+/// than once, if it handled the first one but the response was lost.
+#[derive(Clone, Debug)]
+pub struct Retrying<P> {
+    peripheral: P,
+    policy: RetryPolicy,
+}
+
+impl<P: Peripheral> Retrying<P> {
+    /// Wraps `peripheral`, retrying its operations according to `policy`.
+    pub fn new(peripheral: P, policy: RetryPolicy) -> Self {
+        Retrying { peripheral, policy }
+    }
+
+    /// Returns the policy operations are retried with.
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    /// Returns the wrapped peripheral, for calls which shouldn't be retried.
+    pub fn inner(&self) -> &P {
+        &self.peripheral
+    }
+
+    /// Returns the wrapped peripheral.
+    pub fn into_inner(self) -> P {
+        self.peripheral
+    }
+
+    async fn retry<T, F, Fut>(&self, operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        self.policy
+            .run(operation, || async {
+                if self.policy.reconnect && !self.peripheral.is_connected().await? {
+                    self.peripheral.connect().await?;
+                }
+                Ok(())
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl<P: Peripheral> Peripheral for Retrying<P> {
+    fn id(&self) -> PeripheralId {
+        self.peripheral.id()
+    }
+
+    fn address(&self) -> BDAddr {
+        self.peripheral.address()
+    }
+
+    async fn identity_address(&self, resolver: &rpa::Resolver) -> Result<Option<BDAddr>> {
+        self.peripheral.identity_address(resolver).await
+    }
+
+    async fn properties(&self) -> Result<Option<PeripheralProperties>> {
+        self.peripheral.properties().await
+    }
+
+    async fn refresh_properties(&self) -> Result<Option<PeripheralProperties>> {
+        self.retry(|| self.peripheral.refresh_properties()).await
+    }
+
+    async fn resolve_name(&self) -> Result<Option<String>> {
+        self.retry(|| self.peripheral.resolve_name()).await
+    }
+
+    fn services(&self) -> BTreeSet<Service> {
+        self.peripheral.services()
+    }
+
+    fn rssi_stats(&self) -> Option<RssiStats> {
+        self.peripheral.rssi_stats()
+    }
+
+    async fn is_connected(&self) -> Result<bool> {
+        self.peripheral.is_connected().await
+    }
+
+    async fn connect(&self) -> Result<()> {
+        self.policy
+            .run(|| self.peripheral.connect(), || async { Ok(()) })
+            .await
+    }
+
+    async fn connect_with_transport(&self, transport: Transport) -> Result<()> {
+        self.policy
+            .run(
+                || self.peripheral.connect_with_transport(transport),
+                || async { Ok(()) },
+            )
+            .await
+    }
+
+    async fn disconnect(&self) -> Result<()> {
+        self.peripheral.disconnect().await
+    }
+
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = PeripheralEvent> + Send>>> {
+        self.peripheral.events().await
+    }
+
+    async fn pair(&self) -> Result<()> {
+        self.peripheral.pair().await
+    }
+
+    async fn pair_with_agent(&self, agent: Arc<dyn PairingAgent>) -> Result<()> {
+        self.peripheral.pair_with_agent(agent).await
+    }
+
+    async fn pair_with_oob(&self, data: OobData) -> Result<()> {
+        self.peripheral.pair_with_oob(data).await
+    }
+
+    async fn security_level(&self) -> Result<SecurityLevel> {
+        self.peripheral.security_level().await
+    }
+
+    async fn require_security(&self, level: SecurityLevel) -> Result<()> {
+        self.peripheral.require_security(level).await
+    }
+
+    async fn require_security_with_agent(
+        &self,
+        level: SecurityLevel,
+        agent: Arc<dyn PairingAgent>,
+    ) -> Result<()> {
+        self.peripheral
+            .require_security_with_agent(level, agent)
+            .await
+    }
+
+    async fn request_mtu(&self, mtu: u16) -> Result<u16> {
+        self.retry(|| self.peripheral.request_mtu(mtu)).await
+    }
+
+    async fn request_connection_priority(&self, priority: ConnectionPriority) -> Result<()> {
+        self.retry(|| self.peripheral.request_connection_priority(priority))
+            .await
+    }
+
+    async fn link_events(
+        &self,
+        interval: Duration,
+    ) -> Result<Pin<Box<dyn Stream<Item = LinkSample> + Send>>> {
+        self.peripheral.link_events(interval).await
+    }
+
+    async fn request_data_length(&self, length: DataLength) -> Result<()> {
+        self.retry(|| self.peripheral.request_data_length(length))
+            .await
+    }
+
+    async fn discover_services(&self) -> Result<()> {
+        self.retry(|| self.peripheral.discover_services()).await
+    }
+
+    async fn discover_services_filtered(&self, uuids: &[Uuid]) -> Result<()> {
+        self.retry(|| self.peripheral.discover_services_filtered(uuids))
+            .await
+    }
+
+    async fn write(
+        &self,
+        characteristic: &Characteristic,
+        data: &[u8],
+        write_type: WriteType,
+    ) -> Result<()> {
+        self.retry(|| self.peripheral.write(characteristic, data, write_type))
+            .await
+    }
+
+    async fn read(&self, characteristic: &Characteristic) -> Result<Vec<u8>> {
+        self.retry(|| self.peripheral.read(characteristic)).await
+    }
+
+    async fn subscribe(&self, characteristic: &Characteristic) -> Result<()> {
+        self.retry(|| self.peripheral.subscribe(characteristic))
+            .await
+    }
+
+    async fn subscribe_with(
+        &self,
+        characteristic: &Characteristic,
+        subscription_type: SubscriptionType,
+    ) -> Result<()> {
+        self.retry(|| {
+            self.peripheral
+                .subscribe_with(characteristic, subscription_type)
+        })
+        .await
+    }
+
+    async fn unsubscribe(&self, characteristic: &Characteristic) -> Result<()> {
+        self.retry(|| self.peripheral.unsubscribe(characteristic))
+            .await
+    }
+
+    async fn read_client_configuration(
+        &self,
+        characteristic: &Characteristic,
+    ) -> Result<ClientConfiguration> {
+        self.retry(|| self.peripheral.read_client_configuration(characteristic))
+            .await
+    }
+
+    async fn notifications(&self) -> Result<Pin<Box<dyn Stream<Item = ValueNotification> + Send>>> {
+        self.peripheral.notifications().await
+    }
+
+    async fn notifications_buffered(
+        &self,
+        buffer: NotificationBuffer,
+    ) -> Result<BufferedNotifications> {
+        self.peripheral.notifications_buffered(buffer).await
+    }
+
+    async fn write_descriptor(&self, descriptor: &Descriptor, data: &[u8]) -> Result<()> {
+        self.retry(|| self.peripheral.write_descriptor(descriptor, data))
+            .await
+    }
+
+    async fn read_descriptor(&self, descriptor: &Descriptor) -> Result<Vec<u8>> {
+        self.retry(|| self.peripheral.read_descriptor(descriptor))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn policy(attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            attempts,
+            backoff: Backoff::Fixed(Duration::ZERO),
+            ..Default::default()
+        }
+    }
+
+    async fn run(policy: RetryPolicy, failures: u32, error: fn() -> Error) -> (Result<()>, u32) {
+        let calls = AtomicU32::new(0);
+        let result = policy
+            .run(
+                || async {
+                    if calls.fetch_add(1, Ordering::Relaxed) < failures {
+                        Err(error())
+                    } else {
+                        Ok(())
+                    }
+                },
+                || async { Ok(()) },
+            )
+            .await;
+        (result, calls.load(Ordering::Relaxed))
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors() {
+        let (result, calls) = run(policy(3), 2, || Error::NotConnected).await;
+        assert!(result.is_ok());
+        assert_eq!(calls, 3);
+
+        let (result, calls) = run(policy(3), 5, || Error::NotConnected).await;
+        assert!(matches!(result, Err(Error::NotConnected)));
+        assert_eq!(calls, 3);
+    }
+
+    #[tokio::test]
+    async fn doesnt_retry_other_errors() {
+        let (result, calls) = run(policy(3), 1, || Error::NoSuchCharacteristic).await;
+        assert!(matches!(result, Err(Error::NoSuchCharacteristic)));
+        assert_eq!(calls, 1);
+
+        let (result, calls) = run(policy(0), 1, || Error::NotConnected).await;
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        let backoff = Backoff::Exponential {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+        };
+        assert_eq!(backoff.delay(1), Duration::from_millis(100));
+        assert_eq!(backoff.delay(3), Duration::from_millis(400));
+        assert_eq!(backoff.delay(5), Duration::from_secs(1));
+        assert_eq!(backoff.delay(100), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn transient_native_errors() {
+        assert!(Error::Other(Box::new(NativeError::GattStatus(133))).is_transient());
+        assert!(!Error::Other(Box::new(NativeError::GattStatus(5))).is_transient());
+        assert!(Error::Other(Box::new(NativeError::Dbus {
+            name: "org.bluez.Error.InProgress".to_string(),
+            message: None,
+        }))
+        .is_transient());
+        assert!(Error::Other(Box::new(NativeError::DomException {
+            name: "NetworkError".to_string(),
+            message: "GATT operation already in progress.".to_string(),
+        }))
+        .is_transient());
+        assert!(!Error::Att(super::AttError::InsufficientAuthentication).is_transient());
+    }
+}
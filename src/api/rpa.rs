@@ -0,0 +1,192 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Resolution of resolvable private addresses (RPAs) to the identity of the device which is using
+//! them.
+//!
+//! Devices with privacy enabled advertise from an address which changes every few minutes. If you
+//! have the device's identity resolving key (IRK), for example from bonding with it or exchanged
+//! out of band, you can register it with a [`Resolver`] and then use [`Resolver::resolve`] or
+//! [`Peripheral::identity_address`](super::Peripheral::identity_address) to find out which device
+//! an address belongs to.
+//!
+//! ```
+//! use btleplug::api::{rpa, BDAddr};
+//!
+//! let resolver = rpa::Resolver::new();
+//! let irk: rpa::IdentityResolvingKey = "ec0234a357c8ad05341010a60a397d9b".parse().unwrap();
+//! let identity: BDAddr = "C0:11:22:33:44:55".parse().unwrap();
+//! resolver.register_irk(irk, identity);
+//!
+//! let rpa: BDAddr = "70:81:94:0D:FB:AA".parse().unwrap();
+//! assert_eq!(resolver.resolve(rpa), Some(identity));
+//! ```
+
+use super::{BDAddr, RandomAddressKind};
+use aes::cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit};
+use aes::Aes128;
+use std::fmt::{self, Debug, Formatter};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+/// A 128-bit identity resolving key (IRK), used to resolve the private addresses of a device.
+#[derive(Clone, Copy, Eq, PartialEq, Hash)]
+pub struct IdentityResolvingKey([u8; 16]);
+
+/// An error parsing an [`IdentityResolvingKey`] from a string.
+#[derive(Debug, thiserror::Error, Clone, PartialEq, Eq)]
+#[error("An identity resolving key must be 32 hex digits")]
+pub struct ParseIdentityResolvingKeyError;
+
+impl IdentityResolvingKey {
+    /// Creates a key from its bytes, most significant byte first, which is the order in which
+    /// keys are usually written.
+    pub fn from_bytes(bytes: [u8; 16]) -> Self {
+        Self(bytes)
+    }
+
+    /// Returns the bytes of the key, most significant byte first.
+    pub fn to_bytes(&self) -> [u8; 16] {
+        self.0
+    }
+
+    /// Returns whether `address` is a resolvable private address generated from this key.
+    pub fn resolves(&self, address: BDAddr) -> bool {
+        if address.random_address_kind() != Some(RandomAddressKind::Resolvable) {
+            return false;
+        }
+        // The address is made up of a 24-bit random part (prand) and a 24-bit hash of it.
+        let address = address.into_inner();
+        let (prand, hash) = address.split_at(3);
+        self.ah(prand.try_into().unwrap()) == hash
+    }
+
+    /// The random address hash function `ah` from the Core Specification, Vol 3, Part H, 2.2.2.
+    fn ah(&self, prand: [u8; 3]) -> [u8; 3] {
+        let cipher = Aes128::new(GenericArray::from_slice(&self.0));
+        let mut block = GenericArray::from([0; 16]);
+        block[13..].copy_from_slice(&prand);
+        cipher.encrypt_block(&mut block);
+        block[13..].try_into().unwrap()
+    }
+}
+
+impl Debug for IdentityResolvingKey {
+    // Don't print the key itself, so it doesn't end up in logs.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("IdentityResolvingKey(..)")
+    }
+}
+
+impl FromStr for IdentityResolvingKey {
+    type Err = ParseIdentityResolvingKeyError;
+
+    /// Parses a key from 32 hex digits, most significant byte first.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 32 || !s.is_ascii() {
+            return Err(ParseIdentityResolvingKeyError);
+        }
+        let mut bytes = [0; 16];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16)
+                .map_err(|_| ParseIdentityResolvingKeyError)?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+/// The IRKs of a set of devices, each with the device's identity address, which resolves the
+/// private addresses of those devices. Clones share the same keys, so an application can keep one
+/// per adapter, or share one between them.
+#[derive(Clone, Debug, Default)]
+pub struct Resolver {
+    keys: Arc<RwLock<Vec<(IdentityResolvingKey, BDAddr)>>>,
+}
+
+impl Resolver {
+    /// Creates a resolver with no keys.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the IRK of the device with the given identity address, so that its resolvable
+    /// private addresses can be resolved. Registering a new key for the same identity replaces
+    /// the old one.
+    pub fn register_irk(&self, irk: IdentityResolvingKey, identity_address: BDAddr) {
+        let mut keys = self.keys.write().unwrap();
+        keys.retain(|(_, identity)| *identity != identity_address);
+        keys.push((irk, identity_address));
+    }
+
+    /// Removes the IRK registered for the given identity address, if any.
+    pub fn unregister_irk(&self, identity_address: BDAddr) {
+        self.keys
+            .write()
+            .unwrap()
+            .retain(|(_, identity)| *identity != identity_address);
+    }
+
+    /// Returns the identity address of the device using the resolvable private address
+    /// `address`, if its IRK has been registered with [`register_irk`](Self::register_irk).
+    pub fn resolve(&self, address: BDAddr) -> Option<BDAddr> {
+        self.keys
+            .read()
+            .unwrap()
+            .iter()
+            .find(|(irk, _)| irk.resolves(address))
+            .map(|(_, identity)| *identity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Sample data from the Core Specification, Vol 3, Part H, D.7.
+    const IRK: [u8; 16] = [
+        0xec, 0x02, 0x34, 0xa3, 0x57, 0xc8, 0xad, 0x05, 0x34, 0x10, 0x10, 0xa6, 0x0a, 0x39, 0x7d,
+        0x9b,
+    ];
+
+    #[test]
+    fn ah_sample_data() {
+        let irk = IdentityResolvingKey::from_bytes(IRK);
+        assert_eq!(irk.ah([0x70, 0x81, 0x94]), [0x0d, 0xfb, 0xaa]);
+    }
+
+    #[test]
+    fn resolves_only_matching_rpas() {
+        let irk = IdentityResolvingKey::from_bytes(IRK);
+        assert!(irk.resolves(BDAddr::from([0x70, 0x81, 0x94, 0x0d, 0xfb, 0xaa])));
+        assert!(!irk.resolves(BDAddr::from([0x70, 0x81, 0x94, 0x0d, 0xfb, 0xab])));
+        // Same hash, but the top bits don't mark it as resolvable.
+        assert!(!irk.resolves(BDAddr::from([0xf0, 0x81, 0x94, 0x0d, 0xfb, 0xaa])));
+    }
+
+    #[test]
+    fn resolver_keys_are_separate() {
+        let rpa = BDAddr::from([0x70, 0x81, 0x94, 0x0d, 0xfb, 0xaa]);
+        let identity = BDAddr::from([0xc0, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        let resolver = Resolver::new();
+        let other = Resolver::new();
+        resolver.register_irk(IdentityResolvingKey::from_bytes(IRK), identity);
+        assert_eq!(resolver.clone().resolve(rpa), Some(identity));
+        assert_eq!(other.resolve(rpa), None);
+        resolver.unregister_irk(identity);
+        assert_eq!(resolver.resolve(rpa), None);
+    }
+
+    #[test]
+    fn parse_key() {
+        let irk: IdentityResolvingKey = "ec0234a357c8ad05341010a60a397d9b".parse().unwrap();
+        assert_eq!(irk.to_bytes(), IRK);
+        assert_eq!(
+            "ec0234a357c8ad05".parse::<IdentityResolvingKey>(),
+            Err(ParseIdentityResolvingKeyError)
+        );
+    }
+}
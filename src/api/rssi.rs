@@ -0,0 +1,255 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Smoothing the RSSI of peripherals' advertisements, which varies by several dB from one
+//! advertisement to the next even when nothing moves.
+//!
+//! Tracking is off by default, as it keeps samples for every peripheral the adapter sees. Once it
+//! is turned on with [`Central::set_rssi_tracking`](super::Central::set_rssi_tracking), the RSSI of
+//! each advertisement is recorded, and
+//! [`Peripheral::rssi_stats`](super::Peripheral::rssi_stats) returns its moving average and median
+//! along with the most recent samples.
+//!
+//! ```no_run
+//! use btleplug::api::rssi::RssiTracking;
+//! use btleplug::api::{Central, Peripheral};
+//!
+//! async fn smoothed(central: &impl Central) -> btleplug::Result<()> {
+//!     central.set_rssi_tracking(Some(RssiTracking::default()));
+//!     for peripheral in central.peripherals().await? {
+//!         if let Some(stats) = peripheral.rssi_stats() {
+//!             println!("{}: {:.1} dBm", peripheral.id(), stats.median);
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use crate::platform::PeripheralId;
+use crate::time::SystemTime;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use serde_cr as serde;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+/// How RSSI is tracked once it's turned on with
+/// [`Central::set_rssi_tracking`](super::Central::set_rssi_tracking).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RssiTracking {
+    /// How many of each peripheral's most recent samples are kept, for the median and the history.
+    /// At least one is kept.
+    pub window: usize,
+    /// How much each sample counts towards the exponentially weighted moving average, from 0 to 1.
+    /// Higher values follow changes more quickly, but smooth less.
+    pub smoothing: f64,
+}
+
+impl Default for RssiTracking {
+    fn default() -> Self {
+        RssiTracking {
+            window: 10,
+            smoothing: 0.2,
+        }
+    }
+}
+
+/// The RSSI of one advertisement.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RssiSample {
+    /// In dBm.
+    pub rssi: i16,
+    pub time: SystemTime,
+}
+
+/// A peripheral's RSSI since tracking was turned on, as returned by
+/// [`Peripheral::rssi_stats`](super::Peripheral::rssi_stats). All values are in dBm.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct RssiStats {
+    /// The most recent sample.
+    pub latest: i16,
+    /// The exponentially weighted moving average of all the samples.
+    pub average: f64,
+    /// The median of the samples in the window, which ignores the occasional outlier.
+    pub median: f64,
+    /// The weakest sample in the window.
+    pub min: i16,
+    /// The strongest sample in the window.
+    pub max: i16,
+    /// The samples in the window, oldest first.
+    pub samples: Vec<RssiSample>,
+}
+
+#[derive(Debug)]
+struct Tracker {
+    average: f64,
+    samples: VecDeque<RssiSample>,
+}
+
+impl Tracker {
+    fn stats(&self) -> Option<RssiStats> {
+        let latest = self.samples.back()?.rssi;
+        let mut sorted: Vec<i16> = self.samples.iter().map(|sample| sample.rssi).collect();
+        sorted.sort_unstable();
+        // The two middle samples are the same one if there's an odd number.
+        let len = sorted.len();
+        let median = (f64::from(sorted[(len - 1) / 2]) + f64::from(sorted[len / 2])) / 2.0;
+        Some(RssiStats {
+            latest,
+            average: self.average,
+            median,
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            samples: self.samples.iter().copied().collect(),
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    tracking: Option<RssiTracking>,
+    peripherals: HashMap<PeripheralId, Tracker>,
+    /// Whether a backend which has to listen for RSSI changes itself is doing so.
+    listening: bool,
+}
+
+/// The RSSI samples of an adapter's peripherals, which it shares with them through its
+/// [`OperationContext`](super::operation::OperationContext).
+#[derive(Debug, Default)]
+pub(crate) struct RssiTrackers {
+    state: Mutex<State>,
+}
+
+impl RssiTrackers {
+    /// Turns tracking on or changes how it's done, or turns it off and forgets all the samples
+    /// with `None`.
+    pub(crate) fn set_tracking(&self, tracking: Option<RssiTracking>) {
+        let mut state = self.state.lock().unwrap();
+        if tracking.is_none() {
+            state.peripherals.clear();
+        }
+        state.tracking = tracking;
+    }
+
+    /// Records the RSSI of an advertisement from the peripheral, if tracking is on.
+    pub(crate) fn record(&self, id: &PeripheralId, rssi: i16) {
+        let mut state = self.state.lock().unwrap();
+        let Some(tracking) = state.tracking else {
+            return;
+        };
+        let sample = RssiSample {
+            rssi,
+            time: SystemTime::now(),
+        };
+        let tracker = state
+            .peripherals
+            .entry(id.clone())
+            .or_insert_with(|| Tracker {
+                average: f64::from(rssi),
+                samples: VecDeque::new(),
+            });
+        tracker.average += tracking.smoothing * (f64::from(rssi) - tracker.average);
+        tracker.samples.push_back(sample);
+        while tracker.samples.len() > tracking.window.max(1) {
+            tracker.samples.pop_front();
+        }
+    }
+
+    pub(crate) fn stats(&self, id: &PeripheralId) -> Option<RssiStats> {
+        self.state.lock().unwrap().peripherals.get(id)?.stats()
+    }
+
+    /// Returns whether the caller should start listening for RSSI changes, because tracking is on
+    /// and nothing else is. For backends which aren't told about every advertisement otherwise.
+    // Only used by some backends, so turn off deadcode so we don't get warnings on the other platforms.
+    #[allow(dead_code)]
+    pub(crate) fn start_listening(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let start = state.tracking.is_some() && !state.listening;
+        state.listening |= start;
+        start
+    }
+
+    /// Returns whether the listener started after [`start_listening`](Self::start_listening)
+    /// should carry on, because tracking is still on.
+    #[allow(dead_code)]
+    pub(crate) fn keep_listening(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.listening = state.tracking.is_some();
+        state.listening
+    }
+
+    /// Says that the listener stopped by itself, e.g. because what it listened to went away.
+    #[allow(dead_code)]
+    pub(crate) fn stop_listening(&self) {
+        self.state.lock().unwrap().listening = false;
+    }
+}
+
+// Peripheral IDs can only be made up with the mock backend.
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::api::{Central, Peripheral, PeripheralProperties, ScanFilter};
+    use crate::mock::Manager;
+
+    #[test]
+    fn stats() {
+        let trackers = RssiTrackers::default();
+        let id = PeripheralId::synthetic("hci0", [1, 2, 3, 4, 5, 6].into());
+        trackers.record(&id, -60);
+        assert_eq!(trackers.stats(&id), None);
+
+        trackers.set_tracking(Some(RssiTracking {
+            window: 3,
+            smoothing: 0.5,
+        }));
+        for rssi in [-60, -70, -50, -80] {
+            trackers.record(&id, rssi);
+        }
+        let stats = trackers.stats(&id).unwrap();
+        assert_eq!(stats.latest, -80);
+        assert_eq!(stats.average, -68.75);
+        assert_eq!(stats.median, -70.0);
+        assert_eq!((stats.min, stats.max), (-80, -50));
+        let samples: Vec<i16> = stats.samples.iter().map(|sample| sample.rssi).collect();
+        assert_eq!(samples, vec![-70, -50, -80]);
+
+        trackers.set_tracking(None);
+        assert_eq!(trackers.stats(&id), None);
+    }
+
+    #[tokio::test]
+    async fn rssi_stats() -> crate::Result<()> {
+        let manager = Manager::new().await?;
+        let adapter = manager.add_adapter();
+        adapter.start_scan(ScanFilter::default()).await?;
+        adapter.set_rssi_tracking(Some(RssiTracking::default()));
+        let mut properties = PeripheralProperties::default();
+        for rssi in [-60, -62] {
+            properties.rssi = Some(rssi);
+            adapter.advertise(properties.clone());
+        }
+        let device = adapter.peripherals().await?.remove(0);
+        let stats = device.rssi_stats().unwrap();
+        assert_eq!(stats.median, -61.0);
+        assert_eq!(stats.samples.len(), 2);
+        Ok(())
+    }
+}
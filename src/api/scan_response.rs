@@ -0,0 +1,218 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Putting advertisements back together with their scan responses, for backends which receive
+//! them separately, so that [`PeripheralProperties`] are only updated once per advertising event.
+
+use super::{AdvertisementType, BDAddr, PeripheralProperties, ScanResponseParts};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// The reports which make up one advertising event of a device, to be applied to its properties
+/// together.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct AdvertisingEvent<T> {
+    /// `None` if the scan response came without an advertisement we were waiting on, e.g. because
+    /// the advertisement came before the scan started.
+    pub(crate) advertisement: Option<T>,
+    pub(crate) scan_response: Option<T>,
+}
+
+/// Holds back each scannable advertisement until the scan response to it arrives. If the device
+/// advertises again first, the scan response was lost, and the advertisement is passed on alone.
+// Only used by some backends, so turn off deadcode so we don't get warnings on the other platforms.
+#[allow(dead_code)]
+#[derive(Debug)]
+pub(crate) struct ScanResponses<T> {
+    pending: Mutex<HashMap<BDAddr, T>>,
+}
+
+impl<T> Default for ScanResponses<T> {
+    fn default() -> Self {
+        ScanResponses {
+            pending: Default::default(),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<T> ScanResponses<T> {
+    /// Takes a report from the device with the given address, and returns the advertising events
+    /// which are complete, oldest first.
+    pub(crate) fn receive(
+        &self,
+        address: BDAddr,
+        advertisement_type: Option<AdvertisementType>,
+        report: T,
+    ) -> Vec<AdvertisingEvent<T>> {
+        let mut pending = self.pending.lock().unwrap();
+        let held = pending.remove(&address);
+        if advertisement_type == Some(AdvertisementType::ScanResponse) {
+            return vec![AdvertisingEvent {
+                advertisement: held,
+                scan_response: Some(report),
+            }];
+        }
+        let mut events: Vec<_> = held
+            .map(|advertisement| AdvertisingEvent {
+                advertisement: Some(advertisement),
+                scan_response: None,
+            })
+            .into_iter()
+            .collect();
+        if advertisement_type.is_some_and(|kind| kind.is_scannable()) {
+            pending.insert(address, report);
+        } else {
+            events.push(AdvertisingEvent {
+                advertisement: Some(report),
+                scan_response: None,
+            });
+        }
+        events
+    }
+}
+
+#[allow(dead_code)]
+impl ScanResponseParts {
+    /// Returns which values the properties of a scan response contain.
+    pub(crate) fn of(response: &PeripheralProperties) -> Self {
+        ScanResponseParts {
+            received: Some(true),
+            local_name: response.local_name.is_some(),
+            tx_power_level: response.tx_power_level.is_some(),
+            appearance: response.appearance.is_some(),
+            manufacturer_data: response.manufacturer_data.keys().copied().collect(),
+            service_data: response.service_data.keys().copied().collect(),
+            services: response.services.clone(),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl PeripheralProperties {
+    /// Adds the values in the properties of a scan response to these, which came from the
+    /// advertisement it responded to, and records which values came from it.
+    pub(crate) fn apply_scan_response(&mut self, response: PeripheralProperties) {
+        self.scan_response = ScanResponseParts::of(&response);
+        if response.local_name.is_some() {
+            self.local_name = response.local_name;
+        }
+        if response.tx_power_level.is_some() {
+            self.tx_power_level = response.tx_power_level;
+        }
+        if response.appearance.is_some() {
+            self.appearance = response.appearance;
+        }
+        if response.rssi.is_some() {
+            self.rssi = response.rssi;
+        }
+        if response.last_seen.is_some() {
+            self.last_seen = response.last_seen;
+        }
+        self.manufacturer_data.extend(response.manufacturer_data);
+        self.service_data.extend(response.service_data);
+        for uuid in response.services {
+            if !self.services.contains(&uuid) {
+                self.services.push(uuid);
+            }
+        }
+        if let Some(data) = response.advertising_data {
+            self.advertising_data = Some(match self.advertising_data.take() {
+                Some(advertisement) => [&advertisement[..], &data[..]].concat().into(),
+                None => data,
+            });
+        }
+        self.advertisement_type = Some(AdvertisementType::ScanResponse);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::bleuuid::uuid_from_u16;
+
+    fn event(advertisement: Option<u8>, scan_response: Option<u8>) -> AdvertisingEvent<u8> {
+        AdvertisingEvent {
+            advertisement,
+            scan_response,
+        }
+    }
+
+    #[test]
+    fn receive() {
+        let address: BDAddr = [1, 2, 3, 4, 5, 6].into();
+        let responses = ScanResponses::default();
+        let scannable = Some(AdvertisementType::ConnectableUndirected);
+        let response = Some(AdvertisementType::ScanResponse);
+
+        assert_eq!(responses.receive(address, scannable, 1), vec![]);
+        assert_eq!(
+            responses.receive(address, response, 2),
+            vec![event(Some(1), Some(2))]
+        );
+        // The scan response to this one is lost.
+        assert_eq!(responses.receive(address, scannable, 3), vec![]);
+        assert_eq!(
+            responses.receive(address, scannable, 4),
+            vec![event(Some(3), None)]
+        );
+        assert_eq!(
+            responses.receive(
+                address,
+                Some(AdvertisementType::NonConnectableUndirected),
+                5
+            ),
+            vec![event(Some(4), None), event(Some(5), None)]
+        );
+        assert_eq!(
+            responses.receive(address, response, 6),
+            vec![event(None, Some(6))]
+        );
+        // Devices are kept apart.
+        assert_eq!(responses.receive(address, scannable, 7), vec![]);
+        assert_eq!(
+            responses.receive(BDAddr::default(), None, 8),
+            vec![event(Some(8), None)]
+        );
+    }
+
+    #[test]
+    fn apply_scan_response() {
+        let mut properties = PeripheralProperties {
+            services: vec![uuid_from_u16(0x180f)],
+            tx_power_level: Some(-4),
+            advertisement_type: Some(AdvertisementType::ConnectableUndirected),
+            advertising_data: Some(vec![0x02, 0x01, 0x06].into()),
+            ..Default::default()
+        };
+        properties.manufacturer_data.insert(0x004c, vec![1].into());
+        let mut response = PeripheralProperties {
+            local_name: Some("Sensor".to_string()),
+            advertising_data: Some(vec![0x02, 0x09, b'S'].into()),
+            services: vec![uuid_from_u16(0x180f), uuid_from_u16(0x181a)],
+            ..Default::default()
+        };
+        response.manufacturer_data.insert(0x0059, vec![2].into());
+
+        properties.apply_scan_response(response);
+        assert_eq!(properties.local_name.as_deref(), Some("Sensor"));
+        assert_eq!(properties.tx_power_level, Some(-4));
+        assert_eq!(properties.manufacturer_data.len(), 2);
+        assert_eq!(
+            properties.services,
+            vec![uuid_from_u16(0x180f), uuid_from_u16(0x181a)]
+        );
+        assert_eq!(properties.scan_response.received, Some(true));
+        assert!(properties.scan_response.local_name);
+        assert!(!properties.scan_response.tx_power_level);
+        assert_eq!(properties.scan_response.manufacturer_data, vec![0x0059]);
+        assert_eq!(
+            properties.advertising_data,
+            Some(vec![0x02, 0x01, 0x06, 0x02, 0x09, b'S'].into())
+        );
+    }
+}
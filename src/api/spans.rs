@@ -0,0 +1,201 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! `tracing` spans around each operation, and around what the backends do with each callback from
+//! the platform's Bluetooth stack, with the `tracing` feature. Without it, these do nothing, so
+//! the backends can use them unconditionally.
+
+use crate::Error;
+use std::{fmt::Display, future::Future, time::Duration};
+use uuid::Uuid;
+
+/// The target of the spans of operations, which is also that of their log records.
+#[cfg(feature = "tracing")]
+const OPERATION_TARGET: &str = "btleplug::operation";
+
+/// The target of the spans of callbacks.
+#[cfg(feature = "tracing")]
+const CALLBACK_TARGET: &str = "btleplug::callback";
+
+/// A future run in a [`Span`].
+#[cfg(feature = "tracing")]
+pub(crate) type Instrumented<F> = tracing::instrument::Instrumented<F>;
+#[cfg(not(feature = "tracing"))]
+pub(crate) type Instrumented<F> = F;
+
+/// A `tracing` span, or nothing without the `tracing` feature.
+#[derive(Clone, Debug)]
+pub(crate) struct Span {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+/// Returns the span of a connection or GATT operation on the given peripheral and attribute. Its
+/// `duration_ms`, `outcome` and `status` are recorded with [`Span::record_outcome`] once it
+/// finishes.
+#[allow(unused_variables)]
+pub(crate) fn operation(
+    operation: &'static str,
+    peripheral: Option<&dyn Display>,
+    attribute: Option<Uuid>,
+) -> Span {
+    Span {
+        #[cfg(feature = "tracing")]
+        span: tracing::debug_span!(
+            target: OPERATION_TARGET,
+            "operation",
+            operation,
+            peripheral = peripheral.map(tracing::field::display),
+            attribute = attribute.map(tracing::field::display),
+            duration_ms = tracing::field::Empty,
+            outcome = tracing::field::Empty,
+            status = tracing::field::Empty,
+        ),
+    }
+}
+
+/// Returns the span of a callback from the platform's Bluetooth stack, such as a notification, an
+/// advertisement or a connection event, about the given peripheral if it is about one.
+#[allow(unused_variables)]
+pub(crate) fn callback(callback: &'static str, peripheral: Option<&dyn Display>) -> Span {
+    Span {
+        #[cfg(feature = "tracing")]
+        span: tracing::debug_span!(
+            target: CALLBACK_TARGET,
+            "callback",
+            callback,
+            peripheral = peripheral.map(tracing::field::display),
+        ),
+    }
+}
+
+impl Span {
+    /// Runs `function` in the span.
+    pub(crate) fn in_scope<T>(&self, function: impl FnOnce() -> T) -> T {
+        #[cfg(feature = "tracing")]
+        {
+            self.span.in_scope(function)
+        }
+        #[cfg(not(feature = "tracing"))]
+        {
+            function()
+        }
+    }
+
+    /// Returns `future`, run in the span each time it is polled.
+    pub(crate) fn instrument<F: Future>(&self, future: F) -> Instrumented<F> {
+        #[cfg(feature = "tracing")]
+        {
+            tracing::Instrument::instrument(future, self.span.clone())
+        }
+        #[cfg(not(feature = "tracing"))]
+        {
+            future
+        }
+    }
+
+    /// Records how long an operation took and how it finished.
+    #[allow(unused_variables)]
+    pub(crate) fn record_outcome(&self, duration: Duration, error: Option<&Error>) {
+        #[cfg(feature = "tracing")]
+        {
+            self.span.record("duration_ms", duration.as_millis() as u64);
+            match error {
+                None => {
+                    self.span.record("outcome", "ok");
+                }
+                Some(error) => {
+                    self.span.record("outcome", tracing::field::display(error));
+                    if let Some(status) = error.native_error() {
+                        self.span.record("status", tracing::field::display(status));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+        subscriber::Interest,
+        Metadata, Subscriber,
+    };
+
+    /// Collects the fields of every span as strings.
+    #[derive(Default)]
+    struct Fields(Arc<Mutex<Vec<(String, String)>>>);
+
+    impl Visit for &Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .lock()
+                .unwrap()
+                .push((field.name().to_string(), format!("{:?}", value)));
+        }
+    }
+
+    impl Subscriber for Fields {
+        fn register_callsite(&self, _: &'static Metadata<'static>) -> Interest {
+            Interest::always()
+        }
+
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            span.record(&mut &*self);
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _: &Id, values: &Record<'_>) {
+            values.record(&mut &*self);
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, _: &tracing::Event<'_>) {}
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn records_operation() {
+        let fields = Fields::default();
+        let recorded = fields.0.clone();
+        tracing::subscriber::with_default(fields, || {
+            let span = operation("read", Some(&"peripheral"), Some(Uuid::nil()));
+            span.in_scope(|| {});
+            span.record_outcome(Duration::from_millis(12), Some(&Error::NotConnected));
+        });
+        let recorded = recorded.lock().unwrap();
+        let field = |name: &str| {
+            recorded
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value.as_str())
+        };
+        assert_eq!(field("operation"), Some("\"read\""));
+        assert_eq!(field("peripheral"), Some("peripheral"));
+        assert_eq!(
+            field("attribute"),
+            Some("00000000-0000-0000-0000-000000000000")
+        );
+        assert_eq!(field("duration_ms"), Some("12"));
+        assert_eq!(
+            field("outcome"),
+            Some(Error::NotConnected.to_string().as_str())
+        );
+    }
+}
@@ -0,0 +1,146 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! The Battery Service, through which a peripheral reports how charged its battery is.
+//!
+//! ```no_run
+//! use btleplug::api::standard::battery::Battery as _;
+//! use futures::StreamExt;
+//! # use btleplug::api::Peripheral;
+//! # async fn watch(peripheral: impl Peripheral) -> btleplug::Result<()> {
+//! peripheral.connect().await?;
+//! println!("Battery at {}%", peripheral.battery_level().await?);
+//! let mut levels = peripheral.battery_level_stream().await?;
+//! while let Some(level) = levels.next().await {
+//!     println!("Battery at {}%", level);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use super::find_characteristic;
+use crate::api::{bleuuid::uuid_from_u16, Peripheral};
+use crate::{Error, Result};
+use async_trait::async_trait;
+use futures::stream::{self, Stream, StreamExt};
+use std::pin::Pin;
+use uuid::Uuid;
+
+/// The UUID of the Battery Service.
+pub const BATTERY_SERVICE_UUID: Uuid = uuid_from_u16(0x180F);
+
+/// The UUID of the Battery Level characteristic, whose value is the charge as a percentage.
+pub const BATTERY_LEVEL_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2A19);
+
+fn parse_level(value: &[u8]) -> Result<u8> {
+    value
+        .first()
+        .copied()
+        .ok_or_else(|| Error::Other("Battery Level value is empty".into()))
+}
+
+/// Reads the battery level of a connected peripheral. The Battery Service is discovered if it
+/// hasn't been yet, and [`Error::NoSuchCharacteristic`] is returned if the peripheral doesn't have
+/// one.
+#[async_trait]
+pub trait Battery: Peripheral {
+    /// Reads the battery level, as a percentage.
+    async fn battery_level(&self) -> Result<u8> {
+        let characteristic = find_characteristic(
+            self,
+            BATTERY_SERVICE_UUID,
+            BATTERY_LEVEL_CHARACTERISTIC_UUID,
+        )
+        .await?;
+        parse_level(&self.read(&characteristic).await?)
+    }
+
+    /// Subscribes to the battery level, and returns a stream of it which starts with the current
+    /// level and then has each level the peripheral notifies. Fails with
+    /// [`Error::NotSupported`] if the peripheral doesn't notify its battery level; poll
+    /// [`battery_level`](Self::battery_level) instead.
+    ///
+    /// Dropping the stream doesn't unsubscribe.
+    async fn battery_level_stream(&self) -> Result<Pin<Box<dyn Stream<Item = u8> + Send>>> {
+        let characteristic = find_characteristic(
+            self,
+            BATTERY_SERVICE_UUID,
+            BATTERY_LEVEL_CHARACTERISTIC_UUID,
+        )
+        .await?;
+        let updates = self.subscribe_stream(&characteristic).await?;
+        let current = parse_level(&self.read(&characteristic).await?)?;
+        Ok(Box::pin(stream::once(async move { current }).chain(
+            updates.filter_map(|value| async move { parse_level(&value).ok() }),
+        )))
+    }
+}
+
+impl<P: Peripheral> Battery for P {}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::api::{
+        Central, CharPropFlags, Characteristic, Descriptor, ScanFilter, Service,
+        CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
+    };
+    use crate::mock::Manager;
+
+    #[tokio::test]
+    async fn battery_level() -> Result<()> {
+        let manager = Manager::new().await?;
+        let adapter = manager.add_adapter();
+        adapter.start_scan(ScanFilter::default()).await?;
+        let level = Characteristic {
+            uuid: BATTERY_LEVEL_CHARACTERISTIC_UUID,
+            service_uuid: BATTERY_SERVICE_UUID,
+            properties: CharPropFlags::READ | CharPropFlags::NOTIFY,
+            descriptors: [Descriptor {
+                uuid: CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
+                service_uuid: BATTERY_SERVICE_UUID,
+                characteristic_uuid: BATTERY_LEVEL_CHARACTERISTIC_UUID,
+                handle: None,
+                value: None,
+            }]
+            .into(),
+            handle: None,
+            value_handle: None,
+        };
+        let device = adapter.advertise(Default::default()).unwrap();
+        device.set_services([Service {
+            uuid: BATTERY_SERVICE_UUID,
+            primary: true,
+            characteristics: [level.clone()].into(),
+            included_services: Default::default(),
+            handle: None,
+        }]);
+        device.set_value(&level, [87]);
+        device.connect().await?;
+
+        assert_eq!(device.battery_level().await?, 87);
+        let mut levels = device.battery_level_stream().await?;
+        assert_eq!(levels.next().await, Some(87));
+        assert!(device.notify(&level, [86]));
+        assert_eq!(levels.next().await, Some(86));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn no_battery_service() -> Result<()> {
+        let manager = Manager::new().await?;
+        let adapter = manager.add_adapter();
+        adapter.start_scan(ScanFilter::default()).await?;
+        let device = adapter.advertise(Default::default()).unwrap();
+        device.connect().await?;
+        assert!(matches!(
+            device.battery_level().await,
+            Err(Error::NoSuchCharacteristic)
+        ));
+        Ok(())
+    }
+}
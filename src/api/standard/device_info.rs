@@ -0,0 +1,209 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! The Device Information Service, through which a peripheral says who made it and what hardware
+//! and firmware it has.
+//!
+//! ```no_run
+//! use btleplug::api::standard::device_info::DeviceInfo as _;
+//! # use btleplug::api::Peripheral;
+//! # async fn print(peripheral: impl Peripheral) -> btleplug::Result<()> {
+//! peripheral.connect().await?;
+//! let info = peripheral.device_information().await?;
+//! println!(
+//!     "{} {}, firmware {}",
+//!     info.manufacturer_name.unwrap_or_default(),
+//!     info.model_number.unwrap_or_default(),
+//!     info.firmware_revision.unwrap_or_default(),
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use super::{decode_string, service_characteristics};
+use crate::api::{bleuuid::uuid_from_u16, Peripheral};
+use crate::Result;
+use async_trait::async_trait;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use serde_cr as serde;
+use uuid::Uuid;
+
+/// The UUID of the Device Information Service.
+pub const DEVICE_INFORMATION_SERVICE_UUID: Uuid = uuid_from_u16(0x180A);
+
+pub const MANUFACTURER_NAME_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2A29);
+pub const MODEL_NUMBER_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2A24);
+pub const SERIAL_NUMBER_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2A25);
+pub const HARDWARE_REVISION_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2A27);
+pub const FIRMWARE_REVISION_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2A26);
+pub const SOFTWARE_REVISION_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2A28);
+pub const PNP_ID_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x2A50);
+
+/// What the Device Information Service of a peripheral says about it. Each field is `None` if the
+/// peripheral doesn't have the characteristic for it.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DeviceInformation {
+    pub manufacturer_name: Option<String>,
+    pub model_number: Option<String>,
+    pub serial_number: Option<String>,
+    pub hardware_revision: Option<String>,
+    pub firmware_revision: Option<String>,
+    pub software_revision: Option<String>,
+    pub pnp_id: Option<PnpId>,
+}
+
+/// Who assigned the vendor ID of a [`PnpId`].
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VendorIdSource {
+    /// The vendor ID is a company identifier assigned by the Bluetooth SIG.
+    Bluetooth,
+    /// The vendor ID was assigned by the USB Implementers Forum.
+    Usb,
+    /// A value which the specification reserves.
+    Other(u8),
+}
+
+/// The vendor, product and version IDs a peripheral identifies itself with, as used for
+/// plug-and-play, e.g. by HID hosts.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PnpId {
+    pub vendor_id_source: VendorIdSource,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub product_version: u16,
+}
+
+impl PnpId {
+    /// Parses the value of a PnP ID characteristic, or returns `None` if it's too short.
+    pub fn from_bytes(value: &[u8]) -> Option<PnpId> {
+        let [source, vendor_low, vendor_high, product_low, product_high, version_low, version_high, ..] =
+            *value
+        else {
+            return None;
+        };
+        Some(PnpId {
+            vendor_id_source: match source {
+                1 => VendorIdSource::Bluetooth,
+                2 => VendorIdSource::Usb,
+                other => VendorIdSource::Other(other),
+            },
+            vendor_id: u16::from_le_bytes([vendor_low, vendor_high]),
+            product_id: u16::from_le_bytes([product_low, product_high]),
+            product_version: u16::from_le_bytes([version_low, version_high]),
+        })
+    }
+}
+
+/// Reads the Device Information Service of a connected peripheral.
+#[async_trait]
+pub trait DeviceInfo: Peripheral {
+    /// Reads every characteristic of the Device Information Service the peripheral has. The
+    /// service is discovered if it hasn't been yet, and
+    /// [`Error::NoSuchCharacteristic`](crate::Error::NoSuchCharacteristic) is returned if the
+    /// peripheral doesn't have one.
+    async fn device_information(&self) -> Result<DeviceInformation> {
+        let mut info = DeviceInformation::default();
+        for characteristic in service_characteristics(self, DEVICE_INFORMATION_SERVICE_UUID).await?
+        {
+            let field = match characteristic.uuid {
+                MANUFACTURER_NAME_CHARACTERISTIC_UUID => &mut info.manufacturer_name,
+                MODEL_NUMBER_CHARACTERISTIC_UUID => &mut info.model_number,
+                SERIAL_NUMBER_CHARACTERISTIC_UUID => &mut info.serial_number,
+                HARDWARE_REVISION_CHARACTERISTIC_UUID => &mut info.hardware_revision,
+                FIRMWARE_REVISION_CHARACTERISTIC_UUID => &mut info.firmware_revision,
+                SOFTWARE_REVISION_CHARACTERISTIC_UUID => &mut info.software_revision,
+                PNP_ID_CHARACTERISTIC_UUID => {
+                    info.pnp_id = PnpId::from_bytes(&self.read(&characteristic).await?);
+                    continue;
+                }
+                _ => continue,
+            };
+            *field = Some(decode_string(&self.read(&characteristic).await?));
+        }
+        Ok(info)
+    }
+}
+
+impl<P: Peripheral> DeviceInfo for P {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pnp_id() {
+        assert_eq!(
+            PnpId::from_bytes(&[0x02, 0x6d, 0x04, 0x1c, 0xc5, 0x01, 0x02]),
+            Some(PnpId {
+                vendor_id_source: VendorIdSource::Usb,
+                vendor_id: 0x046d,
+                product_id: 0xc51c,
+                product_version: 0x0201,
+            })
+        );
+        assert_eq!(PnpId::from_bytes(&[0x01, 0x6d, 0x04]), None);
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn device_information() -> Result<()> {
+        use crate::api::{Central, CharPropFlags, Characteristic, ScanFilter, Service};
+        use crate::mock::Manager;
+
+        let manager = Manager::new().await?;
+        let adapter = manager.add_adapter();
+        adapter.start_scan(ScanFilter::default()).await?;
+        let characteristic = |uuid| Characteristic {
+            uuid,
+            service_uuid: DEVICE_INFORMATION_SERVICE_UUID,
+            properties: CharPropFlags::READ,
+            descriptors: Default::default(),
+            handle: None,
+            value_handle: None,
+        };
+        let manufacturer = characteristic(MANUFACTURER_NAME_CHARACTERISTIC_UUID);
+        let firmware = characteristic(FIRMWARE_REVISION_CHARACTERISTIC_UUID);
+        let pnp_id = characteristic(PNP_ID_CHARACTERISTIC_UUID);
+        let device = adapter.advertise(Default::default()).unwrap();
+        device.set_services([Service {
+            uuid: DEVICE_INFORMATION_SERVICE_UUID,
+            primary: true,
+            characteristics: [manufacturer.clone(), firmware.clone(), pnp_id.clone()].into(),
+            included_services: Default::default(),
+            handle: None,
+        }]);
+        device.set_value(&manufacturer, *b"Acme\0");
+        device.set_value(&firmware, *b"1.2.3");
+        device.set_value(&pnp_id, [0x01, 0x59, 0x00, 0x01, 0x00, 0x03, 0x00]);
+        device.connect().await?;
+
+        let info = device.device_information().await?;
+        assert_eq!(info.manufacturer_name.as_deref(), Some("Acme"));
+        assert_eq!(info.firmware_revision.as_deref(), Some("1.2.3"));
+        assert_eq!(info.model_number, None);
+        assert_eq!(info.pnp_id.unwrap().vendor_id, 0x0059);
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Helpers for services defined by the Bluetooth SIG, built on the [`Peripheral`] trait. Each
+//! module has an extension trait which is implemented for every peripheral, so bringing it into
+//! scope is enough to use it.
+
+pub mod battery;
+pub mod device_info;
+pub mod presentation;
+
+use super::{Characteristic, Peripheral};
+use crate::{Error, Result};
+use uuid::Uuid;
+
+/// Returns the characteristics of the service with the given UUID, discovering the service first
+/// if it hasn't been yet. Fails with [`Error::NoSuchCharacteristic`] if the peripheral has no such
+/// service, or it has no characteristics.
+pub(crate) async fn service_characteristics<P: Peripheral>(
+    peripheral: &P,
+    service_uuid: Uuid,
+) -> Result<Vec<Characteristic>> {
+    let find = || -> Vec<Characteristic> {
+        peripheral
+            .characteristics()
+            .into_iter()
+            .filter(|characteristic| characteristic.service_uuid == service_uuid)
+            .collect()
+    };
+    let mut characteristics = find();
+    if characteristics.is_empty() {
+        peripheral
+            .discover_services_filtered(&[service_uuid])
+            .await?;
+        characteristics = find();
+    }
+    if characteristics.is_empty() {
+        return Err(Error::NoSuchCharacteristic);
+    }
+    Ok(characteristics)
+}
+
+/// Finds the characteristic with the given UUID in the given service, discovering the service
+/// first if it hasn't been yet.
+pub(crate) async fn find_characteristic<P: Peripheral>(
+    peripheral: &P,
+    service_uuid: Uuid,
+    characteristic_uuid: Uuid,
+) -> Result<Characteristic> {
+    service_characteristics(peripheral, service_uuid)
+        .await?
+        .into_iter()
+        .find(|characteristic| characteristic.uuid == characteristic_uuid)
+        .ok_or(Error::NoSuchCharacteristic)
+}
+
+/// Decodes a UTF-8 string characteristic value. Some devices pad their strings with NULs, which
+/// are removed.
+pub(crate) fn decode_string(value: &[u8]) -> String {
+    String::from_utf8_lossy(value)
+        .trim_end_matches('\0')
+        .to_string()
+}
@@ -0,0 +1,374 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! The descriptors which say what a characteristic is for and how to show its value: the
+//! Characteristic User Description, a name for people, and the Characteristic Presentation Format,
+//! which gives the value's type, unit and base 10 exponent. A generic GATT browser can use them to
+//! show characteristics it knows nothing else about.
+//!
+//! ```no_run
+//! use btleplug::api::standard::presentation::Presentation as _;
+//! # use btleplug::api::Peripheral;
+//! # async fn show(peripheral: impl Peripheral) -> btleplug::Result<()> {
+//! for characteristic in peripheral.characteristics() {
+//!     let name = peripheral.user_description(&characteristic).await?;
+//!     let value = peripheral.read(&characteristic).await?;
+//!     match peripheral.presentation_format(&characteristic).await? {
+//!         Some(format) => match format.decode_number(&value) {
+//!             Some(number) => println!("{:?}: {} (unit {:#06x})", name, number, format.unit),
+//!             None => println!("{:?}: {:?}", name, value),
+//!         },
+//!         None => println!("{:?}: {:?}", name, value),
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use super::decode_string;
+use crate::api::{bleuuid::uuid_from_u16, Characteristic, Peripheral};
+use crate::{Error, Result};
+use async_trait::async_trait;
+use uuid::Uuid;
+
+/// The UUID of the Characteristic User Description descriptor, whose value is a UTF-8 name for the
+/// characteristic.
+pub const CHARACTERISTIC_USER_DESCRIPTION_UUID: Uuid = uuid_from_u16(0x2901);
+
+/// The UUID of the Characteristic Presentation Format descriptor.
+pub const CHARACTERISTIC_PRESENTATION_FORMAT_UUID: Uuid = uuid_from_u16(0x2904);
+
+/// The type of a characteristic's value, from the Bluetooth SIG's assigned numbers. Integers are
+/// little-endian.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Format {
+    Boolean,
+    /// An unsigned integer of the given number of bits, from 2 to 128.
+    Unsigned(u8),
+    /// A signed integer of the given number of bits, from 8 to 128.
+    Signed(u8),
+    Float32,
+    Float64,
+    /// An IEEE 11073-20601 16-bit SFLOAT.
+    Sfloat,
+    /// An IEEE 11073-20601 32-bit FLOAT.
+    Float,
+    /// Two unsigned 16-bit integers.
+    Duint16,
+    Utf8,
+    Utf16,
+    /// A structure the characteristic's specification defines.
+    Struct,
+    MedicalAsn1,
+    Other(u8),
+}
+
+impl From<u8> for Format {
+    fn from(value: u8) -> Self {
+        match value {
+            0x01 => Format::Boolean,
+            0x02 => Format::Unsigned(2),
+            0x03 => Format::Unsigned(4),
+            0x04 => Format::Unsigned(8),
+            0x05 => Format::Unsigned(12),
+            0x06 => Format::Unsigned(16),
+            0x07 => Format::Unsigned(24),
+            0x08 => Format::Unsigned(32),
+            0x09 => Format::Unsigned(48),
+            0x0a => Format::Unsigned(64),
+            0x0b => Format::Unsigned(128),
+            0x0c => Format::Signed(8),
+            0x0d => Format::Signed(12),
+            0x0e => Format::Signed(16),
+            0x0f => Format::Signed(24),
+            0x10 => Format::Signed(32),
+            0x11 => Format::Signed(48),
+            0x12 => Format::Signed(64),
+            0x13 => Format::Signed(128),
+            0x14 => Format::Float32,
+            0x15 => Format::Float64,
+            0x16 => Format::Sfloat,
+            0x17 => Format::Float,
+            0x18 => Format::Duint16,
+            0x19 => Format::Utf8,
+            0x1a => Format::Utf16,
+            0x1b => Format::Struct,
+            0x1c => Format::MedicalAsn1,
+            other => Format::Other(other),
+        }
+    }
+}
+
+/// The value of a Characteristic Presentation Format descriptor.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct PresentationFormat {
+    pub format: Format,
+    /// The base 10 exponent integer values are scaled by, so that a value of 1234 with an exponent
+    /// of -2 means 12.34.
+    pub exponent: i8,
+    /// The unit, as the 16-bit UUID the Bluetooth SIG assigned it, such as `0x272f` for degrees
+    /// Celsius.
+    pub unit: u16,
+    /// The organization which defined `description`. 1 is the Bluetooth SIG.
+    pub namespace: u8,
+    /// Which of several characteristics of the same type this one is, such as `0x010d` for
+    /// "left" in the Bluetooth SIG's namespace.
+    pub description: u16,
+}
+
+impl PresentationFormat {
+    /// Parses the value of a Characteristic Presentation Format descriptor, or returns `None` if
+    /// it's too short.
+    pub fn from_bytes(value: &[u8]) -> Option<PresentationFormat> {
+        let [format, exponent, unit_low, unit_high, namespace, description_low, description_high, ..] =
+            *value
+        else {
+            return None;
+        };
+        Some(PresentationFormat {
+            format: format.into(),
+            exponent: exponent as i8,
+            unit: u16::from_le_bytes([unit_low, unit_high]),
+            namespace,
+            description: u16::from_le_bytes([description_low, description_high]),
+        })
+    }
+
+    /// Decodes a characteristic value of a numeric format, applying the exponent to integers.
+    /// Returns `None` for other formats, or if the value is too short. Integers of more than 53
+    /// bits may lose precision.
+    pub fn decode_number(&self, value: &[u8]) -> Option<f64> {
+        let scale = |number: f64| number * 10f64.powi(i32::from(self.exponent));
+        match self.format {
+            Format::Boolean => Some(f64::from(*value.first()? & 1)),
+            Format::Unsigned(bits) => Some(scale(unsigned(value, bits)? as f64)),
+            Format::Signed(bits) => {
+                // Sign-extend from the given number of bits.
+                let shift = 128 - u32::from(bits);
+                let number = ((unsigned(value, bits)? << shift) as i128) >> shift;
+                Some(scale(number as f64))
+            }
+            Format::Float32 => Some(f64::from(f32::from_le_bytes(
+                value.get(..4)?.try_into().unwrap(),
+            ))),
+            Format::Float64 => Some(f64::from_le_bytes(value.get(..8)?.try_into().unwrap())),
+            Format::Sfloat => Some(ieee11073_sfloat(u16::from_le_bytes(
+                value.get(..2)?.try_into().unwrap(),
+            ))),
+            Format::Float => Some(ieee11073_float(u32::from_le_bytes(
+                value.get(..4)?.try_into().unwrap(),
+            ))),
+            _ => None,
+        }
+    }
+}
+
+/// Reads a little-endian unsigned integer of the given number of bits, which take up whole bytes
+/// except for 2, 4 and 12 bit integers.
+fn unsigned(value: &[u8], bits: u8) -> Option<u128> {
+    let bytes = value.get(..usize::from(bits).div_ceil(8))?;
+    let number = bytes
+        .iter()
+        .rev()
+        .fold(0u128, |number, &byte| number << 8 | u128::from(byte));
+    Some(number & (u128::MAX >> (128 - u32::from(bits))))
+}
+
+/// Decodes an IEEE 11073-20601 16-bit SFLOAT, which has a 12-bit mantissa and a 4-bit base 10
+/// exponent.
+pub(crate) fn ieee11073_sfloat(value: u16) -> f64 {
+    match value {
+        0x07FE => f64::INFINITY,
+        0x0802 => f64::NEG_INFINITY,
+        // NaN, not at this resolution, and reserved.
+        0x07FF..=0x0801 => f64::NAN,
+        _ => {
+            // Sign-extend the mantissa from 12 bits and the exponent from 4.
+            let mantissa = ((value << 4) as i16) >> 4;
+            let exponent = (value as i16) >> 12;
+            f64::from(mantissa) * 10f64.powi(i32::from(exponent))
+        }
+    }
+}
+
+/// Decodes an IEEE 11073-20601 32-bit FLOAT, which has a 24-bit mantissa and an 8-bit base 10
+/// exponent.
+pub(crate) fn ieee11073_float(value: u32) -> f64 {
+    match value {
+        0x007F_FFFE => f64::INFINITY,
+        0x0080_0002 => f64::NEG_INFINITY,
+        // NaN, not at this resolution, and reserved.
+        0x007F_FFFF..=0x0080_0001 => f64::NAN,
+        _ => {
+            // Sign-extend the mantissa from 24 bits.
+            let mantissa = ((value << 8) as i32) >> 8;
+            let exponent = (value >> 24) as i8;
+            f64::from(mantissa) * 10f64.powi(i32::from(exponent))
+        }
+    }
+}
+
+/// Reads the user description and presentation format of a connected peripheral's
+/// characteristics. These don't change, so each descriptor is only read the first time, with
+/// [`Peripheral::read_descriptor_cached`].
+#[async_trait]
+pub trait Presentation: Peripheral {
+    /// Reads the characteristic's user description, or returns `None` if it doesn't have one.
+    async fn user_description(&self, characteristic: &Characteristic) -> Result<Option<String>> {
+        match characteristic.descriptor(CHARACTERISTIC_USER_DESCRIPTION_UUID) {
+            Some(descriptor) => Ok(Some(decode_string(
+                &self.read_descriptor_cached(descriptor).await?,
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the characteristic's presentation format, or returns `None` if it doesn't have one.
+    ///
+    /// A characteristic whose value is made of several fields has a Characteristic Aggregate
+    /// Format descriptor listing a presentation format for each instead, which this doesn't read.
+    async fn presentation_format(
+        &self,
+        characteristic: &Characteristic,
+    ) -> Result<Option<PresentationFormat>> {
+        let Some(descriptor) = characteristic.descriptor(CHARACTERISTIC_PRESENTATION_FORMAT_UUID)
+        else {
+            return Ok(None);
+        };
+        PresentationFormat::from_bytes(&self.read_descriptor_cached(descriptor).await?)
+            .map(Some)
+            .ok_or_else(|| {
+                Error::Other("Characteristic Presentation Format value is too short".into())
+            })
+    }
+}
+
+impl<P: Peripheral> Presentation for P {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode() {
+        // A temperature in hundredths of a degree Celsius.
+        let format =
+            PresentationFormat::from_bytes(&[0x0e, 0xfe, 0x2f, 0x27, 0x01, 0x00, 0x00]).unwrap();
+        assert_eq!(
+            format,
+            PresentationFormat {
+                format: Format::Signed(16),
+                exponent: -2,
+                unit: 0x272f,
+                namespace: 1,
+                description: 0,
+            }
+        );
+        assert_eq!(
+            format.decode_number(&(-1234i16).to_le_bytes()),
+            Some(-12.34)
+        );
+        assert_eq!(format.decode_number(&[0x01]), None);
+        assert_eq!(PresentationFormat::from_bytes(&[0x0e, 0xfe]), None);
+
+        let format = |format: Format| PresentationFormat {
+            format,
+            exponent: 0,
+            unit: 0x2700,
+            namespace: 0,
+            description: 0,
+        };
+        assert_eq!(
+            format(Format::Unsigned(24)).decode_number(&[1, 2, 3]),
+            Some(197121.0)
+        );
+        assert_eq!(
+            format(Format::Signed(12)).decode_number(&[0xff, 0x0f]),
+            Some(-1.0)
+        );
+        assert_eq!(
+            format(Format::Unsigned(4)).decode_number(&[0xfa]),
+            Some(10.0)
+        );
+        assert_eq!(format(Format::Boolean).decode_number(&[1]), Some(1.0));
+        assert_eq!(
+            format(Format::Float32).decode_number(&1.5f32.to_le_bytes()),
+            Some(1.5)
+        );
+        // 36.5 as 365 * 10^-1.
+        assert_eq!(
+            format(Format::Sfloat).decode_number(&[0x6d, 0xf1]),
+            Some(36.5)
+        );
+        assert!(format(Format::Sfloat)
+            .decode_number(&[0xff, 0x07])
+            .unwrap()
+            .is_nan());
+        assert_eq!(format(Format::Utf8).decode_number(b"36.5"), None);
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn read_descriptors() -> Result<()> {
+        use crate::api::{Central, CharPropFlags, Descriptor, ScanFilter, Service};
+        use crate::mock::Manager;
+
+        let manager = Manager::new().await?;
+        let adapter = manager.add_adapter();
+        adapter.start_scan(ScanFilter::default()).await?;
+        let service_uuid = uuid_from_u16(0x181a);
+        let characteristic_uuid = uuid_from_u16(0x2a6e);
+        let descriptor = |uuid| Descriptor {
+            uuid,
+            service_uuid,
+            characteristic_uuid,
+            handle: None,
+            value: None,
+        };
+        let temperature = Characteristic {
+            uuid: characteristic_uuid,
+            service_uuid,
+            properties: CharPropFlags::READ,
+            descriptors: [
+                descriptor(CHARACTERISTIC_USER_DESCRIPTION_UUID),
+                descriptor(CHARACTERISTIC_PRESENTATION_FORMAT_UUID),
+            ]
+            .into(),
+            handle: None,
+            value_handle: None,
+        };
+        let device = adapter.advertise(Default::default()).unwrap();
+        device.set_services([Service {
+            uuid: service_uuid,
+            primary: true,
+            characteristics: [temperature.clone()].into(),
+            included_services: Default::default(),
+            handle: None,
+        }]);
+        device.set_descriptor_value(
+            &descriptor(CHARACTERISTIC_USER_DESCRIPTION_UUID),
+            *b"Outside\0",
+        );
+        device.set_descriptor_value(
+            &descriptor(CHARACTERISTIC_PRESENTATION_FORMAT_UUID),
+            [0x0e, 0xfe, 0x2f, 0x27, 0x01, 0x00, 0x00],
+        );
+        device.connect().await?;
+        device.discover_services().await?;
+
+        assert_eq!(
+            device.user_description(&temperature).await?,
+            Some("Outside".to_string())
+        );
+        let format = device.presentation_format(&temperature).await?.unwrap();
+        assert_eq!(format.unit, 0x272f);
+        let mut plain = temperature.clone();
+        plain.descriptors.clear();
+        assert_eq!(device.presentation_format(&plain).await?, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,319 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! A byte stream over a pair of characteristics, as used for serial ports over Bluetooth LE.
+//!
+//! Nordic's UART Service (NUS) is the most common such service, and is what a [`UartStream`]
+//! uses by default; [`UartConfig`] can give the UUIDs of others which work the same way. Data
+//! written to the stream is split into writes to the RX characteristic, and notifications from the
+//! TX characteristic are read from it.
+//!
+//! ```no_run
+//! use btleplug::api::uart::{UartConfig, UartStream};
+//! use futures::io::{AsyncReadExt, AsyncWriteExt};
+//! # use btleplug::api::Peripheral;
+//! # async fn talk(peripheral: impl Peripheral + 'static) -> Result<(), Box<dyn std::error::Error>> {
+//! peripheral.connect().await?;
+//! let mut uart = UartStream::new(peripheral, UartConfig::default()).await?;
+//! uart.write_all(b"hello\n").await?;
+//! let mut reply = [0; 64];
+//! let len = uart.read(&mut reply).await?;
+//! println!("{:?}", &reply[..len]);
+//! # Ok(())
+//! # }
+//! ```
+
+use super::{
+    negotiate_write_len, standard::find_characteristic, CharPropFlags, Characteristic, Peripheral,
+    WriteType,
+};
+use crate::{Error, Result};
+use futures::{
+    io::{AsyncRead, AsyncWrite},
+    stream::Stream,
+    FutureExt, StreamExt,
+};
+use std::{
+    fmt::{self, Debug, Formatter},
+    future::Future,
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+use uuid::Uuid;
+
+/// The UUID of Nordic's UART Service.
+pub const NUS_SERVICE_UUID: Uuid = Uuid::from_u128(0x6e400001_b5a3_f393_e0a9_e50e24dcca9e);
+
+/// The UUID of the NUS RX characteristic, which the central writes to.
+pub const NUS_RX_CHARACTERISTIC_UUID: Uuid =
+    Uuid::from_u128(0x6e400002_b5a3_f393_e0a9_e50e24dcca9e);
+
+/// The UUID of the NUS TX characteristic, which the peripheral notifies.
+pub const NUS_TX_CHARACTERISTIC_UUID: Uuid =
+    Uuid::from_u128(0x6e400003_b5a3_f393_e0a9_e50e24dcca9e);
+
+/// Which characteristics a [`UartStream`] uses, and how it writes to them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UartConfig {
+    pub service_uuid: Uuid,
+    /// The characteristic written to.
+    pub rx_uuid: Uuid,
+    /// The characteristic which notifies what the peripheral sends.
+    pub tx_uuid: Uuid,
+    /// The most bytes to write at once. If this is `None`, the largest MTU the platform allows is
+    /// asked for where that's supported, and otherwise the 20 bytes the default MTU allows are
+    /// used.
+    pub max_write_len: Option<usize>,
+    /// How to write. If this is `None`, writes are without response if the RX characteristic
+    /// allows it, with the platform holding back each one until it can be sent. Writing with
+    /// response is slower, but confirms that each write arrived.
+    pub write_type: Option<WriteType>,
+}
+
+impl Default for UartConfig {
+    fn default() -> Self {
+        UartConfig {
+            service_uuid: NUS_SERVICE_UUID,
+            rx_uuid: NUS_RX_CHARACTERISTIC_UUID,
+            tx_uuid: NUS_TX_CHARACTERISTIC_UUID,
+            max_write_len: None,
+            write_type: None,
+        }
+    }
+}
+
+type WriteFuture = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+
+/// A duplex byte stream to a connected peripheral, implementing [`AsyncRead`] and [`AsyncWrite`].
+///
+/// Writes are sent one at a time, so a write finishing means the previous one has been sent;
+/// [`flush`](futures::io::AsyncWriteExt::flush) waits for the last. Reading never reaches the end
+/// of the stream, even if the peripheral disconnects, but writes then fail with
+/// [`io::ErrorKind::NotConnected`]. Anything notified before the stream is read is buffered.
+pub struct UartStream<P> {
+    peripheral: P,
+    rx: Characteristic,
+    write_type: WriteType,
+    max_write_len: usize,
+    incoming: Pin<Box<dyn Stream<Item = Vec<u8>> + Send>>,
+    /// What has been received but not read yet, from `read_offset`.
+    read_buffer: Vec<u8>,
+    read_offset: usize,
+    pending_write: Option<WriteFuture>,
+}
+
+impl<P: Peripheral + 'static> UartStream<P> {
+    /// Finds the service's characteristics on a connected peripheral, discovering the service if
+    /// it hasn't been yet, and subscribes to the TX characteristic.
+    pub async fn new(peripheral: P, config: UartConfig) -> Result<UartStream<P>> {
+        let rx = find_characteristic(&peripheral, config.service_uuid, config.rx_uuid).await?;
+        let tx = find_characteristic(&peripheral, config.service_uuid, config.tx_uuid).await?;
+        let write_type = config.write_type.unwrap_or(
+            if rx
+                .properties
+                .contains(CharPropFlags::WRITE_WITHOUT_RESPONSE)
+            {
+                WriteType::WithoutResponse
+            } else {
+                WriteType::WithResponse
+            },
+        );
+        let max_write_len = match config.max_write_len {
+            Some(len) => len.max(1),
+            None => negotiate_write_len(&peripheral).await,
+        };
+        let incoming = peripheral.subscribe_stream(&tx).await?;
+        Ok(UartStream {
+            peripheral,
+            rx,
+            write_type,
+            max_write_len,
+            incoming,
+            read_buffer: Vec::new(),
+            read_offset: 0,
+            pending_write: None,
+        })
+    }
+
+    /// The peripheral the stream is to.
+    pub fn peripheral(&self) -> &P {
+        &self.peripheral
+    }
+
+    /// The most bytes which are written at once.
+    pub fn max_write_len(&self) -> usize {
+        self.max_write_len
+    }
+
+    /// Waits for the write in progress, if there is one, to finish.
+    fn poll_pending_write(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(write) = &mut self.pending_write {
+            let result = ready!(write.poll_unpin(cx));
+            self.pending_write = None;
+            result.map_err(to_io_error)?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+// Nothing is pinned in place; the futures are boxed.
+impl<P> Unpin for UartStream<P> {}
+
+fn to_io_error(error: Error) -> io::Error {
+    match error {
+        Error::NotConnected => io::Error::new(io::ErrorKind::NotConnected, error),
+        Error::TimedOut { .. } => io::Error::new(io::ErrorKind::TimedOut, error),
+        error => io::Error::other(error),
+    }
+}
+
+impl<P: Peripheral + 'static> AsyncRead for UartStream<P> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        while this.read_offset == this.read_buffer.len() {
+            match ready!(this.incoming.poll_next_unpin(cx)) {
+                Some(value) => {
+                    this.read_buffer = value;
+                    this.read_offset = 0;
+                }
+                None => return Poll::Ready(Ok(0)),
+            }
+        }
+        let available = &this.read_buffer[this.read_offset..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        this.read_offset += len;
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl<P: Peripheral + 'static> AsyncWrite for UartStream<P> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending_write(cx))?;
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let chunk = buf[..buf.len().min(this.max_write_len)].to_vec();
+        let len = chunk.len();
+        let peripheral = this.peripheral.clone();
+        let rx = this.rx.clone();
+        let write_type = this.write_type;
+        this.pending_write = Some(Box::pin(async move {
+            peripheral.write(&rx, &chunk, write_type).await
+        }));
+        // Start the write straight away, rather than on the next call.
+        if let Poll::Ready(Err(e)) = this.poll_pending_write(cx) {
+            return Poll::Ready(Err(e));
+        }
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_pending_write(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_pending_write(cx)
+    }
+}
+
+impl<P: Debug> Debug for UartStream<P> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("UartStream")
+            .field("peripheral", &self.peripheral)
+            .field("rx", &self.rx.uuid)
+            .field("write_type", &self.write_type)
+            .field("max_write_len", &self.max_write_len)
+            .finish()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::api::{
+        Central, Descriptor, ScanFilter, Service, CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
+    };
+    use crate::mock::Manager;
+    use futures::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn read_and_write() -> Result<()> {
+        let manager = Manager::new().await?;
+        let adapter = manager.add_adapter();
+        adapter.start_scan(ScanFilter::default()).await?;
+        let rx = Characteristic {
+            uuid: NUS_RX_CHARACTERISTIC_UUID,
+            service_uuid: NUS_SERVICE_UUID,
+            properties: CharPropFlags::WRITE | CharPropFlags::WRITE_WITHOUT_RESPONSE,
+            descriptors: Default::default(),
+            handle: None,
+            value_handle: None,
+        };
+        let tx = Characteristic {
+            uuid: NUS_TX_CHARACTERISTIC_UUID,
+            service_uuid: NUS_SERVICE_UUID,
+            properties: CharPropFlags::NOTIFY,
+            descriptors: [Descriptor {
+                uuid: CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
+                service_uuid: NUS_SERVICE_UUID,
+                characteristic_uuid: NUS_TX_CHARACTERISTIC_UUID,
+                handle: None,
+                value: None,
+            }]
+            .into(),
+            handle: None,
+            value_handle: None,
+        };
+        let device = adapter.advertise(Default::default()).unwrap();
+        device.set_services([Service {
+            uuid: NUS_SERVICE_UUID,
+            primary: true,
+            characteristics: [rx.clone(), tx.clone()].into(),
+            included_services: Default::default(),
+            handle: None,
+        }]);
+        device.connect().await?;
+
+        let mut uart = UartStream::new(
+            device.clone(),
+            UartConfig {
+                max_write_len: Some(4),
+                ..Default::default()
+            },
+        )
+        .await?;
+        assert_eq!(uart.write(b"hello world").await.unwrap(), 4);
+        uart.write_all(b"hello world").await.unwrap();
+        uart.flush().await.unwrap();
+        // The last write has what was left after the whole chunks.
+        assert_eq!(device.value(&rx), Some(b"rld".to_vec()));
+
+        assert!(device.notify(&tx, *b"abc"));
+        assert!(device.notify(&tx, *b"de"));
+        let mut received = [0; 4];
+        uart.read_exact(&mut received).await.unwrap();
+        assert_eq!(&received, b"abcd");
+        assert_eq!(uart.read(&mut received).await.unwrap(), 1);
+        assert_eq!(received[0], b'e');
+
+        device.simulate_disconnect();
+        let error = uart.write_all(b"!").await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotConnected);
+        Ok(())
+    }
+}
@@ -0,0 +1,322 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use btleplug::api::bleuuid::{uuid_from_u16, uuid_from_u32, BleUuid};
+use btleplug::api::{BDAddr, CentralEvent, CharPropFlags, Characteristic, ScanFilter, WriteType};
+use btleplug::blocking::{Adapter, Manager, Peripheral};
+use std::{
+    env,
+    error::Error,
+    process,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+const USAGE: &str = "\
+Usage: btleplug [options] <command>
+
+Commands:
+  scan                                   List devices as they are discovered
+  connect <device>                       Connect to a device and list its services
+  gatt dump <device>                     Print a device's services, characteristics and
+                                         descriptors, and the value of each readable
+                                         characteristic
+  read <device> <characteristic>         Read a characteristic
+  write <device> <characteristic> <hex>  Write a characteristic
+  subscribe <device> <characteristic>    Print notifications from a characteristic
+
+<device> is a Bluetooth address, a peripheral ID or a local name. <characteristic> is a UUID,
+either in full or as a 16- or 32-bit short UUID such as 2a19.
+
+Options:
+  --adapter <index>    Use the adapter with this index, rather than the first
+  --timeout <seconds>  How long to scan for, or to look for <device> [default: 5]
+  --without-response   Write without response
+  --signed             Write without response, signed with the key from bonding
+  -h, --help           Print this message
+";
+
+enum Command {
+    Scan,
+    Connect(String),
+    GattDump(String),
+    Read(String, Uuid),
+    Write(String, Uuid, Vec<u8>),
+    Subscribe(String, Uuid),
+}
+
+struct Options {
+    adapter: usize,
+    timeout: Duration,
+    write_type: WriteType,
+    command: Command,
+}
+
+fn parse_options() -> Result<Options> {
+    let mut adapter = 0;
+    let mut timeout = Duration::from_secs(5);
+    let mut write_type = WriteType::WithResponse;
+    let mut command = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--adapter" => {
+                adapter = args.next().ok_or("--adapter needs a value")?.parse()?;
+            }
+            "--timeout" => {
+                let seconds: f64 = args.next().ok_or("--timeout needs a value")?.parse()?;
+                timeout = Duration::try_from_secs_f64(seconds)?;
+            }
+            "--without-response" => write_type = WriteType::WithoutResponse,
+            "--signed" => write_type = WriteType::SignedWithoutResponse,
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {}", arg).into()),
+            _ => command.push(arg),
+        }
+    }
+    let command = match command.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["scan"] => Command::Scan,
+        ["connect", device] => Command::Connect(device.to_string()),
+        ["gatt", "dump", device] => Command::GattDump(device.to_string()),
+        ["read", device, uuid] => Command::Read(device.to_string(), parse_uuid(uuid)?),
+        ["write", device, uuid, data] => {
+            Command::Write(device.to_string(), parse_uuid(uuid)?, parse_hex(data)?)
+        }
+        ["subscribe", device, uuid] => Command::Subscribe(device.to_string(), parse_uuid(uuid)?),
+        _ => return Err(USAGE.into()),
+    };
+    Ok(Options {
+        adapter,
+        timeout,
+        write_type,
+        command,
+    })
+}
+
+/// Parses a full UUID or a 16- or 32-bit short one.
+fn parse_uuid(s: &str) -> Result<Uuid> {
+    let short = s.trim_start_matches("0x");
+    let uuid: Result<Uuid> = match short.len() {
+        4 => u16::from_str_radix(short, 16)
+            .map(uuid_from_u16)
+            .map_err(Into::into),
+        8 => u32::from_str_radix(short, 16)
+            .map(uuid_from_u32)
+            .map_err(Into::into),
+        _ => Uuid::parse_str(s).map_err(Into::into),
+    };
+    uuid.map_err(|e| format!("Invalid UUID {}: {}", s, e).into())
+}
+
+fn parse_hex(s: &str) -> Result<Vec<u8>> {
+    let s = s.trim_start_matches("0x");
+    if !s.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(format!("{} isn't made up of hex digits", s).into());
+    }
+    if !s.len().is_multiple_of(2) {
+        return Err(format!("{} has an odd number of hex digits", s).into());
+    }
+    s.as_bytes()
+        .chunks(2)
+        .map(|digits| Ok(u8::from_str_radix(std::str::from_utf8(digits)?, 16)?))
+        .collect()
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn describe(peripheral: &Peripheral) -> Result<String> {
+    let properties = peripheral.properties()?.unwrap_or_default();
+    let mut description = format!("{} ({})", peripheral.address(), peripheral.id());
+    if let Some(name) = properties.local_name {
+        description += &format!(" {:?}", name);
+    }
+    if let Some(rssi) = properties.rssi {
+        description += &format!(" RSSI {}", rssi);
+    }
+    Ok(description)
+}
+
+fn matches(peripheral: &Peripheral, device: &str) -> Result<bool> {
+    if let Ok(address) = BDAddr::from_str(device) {
+        if peripheral.address() == address {
+            return Ok(true);
+        }
+    }
+    if peripheral.id().to_string().eq_ignore_ascii_case(device) {
+        return Ok(true);
+    }
+    let properties = peripheral.properties()?.unwrap_or_default();
+    Ok(properties.local_name.as_deref() == Some(device))
+}
+
+fn scan(adapter: &Adapter, timeout: Duration) -> Result<()> {
+    let mut events = adapter.events()?;
+    adapter.start_scan(ScanFilter::default())?;
+    let deadline = Instant::now() + timeout;
+    while let Some(event) = events.next_timeout(deadline.saturating_duration_since(Instant::now()))
+    {
+        if let CentralEvent::DeviceDiscovered(id) = event {
+            println!("{}", describe(&adapter.peripheral(&id)?)?);
+        }
+    }
+    adapter.stop_scan()?;
+    Ok(())
+}
+
+/// Finds the device, scanning for it if the adapter doesn't know about it already, and connects to
+/// it.
+fn connect(adapter: &Adapter, device: &str, timeout: Duration) -> Result<Peripheral> {
+    let mut found = None;
+    for peripheral in adapter.peripherals()? {
+        if matches(&peripheral, device)? {
+            found = Some(peripheral);
+            break;
+        }
+    }
+    if found.is_none() {
+        let mut events = adapter.events()?;
+        adapter.start_scan(ScanFilter::default())?;
+        let deadline = Instant::now() + timeout;
+        while let Some(event) =
+            events.next_timeout(deadline.saturating_duration_since(Instant::now()))
+        {
+            if let CentralEvent::DeviceDiscovered(id) | CentralEvent::DeviceUpdated(id) = event {
+                let peripheral = adapter.peripheral(&id)?;
+                if matches(&peripheral, device)? {
+                    found = Some(peripheral);
+                    break;
+                }
+            }
+        }
+        adapter.stop_scan()?;
+    }
+    let peripheral = found.ok_or_else(|| format!("Couldn't find {}", device))?;
+    if !peripheral.is_connected()? {
+        peripheral.connect()?;
+    }
+    peripheral.discover_services()?;
+    Ok(peripheral)
+}
+
+fn characteristic(peripheral: &Peripheral, uuid: Uuid) -> Result<Characteristic> {
+    peripheral
+        .characteristics()
+        .into_iter()
+        .find(|characteristic| characteristic.uuid == uuid)
+        .ok_or_else(|| format!("{} has no characteristic {}", peripheral.address(), uuid).into())
+}
+
+fn gatt_dump(peripheral: &Peripheral) -> Result<()> {
+    for service in peripheral.services() {
+        println!(
+            "Service {}{}",
+            service.uuid.to_short_string(),
+            if service.primary { "" } else { " (secondary)" }
+        );
+        for characteristic in &service.characteristics {
+            print!(
+                "  Characteristic {} {:?}",
+                characteristic.uuid.to_short_string(),
+                characteristic.properties
+            );
+            if characteristic.properties.contains(CharPropFlags::READ) {
+                match peripheral.read(characteristic) {
+                    Ok(value) => print!(" = {}", to_hex(&value)),
+                    Err(e) => print!(" (read failed: {})", e),
+                }
+            }
+            println!();
+            for descriptor in &characteristic.descriptors {
+                println!("    Descriptor {}", descriptor.uuid.to_short_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run(options: Options) -> Result<()> {
+    let manager = Manager::new()?;
+    let adapter = manager
+        .adapters()?
+        .into_iter()
+        .nth(options.adapter)
+        .ok_or("No such Bluetooth adapter")?;
+    match options.command {
+        Command::Scan => scan(&adapter, options.timeout),
+        Command::Connect(device) => {
+            let peripheral = connect(&adapter, &device, options.timeout)?;
+            println!("Connected to {}", describe(&peripheral)?);
+            for service in peripheral.services() {
+                println!("Service {}", service.uuid.to_short_string());
+            }
+            peripheral.disconnect()?;
+            Ok(())
+        }
+        Command::GattDump(device) => {
+            let peripheral = connect(&adapter, &device, options.timeout)?;
+            gatt_dump(&peripheral)?;
+            peripheral.disconnect()?;
+            Ok(())
+        }
+        Command::Read(device, uuid) => {
+            let peripheral = connect(&adapter, &device, options.timeout)?;
+            let characteristic = characteristic(&peripheral, uuid)?;
+            println!("{}", to_hex(&peripheral.read(&characteristic)?));
+            peripheral.disconnect()?;
+            Ok(())
+        }
+        Command::Write(device, uuid, data) => {
+            let peripheral = connect(&adapter, &device, options.timeout)?;
+            let characteristic = characteristic(&peripheral, uuid)?;
+            peripheral.write(&characteristic, &data, options.write_type)?;
+            peripheral.disconnect()?;
+            Ok(())
+        }
+        Command::Subscribe(device, uuid) => {
+            let peripheral = connect(&adapter, &device, options.timeout)?;
+            let characteristic = characteristic(&peripheral, uuid)?;
+            let notifications = peripheral.notifications()?;
+            peripheral.subscribe(&characteristic)?;
+            for notification in notifications {
+                if notification.uuid == characteristic.uuid {
+                    println!("{}", to_hex(&notification.value));
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn main() {
+    if let Err(e) = parse_options().and_then(run) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex() {
+        assert_eq!(parse_hex("0x01ff").unwrap(), vec![0x01, 0xff]);
+        assert_eq!(parse_hex("").unwrap(), Vec::<u8>::new());
+        assert!(parse_hex("abc").is_err());
+        assert!(parse_hex("é0").is_err());
+        assert!(parse_hex("+f").is_err());
+        assert_eq!(to_hex(&[0x01, 0xff]), "01ff");
+    }
+}
@@ -0,0 +1,22 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! A command line tool for scanning for and talking to BLE devices, built on `btleplug::blocking`.
+//!
+//! See the "macOS permissions note" in README.md before running this on macOS Big Sur or later.
+
+#[cfg(not(target_arch = "wasm32"))]
+mod cli;
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    cli::main();
+}
+
+// A page can't run a command line tool, and can't block on btleplug's futures anyway.
+#[cfg(target_arch = "wasm32")]
+fn main() {}
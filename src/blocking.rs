@@ -0,0 +1,549 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Synchronous versions of [`Manager`](crate::api::Manager), [`Central`](crate::api::Central) and
+//! [`Peripheral`](crate::api::Peripheral), for applications which don't use async Rust.
+//!
+//! Each [`Manager`] runs an async runtime on a thread of its own, which its adapters and
+//! peripherals share, and every method blocks the calling thread until the operation finishes.
+//! These methods must not be called from async code, as blocking there would stall the executor.
+//! They aren't available on WebAssembly, where the browser's thread can't block.
+//!
+//! ```no_run
+//! use btleplug::api::ScanFilter;
+//! use btleplug::blocking::Manager;
+//! use std::{thread, time::Duration};
+//!
+//! # fn main() -> btleplug::Result<()> {
+//! let manager = Manager::new()?;
+//! let adapter = manager.adapters()?.into_iter().next().expect("No adapters found");
+//! adapter.start_scan(ScanFilter::default())?;
+//! thread::sleep(Duration::from_secs(2));
+//! for peripheral in adapter.peripherals()? {
+//!     println!("{:?}", peripheral.properties()?);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::api::{
+    observer::{Observer, TraceHandler},
+    rssi::{RssiStats, RssiTracking},
+    AdapterInfo, BDAddr, Central as _, CentralEvent, CentralState, Characteristic, Descriptor,
+    DiscoveryEvent, EventFilter, Manager as _, Peripheral as _, PeripheralEvent,
+    PeripheralProperties, ScanFilter, ScanOptions, SecurityLevel, Service, ValueNotification,
+    WriteType,
+};
+use crate::{platform, rt, Error, Result};
+use futures::{
+    channel::oneshot,
+    stream::{Stream, StreamExt},
+};
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Debug, Formatter},
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// A Tokio runtime driven by a thread of its own, which stops when the last handle is dropped.
+struct Runtime {
+    handle: tokio::runtime::Handle,
+    _stop: oneshot::Sender<()>,
+}
+
+impl Runtime {
+    fn new() -> Result<Arc<Self>> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|error| Error::Other(Box::new(error)))?;
+        let handle = runtime.handle().clone();
+        let (stop, stopped) = oneshot::channel::<()>();
+        thread::Builder::new()
+            .name("btleplug-blocking".to_string())
+            .spawn(move || {
+                runtime.block_on(async {
+                    let _ = stopped.await;
+                })
+            })
+            .map_err(|error| Error::Other(Box::new(error)))?;
+        Ok(Arc::new(Runtime {
+            handle,
+            _stop: stop,
+        }))
+    }
+
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.handle.block_on(future)
+    }
+}
+
+impl Debug for Runtime {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("Runtime")
+    }
+}
+
+/// A blocking iterator over a stream of events, such as the one returned by [`Adapter::events`].
+pub struct Events<T> {
+    stream: Pin<Box<dyn Stream<Item = T> + Send>>,
+    runtime: Arc<Runtime>,
+}
+
+impl<T> Events<T> {
+    /// Waits up to `timeout` for the next event. Returns `None` if no event arrived in time, or if
+    /// there will be no more events.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<T> {
+        self.runtime
+            .block_on(rt::timeout(timeout, self.stream.next()))
+            .ok()
+            .flatten()
+    }
+}
+
+#[cfg(feature = "ffi")]
+impl<T> Events<T> {
+    /// Waits for the next event, unless `stop` completes first. Returns `None` if it did, or if
+    /// there will be no more events.
+    pub(crate) fn next_until(&mut self, stop: impl Future + Unpin) -> Option<T> {
+        use futures::future::{select, Either};
+        match self.runtime.block_on(select(self.stream.next(), stop)) {
+            Either::Left((item, _)) => item,
+            Either::Right(_) => None,
+        }
+    }
+
+    /// Events from `stream`, on a runtime of their own, for testing what takes them.
+    #[cfg(test)]
+    pub(crate) fn from_stream(stream: impl Stream<Item = T> + Send + 'static) -> Self {
+        Events {
+            stream: Box::pin(stream),
+            runtime: Runtime::new().unwrap(),
+        }
+    }
+}
+
+impl<T> Iterator for Events<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.runtime.block_on(self.stream.next())
+    }
+}
+
+impl<T> Debug for Events<T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Events").finish_non_exhaustive()
+    }
+}
+
+/// A blocking version of [`platform::Manager`].
+#[derive(Clone, Debug)]
+pub struct Manager {
+    manager: platform::Manager,
+    runtime: Arc<Runtime>,
+}
+
+impl Manager {
+    /// Creates a manager, starting the runtime which it and its adapters and peripherals use.
+    pub fn new() -> Result<Self> {
+        let runtime = Runtime::new()?;
+        let manager = runtime.block_on(platform::Manager::new())?;
+        Ok(Manager { manager, runtime })
+    }
+
+    /// See [`Manager::adapters`](crate::api::Manager::adapters).
+    pub fn adapters(&self) -> Result<Vec<Adapter>> {
+        Ok(self
+            .runtime
+            .block_on(self.manager.adapters())?
+            .into_iter()
+            .map(|adapter| Adapter {
+                adapter,
+                runtime: self.runtime.clone(),
+            })
+            .collect())
+    }
+
+    /// See [`Manager::set_trace_handler`](crate::api::Manager::set_trace_handler).
+    pub fn set_trace_handler(&self, handler: Option<Arc<dyn TraceHandler>>) {
+        self.manager.set_trace_handler(handler);
+    }
+
+    /// Returns the async manager, for calls which this doesn't have a blocking version of.
+    pub fn inner(&self) -> &platform::Manager {
+        &self.manager
+    }
+}
+
+/// A blocking version of [`platform::Adapter`].
+#[derive(Clone, Debug)]
+pub struct Adapter {
+    adapter: platform::Adapter,
+    runtime: Arc<Runtime>,
+}
+
+impl Adapter {
+    fn peripheral_for(&self, peripheral: platform::Peripheral) -> Peripheral {
+        Peripheral {
+            peripheral,
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    /// See [`Central::events`](crate::api::Central::events).
+    pub fn events(&self) -> Result<Events<CentralEvent>> {
+        Ok(Events {
+            stream: self.runtime.block_on(self.adapter.events())?,
+            runtime: self.runtime.clone(),
+        })
+    }
+
+    /// See [`Central::events_filtered`](crate::api::Central::events_filtered).
+    pub fn events_filtered(&self, filter: EventFilter) -> Result<Events<CentralEvent>> {
+        Ok(Events {
+            stream: self
+                .runtime
+                .block_on(self.adapter.events_filtered(filter))?,
+            runtime: self.runtime.clone(),
+        })
+    }
+
+    /// See [`Central::discovery_events`](crate::api::Central::discovery_events).
+    pub fn discovery_events(&self) -> Result<Events<DiscoveryEvent>> {
+        Ok(Events {
+            stream: self.runtime.block_on(self.adapter.discovery_events())?,
+            runtime: self.runtime.clone(),
+        })
+    }
+
+    /// See [`Central::start_scan`](crate::api::Central::start_scan).
+    pub fn start_scan(&self, filter: ScanFilter) -> Result<()> {
+        self.runtime.block_on(self.adapter.start_scan(filter))
+    }
+
+    /// See [`Central::start_scan_with_options`](crate::api::Central::start_scan_with_options).
+    pub fn start_scan_with_options(&self, filter: ScanFilter, options: ScanOptions) -> Result<()> {
+        self.runtime
+            .block_on(self.adapter.start_scan_with_options(filter, options))
+    }
+
+    /// See [`Central::stop_scan`](crate::api::Central::stop_scan).
+    pub fn stop_scan(&self) -> Result<()> {
+        self.runtime.block_on(self.adapter.stop_scan())
+    }
+
+    /// See [`Central::discover`](crate::api::Central::discover).
+    pub fn discover(&self, filter: ScanFilter) -> Result<Events<Peripheral>> {
+        let runtime = self.runtime.clone();
+        let stream = self.runtime.block_on(self.adapter.discover(filter))?;
+        Ok(Events {
+            stream: Box::pin(stream.map(move |peripheral| Peripheral {
+                peripheral,
+                runtime: runtime.clone(),
+            })),
+            runtime: self.runtime.clone(),
+        })
+    }
+
+    /// See [`Central::peripherals`](crate::api::Central::peripherals).
+    pub fn peripherals(&self) -> Result<Vec<Peripheral>> {
+        Ok(self
+            .runtime
+            .block_on(self.adapter.peripherals())?
+            .into_iter()
+            .map(|peripheral| self.peripheral_for(peripheral))
+            .collect())
+    }
+
+    /// See [`Central::peripheral`](crate::api::Central::peripheral).
+    pub fn peripheral(&self, id: &platform::PeripheralId) -> Result<Peripheral> {
+        let peripheral = self.runtime.block_on(self.adapter.peripheral(id))?;
+        Ok(self.peripheral_for(peripheral))
+    }
+
+    /// See [`Central::peripheral_by_address`](crate::api::Central::peripheral_by_address).
+    pub fn peripheral_by_address(&self, address: BDAddr) -> Result<Peripheral> {
+        let peripheral = self
+            .runtime
+            .block_on(self.adapter.peripheral_by_address(address))?;
+        Ok(self.peripheral_for(peripheral))
+    }
+
+    /// See [`Central::add_peripheral`](crate::api::Central::add_peripheral).
+    pub fn add_peripheral(&self, id: &platform::PeripheralId) -> Result<Peripheral> {
+        let peripheral = self.runtime.block_on(self.adapter.add_peripheral(id))?;
+        Ok(self.peripheral_for(peripheral))
+    }
+
+    /// See [`Central::remove_peripheral`](crate::api::Central::remove_peripheral).
+    pub fn remove_peripheral(&self, id: &platform::PeripheralId) -> Result<()> {
+        self.runtime.block_on(self.adapter.remove_peripheral(id))
+    }
+
+    /// See [`Central::adapter_info`](crate::api::Central::adapter_info).
+    pub fn adapter_info(&self) -> Result<AdapterInfo> {
+        self.runtime.block_on(self.adapter.adapter_info())
+    }
+
+    /// See [`Central::adapter_state`](crate::api::Central::adapter_state).
+    pub fn adapter_state(&self) -> Result<CentralState> {
+        self.runtime.block_on(self.adapter.adapter_state())
+    }
+
+    /// See [`Central::is_powered`](crate::api::Central::is_powered).
+    pub fn is_powered(&self) -> Result<bool> {
+        self.runtime.block_on(self.adapter.is_powered())
+    }
+
+    /// See [`Central::set_powered`](crate::api::Central::set_powered).
+    pub fn set_powered(&self, powered: bool) -> Result<()> {
+        self.runtime.block_on(self.adapter.set_powered(powered))
+    }
+
+    /// See [`Central::operation_timeout`](crate::api::Central::operation_timeout).
+    pub fn operation_timeout(&self) -> Option<Duration> {
+        self.adapter.operation_timeout()
+    }
+
+    /// See [`Central::set_operation_timeout`](crate::api::Central::set_operation_timeout).
+    pub fn set_operation_timeout(&self, timeout: Option<Duration>) {
+        self.adapter.set_operation_timeout(timeout);
+    }
+
+    /// See [`Central::set_observer`](crate::api::Central::set_observer).
+    pub fn set_observer(&self, observer: Option<Arc<dyn Observer>>) {
+        self.adapter.set_observer(observer);
+    }
+
+    /// See [`Central::set_peripheral_ttl`](crate::api::Central::set_peripheral_ttl).
+    pub fn set_peripheral_ttl(&self, ttl: Option<Duration>) {
+        self.adapter.set_peripheral_ttl(ttl);
+    }
+
+    /// See [`Central::set_rssi_tracking`](crate::api::Central::set_rssi_tracking).
+    pub fn set_rssi_tracking(&self, tracking: Option<RssiTracking>) {
+        self.adapter.set_rssi_tracking(tracking);
+    }
+
+    /// Returns the async adapter, for calls which this doesn't have a blocking version of.
+    pub fn inner(&self) -> &platform::Adapter {
+        &self.adapter
+    }
+}
+
+/// A blocking version of [`platform::Peripheral`].
+#[derive(Clone, Debug)]
+pub struct Peripheral {
+    peripheral: platform::Peripheral,
+    runtime: Arc<Runtime>,
+}
+
+impl Peripheral {
+    /// See [`Peripheral::id`](crate::api::Peripheral::id).
+    pub fn id(&self) -> platform::PeripheralId {
+        self.peripheral.id()
+    }
+
+    /// See [`Peripheral::address`](crate::api::Peripheral::address).
+    pub fn address(&self) -> BDAddr {
+        self.peripheral.address()
+    }
+
+    /// See [`Peripheral::properties`](crate::api::Peripheral::properties).
+    pub fn properties(&self) -> Result<Option<PeripheralProperties>> {
+        self.runtime.block_on(self.peripheral.properties())
+    }
+
+    /// See [`Peripheral::properties_with_age`](crate::api::Peripheral::properties_with_age).
+    pub fn properties_with_age(&self) -> Result<Option<(PeripheralProperties, Option<Duration>)>> {
+        self.runtime.block_on(self.peripheral.properties_with_age())
+    }
+
+    /// See [`Peripheral::rssi_stats`](crate::api::Peripheral::rssi_stats).
+    pub fn rssi_stats(&self) -> Option<RssiStats> {
+        self.peripheral.rssi_stats()
+    }
+
+    /// See [`Peripheral::refresh_properties`](crate::api::Peripheral::refresh_properties).
+    pub fn refresh_properties(&self) -> Result<Option<PeripheralProperties>> {
+        self.runtime.block_on(self.peripheral.refresh_properties())
+    }
+
+    /// See [`Peripheral::resolve_name`](crate::api::Peripheral::resolve_name).
+    pub fn resolve_name(&self) -> Result<Option<String>> {
+        self.runtime.block_on(self.peripheral.resolve_name())
+    }
+
+    /// See [`Peripheral::services`](crate::api::Peripheral::services).
+    pub fn services(&self) -> BTreeSet<Service> {
+        self.peripheral.services()
+    }
+
+    /// See [`Peripheral::characteristics`](crate::api::Peripheral::characteristics).
+    pub fn characteristics(&self) -> BTreeSet<Characteristic> {
+        self.peripheral.characteristics()
+    }
+
+    /// See [`Peripheral::is_connected`](crate::api::Peripheral::is_connected).
+    pub fn is_connected(&self) -> Result<bool> {
+        self.runtime.block_on(self.peripheral.is_connected())
+    }
+
+    /// See [`Peripheral::connect`](crate::api::Peripheral::connect).
+    pub fn connect(&self) -> Result<()> {
+        self.runtime.block_on(self.peripheral.connect())
+    }
+
+    /// See [`Peripheral::disconnect`](crate::api::Peripheral::disconnect).
+    pub fn disconnect(&self) -> Result<()> {
+        self.runtime.block_on(self.peripheral.disconnect())
+    }
+
+    /// See [`Peripheral::events`](crate::api::Peripheral::events).
+    pub fn events(&self) -> Result<Events<PeripheralEvent>> {
+        Ok(Events {
+            stream: self.runtime.block_on(self.peripheral.events())?,
+            runtime: self.runtime.clone(),
+        })
+    }
+
+    /// See [`Peripheral::security_level`](crate::api::Peripheral::security_level).
+    pub fn security_level(&self) -> Result<SecurityLevel> {
+        self.runtime.block_on(self.peripheral.security_level())
+    }
+
+    /// See [`Peripheral::require_security`](crate::api::Peripheral::require_security).
+    pub fn require_security(&self, level: SecurityLevel) -> Result<()> {
+        self.runtime
+            .block_on(self.peripheral.require_security(level))
+    }
+
+    /// See [`Peripheral::discover_services`](crate::api::Peripheral::discover_services).
+    pub fn discover_services(&self) -> Result<()> {
+        self.runtime.block_on(self.peripheral.discover_services())
+    }
+
+    /// See [`Peripheral::write`](crate::api::Peripheral::write).
+    pub fn write(
+        &self,
+        characteristic: &Characteristic,
+        data: &[u8],
+        write_type: WriteType,
+    ) -> Result<()> {
+        self.runtime
+            .block_on(self.peripheral.write(characteristic, data, write_type))
+    }
+
+    /// See [`Peripheral::read`](crate::api::Peripheral::read).
+    pub fn read(&self, characteristic: &Characteristic) -> Result<Vec<u8>> {
+        self.runtime.block_on(self.peripheral.read(characteristic))
+    }
+
+    /// See [`Peripheral::read_by_uuid`](crate::api::Peripheral::read_by_uuid).
+    pub fn read_by_uuid(&self, service_uuid: Uuid, characteristic_uuid: Uuid) -> Result<Vec<u8>> {
+        self.runtime.block_on(
+            self.peripheral
+                .read_by_uuid(service_uuid, characteristic_uuid),
+        )
+    }
+
+    /// See [`Peripheral::write_by_uuid`](crate::api::Peripheral::write_by_uuid).
+    pub fn write_by_uuid(
+        &self,
+        service_uuid: Uuid,
+        characteristic_uuid: Uuid,
+        data: &[u8],
+        write_type: WriteType,
+    ) -> Result<()> {
+        self.runtime.block_on(self.peripheral.write_by_uuid(
+            service_uuid,
+            characteristic_uuid,
+            data,
+            write_type,
+        ))
+    }
+
+    /// See [`Peripheral::subscribe`](crate::api::Peripheral::subscribe).
+    pub fn subscribe(&self, characteristic: &Characteristic) -> Result<()> {
+        self.runtime
+            .block_on(self.peripheral.subscribe(characteristic))
+    }
+
+    /// See [`Peripheral::unsubscribe`](crate::api::Peripheral::unsubscribe).
+    pub fn unsubscribe(&self, characteristic: &Characteristic) -> Result<()> {
+        self.runtime
+            .block_on(self.peripheral.unsubscribe(characteristic))
+    }
+
+    /// See [`Peripheral::notifications`](crate::api::Peripheral::notifications).
+    pub fn notifications(&self) -> Result<Events<ValueNotification>> {
+        Ok(Events {
+            stream: self.runtime.block_on(self.peripheral.notifications())?,
+            runtime: self.runtime.clone(),
+        })
+    }
+
+    /// See [`Peripheral::write_descriptor`](crate::api::Peripheral::write_descriptor).
+    pub fn write_descriptor(&self, descriptor: &Descriptor, data: &[u8]) -> Result<()> {
+        self.runtime
+            .block_on(self.peripheral.write_descriptor(descriptor, data))
+    }
+
+    /// See [`Peripheral::read_descriptor`](crate::api::Peripheral::read_descriptor).
+    pub fn read_descriptor(&self, descriptor: &Descriptor) -> Result<Vec<u8>> {
+        self.runtime
+            .block_on(self.peripheral.read_descriptor(descriptor))
+    }
+
+    /// Returns the async peripheral, for calls which this doesn't have a blocking version of.
+    pub fn inner(&self) -> &platform::Peripheral {
+        &self.peripheral
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events() {
+        let runtime = Runtime::new().unwrap();
+        // Spawned tasks keep running on the runtime's thread between calls.
+        let (sender, receiver) = futures::channel::mpsc::unbounded();
+        runtime.block_on(async {
+            tokio::spawn(async move {
+                for i in 0..3 {
+                    sender.unbounded_send(i).unwrap();
+                }
+            });
+        });
+        let mut events = Events {
+            stream: Box::pin(receiver),
+            runtime,
+        };
+        assert_eq!(events.next(), Some(0));
+        assert_eq!(events.by_ref().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(events.next_timeout(Duration::from_millis(10)), None);
+    }
+
+    #[cfg(feature = "ffi")]
+    #[test]
+    fn next_until() {
+        let mut events =
+            Events::from_stream(futures::stream::iter([0]).chain(futures::stream::pending()));
+        let (stop, mut stopped) = oneshot::channel::<()>();
+        assert_eq!(events.next_until(&mut stopped), Some(0));
+        drop(stop);
+        assert_eq!(events.next_until(&mut stopped), None);
+    }
+}
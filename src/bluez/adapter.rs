@@ -1,39 +1,245 @@
-use super::peripheral::{Peripheral, PeripheralId};
-use crate::api::{Central, CentralEvent, CentralState, ScanFilter};
+use super::objects::ObjectCache;
+use super::peripheral::{shared_data, LastSeen, Peripheral, PeripheralId, ServiceCache};
+use crate::api::{
+    advertiser::{Advertiser, Advertising},
+    event_filter,
+    monitor::{self, AdvertisementMonitor, MonitorEvent},
+    observer::{Observer, Tracer},
+    operation::OperationContext,
+    rssi::RssiTracking,
+    spans, AdapterCapabilities, AdapterInfo, Central, CentralEvent, CentralState, ClassicDevice,
+    DataLength, EventFilter, NativeError, Peripheral as _, ScanFilter, ScanOptions,
+};
+use crate::linux::{controller::Controller, mgmt::Mgmt};
+use crate::rt;
 use crate::{Error, Result};
 use async_trait::async_trait;
 use bluez_async::{
     AdapterEvent, AdapterId, BluetoothError, BluetoothEvent, BluetoothSession, DeviceEvent,
-    DiscoveryFilter, Transport,
+    DeviceId, DeviceInfo, DiscoveryFilter, Transport,
+};
+use dbus::{
+    arg::{Append, Arg},
+    nonblock::stdintf::org_freedesktop_dbus::Properties,
+    Path,
 };
 use futures::stream::{self, Stream, StreamExt};
+use log::warn;
+use std::collections::HashMap;
 use std::pin::Pin;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+/// The operation context of each adapter, kept by the manager as it creates a new `Adapter`
+/// whenever one is asked for.
+pub(crate) type OperationContexts = Arc<Mutex<HashMap<AdapterId, OperationContext>>>;
+
+/// The index of an adapter in the kernel's management interface, which is in its name.
+pub(super) fn mgmt_index(adapter: &AdapterId) -> Result<u16> {
+    let name = adapter.to_string();
+    name.strip_prefix("hci")
+        .and_then(|index| index.parse().ok())
+        .ok_or_else(|| Error::RuntimeError(format!("Unexpected adapter name {}", name)))
+}
 
 /// Implementation of [api::Central](crate::api::Central).
 #[derive(Clone, Debug)]
 pub struct Adapter {
     session: BluetoothSession,
     adapter: AdapterId,
+    last_seen: LastSeen,
+    service_cache: ServiceCache,
+    object_cache: ObjectCache,
+    operation_context: OperationContext,
 }
 
 impl Adapter {
-    pub(crate) fn new(session: BluetoothSession, adapter: AdapterId) -> Self {
-        Self { session, adapter }
+    pub(crate) fn new(
+        session: BluetoothSession,
+        adapter: AdapterId,
+        last_seen: LastSeen,
+        service_cache: ServiceCache,
+        object_cache: ObjectCache,
+        operation_contexts: &OperationContexts,
+        tracer: &Tracer,
+    ) -> Self {
+        let operation_context = operation_contexts
+            .lock()
+            .unwrap()
+            .entry(adapter.clone())
+            .or_insert_with(|| OperationContext::with_tracer(tracer.clone()))
+            .clone();
+        Self {
+            session,
+            adapter,
+            last_seen,
+            service_cache,
+            object_cache,
+            operation_context,
+        }
     }
-}
 
-fn get_central_state(powered: bool) -> CentralState {
-    match powered {
-        true => CentralState::PoweredOn,
-        false => CentralState::PoweredOff,
+    async fn device_info(&self, id: &PeripheralId) -> Result<DeviceInfo> {
+        let device = self.object_cache.device_info(&self.session, &id.0).await;
+        device.map_err(|e| {
+            if let BluetoothError::DbusError(_) = e {
+                Error::DeviceNotFound
+            } else {
+                e.into()
+            }
+        })
     }
-}
 
-#[async_trait]
-impl Central for Adapter {
-    type Peripheral = Peripheral;
+    fn peripheral_for(&self, device: DeviceInfo) -> Peripheral {
+        Peripheral::new(
+            self.session.clone(),
+            device,
+            self.last_seen.clone(),
+            &self.service_cache,
+            self.object_cache.clone(),
+            self.operation_context.clone(),
+        )
+    }
 
-    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = CentralEvent> + Send>>> {
+    /// Removes devices from BlueZ, and forgets what we kept about them. bluez_async doesn't expose
+    /// `RemoveDevice`, so this calls it directly.
+    async fn remove_devices(&self, devices: &[DeviceId]) -> Result<()> {
+        if devices.is_empty() {
+            return Ok(());
+        }
+        let proxy = self.object_cache.proxy(self.adapter.clone());
+        let mut result = Ok(());
+        for device in devices {
+            self.last_seen.lock().unwrap().remove(device);
+            self.service_cache.remove(device);
+            let removed: std::result::Result<(), dbus::Error> = proxy
+                .method_call(
+                    "org.bluez.Adapter1",
+                    "RemoveDevice",
+                    (Path::from(device.clone()),),
+                )
+                .await;
+            if let Err(e) = removed {
+                result = Err(BluetoothError::from(e).into());
+            }
+        }
+        result
+    }
+
+    /// Returns the devices which haven't been seen since `cutoff`, other than those which are
+    /// connected or paired.
+    fn expired(&self, devices: &[DeviceInfo], cutoff: SystemTime) -> Vec<DeviceId> {
+        let last_seen = self.last_seen.lock().unwrap();
+        devices
+            .iter()
+            .filter(|device| {
+                !device.connected
+                    && !device.paired
+                    && last_seen
+                        .get(&device.id)
+                        .is_some_and(|last_seen| *last_seen < cutoff)
+            })
+            .map(|device| device.id.clone())
+            .collect()
+    }
+
+    /// Removes the devices which haven't been seen for the peripheral TTL, if it's time to check.
+    async fn expire_peripherals(&self) {
+        let Some(cutoff) = self.operation_context.expiry().cutoff() else {
+            return;
+        };
+        let expired = match self.session.get_devices_on_adapter(&self.adapter).await {
+            Ok(devices) => self.expired(&devices, cutoff),
+            Err(e) => {
+                warn!("Error getting devices to expire: {:?}", e);
+                return;
+            }
+        };
+        if let Err(e) = self.remove_devices(&expired).await {
+            warn!("Error removing expired devices: {:?}", e);
+        }
+    }
+
+    /// bluez_async doesn't expose the adapter's roles or advertising capabilities, or which of
+    /// BlueZ's experimental features are enabled, so read them from BlueZ directly. Older versions
+    /// of BlueZ don't have these properties, in which case the capabilities are left as `None` and
+    /// no experimental features are listed.
+    async fn capabilities(&self) -> Result<(AdapterCapabilities, Vec<Uuid>)> {
+        let proxy = self.object_cache.proxy(self.adapter.clone());
+        let roles: Option<Vec<String>> = proxy.get("org.bluez.Adapter1", "Roles").await.ok();
+        let experimental: Option<Vec<String>> = proxy
+            .get("org.bluez.Adapter1", "ExperimentalFeatures")
+            .await
+            .ok();
+        // Secondary channels are only used by extended advertising, and each corresponds to a PHY.
+        let secondary_channels: Option<Vec<String>> = proxy
+            .get(
+                "org.bluez.LEAdvertisingManager1",
+                "SupportedSecondaryChannels",
+            )
+            .await
+            .ok();
+        // Before 5.65, or without the kernel's support, BlueZ doesn't have the monitor manager at all.
+        let monitor_types: Option<Vec<String>> = proxy
+            .get(
+                "org.bluez.AdvertisementMonitorManager1",
+                "SupportedMonitorTypes",
+            )
+            .await
+            .ok();
+
+        let has_channel = |phy| {
+            secondary_channels
+                .as_ref()
+                .map(|channels| channels.iter().any(|channel| channel == phy))
+        };
+        let capabilities = AdapterCapabilities {
+            extended_advertising: secondary_channels
+                .as_ref()
+                .map(|channels| !channels.is_empty()),
+            le_2m_phy: has_channel("2M"),
+            le_coded_phy: has_channel("Coded"),
+            peripheral_role: roles.map(|roles| {
+                roles
+                    .iter()
+                    .any(|role| role == "peripheral" || role == "central-peripheral")
+            }),
+            // BlueZ uses EATT when the kernel supports it, but doesn't say whether it does.
+            eatt: None,
+            advertisement_monitor: Some(
+                monitor_types.is_some_and(|types| types.iter().any(|t| t == "or_patterns")),
+            ),
+        };
+        let experimental_features = experimental
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|uuid| Uuid::parse_str(uuid).ok())
+            .collect();
+        Ok((capabilities, experimental_features))
+    }
+
+    /// Sets a property of the adapter which bluez_async doesn't have a setter for.
+    async fn set_property<T: Arg + Append>(&self, name: &str, value: T) -> Result<()> {
+        self.object_cache
+            .proxy(self.adapter.clone())
+            .set("org.bluez.Adapter1", name, value)
+            .await
+            .map_err(BluetoothError::from)?;
+        Ok(())
+    }
+
+    /// Returns a stream of the adapter's events, leaving out those about peripherals other than
+    /// `peripherals` before asking BlueZ for their details, unless `peripherals` is empty.
+    async fn event_stream(
+        &self,
+        peripherals: Vec<PeripheralId>,
+    ) -> Result<Pin<Box<dyn Stream<Item = CentralEvent> + Send>>> {
+        let wanted = move |id: &DeviceId| {
+            peripherals.is_empty() || peripherals.contains(&PeripheralId::from(id.clone()))
+        };
         // There's a race between getting this event stream and getting the current set of devices.
         // Get the stream first, on the basis that it's better to have a duplicate DeviceDiscovered
         // event than to miss one. It's unlikely to happen in any case.
@@ -42,10 +248,13 @@ impl Central for Adapter {
         // Synthesise `DeviceDiscovered' and `DeviceConnected` events for existing peripherals.
         let devices = self.session.get_devices().await?;
         let adapter_id = self.adapter.clone();
+        let initial_wanted = wanted.clone();
         let initial_events = stream::iter(
             devices
                 .into_iter()
-                .filter(move |device| device.id.adapter() == adapter_id)
+                .filter(move |device| {
+                    device.id.adapter() == adapter_id && initial_wanted(&device.id)
+                })
                 .flat_map(|device| {
                     let mut events = vec![CentralEvent::DeviceDiscovered(device.id.clone().into())];
                     if device.connected {
@@ -55,51 +264,209 @@ impl Central for Adapter {
                 }),
         );
 
-        let session = self.session.clone();
-        let adapter_id = self.adapter.clone();
+        let adapter = self.clone();
         let events = events
-            .filter_map(move |event| central_event(event, session.clone(), adapter_id.clone()));
+            .filter(move |event| {
+                let wanted = match event {
+                    BluetoothEvent::Device { id, .. } => wanted(id),
+                    _ => true,
+                };
+                async move { wanted }
+            })
+            .then(move |event| {
+                let adapter = adapter.clone();
+                let span = match &event {
+                    BluetoothEvent::Device { id, .. } => spans::callback("event", Some(id)),
+                    _ => spans::callback("event", None),
+                };
+                span.instrument(async move {
+                    let classic = classic_event(&event, &adapter).await;
+                    central_event(event, adapter)
+                        .await
+                        .into_iter()
+                        .chain(classic)
+                })
+            })
+            .flat_map(stream::iter);
+
+        Ok(Box::pin(rt::Io::new(initial_events.chain(events))))
+    }
+}
+
+/// bluetoothd doesn't say which version it is over D-Bus, so ask it where distributions install
+/// it. Returns `None` if it isn't there, e.g. in a container using the host's bluetoothd. This
+/// runs bluetoothd, so it's only done once, off the async runtime's threads.
+async fn bluez_version() -> Option<String> {
+    const PATHS: [&str; 3] = [
+        "/usr/libexec/bluetooth/bluetoothd",
+        "/usr/lib/bluetooth/bluetoothd",
+        "/usr/sbin/bluetoothd",
+    ];
+    static VERSION: OnceCell<Option<String>> = OnceCell::const_new();
+    VERSION
+        .get_or_init(|| async {
+            rt::spawn_blocking(|| {
+                PATHS.iter().find_map(|path| {
+                    let output = Command::new(path).arg("--version").output().ok()?;
+                    let version = String::from_utf8(output.stdout).ok()?.trim().to_string();
+                    (output.status.success() && !version.is_empty()).then_some(version)
+                })
+            })
+            .await
+        })
+        .await
+        .clone()
+}
 
-        Ok(Box::pin(initial_events.chain(events)))
+fn get_central_state(powered: bool) -> CentralState {
+    match powered {
+        true => CentralState::PoweredOn,
+        false => CentralState::PoweredOff,
+    }
+}
+
+#[async_trait]
+impl Central for Adapter {
+    type Peripheral = Peripheral;
+
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = CentralEvent> + Send>>> {
+        rt::Io::new(self.event_stream(vec![])).await
+    }
+
+    async fn events_filtered(
+        &self,
+        filter: EventFilter,
+    ) -> Result<Pin<Box<dyn Stream<Item = CentralEvent> + Send>>> {
+        rt::Io::new(async {
+            let events = self.event_stream(filter.peripherals.clone()).await?;
+            let kinds_and_peripherals = filter.clone();
+            let events = events.filter(move |event| {
+                let matches = kinds_and_peripherals.matches_kind_and_peripheral(event);
+                async move { matches }
+            });
+            Ok(event_filter::by_services(
+                self,
+                Box::pin(events),
+                filter.services,
+            ))
+        })
+        .await
     }
 
     async fn start_scan(&self, filter: ScanFilter) -> Result<()> {
-        let filter = DiscoveryFilter {
-            service_uuids: filter.services,
-            duplicate_data: Some(true),
-            transport: Some(Transport::Auto),
-            ..Default::default()
-        };
-        self.session
-            .start_discovery_on_adapter_with_filter(&self.adapter, &filter)
-            .await?;
-        Ok(())
+        rt::Io::new(async {
+            let filter = DiscoveryFilter {
+                service_uuids: filter.services,
+                duplicate_data: Some(true),
+                // Only run an inquiry for Classic devices when asked to, as it slows down finding LE
+                // devices.
+                transport: Some(if filter.classic {
+                    Transport::Auto
+                } else {
+                    Transport::Le
+                }),
+                ..Default::default()
+            };
+            self.session
+                .start_discovery_on_adapter_with_filter(&self.adapter, &filter)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// bluetoothd only reads the scan timing from its configuration, so this sets it through the
+    /// kernel's management interface, as bluetoothd does at startup.
+    async fn start_scan_with_options(
+        &self,
+        filter: ScanFilter,
+        options: ScanOptions,
+    ) -> Result<()> {
+        rt::Io::new(async {
+            if let Some((interval, window)) = options.timing() {
+                Mgmt::open()?
+                    .set_discovery_scan_timing(mgmt_index(&self.adapter)?, interval, window)
+                    .await?;
+            }
+            self.start_scan(filter).await
+        })
+        .await
     }
 
     async fn stop_scan(&self) -> Result<()> {
-        self.session
-            .stop_discovery_on_adapter(&self.adapter)
-            .await?;
-        Ok(())
+        rt::Io::new(async {
+            self.session
+                .stop_discovery_on_adapter(&self.adapter)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn monitor(
+        &self,
+        monitor: AdvertisementMonitor,
+    ) -> Result<Pin<Box<dyn Stream<Item = MonitorEvent> + Send>>>
+    where
+        Self: 'static,
+    {
+        rt::Io::new(async {
+            // BlueZ can't offload a monitor without patterns, and older versions can't offload any.
+            let (capabilities, _) = self.capabilities().await?;
+            if !monitor.patterns.is_empty() && capabilities.advertisement_monitor == Some(true) {
+                if let Some(events) =
+                    super::monitor::register(&self.session, &self.adapter, &monitor).await?
+                {
+                    return Ok(events);
+                }
+            }
+            monitor::by_scanning(self, monitor).await
+        })
+        .await
     }
 
     async fn peripherals(&self) -> Result<Vec<Peripheral>> {
-        let devices = self.session.get_devices_on_adapter(&self.adapter).await?;
-        Ok(devices
-            .into_iter()
-            .map(|device| Peripheral::new(self.session.clone(), device))
-            .collect())
+        rt::Io::new(async {
+            let mut devices = self
+                .object_cache
+                .devices_on_adapter(&self.session, &self.adapter)
+                .await?;
+            if let Some(cutoff) = self.operation_context.expiry().cutoff() {
+                let expired = self.expired(&devices, cutoff);
+                if let Err(e) = self.remove_devices(&expired).await {
+                    warn!("Error removing expired devices: {:?}", e);
+                }
+                devices.retain(|device| !expired.contains(&device.id));
+            }
+            Ok(devices
+                .into_iter()
+                .map(|device| self.peripheral_for(device))
+                .collect())
+        })
+        .await
     }
 
     async fn peripheral(&self, id: &PeripheralId) -> Result<Peripheral> {
-        let device = self.session.get_device_info(&id.0).await.map_err(|e| {
-            if let BluetoothError::DbusError(_) = e {
-                Error::DeviceNotFound
-            } else {
-                e.into()
-            }
-        })?;
-        Ok(Peripheral::new(self.session.clone(), device))
+        rt::Io::new(async { Ok(self.peripheral_for(self.device_info(id).await?)) }).await
+    }
+
+    async fn connected_peripherals(&self, services: &[Uuid]) -> Result<Vec<Peripheral>> {
+        rt::Io::new(async {
+            let devices = self
+                .object_cache
+                .devices_on_adapter(&self.session, &self.adapter)
+                .await?;
+            Ok(devices
+                .into_iter()
+                .filter(|device| {
+                    device.connected
+                        && (services.is_empty()
+                            || services.iter().any(|uuid| device.services.contains(uuid)))
+                })
+                .map(|device| self.peripheral_for(device))
+                .collect())
+        })
+        .await
     }
 
     async fn add_peripheral(&self, _address: &PeripheralId) -> Result<Peripheral> {
@@ -108,42 +475,243 @@ impl Central for Adapter {
         ))
     }
 
-    async fn adapter_info(&self) -> Result<String> {
-        let adapter_info = self.session.get_adapter_info(&self.adapter).await?;
-        Ok(format!("{} ({})", adapter_info.id, adapter_info.modalias))
+    async fn remove_peripheral(&self, id: &PeripheralId) -> Result<()> {
+        rt::Io::new(async {
+            let peripheral = self.peripheral(id).await?;
+            if peripheral.is_connected().await? {
+                peripheral.disconnect().await?;
+            }
+            self.remove_devices(std::slice::from_ref(&id.0)).await
+        })
+        .await
+    }
+
+    /// bluetoothd has no D-Bus API for the accept list, so this uses the kernel's management
+    /// interface, which bluetoothd itself uses to reconnect to bonded devices.
+    async fn add_to_accept_list(&self, id: &PeripheralId) -> Result<()> {
+        rt::Io::new(async {
+            let device = self.device_info(id).await?;
+            Mgmt::open()?
+                .add_device(
+                    mgmt_index(&self.adapter)?,
+                    device.mac_address.into(),
+                    device.address_type.into(),
+                )
+                .await
+        })
+        .await
+    }
+
+    async fn remove_from_accept_list(&self, id: &PeripheralId) -> Result<()> {
+        rt::Io::new(async {
+            let device = self.device_info(id).await?;
+            Mgmt::open()?
+                .remove_device(
+                    mgmt_index(&self.adapter)?,
+                    device.mac_address.into(),
+                    device.address_type.into(),
+                )
+                .await
+        })
+        .await
+    }
+
+    async fn max_data_length(&self) -> Result<DataLength> {
+        Controller::open(mgmt_index(&self.adapter)?)?
+            .max_data_length()
+            .await
+    }
+
+    async fn suggested_data_length(&self) -> Result<DataLength> {
+        Controller::open(mgmt_index(&self.adapter)?)?
+            .suggested_data_length()
+            .await
+    }
+
+    async fn set_suggested_data_length(&self, length: DataLength) -> Result<()> {
+        Controller::open(mgmt_index(&self.adapter)?)?
+            .set_suggested_data_length(length)
+            .await
+    }
+
+    async fn adapter_info(&self) -> Result<AdapterInfo> {
+        rt::Io::new(async {
+            let adapter_info = self.session.get_adapter_info(&self.adapter).await?;
+            let (capabilities, experimental_features) = self.capabilities().await?;
+            // BlueZ defaults the alias to the system name.
+            let alias = (adapter_info.alias != adapter_info.name).then_some(adapter_info.alias);
+            Ok(AdapterInfo {
+                id: adapter_info.id.to_string(),
+                description: format!("{} ({})", adapter_info.id, adapter_info.modalias),
+                address: Some(adapter_info.mac_address.into()),
+                name: Some(adapter_info.name),
+                alias,
+                capabilities,
+                stack_version: bluez_version().await,
+                experimental_features,
+            })
+        })
+        .await
     }
 
     async fn adapter_state(&self) -> Result<CentralState> {
-        let mut powered = false;
-        if let Ok(info) = self.session.get_adapter_info(&self.adapter).await {
-            powered = info.powered;
+        rt::Io::new(async {
+            let mut powered = false;
+            if let Ok(info) = self.session.get_adapter_info(&self.adapter).await {
+                powered = info.powered;
+            }
+            Ok(get_central_state(powered))
+        })
+        .await
+    }
+
+    async fn is_powered(&self) -> Result<bool> {
+        rt::Io::new(async { Ok(self.session.get_adapter_info(&self.adapter).await?.powered) }).await
+    }
+
+    async fn set_powered(&self, powered: bool) -> Result<()> {
+        rt::Io::new(async {
+            self.session.set_powered(&self.adapter, powered).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// A `DiscoverableTimeout` of 0 keeps the adapter discoverable until told otherwise.
+    async fn set_discoverable(&self, discoverable: bool, timeout: Option<Duration>) -> Result<()> {
+        rt::Io::new(async {
+            if discoverable {
+                let seconds =
+                    timeout.map_or(0, |timeout| timeout.as_secs().clamp(1, u32::MAX.into()));
+                self.set_property("DiscoverableTimeout", seconds as u32)
+                    .await?;
+            }
+            self.set_property("Discoverable", discoverable).await
+        })
+        .await
+    }
+
+    async fn set_alias(&self, alias: &str) -> Result<()> {
+        rt::Io::new(self.set_property("Alias", alias.to_string())).await
+    }
+
+    async fn set_pairable(&self, pairable: bool) -> Result<()> {
+        rt::Io::new(self.set_property("Pairable", pairable)).await
+    }
+
+    async fn start_advertising(&self, advertiser: Advertiser) -> Result<Advertising> {
+        rt::Io::new(super::advertising::register(&self.adapter, &advertiser)).await
+    }
+
+    fn operation_timeout(&self) -> Option<Duration> {
+        self.operation_context.timeout()
+    }
+
+    fn set_operation_timeout(&self, timeout: Option<Duration>) {
+        self.operation_context.set_timeout(timeout);
+    }
+
+    fn set_observer(&self, observer: Option<Arc<dyn Observer>>) {
+        self.operation_context.set_observer(observer);
+    }
+
+    fn set_peripheral_ttl(&self, ttl: Option<Duration>) {
+        self.operation_context.expiry().set_ttl(ttl);
+    }
+
+    /// BlueZ only tells us about RSSI changes through its events, so this follows them for as long
+    /// as tracking is on.
+    fn set_rssi_tracking(&self, tracking: Option<RssiTracking>) {
+        self.operation_context.rssi().set_tracking(tracking);
+        if !self.operation_context.rssi().start_listening() {
+            return;
         }
-        Ok(get_central_state(powered))
+        let session = self.session.clone();
+        let adapter = self.adapter.clone();
+        let operation_context = self.operation_context.clone();
+        rt::spawn_io(async move {
+            let rssi = operation_context.rssi();
+            let mut events = match session.adapter_event_stream(&adapter).await {
+                Ok(events) => events,
+                Err(e) => {
+                    warn!("Error listening for RSSI changes: {:?}", e);
+                    rssi.stop_listening();
+                    return;
+                }
+            };
+            while let Some(event) = events.next().await {
+                if !rssi.keep_listening() {
+                    return;
+                }
+                if let BluetoothEvent::Device {
+                    id,
+                    event: DeviceEvent::Rssi { rssi: value },
+                } = event
+                {
+                    rssi.record(&id.into(), value);
+                }
+            }
+            rssi.stop_listening();
+        });
     }
 }
 
 impl From<BluetoothError> for Error {
     fn from(error: BluetoothError) -> Self {
-        Error::Other(Box::new(error))
+        match error {
+            BluetoothError::DbusError(error) => Error::Other(Box::new(NativeError::Dbus {
+                name: error.name().unwrap_or_default().to_string(),
+                message: error.message().map(ToString::to_string),
+            })),
+            error => Error::Other(Box::new(error)),
+        }
     }
 }
 
-async fn central_event(
-    event: BluetoothEvent,
-    session: BluetoothSession,
-    adapter_id: AdapterId,
-) -> Option<CentralEvent> {
+/// Returns the event for a newly discovered device if it was found by an inquiry, which gives it a
+/// Class of Device that LE advertisements don't carry.
+async fn classic_event(event: &BluetoothEvent, adapter: &Adapter) -> Option<CentralEvent> {
+    match event {
+        BluetoothEvent::Device {
+            id,
+            event: DeviceEvent::Discovered,
+        } if id.adapter() == adapter.adapter => {
+            let device = adapter
+                .object_cache
+                .device_info(&adapter.session, id)
+                .await
+                .ok()?;
+            let mut classic = ClassicDevice::new(device.mac_address.into());
+            classic.class = Some(device.class?);
+            classic.name = device.name;
+            classic.rssi = device.rssi;
+            Some(CentralEvent::ClassicDeviceDiscovered(classic))
+        }
+        _ => None,
+    }
+}
+
+async fn central_event(event: BluetoothEvent, adapter: Adapter) -> Option<CentralEvent> {
+    let session = &adapter.session;
+    // Advertisements are reported many times a second, so their devices are looked up in the
+    // cache.
+    let cache = &adapter.object_cache;
+    let adapter_id = &adapter.adapter;
     match event {
         BluetoothEvent::Device {
             id,
             event: device_event,
-        } if id.adapter() == adapter_id => match device_event {
+        } if id.adapter() == *adapter_id => match device_event {
             DeviceEvent::Discovered => {
-                let device = session.get_device_info(&id).await.ok()?;
+                let device = cache.device_info(session, &id).await.ok()?;
+                rt::spawn_io({
+                    let adapter = adapter.clone();
+                    async move { adapter.expire_peripherals().await }
+                });
                 Some(CentralEvent::DeviceDiscovered(device.id.into()))
             }
             DeviceEvent::Connected { connected } => {
-                let device = session.get_device_info(&id).await.ok()?;
+                let device = cache.device_info(session, &id).await.ok()?;
                 if connected {
                     Some(CentralEvent::DeviceConnected(device.id.into()))
                 } else {
@@ -151,25 +719,37 @@ async fn central_event(
                 }
             }
             DeviceEvent::Rssi { rssi: _ } => {
-                let device = session.get_device_info(&id).await.ok()?;
+                let device = cache.device_info(session, &id).await.ok()?;
                 Some(CentralEvent::DeviceUpdated(device.id.into()))
             }
             DeviceEvent::ManufacturerData { manufacturer_data } => {
-                let device = session.get_device_info(&id).await.ok()?;
+                let device = cache.device_info(session, &id).await.ok()?;
                 Some(CentralEvent::ManufacturerDataAdvertisement {
                     id: device.id.into(),
-                    manufacturer_data,
+                    manufacturer_data: shared_data(manufacturer_data),
                 })
             }
             DeviceEvent::ServiceData { service_data } => {
-                let device = session.get_device_info(&id).await.ok()?;
+                let device = cache.device_info(session, &id).await.ok()?;
                 Some(CentralEvent::ServiceDataAdvertisement {
                     id: device.id.into(),
-                    service_data,
+                    service_data: shared_data(service_data),
                 })
             }
             DeviceEvent::Services { services } => {
                 let device = session.get_device_info(&id).await.ok()?;
+                // BlueZ handles Service Changed indications itself, and updates the UUIDs of a
+                // connected device when the services it found change.
+                if device.connected && device.services_resolved {
+                    let id = device.id.clone();
+                    let peripheral = adapter.peripheral_for(device);
+                    if peripheral.has_services() {
+                        if let Err(e) = peripheral.discover_services().await {
+                            warn!("Error rediscovering services: {:?}", e);
+                        }
+                    }
+                    return Some(CentralEvent::ServicesChanged(id.into()));
+                }
                 Some(CentralEvent::ServicesAdvertisement {
                     id: device.id.into(),
                     services,
@@ -179,14 +759,11 @@ async fn central_event(
         },
         BluetoothEvent::Adapter {
             id,
-            event: adapter_event,
-        } if id == adapter_id => match adapter_event {
-            AdapterEvent::Powered { powered } => {
-                let state = get_central_state(powered);
-                Some(CentralEvent::StateUpdate(state))
-            }
-            _ => None,
-        },
+            event: AdapterEvent::Powered { powered },
+        } if id == *adapter_id => {
+            let state = get_central_state(powered);
+            Some(CentralEvent::StateUpdate(state))
+        }
         _ => None,
     }
 }
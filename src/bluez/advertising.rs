@@ -0,0 +1,198 @@
+//! Advertises with `org.bluez.LEAdvertisingManager1`. bluez_async doesn't support it, and BlueZ
+//! reads the advertisement from an object which the application exports, so this talks to BlueZ on
+//! a connection of its own.
+
+use crate::api::advertiser::{Advertiser, Advertising};
+use crate::rt::{self, Task};
+use crate::{Error, Result};
+use bluez_async::{AdapterId, BluetoothError};
+use dbus::{
+    arg::{PropMap, RefArg, Variant},
+    channel::{MatchingReceiver, Sender, Token},
+    message::MatchRule,
+    nonblock::{Proxy, SyncConnection},
+    strings::ErrorName,
+    Message, Path,
+};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const MANAGER_INTERFACE: &str = "org.bluez.LEAdvertisingManager1";
+const ADVERTISEMENT_INTERFACE: &str = "org.bluez.LEAdvertisement1";
+
+/// Each advertisement gets its own object path, so that several can be registered at once.
+static NEXT_ADVERTISEMENT: AtomicUsize = AtomicUsize::new(0);
+
+/// Unregisters the advertisement once it is dropped.
+struct Registration {
+    connection: Arc<SyncConnection>,
+    resource: Option<Task>,
+    adapter: AdapterId,
+    object: Path<'static>,
+    token: Token,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.connection.stop_receive(self.token);
+        let resource = self.resource.take().unwrap();
+        if !rt::can_spawn() {
+            resource.abort();
+            return;
+        }
+        let proxy = adapter_proxy(&self.adapter, self.connection.clone());
+        let object = self.object.clone();
+        rt::spawn_io(async move {
+            let _: std::result::Result<(), dbus::Error> = proxy
+                .method_call(MANAGER_INTERFACE, "UnregisterAdvertisement", (object,))
+                .await;
+            resource.abort();
+        });
+    }
+}
+
+fn adapter_proxy(
+    adapter: &AdapterId,
+    connection: Arc<SyncConnection>,
+) -> Proxy<'static, Arc<SyncConnection>> {
+    Proxy::new(
+        "org.bluez",
+        Path::from(adapter.clone()),
+        Duration::from_secs(5),
+        connection,
+    )
+}
+
+/// Registers `advertiser` with BlueZ as a broadcast advertisement, which BlueZ advertises until
+/// the returned [`Advertising`] is dropped.
+pub(super) async fn register(adapter: &AdapterId, advertiser: &Advertiser) -> Result<Advertising> {
+    // BlueZ checks the length too, but only says that it's invalid.
+    advertiser.to_ad_structures()?;
+    let (resource, connection) =
+        dbus_tokio::connection::new_system_sync().map_err(BluetoothError::from)?;
+    let resource = rt::spawn_io(async {
+        resource.await;
+    });
+    let object = Path::from(format!(
+        "/io/github/btleplug/advertisement{}",
+        NEXT_ADVERTISEMENT.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let token = connection.start_receive(
+        MatchRule::new_method_call(),
+        Box::new({
+            let advertiser = advertiser.clone();
+            let object = object.clone();
+            move |message, connection| {
+                let reply = handle_call(&message, &object, &advertiser);
+                let _ = connection.send(reply);
+                true
+            }
+        }),
+    );
+    let registration = Registration {
+        connection: connection.clone(),
+        resource: Some(resource),
+        adapter: adapter.clone(),
+        object: object.clone(),
+        token,
+    };
+
+    let registered: std::result::Result<(), dbus::Error> = adapter_proxy(adapter, connection)
+        .method_call(
+            MANAGER_INTERFACE,
+            "RegisterAdvertisement",
+            (object, PropMap::new()),
+        )
+        .await;
+    match registered {
+        Ok(()) => Ok(Advertising::new(move || drop(registration))),
+        Err(e)
+            if matches!(
+                e.name(),
+                Some("org.freedesktop.DBus.Error.UnknownInterface")
+                    | Some("org.freedesktop.DBus.Error.UnknownMethod")
+                    | Some("org.freedesktop.DBus.Error.UnknownObject")
+            ) =>
+        {
+            Err(Error::NotSupported(
+                "The adapter can't advertise".to_string(),
+            ))
+        }
+        Err(e) => Err(BluetoothError::from(e).into()),
+    }
+}
+
+/// Answers a call from BlueZ to the advertisement's object, which BlueZ also finds it through as
+/// an object manager.
+fn handle_call(message: &Message, object: &Path<'static>, advertiser: &Advertiser) -> Message {
+    let path = message.path();
+    let interface = message.interface();
+    let member = message.member();
+    match (path.as_deref(), interface.as_deref(), member.as_deref()) {
+        (Some(path), _, _) if path != &**object => unknown_method(message),
+        (_, Some("org.freedesktop.DBus.ObjectManager"), Some("GetManagedObjects")) => {
+            let mut interfaces = HashMap::new();
+            interfaces.insert(ADVERTISEMENT_INTERFACE.to_string(), properties(advertiser));
+            let mut objects = HashMap::new();
+            objects.insert(object.clone(), interfaces);
+            message.method_return().append1(objects)
+        }
+        (_, Some("org.freedesktop.DBus.Properties"), Some("GetAll")) => {
+            let properties = match message.read1::<&str>() {
+                Ok(ADVERTISEMENT_INTERFACE) => properties(advertiser),
+                _ => PropMap::new(),
+            };
+            message.method_return().append1(properties)
+        }
+        (_, Some(ADVERTISEMENT_INTERFACE), Some("Release")) => message.method_return(),
+        _ => unknown_method(message),
+    }
+}
+
+fn unknown_method(message: &Message) -> Message {
+    message.error(
+        &ErrorName::from("org.freedesktop.DBus.Error.UnknownMethod"),
+        &CString::new("Unknown method").unwrap(),
+    )
+}
+
+/// The properties of the advertisement's object, which tell BlueZ what to advertise.
+fn properties(advertiser: &Advertiser) -> PropMap {
+    let mut properties: PropMap = HashMap::new();
+    let mut insert = |name: &str, value: Box<dyn RefArg>| {
+        properties.insert(name.to_string(), Variant(value));
+    };
+    insert("Type", Box::new("broadcast".to_string()));
+    if !advertiser.services.is_empty() {
+        let services: Vec<String> = advertiser
+            .services
+            .iter()
+            .map(|uuid| uuid.to_string())
+            .collect();
+        insert("ServiceUUIDs", Box::new(services));
+    }
+    if !advertiser.service_data.is_empty() {
+        let service_data: HashMap<String, Variant<Vec<u8>>> = advertiser
+            .service_data
+            .iter()
+            .map(|(uuid, data)| (uuid.to_string(), Variant(data.clone())))
+            .collect();
+        insert("ServiceData", Box::new(service_data));
+    }
+    if !advertiser.manufacturer_data.is_empty() {
+        let manufacturer_data: HashMap<u16, Variant<Vec<u8>>> = advertiser
+            .manufacturer_data
+            .iter()
+            .map(|(&company_id, data)| (company_id, Variant(data.clone())))
+            .collect();
+        insert("ManufacturerData", Box::new(manufacturer_data));
+    }
+    if let Some(name) = &advertiser.local_name {
+        insert("LocalName", Box::new(name.clone()));
+    }
+    properties
+}
@@ -0,0 +1,256 @@
+//! Answers BlueZ's requests for user input while pairing with `org.bluez.Agent1`, passing them on
+//! to a [`PairingAgent`]. bluez_async doesn't support agents, and BlueZ calls back into an object
+//! which the application exports, so this talks to BlueZ on a connection of its own. BlueZ only
+//! asks the agent of the connection which started pairing, unless it is the default agent, so
+//! pairing is started on that connection too.
+
+use super::objects::object_id;
+use super::peripheral::PeripheralId;
+use crate::api::pairing::{PairingAgent, PairingRequest, PairingResponse};
+use crate::api::spans;
+use crate::rt::{self, Task};
+use crate::Result;
+use bluez_async::{BluetoothError, DeviceId};
+use dbus::{
+    channel::{MatchingReceiver, Sender, Token},
+    message::MatchRule,
+    nonblock::{Proxy, SyncConnection},
+    strings::ErrorName,
+    Message, Path,
+};
+use std::ffi::CString;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const AGENT_MANAGER_INTERFACE: &str = "org.bluez.AgentManager1";
+const AGENT_INTERFACE: &str = "org.bluez.Agent1";
+const DEVICE_INTERFACE: &str = "org.bluez.Device1";
+
+/// Each agent gets its own object path, so that several can be registered at once.
+static NEXT_AGENT: AtomicUsize = AtomicUsize::new(0);
+
+/// The input and output the user has for pairing, which BlueZ uses to choose how they confirm it,
+/// and so which [`PairingRequest`]s the agent is given.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum AgentCapability {
+    /// The agent can show a PIN, but not take any input.
+    DisplayOnly,
+    /// The agent can show a PIN and have the user confirm or reject it.
+    DisplayYesNo,
+    /// The agent can have the user enter a PIN, but not show one.
+    KeyboardOnly,
+    /// The agent can only agree to pairing which needs no PIN.
+    NoInputNoOutput,
+    /// The agent can show a PIN and have the user enter one, which allows every way of pairing.
+    #[default]
+    KeyboardDisplay,
+}
+
+impl AgentCapability {
+    fn as_str(self) -> &'static str {
+        match self {
+            AgentCapability::DisplayOnly => "DisplayOnly",
+            AgentCapability::DisplayYesNo => "DisplayYesNo",
+            AgentCapability::KeyboardOnly => "KeyboardOnly",
+            AgentCapability::NoInputNoOutput => "NoInputNoOutput",
+            AgentCapability::KeyboardDisplay => "KeyboardDisplay",
+        }
+    }
+}
+
+/// A [`PairingAgent`] registered with BlueZ, by
+/// [`Manager::register_agent`](super::manager::Manager::register_agent). It is unregistered when
+/// this is dropped.
+pub struct AgentRegistration {
+    connection: Arc<SyncConnection>,
+    resource: Option<Task>,
+    path: Path<'static>,
+    token: Token,
+}
+
+impl Debug for AgentRegistration {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("AgentRegistration")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl Drop for AgentRegistration {
+    fn drop(&mut self) {
+        // The handler holds on to the connection, so it has to be removed for the connection to go.
+        self.connection.stop_receive(self.token);
+        let resource = self.resource.take().unwrap();
+        if !rt::can_spawn() {
+            resource.abort();
+            return;
+        }
+        let proxy = agent_manager_proxy(self.connection.clone());
+        let path = self.path.clone();
+        rt::spawn_io(async move {
+            let _: std::result::Result<(), dbus::Error> = proxy
+                .method_call(AGENT_MANAGER_INTERFACE, "UnregisterAgent", (path,))
+                .await;
+            resource.abort();
+        });
+    }
+}
+
+fn agent_manager_proxy(connection: Arc<SyncConnection>) -> Proxy<'static, Arc<SyncConnection>> {
+    Proxy::new(
+        "org.bluez",
+        "/org/bluez",
+        Duration::from_secs(5),
+        connection,
+    )
+}
+
+impl AgentRegistration {
+    /// Registers `agent` with BlueZ, as the default agent if `default` is set, which BlueZ asks
+    /// when something other than an application, such as the device, starts pairing.
+    pub(super) async fn register(
+        capability: AgentCapability,
+        agent: Arc<dyn PairingAgent>,
+        default: bool,
+    ) -> Result<Self> {
+        let (resource, connection) =
+            dbus_tokio::connection::new_system_sync().map_err(BluetoothError::from)?;
+        let resource = rt::spawn_io(async {
+            resource.await;
+        });
+        let path = Path::from(format!(
+            "/io/github/btleplug/agent{}",
+            NEXT_AGENT.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let token = connection.start_receive(
+            MatchRule::new_method_call(),
+            Box::new({
+                let path = path.clone();
+                let connection = connection.clone();
+                move |message, _| {
+                    handle_call(message, &path, &agent, &connection);
+                    true
+                }
+            }),
+        );
+        let registration = AgentRegistration {
+            connection: connection.clone(),
+            resource: Some(resource),
+            path: path.clone(),
+            token,
+        };
+
+        let proxy = agent_manager_proxy(connection);
+        proxy
+            .method_call::<(), _, _, _>(
+                AGENT_MANAGER_INTERFACE,
+                "RegisterAgent",
+                (path.clone(), capability.as_str()),
+            )
+            .await
+            .map_err(BluetoothError::from)?;
+        if default {
+            proxy
+                .method_call::<(), _, _, _>(AGENT_MANAGER_INTERFACE, "RequestDefaultAgent", (path,))
+                .await
+                .map_err(BluetoothError::from)?;
+        }
+        Ok(registration)
+    }
+
+    /// Pairs with the device on the agent's connection, so that BlueZ asks the agent.
+    pub(super) async fn pair(&self, device: &DeviceId, timeout: Duration) -> Result<()> {
+        Proxy::new(
+            "org.bluez",
+            Path::from(device.to_string()),
+            timeout,
+            self.connection.clone(),
+        )
+        .method_call::<(), _, _, _>(DEVICE_INTERFACE, "Pair", ())
+        .await
+        .map_err(BluetoothError::from)?;
+        Ok(())
+    }
+}
+
+/// Answers a call from BlueZ to the agent's object. The agent may take a while to answer, for
+/// example if it asks the user, so it is asked on a task of its own, which replies once it has.
+fn handle_call(
+    message: Message,
+    path: &Path<'static>,
+    agent: &Arc<dyn PairingAgent>,
+    connection: &Arc<SyncConnection>,
+) {
+    let reply = |reply: Message| {
+        let _ = connection.send(reply);
+    };
+    if message.path().as_deref() != Some(&**path)
+        || message.interface().as_deref() != Some(AGENT_INTERFACE)
+    {
+        return reply(unknown_method(&message));
+    }
+    let Some(member) = message.member().map(|member| member.to_string()) else {
+        return reply(unknown_method(&message));
+    };
+    let request = match member.as_str() {
+        "Release" | "Cancel" => return reply(message.method_return()),
+        "RequestPinCode" | "RequestPasskey" => PairingRequest::ProvidePin,
+        "DisplayPinCode" => match message.read2::<Path, &str>() {
+            Ok((_, pin)) => PairingRequest::DisplayPin(pin.to_string()),
+            Err(_) => return reply(rejected(&message)),
+        },
+        "DisplayPasskey" => match message.read3::<Path, u32, u16>() {
+            // BlueZ calls again for each key the user enters on the device.
+            Ok((_, passkey, 0)) => PairingRequest::DisplayPin(format!("{:06}", passkey)),
+            Ok(_) => return reply(message.method_return()),
+            Err(_) => return reply(rejected(&message)),
+        },
+        "RequestConfirmation" => match message.read2::<Path, u32>() {
+            Ok((_, passkey)) => PairingRequest::ConfirmPin(format!("{:06}", passkey)),
+            Err(_) => return reply(rejected(&message)),
+        },
+        "RequestAuthorization" | "AuthorizeService" => PairingRequest::Confirm,
+        _ => return reply(unknown_method(&message)),
+    };
+    let Ok(device) = message.read1::<Path>() else {
+        return reply(rejected(&message));
+    };
+    let id = PeripheralId(object_id(device.to_string()));
+    let agent = agent.clone();
+    let connection = connection.clone();
+    let span = spans::callback("pairing request", Some(&id));
+    rt::spawn_io(span.instrument(async move {
+        let response = agent.request(&id, request).await;
+        let reply = match (member.as_str(), response) {
+            (_, PairingResponse::Reject) => rejected(&message),
+            ("RequestPinCode", PairingResponse::AcceptWithPin(pin)) => {
+                message.method_return().append1(pin)
+            }
+            ("RequestPasskey", PairingResponse::AcceptWithPin(pin)) => match pin.parse::<u32>() {
+                Ok(passkey) if passkey <= 999999 => message.method_return().append1(passkey),
+                _ => rejected(&message),
+            },
+            ("RequestPinCode" | "RequestPasskey", _) => rejected(&message),
+            _ => message.method_return(),
+        };
+        let _ = connection.send(reply);
+    }));
+}
+
+fn rejected(message: &Message) -> Message {
+    message.error(
+        &ErrorName::from("org.bluez.Error.Rejected"),
+        &CString::new("Rejected").unwrap(),
+    )
+}
+
+fn unknown_method(message: &Message) -> Message {
+    message.error(
+        &ErrorName::from("org.freedesktop.DBus.Error.UnknownMethod"),
+        &CString::new("Unknown method").unwrap(),
+    )
+}
@@ -1,18 +1,209 @@
-use super::adapter::Adapter;
-use crate::{api, Result};
+use super::adapter::{Adapter, OperationContexts};
+use super::agent::{AgentCapability, AgentRegistration};
+use super::objects::ObjectCache;
+use super::peripheral::{LastSeen, ServiceCache};
+use crate::api::observer::{TraceHandler, Tracer};
+use crate::api::pairing::PairingAgent;
+use crate::rt::{self, Task};
+use crate::{api, api::ManagerEvent, Error, Result};
 use async_trait::async_trait;
-use bluez_async::BluetoothSession;
+use bluez_async::{BluetoothError, BluetoothEvent, BluetoothSession, DeviceEvent, DeviceId};
+use dbus::{
+    message::SignalArgs,
+    nonblock::stdintf::org_freedesktop_dbus::{
+        ObjectManagerInterfacesAdded, ObjectManagerInterfacesRemoved,
+    },
+    Path,
+};
+use futures::stream::{self, Stream, StreamExt};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::SystemTime;
+
+const ADAPTER_INTERFACE: &str = "org.bluez.Adapter1";
 
 /// Implementation of [api::Manager](crate::api::Manager).
 #[derive(Clone, Debug)]
 pub struct Manager {
     session: BluetoothSession,
+    last_seen: LastSeen,
+    service_cache: ServiceCache,
+    object_cache: ObjectCache,
+    operation_contexts: OperationContexts,
+    tracer: Tracer,
 }
 
 impl Manager {
     pub async fn new() -> Result<Self> {
-        let (_, session) = BluetoothSession::new().await?;
-        Ok(Self { session })
+        rt::Io::new(async {
+            let (_, session) = BluetoothSession::new().await?;
+            // BlueZ doesn't record when a device last advertised, so keep track of it ourselves for
+            // as long as the manager is alive, until BlueZ removes the device.
+            let last_seen = Arc::new(Mutex::new(HashMap::new()));
+            let events = session.event_stream().await?;
+            rt::spawn_io(track_last_seen(events, Arc::downgrade(&last_seen)));
+            let object_cache = ObjectCache::new(last_seen.clone()).await?;
+            Ok(Self {
+                session,
+                last_seen,
+                service_cache: ServiceCache::default(),
+                object_cache,
+                operation_contexts: OperationContexts::default(),
+                tracer: Tracer::default(),
+            })
+        })
+        .await
+    }
+
+    /// Registers `agent` with BlueZ as the default pairing agent, which BlueZ asks to confirm
+    /// pairing or to show or provide a PIN when it pairs by itself, such as when a characteristic
+    /// needs a paired link, or when a device asks to pair. Without an agent, pairing with devices
+    /// which need a PIN fails. This replaces any other default agent, such as the desktop's, until
+    /// the returned registration is dropped.
+    ///
+    /// [`Peripheral::pair_with_agent`](api::Peripheral::pair_with_agent) doesn't need this, as it
+    /// registers the agent it is given while pairing.
+    pub async fn register_agent(
+        &self,
+        capability: AgentCapability,
+        agent: Arc<dyn PairingAgent>,
+    ) -> Result<AgentRegistration> {
+        rt::Io::new(AgentRegistration::register(capability, agent, true)).await
+    }
+}
+
+async fn track_last_seen(
+    mut events: impl Stream<Item = BluetoothEvent> + Unpin,
+    last_seen: Weak<Mutex<HashMap<DeviceId, SystemTime>>>,
+) {
+    while let Some(event) = events.next().await {
+        let Some(last_seen) = last_seen.upgrade() else {
+            break;
+        };
+        if let BluetoothEvent::Device {
+            id,
+            event:
+                DeviceEvent::Discovered
+                | DeviceEvent::Rssi { .. }
+                | DeviceEvent::ManufacturerData { .. }
+                | DeviceEvent::ServiceData { .. },
+        } = event
+        {
+            last_seen.lock().unwrap().insert(id, SystemTime::now());
+        }
+    }
+}
+
+enum AdapterChange {
+    Added(Path<'static>),
+    Removed(Path<'static>),
+}
+
+/// Aborts the task driving our D-Bus connection when the adapter event stream is dropped, which
+/// closes the connection and all of its matches with it.
+struct ConnectionGuard(Task);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+struct AdapterWatch {
+    changes: Pin<Box<dyn Stream<Item = AdapterChange> + Send>>,
+    adapters: HashMap<Path<'static>, Adapter>,
+    manager: Manager,
+    _connection: ConnectionGuard,
+}
+
+impl Manager {
+    fn adapter(&self, id: bluez_async::AdapterId) -> Adapter {
+        Adapter::new(
+            self.session.clone(),
+            id,
+            self.last_seen.clone(),
+            self.service_cache.clone(),
+            self.object_cache.clone(),
+            &self.operation_contexts,
+            &self.tracer,
+        )
+    }
+
+    /// Listens for BlueZ adding and removing adapter objects. bluez_async doesn't expose these
+    /// signals, so this uses a D-Bus connection of its own.
+    async fn adapter_changes(
+        &self,
+    ) -> std::result::Result<
+        (
+            ConnectionGuard,
+            impl Stream<Item = AdapterChange> + Send + 'static,
+        ),
+        BluetoothError,
+    > {
+        let (resource, connection) = dbus_tokio::connection::new_system_sync()?;
+        let guard = ConnectionGuard(rt::spawn_io(async {
+            resource.await;
+        }));
+        let bus_name = "org.bluez".into();
+        let (_added_match, added) = connection
+            .add_match(
+                ObjectManagerInterfacesAdded::match_rule(Some(&bus_name), None).static_clone(),
+            )
+            .await?
+            .stream::<ObjectManagerInterfacesAdded>();
+        let (_removed_match, removed) = connection
+            .add_match(
+                ObjectManagerInterfacesRemoved::match_rule(Some(&bus_name), None).static_clone(),
+            )
+            .await?
+            .stream::<ObjectManagerInterfacesRemoved>();
+        let added = added.filter_map(|(_, signal)| async move {
+            signal
+                .interfaces
+                .contains_key(ADAPTER_INTERFACE)
+                .then_some(AdapterChange::Added(signal.object))
+        });
+        let removed = removed.filter_map(|(_, signal)| async move {
+            signal
+                .interfaces
+                .iter()
+                .any(|interface| interface == ADAPTER_INTERFACE)
+                .then_some(AdapterChange::Removed(signal.object))
+        });
+        Ok((guard, stream::select(added, removed)))
+    }
+}
+
+impl AdapterWatch {
+    async fn next_event(&mut self) -> Option<ManagerEvent<Adapter>> {
+        while let Some(change) = self.changes.next().await {
+            match change {
+                AdapterChange::Added(path) => {
+                    if self.adapters.contains_key(&path) {
+                        continue;
+                    }
+                    let Ok(adapters) = self.manager.session.get_adapters().await else {
+                        continue;
+                    };
+                    let Some(info) = adapters
+                        .into_iter()
+                        .find(|info| Path::from(info.id.clone()) == path)
+                    else {
+                        continue;
+                    };
+                    let adapter = self.manager.adapter(info.id);
+                    self.adapters.insert(path, adapter.clone());
+                    return Some(ManagerEvent::AdapterAdded(adapter));
+                }
+                AdapterChange::Removed(path) => {
+                    if let Some(adapter) = self.adapters.remove(&path) {
+                        return Some(ManagerEvent::AdapterRemoved(adapter));
+                    }
+                }
+            }
+        }
+        None
     }
 }
 
@@ -21,10 +212,52 @@ impl api::Manager for Manager {
     type Adapter = Adapter;
 
     async fn adapters(&self) -> Result<Vec<Adapter>> {
-        let adapters = self.session.get_adapters().await?;
-        Ok(adapters
-            .into_iter()
-            .map(|adapter| Adapter::new(self.session.clone(), adapter.id))
-            .collect())
+        rt::Io::new(async {
+            let adapters = self.session.get_adapters().await?;
+            Ok(adapters
+                .into_iter()
+                .map(|adapter| self.adapter(adapter.id))
+                .collect())
+        })
+        .await
+    }
+
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = ManagerEvent<Adapter>> + Send>>> {
+        let events = rt::Io::new(async {
+            // Subscribe before listing the current adapters so that we don't miss one being added in
+            // between. Duplicates are filtered out by `AdapterWatch`.
+            let (connection, changes) = self.adapter_changes().await?;
+            let adapters: HashMap<_, _> = self
+                .session
+                .get_adapters()
+                .await?
+                .into_iter()
+                .map(|info| (Path::from(info.id.clone()), self.adapter(info.id)))
+                .collect();
+            let initial_events = stream::iter(
+                adapters
+                    .values()
+                    .cloned()
+                    .map(ManagerEvent::AdapterAdded)
+                    .collect::<Vec<_>>(),
+            );
+            let watch = AdapterWatch {
+                changes: Box::pin(changes),
+                adapters,
+                manager: self.clone(),
+                _connection: connection,
+            };
+            let changes = stream::unfold(watch, |mut watch| async move {
+                let event = watch.next_event().await?;
+                Some((event, watch))
+            });
+            Ok::<_, Error>(initial_events.chain(changes))
+        })
+        .await?;
+        Ok(Box::pin(rt::Io::new(events)))
+    }
+
+    fn set_trace_handler(&self, handler: Option<Arc<dyn TraceHandler>>) {
+        self.tracer.set(handler);
     }
 }
@@ -1,3 +1,7 @@
 pub mod adapter;
+mod advertising;
+pub mod agent;
 pub mod manager;
+mod monitor;
+mod objects;
 pub mod peripheral;
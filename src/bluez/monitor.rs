@@ -0,0 +1,237 @@
+//! Offloads advertisement monitors to BlueZ with `org.bluez.AdvertisementMonitor1`. bluez_async
+//! doesn't support it, and BlueZ calls back into an object which the application exports, so this
+//! talks to BlueZ on a connection of its own.
+
+use super::objects::object_id;
+use super::peripheral::PeripheralId;
+use crate::api::monitor::{AdvertisementMonitor, MonitorEvent};
+use crate::api::spans;
+use crate::rt::{self, Task};
+use crate::Result;
+use bluez_async::{AdapterId, BluetoothError, BluetoothSession};
+use dbus::{
+    arg::{PropMap, RefArg, Variant},
+    channel::{MatchingReceiver, Sender, Token},
+    message::MatchRule,
+    nonblock::{Proxy, SyncConnection},
+    strings::ErrorName,
+    Message, Path,
+};
+use futures::channel::mpsc;
+use futures::stream::{Stream, StreamExt};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const MANAGER_INTERFACE: &str = "org.bluez.AdvertisementMonitorManager1";
+const MONITOR_INTERFACE: &str = "org.bluez.AdvertisementMonitor1";
+
+/// Each monitor gets its own object path, so that several can be registered at once.
+static NEXT_MONITOR: AtomicUsize = AtomicUsize::new(0);
+
+/// Unregisters the monitor once the stream of its events is dropped.
+struct Registration {
+    connection: Arc<SyncConnection>,
+    resource: Option<Task>,
+    adapter: AdapterId,
+    root: Path<'static>,
+    token: Token,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.connection.stop_receive(self.token);
+        let resource = self.resource.take().unwrap();
+        if !rt::can_spawn() {
+            resource.abort();
+            return;
+        }
+        let proxy = adapter_proxy(&self.adapter, self.connection.clone());
+        let root = self.root.clone();
+        rt::spawn_io(async move {
+            let _: std::result::Result<(), dbus::Error> = proxy
+                .method_call(MANAGER_INTERFACE, "UnregisterMonitor", (root,))
+                .await;
+            resource.abort();
+        });
+    }
+}
+
+fn adapter_proxy(
+    adapter: &AdapterId,
+    connection: Arc<SyncConnection>,
+) -> Proxy<'static, Arc<SyncConnection>> {
+    Proxy::new(
+        "org.bluez",
+        Path::from(adapter.clone()),
+        Duration::from_secs(5),
+        connection,
+    )
+}
+
+/// Registers `monitor` with BlueZ, and returns the events it sends. Returns `None` if this version
+/// of BlueZ or the kernel doesn't support advertisement monitors.
+pub(super) async fn register(
+    session: &BluetoothSession,
+    adapter: &AdapterId,
+    monitor: &AdvertisementMonitor,
+) -> Result<Option<Pin<Box<dyn Stream<Item = MonitorEvent> + Send>>>> {
+    let (resource, connection) =
+        dbus_tokio::connection::new_system_sync().map_err(BluetoothError::from)?;
+    let resource = rt::spawn_io(async {
+        resource.await;
+    });
+    let root = Path::from(format!(
+        "/io/github/btleplug/monitor{}",
+        NEXT_MONITOR.fetch_add(1, Ordering::Relaxed)
+    ));
+    let object = Path::from(format!("{}/0", root));
+
+    let (sender, receiver) = mpsc::unbounded();
+    let token = connection.start_receive(
+        MatchRule::new_method_call(),
+        Box::new({
+            let monitor = monitor.clone();
+            let root = root.clone();
+            move |message, connection| {
+                let reply = handle_call(&message, &root, &object, &monitor, &sender);
+                let _ = connection.send(reply);
+                true
+            }
+        }),
+    );
+    let registration = Registration {
+        connection: connection.clone(),
+        resource: Some(resource),
+        adapter: adapter.clone(),
+        root: root.clone(),
+        token,
+    };
+
+    let registered: std::result::Result<(), dbus::Error> = adapter_proxy(adapter, connection)
+        .method_call(MANAGER_INTERFACE, "RegisterMonitor", (root,))
+        .await;
+    match registered {
+        Ok(()) => {}
+        Err(e)
+            if matches!(
+                e.name(),
+                Some("org.freedesktop.DBus.Error.UnknownInterface")
+                    | Some("org.freedesktop.DBus.Error.UnknownMethod")
+                    | Some("org.freedesktop.DBus.Error.UnknownObject")
+            ) =>
+        {
+            return Ok(None);
+        }
+        Err(e) => return Err(BluetoothError::from(e).into()),
+    }
+
+    let session = session.clone();
+    let adapter = adapter.clone();
+    Ok(Some(Box::pin(receiver.filter_map(
+        move |(found, device): (bool, Path<'static>)| {
+            // Keep the monitor registered for as long as the stream is around.
+            let _registration = &registration;
+            let session = session.clone();
+            let adapter = adapter.clone();
+            let peripheral = PeripheralId(object_id(device.to_string()));
+            let span = spans::callback("advertisement monitor", Some(&peripheral));
+            span.instrument(async move {
+                let id: PeripheralId = session
+                    .get_devices_on_adapter(&adapter)
+                    .await
+                    .ok()?
+                    .into_iter()
+                    .find(|info| Path::from(info.id.clone()) == device)?
+                    .id
+                    .into();
+                Some(if found {
+                    MonitorEvent::DeviceFound(id)
+                } else {
+                    MonitorEvent::DeviceLost(id)
+                })
+            })
+        },
+    ))))
+}
+
+/// Answers a call from BlueZ to the monitor's object, or to the object manager at `root` which
+/// BlueZ finds it through, passing on found and lost devices to `sender`.
+fn handle_call(
+    message: &Message,
+    root: &Path<'static>,
+    object: &Path<'static>,
+    monitor: &AdvertisementMonitor,
+    sender: &mpsc::UnboundedSender<(bool, Path<'static>)>,
+) -> Message {
+    let path = message.path();
+    let interface = message.interface();
+    let member = message.member();
+    match (path.as_deref(), interface.as_deref(), member.as_deref()) {
+        (Some(path), Some("org.freedesktop.DBus.ObjectManager"), Some("GetManagedObjects"))
+            if path == &**root =>
+        {
+            let mut interfaces = HashMap::new();
+            interfaces.insert(MONITOR_INTERFACE.to_string(), properties(monitor));
+            let mut objects = HashMap::new();
+            objects.insert(object.clone(), interfaces);
+            message.method_return().append1(objects)
+        }
+        (Some(path), Some("org.freedesktop.DBus.Properties"), Some("GetAll"))
+            if path == &**object =>
+        {
+            let properties = match message.read1::<&str>() {
+                Ok(MONITOR_INTERFACE) => properties(monitor),
+                _ => PropMap::new(),
+            };
+            message.method_return().append1(properties)
+        }
+        (Some(path), Some(MONITOR_INTERFACE), Some(member)) if path == &**object => {
+            match member {
+                "Release" | "Activate" => {}
+                "DeviceFound" | "DeviceLost" => {
+                    if let Ok(device) = message.read1::<Path>() {
+                        let _ =
+                            sender.unbounded_send((member == "DeviceFound", device.into_static()));
+                    }
+                }
+                _ => return unknown_method(message),
+            }
+            message.method_return()
+        }
+        _ => unknown_method(message),
+    }
+}
+
+fn unknown_method(message: &Message) -> Message {
+    message.error(
+        &ErrorName::from("org.freedesktop.DBus.Error.UnknownMethod"),
+        &CString::new("Unknown method").unwrap(),
+    )
+}
+
+/// The properties of the monitor's object, which tell BlueZ what to match.
+fn properties(monitor: &AdvertisementMonitor) -> PropMap {
+    let mut properties: PropMap = HashMap::new();
+    let mut insert = |name: &str, value: Box<dyn RefArg>| {
+        properties.insert(name.to_string(), Variant(value));
+    };
+    insert("Type", Box::new("or_patterns".to_string()));
+    let patterns: Vec<(u8, u8, Vec<u8>)> = monitor
+        .patterns
+        .iter()
+        .map(|pattern| (pattern.offset, pattern.ad_type, pattern.content.clone()))
+        .collect();
+    insert("Patterns", Box::new(patterns));
+    if let Some(rssi) = monitor.rssi {
+        let seconds = |timeout: Duration| timeout.as_secs().clamp(1, u16::MAX.into()) as u16;
+        insert("RSSIHighThreshold", Box::new(rssi.high));
+        insert("RSSIHighTimeout", Box::new(seconds(rssi.high_timeout)));
+        insert("RSSILowThreshold", Box::new(rssi.low));
+        insert("RSSILowTimeout", Box::new(seconds(rssi.low_timeout)));
+    }
+    properties
+}
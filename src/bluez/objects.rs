@@ -0,0 +1,480 @@
+//! Copies of BlueZ's D-Bus objects, so that a peripheral's properties and services can be read
+//! without a round trip to bluetoothd for each object.
+//!
+//! bluez_async reads each device's properties with a `GetAll` call of its own, and walks a
+//! device's GATT objects one call at a time, which adds up to seconds with hundreds of devices or
+//! attributes. Instead, all of BlueZ's objects are read with one `GetManagedObjects` call, and the
+//! properties of the devices are then kept up to date from BlueZ's `PropertiesChanged` and
+//! `InterfacesRemoved` signals.
+
+use super::peripheral::LastSeen;
+use crate::rt::{self, Task};
+use bluez_async::{
+    AdapterId, BluetoothError, BluetoothSession, CharacteristicInfo, DescriptorInfo, DeviceId,
+    DeviceInfo, ServiceInfo,
+};
+use dbus::{
+    arg::{cast, prop_cast, PropMap, RefArg, Variant},
+    message::SignalArgs,
+    nonblock::{
+        stdintf::org_freedesktop_dbus::{
+            ObjectManager, ObjectManagerInterfacesRemoved, PropertiesPropertiesChanged,
+        },
+        Proxy, SyncConnection,
+    },
+    Path,
+};
+use futures::future::ready;
+use futures::stream::{self, StreamExt};
+use serde_cr::{de::value::MapDeserializer, de::DeserializeOwned};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{self, Debug, Formatter};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+const DEVICE_INTERFACE: &str = "org.bluez.Device1";
+const SERVICE_INTERFACE: &str = "org.bluez.GattService1";
+const CHARACTERISTIC_INTERFACE: &str = "org.bluez.GattCharacteristic1";
+const DESCRIPTOR_INTERFACE: &str = "org.bluez.GattDescriptor1";
+
+/// As long as bluez_async waits for BlueZ to answer.
+const METHOD_CALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Makes up one of bluez_async's object IDs. They have no public constructor, but can be
+/// deserialized from their object path.
+pub(crate) fn object_id<T: DeserializeOwned>(path: String) -> T {
+    let fields = MapDeserializer::<_, serde_cr::de::value::Error>::new(std::iter::once((
+        "object_path",
+        path,
+    )));
+    T::deserialize(fields).expect("Invalid object path")
+}
+
+/// A GATT service of a device, along with everything under it.
+#[derive(Debug)]
+pub(crate) struct GattService {
+    pub(crate) info: ServiceInfo,
+    /// The UUIDs of the services this one includes.
+    pub(crate) included: BTreeSet<Uuid>,
+    /// In handle order, as are their descriptors.
+    pub(crate) characteristics: Vec<(CharacteristicInfo, Vec<DescriptorInfo>)>,
+}
+
+#[derive(Debug, Default)]
+struct Devices {
+    /// Whether the devices are being kept up to date, which stops if the connection is lost.
+    tracking: bool,
+    devices: HashMap<DeviceId, DeviceInfo>,
+    /// Counts the changes to devices, and records the count at each device's last change, so
+    /// that properties read before a change aren't kept over it.
+    changes: u64,
+    changed: HashMap<DeviceId, u64>,
+    /// The AD structures of each device's `AdvertisingData`, which bluez_async doesn't read, so
+    /// this only has what has changed since the cache was created.
+    advertising_data: HashMap<DeviceId, Arc<[u8]>>,
+}
+
+impl Devices {
+    fn insert(&mut self, since: u64, device: DeviceInfo) {
+        if self.tracking
+            && self
+                .changed
+                .get(&device.id)
+                .is_none_or(|&changed| changed <= since)
+        {
+            self.devices.insert(device.id.clone(), device);
+        }
+    }
+
+    fn properties_changed(&mut self, id: DeviceId, changed: &PropMap, invalidated: &[String]) {
+        self.changes += 1;
+        self.changed.insert(id.clone(), self.changes);
+        if let Some(data) = changed.get("AdvertisingData") {
+            match advertising_data(data) {
+                Some(data) => self.advertising_data.insert(id.clone(), data),
+                None => self.advertising_data.remove(&id),
+            };
+        } else if invalidated.iter().any(|name| name == "AdvertisingData") {
+            self.advertising_data.remove(&id);
+        }
+        let Some(device) = self.devices.get_mut(&id) else {
+            return;
+        };
+        let understood = changed
+            .iter()
+            .all(|(name, value)| apply(device, name, value).is_some())
+            && invalidated.iter().all(|name| invalidate(device, name));
+        // Read the device again next time, rather than keep it half updated.
+        if !understood {
+            self.devices.remove(&id);
+        }
+    }
+
+    fn removed(&mut self, id: &DeviceId) {
+        self.devices.remove(id);
+        self.changed.remove(id);
+        self.advertising_data.remove(id);
+    }
+}
+
+/// Puts BlueZ's `AdvertisingData`, a map from AD type to data, back together into AD structures.
+fn advertising_data(value: &Variant<Box<dyn RefArg>>) -> Option<Arc<[u8]>> {
+    let structures: BTreeMap<u8, &Vec<u8>> =
+        cast::<HashMap<u8, Variant<Box<dyn RefArg>>>>(&*value.0)?
+            .iter()
+            .map(|(&ad_type, data)| Some((ad_type, cast::<Vec<u8>>(&*data.0)?)))
+            .collect::<Option<_>>()?;
+    let mut data = Vec::new();
+    for (ad_type, value) in structures {
+        data.push(u8::try_from(value.len() + 1).ok()?);
+        data.push(ad_type);
+        data.extend_from_slice(value);
+    }
+    Some(data.into())
+}
+
+/// Applies the new value of one of a device's properties, or returns `None` if it's one we don't
+/// keep track of.
+fn apply(device: &mut DeviceInfo, name: &str, value: &Variant<Box<dyn RefArg>>) -> Option<()> {
+    let value = &*value.0;
+    match name {
+        "Name" => device.name = Some(cast::<String>(value)?.clone()),
+        "Alias" => device.alias = Some(cast::<String>(value)?.clone()),
+        "Icon" => device.icon = Some(cast::<String>(value)?.clone()),
+        "RSSI" => device.rssi = Some(*cast::<i16>(value)?),
+        "TxPower" => device.tx_power = Some(*cast::<i16>(value)?),
+        "Appearance" => device.appearance = Some(*cast::<u16>(value)?),
+        "Class" => device.class = Some(*cast::<u32>(value)?),
+        "Paired" => device.paired = *cast::<bool>(value)?,
+        "Bonded" => device.bonded = *cast::<bool>(value)?,
+        "Connected" => device.connected = *cast::<bool>(value)?,
+        "ServicesResolved" => device.services_resolved = *cast::<bool>(value)?,
+        "Trusted" => device.trusted = *cast::<bool>(value)?,
+        "Blocked" => device.blocked = *cast::<bool>(value)?,
+        "LegacyPairing" => device.legacy_pairing = *cast::<bool>(value)?,
+        "WakeAllowed" => device.wake_allowed = *cast::<bool>(value)?,
+        "UUIDs" => {
+            device.services = cast::<Vec<String>>(value)?
+                .iter()
+                .filter_map(|uuid| Uuid::parse_str(uuid).ok())
+                .collect()
+        }
+        "ManufacturerData" => {
+            device.manufacturer_data = cast::<HashMap<u16, Variant<Box<dyn RefArg>>>>(value)?
+                .iter()
+                .filter_map(|(&id, data)| Some((id, cast::<Vec<u8>>(&*data.0)?.clone())))
+                .collect()
+        }
+        // Kept apart from the device's properties, by `Devices::properties_changed`.
+        "AdvertisingData" => {}
+        "ServiceData" => {
+            device.service_data = cast::<PropMap>(value)?
+                .iter()
+                .filter_map(|(uuid, data)| {
+                    Some((
+                        Uuid::parse_str(uuid).ok()?,
+                        cast::<Vec<u8>>(&*data.0)?.clone(),
+                    ))
+                })
+                .collect()
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+/// Forgets the value of one of a device's properties, which BlueZ does for example with the RSSI
+/// when the device stops advertising. Returns whether it's one we keep track of.
+fn invalidate(device: &mut DeviceInfo, name: &str) -> bool {
+    match name {
+        "Name" => device.name = None,
+        "Alias" => device.alias = None,
+        "Icon" => device.icon = None,
+        "RSSI" => device.rssi = None,
+        "TxPower" => device.tx_power = None,
+        "Appearance" => device.appearance = None,
+        "Class" => device.class = None,
+        "UUIDs" => device.services.clear(),
+        "ManufacturerData" => device.manufacturer_data.clear(),
+        "ServiceData" => device.service_data.clear(),
+        "AdvertisingData" => {}
+        _ => return false,
+    }
+    true
+}
+
+/// Aborts the tasks driving our D-Bus connection and listening to it once the last copy of the
+/// cache is dropped.
+struct Connection {
+    connection: Arc<SyncConnection>,
+    tasks: [Task; 2],
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// The devices BlueZ knows, and a D-Bus connection of our own for reading its objects, shared
+/// between the manager, its adapters and their peripherals.
+#[derive(Clone)]
+pub(crate) struct ObjectCache {
+    connection: Arc<Connection>,
+    devices: Arc<Mutex<Devices>>,
+}
+
+impl Debug for ObjectCache {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("ObjectCache").finish_non_exhaustive()
+    }
+}
+
+enum Signal {
+    Changed(Path<'static>, PropertiesPropertiesChanged),
+    Removed(ObjectManagerInterfacesRemoved),
+}
+
+impl ObjectCache {
+    /// Connects to D-Bus and starts listening for changes to devices. No device is cached before
+    /// this returns, so none of the changes to a cached device are missed. Devices BlueZ removes
+    /// are forgotten in `last_seen` too.
+    pub(crate) async fn new(last_seen: LastSeen) -> Result<Self, BluetoothError> {
+        let (resource, connection) = dbus_tokio::connection::new_system_sync()?;
+        let resource = rt::spawn_io(async {
+            resource.await;
+        });
+        let bus_name = "org.bluez".into();
+        let subscribed = async {
+            let (changed_match, changed) = connection
+                .add_match(
+                    PropertiesPropertiesChanged::match_rule(Some(&bus_name), None).static_clone(),
+                )
+                .await?
+                .stream::<PropertiesPropertiesChanged>();
+            let (removed_match, removed) = connection
+                .add_match(
+                    ObjectManagerInterfacesRemoved::match_rule(Some(&bus_name), None)
+                        .static_clone(),
+                )
+                .await?
+                .stream::<ObjectManagerInterfacesRemoved>();
+            Ok::<_, dbus::Error>(((changed_match, removed_match), changed, removed))
+        };
+        let (matches, changed, removed) = match subscribed.await {
+            Ok(subscribed) => subscribed,
+            Err(error) => {
+                resource.abort();
+                return Err(error.into());
+            }
+        };
+        let changed = changed.filter_map(|(message, signal)| {
+            let path = message.path().map(|path| path.into_static());
+            ready(path.map(|path| Signal::Changed(path, signal)))
+        });
+        let removed = removed.map(|(_, signal)| Signal::Removed(signal));
+        let devices = Arc::new(Mutex::new(Devices {
+            tracking: true,
+            ..Default::default()
+        }));
+        let tracker = rt::spawn_io({
+            let devices = devices.clone();
+            async move {
+                let _matches = matches;
+                let mut signals = stream::select(changed, removed);
+                while let Some(signal) = signals.next().await {
+                    let mut devices = devices.lock().unwrap();
+                    match signal {
+                        Signal::Changed(path, signal)
+                            if signal.interface_name == DEVICE_INTERFACE =>
+                        {
+                            devices.properties_changed(
+                                object_id(path.to_string()),
+                                &signal.changed_properties,
+                                &signal.invalidated_properties,
+                            );
+                        }
+                        Signal::Removed(signal)
+                            if signal
+                                .interfaces
+                                .iter()
+                                .any(|name| name == DEVICE_INTERFACE) =>
+                        {
+                            let id = object_id(signal.object.to_string());
+                            last_seen.lock().unwrap().remove(&id);
+                            devices.removed(&id);
+                        }
+                        _ => {}
+                    }
+                }
+                // The connection was lost, so the copies can't be kept up to date any more.
+                let mut devices = devices.lock().unwrap();
+                devices.tracking = false;
+                devices.devices.clear();
+                devices.advertising_data.clear();
+            }
+        });
+        Ok(ObjectCache {
+            connection: Arc::new(Connection {
+                connection,
+                tasks: [resource, tracker],
+            }),
+            devices,
+        })
+    }
+
+    /// A proxy for one of BlueZ's objects on our connection, for the calls bluez_async doesn't
+    /// have, so that they don't each need a connection of their own.
+    pub(crate) fn proxy(
+        &self,
+        path: impl Into<Path<'static>>,
+    ) -> Proxy<'static, Arc<SyncConnection>> {
+        Proxy::new(
+            "org.bluez",
+            path,
+            METHOD_CALL_TIMEOUT,
+            self.connection.connection.clone(),
+        )
+    }
+
+    fn changes(&self) -> u64 {
+        self.devices.lock().unwrap().changes
+    }
+
+    /// Returns the device's properties, only asking BlueZ for them if they aren't cached.
+    pub(crate) async fn device_info(
+        &self,
+        session: &BluetoothSession,
+        id: &DeviceId,
+    ) -> Result<DeviceInfo, BluetoothError> {
+        if let Some(device) = self.devices.lock().unwrap().devices.get(id) {
+            return Ok(device.clone());
+        }
+        let since = self.changes();
+        let device = session.get_device_info(id).await?;
+        self.devices.lock().unwrap().insert(since, device.clone());
+        Ok(device)
+    }
+
+    /// Returns the AD structures of the device's `AdvertisingData`, if BlueZ has reported any.
+    pub(crate) fn advertising_data(&self, id: &DeviceId) -> Option<Arc<[u8]>> {
+        self.devices
+            .lock()
+            .unwrap()
+            .advertising_data
+            .get(id)
+            .cloned()
+    }
+
+    /// Returns the devices BlueZ knows on the adapter, read with one `GetManagedObjects` call, and
+    /// caches them.
+    pub(crate) async fn devices_on_adapter(
+        &self,
+        session: &BluetoothSession,
+        adapter: &AdapterId,
+    ) -> Result<Vec<DeviceInfo>, BluetoothError> {
+        let since = self.changes();
+        let devices = session.get_devices_on_adapter(adapter).await?;
+        let mut cache = self.devices.lock().unwrap();
+        for device in &devices {
+            cache.insert(since, device.clone());
+        }
+        Ok(devices)
+    }
+
+    /// Reads the device's GATT services, characteristics and descriptors with one
+    /// `GetManagedObjects` call, in handle order.
+    pub(crate) async fn gatt_services(
+        &self,
+        device: &DeviceId,
+    ) -> Result<Vec<GattService>, BluetoothError> {
+        let objects = self.proxy("/").get_managed_objects().await?;
+        let prefix = format!("{}/", Path::from(device.clone()));
+        // BlueZ names GATT objects after their handles, in fixed width hex, so sorting by path
+        // puts them in handle order under their parents.
+        let objects: BTreeMap<String, HashMap<String, PropMap>> = objects
+            .into_iter()
+            .map(|(path, interfaces)| (path.to_string(), interfaces))
+            .filter(|(path, _)| path.starts_with(&prefix))
+            .collect();
+
+        let mut services = BTreeMap::new();
+        let mut includes = HashMap::new();
+        let mut characteristics = BTreeMap::new();
+        let mut descriptors = Vec::new();
+        for (path, interfaces) in &objects {
+            if let Some(properties) = interfaces.get(SERVICE_INTERFACE) {
+                let info = ServiceInfo {
+                    id: object_id(path.clone()),
+                    uuid: uuid(properties)?,
+                    primary: *prop_cast::<bool>(properties, "Primary")
+                        .ok_or(BluetoothError::RequiredPropertyMissing("Primary"))?,
+                };
+                let included: Vec<String> = prop_cast::<Vec<Path<'static>>>(properties, "Includes")
+                    .into_iter()
+                    .flatten()
+                    .map(|path| path.to_string())
+                    .collect();
+                includes.insert(path.as_str(), included);
+                services.insert(path.as_str(), info);
+            } else if let Some(properties) = interfaces.get(CHARACTERISTIC_INTERFACE) {
+                let flags = prop_cast::<Vec<String>>(properties, "Flags")
+                    .ok_or(BluetoothError::RequiredPropertyMissing("Flags"))?;
+                let info = CharacteristicInfo {
+                    id: object_id(path.clone()),
+                    uuid: uuid(properties)?,
+                    flags: flags.as_slice().try_into()?,
+                    mtu: prop_cast::<u16>(properties, "MTU").copied(),
+                };
+                characteristics.insert(path.as_str(), (info, Vec::new()));
+            } else if let Some(properties) = interfaces.get(DESCRIPTOR_INTERFACE) {
+                let info = DescriptorInfo {
+                    id: object_id(path.clone()),
+                    uuid: uuid(properties)?,
+                };
+                descriptors.push((path.as_str(), info));
+            }
+        }
+
+        for (path, descriptor) in descriptors {
+            if let Some((_, descriptors)) = parent(path).and_then(|c| characteristics.get_mut(c)) {
+                descriptors.push(descriptor);
+            }
+        }
+        let mut gatt_services: BTreeMap<&str, GattService> = services
+            .iter()
+            .map(|(&path, info)| {
+                let included = includes[path]
+                    .iter()
+                    .filter_map(|included| services.get(included.as_str()))
+                    .map(|service| service.uuid)
+                    .collect();
+                let service = GattService {
+                    info: info.clone(),
+                    included,
+                    characteristics: Vec::new(),
+                };
+                (path, service)
+            })
+            .collect();
+        for (path, characteristic) in characteristics {
+            if let Some(service) = parent(path).and_then(|s| gatt_services.get_mut(s)) {
+                service.characteristics.push(characteristic);
+            }
+        }
+        Ok(gatt_services.into_values().collect())
+    }
+}
+
+fn uuid(properties: &PropMap) -> Result<Uuid, BluetoothError> {
+    let uuid = prop_cast::<String>(properties, "UUID")
+        .ok_or(BluetoothError::RequiredPropertyMissing("UUID"))?;
+    Ok(Uuid::parse_str(uuid)?)
+}
+
+fn parent(path: &str) -> Option<&str> {
+    Some(path.rsplit_once('/')?.0)
+}
@@ -1,27 +1,62 @@
 use async_trait::async_trait;
 use bluez_async::{
-    BluetoothEvent, BluetoothSession, CharacteristicEvent, CharacteristicFlags, CharacteristicId,
-    CharacteristicInfo, DescriptorInfo, DeviceId, DeviceInfo, MacAddress, ServiceInfo,
-    WriteOptions,
+    BluetoothError, BluetoothEvent, BluetoothSession, CharacteristicEvent, CharacteristicFlags,
+    CharacteristicId, CharacteristicInfo, DescriptorInfo, DeviceEvent, DeviceId, DeviceInfo,
+    MacAddress, ServiceInfo, WriteOptions,
 };
-use futures::future::{join_all, ready};
-use futures::stream::{Stream, StreamExt};
+use dbus::{
+    arg::PropMap,
+    nonblock::{stdintf::org_freedesktop_dbus::Properties, Proxy},
+    Path,
+};
+use futures::future::ready;
+use futures::stream::{self, Stream, StreamExt};
+use log::debug;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "serde")]
 use serde_cr as serde;
 use std::collections::{BTreeSet, HashMap};
 use std::fmt::{self, Display, Formatter};
+use std::hash::Hash;
+use std::os::fd::FromRawFd;
 use std::pin::Pin;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
 use uuid::Uuid;
 
+use super::adapter::mgmt_index;
+use super::agent::{AgentCapability, AgentRegistration};
+use super::objects::ObjectCache;
 use crate::api::{
-    self, AddressType, BDAddr, CharPropFlags, Characteristic, Descriptor, PeripheralProperties,
-    Service, ValueNotification, WriteType,
+    self,
+    descriptor_cache::DescriptorCache,
+    link::{self, LinkSample},
+    operation::OperationContext,
+    pairing::{OobData, PairingAgent},
+    peripheral_events::PeripheralEventSender,
+    rssi::RssiStats,
+    spans, AddressType, AttError, BDAddr, CharPropFlags, Characteristic, DataLength, Descriptor,
+    DisconnectReason, PeripheralEvent, PeripheralProperties, SecurityLevel, Service,
+    SubscriptionType, Transport, ValueNotification, WriteType,
 };
+use crate::linux::{controller::Controller, mgmt::Mgmt, sys::Socket};
+use crate::rt::{self, Task};
 use crate::{Error, Result};
 
+/// How long BlueZ has to pair with a device when the agent may be waiting for the user.
+const PAIRING_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How long BlueZ has to pair with a device using out-of-band data, which needs no input.
+const OOB_PAIRING_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long BlueZ has to connect over one bearer, which is about as long as it takes to give up.
+const BEARER_CONNECT_TIMEOUT: Duration = Duration::from_secs(45);
+
+const CHARACTERISTIC_INTERFACE: &str = "org.bluez.GattCharacteristic1";
+
 #[derive(Clone, Debug)]
 struct CharacteristicInternal {
     info: CharacteristicInfo,
@@ -38,6 +73,7 @@ impl CharacteristicInternal {
 struct ServiceInternal {
     info: ServiceInfo,
     characteristics: HashMap<Uuid, CharacteristicInternal>,
+    included_services: BTreeSet<Uuid>,
 }
 
 #[cfg_attr(
@@ -54,13 +90,137 @@ impl Display for PeripheralId {
     }
 }
 
+#[cfg(any(feature = "mock", feature = "hci"))]
+impl PeripheralId {
+    /// The ID of a peripheral of the [mock](crate::mock) or [hci](crate::hci) backend, in the same
+    /// form as one from BlueZ, e.g. `hci0/dev_00_11_22_33_44_55`.
+    pub(crate) fn synthetic(adapter_id: &str, address: BDAddr) -> Self {
+        let path = format!(
+            "/org/bluez/{}/dev_{}",
+            adapter_id,
+            address.to_string().replace(':', "_")
+        );
+        PeripheralId(super::objects::object_id(path))
+    }
+}
+
+/// The time each device was last seen advertising, shared between the manager, its adapters and
+/// their peripherals.
+pub(crate) type LastSeen = Arc<Mutex<HashMap<DeviceId, SystemTime>>>;
+
+type Services = Arc<Mutex<HashMap<Uuid, ServiceInternal>>>;
+
+/// The services discovered on each device and the descriptor values read from them, shared between
+/// the manager, its adapters and their peripherals, so that every `Peripheral` for a device sees
+/// the same services and they can be refreshed when the device's services change. Their events
+/// which BlueZ doesn't signal are sent through it too.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ServiceCache {
+    devices: Arc<Mutex<HashMap<DeviceId, (Services, DescriptorCache)>>>,
+    events: PeripheralEventSender,
+    acquired: AcquiredNotifications,
+}
+
+impl ServiceCache {
+    fn for_device(&self, device: &DeviceId) -> (Services, DescriptorCache) {
+        self.devices
+            .lock()
+            .unwrap()
+            .entry(device.clone())
+            .or_default()
+            .clone()
+    }
+
+    pub(crate) fn remove(&self, device: &DeviceId) {
+        self.devices.lock().unwrap().remove(device);
+    }
+}
+
+/// The notifications of the characteristics whose socket has been acquired with `AcquireNotify`,
+/// which BlueZ sends down the socket rather than as changes to the characteristic's `Value`.
+#[derive(Clone, Debug)]
+struct AcquiredNotifications {
+    /// The task reading each characteristic's socket, which closes it when aborted.
+    readers: Arc<Mutex<HashMap<CharacteristicId, Task>>>,
+    values: broadcast::Sender<(CharacteristicId, Vec<u8>)>,
+}
+
+impl Default for AcquiredNotifications {
+    fn default() -> Self {
+        // Notifications can come in much faster than peripheral events.
+        let (values, _) = broadcast::channel(256);
+        AcquiredNotifications {
+            readers: Default::default(),
+            values,
+        }
+    }
+}
+
+impl AcquiredNotifications {
+    /// Whether the characteristic's socket is still being read, which stops when the device
+    /// disconnects.
+    fn is_reading(&self, characteristic: &CharacteristicId) -> bool {
+        self.readers
+            .lock()
+            .unwrap()
+            .get(characteristic)
+            .is_some_and(|reader| !reader.is_finished())
+    }
+
+    fn start(&self, characteristic: CharacteristicId, socket: Socket, mtu: u16) {
+        let values = self.values.clone();
+        let id = characteristic.clone();
+        let reader = rt::spawn_io(async move {
+            while let Ok(value) = socket.recv(mtu.into()).await {
+                let _ = values.send((id.clone(), value));
+            }
+        });
+        if let Some(previous) = self.readers.lock().unwrap().insert(characteristic, reader) {
+            previous.abort();
+        }
+    }
+
+    /// Stops reading the characteristic's socket, which tells BlueZ to stop notifying. Returns
+    /// whether it was being read.
+    fn release(&self, characteristic: &CharacteristicId) -> bool {
+        let Some(reader) = self.readers.lock().unwrap().remove(characteristic) else {
+            return false;
+        };
+        let reading = !reader.is_finished();
+        reader.abort();
+        reading
+    }
+
+    /// The notifications of the device's acquired characteristics, as BlueZ would signal them
+    /// otherwise.
+    fn events(&self, device: DeviceId) -> impl Stream<Item = BluetoothEvent> + Send {
+        BroadcastStream::new(self.values.subscribe()).filter_map(move |value| {
+            ready(match value {
+                Ok((id, value)) if id.service().device() == device => {
+                    Some(BluetoothEvent::Characteristic {
+                        id,
+                        event: CharacteristicEvent::Value { value },
+                    })
+                }
+                _ => None,
+            })
+        })
+    }
+}
+
 /// Implementation of [api::Peripheral](crate::api::Peripheral).
 #[derive(Clone, Debug)]
 pub struct Peripheral {
     session: BluetoothSession,
     device: DeviceId,
     mac_address: BDAddr,
-    services: Arc<Mutex<HashMap<Uuid, ServiceInternal>>>,
+    services: Services,
+    descriptor_cache: DescriptorCache,
+    events: PeripheralEventSender,
+    acquired: AcquiredNotifications,
+    last_seen: LastSeen,
+    object_cache: ObjectCache,
+    operation_context: OperationContext,
 }
 
 fn get_characteristic<'a>(
@@ -87,15 +247,96 @@ fn get_characteristic<'a>(
 }
 
 impl Peripheral {
-    pub(crate) fn new(session: BluetoothSession, device: DeviceInfo) -> Self {
+    pub(crate) fn new(
+        session: BluetoothSession,
+        device: DeviceInfo,
+        last_seen: LastSeen,
+        service_cache: &ServiceCache,
+        object_cache: ObjectCache,
+        operation_context: OperationContext,
+    ) -> Self {
+        let (services, descriptor_cache) = service_cache.for_device(&device.id);
         Peripheral {
             session,
+            services,
+            descriptor_cache,
+            events: service_cache.events.clone(),
+            acquired: service_cache.acquired.clone(),
+            object_cache,
+            operation_context: operation_context.for_peripheral(PeripheralId(device.id.clone())),
             device: device.id,
             mac_address: device.mac_address.into(),
-            services: Arc::new(Mutex::new(HashMap::new())),
+            last_seen,
         }
     }
 
+    /// Whether services have been discovered for this device.
+    pub(crate) fn has_services(&self) -> bool {
+        !self.services.lock().unwrap().is_empty()
+    }
+
+    /// Fetches the services with the given UUIDs and the services they include, or all services,
+    /// along with their characteristics and descriptors. BlueZ has already discovered them all
+    /// when it connected, and they're all read with one D-Bus call, so filtering just saves
+    /// keeping the rest.
+    async fn discover_services_matching(&self, uuids: Option<&[Uuid]>) -> Result<()> {
+        let services = self.object_cache.gatt_services(&self.device).await?;
+        let wanted = uuids.map(|uuids| {
+            let included_services: HashMap<Uuid, &BTreeSet<Uuid>> = services
+                .iter()
+                .map(|service| (service.info.uuid, &service.included))
+                .collect();
+            let mut wanted: BTreeSet<Uuid> = uuids.iter().copied().collect();
+            let mut pending: Vec<Uuid> = uuids.to_vec();
+            while let Some(uuid) = pending.pop() {
+                for &included in included_services.get(&uuid).into_iter().copied().flatten() {
+                    if wanted.insert(included) {
+                        pending.push(included);
+                    }
+                }
+            }
+            wanted
+        });
+        let mut services_internal = HashMap::new();
+        for service in services {
+            if wanted
+                .as_ref()
+                .is_some_and(|wanted| !wanted.contains(&service.info.uuid))
+            {
+                continue;
+            }
+            let mut characteristics = HashMap::new();
+            for (characteristic, descriptors) in service.characteristics {
+                // Only consider the first characteristic of each UUID
+                // This "should" be unique, but of course it's not enforced
+                characteristics
+                    .entry(characteristic.uuid)
+                    .or_insert_with(|| {
+                        let descriptors = descriptors
+                            .into_iter()
+                            .map(|descriptor| (descriptor.uuid, descriptor))
+                            .collect();
+                        CharacteristicInternal::new(characteristic, descriptors)
+                    });
+            }
+            services_internal.insert(
+                service.info.uuid,
+                ServiceInternal {
+                    info: service.info,
+                    characteristics,
+                    included_services: service.included,
+                },
+            );
+        }
+        let mut services = self.services.lock().map_err(Into::<Error>::into)?;
+        if uuids.is_some() {
+            services.extend(services_internal);
+        } else {
+            *services = services_internal;
+        }
+        Ok(())
+    }
+
     fn characteristic_info(&self, characteristic: &Characteristic) -> Result<CharacteristicInfo> {
         let services = self.services.lock().map_err(Into::<Error>::into)?;
         get_characteristic(
@@ -107,6 +348,45 @@ impl Peripheral {
         .cloned()
     }
 
+    /// Acquires a socket for the characteristic's notifications where BlueZ supports it, so that
+    /// they don't each take a D-Bus signal. Returns whether it did, so that `StartNotify` can be
+    /// used instead with BlueZ before 5.46, for characteristics which only indicate, and for ones
+    /// which another client has already acquired.
+    async fn acquire_notify(&self, characteristic: &CharacteristicInfo) -> bool {
+        if !characteristic.flags.contains(CharacteristicFlags::NOTIFY) {
+            return false;
+        }
+        if self.acquired.is_reading(&characteristic.id) {
+            return true;
+        }
+        let proxy = self.object_cache.proxy(characteristic.id.clone());
+        // BlueZ only has this property where AcquireNotify is supported.
+        let acquired: std::result::Result<bool, dbus::Error> =
+            proxy.get(CHARACTERISTIC_INTERFACE, "NotifyAcquired").await;
+        if !matches!(acquired, Ok(false)) {
+            return false;
+        }
+        let socket: std::result::Result<(dbus::arg::OwnedFd, u16), dbus::Error> = proxy
+            .method_call(CHARACTERISTIC_INTERFACE, "AcquireNotify", (PropMap::new(),))
+            .await;
+        let (fd, mtu) = match socket {
+            Ok(socket) => socket,
+            Err(e) => {
+                debug!("AcquireNotify failed, falling back to StartNotify: {:?}", e);
+                return false;
+            }
+        };
+        // SAFETY: the descriptor came with the reply, and nothing else owns it.
+        let fd = unsafe { std::os::fd::OwnedFd::from_raw_fd(fd.into_fd()) };
+        match Socket::from_fd(fd) {
+            Ok(socket) => {
+                self.acquired.start(characteristic.id.clone(), socket, mtu);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
     fn descriptor_info(&self, descriptor: &Descriptor) -> Result<DescriptorInfo> {
         let services = self.services.lock().map_err(Into::<Error>::into)?;
         let characteristic = get_characteristic(
@@ -138,92 +418,260 @@ impl api::Peripheral for Peripheral {
         self.mac_address
     }
 
+    /// Answered from the properties of the device as last read from BlueZ and kept up to date
+    /// since, so that listing many peripherals' properties doesn't ask BlueZ for each in turn.
     async fn properties(&self) -> Result<Option<PeripheralProperties>> {
-        let device_info = self.device_info().await?;
-        Ok(Some(PeripheralProperties {
-            address: device_info.mac_address.into(),
-            address_type: Some(device_info.address_type.into()),
-            local_name: device_info.name,
-            tx_power_level: device_info.tx_power,
-            rssi: device_info.rssi,
-            manufacturer_data: device_info.manufacturer_data,
-            service_data: device_info.service_data,
-            services: device_info.services,
-            class: device_info.class,
-        }))
+        rt::Io::new(async {
+            let device_info = self
+                .object_cache
+                .device_info(&self.session, &self.device)
+                .await?;
+            Ok(Some(PeripheralProperties {
+                address: device_info.mac_address.into(),
+                address_type: Some(device_info.address_type.into()),
+                local_name: device_info.name,
+                tx_power_level: device_info.tx_power,
+                rssi: device_info.rssi,
+                manufacturer_data: shared_data(device_info.manufacturer_data),
+                service_data: shared_data(device_info.service_data),
+                services: device_info.services,
+                class: device_info.class,
+                // BlueZ doesn't expose the advertising PDU type over D-Bus.
+                advertisement_type: None,
+                connectable: None,
+                last_seen: self
+                    .last_seen
+                    .lock()
+                    .map_err(Into::<Error>::into)?
+                    .get(&self.device)
+                    .copied(),
+                appearance: device_info.appearance.map(Into::into),
+                scan_response: Default::default(),
+                advertising_data: self.object_cache.advertising_data(&self.device),
+            }))
+        })
+        .await
+    }
+
+    fn rssi_stats(&self) -> Option<RssiStats> {
+        self.operation_context.rssi().stats(&self.id())
     }
 
     fn services(&self) -> BTreeSet<Service> {
-        self.services
+        let services = self
+            .services
             .lock()
             .unwrap()
             .values()
             .map(|service| service.into())
-            .collect()
+            .collect();
+        self.descriptor_cache.fill(services)
     }
 
     async fn is_connected(&self) -> Result<bool> {
-        let device_info = self.device_info().await?;
-        Ok(device_info.connected)
+        rt::Io::new(async {
+            let device_info = self.device_info().await?;
+            Ok(device_info.connected)
+        })
+        .await
+    }
+
+    /// BlueZ only asks the agent registered by the D-Bus connection which started pairing, so the
+    /// agent is registered, and pairing started, on a connection of its own.
+    async fn pair_with_agent(&self, agent: Arc<dyn PairingAgent>) -> Result<()> {
+        rt::Io::new(async {
+            if self.device_info().await?.paired {
+                return Ok(());
+            }
+            let registration =
+                AgentRegistration::register(AgentCapability::KeyboardDisplay, agent, false).await?;
+            registration.pair(&self.device, PAIRING_TIMEOUT).await?;
+            self.events
+                .send(&self.id(), PeripheralEvent::PairingStateChanged(true));
+            Ok(())
+        })
+        .await
+    }
+
+    /// bluetoothd has no D-Bus API for out-of-band data, so the kernel is given it through the
+    /// management interface, and uses it when BlueZ then pairs.
+    async fn pair_with_oob(&self, data: OobData) -> Result<()> {
+        rt::Io::new(async {
+            let device_info = self.device_info().await?;
+            if device_info.paired {
+                return Ok(());
+            }
+            Mgmt::open()?
+                .add_remote_oob_data(
+                    mgmt_index(&self.device.adapter())?,
+                    device_info.mac_address.into(),
+                    device_info.address_type.into(),
+                    &data,
+                )
+                .await?;
+            self.session
+                .pair_with_timeout(&self.device, OOB_PAIRING_TIMEOUT)
+                .await?;
+            self.events
+                .send(&self.id(), PeripheralEvent::PairingStateChanged(true));
+            Ok(())
+        })
+        .await
+    }
+
+    /// BlueZ encrypts links to bonded devices, but doesn't say whether pairing was authenticated.
+    async fn security_level(&self) -> Result<SecurityLevel> {
+        rt::Io::new(async {
+            let device_info = self.device_info().await?;
+            if !device_info.connected {
+                Err(Error::NotConnected)
+            } else if device_info.paired {
+                Ok(SecurityLevel::Encrypted)
+            } else {
+                Ok(SecurityLevel::None)
+            }
+        })
+        .await
     }
 
     async fn connect(&self) -> Result<()> {
-        self.session.connect(&self.device).await?;
-        Ok(())
+        rt::Io::new(self.operation_context.run("connect", async {
+            self.session.connect(&self.device).await?;
+            Ok(())
+        }))
+        .await
+    }
+
+    /// `Device1.Connect` connects whichever bearers the device was last seen on. Since 5.81,
+    /// BlueZ has an interface for each bearer, which bluez_async doesn't wrap, to connect just one.
+    async fn connect_with_transport(&self, transport: Transport) -> Result<()> {
+        rt::Io::new(async {
+            let interface = match transport {
+                Transport::Auto => return self.connect().await,
+                Transport::Le => "org.bluez.Bearer.LE1",
+                Transport::BrEdr => "org.bluez.Bearer.BREDR1",
+            };
+            self.operation_context
+                .run("connect", async {
+                    let (resource, connection) =
+                        dbus_tokio::connection::new_system_sync().map_err(BluetoothError::from)?;
+                    let resource = rt::spawn_io(async {
+                        resource.await;
+                    });
+                    let proxy = Proxy::new(
+                        "org.bluez",
+                        Path::from(self.device.to_string()),
+                        BEARER_CONNECT_TIMEOUT,
+                        connection,
+                    );
+                    let connected: std::result::Result<(), dbus::Error> =
+                        proxy.method_call(interface, "Connect", ()).await;
+                    resource.abort();
+                    match connected {
+                        Ok(()) => Ok(()),
+                        Err(e)
+                            if matches!(
+                                e.name(),
+                                Some("org.freedesktop.DBus.Error.UnknownInterface")
+                                    | Some("org.freedesktop.DBus.Error.UnknownMethod")
+                                    | Some("org.freedesktop.DBus.Error.UnknownObject")
+                            ) =>
+                        {
+                            Err(Error::NotSupported(
+                                "This version of BlueZ can't choose the transport to connect over"
+                                    .to_string(),
+                            ))
+                        }
+                        Err(e) => Err(BluetoothError::from(e).into()),
+                    }
+                })
+                .await
+        })
+        .await
     }
 
     async fn disconnect(&self) -> Result<()> {
-        self.session.disconnect(&self.device).await?;
-        Ok(())
+        rt::Io::new(async {
+            self.session.disconnect(&self.device).await?;
+            Ok(())
+        })
+        .await
     }
 
-    async fn discover_services(&self) -> Result<()> {
-        let mut services_internal = HashMap::new();
-        let services = self.session.get_services(&self.device).await?;
-        for service in services {
-            let characteristics = self.session.get_characteristics(&service.id).await?;
-            let characteristics = join_all(
-                characteristics
-                    .into_iter()
-                    .fold(
-                        // Only consider the first characteristic of each UUID
-                        // This "should" be unique, but of course it's not enforced
-                        HashMap::<Uuid, CharacteristicInfo>::new(),
-                        |mut map, characteristic| {
-                            if !map.contains_key(&characteristic.uuid) {
-                                map.insert(characteristic.uuid, characteristic);
-                            }
-                            map
-                        },
-                    )
-                    .into_iter()
-                    .map(|mapped_characteristic| async {
-                        let characteristic = mapped_characteristic.1;
-                        let descriptors = self
-                            .session
-                            .get_descriptors(&characteristic.id)
-                            .await
-                            .unwrap_or(Vec::new())
-                            .into_iter()
-                            .map(|descriptor| (descriptor.uuid, descriptor))
-                            .collect();
-                        CharacteristicInternal::new(characteristic, descriptors)
-                    }),
-            )
-            .await;
-            services_internal.insert(
-                service.uuid,
-                ServiceInternal {
-                    info: service,
-                    characteristics: characteristics
-                        .into_iter()
-                        .map(|characteristic| (characteristic.info.uuid, characteristic))
-                        .collect(),
+    /// BlueZ signals changes to the device's properties, which carry no reason for a disconnection.
+    /// Its `ServicesResolved` property is set before btleplug has read the services, so the
+    /// services are reported resolved once `discover_services` is done instead.
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = PeripheralEvent> + Send>>> {
+        let sent = self.events.subscribe(self.id());
+        let signalled = rt::Io::new(self.session.device_event_stream(&self.device)).await?;
+        let signalled = rt::Io::new(signalled).filter_map(|event| {
+            let event = match event {
+                BluetoothEvent::Device { event, .. } => match event {
+                    DeviceEvent::Connected { connected: true } => Some(PeripheralEvent::Connected),
+                    DeviceEvent::Connected { connected: false } => {
+                        Some(PeripheralEvent::Disconnected(DisconnectReason::Unknown))
+                    }
+                    DeviceEvent::Rssi { .. }
+                    | DeviceEvent::ManufacturerData { .. }
+                    | DeviceEvent::ServiceData { .. }
+                    | DeviceEvent::Services { .. } => Some(PeripheralEvent::PropertiesUpdated),
+                    _ => None,
                 },
-            );
-        }
-        *(self.services.lock().map_err(Into::<Error>::into)?) = services_internal;
-        Ok(())
+                _ => None,
+            };
+            ready(event)
+        });
+        Ok(Box::pin(stream::select(signalled, sent)))
+    }
+
+    /// bluetoothd has no D-Bus API for the data length, so this sends the HCI command itself.
+    async fn request_data_length(&self, length: DataLength) -> Result<()> {
+        self.operation_context
+            .run("request data length", async {
+                Controller::open(mgmt_index(&self.device.adapter())?)?
+                    .set_data_length(self.mac_address, length)
+                    .await
+            })
+            .await
+    }
+
+    async fn link_events(
+        &self,
+        interval: Duration,
+    ) -> Result<Pin<Box<dyn Stream<Item = LinkSample> + Send>>> {
+        Ok(link::samples(
+            self.clone(),
+            self.operation_context.clone(),
+            interval,
+        ))
+    }
+
+    async fn discover_services(&self) -> Result<()> {
+        rt::Io::new(async {
+            self.operation_context
+                .run("discover services", async {
+                    self.discover_services_matching(None).await
+                })
+                .await?;
+            self.events
+                .send(&self.id(), PeripheralEvent::ServicesResolved);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn discover_services_filtered(&self, uuids: &[Uuid]) -> Result<()> {
+        rt::Io::new(async {
+            self.operation_context
+                .run("discover services", async {
+                    self.discover_services_matching(Some(uuids)).await
+                })
+                .await?;
+            self.events
+                .send(&self.id(), PeripheralEvent::ServicesResolved);
+            Ok(())
+        })
+        .await
     }
 
     async fn write(
@@ -232,38 +680,81 @@ impl api::Peripheral for Peripheral {
         data: &[u8],
         write_type: WriteType,
     ) -> Result<()> {
-        let characteristic_info = self.characteristic_info(characteristic)?;
-        let options = WriteOptions {
-            write_type: Some(write_type.into()),
-            ..Default::default()
-        };
-        Ok(self
-            .session
-            .write_characteristic_value_with_options(&characteristic_info.id, data, options)
-            .await?)
+        rt::Io::new(
+            self.operation_context
+                .run_write("write", characteristic.uuid, data, async {
+                    write_type.check_signed(characteristic)?;
+                    let characteristic_info = self.characteristic_info(characteristic)?;
+                    let options = WriteOptions {
+                        write_type: Some(write_type.into()),
+                        ..Default::default()
+                    };
+                    self.session
+                        .write_characteristic_value_with_options(
+                            &characteristic_info.id,
+                            data,
+                            options,
+                        )
+                        .await
+                        .map_err(gatt_error)
+                }),
+        )
+        .await
     }
 
     async fn read(&self, characteristic: &Characteristic) -> Result<Vec<u8>> {
-        let characteristic_info = self.characteristic_info(characteristic)?;
-        Ok(self
-            .session
-            .read_characteristic_value(&characteristic_info.id)
-            .await?)
+        rt::Io::new(
+            self.operation_context
+                .run_read("read", characteristic.uuid, async {
+                    let characteristic_info = self.characteristic_info(characteristic)?;
+                    self.session
+                        .read_characteristic_value(&characteristic_info.id)
+                        .await
+                        .map_err(gatt_error)
+                }),
+        )
+        .await
     }
 
     async fn subscribe(&self, characteristic: &Characteristic) -> Result<()> {
-        let characteristic_info = self.characteristic_info(characteristic)?;
-        Ok(self.session.start_notify(&characteristic_info.id).await?)
+        rt::Io::new(
+            self.operation_context
+                .run_on("subscribe", characteristic.uuid, async {
+                    let characteristic_info = self.characteristic_info(characteristic)?;
+                    if self.acquire_notify(&characteristic_info).await {
+                        return Ok(());
+                    }
+                    self.session
+                        .start_notify(&characteristic_info.id)
+                        .await
+                        .map_err(gatt_error)
+                }),
+        )
+        .await
     }
 
     async fn unsubscribe(&self, characteristic: &Characteristic) -> Result<()> {
-        let characteristic_info = self.characteristic_info(characteristic)?;
-        Ok(self.session.stop_notify(&characteristic_info.id).await?)
+        rt::Io::new(
+            self.operation_context
+                .run_on("unsubscribe", characteristic.uuid, async {
+                    let characteristic_info = self.characteristic_info(characteristic)?;
+                    if self.acquired.release(&characteristic_info.id) {
+                        return Ok(());
+                    }
+                    self.session
+                        .stop_notify(&characteristic_info.id)
+                        .await
+                        .map_err(gatt_error)
+                }),
+        )
+        .await
     }
 
     async fn notifications(&self) -> Result<Pin<Box<dyn Stream<Item = ValueNotification> + Send>>> {
         let device_id = self.device.clone();
-        let events = self.session.device_event_stream(&device_id).await?;
+        let events = rt::Io::new(self.session.device_event_stream(&device_id)).await?;
+        let events = rt::Io::new(events);
+        let events = stream::select(events, self.acquired.events(device_id.clone()));
         let services = self.services.clone();
         Ok(Box::pin(events.filter_map(move |event| {
             ready(value_notification(event, &device_id, services.clone()))
@@ -271,48 +762,132 @@ impl api::Peripheral for Peripheral {
     }
 
     async fn write_descriptor(&self, descriptor: &Descriptor, data: &[u8]) -> Result<()> {
-        let descriptor_info = self.descriptor_info(descriptor)?;
-        Ok(self
-            .session
-            .write_descriptor_value(&descriptor_info.id, data)
-            .await?)
+        rt::Io::new(self.operation_context.run_write(
+            "write descriptor",
+            descriptor.uuid,
+            data,
+            async {
+                let descriptor_info = self.descriptor_info(descriptor)?;
+                self.session
+                    .write_descriptor_value(&descriptor_info.id, data)
+                    .await
+                    .map_err(gatt_error)?;
+                self.descriptor_cache.insert(descriptor, data);
+                Ok(())
+            },
+        ))
+        .await
     }
 
     async fn read_descriptor(&self, descriptor: &Descriptor) -> Result<Vec<u8>> {
-        let descriptor_info = self.descriptor_info(descriptor)?;
-        Ok(self
-            .session
-            .read_descriptor_value(&descriptor_info.id)
-            .await?)
+        rt::Io::new(
+            self.operation_context
+                .run_read("read descriptor", descriptor.uuid, async {
+                    let descriptor_info = self.descriptor_info(descriptor)?;
+                    let value = self
+                        .session
+                        .read_descriptor_value(&descriptor_info.id)
+                        .await
+                        .map_err(gatt_error)?;
+                    self.descriptor_cache.insert(descriptor, &value);
+                    Ok(value)
+                }),
+        )
+        .await
     }
 }
 
+/// Moves the values of bluez_async's manufacturer or service data into shared buffers.
+pub(crate) fn shared_data<K: Eq + Hash>(data: HashMap<K, Vec<u8>>) -> HashMap<K, Arc<[u8]>> {
+    data.into_iter()
+        .map(|(key, value)| (key, value.into()))
+        .collect()
+}
+
 fn value_notification(
     event: BluetoothEvent,
     device_id: &DeviceId,
-    services: Arc<Mutex<HashMap<Uuid, ServiceInternal>>>,
+    services: Services,
 ) -> Option<ValueNotification> {
     match event {
         BluetoothEvent::Characteristic {
             id,
             event: CharacteristicEvent::Value { value },
         } if id.service().device() == *device_id => {
-            let services = services.lock().unwrap();
-            let uuid = find_characteristic_by_id(&services, id)?.uuid;
-            Some(ValueNotification { uuid, value })
+            spans::callback("notification", Some(device_id)).in_scope(|| {
+                let timestamp = Instant::now();
+                let services = services.lock().unwrap();
+                let handle = characteristic_handle(&id).and_then(|handle| handle.checked_add(1));
+                let (service_uuid, characteristic) = find_characteristic_by_id(&services, id)?;
+                Some(ValueNotification {
+                    uuid: characteristic.uuid,
+                    service_uuid,
+                    handle,
+                    // BlueZ uses notifications whenever the characteristic supports them.
+                    subscription_type: SubscriptionType::preferred(characteristic.flags.into())
+                        .unwrap_or(SubscriptionType::Notify),
+                    value,
+                    timestamp,
+                })
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Maps the error from a GATT operation to [`Error::Att`] if BlueZ says which ATT error the
+/// device returned.
+fn gatt_error(error: BluetoothError) -> Error {
+    if let BluetoothError::DbusError(dbus_error) = &error {
+        if let Some(att_error) = att_error(dbus_error.name(), dbus_error.message()) {
+            return Error::Att(att_error);
+        }
+    }
+    error.into()
+}
+
+/// BlueZ turns some ATT errors into D-Bus errors of its own, and reports the rest by code.
+fn att_error(name: Option<&str>, message: Option<&str>) -> Option<AttError> {
+    match name? {
+        // BlueZ also uses this for insufficient authorization and encryption key size.
+        "org.bluez.Error.NotAuthorized" => Some(AttError::InsufficientAuthentication),
+        "org.bluez.Error.NotPermitted" => match message? {
+            "Read not permitted" => Some(AttError::ReadNotPermitted),
+            "Write not permitted" => Some(AttError::WriteNotPermitted),
+            _ => None,
+        },
+        "org.bluez.Error.InvalidOffset" => Some(AttError::InvalidOffset),
+        "org.bluez.Error.InvalidValueLength" => Some(AttError::InvalidAttributeValueLength),
+        "org.bluez.Error.Failed" => {
+            let code = message?.strip_prefix("Operation failed with ATT error: 0x")?;
+            u8::from_str_radix(code, 16).ok().map(AttError::from)
         }
         _ => None,
     }
 }
 
+/// bluez_async doesn't expose which services include which, so read the `Includes` property of
+/// each service from BlueZ directly. Older versions of BlueZ don't have it, in which case no
+/// services are included.
+/// BlueZ names GATT objects after the handle of their declaration in hex, e.g.
+/// `.../service000a/char000b`.
+fn handle_from_path(path: &str, kind: &str) -> Option<u16> {
+    let (_, handle) = path.rsplit_once(kind)?;
+    u16::from_str_radix(handle, 16).ok()
+}
+
+fn characteristic_handle(id: &CharacteristicId) -> Option<u16> {
+    handle_from_path(&id.to_string(), "/char")
+}
+
 fn find_characteristic_by_id(
     services: &HashMap<Uuid, ServiceInternal>,
     characteristic_id: CharacteristicId,
-) -> Option<&CharacteristicInfo> {
+) -> Option<(Uuid, &CharacteristicInfo)> {
     for service in services.values() {
         for characteristic in service.characteristics.values() {
             if characteristic.info.id == characteristic_id {
-                return Some(&characteristic.info);
+                return Some((service.info.uuid, &characteristic.info));
             }
         }
     }
@@ -322,7 +897,11 @@ fn find_characteristic_by_id(
 impl From<WriteType> for bluez_async::WriteType {
     fn from(write_type: WriteType) -> Self {
         match write_type {
-            WriteType::WithoutResponse => bluez_async::WriteType::WithoutResponse,
+            // BlueZ signs a command to a characteristic with the signed write property when the
+            // link isn't encrypted.
+            WriteType::WithoutResponse | WriteType::SignedWithoutResponse => {
+                bluez_async::WriteType::WithoutResponse
+            }
             WriteType::WithResponse => bluez_async::WriteType::WithResponse,
         }
     }
@@ -358,6 +937,8 @@ fn make_descriptor(
         uuid: info.uuid,
         characteristic_uuid,
         service_uuid,
+        handle: handle_from_path(&info.id.to_string(), "/desc"),
+        value: None,
     }
 }
 
@@ -366,14 +947,17 @@ fn make_characteristic(
     service_uuid: Uuid,
 ) -> Characteristic {
     let CharacteristicInternal { info, descriptors } = characteristic;
+    let handle = characteristic_handle(&info.id);
     Characteristic {
         uuid: info.uuid,
         properties: info.flags.into(),
         descriptors: descriptors
-            .iter()
-            .map(|(_, descriptor)| make_descriptor(descriptor, info.uuid, service_uuid))
+            .values()
+            .map(|descriptor| make_descriptor(descriptor, info.uuid, service_uuid))
             .collect(),
         service_uuid,
+        handle,
+        value_handle: handle.and_then(|handle| handle.checked_add(1)),
     }
 }
 
@@ -387,6 +971,8 @@ impl From<&ServiceInternal> for Service {
                 .values()
                 .map(|characteristic| make_characteristic(characteristic, service.info.uuid))
                 .collect(),
+            included_services: service.included_services.clone(),
+            handle: handle_from_path(&service.info.id.to_string(), "/service"),
         }
     }
 }
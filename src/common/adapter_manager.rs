@@ -1,3 +1,8 @@
+use crate::api::{
+    observer::Tracer, operation::OperationContext, peripheral_events::PeripheralEventSender,
+    peripheral_store::PeripheralStore, CentralEvent, EventFilter, Peripheral,
+};
+use crate::platform::PeripheralId;
 /// Implements common functionality for adapters across platforms.
 // btleplug Source Code File
 //
@@ -11,31 +16,47 @@
 // following copyright:
 //
 // Copyright (c) 2014 The Rust Project Developers
-use crate::api::{CentralEvent, Peripheral};
-use crate::platform::PeripheralId;
+use crate::time::SystemTime;
+use crate::{Error, Result};
 use dashmap::{mapref::one::RefMut, DashMap};
 use futures::stream::{Stream, StreamExt};
 use log::trace;
+use std::fmt::{self, Debug, Formatter};
 use std::pin::Pin;
 use tokio::sync::broadcast;
 use tokio_stream::wrappers::BroadcastStream;
 
+/// Keeps the peripherals of an adapter, of which there may be thousands. Looking one up by ID
+/// takes constant time, and doesn't hold up the platform reporting the others; see
+/// [`PeripheralStore`] for what each operation costs.
 #[derive(Debug)]
 pub struct AdapterManager<PeripheralType>
 where
     PeripheralType: Peripheral,
 {
-    peripherals: DashMap<PeripheralId, PeripheralType>,
+    peripherals: PeripheralStore<PeripheralType>,
+    /// When each peripheral was last seen, or `None` while it's connected, for removing those which
+    /// haven't been seen for the peripheral TTL.
+    last_seen: DashMap<PeripheralId, Option<SystemTime>>,
     events_channel: broadcast::Sender<CentralEvent>,
+    peripheral_events: PeripheralEventSender,
+    operation_context: OperationContext,
+    on_remove: Option<RemoveHook>,
+}
+
+/// Called with the ID of each peripheral removed from the manager, for platforms which keep
+/// state of their own for it.
+pub struct RemoveHook(Box<dyn Fn(&PeripheralId) + Send + Sync>);
+
+impl Debug for RemoveHook {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("RemoveHook")
+    }
 }
 
 impl<PeripheralType: Peripheral + 'static> Default for AdapterManager<PeripheralType> {
     fn default() -> Self {
-        let (broadcast_sender, _) = broadcast::channel(16);
-        AdapterManager {
-            peripherals: DashMap::new(),
-            events_channel: broadcast_sender,
-        }
+        Self::new(Tracer::default())
     }
 }
 
@@ -43,11 +64,57 @@ impl<PeripheralType> AdapterManager<PeripheralType>
 where
     PeripheralType: Peripheral + 'static,
 {
+    /// Creates a manager for an adapter of the platform manager with the given tracer.
+    pub fn new(tracer: Tracer) -> Self {
+        let (broadcast_sender, _) = broadcast::channel(16);
+        AdapterManager {
+            peripherals: PeripheralStore::default(),
+            last_seen: DashMap::new(),
+            events_channel: broadcast_sender,
+            peripheral_events: PeripheralEventSender::default(),
+            operation_context: OperationContext::with_tracer(tracer),
+            on_remove: None,
+        }
+    }
+
+    /// Creates a manager which calls `on_remove` for each peripheral removed from it, other than
+    /// because it disconnected.
+    // Only used on macOS/iOS, so turn off deadcode so we don't get warnings on the other platforms.
+    #[allow(dead_code)]
+    pub fn with_remove_hook(
+        tracer: Tracer,
+        on_remove: impl Fn(&PeripheralId) + Send + Sync + 'static,
+    ) -> Self {
+        AdapterManager {
+            on_remove: Some(RemoveHook(Box::new(on_remove))),
+            ..Self::new(tracer)
+        }
+    }
+
     pub fn emit(&self, event: CentralEvent) {
-        if let CentralEvent::DeviceDisconnected(ref id) = event {
-            self.peripherals.remove(id);
+        self.operation_context.observe_event(&event);
+        match event {
+            CentralEvent::DeviceDisconnected(ref id) => {
+                self.peripherals.remove(id);
+                self.last_seen.remove(id);
+            }
+            CentralEvent::DeviceConnected(ref id) => {
+                self.last_seen.insert(id.clone(), None);
+            }
+            CentralEvent::DeviceUpdated(ref id) => {
+                if let Some(mut last_seen) = self.last_seen.get_mut(id) {
+                    if last_seen.is_some() {
+                        *last_seen = Some(SystemTime::now());
+                    }
+                }
+            }
+            // Platforms may hold on to a peripheral while emitting updates for it, so only expire
+            // peripherals when a new one is found.
+            CentralEvent::DeviceDiscovered(_) => self.expire_peripherals(),
+            _ => {}
         }
 
+        self.peripheral_events.send_central(&event);
         if let Err(lost) = self.events_channel.send(event) {
             trace!("Lost central event, while nothing subscribed: {:?}", lost);
         }
@@ -58,19 +125,79 @@ where
         Box::pin(BroadcastStream::new(receiver).filter_map(|x| async move { x.ok() }))
     }
 
+    /// The events which match the filter's kinds and peripherals, for
+    /// [`Central::events_filtered`](crate::api::Central::events_filtered).
+    pub fn filtered_event_stream(
+        &self,
+        filter: &EventFilter,
+    ) -> Pin<Box<dyn Stream<Item = CentralEvent> + Send>> {
+        self.peripheral_events.subscribe_filtered(filter)
+    }
+
+    /// The events of the adapter's peripherals, for [`Peripheral::events`].
+    pub fn peripheral_events(&self) -> &PeripheralEventSender {
+        &self.peripheral_events
+    }
+
+    /// The operation context shared by the adapter and all its peripherals.
+    pub fn operation_context(&self) -> &OperationContext {
+        &self.operation_context
+    }
+
     pub fn add_peripheral(&self, peripheral: PeripheralType) {
         assert!(
-            !self.peripherals.contains_key(&peripheral.id()),
+            !self.peripherals.contains(&peripheral.id()),
             "Adding a peripheral that's already in the map."
         );
-        self.peripherals.insert(peripheral.id(), peripheral);
+        self.last_seen
+            .insert(peripheral.id(), Some(SystemTime::now()));
+        self.peripherals.insert(peripheral);
     }
 
-    pub fn peripherals(&self) -> Vec<PeripheralType> {
-        self.peripherals
+    pub fn remove_peripheral(&self, id: &PeripheralId) -> Option<PeripheralType> {
+        self.last_seen.remove(id);
+        let peripheral = self.peripherals.remove(id)?;
+        if let Some(on_remove) = &self.on_remove {
+            (on_remove.0)(id);
+        }
+        Some(peripheral)
+    }
+
+    /// Implements [`Central::remove_peripheral`](crate::api::Central::remove_peripheral).
+    pub async fn disconnect_and_remove(&self, id: &PeripheralId) -> Result<()> {
+        let peripheral = self.peripheral(id).ok_or(Error::DeviceNotFound)?;
+        if peripheral.is_connected().await? {
+            peripheral.disconnect().await?;
+        }
+        self.remove_peripheral(id);
+        Ok(())
+    }
+
+    /// Removes the peripherals which haven't been seen for the peripheral TTL, if it's time to
+    /// check.
+    fn expire_peripherals(&self) {
+        let Some(cutoff) = self.operation_context.expiry().cutoff() else {
+            return;
+        };
+        let expired: Vec<_> = self
+            .last_seen
             .iter()
-            .map(|val| val.value().clone())
-            .collect()
+            .filter(|entry| entry.value().is_some_and(|last_seen| last_seen < cutoff))
+            .map(|entry| entry.key().clone())
+            .collect();
+        for id in expired {
+            trace!(
+                "Removing {:?}, which hasn't been seen since before {:?}",
+                id,
+                cutoff
+            );
+            self.remove_peripheral(&id);
+        }
+    }
+
+    pub fn peripherals(&self) -> Vec<PeripheralType> {
+        self.expire_peripherals();
+        self.peripherals.peripherals()
     }
 
     // Only used on windows and macOS/iOS, so turn off deadcode so we don't get warnings on android/linux.
@@ -83,6 +210,6 @@ where
     }
 
     pub fn peripheral(&self, id: &PeripheralId) -> Option<PeripheralType> {
-        self.peripherals.get(id).map(|val| val.value().clone())
+        self.peripherals.get(id)
     }
 }
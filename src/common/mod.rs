@@ -1,2 +1 @@
 pub mod adapter_manager;
-pub mod util;
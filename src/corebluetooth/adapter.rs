@@ -2,10 +2,18 @@ use super::internal::{
     run_corebluetooth_thread, CoreBluetoothEvent, CoreBluetoothMessage, CoreBluetoothReply,
     CoreBluetoothReplyFuture,
 };
-use super::peripheral::{Peripheral, PeripheralId};
-use crate::api::{Central, CentralEvent, CentralState, ScanFilter};
+use super::manager::AuthorizationWatch;
+use super::peripheral::{ConnectOptions, Peripheral, PeripheralId};
+use crate::api::{
+    advertiser::{Advertiser, Advertising},
+    connect_when_advertising, event_filter,
+    observer::{Observer, Tracer},
+    rssi::RssiTracking,
+    AdapterInfo, BDAddr, Central, CentralEvent, CentralState, ConnectTarget, EventFilter,
+    ScanFilter,
+};
 use crate::common::adapter_manager::AdapterManager;
-use crate::{Error, Result};
+use crate::{rt, Error, Result};
 use async_trait::async_trait;
 use futures::channel::mpsc::{self, Sender};
 use futures::sink::SinkExt;
@@ -14,84 +22,206 @@ use log::*;
 use objc2_core_bluetooth::CBManagerState;
 use std::pin::Pin;
 use std::sync::Arc;
-use tokio::task;
+use std::time::Duration;
+use uuid::Uuid;
 
 /// Implementation of [api::Central](crate::api::Central).
 #[derive(Clone, Debug)]
 pub struct Adapter {
     manager: Arc<AdapterManager<Peripheral>>,
     sender: Sender<CoreBluetoothMessage>,
+    restored_state: Option<Arc<RestoredState>>,
+}
+
+/// What the system restored for an app it relaunched in the background, for an adapter from a
+/// manager created with [`Manager::with_restore_identifier`](super::manager::Manager::with_restore_identifier).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RestoredState {
+    /// The peripherals the app was connected or connecting to when it was terminated. They are
+    /// already known to the adapter.
+    pub peripherals: Vec<RestoredPeripheral>,
+    /// The services the app was scanning for, if it was scanning. An empty list means it was
+    /// scanning for all devices. Scanning doesn't resume by itself, so call
+    /// [`Central::start_scan`] again to carry on.
+    pub scan_services: Option<Vec<Uuid>>,
+}
+
+/// A peripheral which was restored by the system, and what it was doing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RestoredPeripheral {
+    pub id: PeripheralId,
+    /// Whether the peripheral is still connected. Its services are discovered again, and
+    /// [`CentralEvent::ServicesChanged`] is emitted once they are known. Otherwise the system
+    /// carries on connecting to it, and it needs to be connected to again.
+    pub connected: bool,
+    /// The UUIDs of the characteristics which were subscribed to. Their notifications keep
+    /// arriving on [`Peripheral::notifications`](crate::api::Peripheral::notifications).
+    pub subscribed: Vec<Uuid>,
 }
 
 fn get_central_state(state: CBManagerState) -> CentralState {
     match state {
         CBManagerState::PoweredOn => CentralState::PoweredOn,
         CBManagerState::PoweredOff => CentralState::PoweredOff,
+        CBManagerState::Unauthorized => CentralState::Unauthorized,
+        CBManagerState::Resetting => CentralState::Resetting,
+        CBManagerState::Unsupported => CentralState::Unsupported,
         _ => CentralState::Unknown,
     }
 }
 
+fn handle_event(
+    manager: &Arc<AdapterManager<Peripheral>>,
+    adapter_sender: &Sender<CoreBluetoothMessage>,
+    authorization: &AuthorizationWatch,
+    event: CoreBluetoothEvent,
+) {
+    match event {
+        CoreBluetoothEvent::DeviceDiscovered {
+            uuid,
+            name,
+            event_receiver,
+        } => {
+            manager.add_peripheral(Peripheral::new(
+                uuid,
+                name,
+                Arc::downgrade(manager),
+                event_receiver,
+                adapter_sender.clone(),
+                manager.operation_context().clone(),
+            ));
+            manager.emit(CentralEvent::DeviceDiscovered(uuid.into()));
+        }
+        CoreBluetoothEvent::DeviceUpdated { uuid, name } => {
+            let id = uuid.into();
+            if let Some(entry) = manager.peripheral_mut(&id) {
+                entry.value().update_name(&name);
+                manager.emit(CentralEvent::DeviceUpdated(id));
+            }
+        }
+        CoreBluetoothEvent::DeviceConnected { uuid } => {
+            manager.emit(CentralEvent::DeviceConnected(uuid.into()));
+        }
+        CoreBluetoothEvent::DeviceDisconnected { uuid } => {
+            manager.emit(CentralEvent::DeviceDisconnected(uuid.into()));
+        }
+        CoreBluetoothEvent::DidUpdateState { state } => {
+            authorization.refresh();
+            let central_state = get_central_state(state);
+            manager.emit(CentralEvent::StateUpdate(central_state));
+        }
+        // Restoration only happens before the first state update, which Adapter::new waits for.
+        CoreBluetoothEvent::StateRestored(_) => {}
+    }
+}
+
 impl Adapter {
-    pub(crate) async fn new() -> Result<Self> {
+    pub(crate) async fn new(
+        restore_identifier: Option<String>,
+        tracer: Tracer,
+        authorization: Arc<AuthorizationWatch>,
+    ) -> Result<Self> {
         let (sender, mut receiver) = mpsc::channel(256);
-        let adapter_sender = run_corebluetooth_thread(sender)?;
+        let adapter_sender = run_corebluetooth_thread(sender, restore_identifier)?;
+        let forget_sender = adapter_sender.clone();
+        let manager = Arc::new(AdapterManager::with_remove_hook(
+            tracer,
+            move |id: &PeripheralId| {
+                let message = CoreBluetoothMessage::ForgetPeripheral {
+                    peripheral_uuid: id.0,
+                };
+                if let Err(e) = forget_sender.clone().try_send(message) {
+                    warn!("Failed to forget removed peripheral: {}", e);
+                }
+            },
+        ));
         // Since init currently blocked until the state update, we know the
         // receiver is dropped after that. We can pick it up here and make it
-        // part of our event loop to update our peripherals.
+        // part of our event loop to update our peripherals. The system restores
+        // peripherals before the first state update, so they are added first.
         debug!("Waiting on adapter connect");
-        if !matches!(
-            receiver.next().await,
-            Some(CoreBluetoothEvent::DidUpdateState { state: _ })
-        ) {
-            return Err(Error::Other(
-                "Adapter failed to connect.".to_string().into(),
-            ));
+        let mut restored_state = None;
+        loop {
+            match receiver.next().await {
+                Some(CoreBluetoothEvent::DidUpdateState { state: _ }) => {
+                    authorization.refresh();
+                    break;
+                }
+                Some(CoreBluetoothEvent::StateRestored(state)) => {
+                    restored_state = Some(Arc::new(state));
+                }
+                Some(event) => handle_event(&manager, &adapter_sender, &authorization, event),
+                None => {
+                    return Err(Error::Other(
+                        "Adapter failed to connect.".to_string().into(),
+                    ))
+                }
+            }
         }
         debug!("Adapter connected");
-        let manager = Arc::new(AdapterManager::default());
 
         let manager_clone = manager.clone();
         let adapter_sender_clone = adapter_sender.clone();
-        task::spawn(async move {
+        rt::spawn(async move {
             while let Some(msg) = receiver.next().await {
-                match msg {
-                    CoreBluetoothEvent::DeviceDiscovered {
-                        uuid,
-                        name,
-                        event_receiver,
-                    } => {
-                        manager_clone.add_peripheral(Peripheral::new(
-                            uuid,
-                            name,
-                            Arc::downgrade(&manager_clone),
-                            event_receiver,
-                            adapter_sender_clone.clone(),
-                        ));
-                        manager_clone.emit(CentralEvent::DeviceDiscovered(uuid.into()));
-                    }
-                    CoreBluetoothEvent::DeviceUpdated { uuid, name } => {
-                        let id = uuid.into();
-                        if let Some(entry) = manager_clone.peripheral_mut(&id) {
-                            entry.value().update_name(&name);
-                            manager_clone.emit(CentralEvent::DeviceUpdated(id));
-                        }
-                    }
-                    CoreBluetoothEvent::DeviceDisconnected { uuid } => {
-                        manager_clone.emit(CentralEvent::DeviceDisconnected(uuid.into()));
-                    }
-                    CoreBluetoothEvent::DidUpdateState { state } => {
-                        let central_state = get_central_state(state);
-                        manager_clone.emit(CentralEvent::StateUpdate(central_state));
-                    }
-                }
+                handle_event(&manager_clone, &adapter_sender_clone, &authorization, msg);
             }
         });
 
         Ok(Adapter {
             manager,
             sender: adapter_sender,
+            restored_state,
         })
     }
+
+    /// Returns what the system restored when it relaunched the app, if the manager was created
+    /// with [`Manager::with_restore_identifier`](super::manager::Manager::with_restore_identifier)
+    /// and the app was relaunched to handle a Bluetooth event.
+    pub fn restored_state(&self) -> Option<&RestoredState> {
+        self.restored_state.as_deref()
+    }
+
+    /// Asks iOS to report when a peripheral with any of the given services connects to or
+    /// disconnects from the system, even if another app connected to it, as
+    /// [`CentralEvent::DeviceConnected`] and [`CentralEvent::DeviceDisconnected`]. iOS wakes apps
+    /// with the `bluetooth-central` background mode up for these events while in the background.
+    ///
+    /// This replaces the services given before.
+    #[cfg(target_os = "ios")]
+    pub async fn register_for_connection_events(&self, services: &[Uuid]) -> Result<()> {
+        self.sender
+            .to_owned()
+            .send(CoreBluetoothMessage::RegisterForConnectionEvents {
+                services: services.to_vec(),
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+impl Adapter {
+    /// Returns the peripheral with the given ID once the event loop has added it, after the
+    /// CoreBluetooth thread reported it. `events` must have been subscribed to before asking for
+    /// the peripheral, so that it isn't missed.
+    async fn wait_for_peripheral(
+        &self,
+        events: &mut Pin<Box<dyn Stream<Item = CentralEvent> + Send>>,
+        id: &PeripheralId,
+    ) -> Result<Peripheral> {
+        loop {
+            if let Some(peripheral) = self.manager.peripheral(id) {
+                return Ok(peripheral);
+            }
+            match events.next().await {
+                Some(CentralEvent::DeviceDiscovered(_)) => {}
+                Some(_) => continue,
+                None => return Err(Error::DeviceNotFound),
+            }
+        }
+    }
 }
 
 #[async_trait]
@@ -102,6 +232,14 @@ impl Central for Adapter {
         Ok(self.manager.event_stream())
     }
 
+    async fn events_filtered(
+        &self,
+        filter: EventFilter,
+    ) -> Result<Pin<Box<dyn Stream<Item = CentralEvent> + Send>>> {
+        let events = self.manager.filtered_event_stream(&filter);
+        Ok(event_filter::by_services(self, events, filter.services))
+    }
+
     async fn start_scan(&self, filter: ScanFilter) -> Result<()> {
         self.sender
             .to_owned()
@@ -123,18 +261,80 @@ impl Central for Adapter {
     }
 
     async fn peripheral(&self, id: &PeripheralId) -> Result<Peripheral> {
-        self.manager.peripheral(id).ok_or(Error::DeviceNotFound)
+        if let Some(peripheral) = self.manager.peripheral(id) {
+            return Ok(peripheral);
+        }
+        // The peripheral is added to the manager by the event loop, so listen for it being
+        // discovered before asking for it.
+        let mut events = self.manager.event_stream();
+        let fut = CoreBluetoothReplyFuture::default();
+        self.sender
+            .to_owned()
+            .send(CoreBluetoothMessage::RetrievePeripheral {
+                peripheral_uuid: id.0,
+                future: fut.get_state_clone(),
+            })
+            .await?;
+        match fut.await {
+            CoreBluetoothReply::Ok => {}
+            CoreBluetoothReply::Err(_) => return Err(Error::DeviceNotFound),
+            _ => panic!("Shouldn't get anything but Ok or Err!"),
+        }
+        self.wait_for_peripheral(&mut events, id).await
     }
 
-    async fn add_peripheral(&self, _address: &PeripheralId) -> Result<Peripheral> {
-        Err(Error::NotSupported(
-            "Can't add a Peripheral from a PeripheralId".to_string(),
-        ))
+    async fn connected_peripherals(&self, services: &[Uuid]) -> Result<Vec<Peripheral>> {
+        let mut events = self.manager.event_stream();
+        let fut = CoreBluetoothReplyFuture::default();
+        self.sender
+            .to_owned()
+            .send(CoreBluetoothMessage::RetrieveConnectedPeripherals {
+                services: services.to_vec(),
+                future: fut.get_state_clone(),
+            })
+            .await?;
+        let uuids = match fut.await {
+            CoreBluetoothReply::Peripherals(uuids) => uuids,
+            _ => panic!("Shouldn't get anything but Peripherals!"),
+        };
+        let mut peripherals = Vec::with_capacity(uuids.len());
+        for uuid in uuids {
+            peripherals.push(self.wait_for_peripheral(&mut events, &uuid.into()).await?);
+        }
+        Ok(peripherals)
     }
 
-    async fn adapter_info(&self) -> Result<String> {
-        // TODO: Get information about the adapter.
-        Ok("CoreBluetooth".to_string())
+    async fn peripheral_by_address(&self, _address: BDAddr) -> Result<Peripheral> {
+        Err(Error::AddressNotAvailable)
+    }
+
+    async fn add_peripheral(&self, id: &PeripheralId) -> Result<Peripheral> {
+        self.peripheral(id).await
+    }
+
+    async fn remove_peripheral(&self, id: &PeripheralId) -> Result<()> {
+        self.manager.disconnect_and_remove(id).await
+    }
+
+    async fn connect_when_available(&self, target: ConnectTarget) -> Result<Peripheral> {
+        if let ConnectTarget::Peripheral(id) = &target {
+            if let Some(peripheral) = self.manager.peripheral(id) {
+                peripheral
+                    .connect_when_discovered(ConnectOptions::default())
+                    .await?;
+                return Ok(peripheral);
+            }
+        }
+        connect_when_advertising(self, target).await
+    }
+
+    async fn adapter_info(&self) -> Result<AdapterInfo> {
+        // CoreBluetooth doesn't expose anything about the adapter itself, and there is only one.
+        Ok(AdapterInfo {
+            id: "default".to_string(),
+            description: "CoreBluetooth".to_string(),
+            ..Default::default()
+        })
     }
 
     async fn adapter_state(&self) -> Result<CentralState> {
@@ -154,4 +354,41 @@ impl Central for Adapter {
             _ => panic!("Shouldn't get anything but a AdapterState!"),
         }
     }
+
+    async fn set_powered(&self, _powered: bool) -> Result<()> {
+        Err(Error::NotSupported(
+            "CoreBluetooth doesn't allow apps to turn Bluetooth on or off".to_string(),
+        ))
+    }
+
+    async fn start_advertising(&self, _advertiser: Advertiser) -> Result<Advertising> {
+        Err(Error::NotSupported(
+            "CoreBluetooth only advertises a local name and service UUIDs, and refuses Apple's \
+             manufacturer data; advertise iBeacons with Core Location's CLBeaconRegion instead"
+                .to_string(),
+        ))
+    }
+
+    fn operation_timeout(&self) -> Option<Duration> {
+        self.manager.operation_context().timeout()
+    }
+
+    fn set_operation_timeout(&self, timeout: Option<Duration>) {
+        self.manager.operation_context().set_timeout(timeout);
+    }
+
+    fn set_observer(&self, observer: Option<Arc<dyn Observer>>) {
+        self.manager.operation_context().set_observer(observer);
+    }
+
+    fn set_peripheral_ttl(&self, ttl: Option<Duration>) {
+        self.manager.operation_context().expiry().set_ttl(ttl);
+    }
+
+    fn set_rssi_tracking(&self, tracking: Option<RssiTracking>) {
+        self.manager
+            .operation_context()
+            .rssi()
+            .set_tracking(tracking);
+    }
 }
@@ -16,18 +16,22 @@
 // This file may not be copied, modified, or distributed except
 // according to those terms.
 
+use super::internal::CoreBluetoothReply;
 use super::utils::nsstring_to_string;
 use super::utils::{core_bluetooth::cbuuid_to_uuid, nsuuid_to_uuid};
+use crate::api::NativeError;
 use futures::channel::mpsc::Sender;
 use futures::sink::SinkExt;
 use log::{error, trace};
 use objc2::runtime::{AnyObject, ProtocolObject};
 use objc2::{declare_class, msg_send_id, mutability, rc::Retained, ClassType, DeclaredClass};
 use objc2_core_bluetooth::{
-    CBAdvertisementDataLocalNameKey, CBAdvertisementDataManufacturerDataKey,
-    CBAdvertisementDataServiceDataKey, CBAdvertisementDataServiceUUIDsKey, CBCentralManager,
-    CBCentralManagerDelegate, CBCharacteristic, CBDescriptor, CBManagerState, CBPeripheral,
-    CBPeripheralDelegate, CBService, CBUUID,
+    CBATTErrorDomain, CBAdvertisementDataIsConnectable, CBAdvertisementDataLocalNameKey,
+    CBAdvertisementDataManufacturerDataKey, CBAdvertisementDataServiceDataKey,
+    CBAdvertisementDataServiceUUIDsKey, CBAdvertisementDataTxPowerLevelKey, CBCentralManager,
+    CBCentralManagerDelegate, CBCentralManagerRestoredStatePeripheralsKey,
+    CBCentralManagerRestoredStateScanServicesKey, CBCharacteristic, CBConnectionEvent,
+    CBDescriptor, CBManagerState, CBPeripheral, CBPeripheralDelegate, CBService, CBUUID,
 };
 use objc2_foundation::{
     NSArray, NSData, NSDictionary, NSError, NSNumber, NSObject, NSObjectProtocol, NSString,
@@ -37,6 +41,7 @@ use std::{
     collections::HashMap,
     fmt::{self, Debug, Formatter},
     ops::Deref,
+    sync::Arc,
 };
 use uuid::Uuid;
 
@@ -44,10 +49,20 @@ pub enum CentralDelegateEvent {
     DidUpdateState {
         state: CBManagerState,
     },
+    WillRestoreState {
+        peripherals: Vec<Retained<CBPeripheral>>,
+        scan_services: Option<Vec<Uuid>>,
+    },
     DiscoveredPeripheral {
         cbperipheral: Retained<CBPeripheral>,
         local_name: Option<String>,
     },
+    Advertisement {
+        peripheral_uuid: Uuid,
+        connectable: Option<bool>,
+        tx_power_level: Option<i16>,
+        rssi: i16,
+    },
     DiscoveredServices {
         peripheral_uuid: Uuid,
         services: HashMap<Uuid, Retained<CBService>>,
@@ -55,12 +70,12 @@ pub enum CentralDelegateEvent {
     ManufacturerData {
         peripheral_uuid: Uuid,
         manufacturer_id: u16,
-        data: Vec<u8>,
+        data: Arc<[u8]>,
         rssi: i16,
     },
     ServiceData {
         peripheral_uuid: Uuid,
-        service_data: HashMap<Uuid, Vec<u8>>,
+        service_data: HashMap<Uuid, Arc<[u8]>>,
         rssi: i16,
     },
     Services {
@@ -68,7 +83,12 @@ pub enum CentralDelegateEvent {
         service_uuids: Vec<Uuid>,
         rssi: i16,
     },
-    // DiscoveredIncludedServices(Uuid, HashMap<Uuid, Retained<CBService>>),
+    DiscoveredIncludedServices {
+        peripheral_uuid: Uuid,
+        service_uuid: Uuid,
+        /// Included service UUID to CBService
+        included_services: HashMap<Uuid, Retained<CBService>>,
+    },
     DiscoveredCharacteristics {
         peripheral_uuid: Uuid,
         service_uuid: Uuid,
@@ -84,6 +104,10 @@ pub enum CentralDelegateEvent {
     ConnectedDevice {
         peripheral_uuid: Uuid,
     },
+    ConnectionEvent {
+        cbperipheral: Retained<CBPeripheral>,
+        connected: bool,
+    },
     ConnectionFailed {
         peripheral_uuid: Uuid,
         error_description: Option<String>,
@@ -91,6 +115,20 @@ pub enum CentralDelegateEvent {
     DisconnectedDevice {
         peripheral_uuid: Uuid,
     },
+    /// The RSSI was read, with either [`CoreBluetoothReply::Rssi`] or the error.
+    ReadRssi {
+        peripheral_uuid: Uuid,
+        reply: CoreBluetoothReply,
+    },
+    ReadyToSendWriteWithoutResponse {
+        peripheral_uuid: Uuid,
+    },
+    /// The peripheral's name changed, e.g. when it was read from the Generic Access service after
+    /// connecting.
+    NameUpdated {
+        peripheral_uuid: Uuid,
+        name: String,
+    },
     CharacteristicSubscribed {
         peripheral_uuid: Uuid,
         service_uuid: Uuid,
@@ -125,6 +163,46 @@ pub enum CentralDelegateEvent {
         characteristic_uuid: Uuid,
         descriptor_uuid: Uuid,
     },
+    CharacteristicOperationFailed {
+        peripheral_uuid: Uuid,
+        service_uuid: Uuid,
+        characteristic_uuid: Uuid,
+        operation: GattOperation,
+        error: CoreBluetoothReply,
+    },
+    DescriptorOperationFailed {
+        peripheral_uuid: Uuid,
+        service_uuid: Uuid,
+        characteristic_uuid: Uuid,
+        descriptor_uuid: Uuid,
+        operation: GattOperation,
+        error: CoreBluetoothReply,
+    },
+}
+
+/// The GATT operation a delegate callback reports the failure of.
+#[derive(Clone, Copy, Debug)]
+pub enum GattOperation {
+    Read,
+    Write,
+    Subscribe,
+    Unsubscribe,
+}
+
+/// Converts the error from a delegate callback to the reply for the operation's future, using the
+/// ATT error the device returned if there was one.
+fn error_reply(error: &NSError) -> CoreBluetoothReply {
+    let domain = error.domain();
+    if domain.deref() == unsafe { CBATTErrorDomain } {
+        if let Ok(code) = u8::try_from(error.code()) {
+            return CoreBluetoothReply::AttError(code.into());
+        }
+    }
+    CoreBluetoothReply::NativeError(NativeError::NSError {
+        domain: domain.to_string(),
+        code: error.code(),
+        description: error.localizedDescription().to_string(),
+    })
 }
 
 impl Debug for CentralDelegateEvent {
@@ -134,6 +212,25 @@ impl Debug for CentralDelegateEvent {
                 .debug_struct("CentralDelegateEvent")
                 .field("state", state)
                 .finish(),
+            CentralDelegateEvent::ConnectionEvent {
+                cbperipheral,
+                connected,
+            } => f
+                .debug_struct("ConnectionEvent")
+                .field("cbperipheral", cbperipheral.deref())
+                .field("connected", connected)
+                .finish(),
+            CentralDelegateEvent::WillRestoreState {
+                peripherals,
+                scan_services,
+            } => f
+                .debug_struct("WillRestoreState")
+                .field(
+                    "peripherals",
+                    &peripherals.iter().map(|p| p.deref()).collect::<Vec<_>>(),
+                )
+                .field("scan_services", scan_services)
+                .finish(),
             CentralDelegateEvent::DiscoveredPeripheral {
                 cbperipheral,
                 local_name,
@@ -142,6 +239,18 @@ impl Debug for CentralDelegateEvent {
                 .field("cbperipheral", cbperipheral.deref())
                 .field("local_name", local_name)
                 .finish(),
+            CentralDelegateEvent::Advertisement {
+                peripheral_uuid,
+                connectable,
+                tx_power_level,
+                rssi,
+            } => f
+                .debug_struct("Advertisement")
+                .field("peripheral_uuid", peripheral_uuid)
+                .field("connectable", connectable)
+                .field("tx_power_level", tx_power_level)
+                .field("rssi", rssi)
+                .finish(),
             CentralDelegateEvent::DiscoveredServices {
                 peripheral_uuid,
                 services,
@@ -150,6 +259,19 @@ impl Debug for CentralDelegateEvent {
                 .field("peripheral_uuid", peripheral_uuid)
                 .field("services", &services.keys().collect::<Vec<_>>())
                 .finish(),
+            CentralDelegateEvent::DiscoveredIncludedServices {
+                peripheral_uuid,
+                service_uuid,
+                included_services,
+            } => f
+                .debug_struct("DiscoveredIncludedServices")
+                .field("peripheral_uuid", peripheral_uuid)
+                .field("service_uuid", service_uuid)
+                .field(
+                    "included_services",
+                    &included_services.keys().collect::<Vec<_>>(),
+                )
+                .finish(),
             CentralDelegateEvent::DiscoveredCharacteristics {
                 peripheral_uuid,
                 service_uuid,
@@ -191,6 +313,26 @@ impl Debug for CentralDelegateEvent {
                 .debug_struct("DisconnectedDevice")
                 .field("peripheral_uuid", peripheral_uuid)
                 .finish(),
+            CentralDelegateEvent::ReadRssi {
+                peripheral_uuid,
+                reply,
+            } => f
+                .debug_struct("ReadRssi")
+                .field("peripheral_uuid", peripheral_uuid)
+                .field("reply", reply)
+                .finish(),
+            CentralDelegateEvent::ReadyToSendWriteWithoutResponse { peripheral_uuid } => f
+                .debug_struct("ReadyToSendWriteWithoutResponse")
+                .field("peripheral_uuid", peripheral_uuid)
+                .finish(),
+            CentralDelegateEvent::NameUpdated {
+                peripheral_uuid,
+                name,
+            } => f
+                .debug_struct("NameUpdated")
+                .field("peripheral_uuid", peripheral_uuid)
+                .field("name", name)
+                .finish(),
             CentralDelegateEvent::CharacteristicSubscribed {
                 peripheral_uuid,
                 service_uuid,
@@ -291,6 +433,36 @@ impl Debug for CentralDelegateEvent {
                 .field("characteristic_uuid", characteristic_uuid)
                 .field("descriptor_uuid", descriptor_uuid)
                 .finish(),
+            CentralDelegateEvent::CharacteristicOperationFailed {
+                peripheral_uuid,
+                service_uuid,
+                characteristic_uuid,
+                operation,
+                error,
+            } => f
+                .debug_struct("CharacteristicOperationFailed")
+                .field("peripheral_uuid", peripheral_uuid)
+                .field("service_uuid", service_uuid)
+                .field("characteristic_uuid", characteristic_uuid)
+                .field("operation", operation)
+                .field("error", error)
+                .finish(),
+            CentralDelegateEvent::DescriptorOperationFailed {
+                peripheral_uuid,
+                service_uuid,
+                characteristic_uuid,
+                descriptor_uuid,
+                operation,
+                error,
+            } => f
+                .debug_struct("DescriptorOperationFailed")
+                .field("peripheral_uuid", peripheral_uuid)
+                .field("service_uuid", service_uuid)
+                .field("characteristic_uuid", characteristic_uuid)
+                .field("descriptor_uuid", descriptor_uuid)
+                .field("operation", operation)
+                .field("error", error)
+                .finish(),
         }
     }
 }
@@ -319,10 +491,38 @@ declare_class!(
             self.send_event(CentralDelegateEvent::DidUpdateState { state });
         }
 
-        // #[method(centralManager:willRestoreState:)]
-        // fn delegate_centralmanager_willrestorestate(&self, _central: &CBCentralManager, _dict: &NSDictionary<NSString, AnyObject>) {
-        //     trace!("delegate_centralmanager_willrestorestate");
-        // }
+        #[method(centralManager:willRestoreState:)]
+        fn delegate_centralmanager_willrestorestate(
+            &self,
+            _central: &CBCentralManager,
+            dict: &NSDictionary<NSString, AnyObject>,
+        ) {
+            trace!("delegate_centralmanager_willrestorestate");
+            let peripherals = dict
+                .get(unsafe { CBCentralManagerRestoredStatePeripheralsKey })
+                .map(|peripherals| {
+                    // SAFETY: peripherals is `NSArray<CBPeripheral>`
+                    let peripherals: *const AnyObject = peripherals;
+                    let peripherals: *const NSArray<CBPeripheral> = peripherals.cast();
+                    unsafe { &*peripherals }
+                        .iter()
+                        .map(|peripheral| peripheral.retain())
+                        .collect()
+                })
+                .unwrap_or_default();
+            let scan_services = dict
+                .get(unsafe { CBCentralManagerRestoredStateScanServicesKey })
+                .map(|services| {
+                    // SAFETY: services is `NSArray<CBUUID>`
+                    let services: *const AnyObject = services;
+                    let services: *const NSArray<CBUUID> = services.cast();
+                    unsafe { &*services }.iter().map(cbuuid_to_uuid).collect()
+                });
+            self.send_event(CentralDelegateEvent::WillRestoreState {
+                peripherals,
+                scan_services,
+            });
+        }
 
         #[method(centralManager:didConnectPeripheral:)]
         fn delegate_centralmanager_didconnectperipheral(
@@ -340,6 +540,24 @@ declare_class!(
             self.send_event(CentralDelegateEvent::ConnectedDevice { peripheral_uuid });
         }
 
+        #[method(centralManager:connectionEventDidOccur:forPeripheral:)]
+        fn delegate_centralmanager_connectioneventdidoccur_forperipheral(
+            &self,
+            _central: &CBCentralManager,
+            event: CBConnectionEvent,
+            peripheral: &CBPeripheral,
+        ) {
+            trace!(
+                "delegate_centralmanager_connectioneventdidoccur_forperipheral {:?} {}",
+                event,
+                peripheral_debug(peripheral)
+            );
+            self.send_event(CentralDelegateEvent::ConnectionEvent {
+                cbperipheral: peripheral.retain(),
+                connected: event == CBConnectionEvent::PeerConnected,
+            });
+        }
+
         #[method(centralManager:didDisconnectPeripheral:error:)]
         fn delegate_centralmanager_diddisconnectperipheral_error(
             &self,
@@ -398,6 +616,29 @@ declare_class!(
 
             let peripheral_uuid = nsuuid_to_uuid(unsafe { &peripheral.identifier() });
 
+            let connectable = adv_data
+                .get(unsafe { CBAdvertisementDataIsConnectable })
+                .map(|connectable| {
+                    // SAFETY: connectable is `NSNumber`
+                    let connectable: *const AnyObject = connectable;
+                    let connectable: *const NSNumber = connectable.cast();
+                    unsafe { &*connectable }.as_bool()
+                });
+            let tx_power_level = adv_data
+                .get(unsafe { CBAdvertisementDataTxPowerLevelKey })
+                .map(|tx_power_level| {
+                    // SAFETY: tx_power_level is `NSNumber`
+                    let tx_power_level: *const AnyObject = tx_power_level;
+                    let tx_power_level: *const NSNumber = tx_power_level.cast();
+                    unsafe { &*tx_power_level }.as_i16()
+                });
+            self.send_event(CentralDelegateEvent::Advertisement {
+                peripheral_uuid,
+                connectable,
+                tx_power_level,
+                rssi: rssi_value,
+            });
+
             let manufacturer_data = adv_data.get(unsafe { CBAdvertisementDataManufacturerDataKey });
             if let Some(manufacturer_data) = manufacturer_data {
                 // SAFETY: manufacturer_data is `NSData`
@@ -412,7 +653,7 @@ declare_class!(
                     self.send_event(CentralDelegateEvent::ManufacturerData {
                         peripheral_uuid,
                         manufacturer_id: u16::from_le_bytes(manufacturer_id.try_into().unwrap()),
-                        data: Vec::from(manufacturer_data),
+                        data: manufacturer_data.into(),
                         rssi: rssi_value,
                     });
                 }
@@ -428,7 +669,7 @@ declare_class!(
                 let mut result = HashMap::new();
                 for uuid in service_data.keys() {
                     let data = &service_data[uuid];
-                    result.insert(cbuuid_to_uuid(uuid), data.bytes().to_vec());
+                    result.insert(cbuuid_to_uuid(uuid), data.bytes().into());
                 }
 
                 self.send_event(CentralDelegateEvent::ServiceData {
@@ -493,6 +734,21 @@ declare_class!(
             }
         }
 
+        #[method(peripheral:didModifyServices:)]
+        fn delegate_peripheral_didmodifyservices(
+            &self,
+            peripheral: &CBPeripheral,
+            _invalidated_services: &NSArray<CBService>,
+        ) {
+            trace!(
+                "delegate_peripheral_didmodifyservices {}",
+                peripheral_debug(peripheral)
+            );
+            // Rediscover everything, the same as when we first connected. The results replace
+            // the services we knew about before.
+            unsafe { peripheral.discoverServices(None) }
+        }
+
         #[method(peripheral:didDiscoverIncludedServicesForService:error:)]
         fn delegate_peripheral_diddiscoverincludedservicesforservice_error(
             &self,
@@ -506,12 +762,24 @@ declare_class!(
                 service_debug(service),
                 localized_description(error)
             );
+            // Send the event even on error, as discovery isn't finished until we've heard back
+            // about every service's included services.
+            let mut included_services = HashMap::new();
             if error.is_none() {
                 let includes = unsafe { service.includedServices() }.unwrap_or_default();
                 for s in includes {
                     unsafe { peripheral.discoverCharacteristics_forService(None, &s) };
+                    let uuid = cbuuid_to_uuid(unsafe { &s.UUID() });
+                    included_services.insert(uuid, s);
                 }
             }
+            let peripheral_uuid = nsuuid_to_uuid(unsafe { &peripheral.identifier() });
+            let service_uuid = cbuuid_to_uuid(unsafe { &service.UUID() });
+            self.send_event(CentralDelegateEvent::DiscoveredIncludedServices {
+                peripheral_uuid,
+                service_uuid,
+                included_services,
+            });
         }
 
         #[method(peripheral:didDiscoverCharacteristicsForService:error:)]
@@ -602,6 +870,8 @@ declare_class!(
                     data: get_characteristic_value(characteristic),
                 });
                 // Notify BluetoothGATTCharacteristic::read_value that read was successful.
+            } else if let Some(error) = error {
+                self.send_characteristic_error(peripheral, characteristic, GattOperation::Read, error);
             }
         }
 
@@ -625,6 +895,8 @@ declare_class!(
                     service_uuid: cbuuid_to_uuid(unsafe { &service.UUID() }),
                     characteristic_uuid: cbuuid_to_uuid(unsafe { &characteristic.UUID() }),
                 });
+            } else if let Some(error) = error {
+                self.send_characteristic_error(peripheral, characteristic, GattOperation::Write, error);
             }
         }
 
@@ -633,10 +905,25 @@ declare_class!(
             &self,
             peripheral: &CBPeripheral,
             characteristic: &CBCharacteristic,
-            _error: Option<&NSError>,
+            error: Option<&NSError>,
         ) {
-            trace!("delegate_peripheral_didupdatenotificationstateforcharacteristic_error");
-            // TODO check for error here
+            trace!(
+                "delegate_peripheral_didupdatenotificationstateforcharacteristic_error {} {} {}",
+                peripheral_debug(peripheral),
+                characteristic_debug(characteristic),
+                localized_description(error)
+            );
+            if let Some(error) = error {
+                // The notification state is left as it was, so tells us which way we failed to
+                // change it.
+                let operation = if unsafe { characteristic.isNotifying() } {
+                    GattOperation::Unsubscribe
+                } else {
+                    GattOperation::Subscribe
+                };
+                self.send_characteristic_error(peripheral, characteristic, operation, error);
+                return;
+            }
             let peripheral_uuid = nsuuid_to_uuid(unsafe { &peripheral.identifier() });
             let service = unsafe { characteristic.service() }.unwrap();
             let service_uuid = cbuuid_to_uuid(unsafe { &service.UUID() });
@@ -660,14 +947,21 @@ declare_class!(
         fn delegate_peripheral_didreadrssi_error(
             &self,
             peripheral: &CBPeripheral,
-            _rssi: &NSNumber,
+            rssi: &NSNumber,
             error: Option<&NSError>,
         ) {
             trace!(
                 "delegate_peripheral_didreadrssi_error {}",
                 peripheral_debug(peripheral)
             );
-            if error.is_none() {}
+            let reply = match error {
+                None => CoreBluetoothReply::Rssi(rssi.as_i16()),
+                Some(error) => error_reply(error),
+            };
+            self.send_event(CentralDelegateEvent::ReadRssi {
+                peripheral_uuid: nsuuid_to_uuid(unsafe { &peripheral.identifier() }),
+                reply,
+            });
         }
 
         #[method(peripheral:didUpdateValueForDescriptor:error:)]
@@ -694,6 +988,8 @@ declare_class!(
                     data: get_descriptor_value(&descriptor),
                 });
                 // Notify BluetoothGATTCharacteristic::read_value that read was successful.
+            } else if let Some(error) = error {
+                self.send_descriptor_error(peripheral, descriptor, GattOperation::Read, error);
             }
         }
 
@@ -719,6 +1015,33 @@ declare_class!(
                     characteristic_uuid: cbuuid_to_uuid(unsafe { &characteristic.UUID() }),
                     descriptor_uuid: cbuuid_to_uuid(unsafe { &descriptor.UUID() }),
                 });
+            } else if let Some(error) = error {
+                self.send_descriptor_error(peripheral, descriptor, GattOperation::Write, error);
+            }
+        }
+
+        #[method(peripheralIsReadyToSendWriteWithoutResponse:)]
+        fn delegate_peripheralisreadytosendwritewithoutresponse(&self, peripheral: &CBPeripheral) {
+            trace!(
+                "delegate_peripheralisreadytosendwritewithoutresponse {}",
+                peripheral_debug(peripheral)
+            );
+            self.send_event(CentralDelegateEvent::ReadyToSendWriteWithoutResponse {
+                peripheral_uuid: nsuuid_to_uuid(unsafe { &peripheral.identifier() }),
+            });
+        }
+
+        #[method(peripheralDidUpdateName:)]
+        fn delegate_peripheraldidupdatename(&self, peripheral: &CBPeripheral) {
+            trace!(
+                "delegate_peripheraldidupdatename {}",
+                peripheral_debug(peripheral)
+            );
+            if let Some(name) = unsafe { peripheral.name() } {
+                self.send_event(CentralDelegateEvent::NameUpdated {
+                    peripheral_uuid: nsuuid_to_uuid(unsafe { &peripheral.identifier() }),
+                    name: name.to_string(),
+                });
             }
         }
     }
@@ -730,6 +1053,42 @@ impl CentralDelegate {
         unsafe { msg_send_id![super(this), init] }
     }
 
+    fn send_characteristic_error(
+        &self,
+        peripheral: &CBPeripheral,
+        characteristic: &CBCharacteristic,
+        operation: GattOperation,
+        error: &NSError,
+    ) {
+        let service = unsafe { characteristic.service() }.unwrap();
+        self.send_event(CentralDelegateEvent::CharacteristicOperationFailed {
+            peripheral_uuid: nsuuid_to_uuid(unsafe { &peripheral.identifier() }),
+            service_uuid: cbuuid_to_uuid(unsafe { &service.UUID() }),
+            characteristic_uuid: cbuuid_to_uuid(unsafe { &characteristic.UUID() }),
+            operation,
+            error: error_reply(error),
+        });
+    }
+
+    fn send_descriptor_error(
+        &self,
+        peripheral: &CBPeripheral,
+        descriptor: &CBDescriptor,
+        operation: GattOperation,
+        error: &NSError,
+    ) {
+        let characteristic = unsafe { descriptor.characteristic() }.unwrap();
+        let service = unsafe { characteristic.service() }.unwrap();
+        self.send_event(CentralDelegateEvent::DescriptorOperationFailed {
+            peripheral_uuid: nsuuid_to_uuid(unsafe { &peripheral.identifier() }),
+            service_uuid: cbuuid_to_uuid(unsafe { &service.UUID() }),
+            characteristic_uuid: cbuuid_to_uuid(unsafe { &characteristic.UUID() }),
+            descriptor_uuid: cbuuid_to_uuid(unsafe { &descriptor.UUID() }),
+            operation,
+            error: error_reply(error),
+        });
+    }
+
     fn send_event(&self, event: CentralDelegateEvent) {
         let mut sender = self.ivars().clone();
         futures::executor::block_on(async {
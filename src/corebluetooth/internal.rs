@@ -9,15 +9,20 @@
 // multiple), see https://forums.developer.apple.com/thread/20810
 
 use super::{
-    central_delegate::{CentralDelegate, CentralDelegateEvent},
+    adapter::{RestoredPeripheral, RestoredState},
+    central_delegate::{CentralDelegate, CentralDelegateEvent, GattOperation},
     ffi,
     future::{BtlePlugFuture, BtlePlugFutureStateShared},
+    peripheral::ConnectOptions,
     utils::{
         core_bluetooth::{cbuuid_to_uuid, uuid_to_cbuuid},
         nsuuid_to_uuid,
     },
 };
-use crate::api::{CharPropFlags, Characteristic, Descriptor, ScanFilter, Service, WriteType};
+use crate::api::{
+    spans, AttError, CharPropFlags, Characteristic, Descriptor, NativeError, ScanFilter, Service,
+    SubscriptionType, ValueNotification, WriteType,
+};
 use crate::Error;
 use futures::channel::mpsc::{self, Receiver, Sender};
 use futures::select;
@@ -25,19 +30,31 @@ use futures::sink::SinkExt;
 use futures::stream::{Fuse, StreamExt};
 use log::{error, trace, warn};
 use objc2::{msg_send_id, ClassType};
-use objc2::{rc::Retained, runtime::AnyObject};
+use objc2::{
+    rc::Retained,
+    runtime::{AnyObject, ProtocolObject},
+};
+#[cfg(target_os = "ios")]
+use objc2_core_bluetooth::CBConnectionEventMatchingOptionServiceUUIDs;
 use objc2_core_bluetooth::{
-    CBCentralManager, CBCentralManagerScanOptionAllowDuplicatesKey, CBCharacteristic,
-    CBCharacteristicProperties, CBCharacteristicWriteType, CBDescriptor, CBManager,
+    CBCentralManager, CBCentralManagerOptionRestoreIdentifierKey,
+    CBCentralManagerScanOptionAllowDuplicatesKey, CBCharacteristic, CBCharacteristicProperties,
+    CBCharacteristicWriteType, CBConnectPeripheralOptionNotifyOnConnectionKey,
+    CBConnectPeripheralOptionNotifyOnDisconnectionKey,
+    CBConnectPeripheralOptionNotifyOnNotificationKey, CBDescriptor, CBManager,
     CBManagerAuthorization, CBManagerState, CBPeripheral, CBPeripheralState, CBService, CBUUID,
 };
-use objc2_foundation::{NSArray, NSData, NSMutableDictionary, NSNumber};
+use objc2_foundation::{
+    NSArray, NSData, NSDictionary, NSMutableDictionary, NSNumber, NSString, NSUUID,
+};
 use std::{
     collections::{BTreeSet, HashMap, VecDeque},
     ffi::CString,
     fmt::{self, Debug, Formatter},
     ops::Deref,
+    sync::Arc,
     thread,
+    time::Instant,
 };
 use tokio::runtime;
 use uuid::Uuid;
@@ -149,18 +166,29 @@ pub enum CoreBluetoothReply {
     AdapterState(CBManagerState),
     ReadResult(Vec<u8>),
     Connected(BTreeSet<Service>),
+    Peripherals(Vec<Uuid>),
     State(CBPeripheralState),
+    Rssi(i16),
+    Notifying(bool),
     Ok,
     Err(String),
+    AttError(AttError),
+    NativeError(NativeError),
 }
 
 #[derive(Debug)]
 pub enum PeripheralEventInternal {
     Disconnected,
-    Notification(Uuid, Vec<u8>),
-    ManufacturerData(u16, Vec<u8>, i16),
-    ServiceData(HashMap<Uuid, Vec<u8>>, i16),
+    ServicesChanged(BTreeSet<Service>),
+    Notification(ValueNotification),
+    ManufacturerData(u16, Arc<[u8]>, i16),
+    ServiceData(HashMap<Uuid, Arc<[u8]>>, i16),
     Services(Vec<Uuid>, i16),
+    Advertisement {
+        connectable: Option<bool>,
+        tx_power_level: Option<i16>,
+        rssi: i16,
+    },
 }
 
 pub type CoreBluetoothReplyStateShared = BtlePlugFutureStateShared<CoreBluetoothReply>;
@@ -169,7 +197,16 @@ pub type CoreBluetoothReplyFuture = BtlePlugFuture<CoreBluetoothReply>;
 struct ServiceInternal {
     cbservice: Retained<CBService>,
     characteristics: HashMap<Uuid, CharacteristicInternal>,
+    included_services: BTreeSet<Uuid>,
     pub discovered: bool,
+    pub included_services_discovered: bool,
+}
+
+/// A write without response which is waiting for CoreBluetooth to have room to send it.
+struct PendingWrite {
+    characteristic: Retained<CBCharacteristic>,
+    data: Vec<u8>,
+    future: CoreBluetoothReplyStateShared,
 }
 
 struct PeripheralInternal {
@@ -178,6 +215,9 @@ struct PeripheralInternal {
     pub event_sender: Sender<PeripheralEventInternal>,
     pub disconnected_future_state: Option<CoreBluetoothReplyStateShared>,
     pub connected_future_state: Option<CoreBluetoothReplyStateShared>,
+    /// The futures waiting for the RSSI being read.
+    rssi_futures: Vec<CoreBluetoothReplyStateShared>,
+    pending_writes: VecDeque<PendingWrite>,
 }
 
 impl Debug for PeripheralInternal {
@@ -209,6 +249,32 @@ impl PeripheralInternal {
             event_sender,
             connected_future_state: None,
             disconnected_future_state: None,
+            rssi_futures: Vec::new(),
+            pending_writes: VecDeque::new(),
+        }
+    }
+
+    /// Sends queued writes without response, in order, for as long as CoreBluetooth has room for
+    /// them. Each write's future is only fulfilled once it has been handed to CoreBluetooth, so
+    /// writing in a loop waits for the peripheral rather than overrunning CoreBluetooth's buffer,
+    /// which would silently drop writes.
+    pub fn send_pending_writes(&mut self) {
+        while !self.pending_writes.is_empty()
+            && unsafe { self.peripheral.canSendWriteWithoutResponse() }
+        {
+            let write = self.pending_writes.pop_front().unwrap();
+            unsafe {
+                self.peripheral.writeValue_forCharacteristic_type(
+                    &NSData::from_vec(write.data),
+                    &write.characteristic,
+                    CBCharacteristicWriteType::CBCharacteristicWriteWithoutResponse,
+                );
+            }
+            write
+                .future
+                .lock()
+                .unwrap()
+                .set_reply(CoreBluetoothReply::Ok);
         }
     }
 
@@ -242,6 +308,33 @@ impl PeripheralInternal {
         }
     }
 
+    pub fn set_included_services(
+        &mut self,
+        service_uuid: Uuid,
+        included_services: HashMap<Uuid, Retained<CBService>>,
+    ) {
+        let Some(service) = self.services.get_mut(&service_uuid) else {
+            warn!("Got included services for a service we don't know about");
+            return;
+        };
+        service.included_services = included_services.keys().copied().collect();
+        service.included_services_discovered = true;
+        // Characteristics have already been requested for the included services. We only follow
+        // one level of inclusion, so don't wait for their own included services.
+        for (included_uuid, cbservice) in included_services {
+            self.services
+                .entry(included_uuid)
+                .or_insert_with(|| ServiceInternal {
+                    cbservice,
+                    characteristics: HashMap::new(),
+                    included_services: BTreeSet::new(),
+                    discovered: false,
+                    included_services_discovered: true,
+                });
+        }
+        self.check_discovered();
+    }
+
     pub fn set_characteristic_descriptors(
         &mut self,
         service_uuid: Uuid,
@@ -284,10 +377,11 @@ impl PeripheralInternal {
         // service map. Once that's done, we're filled out enough and can send
         // back a Connected reply to the waiting future with all of the
         // characteristic info in it.
-        if !self.services.values().any(|service| !service.discovered) {
-            if self.connected_future_state.is_none() {
-                panic!("We should still have a future at this point!");
-            }
+        if !self
+            .services
+            .values()
+            .any(|service| !service.discovered || !service.included_services_discovered)
+        {
             let services = self
                 .services
                 .iter()
@@ -305,6 +399,8 @@ impl PeripheralInternal {
                                     uuid: descriptor_uuid,
                                     service_uuid,
                                     characteristic_uuid,
+                                    handle: None,
+                                    value: None,
                                 })
                                 .collect();
                             Characteristic {
@@ -312,17 +408,31 @@ impl PeripheralInternal {
                                 service_uuid,
                                 descriptors,
                                 properties: characteristic.properties,
+                                handle: None,
+                                value_handle: None,
                             }
                         })
                         .collect(),
+                    included_services: service.included_services.clone(),
+                    handle: None,
                 })
                 .collect();
-            self.connected_future_state
-                .take()
-                .unwrap()
-                .lock()
-                .unwrap()
-                .set_reply(CoreBluetoothReply::Connected(services));
+            match self.connected_future_state.take() {
+                Some(future) => future
+                    .lock()
+                    .unwrap()
+                    .set_reply(CoreBluetoothReply::Connected(services)),
+                // Without a connect future, this is rediscovery after the device's services
+                // changed.
+                None => {
+                    if let Err(e) = self
+                        .event_sender
+                        .try_send(PeripheralEventInternal::ServicesChanged(services))
+                    {
+                        error!("Error sending services changed event: {}", e);
+                    }
+                }
+            }
         }
     }
 
@@ -335,6 +445,9 @@ impl PeripheralInternal {
 
         // Fulfill all pending futures
         let error = CoreBluetoothReply::Err(String::from("Device disconnected"));
+        for write in self.pending_writes.drain(..) {
+            write.future.lock().unwrap().set_reply(error.clone());
+        }
         self.services.iter().for_each(|(_, service)| {
             service
                 .characteristics
@@ -375,6 +488,9 @@ struct CoreBluetoothInternal {
     // task::block this when sending even though it'll never actually block.
     event_sender: Sender<CoreBluetoothEvent>,
     message_receiver: Fuse<Receiver<CoreBluetoothMessage>>,
+    // Restored peripherals which are connected, whose services are discovered once the manager is
+    // powered on.
+    restored: Vec<Uuid>,
 }
 
 impl Debug for CoreBluetoothInternal {
@@ -386,6 +502,7 @@ impl Debug for CoreBluetoothInternal {
             .field("delegate_receiver", &self.delegate_receiver)
             .field("event_sender", &self.event_sender)
             .field("message_receiver", &self.message_receiver)
+            .field("restored", &self.restored)
             .finish()
     }
 }
@@ -399,14 +516,32 @@ pub enum CoreBluetoothMessage {
         filter: ScanFilter,
     },
     StopScanning,
+    RetrievePeripheral {
+        peripheral_uuid: Uuid,
+        future: CoreBluetoothReplyStateShared,
+    },
+    RetrieveConnectedPeripherals {
+        services: Vec<Uuid>,
+        future: CoreBluetoothReplyStateShared,
+    },
     ConnectDevice {
         peripheral_uuid: Uuid,
+        options: ConnectOptions,
         future: CoreBluetoothReplyStateShared,
     },
+    #[cfg(target_os = "ios")]
+    RegisterForConnectionEvents {
+        services: Vec<Uuid>,
+    },
     DisconnectDevice {
         peripheral_uuid: Uuid,
         future: CoreBluetoothReplyStateShared,
     },
+    /// Drops what we keep about a peripheral the adapter has removed, so that it's reported as
+    /// discovered again if it's seen again.
+    ForgetPeripheral {
+        peripheral_uuid: Uuid,
+    },
     ReadValue {
         peripheral_uuid: Uuid,
         service_uuid: Uuid,
@@ -437,6 +572,16 @@ pub enum CoreBluetoothMessage {
         peripheral_uuid: Uuid,
         future: CoreBluetoothReplyStateShared,
     },
+    ReadRssi {
+        peripheral_uuid: Uuid,
+        future: CoreBluetoothReplyStateShared,
+    },
+    IsNotifying {
+        peripheral_uuid: Uuid,
+        service_uuid: Uuid,
+        characteristic_uuid: Uuid,
+        future: CoreBluetoothReplyStateShared,
+    },
     ReadDescriptorValue {
         peripheral_uuid: Uuid,
         service_uuid: Uuid,
@@ -468,15 +613,20 @@ pub enum CoreBluetoothEvent {
         uuid: Uuid,
         name: String,
     },
+    DeviceConnected {
+        uuid: Uuid,
+    },
     DeviceDisconnected {
         uuid: Uuid,
     },
+    StateRestored(RestoredState),
 }
 
 impl CoreBluetoothInternal {
     pub fn new(
         message_receiver: Receiver<CoreBluetoothMessage>,
         event_sender: Sender<CoreBluetoothEvent>,
+        restore_identifier: Option<String>,
     ) -> Self {
         // Pretty sure these come preallocated?
         let (sender, receiver) = mpsc::channel::<CentralDelegateEvent>(256);
@@ -487,8 +637,19 @@ impl CoreBluetoothInternal {
             unsafe { ffi::dispatch_queue_create(label.as_ptr(), ffi::DISPATCH_QUEUE_SERIAL) };
         let queue: *mut AnyObject = queue.cast();
 
-        let manager = unsafe {
-            msg_send_id![CBCentralManager::alloc(), initWithDelegate: &*delegate, queue: queue]
+        let manager = match restore_identifier {
+            Some(identifier) => {
+                let options = NSDictionary::from_id_slice(
+                    &[unsafe { CBCentralManagerOptionRestoreIdentifierKey }],
+                    &[NSString::from_str(&identifier)],
+                );
+                unsafe {
+                    msg_send_id![CBCentralManager::alloc(), initWithDelegate: &*delegate, queue: queue, options: &*options]
+                }
+            }
+            None => unsafe {
+                msg_send_id![CBCentralManager::alloc(), initWithDelegate: &*delegate, queue: queue]
+            },
         };
 
         Self {
@@ -498,6 +659,7 @@ impl CoreBluetoothInternal {
             event_sender,
             message_receiver: message_receiver.fuse(),
             delegate,
+            restored: Vec::new(),
         }
     }
 
@@ -512,7 +674,7 @@ impl CoreBluetoothInternal {
         &mut self,
         peripheral_uuid: Uuid,
         manufacturer_id: u16,
-        manufacturer_data: Vec<u8>,
+        manufacturer_data: Arc<[u8]>,
         rssi: i16,
     ) {
         trace!(
@@ -538,7 +700,7 @@ impl CoreBluetoothInternal {
     async fn on_service_data(
         &mut self,
         peripheral_uuid: Uuid,
-        service_data: HashMap<Uuid, Vec<u8>>,
+        service_data: HashMap<Uuid, Arc<[u8]>>,
         rssi: i16,
     ) {
         trace!("Got service data advertisement! {:?}", service_data);
@@ -566,6 +728,28 @@ impl CoreBluetoothInternal {
         }
     }
 
+    async fn on_advertisement(
+        &mut self,
+        peripheral_uuid: Uuid,
+        connectable: Option<bool>,
+        tx_power_level: Option<i16>,
+        rssi: i16,
+    ) {
+        if let Some(p) = self.peripherals.get_mut(&peripheral_uuid) {
+            if let Err(e) = p
+                .event_sender
+                .send(PeripheralEventInternal::Advertisement {
+                    connectable,
+                    tx_power_level,
+                    rssi,
+                })
+                .await
+            {
+                error!("Error sending notification event: {}", e);
+            }
+        }
+    }
+
     async fn on_discovered_peripheral(
         &mut self,
         peripheral: Retained<CBPeripheral>,
@@ -593,19 +777,142 @@ impl CoreBluetoothInternal {
                 .await;
             }
         } else {
-            // Create our channels
-            let (event_sender, event_receiver) = mpsc::channel(256);
-            self.peripherals
-                .insert(uuid, PeripheralInternal::new(peripheral, event_sender));
-            self.dispatch_event(CoreBluetoothEvent::DeviceDiscovered {
-                uuid,
-                name: name.map(|name| name.to_string()),
-                event_receiver,
-            })
+            self.add_peripheral(uuid, peripheral, name).await;
+        }
+    }
+
+    async fn add_peripheral(
+        &mut self,
+        uuid: Uuid,
+        peripheral: Retained<CBPeripheral>,
+        name: Option<String>,
+    ) {
+        // Create our channels
+        let (event_sender, event_receiver) = mpsc::channel(256);
+        self.peripherals
+            .insert(uuid, PeripheralInternal::new(peripheral, event_sender));
+        self.dispatch_event(CoreBluetoothEvent::DeviceDiscovered {
+            uuid,
+            name,
+            event_receiver,
+        })
+        .await;
+    }
+
+    /// Takes over the peripherals the system kept connections to while the app wasn't running. This
+    /// is called before the first state update, so the services of connected peripherals are only
+    /// discovered once the manager is powered on.
+    async fn on_restore_state(
+        &mut self,
+        peripherals: Vec<Retained<CBPeripheral>>,
+        scan_services: Option<Vec<Uuid>>,
+    ) {
+        let mut state = RestoredState {
+            peripherals: Vec::with_capacity(peripherals.len()),
+            scan_services,
+        };
+        for peripheral in peripherals {
+            let uuid = nsuuid_to_uuid(unsafe { &peripheral.identifier() });
+            trace!("Restoring peripheral {}", uuid);
+            // The delegate is otherwise only set when the peripheral connects.
+            unsafe { peripheral.setDelegate(Some(ProtocolObject::from_ref(&*self.delegate))) };
+            let connected = unsafe { peripheral.state() } == CBPeripheralState::Connected;
+            let mut subscribed = Vec::new();
+            for service in unsafe { peripheral.services() }
+                .iter()
+                .flat_map(|s| s.iter())
+            {
+                for characteristic in unsafe { service.characteristics() }
+                    .iter()
+                    .flat_map(|c| c.iter())
+                {
+                    if unsafe { characteristic.isNotifying() } {
+                        subscribed.push(cbuuid_to_uuid(unsafe { &characteristic.UUID() }));
+                    }
+                }
+            }
+            if connected {
+                self.restored.push(uuid);
+            }
+            if !self.peripherals.contains_key(&uuid) {
+                let name = unsafe { peripheral.name() }.map(|name| name.to_string());
+                self.add_peripheral(uuid, peripheral, name).await;
+            }
+            state.peripherals.push(RestoredPeripheral {
+                id: uuid.into(),
+                connected,
+                subscribed,
+            });
+        }
+        self.dispatch_event(CoreBluetoothEvent::StateRestored(state))
             .await;
+    }
+
+    fn on_update_state(&mut self, state: CBManagerState) {
+        if state != CBManagerState::PoweredOn {
+            return;
+        }
+        for uuid in self.restored.drain(..) {
+            if let Some(p) = self.peripherals.get(&uuid) {
+                unsafe { p.peripheral.discoverServices(None) };
+            }
         }
     }
 
+    /// Looks up a peripheral which the system knows from before, for example because we connected
+    /// to it when the app last ran, so that it can be connected to without scanning for it. The
+    /// peripheral is reported as discovered before the reply is sent.
+    async fn retrieve_peripheral(
+        &mut self,
+        peripheral_uuid: Uuid,
+        fut: CoreBluetoothReplyStateShared,
+    ) {
+        if !self.peripherals.contains_key(&peripheral_uuid) {
+            let identifiers =
+                NSArray::from_vec(vec![NSUUID::from_bytes(*peripheral_uuid.as_bytes())]);
+            let peripherals = unsafe {
+                self.manager
+                    .retrievePeripheralsWithIdentifiers(&identifiers)
+            };
+            let Some(peripheral) = peripherals.first_retained() else {
+                fut.lock()
+                    .unwrap()
+                    .set_reply(CoreBluetoothReply::Err("Peripheral not known".to_string()));
+                return;
+            };
+            let name = unsafe { peripheral.name() }.map(|name| name.to_string());
+            self.add_peripheral(peripheral_uuid, peripheral, name).await;
+        }
+        fut.lock().unwrap().set_reply(CoreBluetoothReply::Ok);
+    }
+
+    /// Looks up the peripherals with any of the given services which are connected to the system,
+    /// possibly by another app. Those we didn't know are reported as discovered before the reply
+    /// is sent.
+    async fn retrieve_connected_peripherals(
+        &mut self,
+        services: Vec<Uuid>,
+        fut: CoreBluetoothReplyStateShared,
+    ) {
+        let services = NSArray::from_vec(services.into_iter().map(uuid_to_cbuuid).collect());
+        let peripherals = unsafe {
+            self.manager
+                .retrieveConnectedPeripheralsWithServices(&services)
+        };
+        let mut uuids = Vec::new();
+        for peripheral in (0..peripherals.len()).filter_map(|i| peripherals.get_retained(i)) {
+            let uuid = nsuuid_to_uuid(unsafe { &peripheral.identifier() });
+            if !self.peripherals.contains_key(&uuid) {
+                let name = unsafe { peripheral.name() }.map(|name| name.to_string());
+                self.add_peripheral(uuid, peripheral, name).await;
+            }
+            uuids.push(uuid);
+        }
+        fut.lock()
+            .unwrap()
+            .set_reply(CoreBluetoothReply::Peripherals(uuids));
+    }
+
     fn on_discovered_services(
         &mut self,
         peripheral_uuid: Uuid,
@@ -624,7 +931,9 @@ impl CoreBluetoothInternal {
                         ServiceInternal {
                             cbservice,
                             characteristics: HashMap::new(),
+                            included_services: BTreeSet::new(),
                             discovered: false,
+                            included_services_discovered: false,
                         },
                     )
                 })
@@ -633,6 +942,25 @@ impl CoreBluetoothInternal {
         }
     }
 
+    fn on_discovered_included_services(
+        &mut self,
+        peripheral_uuid: Uuid,
+        service_uuid: Uuid,
+        included_services: HashMap<Uuid, Retained<CBService>>,
+    ) {
+        trace!(
+            "Found included services for peripheral {} service {}:",
+            peripheral_uuid,
+            service_uuid
+        );
+        for id in included_services.keys() {
+            trace!("{}", id);
+        }
+        if let Some(p) = self.peripherals.get_mut(&peripheral_uuid) {
+            p.set_included_services(service_uuid, included_services);
+        }
+    }
+
     fn on_discovered_characteristics(
         &mut self,
         peripheral_uuid: Uuid,
@@ -719,6 +1047,10 @@ impl CoreBluetoothInternal {
                 .get_mut(&peripheral_uuid)
                 .expect("If we're here we should have an ID")
                 .confirm_disconnect();
+            self.on_read_rssi(
+                peripheral_uuid,
+                CoreBluetoothReply::Err("Peripheral disconnected".to_string()),
+            );
             self.peripherals.remove(&peripheral_uuid);
             self.dispatch_event(CoreBluetoothEvent::DeviceDisconnected {
                 uuid: peripheral_uuid,
@@ -816,10 +1148,19 @@ impl CoreBluetoothInternal {
                             .set_reply(CoreBluetoothReply::ReadResult(data_clone));
                     } else if let Err(e) = peripheral
                         .event_sender
-                        .send(PeripheralEventInternal::Notification(
-                            characteristic_uuid,
-                            data,
-                        ))
+                        .send(PeripheralEventInternal::Notification(ValueNotification {
+                            uuid: characteristic_uuid,
+                            service_uuid,
+                            handle: None,
+                            // CoreBluetooth uses notifications whenever the characteristic
+                            // supports them.
+                            subscription_type: SubscriptionType::preferred(
+                                characteristic.properties,
+                            )
+                            .unwrap_or(SubscriptionType::Notify),
+                            value: data,
+                            timestamp: Instant::now(),
+                        }))
                         .await
                     {
                         error!("Error sending notification event: {}", e);
@@ -844,13 +1185,99 @@ impl CoreBluetoothInternal {
         }
     }
 
-    fn connect_peripheral(&mut self, peripheral_uuid: Uuid, fut: CoreBluetoothReplyStateShared) {
+    fn on_characteristic_operation_failed(
+        &mut self,
+        peripheral_uuid: Uuid,
+        service_uuid: Uuid,
+        characteristic_uuid: Uuid,
+        operation: GattOperation,
+        error: CoreBluetoothReply,
+    ) {
+        if let Some(characteristic) =
+            self.get_characteristic(peripheral_uuid, service_uuid, characteristic_uuid)
+        {
+            trace!("Got {:?} failed event!", operation);
+            let futures = match operation {
+                GattOperation::Read => &mut characteristic.read_future_state,
+                GattOperation::Write => &mut characteristic.write_future_state,
+                GattOperation::Subscribe => &mut characteristic.subscribe_future_state,
+                GattOperation::Unsubscribe => &mut characteristic.unsubscribe_future_state,
+            };
+            // A failed read with no read pending was a notification, which there's no one to tell.
+            if let Some(state) = futures.pop_back() {
+                state.lock().unwrap().set_reply(error);
+            }
+        }
+    }
+
+    fn connect_peripheral(
+        &mut self,
+        peripheral_uuid: Uuid,
+        options: ConnectOptions,
+        fut: CoreBluetoothReplyStateShared,
+    ) {
         trace!("Trying to connect peripheral!");
         if let Some(p) = self.peripherals.get_mut(&peripheral_uuid) {
             trace!("Connecting peripheral!");
             p.connected_future_state = Some(fut);
-            unsafe { self.manager.connectPeripheral_options(&p.peripheral, None) };
+            let mut dictionary = NSMutableDictionary::new();
+            for (key, enabled) in [
+                (
+                    unsafe { CBConnectPeripheralOptionNotifyOnConnectionKey },
+                    options.notify_on_connection,
+                ),
+                (
+                    unsafe { CBConnectPeripheralOptionNotifyOnDisconnectionKey },
+                    options.notify_on_disconnection,
+                ),
+                (
+                    unsafe { CBConnectPeripheralOptionNotifyOnNotificationKey },
+                    options.notify_on_notification,
+                ),
+            ] {
+                if enabled {
+                    dictionary.insert_id(
+                        key,
+                        Retained::into_super(Retained::into_super(Retained::into_super(
+                            NSNumber::new_bool(true),
+                        ))),
+                    );
+                }
+            }
+            unsafe {
+                self.manager
+                    .connectPeripheral_options(&p.peripheral, Some(&dictionary))
+            };
+        }
+    }
+
+    #[cfg(target_os = "ios")]
+    fn register_for_connection_events(&mut self, services: Vec<Uuid>) {
+        let services = NSArray::from_vec(services.into_iter().map(uuid_to_cbuuid).collect());
+        let options = NSDictionary::from_vec(
+            &[unsafe { CBConnectionEventMatchingOptionServiceUUIDs }],
+            vec![Retained::into_super(Retained::into_super(services))],
+        );
+        unsafe {
+            self.manager
+                .registerForConnectionEventsWithOptions(Some(&options))
+        };
+    }
+
+    /// Handles a peripheral connecting to or disconnecting from the system, which we registered
+    /// for with `register_for_connection_events`. It may not be us who connected to it.
+    async fn on_connection_event(&mut self, peripheral: Retained<CBPeripheral>, connected: bool) {
+        let uuid = nsuuid_to_uuid(unsafe { &peripheral.identifier() });
+        if !connected {
+            self.on_peripheral_disconnect(uuid).await;
+            return;
         }
+        if !self.peripherals.contains_key(&uuid) {
+            let name = unsafe { peripheral.name() }.map(|name| name.to_string());
+            self.add_peripheral(uuid, peripheral, name).await;
+        }
+        self.dispatch_event(CoreBluetoothEvent::DeviceConnected { uuid })
+            .await;
     }
 
     fn disconnect_peripheral(&mut self, peripheral_uuid: Uuid, fut: CoreBluetoothReplyStateShared) {
@@ -872,6 +1299,23 @@ impl CoreBluetoothInternal {
         }
     }
 
+    fn is_notifying(
+        &mut self,
+        peripheral_uuid: Uuid,
+        service_uuid: Uuid,
+        characteristic_uuid: Uuid,
+        fut: CoreBluetoothReplyStateShared,
+    ) {
+        let reply =
+            match self.get_characteristic(peripheral_uuid, service_uuid, characteristic_uuid) {
+                Some(characteristic) => CoreBluetoothReply::Notifying(unsafe {
+                    characteristic.characteristic.isNotifying()
+                }),
+                None => CoreBluetoothReply::Err("Characteristic not found".to_string()),
+            };
+        fut.lock().unwrap().set_reply(reply);
+    }
+
     fn write_value(
         &mut self,
         peripheral_uuid: Uuid,
@@ -886,27 +1330,25 @@ impl CoreBluetoothInternal {
                 if let Some(characteristic) = service.characteristics.get_mut(&characteristic_uuid)
                 {
                     trace!("Writing value! With kind {:?}", kind);
+                    // WriteWithoutResponse does not call the corebluetooth callback, so queue it
+                    // until there is room to send it, and then it just succeeds silently.
+                    if kind == WriteType::WithoutResponse {
+                        peripheral.pending_writes.push_back(PendingWrite {
+                            characteristic: characteristic.characteristic.clone(),
+                            data,
+                            future: fut,
+                        });
+                        peripheral.send_pending_writes();
+                        return;
+                    }
                     unsafe {
                         peripheral.peripheral.writeValue_forCharacteristic_type(
                             &NSData::from_vec(data),
                             &characteristic.characteristic,
-                            match kind {
-                                WriteType::WithResponse => {
-                                    CBCharacteristicWriteType::CBCharacteristicWriteWithResponse
-                                }
-                                WriteType::WithoutResponse => {
-                                    CBCharacteristicWriteType::CBCharacteristicWriteWithoutResponse
-                                }
-                            },
+                            CBCharacteristicWriteType::CBCharacteristicWriteWithResponse,
                         );
                     }
-                    // WriteWithoutResponse does not call the corebluetooth
-                    // callback, it just always succeeds silently.
-                    if kind == WriteType::WithoutResponse {
-                        fut.lock().unwrap().set_reply(CoreBluetoothReply::Ok);
-                    } else {
-                        characteristic.write_future_state.push_front(fut);
-                    }
+                    characteristic.write_future_state.push_front(fut);
                 }
             }
         }
@@ -1066,6 +1508,45 @@ impl CoreBluetoothInternal {
         }
     }
 
+    fn read_rssi(&mut self, peripheral_uuid: Uuid, fut: CoreBluetoothReplyStateShared) {
+        match self.peripherals.get_mut(&peripheral_uuid) {
+            Some(p) if unsafe { p.peripheral.state() } == CBPeripheralState::Connected => {
+                // Reads which are already waiting get the same answer.
+                p.rssi_futures.push(fut);
+                if p.rssi_futures.len() == 1 {
+                    unsafe { p.peripheral.readRSSI() };
+                }
+            }
+            _ => fut.lock().unwrap().set_reply(CoreBluetoothReply::Err(
+                "Peripheral not connected".to_string(),
+            )),
+        }
+    }
+
+    fn on_read_rssi(&mut self, peripheral_uuid: Uuid, reply: CoreBluetoothReply) {
+        if let Some(p) = self.peripherals.get_mut(&peripheral_uuid) {
+            for fut in p.rssi_futures.drain(..) {
+                fut.lock().unwrap().set_reply(reply.clone());
+            }
+        }
+    }
+
+    async fn on_name_updated(&mut self, peripheral_uuid: Uuid, name: String) {
+        if self.peripherals.contains_key(&peripheral_uuid) {
+            self.dispatch_event(CoreBluetoothEvent::DeviceUpdated {
+                uuid: peripheral_uuid,
+                name,
+            })
+            .await;
+        }
+    }
+
+    fn on_ready_to_send_write_without_response(&mut self, peripheral_uuid: Uuid) {
+        if let Some(peripheral) = self.peripherals.get_mut(&peripheral_uuid) {
+            peripheral.send_pending_writes();
+        }
+    }
+
     fn on_descriptor_written(
         &mut self,
         peripheral_uuid: Uuid,
@@ -1085,6 +1566,33 @@ impl CoreBluetoothInternal {
         }
     }
 
+    fn on_descriptor_operation_failed(
+        &mut self,
+        peripheral_uuid: Uuid,
+        service_uuid: Uuid,
+        characteristic_uuid: Uuid,
+        descriptor_uuid: Uuid,
+        operation: GattOperation,
+        error: CoreBluetoothReply,
+    ) {
+        if let Some(descriptor) = self.get_descriptor(
+            peripheral_uuid,
+            service_uuid,
+            characteristic_uuid,
+            descriptor_uuid,
+        ) {
+            trace!("Got descriptor {:?} failed event!", operation);
+            let futures = match operation {
+                GattOperation::Read => &mut descriptor.read_future_state,
+                GattOperation::Write => &mut descriptor.write_future_state,
+                GattOperation::Subscribe | GattOperation::Unsubscribe => return,
+            };
+            if let Some(state) = futures.pop_back() {
+                state.lock().unwrap().set_reply(error);
+            }
+        }
+    }
+
     async fn wait_for_message(&mut self) {
         select! {
             delegate_msg = self.delegate_receiver.select_next_some() => {
@@ -1093,28 +1601,47 @@ impl CoreBluetoothInternal {
                     // "ready" variable in our adapter that will cause scans/etc
                     // to fail if this hasn't updated.
                     CentralDelegateEvent::DidUpdateState{state} => {
-                        self.dispatch_event(CoreBluetoothEvent::DidUpdateState{state}).await
+                        self.dispatch_event(CoreBluetoothEvent::DidUpdateState{state}).await;
+                        self.on_update_state(state);
+                    }
+                    CentralDelegateEvent::WillRestoreState{peripherals, scan_services} => {
+                        self.on_restore_state(peripherals, scan_services).await
                     }
                     CentralDelegateEvent::DiscoveredPeripheral{cbperipheral, local_name} => {
                         self.on_discovered_peripheral(cbperipheral, local_name).await
                     }
+                    CentralDelegateEvent::Advertisement{peripheral_uuid, connectable, tx_power_level, rssi} => {
+                        spans::callback("advertisement", Some(&peripheral_uuid))
+                            .instrument(self.on_advertisement(peripheral_uuid, connectable, tx_power_level, rssi))
+                            .await
+                    }
                     CentralDelegateEvent::DiscoveredServices{peripheral_uuid, services} => {
                         self.on_discovered_services(peripheral_uuid, services)
                     }
+                    CentralDelegateEvent::DiscoveredIncludedServices{peripheral_uuid, service_uuid, included_services} => {
+                        self.on_discovered_included_services(peripheral_uuid, service_uuid, included_services)
+                    }
                     CentralDelegateEvent::DiscoveredCharacteristics{peripheral_uuid, service_uuid, characteristics} => {
                         self.on_discovered_characteristics(peripheral_uuid, service_uuid, characteristics)
                     }
                     CentralDelegateEvent::DiscoveredCharacteristicDescriptors{peripheral_uuid, service_uuid, characteristic_uuid, descriptors} => {
                         self.on_discovered_characteristic_descriptors(peripheral_uuid, service_uuid, characteristic_uuid, descriptors)
                     }
+                    CentralDelegateEvent::ConnectionEvent{cbperipheral, connected} => {
+                        self.on_connection_event(cbperipheral, connected).await
+                    }
                     CentralDelegateEvent::ConnectedDevice{peripheral_uuid} => {
-                            self.on_peripheral_connect(peripheral_uuid)
+                        spans::callback("connection", Some(&peripheral_uuid))
+                            .in_scope(|| self.on_peripheral_connect(peripheral_uuid))
                     },
                     CentralDelegateEvent::ConnectionFailed{peripheral_uuid, error_description} => {
-                        self.on_peripheral_connection_failed(peripheral_uuid, error_description)
+                        spans::callback("connection", Some(&peripheral_uuid))
+                            .in_scope(|| self.on_peripheral_connection_failed(peripheral_uuid, error_description))
                     },
                     CentralDelegateEvent::DisconnectedDevice{peripheral_uuid} => {
-                        self.on_peripheral_disconnect(peripheral_uuid).await
+                        spans::callback("connection", Some(&peripheral_uuid))
+                            .instrument(self.on_peripheral_disconnect(peripheral_uuid))
+                            .await
                     }
                     CentralDelegateEvent::CharacteristicSubscribed{
                         peripheral_uuid,
@@ -1131,20 +1658,30 @@ impl CoreBluetoothInternal {
                         service_uuid,
                         characteristic_uuid,
                         data,
-                     } => self.on_characteristic_read(peripheral_uuid, service_uuid,characteristic_uuid, data).await,
+                     } => {
+                        spans::callback("notification", Some(&peripheral_uuid))
+                            .instrument(self.on_characteristic_read(peripheral_uuid, service_uuid,characteristic_uuid, data))
+                            .await
+                     }
                     CentralDelegateEvent::CharacteristicWritten{
                         peripheral_uuid,
                         service_uuid,
                         characteristic_uuid,
                     } => self.on_characteristic_written(peripheral_uuid, service_uuid, characteristic_uuid),
                     CentralDelegateEvent::ManufacturerData{peripheral_uuid, manufacturer_id, data, rssi} => {
-                        self.on_manufacturer_data(peripheral_uuid, manufacturer_id, data, rssi).await
+                        spans::callback("advertisement", Some(&peripheral_uuid))
+                            .instrument(self.on_manufacturer_data(peripheral_uuid, manufacturer_id, data, rssi))
+                            .await
                     },
                     CentralDelegateEvent::ServiceData{peripheral_uuid, service_data, rssi} => {
-                        self.on_service_data(peripheral_uuid, service_data, rssi).await
+                        spans::callback("advertisement", Some(&peripheral_uuid))
+                            .instrument(self.on_service_data(peripheral_uuid, service_data, rssi))
+                            .await
                     },
                     CentralDelegateEvent::Services{peripheral_uuid, service_uuids, rssi} => {
-                        self.on_services(peripheral_uuid, service_uuids, rssi).await
+                        spans::callback("advertisement", Some(&peripheral_uuid))
+                            .instrument(self.on_services(peripheral_uuid, service_uuids, rssi))
+                            .await
                     },
                     CentralDelegateEvent::DescriptorNotified{
                         peripheral_uuid,
@@ -1159,6 +1696,30 @@ impl CoreBluetoothInternal {
                         characteristic_uuid,
                         descriptor_uuid,
                     } => self.on_descriptor_written(peripheral_uuid, service_uuid, characteristic_uuid, descriptor_uuid),
+                    CentralDelegateEvent::ReadRssi{peripheral_uuid, reply} => {
+                        self.on_read_rssi(peripheral_uuid, reply)
+                    }
+                    CentralDelegateEvent::ReadyToSendWriteWithoutResponse{peripheral_uuid} => {
+                        self.on_ready_to_send_write_without_response(peripheral_uuid)
+                    }
+                    CentralDelegateEvent::NameUpdated{peripheral_uuid, name} => {
+                        self.on_name_updated(peripheral_uuid, name).await
+                    }
+                    CentralDelegateEvent::CharacteristicOperationFailed{
+                        peripheral_uuid,
+                        service_uuid,
+                        characteristic_uuid,
+                        operation,
+                        error,
+                    } => self.on_characteristic_operation_failed(peripheral_uuid, service_uuid, characteristic_uuid, operation, error),
+                    CentralDelegateEvent::DescriptorOperationFailed{
+                        peripheral_uuid,
+                        service_uuid,
+                        characteristic_uuid,
+                        descriptor_uuid,
+                        operation,
+                        error,
+                    } => self.on_descriptor_operation_failed(peripheral_uuid, service_uuid, characteristic_uuid, descriptor_uuid, operation, error),
                 };
             }
             adapter_msg = self.message_receiver.select_next_some() => {
@@ -1169,13 +1730,26 @@ impl CoreBluetoothInternal {
                     },
                     CoreBluetoothMessage::StartScanning{filter} => self.start_discovery(filter),
                     CoreBluetoothMessage::StopScanning => self.stop_discovery(),
-                    CoreBluetoothMessage::ConnectDevice{peripheral_uuid, future} => {
+                    CoreBluetoothMessage::RetrievePeripheral{peripheral_uuid, future} => {
+                        self.retrieve_peripheral(peripheral_uuid, future).await
+                    }
+                    CoreBluetoothMessage::RetrieveConnectedPeripherals{services, future} => {
+                        self.retrieve_connected_peripherals(services, future).await
+                    }
+                    CoreBluetoothMessage::ConnectDevice{peripheral_uuid, options, future} => {
                         trace!("got connectdevice msg!");
-                        self.connect_peripheral(peripheral_uuid, future);
+                        self.connect_peripheral(peripheral_uuid, options, future);
+                    }
+                    #[cfg(target_os = "ios")]
+                    CoreBluetoothMessage::RegisterForConnectionEvents{services} => {
+                        self.register_for_connection_events(services);
                     }
                     CoreBluetoothMessage::DisconnectDevice{peripheral_uuid, future} => {
                         self.disconnect_peripheral(peripheral_uuid, future);
                     }
+                    CoreBluetoothMessage::ForgetPeripheral{peripheral_uuid} => {
+                        self.peripherals.remove(&peripheral_uuid);
+                    }
                     CoreBluetoothMessage::ReadValue{peripheral_uuid, service_uuid,characteristic_uuid, future} => {
                         self.read_value(peripheral_uuid, service_uuid,characteristic_uuid, future)
                     }
@@ -1195,6 +1769,12 @@ impl CoreBluetoothInternal {
                     CoreBluetoothMessage::IsConnected{peripheral_uuid, future} => {
                         self.is_connected(peripheral_uuid, future);
                     },
+                    CoreBluetoothMessage::ReadRssi{peripheral_uuid, future} => {
+                        self.read_rssi(peripheral_uuid, future);
+                    },
+                    CoreBluetoothMessage::IsNotifying{peripheral_uuid, service_uuid, characteristic_uuid, future} => {
+                        self.is_notifying(peripheral_uuid, service_uuid, characteristic_uuid, future);
+                    },
                     CoreBluetoothMessage::ReadDescriptorValue{peripheral_uuid, service_uuid, characteristic_uuid, descriptor_uuid, future} => {
                         self.read_descriptor_value(peripheral_uuid, service_uuid, characteristic_uuid, descriptor_uuid, future)
                     }
@@ -1266,6 +1846,7 @@ impl Drop for CoreBluetoothInternal {
 
 pub fn run_corebluetooth_thread(
     event_sender: Sender<CoreBluetoothEvent>,
+    restore_identifier: Option<String>,
 ) -> Result<Sender<CoreBluetoothMessage>, Error> {
     let authorization = unsafe { CBManager::authorization_class() };
     if authorization != CBManagerAuthorization::AllowedAlways
@@ -1281,7 +1862,7 @@ pub fn run_corebluetooth_thread(
     thread::spawn(move || {
         let runtime = runtime::Builder::new_current_thread().build().unwrap();
         runtime.block_on(async move {
-            let mut cbi = CoreBluetoothInternal::new(receiver, event_sender);
+            let mut cbi = CoreBluetoothInternal::new(receiver, event_sender, restore_identifier);
             loop {
                 cbi.wait_for_message().await;
             }
@@ -6,16 +6,151 @@
 // for full license information.
 
 use super::adapter::Adapter;
-use crate::{api, Result};
+use crate::api::observer::{TraceHandler, Tracer};
+use crate::{api, api::ManagerEvent, Result};
 use async_trait::async_trait;
+use futures::stream::{self, Stream, StreamExt};
+use objc2_core_bluetooth::{CBManager, CBManagerAuthorization};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use tokio_stream::wrappers::WatchStream;
+
+/// Whether the app is allowed to use Bluetooth, from `CBManager.authorization`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Authorization {
+    /// The user hasn't been asked yet. They are asked when the first adapter is created.
+    NotDetermined,
+    /// The app can't use Bluetooth because of restrictions the user can't change, such as
+    /// parental controls.
+    Restricted,
+    /// The user denied the app the use of Bluetooth.
+    Denied,
+    /// The app is allowed to use Bluetooth.
+    Allowed,
+}
+
+impl Authorization {
+    pub(crate) fn current() -> Self {
+        match unsafe { CBManager::authorization_class() } {
+            CBManagerAuthorization::AllowedAlways => Authorization::Allowed,
+            CBManagerAuthorization::Denied => Authorization::Denied,
+            CBManagerAuthorization::Restricted => Authorization::Restricted,
+            _ => Authorization::NotDetermined,
+        }
+    }
+}
+
+/// Passes changes of authorization on to the subscribers of
+/// [`Manager::authorization_events`]. The system only tells us about them by updating the state of
+/// the central manager, so adapters check it again then.
+#[derive(Debug)]
+pub(crate) struct AuthorizationWatch(watch::Sender<Authorization>);
+
+impl AuthorizationWatch {
+    pub(crate) fn refresh(&self) {
+        let authorization = Authorization::current();
+        self.0.send_if_modified(|last| {
+            let changed = *last != authorization;
+            *last = authorization;
+            changed
+        });
+    }
+}
 
 /// Implementation of [api::Manager](crate::api::Manager).
 #[derive(Clone, Debug)]
-pub struct Manager {}
+pub struct Manager {
+    restore_identifier: Option<String>,
+    tracer: Tracer,
+    authorization: Arc<AuthorizationWatch>,
+    /// The adapter created by [`request_authorization`](Self::request_authorization), which is
+    /// handed out by the next call to [`adapters`](api::Manager::adapters).
+    prompted: Arc<Mutex<Option<Adapter>>>,
+}
 
 impl Manager {
     pub async fn new() -> Result<Self> {
-        Ok(Self {})
+        Ok(Self {
+            restore_identifier: None,
+            tracer: Tracer::default(),
+            authorization: Self::authorization_watch(),
+            prompted: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Creates a manager whose adapter opts in to state restoration with the given identifier.
+    ///
+    /// If the app is terminated while it has connections or pending connections, or is scanning,
+    /// the system keeps them going, and relaunches the app in the background when one of them
+    /// needs it. An adapter created with the same identifier after relaunching picks them up again,
+    /// and says what was restored in [`Adapter::restored_state`]. On iOS, this needs the
+    /// `bluetooth-central` background mode in the app's `Info.plist`.
+    ///
+    /// Each call to [`adapters`](api::Manager::adapters) creates a new adapter, and the system only
+    /// restores state to one of them, so only get the adapter once.
+    pub async fn with_restore_identifier(identifier: impl Into<String>) -> Result<Self> {
+        Ok(Self {
+            restore_identifier: Some(identifier.into()),
+            tracer: Tracer::default(),
+            authorization: Self::authorization_watch(),
+            prompted: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    fn authorization_watch() -> Arc<AuthorizationWatch> {
+        Arc::new(AuthorizationWatch(watch::Sender::new(
+            Authorization::current(),
+        )))
+    }
+
+    /// Returns whether the app is allowed to use Bluetooth. Creating an adapter fails with
+    /// [`Error::PermissionDenied`](crate::Error::PermissionDenied) if it is
+    /// [`Denied`](Authorization::Denied) or [`Restricted`](Authorization::Restricted), so check
+    /// this first to explain to the user why Bluetooth isn't available instead.
+    pub fn authorization(&self) -> Authorization {
+        Authorization::current()
+    }
+
+    /// Returns a stream of the changes to [`authorization`](Self::authorization), such as when the
+    /// user answers the prompt shown when the first adapter is created, or later changes the
+    /// app's permission in Settings. Changes are only noticed while this manager has an adapter.
+    pub fn authorization_events(&self) -> Pin<Box<dyn Stream<Item = Authorization> + Send>> {
+        self.authorization.refresh();
+        Box::pin(WatchStream::from_changes(self.authorization.0.subscribe()))
+    }
+
+    /// Asks the user to allow the app to use Bluetooth, if they haven't been asked yet, and waits
+    /// for their answer. Otherwise the prompt appears whenever the first adapter happens to be
+    /// created, and nothing is found until the user answers it.
+    ///
+    /// Asking means creating the central manager, so the adapter created for it is kept, and
+    /// returned by the next call to [`adapters`](api::Manager::adapters).
+    pub async fn request_authorization(&self) -> Result<Authorization> {
+        let authorization = self.authorization();
+        if authorization != Authorization::NotDetermined {
+            return Ok(authorization);
+        }
+        let mut changes = self.authorization_events();
+        let adapter = self.new_adapter().await?;
+        *self.prompted.lock().unwrap() = Some(adapter);
+        // The system may report the state before the user has answered.
+        while self.authorization() == Authorization::NotDetermined {
+            if changes.next().await.is_none() {
+                break;
+            }
+        }
+        Ok(self.authorization())
+    }
+
+    async fn new_adapter(&self) -> Result<Adapter> {
+        Adapter::new(
+            self.restore_identifier.clone(),
+            self.tracer.clone(),
+            self.authorization.clone(),
+        )
+        .await
     }
 }
 
@@ -24,8 +159,25 @@ impl api::Manager for Manager {
     type Adapter = Adapter;
 
     async fn adapters(&self) -> Result<Vec<Adapter>> {
-        Ok(vec![Adapter::new().await?])
+        let prompted = self.prompted.lock().unwrap().take();
+        Ok(vec![match prompted {
+            Some(adapter) => adapter,
+            None => self.new_adapter().await?,
+        }])
         // TODO What do we do if there is no bluetooth adapter, like on an older
         // macbook pro? Will BluetoothAdapter::init() fail?
     }
+
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = ManagerEvent<Adapter>> + Send>>> {
+        // There is only ever one adapter, and it can't be removed.
+        let adapters = api::Manager::adapters(self).await?;
+        Ok(Box::pin(
+            stream::iter(adapters.into_iter().map(ManagerEvent::AdapterAdded))
+                .chain(stream::pending()),
+        ))
+    }
+
+    fn set_trace_handler(&self, handler: Option<Arc<dyn TraceHandler>>) {
+        self.tracer.set(handler);
+    }
 }
@@ -10,11 +10,17 @@ use super::internal::{
 };
 use crate::{
     api::{
-        self, BDAddr, CentralEvent, CharPropFlags, Characteristic, Descriptor,
-        PeripheralProperties, Service, ValueNotification, WriteType,
+        self,
+        descriptor_cache::DescriptorCache,
+        link::{self, LinkSample},
+        operation::OperationContext,
+        rssi::RssiStats,
+        BDAddr, CentralEvent, CharPropFlags, Characteristic, ClientConfiguration, Descriptor,
+        DisconnectReason, PeripheralEvent, PeripheralProperties, Service, ValueNotification,
+        WriteType, CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
     },
-    common::{adapter_manager::AdapterManager, util::notifications_stream_from_broadcast_receiver},
-    Error, Result,
+    common::adapter_manager::AdapterManager,
+    rt, Error, Result,
 };
 use async_trait::async_trait;
 use futures::channel::mpsc::{Receiver, SendError, Sender};
@@ -32,9 +38,9 @@ use std::{
     fmt::{self, Debug, Display, Formatter},
     pin::Pin,
     sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
 };
 use tokio::sync::broadcast;
-use tokio::task;
 use uuid::Uuid;
 
 #[cfg_attr(
@@ -43,7 +49,7 @@ use uuid::Uuid;
     serde(crate = "serde_cr")
 )]
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
-pub struct PeripheralId(Uuid);
+pub struct PeripheralId(pub(crate) Uuid);
 
 impl Display for PeripheralId {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
@@ -51,6 +57,28 @@ impl Display for PeripheralId {
     }
 }
 
+#[cfg(feature = "mock")]
+impl PeripheralId {
+    /// The ID of a peripheral of the [mock](crate::mock) backend. CoreBluetooth identifies
+    /// peripherals by UUID, so this is made from the address.
+    pub(crate) fn synthetic(_adapter_id: &str, address: BDAddr) -> Self {
+        PeripheralId(Uuid::from_u64_pair(0, address.into()))
+    }
+}
+
+/// Alerts iOS shows for a connection while the app is suspended, for
+/// [`Peripheral::connect_when_discovered`]. Apps with the `bluetooth-central` background mode
+/// are woken up instead, so these are for apps which don't have it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ConnectOptions {
+    /// Alert the user when the peripheral connects.
+    pub notify_on_connection: bool,
+    /// Alert the user when the peripheral disconnects.
+    pub notify_on_disconnection: bool,
+    /// Alert the user when a notification is received from the peripheral.
+    pub notify_on_notification: bool,
+}
+
 /// Implementation of [api::Peripheral](crate::api::Peripheral).
 #[derive(Clone)]
 pub struct Peripheral {
@@ -62,8 +90,10 @@ struct Shared {
     manager: Weak<AdapterManager<Peripheral>>,
     uuid: Uuid,
     services: Mutex<BTreeSet<Service>>,
+    descriptor_cache: DescriptorCache,
     properties: Mutex<PeripheralProperties>,
     message_sender: Sender<CoreBluetoothMessage>,
+    operation_context: OperationContext,
     // We're not actually holding a peripheral object here, that's held out in
     // the objc thread. We'll just communicate with it through our
     // receiver/sender pair.
@@ -77,16 +107,26 @@ impl Shared {
             trace!("Could not emit an event. AdapterManager has been dropped");
         }
     }
+
+    fn send_peripheral_event(&self, event: PeripheralEvent) {
+        if let Some(manager) = self.manager.upgrade() {
+            manager
+                .peripheral_events()
+                .send(&PeripheralId(self.uuid), event);
+        }
+    }
 }
 
 impl Peripheral {
-    // This calls tokio::task::spawn, so it must be called from the context of a Tokio Runtime.
+    // This spawns a task, so with the runtime-tokio feature it must be called from the context of a
+    // Tokio Runtime.
     pub(crate) fn new(
         uuid: Uuid,
         local_name: Option<String>,
         manager: Weak<AdapterManager<Self>>,
         event_receiver: Receiver<PeripheralEventInternal>,
         message_sender: Sender<CoreBluetoothMessage>,
+        operation_context: OperationContext,
     ) -> Self {
         // Since we're building the object, we have an active advertisement.
         // Build properties now.
@@ -100,6 +140,13 @@ impl Peripheral {
             service_data: HashMap::new(),
             services: Vec::new(),
             class: None,
+            advertisement_type: None,
+            connectable: None,
+            last_seen: Some(SystemTime::now()),
+            // CoreBluetooth doesn't expose the appearance from advertisements.
+            appearance: None,
+            scan_response: Default::default(),
+            advertising_data: None,
         });
         let (notifications_channel, _) = broadcast::channel(16);
 
@@ -107,20 +154,20 @@ impl Peripheral {
             properties,
             manager,
             services: Mutex::new(BTreeSet::new()),
+            descriptor_cache: DescriptorCache::default(),
             notifications_channel,
             uuid,
             message_sender,
+            operation_context: operation_context.for_peripheral(PeripheralId(uuid)),
         });
         let shared_clone = shared.clone();
-        task::spawn(async move {
+        rt::spawn(async move {
             let mut event_receiver = event_receiver;
             let shared = shared_clone;
 
             loop {
                 match event_receiver.next().await {
-                    Some(PeripheralEventInternal::Notification(uuid, data)) => {
-                        let notification = ValueNotification { uuid, value: data };
-
+                    Some(PeripheralEventInternal::Notification(notification)) => {
                         // Note: we ignore send errors here which may happen while there are no
                         // receivers...
                         let _ = shared.notifications_channel.send(notification);
@@ -160,6 +207,29 @@ impl Peripheral {
                             services,
                         });
                     }
+                    Some(PeripheralEventInternal::Advertisement {
+                        connectable,
+                        tx_power_level,
+                        rssi,
+                    }) => {
+                        shared
+                            .operation_context
+                            .rssi()
+                            .record(&PeripheralId(shared.uuid), rssi);
+                        let mut properties = shared.properties.lock().unwrap();
+                        properties.last_seen = Some(SystemTime::now());
+                        properties.rssi = Some(rssi);
+                        if connectable.is_some() {
+                            properties.connectable = connectable;
+                        }
+                        if tx_power_level.is_some() {
+                            properties.tx_power_level = tx_power_level;
+                        }
+                    }
+                    Some(PeripheralEventInternal::ServicesChanged(services)) => {
+                        *shared.services.lock().unwrap() = services;
+                        shared.emit_event(CentralEvent::ServicesChanged(shared.uuid.into()));
+                    }
                     Some(PeripheralEventInternal::Disconnected) => (),
                     None => {
                         info!("Event receiver died, breaking out of corebluetooth device loop.");
@@ -174,6 +244,40 @@ impl Peripheral {
     pub(super) fn update_name(&self, name: &str) {
         self.shared.properties.lock().unwrap().local_name = Some(name.to_string());
     }
+
+    /// Connects to the peripheral whenever it is next discovered, with no timeout.
+    ///
+    /// CoreBluetooth keeps a connection request pending until the device is in range, including
+    /// while the app is in the background or suspended, and iOS wakes the app up to handle the
+    /// connection. This is how backgrounded apps reconnect to their devices, rather than by
+    /// scanning, which iOS throttles for them. Disconnecting cancels the request.
+    pub async fn connect_when_discovered(&self, options: ConnectOptions) -> Result<()> {
+        self.connect_with_options(options).await
+    }
+
+    async fn connect_with_options(&self, options: ConnectOptions) -> Result<()> {
+        let fut = CoreBluetoothReplyFuture::default();
+        self.shared
+            .message_sender
+            .to_owned()
+            .send(CoreBluetoothMessage::ConnectDevice {
+                peripheral_uuid: self.shared.uuid,
+                options,
+                future: fut.get_state_clone(),
+            })
+            .await?;
+        match fut.await {
+            CoreBluetoothReply::Connected(services) => {
+                *(self.shared.services.lock().map_err(Into::<Error>::into)?) = services;
+                self.shared
+                    .emit_event(CentralEvent::DeviceConnected(self.shared.uuid.into()));
+            }
+            CoreBluetoothReply::Err(msg) => return Err(Error::RuntimeError(msg)),
+            _ => panic!("Shouldn't get anything but connected or err!"),
+        }
+        trace!("Device connected!");
+        Ok(())
+    }
 }
 
 impl Display for Peripheral {
@@ -217,8 +321,44 @@ impl api::Peripheral for Peripheral {
         ))
     }
 
+    async fn refresh_properties(&self) -> Result<Option<PeripheralProperties>> {
+        if self.is_connected().await? {
+            let rssi = self
+                .shared
+                .operation_context
+                .run("read RSSI", async {
+                    let fut = CoreBluetoothReplyFuture::default();
+                    self.shared
+                        .message_sender
+                        .to_owned()
+                        .send(CoreBluetoothMessage::ReadRssi {
+                            peripheral_uuid: self.shared.uuid,
+                            future: fut.get_state_clone(),
+                        })
+                        .await?;
+                    match fut.await {
+                        CoreBluetoothReply::Rssi(rssi) => Ok(rssi),
+                        CoreBluetoothReply::Err(msg) => Err(Error::RuntimeError(msg)),
+                        CoreBluetoothReply::AttError(error) => Err(Error::Att(error)),
+                        CoreBluetoothReply::NativeError(error) => {
+                            Err(Error::Other(Box::new(error)))
+                        }
+                        reply => panic!("Unexpected reply: {:?}", reply),
+                    }
+                })
+                .await?;
+            self.shared.properties.lock().unwrap().rssi = Some(rssi);
+        }
+        self.properties().await
+    }
+
+    fn rssi_stats(&self) -> Option<RssiStats> {
+        self.shared.operation_context.rssi().stats(&self.id())
+    }
+
     fn services(&self) -> BTreeSet<Service> {
-        self.shared.services.lock().unwrap().clone()
+        let services = self.shared.services.lock().unwrap().clone();
+        self.shared.descriptor_cache.fill(services)
     }
 
     async fn is_connected(&self) -> Result<bool> {
@@ -241,26 +381,13 @@ impl api::Peripheral for Peripheral {
     }
 
     async fn connect(&self) -> Result<()> {
-        let fut = CoreBluetoothReplyFuture::default();
         self.shared
-            .message_sender
-            .to_owned()
-            .send(CoreBluetoothMessage::ConnectDevice {
-                peripheral_uuid: self.shared.uuid,
-                future: fut.get_state_clone(),
-            })
-            .await?;
-        match fut.await {
-            CoreBluetoothReply::Connected(services) => {
-                *(self.shared.services.lock().map_err(Into::<Error>::into)?) = services;
-                self.shared
-                    .emit_event(CentralEvent::DeviceConnected(self.shared.uuid.into()));
-            }
-            CoreBluetoothReply::Err(msg) => return Err(Error::RuntimeError(msg)),
-            _ => panic!("Shouldn't get anything but connected or err!"),
-        }
-        trace!("Device connected!");
-        Ok(())
+            .operation_context
+            .run(
+                "connect",
+                self.connect_with_options(ConnectOptions::default()),
+            )
+            .await
     }
 
     async fn disconnect(&self) -> Result<()> {
@@ -275,6 +402,11 @@ impl api::Peripheral for Peripheral {
             .await?;
         match fut.await {
             CoreBluetoothReply::Ok => {
+                if let Some(manager) = self.shared.manager.upgrade() {
+                    manager
+                        .peripheral_events()
+                        .set_disconnect_reason(&self.id(), DisconnectReason::Local);
+                }
                 self.shared
                     .emit_event(CentralEvent::DeviceDisconnected(self.shared.uuid.into()));
                 trace!("Device disconnected!");
@@ -284,9 +416,32 @@ impl api::Peripheral for Peripheral {
         Ok(())
     }
 
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = PeripheralEvent> + Send>>> {
+        let manager = self.shared.manager.upgrade().ok_or(Error::DeviceNotFound)?;
+        Ok(manager.peripheral_events().subscribe(self.id()))
+    }
+
+    async fn link_events(
+        &self,
+        interval: Duration,
+    ) -> Result<Pin<Box<dyn Stream<Item = LinkSample> + Send>>> {
+        Ok(link::samples(
+            self.clone(),
+            self.shared.operation_context.clone(),
+            interval,
+        ))
+    }
+
     async fn discover_services(&self) -> Result<()> {
-        // TODO: Actually discover on this, rather than on connection
-        Ok(())
+        self.shared
+            .operation_context
+            .run("discover services", async {
+                // TODO: Actually discover on this, rather than on connection
+                self.shared
+                    .send_peripheral_event(PeripheralEvent::ServicesResolved);
+                Ok(())
+            })
+            .await
     }
 
     async fn write(
@@ -295,84 +450,169 @@ impl api::Peripheral for Peripheral {
         data: &[u8],
         mut write_type: WriteType,
     ) -> Result<()> {
-        let fut = CoreBluetoothReplyFuture::default();
-        // If we get WriteWithoutResponse for a characteristic that only
-        // supports WriteWithResponse, slam the type to WriteWithResponse.
-        // Otherwise we won't handle the future correctly.
-        if write_type == WriteType::WithoutResponse
-            && !characteristic
-                .properties
-                .contains(CharPropFlags::WRITE_WITHOUT_RESPONSE)
-        {
-            write_type = WriteType::WithResponse
-        }
         self.shared
-            .message_sender
-            .to_owned()
-            .send(CoreBluetoothMessage::WriteValue {
-                peripheral_uuid: self.shared.uuid,
-                service_uuid: characteristic.service_uuid,
-                characteristic_uuid: characteristic.uuid,
-                data: Vec::from(data),
-                write_type,
-                future: fut.get_state_clone(),
+            .operation_context
+            .run_write("write", characteristic.uuid, data, async {
+                // Core Bluetooth doesn't expose signed writes.
+                if write_type == WriteType::SignedWithoutResponse {
+                    return Err(Error::NotSupported(
+                        "Signed writes aren't supported on this platform".into(),
+                    ));
+                }
+                let fut = CoreBluetoothReplyFuture::default();
+                // If we get WriteWithoutResponse for a characteristic that only
+                // supports WriteWithResponse, slam the type to WriteWithResponse.
+                // Otherwise we won't handle the future correctly.
+                if write_type == WriteType::WithoutResponse
+                    && !characteristic
+                        .properties
+                        .contains(CharPropFlags::WRITE_WITHOUT_RESPONSE)
+                {
+                    write_type = WriteType::WithResponse
+                }
+                self.shared
+                    .message_sender
+                    .to_owned()
+                    .send(CoreBluetoothMessage::WriteValue {
+                        peripheral_uuid: self.shared.uuid,
+                        service_uuid: characteristic.service_uuid,
+                        characteristic_uuid: characteristic.uuid,
+                        data: Vec::from(data),
+                        write_type,
+                        future: fut.get_state_clone(),
+                    })
+                    .await?;
+                match fut.await {
+                    CoreBluetoothReply::Ok => {}
+                    CoreBluetoothReply::Err(msg) => return Err(Error::RuntimeError(msg)),
+                    CoreBluetoothReply::AttError(error) => return Err(Error::Att(error)),
+                    CoreBluetoothReply::NativeError(error) => {
+                        return Err(Error::Other(Box::new(error)))
+                    }
+                    reply => panic!("Unexpected reply: {:?}", reply),
+                }
+                Ok(())
             })
-            .await?;
-        match fut.await {
-            CoreBluetoothReply::Ok => {}
-            CoreBluetoothReply::Err(msg) => return Err(Error::RuntimeError(msg)),
-            reply => panic!("Unexpected reply: {:?}", reply),
-        }
-        Ok(())
+            .await
     }
 
     async fn read(&self, characteristic: &Characteristic) -> Result<Vec<u8>> {
-        let fut = CoreBluetoothReplyFuture::default();
         self.shared
-            .message_sender
-            .to_owned()
-            .send(CoreBluetoothMessage::ReadValue {
-                peripheral_uuid: self.shared.uuid,
-                service_uuid: characteristic.service_uuid,
-                characteristic_uuid: characteristic.uuid,
-                future: fut.get_state_clone(),
+            .operation_context
+            .run_read("read", characteristic.uuid, async {
+                let fut = CoreBluetoothReplyFuture::default();
+                self.shared
+                    .message_sender
+                    .to_owned()
+                    .send(CoreBluetoothMessage::ReadValue {
+                        peripheral_uuid: self.shared.uuid,
+                        service_uuid: characteristic.service_uuid,
+                        characteristic_uuid: characteristic.uuid,
+                        future: fut.get_state_clone(),
+                    })
+                    .await?;
+                match fut.await {
+                    CoreBluetoothReply::ReadResult(chars) => Ok(chars),
+                    CoreBluetoothReply::Err(msg) => Err(Error::RuntimeError(msg)),
+                    CoreBluetoothReply::AttError(error) => Err(Error::Att(error)),
+                    CoreBluetoothReply::NativeError(error) => Err(Error::Other(Box::new(error))),
+                    _ => {
+                        panic!("Shouldn't get anything but read result!");
+                    }
+                }
             })
-            .await?;
-        match fut.await {
-            CoreBluetoothReply::ReadResult(chars) => Ok(chars),
-            CoreBluetoothReply::Err(msg) => return Err(Error::RuntimeError(msg)),
-            _ => {
-                panic!("Shouldn't get anything but read result!");
-            }
-        }
+            .await
     }
 
     async fn subscribe(&self, characteristic: &Characteristic) -> Result<()> {
-        let fut = CoreBluetoothReplyFuture::default();
         self.shared
-            .message_sender
-            .to_owned()
-            .send(CoreBluetoothMessage::Subscribe {
-                peripheral_uuid: self.shared.uuid,
-                service_uuid: characteristic.service_uuid,
-                characteristic_uuid: characteristic.uuid,
-                future: fut.get_state_clone(),
+            .operation_context
+            .run_on("subscribe", characteristic.uuid, async {
+                let fut = CoreBluetoothReplyFuture::default();
+                self.shared
+                    .message_sender
+                    .to_owned()
+                    .send(CoreBluetoothMessage::Subscribe {
+                        peripheral_uuid: self.shared.uuid,
+                        service_uuid: characteristic.service_uuid,
+                        characteristic_uuid: characteristic.uuid,
+                        future: fut.get_state_clone(),
+                    })
+                    .await?;
+                match fut.await {
+                    CoreBluetoothReply::Ok => trace!("subscribed!"),
+                    CoreBluetoothReply::Err(msg) => return Err(Error::RuntimeError(msg)),
+                    CoreBluetoothReply::AttError(error) => return Err(Error::Att(error)),
+                    CoreBluetoothReply::NativeError(error) => {
+                        return Err(Error::Other(Box::new(error)))
+                    }
+                    _ => panic!("Didn't subscribe!"),
+                }
+                Ok(())
             })
-            .await?;
-        match fut.await {
-            CoreBluetoothReply::Ok => trace!("subscribed!"),
-            CoreBluetoothReply::Err(msg) => return Err(Error::RuntimeError(msg)),
-            _ => panic!("Didn't subscribe!"),
-        }
-        Ok(())
+            .await
     }
 
     async fn unsubscribe(&self, characteristic: &Characteristic) -> Result<()> {
+        self.shared
+            .operation_context
+            .run_on("unsubscribe", characteristic.uuid, async {
+                let fut = CoreBluetoothReplyFuture::default();
+                self.shared
+                    .message_sender
+                    .to_owned()
+                    .send(CoreBluetoothMessage::Unsubscribe {
+                        peripheral_uuid: self.shared.uuid,
+                        service_uuid: characteristic.service_uuid,
+                        characteristic_uuid: characteristic.uuid,
+                        future: fut.get_state_clone(),
+                    })
+                    .await?;
+                match fut.await {
+                    CoreBluetoothReply::Ok => {}
+                    CoreBluetoothReply::Err(msg) => return Err(Error::RuntimeError(msg)),
+                    CoreBluetoothReply::AttError(error) => return Err(Error::Att(error)),
+                    CoreBluetoothReply::NativeError(error) => {
+                        return Err(Error::Other(Box::new(error)))
+                    }
+                    _ => panic!("Didn't unsubscribe!"),
+                }
+                Ok(())
+            })
+            .await
+    }
+
+    async fn read_client_configuration(
+        &self,
+        characteristic: &Characteristic,
+    ) -> Result<ClientConfiguration> {
+        self.shared
+            .operation_context
+            .run_on(
+                "read descriptor",
+                CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
+                async {
+                    // CoreBluetooth doesn't allow reading the CCCD, but does tell us whether it has enabled
+                    // notifications. It uses indications only if the characteristic doesn't support
+                    // notifications.
+                    if !self.is_subscribed(characteristic).await? {
+                        Ok(ClientConfiguration::empty())
+                    } else if characteristic.properties.contains(CharPropFlags::NOTIFY) {
+                        Ok(ClientConfiguration::NOTIFY)
+                    } else {
+                        Ok(ClientConfiguration::INDICATE)
+                    }
+                },
+            )
+            .await
+    }
+
+    async fn is_subscribed(&self, characteristic: &Characteristic) -> Result<bool> {
         let fut = CoreBluetoothReplyFuture::default();
         self.shared
             .message_sender
             .to_owned()
-            .send(CoreBluetoothMessage::Unsubscribe {
+            .send(CoreBluetoothMessage::IsNotifying {
                 peripheral_uuid: self.shared.uuid,
                 service_uuid: characteristic.service_uuid,
                 characteristic_uuid: characteristic.uuid,
@@ -380,58 +620,80 @@ impl api::Peripheral for Peripheral {
             })
             .await?;
         match fut.await {
-            CoreBluetoothReply::Ok => {}
-            CoreBluetoothReply::Err(msg) => return Err(Error::RuntimeError(msg)),
-            _ => panic!("Didn't unsubscribe!"),
+            CoreBluetoothReply::Notifying(notifying) => Ok(notifying),
+            CoreBluetoothReply::Err(msg) => Err(Error::RuntimeError(msg)),
+            _ => panic!("Shouldn't get anything but Notifying!"),
         }
-        Ok(())
     }
 
     async fn notifications(&self) -> Result<Pin<Box<dyn Stream<Item = ValueNotification> + Send>>> {
         let receiver = self.shared.notifications_channel.subscribe();
-        Ok(notifications_stream_from_broadcast_receiver(receiver))
+        Ok(self.shared.operation_context.notifications_stream(receiver))
     }
 
     async fn write_descriptor(&self, descriptor: &Descriptor, data: &[u8]) -> Result<()> {
-        let fut = CoreBluetoothReplyFuture::default();
         self.shared
-            .message_sender
-            .to_owned()
-            .send(CoreBluetoothMessage::WriteDescriptorValue {
-                peripheral_uuid: self.shared.uuid,
-                service_uuid: descriptor.service_uuid,
-                characteristic_uuid: descriptor.characteristic_uuid,
-                descriptor_uuid: descriptor.uuid,
-                data: Vec::from(data),
-                future: fut.get_state_clone(),
+            .operation_context
+            .run_write("write descriptor", descriptor.uuid, data, async {
+                let fut = CoreBluetoothReplyFuture::default();
+                self.shared
+                    .message_sender
+                    .to_owned()
+                    .send(CoreBluetoothMessage::WriteDescriptorValue {
+                        peripheral_uuid: self.shared.uuid,
+                        service_uuid: descriptor.service_uuid,
+                        characteristic_uuid: descriptor.characteristic_uuid,
+                        descriptor_uuid: descriptor.uuid,
+                        data: Vec::from(data),
+                        future: fut.get_state_clone(),
+                    })
+                    .await?;
+                match fut.await {
+                    CoreBluetoothReply::Ok => {}
+                    CoreBluetoothReply::Err(msg) => return Err(Error::RuntimeError(msg)),
+                    CoreBluetoothReply::AttError(error) => return Err(Error::Att(error)),
+                    CoreBluetoothReply::NativeError(error) => {
+                        return Err(Error::Other(Box::new(error)))
+                    }
+                    reply => panic!("Unexpected reply: {:?}", reply),
+                }
+                self.shared.descriptor_cache.insert(descriptor, data);
+                Ok(())
             })
-            .await?;
-        match fut.await {
-            CoreBluetoothReply::Ok => {}
-            reply => panic!("Unexpected reply: {:?}", reply),
-        }
-        Ok(())
+            .await
     }
 
     async fn read_descriptor(&self, descriptor: &Descriptor) -> Result<Vec<u8>> {
-        let fut = CoreBluetoothReplyFuture::default();
         self.shared
-            .message_sender
-            .to_owned()
-            .send(CoreBluetoothMessage::ReadDescriptorValue {
-                peripheral_uuid: self.shared.uuid,
-                service_uuid: descriptor.service_uuid,
-                characteristic_uuid: descriptor.characteristic_uuid,
-                descriptor_uuid: descriptor.uuid,
-                future: fut.get_state_clone(),
+            .operation_context
+            .run_read("read descriptor", descriptor.uuid, async {
+                let fut = CoreBluetoothReplyFuture::default();
+                self.shared
+                    .message_sender
+                    .to_owned()
+                    .send(CoreBluetoothMessage::ReadDescriptorValue {
+                        peripheral_uuid: self.shared.uuid,
+                        service_uuid: descriptor.service_uuid,
+                        characteristic_uuid: descriptor.characteristic_uuid,
+                        descriptor_uuid: descriptor.uuid,
+                        future: fut.get_state_clone(),
+                    })
+                    .await?;
+                let value = match fut.await {
+                    CoreBluetoothReply::ReadResult(chars) => chars,
+                    CoreBluetoothReply::Err(msg) => return Err(Error::RuntimeError(msg)),
+                    CoreBluetoothReply::AttError(error) => return Err(Error::Att(error)),
+                    CoreBluetoothReply::NativeError(error) => {
+                        return Err(Error::Other(Box::new(error)))
+                    }
+                    _ => {
+                        panic!("Shouldn't get anything but read result!");
+                    }
+                };
+                self.shared.descriptor_cache.insert(descriptor, &value);
+                Ok(value)
             })
-            .await?;
-        match fut.await {
-            CoreBluetoothReply::ReadResult(chars) => Ok(chars),
-            _ => {
-                panic!("Shouldn't get anything but read result!");
-            }
-        }
+            .await
     }
 }
 
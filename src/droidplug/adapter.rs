@@ -6,25 +6,76 @@ use super::{
     peripheral::{Peripheral, PeripheralId},
 };
 use crate::{
-    api::{BDAddr, Central, CentralEvent, CentralState, PeripheralProperties, ScanFilter},
+    api::{
+        connect_when_advertising, event_filter,
+        observer::{Observer, TraceHandler},
+        rssi::RssiTracking,
+        spans, AdapterCapabilities, AdapterInfo, BDAddr, Central, CentralEvent, CentralState,
+        ConnectTarget, DiscoveryEvent, EventFilter, PeripheralProperties, ScanFilter, ScanMode,
+        ScanOptions, Transport,
+    },
     common::adapter_manager::AdapterManager,
     Error, Result,
 };
 use async_trait::async_trait;
 use futures::stream::Stream;
 use jni::{
-    objects::{GlobalRef, JObject, JString},
+    objects::{GlobalRef, JClass, JObject, JString, JValue},
     strings::JavaStr,
-    sys::jboolean,
+    sys::{jboolean, jint},
     JNIEnv,
 };
+use jni_utils::exceptions::try_block;
 use std::{
     fmt::{Debug, Formatter},
     pin::Pin,
     str::FromStr,
     sync::Arc,
+    time::Duration,
 };
 
+// Values of BluetoothAdapter.STATE_*. The Java side returns -1 when there is no adapter.
+const STATE_OFF: jint = 10;
+const STATE_ON: jint = 12;
+const STATE_NO_ADAPTER: jint = -1;
+
+fn get_central_state(state: jint) -> CentralState {
+    match state {
+        STATE_ON => CentralState::PoweredOn,
+        STATE_OFF => CentralState::PoweredOff,
+        STATE_NO_ADAPTER => CentralState::Unsupported,
+        // STATE_TURNING_ON and STATE_TURNING_OFF.
+        _ => CentralState::Unknown,
+    }
+}
+
+/// A runtime permission which apps need to be granted to use Bluetooth, depending on the version of
+/// Android.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Permission {
+    /// `BLUETOOTH_SCAN`, which scanning needs from Android 12.
+    Scan,
+    /// `BLUETOOTH_CONNECT`, which connecting and most other operations need from Android 12.
+    Connect,
+    /// `ACCESS_FINE_LOCATION`, or `ACCESS_COARSE_LOCATION` before Android 10, which scanning needs
+    /// before Android 12.
+    Location,
+}
+
+impl Permission {
+    const ALL: [Permission; 3] = [Permission::Scan, Permission::Connect, Permission::Location];
+
+    // The values the Java side uses.
+    fn to_jint(self) -> jint {
+        match self {
+            Permission::Scan => 0,
+            Permission::Connect => 1,
+            Permission::Location => 2,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Adapter {
     manager: Arc<AdapterManager<Peripheral>>,
@@ -58,6 +109,11 @@ impl Adapter {
         Ok(adapter)
     }
 
+    /// There is only one adapter, so the manager's trace handler is set on it directly.
+    pub(crate) fn set_trace_handler(&self, handler: Option<Arc<dyn TraceHandler>>) {
+        self.manager.operation_context().tracer().set(handler);
+    }
+
     pub fn report_scan_result(&self, scan_result: JObject) -> Result<Peripheral> {
         use std::convert::TryInto;
 
@@ -66,31 +122,161 @@ impl Adapter {
 
         let (addr, properties): (BDAddr, Option<PeripheralProperties>) = scan_result.try_into()?;
 
-        match self.manager.peripheral(&PeripheralId(addr)) {
-            Some(p) => match properties {
-                Some(properties) => {
-                    self.report_properties(&p, properties, false);
-                    Ok(p)
-                }
-                None => {
-                    //self.manager.emit(CentralEvent::DeviceDisconnected(addr));
-                    Err(Error::DeviceNotFound)
-                }
-            },
-            None => match properties {
-                Some(properties) => {
-                    let p = self.add(addr)?;
-                    self.report_properties(&p, properties, true);
-                    Ok(p)
-                }
-                None => Err(Error::DeviceNotFound),
-            },
+        let id = PeripheralId(addr);
+        spans::callback("advertisement", Some(&id)).in_scope(|| {
+            match self.manager.peripheral(&id) {
+                Some(p) => match properties {
+                    Some(properties) => {
+                        self.report_properties(&p, properties, false);
+                        Ok(p)
+                    }
+                    None => {
+                        //self.manager.emit(CentralEvent::DeviceDisconnected(addr));
+                        Err(Error::DeviceNotFound)
+                    }
+                },
+                None => match properties {
+                    Some(properties) => {
+                        let p = self.add(addr)?;
+                        self.report_properties(&p, properties, true);
+                        Ok(p)
+                    }
+                    None => Err(Error::DeviceNotFound),
+                },
+            }
+        })
+    }
+
+    fn call_string_method(&self, env: &JNIEnv, name: &str) -> Result<Option<String>> {
+        let value = env
+            .call_method(&self.internal, name, "()Ljava/lang/String;", &[])?
+            .l()?;
+        if value.is_null() {
+            return Ok(None);
+        }
+        let value = JavaStr::from_env(env, value.into())?;
+        Ok(Some(
+            value
+                .to_str()
+                .map_err(|e| Error::Other(e.into()))?
+                .to_owned(),
+        ))
+    }
+
+    fn call_boolean_method(&self, env: &JNIEnv, name: &str) -> Result<Option<bool>> {
+        self.call_boolean_method_with(env, name, "()Ljava/lang/Boolean;", &[])
+    }
+
+    fn call_boolean_method_with(
+        &self,
+        env: &JNIEnv,
+        name: &str,
+        sig: &str,
+        args: &[JValue],
+    ) -> Result<Option<bool>> {
+        let value = env.call_method(&self.internal, name, sig, args)?.l()?;
+        if value.is_null() {
+            return Ok(None);
+        }
+        Ok(Some(
+            env.call_method(value, "booleanValue", "()Z", &[])?.z()?,
+        ))
+    }
+
+    /// Returns whether the app has been granted the permission, which is always the case for
+    /// permissions this version of Android doesn't use.
+    pub fn has_permission(&self, permission: Permission) -> Result<bool> {
+        let env = global_jvm().get_env()?;
+        self.call_boolean_method_with(
+            &env,
+            "hasPermission",
+            "(I)Ljava/lang/Boolean;",
+            &[permission.to_jint().into()],
+        )?
+        .ok_or_else(|| Error::RuntimeError("Couldn't find the application context".to_string()))
+    }
+
+    /// Returns the permissions this version of Android needs for Bluetooth which the app hasn't
+    /// been granted.
+    pub fn missing_permissions(&self) -> Result<Vec<Permission>> {
+        let mut missing = Vec::new();
+        for permission in Permission::ALL {
+            if !self.has_permission(permission)? {
+                missing.push(permission);
+            }
         }
+        Ok(missing)
+    }
+
+    /// Asks the user to grant the given permissions, leaving out those this version of Android
+    /// doesn't use. `activity` is the `android.app.Activity` to ask from, which is told the outcome
+    /// in `onRequestPermissionsResult`. Use [`missing_permissions`](Self::missing_permissions)
+    /// afterwards to see what was granted.
+    pub fn request_permissions(&self, activity: JObject, permissions: &[Permission]) -> Result<()> {
+        let env = global_jvm().get_env()?;
+        let codes: Vec<jint> = permissions.iter().map(|p| p.to_jint()).collect();
+        let array = env.new_int_array(codes.len() as i32)?;
+        env.set_int_array_region(array, 0, &codes)?;
+        env.call_method(
+            &self.internal,
+            "requestPermissions",
+            "(Landroid/app/Activity;[I)V",
+            &[activity.into(), JObject::from(array).into()],
+        )?;
+        Ok(())
+    }
+
+    /// Returns whether the device supports Bluetooth LE, from `FEATURE_BLUETOOTH_LE`.
+    pub fn is_le_supported(&self) -> Result<bool> {
+        let env = global_jvm().get_env()?;
+        self.call_boolean_method(&env, "isLeSupported")?
+            .ok_or_else(|| Error::RuntimeError("Couldn't find the application context".to_string()))
+    }
+
+    async fn scan(&self, filter: ScanFilter, mode: ScanMode) -> Result<()> {
+        // Before Android 12, scanning without the location permission silently finds nothing.
+        if matches!(self.has_permission(Permission::Location), Ok(false)) {
+            return Err(Error::PermissionDenied);
+        }
+        // Values of ScanSettings.SCAN_MODE_*.
+        let mode: jint = match mode {
+            ScanMode::LowPower => 0,
+            ScanMode::Balanced | ScanMode::Chooser => 1,
+            ScanMode::LowLatency => 2,
+        };
+        let env = global_jvm().get_env()?;
+        let filter = JScanFilter::new(&env, filter)?;
+        try_block(&env, || {
+            env.call_method(
+                &self.internal,
+                "startScan",
+                "(Lcom/nonpolynomial/btleplug/android/impl/ScanFilter;I)V",
+                &[filter.into(), mode.into()],
+            )?;
+            Ok(Ok(()))
+        })
+        .catch(
+            JClass::from(
+                jni_utils::classcache::get_class(
+                    "com/nonpolynomial/btleplug/android/impl/PermissionDeniedException",
+                )
+                .unwrap()
+                .as_obj(),
+            ),
+            |_| Ok(Err(Error::PermissionDenied)),
+        )
+        .result()?
     }
 
     fn add(&self, address: BDAddr) -> Result<Peripheral> {
         let env = global_jvm().get_env()?;
-        let peripheral = Peripheral::new(&env, self.internal.as_obj(), address)?;
+        let peripheral = Peripheral::new(
+            &env,
+            self.internal.as_obj(),
+            address,
+            Arc::downgrade(&self.manager),
+            self.manager.operation_context().clone(),
+        )?;
         self.manager.add_peripheral(peripheral.clone());
         Ok(peripheral)
     }
@@ -102,6 +288,17 @@ impl Adapter {
         new: bool,
     ) {
         peripheral.report_properties(properties.clone());
+        if let Some(rssi) = properties.rssi {
+            self.manager
+                .operation_context()
+                .rssi()
+                .record(&PeripheralId(properties.address), rssi);
+        }
+        self.manager.peripheral_events().send_discovery(
+            &PeripheralId(properties.address),
+            properties.clone(),
+            new,
+        );
         self.manager.emit(if new {
             CentralEvent::DeviceDiscovered(PeripheralId(properties.address))
         } else {
@@ -127,25 +324,63 @@ impl Adapter {
 impl Central for Adapter {
     type Peripheral = Peripheral;
 
-    async fn adapter_info(&self) -> Result<String> {
-        // TODO: Get information about the adapter.
-        Ok("Android".to_string())
+    async fn adapter_info(&self) -> Result<AdapterInfo> {
+        let env = global_jvm().get_env()?;
+        // Since Android 6 apps get this placeholder instead of the real address.
+        let address = self
+            .call_string_method(&env, "getAddress")?
+            .and_then(|address| BDAddr::from_str(&address).ok())
+            .filter(|address| *address != BDAddr::from([0x02, 0, 0, 0, 0, 0]));
+        Ok(AdapterInfo {
+            // Android only ever has the default adapter.
+            id: "default".to_string(),
+            description: "Android".to_string(),
+            address,
+            name: self.call_string_method(&env, "getName")?,
+            alias: None,
+            capabilities: AdapterCapabilities {
+                extended_advertising: self
+                    .call_boolean_method(&env, "isLeExtendedAdvertisingSupported")?,
+                le_2m_phy: self.call_boolean_method(&env, "isLe2MPhySupported")?,
+                le_coded_phy: self.call_boolean_method(&env, "isLeCodedPhySupported")?,
+                peripheral_role: self
+                    .call_boolean_method(&env, "isMultipleAdvertisementSupported")?,
+                // Android doesn't say whether its stack uses EATT.
+                eatt: None,
+                advertisement_monitor: Some(false),
+            },
+            stack_version: None,
+            experimental_features: Vec::new(),
+        })
     }
 
     async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = CentralEvent> + Send>>> {
         Ok(self.manager.event_stream())
     }
 
+    async fn events_filtered(
+        &self,
+        filter: EventFilter,
+    ) -> Result<Pin<Box<dyn Stream<Item = CentralEvent> + Send>>> {
+        let events = self.manager.filtered_event_stream(&filter);
+        Ok(event_filter::by_services(self, events, filter.services))
+    }
+
+    async fn discovery_events(&self) -> Result<Pin<Box<dyn Stream<Item = DiscoveryEvent> + Send>>> {
+        Ok(self.manager.peripheral_events().subscribe_discoveries())
+    }
+
     async fn start_scan(&self, filter: ScanFilter) -> Result<()> {
-        let env = global_jvm().get_env()?;
-        let filter = JScanFilter::new(&env, filter)?;
-        env.call_method(
-            &self.internal,
-            "startScan",
-            "(Lcom/nonpolynomial/btleplug/android/impl/ScanFilter;)V",
-            &[filter.into()],
-        )?;
-        Ok(())
+        self.scan(filter, ScanMode::LowPower).await
+    }
+
+    async fn start_scan_with_options(
+        &self,
+        filter: ScanFilter,
+        options: ScanOptions,
+    ) -> Result<()> {
+        self.scan(filter, options.mode().unwrap_or(ScanMode::LowPower))
+            .await
     }
 
     async fn stop_scan(&self) -> Result<()> {
@@ -164,12 +399,79 @@ impl Central for Adapter {
             .ok_or(Error::DeviceNotFound)
     }
 
+    async fn peripheral_by_address(&self, address: BDAddr) -> Result<Peripheral> {
+        self.manager
+            .peripheral(&PeripheralId(address))
+            .ok_or(Error::DeviceNotFound)
+    }
+
     async fn add_peripheral(&self, address: &PeripheralId) -> Result<Peripheral> {
         self.add(address.0)
     }
 
+    async fn remove_peripheral(&self, id: &PeripheralId) -> Result<()> {
+        self.manager.disconnect_and_remove(id).await
+    }
+
+    async fn connect_when_available(&self, target: ConnectTarget) -> Result<Peripheral> {
+        match target {
+            ConnectTarget::Peripheral(id) => {
+                // Android can connect to a device it hasn't seen, by its address.
+                let peripheral = match self.manager.peripheral(&id) {
+                    Some(peripheral) => peripheral,
+                    None => self.add(id.0)?,
+                };
+                peripheral.connect_gatt(true, Transport::Auto).await?;
+                Ok(peripheral)
+            }
+            target => connect_when_advertising(self, target).await,
+        }
+    }
+
     async fn adapter_state(&self) -> Result<CentralState> {
-        Ok(CentralState::Unknown)
+        let env = global_jvm().get_env()?;
+        let state = env
+            .call_method(&self.internal, "getState", "()I", &[])?
+            .i()?;
+        Ok(get_central_state(state))
+    }
+
+    async fn set_powered(&self, powered: bool) -> Result<()> {
+        let env = global_jvm().get_env()?;
+        let changed = env
+            .call_method(&self.internal, "setPowered", "(Z)Z", &[powered.into()])?
+            .z()?;
+        if changed {
+            Ok(())
+        } else {
+            // Android 13 and later don't let apps turn the adapter on or off at all.
+            Err(Error::NotSupported(
+                "Android did not allow turning the adapter on or off".to_string(),
+            ))
+        }
+    }
+
+    fn operation_timeout(&self) -> Option<Duration> {
+        self.manager.operation_context().timeout()
+    }
+
+    fn set_operation_timeout(&self, timeout: Option<Duration>) {
+        self.manager.operation_context().set_timeout(timeout);
+    }
+
+    fn set_observer(&self, observer: Option<Arc<dyn Observer>>) {
+        self.manager.operation_context().set_observer(observer);
+    }
+
+    fn set_peripheral_ttl(&self, ttl: Option<Duration>) {
+        self.manager.operation_context().expiry().set_ttl(ttl);
+    }
+
+    fn set_rssi_tracking(&self, tracking: Option<RssiTracking>) {
+        self.manager
+            .operation_context()
+            .rssi()
+            .set_tracking(tracking);
     }
 }
 
@@ -200,3 +502,49 @@ pub(crate) fn adapter_on_connection_state_changed_internal(
     });
     Ok(())
 }
+
+pub(crate) fn adapter_on_services_changed_internal(
+    env: &JNIEnv,
+    obj: JObject,
+    addr: JString,
+    services: JObject,
+) -> crate::Result<()> {
+    let adapter = env.get_rust_field::<_, _, Adapter>(obj, "handle")?;
+    let addr_str = JavaStr::from_env(env, addr)?;
+    let addr_str = addr_str.to_str().map_err(|e| Error::Other(e.into()))?;
+    let id = PeripheralId(BDAddr::from_str(addr_str)?);
+    if let Some(peripheral) = adapter.manager.peripheral(&id) {
+        peripheral.set_services(env, services)?;
+    }
+    adapter.manager.emit(CentralEvent::ServicesChanged(id));
+    Ok(())
+}
+
+pub(crate) fn adapter_on_mtu_changed_internal(
+    env: &JNIEnv,
+    obj: JObject,
+    addr: JString,
+    mtu: jint,
+) -> crate::Result<()> {
+    let adapter = env.get_rust_field::<_, _, Adapter>(obj, "handle")?;
+    let addr_str = JavaStr::from_env(env, addr)?;
+    let addr_str = addr_str.to_str().map_err(|e| Error::Other(e.into()))?;
+    let id = PeripheralId(BDAddr::from_str(addr_str)?);
+    adapter.manager.emit(CentralEvent::MtuChanged {
+        id,
+        mtu: mtu as u16,
+    });
+    Ok(())
+}
+
+pub(crate) fn adapter_on_state_changed_internal(
+    env: &JNIEnv,
+    obj: JObject,
+    state: jint,
+) -> crate::Result<()> {
+    let adapter = env.get_rust_field::<_, _, Adapter>(obj, "handle")?;
+    adapter
+        .manager
+        .emit(CentralEvent::StateUpdate(get_central_state(state)));
+    Ok(())
+}
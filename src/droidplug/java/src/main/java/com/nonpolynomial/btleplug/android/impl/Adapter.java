@@ -1,7 +1,15 @@
 package com.nonpolynomial.btleplug.android.impl;
 
+import android.Manifest;
 import android.annotation.SuppressLint;
+import android.app.Activity;
+import android.content.BroadcastReceiver;
+import android.content.Context;
+import android.content.Intent;
+import android.content.IntentFilter;
+import android.content.pm.PackageManager;
 import android.bluetooth.BluetoothAdapter;
+import android.bluetooth.BluetoothGattService;
 import android.bluetooth.BluetoothManager;
 import android.bluetooth.le.BluetoothLeScanner;
 import android.bluetooth.le.ScanCallback;
@@ -16,13 +24,180 @@ import java.util.List;
 
 @SuppressWarnings("unused") // Native code uses this class.
 class Adapter {
+    // Values of the Rust Permission enum.
+    private static final int PERMISSION_SCAN = 0;
+    private static final int PERMISSION_CONNECT = 1;
+    private static final int PERMISSION_LOCATION = 2;
+
+    private static final int PERMISSIONS_REQUEST_CODE = 0x424c;
+
     private long handle;
     private final Callback callback = new Callback();
 
-    public Adapter() {}
+    private final StateReceiver stateReceiver = new StateReceiver();
+
+    // The application, or null if it couldn't be found.
+    private final Context context;
+
+    public Adapter() {
+        // There's no Context passed to init, so find the application through ActivityThread to
+        // listen for adapter state changes. If that fails we simply don't report them.
+        Context context = null;
+        try {
+            context = (Context) Class.forName("android.app.ActivityThread")
+                    .getMethod("currentApplication")
+                    .invoke(null);
+            if (context != null) {
+                context.registerReceiver(this.stateReceiver,
+                        new IntentFilter(BluetoothAdapter.ACTION_STATE_CHANGED));
+            }
+        } catch (ReflectiveOperationException e) {
+            // Ignore, adapter_state() still works.
+        }
+        this.context = context;
+    }
+
+    // Returns the manifest permission this version of Android needs for a permission, or null if
+    // it doesn't need one.
+    private static String permissionName(int permission) {
+        switch (permission) {
+            case PERMISSION_SCAN:
+                return Build.VERSION.SDK_INT >= 31 ? Manifest.permission.BLUETOOTH_SCAN : null;
+            case PERMISSION_CONNECT:
+                return Build.VERSION.SDK_INT >= 31 ? Manifest.permission.BLUETOOTH_CONNECT : null;
+            case PERMISSION_LOCATION:
+                // From Android 12, scanning needs BLUETOOTH_SCAN instead.
+                if (Build.VERSION.SDK_INT >= 31) {
+                    return null;
+                }
+                return Build.VERSION.SDK_INT >= 29
+                        ? Manifest.permission.ACCESS_FINE_LOCATION
+                        : Manifest.permission.ACCESS_COARSE_LOCATION;
+            default:
+                throw new IllegalArgumentException("Unknown permission " + permission);
+        }
+    }
+
+    // Returns null if there is no context to check with.
+    public Boolean hasPermission(int permission) {
+        String name = permissionName(permission);
+        if (name == null) {
+            return true;
+        }
+        if (this.context == null) {
+            return null;
+        }
+        return this.context.checkSelfPermission(name) == PackageManager.PERMISSION_GRANTED;
+    }
+
+    // The activity gets the result in onRequestPermissionsResult.
+    public void requestPermissions(Activity activity, int[] permissions) {
+        ArrayList<String> names = new ArrayList<>();
+        for (int permission : permissions) {
+            String name = permissionName(permission);
+            if (name != null && !names.contains(name)) {
+                names.add(name);
+            }
+        }
+        if (!names.isEmpty()) {
+            activity.requestPermissions(names.toArray(new String[0]), PERMISSIONS_REQUEST_CODE);
+        }
+    }
+
+    // Returns null if there is no context to check with.
+    public Boolean isLeSupported() {
+        if (this.context == null) {
+            return null;
+        }
+        return this.context.getPackageManager()
+                .hasSystemFeature(PackageManager.FEATURE_BLUETOOTH_LE);
+    }
+
+    // Returns one of the BluetoothAdapter.STATE_* values, or -1 if there is no adapter.
+    public int getState() {
+        BluetoothAdapter bluetoothAdapter = BluetoothAdapter.getDefaultAdapter();
+        if (bluetoothAdapter == null) {
+            return -1;
+        }
+        return bluetoothAdapter.getState();
+    }
+
+    // Returns false if the request was refused, which is always the case from Android 13.
+    @SuppressLint("MissingPermission")
+    @SuppressWarnings("deprecation")
+    public boolean setPowered(boolean powered) {
+        BluetoothAdapter bluetoothAdapter = BluetoothAdapter.getDefaultAdapter();
+        if (bluetoothAdapter == null) {
+            return false;
+        }
+        try {
+            return powered ? bluetoothAdapter.enable() : bluetoothAdapter.disable();
+        } catch (SecurityException e) {
+            return false;
+        }
+    }
+
+    @SuppressLint("MissingPermission")
+    public String getName() {
+        BluetoothAdapter bluetoothAdapter = BluetoothAdapter.getDefaultAdapter();
+        if (bluetoothAdapter == null) {
+            return null;
+        }
+        try {
+            return bluetoothAdapter.getName();
+        } catch (SecurityException e) {
+            return null;
+        }
+    }
+
+    @SuppressLint({"HardwareIds", "MissingPermission"})
+    public String getAddress() {
+        BluetoothAdapter bluetoothAdapter = BluetoothAdapter.getDefaultAdapter();
+        if (bluetoothAdapter == null) {
+            return null;
+        }
+        try {
+            return bluetoothAdapter.getAddress();
+        } catch (SecurityException e) {
+            return null;
+        }
+    }
+
+    // Returns null where the feature can't be queried on this version of Android.
+    public Boolean isLeExtendedAdvertisingSupported() {
+        BluetoothAdapter bluetoothAdapter = BluetoothAdapter.getDefaultAdapter();
+        if (bluetoothAdapter == null || Build.VERSION.SDK_INT < 26) {
+            return null;
+        }
+        return bluetoothAdapter.isLeExtendedAdvertisingSupported();
+    }
+
+    public Boolean isLe2MPhySupported() {
+        BluetoothAdapter bluetoothAdapter = BluetoothAdapter.getDefaultAdapter();
+        if (bluetoothAdapter == null || Build.VERSION.SDK_INT < 26) {
+            return null;
+        }
+        return bluetoothAdapter.isLe2MPhySupported();
+    }
+
+    public Boolean isLeCodedPhySupported() {
+        BluetoothAdapter bluetoothAdapter = BluetoothAdapter.getDefaultAdapter();
+        if (bluetoothAdapter == null || Build.VERSION.SDK_INT < 26) {
+            return null;
+        }
+        return bluetoothAdapter.isLeCodedPhySupported();
+    }
+
+    public Boolean isMultipleAdvertisementSupported() {
+        BluetoothAdapter bluetoothAdapter = BluetoothAdapter.getDefaultAdapter();
+        if (bluetoothAdapter == null) {
+            return null;
+        }
+        return bluetoothAdapter.isMultipleAdvertisementSupported();
+    }
 
     @SuppressLint("MissingPermission")
-    public void startScan(ScanFilter filter) {
+    public void startScan(ScanFilter filter, int scanMode) {
         BluetoothAdapter bluetoothAdapter = BluetoothAdapter.getDefaultAdapter();
         if (bluetoothAdapter == null) {
           throw new RuntimeException("No bluetooth adapter available.");
@@ -40,18 +215,24 @@ class Adapter {
         if (Build.VERSION.SDK_INT >= 26) {
             settings = new ScanSettings.Builder()
                     .setCallbackType(ScanSettings.CALLBACK_TYPE_ALL_MATCHES)
+                    .setScanMode(scanMode)
                     .setLegacy(false)
                     .build();
         } else {
             settings = new ScanSettings.Builder()
                     .setCallbackType(ScanSettings.CALLBACK_TYPE_ALL_MATCHES)
+                    .setScanMode(scanMode)
                     .build();
         }
         BluetoothLeScanner scanner = bluetoothAdapter.getBluetoothLeScanner();
         if (scanner == null) {
           throw new RuntimeException("No bluetooth scanner available for adapter");
         }
-        scanner.startScan(filters, settings, this.callback);
+        try {
+            scanner.startScan(filters, settings, this.callback);
+        } catch (SecurityException e) {
+            throw new PermissionDeniedException(e);
+        }
     }
 
     @SuppressLint("MissingPermission")
@@ -69,10 +250,24 @@ class Adapter {
 
     public native void onConnectionStateChanged(String address, boolean connected);
 
+    private native void onStateChanged(int state);
+
+    public native void onServicesChanged(String address, List<BluetoothGattService> services);
+
+    public native void onMtuChanged(String address, int mtu);
+
     private class Callback extends ScanCallback {
         @Override
         public void onScanResult(int callbackType, ScanResult result) {
             Adapter.this.reportScanResult(result);
         }
     }
+
+    private class StateReceiver extends BroadcastReceiver {
+        @Override
+        public void onReceive(Context context, Intent intent) {
+            int state = intent.getIntExtra(BluetoothAdapter.EXTRA_STATE, BluetoothAdapter.ERROR);
+            Adapter.this.onStateChanged(state);
+        }
+    }
 }
@@ -0,0 +1,14 @@
+package com.nonpolynomial.btleplug.android.impl;
+
+class AttException extends BluetoothException {
+    private final int status;
+
+    public AttException(int status) {
+        super();
+        this.status = status;
+    }
+
+    public int getStatus() {
+        return this.status;
+    }
+}
@@ -28,6 +28,7 @@ class Peripheral {
     private final BluetoothDevice device;
     private final Adapter adapter;
     private BluetoothGatt gatt;
+    private int transport = BluetoothDevice.TRANSPORT_AUTO;
     private final Callback callback;
     private boolean connected = false;
 
@@ -43,7 +44,7 @@ class Peripheral {
     }
 
     @SuppressLint("MissingPermission")
-    public Future<Void> connect() {
+    public Future<Void> connect(boolean autoConnect, int transport) {
         SimpleFuture<Void> future = new SimpleFuture<>();
         synchronized (this) {
             this.queueCommand(() -> {
@@ -65,10 +66,16 @@ class Peripheral {
 
                     if (this.connected) {
                         Peripheral.this.wakeCommand(future, null);
-                    } else if (this.gatt == null) {
+                    } else if (this.gatt == null || this.transport != transport) {
+                        // A BluetoothGatt stays on the transport it was created with.
+                        if (this.gatt != null) {
+                            this.gatt.close();
+                            this.gatt = null;
+                        }
                         try {
                             this.setCommandCallback(callback);
-                            this.gatt = this.device.connectGatt(null, false, this.callback);
+                            this.transport = transport;
+                            this.gatt = this.device.connectGatt(null, autoConnect, this.callback, transport);
                         } catch (SecurityException ex) {
                             throw new PermissionDeniedException(ex);
                         }
@@ -139,6 +146,9 @@ class Peripheral {
                                 if (!characteristic.getUuid().equals(uuid)) {
                                     throw new UnexpectedCharacteristicException();
                                 }
+                                if (status != BluetoothGatt.GATT_SUCCESS) {
+                                    throw new AttException(status);
+                                }
 
                                 Peripheral.this.wakeCommand(future, characteristic.getValue());
                             });
@@ -187,6 +197,9 @@ class Peripheral {
                                 if (!characteristic.getUuid().equals(uuid)) {
                                     throw new UnexpectedCharacteristicException();
                                 }
+                                if (status != BluetoothGatt.GATT_SUCCESS) {
+                                    throw new AttException(status);
+                                }
 
                                 Peripheral.this.wakeCommand(future, null);
                             });
@@ -259,7 +272,109 @@ class Peripheral {
     }
 
     @SuppressLint("MissingPermission")
-    public Future<Void> setCharacteristicNotification(UUID uuid, boolean enable) {
+    public Future<Integer> requestMtu(int mtu) {
+        SimpleFuture<Integer> future = new SimpleFuture<>();
+        synchronized (this) {
+            this.queueCommand(() -> {
+                this.asyncWithFuture(future, () -> {
+                    if (!this.connected) {
+                        throw new NotConnectedException();
+                    }
+
+                    this.setCommandCallback(new CommandCallback() {
+                        @Override
+                        public void onMtuChanged(BluetoothGatt gatt, int mtu, int status) {
+                            Peripheral.this.asyncWithFuture(future, () -> {
+                                if (status != BluetoothGatt.GATT_SUCCESS) {
+                                    throw new AttException(status);
+                                }
+
+                                Peripheral.this.wakeCommand(future, mtu);
+                            });
+                        }
+
+                        @Override
+                        public void onConnectionStateChange(BluetoothGatt gatt, int status, int newState) {
+                            Peripheral.this.asyncWithFuture(future, () -> {
+                                if (newState == BluetoothGatt.STATE_DISCONNECTED) {
+                                    throw new NotConnectedException();
+                                }
+                            });
+                        }
+                    });
+                    if (!this.gatt.requestMtu(mtu)) {
+                        throw new RuntimeException("Unable to request MTU");
+                    }
+                });
+            });
+        }
+        return future;
+    }
+
+    @SuppressLint("MissingPermission")
+    public Future<Integer> readRemoteRssi() {
+        SimpleFuture<Integer> future = new SimpleFuture<>();
+        synchronized (this) {
+            this.queueCommand(() -> {
+                this.asyncWithFuture(future, () -> {
+                    if (!this.connected) {
+                        throw new NotConnectedException();
+                    }
+
+                    this.setCommandCallback(new CommandCallback() {
+                        @Override
+                        public void onReadRemoteRssi(BluetoothGatt gatt, int rssi, int status) {
+                            Peripheral.this.asyncWithFuture(future, () -> {
+                                if (status != BluetoothGatt.GATT_SUCCESS) {
+                                    throw new AttException(status);
+                                }
+
+                                Peripheral.this.wakeCommand(future, rssi);
+                            });
+                        }
+
+                        @Override
+                        public void onConnectionStateChange(BluetoothGatt gatt, int status, int newState) {
+                            Peripheral.this.asyncWithFuture(future, () -> {
+                                if (newState == BluetoothGatt.STATE_DISCONNECTED) {
+                                    throw new NotConnectedException();
+                                }
+                            });
+                        }
+                    });
+                    if (!this.gatt.readRemoteRssi()) {
+                        throw new RuntimeException("Unable to read RSSI");
+                    }
+                });
+            });
+        }
+        return future;
+    }
+
+    // Takes one of the BluetoothGatt.CONNECTION_PRIORITY_* values. There is no callback for this,
+    // so it completes once the request has been made.
+    @SuppressLint("MissingPermission")
+    public Future<Void> requestConnectionPriority(int priority) {
+        SimpleFuture<Void> future = new SimpleFuture<>();
+        synchronized (this) {
+            this.queueCommand(() -> {
+                this.asyncWithFuture(future, () -> {
+                    if (!this.connected) {
+                        throw new NotConnectedException();
+                    }
+
+                    if (!this.gatt.requestConnectionPriority(priority)) {
+                        throw new RuntimeException("Unable to request connection priority");
+                    }
+                    Peripheral.this.wakeCommand(future, null);
+                });
+            });
+        }
+        return future;
+    }
+
+    @SuppressLint("MissingPermission")
+    public Future<Void> setCharacteristicNotification(UUID uuid, boolean enable, boolean indicate) {
         SimpleFuture<Void> future = new SimpleFuture<>();
         synchronized (this) {
             this.queueCommand(() -> {
@@ -274,7 +389,13 @@ class Peripheral {
                     }
 
                     BluetoothGattDescriptor descriptor = characteristic.getDescriptor(CLIENT_CHARACTERISTIC_CONFIGURATION_DESCRIPTOR);
-                    descriptor.setValue(enable ? BluetoothGattDescriptor.ENABLE_NOTIFICATION_VALUE : BluetoothGattDescriptor.DISABLE_NOTIFICATION_VALUE);
+                    if (!enable) {
+                        descriptor.setValue(BluetoothGattDescriptor.DISABLE_NOTIFICATION_VALUE);
+                    } else if (indicate) {
+                        descriptor.setValue(BluetoothGattDescriptor.ENABLE_INDICATION_VALUE);
+                    } else {
+                        descriptor.setValue(BluetoothGattDescriptor.ENABLE_NOTIFICATION_VALUE);
+                    }
                     if (!this.gatt.writeDescriptor(descriptor)) {
                         throw new RuntimeException("Unable to write client characteristic configuration descriptor");
                     }
@@ -283,13 +404,12 @@ class Peripheral {
                         @Override
                         public void onDescriptorWrite(BluetoothGatt gatt, BluetoothGattDescriptor descriptor, int status) {
                             Peripheral.this.asyncWithFuture(future, () -> {
-                                if (status != BluetoothGatt.GATT_SUCCESS) {
-                                    throw new RuntimeException("Unable to write descriptor");
-                                }
-
                                 if (!descriptor.getUuid().equals(CLIENT_CHARACTERISTIC_CONFIGURATION_DESCRIPTOR) || !descriptor.getCharacteristic().getUuid().equals(uuid)) {
                                     throw new UnexpectedCharacteristicException();
                                 }
+                                if (status != BluetoothGatt.GATT_SUCCESS) {
+                                    throw new AttException(status);
+                                }
 
                                 Peripheral.this.wakeCommand(future, null);
                             });
@@ -341,6 +461,9 @@ class Peripheral {
                                 if (!descriptor.getUuid().equals(uuid)) {
                                     throw new UnexpectedCharacteristicException();
                                 }
+                                if (status != BluetoothGatt.GATT_SUCCESS) {
+                                    throw new AttException(status);
+                                }
 
                                 Peripheral.this.wakeCommand(future, descriptor.getValue());
                             });
@@ -374,6 +497,9 @@ class Peripheral {
                                 if (!descriptor.getUuid().equals(uuid)) {
                                     throw new UnexpectedCharacteristicException();
                                 }
+                                if (status != BluetoothGatt.GATT_SUCCESS) {
+                                    throw new AttException(status);
+                                }
 
                                 Peripheral.this.wakeCommand(future, null);
                             });
@@ -452,6 +578,39 @@ class Peripheral {
         this.runNextCommand();
     }
 
+    @SuppressLint("MissingPermission")
+    private void rediscoverServices() {
+        synchronized (this) {
+            this.queueCommand(() -> {
+                if (!this.connected) {
+                    this.runNextCommand();
+                    return;
+                }
+
+                this.setCommandCallback(new CommandCallback() {
+                    @Override
+                    public void onServicesDiscovered(BluetoothGatt gatt, int status) {
+                        Peripheral.this.runNextCommand();
+                        if (status == BluetoothGatt.GATT_SUCCESS) {
+                            Peripheral.this.adapter.onServicesChanged(Peripheral.this.device.getAddress(), gatt.getServices());
+                        }
+                    }
+                    @Override
+                    public void onConnectionStateChange(BluetoothGatt gatt, int status, int newState) {
+                        if (newState == BluetoothGatt.STATE_DISCONNECTED) {
+                            Peripheral.this.gatt.close();
+                            Peripheral.this.gatt = null;
+                            Peripheral.this.runNextCommand();
+                        }
+                    }
+                });
+                if (!this.gatt.discoverServices()) {
+                    this.runNextCommand();
+                }
+            });
+        }
+    }
+
     private <T> void asyncWithFuture(SimpleFuture<T> future, Runnable callback) {
         try {
             callback.run();
@@ -514,6 +673,33 @@ class Peripheral {
             }
         }
 
+        @Override
+        public void onMtuChanged(BluetoothGatt gatt, int mtu, int status) {
+            synchronized (Peripheral.this) {
+                if (Peripheral.this.commandCallback != null) {
+                    Peripheral.this.commandCallback.onMtuChanged(gatt, mtu, status);
+                }
+            }
+            if (status == BluetoothGatt.GATT_SUCCESS) {
+                Peripheral.this.adapter.onMtuChanged(Peripheral.this.device.getAddress(), mtu);
+            }
+        }
+
+        @Override
+        public void onReadRemoteRssi(BluetoothGatt gatt, int rssi, int status) {
+            synchronized (Peripheral.this) {
+                if (Peripheral.this.commandCallback != null) {
+                    Peripheral.this.commandCallback.onReadRemoteRssi(gatt, rssi, status);
+                }
+            }
+        }
+
+        @Override
+        public void onServiceChanged(BluetoothGatt gatt) {
+            // The device's services have changed, so the ones we have are no longer valid.
+            Peripheral.this.rediscoverServices();
+        }
+
         @Override
         public void onCharacteristicChanged(BluetoothGatt gatt, BluetoothGattCharacteristic characteristic) {
             BluetoothGattCharacteristic characteristic2 = new BluetoothGattCharacteristic(characteristic.getUuid(), characteristic.getProperties(), characteristic.getPermissions());
@@ -569,5 +755,15 @@ class Peripheral {
         public void onDescriptorWrite(BluetoothGatt gatt, BluetoothGattDescriptor descriptor, int status) {
             throw new UnexpectedCallbackException();
         }
+
+        @Override
+        public void onReadRemoteRssi(BluetoothGatt gatt, int rssi, int status) {
+            throw new UnexpectedCallbackException();
+        }
+
+        @Override
+        public void onMtuChanged(BluetoothGatt gatt, int mtu, int status) {
+            // Android can change the MTU by itself, e.g. when connecting, so this isn't unexpected.
+        }
     }
 }
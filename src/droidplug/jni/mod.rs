@@ -1,7 +1,10 @@
 pub mod objects;
 
 use ::jni::{objects::JObject, JNIEnv, JavaVM, NativeMethod};
-use jni::{objects::JString, sys::jboolean};
+use jni::{
+    objects::JString,
+    sys::{jboolean, jint},
+};
 use once_cell::sync::OnceCell;
 use std::ffi::c_void;
 
@@ -22,6 +25,21 @@ pub fn init(env: &JNIEnv) -> crate::Result<()> {
                     sig: "(Ljava/lang/String;Z)V".into(),
                     fn_ptr: adapter_on_connection_state_changed as *mut c_void,
                 },
+                NativeMethod {
+                    name: "onStateChanged".into(),
+                    sig: "(I)V".into(),
+                    fn_ptr: adapter_on_state_changed as *mut c_void,
+                },
+                NativeMethod {
+                    name: "onServicesChanged".into(),
+                    sig: "(Ljava/lang/String;Ljava/util/List;)V".into(),
+                    fn_ptr: adapter_on_services_changed as *mut c_void,
+                },
+                NativeMethod {
+                    name: "onMtuChanged".into(),
+                    sig: "(Ljava/lang/String;I)V".into(),
+                    fn_ptr: adapter_on_mtu_changed as *mut c_void,
+                },
             ],
         )?;
         jni_utils::classcache::find_add_class(
@@ -52,6 +70,10 @@ pub fn init(env: &JNIEnv) -> crate::Result<()> {
             env,
             "com/nonpolynomial/btleplug/android/impl/NoSuchCharacteristicException",
         )?;
+        jni_utils::classcache::find_add_class(
+            env,
+            "com/nonpolynomial/btleplug/android/impl/AttException",
+        )?;
     }
     Ok(())
 }
@@ -81,3 +103,20 @@ extern "C" fn adapter_on_connection_state_changed(
     let _ =
         super::adapter::adapter_on_connection_state_changed_internal(&env, obj, addr, connected);
 }
+
+extern "C" fn adapter_on_state_changed(env: JNIEnv, obj: JObject, state: jint) {
+    let _ = super::adapter::adapter_on_state_changed_internal(&env, obj, state);
+}
+
+extern "C" fn adapter_on_services_changed(
+    env: JNIEnv,
+    obj: JObject,
+    addr: JString,
+    services: JObject,
+) {
+    let _ = super::adapter::adapter_on_services_changed_internal(&env, obj, addr, services);
+}
+
+extern "C" fn adapter_on_mtu_changed(env: JNIEnv, obj: JObject, addr: JString, mtu: jint) {
+    let _ = super::adapter::adapter_on_mtu_changed_internal(&env, obj, addr, mtu);
+}
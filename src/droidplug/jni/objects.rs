@@ -7,10 +7,10 @@ use jni::{
     JNIEnv,
 };
 use jni_utils::{future::JFuture, stream::JStream, uuid::JUuid};
-use std::{collections::HashMap, convert::TryFrom, iter::Iterator};
+use std::{collections::HashMap, convert::TryFrom, iter::Iterator, time::SystemTime};
 use uuid::Uuid;
 
-use crate::api::{BDAddr, CharPropFlags, PeripheralProperties, ScanFilter};
+use crate::api::{AddressType, BDAddr, CharPropFlags, PeripheralProperties, ScanFilter};
 
 pub struct JPeripheral<'a: 'b, 'b> {
     internal: JObject<'a>,
@@ -18,6 +18,9 @@ pub struct JPeripheral<'a: 'b, 'b> {
     disconnect: JMethodID<'a>,
     is_connected: JMethodID<'a>,
     discover_services: JMethodID<'a>,
+    request_mtu: JMethodID<'a>,
+    request_connection_priority: JMethodID<'a>,
+    read_remote_rssi: JMethodID<'a>,
     read: JMethodID<'a>,
     write: JMethodID<'a>,
     set_characteristic_notification: JMethodID<'a>,
@@ -59,7 +62,7 @@ impl<'a: 'b, 'b> JPeripheral<'a, 'b> {
         let connect = env.get_method_id(
             class,
             "connect",
-            "()Lio/github/gedgygedgy/rust/future/Future;",
+            "(ZI)Lio/github/gedgygedgy/rust/future/Future;",
         )?;
         let disconnect = env.get_method_id(
             class,
@@ -72,6 +75,21 @@ impl<'a: 'b, 'b> JPeripheral<'a, 'b> {
             "discoverServices",
             "()Lio/github/gedgygedgy/rust/future/Future;",
         )?;
+        let request_mtu = env.get_method_id(
+            class,
+            "requestMtu",
+            "(I)Lio/github/gedgygedgy/rust/future/Future;",
+        )?;
+        let request_connection_priority = env.get_method_id(
+            class,
+            "requestConnectionPriority",
+            "(I)Lio/github/gedgygedgy/rust/future/Future;",
+        )?;
+        let read_remote_rssi = env.get_method_id(
+            class,
+            "readRemoteRssi",
+            "()Lio/github/gedgygedgy/rust/future/Future;",
+        )?;
         let read = env.get_method_id(
             class,
             "read",
@@ -85,7 +103,7 @@ impl<'a: 'b, 'b> JPeripheral<'a, 'b> {
         let set_characteristic_notification = env.get_method_id(
             class,
             "setCharacteristicNotification",
-            "(Ljava/util/UUID;Z)Lio/github/gedgygedgy/rust/future/Future;",
+            "(Ljava/util/UUID;ZZ)Lio/github/gedgygedgy/rust/future/Future;",
         )?;
         let get_notifications = env.get_method_id(
             class,
@@ -108,6 +126,9 @@ impl<'a: 'b, 'b> JPeripheral<'a, 'b> {
             disconnect,
             is_connected,
             discover_services,
+            request_mtu,
+            request_connection_priority,
+            read_remote_rssi,
             read,
             write,
             set_characteristic_notification,
@@ -137,14 +158,14 @@ impl<'a: 'b, 'b> JPeripheral<'a, 'b> {
         Self::from_env_impl(env, obj)
     }
 
-    pub fn connect(&self) -> Result<JFuture<'a, 'b>> {
+    pub fn connect(&self, auto_connect: bool, transport: i32) -> Result<JFuture<'a, 'b>> {
         let future_obj = self
             .env
             .call_method_unchecked(
                 self.internal,
                 self.connect,
                 JavaType::Object("Lio/github/gedgygedgy/rust/future/Future;".to_string()),
-                &[],
+                &[auto_connect.into(), transport.into()],
             )?
             .l()?;
         JFuture::from_env(self.env, future_obj)
@@ -187,6 +208,45 @@ impl<'a: 'b, 'b> JPeripheral<'a, 'b> {
         JFuture::from_env(self.env, future_obj)
     }
 
+    pub fn request_mtu(&self, mtu: jint) -> Result<JFuture<'a, 'b>> {
+        let future_obj = self
+            .env
+            .call_method_unchecked(
+                self.internal,
+                self.request_mtu,
+                JavaType::Object("Lio/github/gedgygedgy/rust/future/Future;".to_string()),
+                &[mtu.into()],
+            )?
+            .l()?;
+        JFuture::from_env(self.env, future_obj)
+    }
+
+    pub fn request_connection_priority(&self, priority: jint) -> Result<JFuture<'a, 'b>> {
+        let future_obj = self
+            .env
+            .call_method_unchecked(
+                self.internal,
+                self.request_connection_priority,
+                JavaType::Object("Lio/github/gedgygedgy/rust/future/Future;".to_string()),
+                &[priority.into()],
+            )?
+            .l()?;
+        JFuture::from_env(self.env, future_obj)
+    }
+
+    pub fn read_remote_rssi(&self) -> Result<JFuture<'a, 'b>> {
+        let future_obj = self
+            .env
+            .call_method_unchecked(
+                self.internal,
+                self.read_remote_rssi,
+                JavaType::Object("Lio/github/gedgygedgy/rust/future/Future;".to_string()),
+                &[],
+            )?
+            .l()?;
+        JFuture::from_env(self.env, future_obj)
+    }
+
     pub fn read(&self, uuid: JUuid<'a, 'b>) -> Result<JFuture<'a, 'b>> {
         let future_obj = self
             .env
@@ -222,6 +282,7 @@ impl<'a: 'b, 'b> JPeripheral<'a, 'b> {
         &self,
         uuid: JUuid<'a, 'b>,
         enable: bool,
+        indicate: bool,
     ) -> Result<JFuture<'a, 'b>> {
         let future_obj = self
             .env
@@ -229,7 +290,7 @@ impl<'a: 'b, 'b> JPeripheral<'a, 'b> {
                 self.internal,
                 self.set_characteristic_notification,
                 JavaType::Object("Lio/github/gedgygedgy/rust/future/Future;".to_string()),
-                &[uuid.into(), enable.into()],
+                &[uuid.into(), enable.into(), indicate.into()],
             )?
             .l()?;
         JFuture::from_env(self.env, future_obj)
@@ -287,8 +348,10 @@ impl<'a: 'b, 'b> JPeripheral<'a, 'b> {
 pub struct JBluetoothGattService<'a: 'b, 'b> {
     internal: JObject<'a>,
     get_uuid: JMethodID<'a>,
-    //is_primary: JMethodID<'a>,
+    get_type: JMethodID<'a>,
     get_characteristics: JMethodID<'a>,
+    get_included_services: JMethodID<'a>,
+    get_instance_id: JMethodID<'a>,
     env: &'b JNIEnv<'a>,
 }
 
@@ -297,30 +360,54 @@ impl<'a: 'b, 'b> JBluetoothGattService<'a, 'b> {
         let class = env.auto_local(env.find_class("android/bluetooth/BluetoothGattService")?);
 
         let get_uuid = env.get_method_id(&class, "getUuid", "()Ljava/util/UUID;")?;
-        //let is_primary = env.get_method_id(&class, "isPrimary", "()Z;")?;
+        let get_type = env.get_method_id(&class, "getType", "()I")?;
         let get_characteristics =
             env.get_method_id(&class, "getCharacteristics", "()Ljava/util/List;")?;
+        let get_included_services =
+            env.get_method_id(&class, "getIncludedServices", "()Ljava/util/List;")?;
+        let get_instance_id = env.get_method_id(&class, "getInstanceId", "()I")?;
         Ok(Self {
             internal: obj,
             get_uuid,
-            //is_primary,
+            get_type,
             get_characteristics,
+            get_included_services,
+            get_instance_id,
             env,
         })
     }
 
     pub fn is_primary(&self) -> Result<bool> {
-        /*
-        self.env
-        .call_method_unchecked(
-            self.internal,
-            self.is_primary,
-            JavaType::Primitive(Primitive::Boolean),
-            &[],
-        )?
-        .z()
-        */
-        Ok(true)
+        // BluetoothGattService.SERVICE_TYPE_PRIMARY
+        const SERVICE_TYPE_PRIMARY: i32 = 0;
+        let service_type = self
+            .env
+            .call_method_unchecked(
+                self.internal,
+                self.get_type,
+                JavaType::Primitive(Primitive::Int),
+                &[],
+            )?
+            .i()?;
+        Ok(service_type == SERVICE_TYPE_PRIMARY)
+    }
+
+    pub fn get_included_services(&self) -> Result<Vec<JBluetoothGattService<'a, 'b>>> {
+        let obj = self
+            .env
+            .call_method_unchecked(
+                self.internal,
+                self.get_included_services,
+                JavaType::Object("Ljava/util/List;".to_string()),
+                &[],
+            )?
+            .l()?;
+        let service_list = JList::from_env(self.env, obj)?;
+        let mut service_vec = vec![];
+        for service in service_list.iter()? {
+            service_vec.push(JBluetoothGattService::from_env(self.env, service)?);
+        }
+        Ok(service_vec)
     }
 
     pub fn get_uuid(&self) -> Result<Uuid> {
@@ -337,6 +424,19 @@ impl<'a: 'b, 'b> JBluetoothGattService<'a, 'b> {
         Ok(uuid_obj.as_uuid()?)
     }
 
+    pub fn get_instance_id(&self) -> Result<u16> {
+        let id = self
+            .env
+            .call_method_unchecked(
+                self.internal,
+                self.get_instance_id,
+                JavaType::Primitive(Primitive::Int),
+                &[],
+            )?
+            .i()?;
+        Ok(id as u16)
+    }
+
     pub fn get_characteristics(&self) -> Result<Vec<JBluetoothGattCharacteristic>> {
         let obj = self
             .env
@@ -362,6 +462,8 @@ pub struct JBluetoothGattCharacteristic<'a: 'b, 'b> {
     get_properties: JMethodID<'a>,
     get_value: JMethodID<'a>,
     get_descriptors: JMethodID<'a>,
+    get_service: JMethodID<'a>,
+    get_instance_id: JMethodID<'a>,
     env: &'b JNIEnv<'a>,
 }
 
@@ -374,16 +476,37 @@ impl<'a: 'b, 'b> JBluetoothGattCharacteristic<'a, 'b> {
         let get_properties = env.get_method_id(&class, "getProperties", "()I")?;
         let get_descriptors = env.get_method_id(&class, "getDescriptors", "()Ljava/util/List;")?;
         let get_value = env.get_method_id(&class, "getValue", "()[B")?;
+        let get_service = env.get_method_id(
+            &class,
+            "getService",
+            "()Landroid/bluetooth/BluetoothGattService;",
+        )?;
+        let get_instance_id = env.get_method_id(&class, "getInstanceId", "()I")?;
         Ok(Self {
             internal: obj,
             get_uuid,
             get_properties,
             get_value,
             get_descriptors,
+            get_service,
+            get_instance_id,
             env,
         })
     }
 
+    pub fn get_service(&self) -> Result<JBluetoothGattService<'a, 'b>> {
+        let obj = self
+            .env
+            .call_method_unchecked(
+                self.internal,
+                self.get_service,
+                JavaType::Object("Landroid/bluetooth/BluetoothGattService;".to_string()),
+                &[],
+            )?
+            .l()?;
+        JBluetoothGattService::from_env(self.env, obj)
+    }
+
     pub fn get_uuid(&self) -> Result<Uuid> {
         let obj = self
             .env
@@ -398,6 +521,19 @@ impl<'a: 'b, 'b> JBluetoothGattCharacteristic<'a, 'b> {
         Ok(uuid_obj.as_uuid()?)
     }
 
+    pub fn get_instance_id(&self) -> Result<u16> {
+        let id = self
+            .env
+            .call_method_unchecked(
+                self.internal,
+                self.get_instance_id,
+                JavaType::Primitive(Primitive::Int),
+                &[],
+            )?
+            .i()?;
+        Ok(id as u16)
+    }
+
     pub fn get_properties(&self) -> Result<CharPropFlags> {
         let flags = self
             .env
@@ -479,21 +615,58 @@ impl<'a: 'b, 'b> JBluetoothGattDescriptor<'a, 'b> {
 pub struct JBluetoothDevice<'a: 'b, 'b> {
     internal: JObject<'a>,
     get_address: JMethodID<'a>,
+    get_address_type: Option<JMethodID<'a>>,
     env: &'b JNIEnv<'a>,
 }
 
+// Values of BluetoothDevice.ADDRESS_TYPE_*.
+const ADDRESS_TYPE_PUBLIC: jint = 0;
+const ADDRESS_TYPE_RANDOM: jint = 1;
+
+fn sdk_version(env: &JNIEnv) -> Result<jint> {
+    env.get_static_field("android/os/Build$VERSION", "SDK_INT", "I")?
+        .i()
+}
+
 impl<'a: 'b, 'b> JBluetoothDevice<'a, 'b> {
     pub fn from_env(env: &'b JNIEnv<'a>, obj: JObject<'a>) -> Result<Self> {
         let class = env.auto_local(env.find_class("android/bluetooth/BluetoothDevice")?);
 
         let get_address = env.get_method_id(&class, "getAddress", "()Ljava/lang/String;")?;
+        // getAddressType() was only added in Android 15.
+        let get_address_type = if sdk_version(env)? >= 35 {
+            Some(env.get_method_id(&class, "getAddressType", "()I")?)
+        } else {
+            None
+        };
         Ok(Self {
             internal: obj,
             get_address,
+            get_address_type,
             env,
         })
     }
 
+    pub fn get_address_type(&self) -> Result<Option<AddressType>> {
+        let Some(get_address_type) = self.get_address_type else {
+            return Ok(None);
+        };
+        let address_type = self
+            .env
+            .call_method_unchecked(
+                self.internal,
+                get_address_type,
+                JavaType::Primitive(Primitive::Int),
+                &[],
+            )?
+            .i()?;
+        Ok(match address_type {
+            ADDRESS_TYPE_PUBLIC => Some(AddressType::Public),
+            ADDRESS_TYPE_RANDOM => Some(AddressType::Random),
+            _ => None,
+        })
+    }
+
     pub fn get_address(&self) -> Result<JString<'a>> {
         let obj = self
             .env
@@ -551,6 +724,7 @@ pub struct JScanResult<'a: 'b, 'b> {
     get_scan_record: JMethodID<'a>,
     get_tx_power: JMethodID<'a>,
     get_rssi: JMethodID<'a>,
+    is_connectable: JMethodID<'a>,
     env: &'b JNIEnv<'a>,
 }
 
@@ -567,12 +741,14 @@ impl<'a: 'b, 'b> JScanResult<'a, 'b> {
         )?;
         let get_tx_power = env.get_method_id(&class, "getTxPower", "()I")?;
         let get_rssi = env.get_method_id(&class, "getRssi", "()I")?;
+        let is_connectable = env.get_method_id(&class, "isConnectable", "()Z")?;
         Ok(Self {
             internal: obj,
             get_device,
             get_scan_record,
             get_tx_power,
             get_rssi,
+            is_connectable,
             env,
         })
     }
@@ -624,6 +800,17 @@ impl<'a: 'b, 'b> JScanResult<'a, 'b> {
             )?
             .i()
     }
+
+    pub fn is_connectable(&self) -> Result<bool> {
+        self.env
+            .call_method_unchecked(
+                self.internal,
+                self.is_connectable,
+                JavaType::Primitive(Primitive::Boolean),
+                &[],
+            )?
+            .z()
+    }
 }
 
 impl<'a: 'b, 'b> TryFrom<JScanResult<'a, 'b>> for (BDAddr, Option<PeripheralProperties>) {
@@ -671,7 +858,15 @@ impl<'a: 'b, 'b> TryFrom<JScanResult<'a, 'b>> for (BDAddr, Option<PeripheralProp
                 )
             };
 
-            let tx_power_level = result.get_tx_power()?;
+            // Prefer the TX Power Level AD type, which is what the other platforms report, and
+            // fall back to the TX power from the extended advertising header.
+            let tx_power_level = record.get_tx_power_level()?;
+            const TX_POWER_LEVEL_NOT_PRESENT: jint = jint::MIN; // from ScanRecord documentation
+            let tx_power_level = if tx_power_level == TX_POWER_LEVEL_NOT_PRESENT {
+                result.get_tx_power()?
+            } else {
+                tx_power_level
+            };
             const TX_POWER_NOT_PRESENT: jint = 127; // from ScanResult documentation
             let tx_power_level = if tx_power_level == TX_POWER_NOT_PRESENT {
                 None
@@ -681,6 +876,8 @@ impl<'a: 'b, 'b> TryFrom<JScanResult<'a, 'b>> for (BDAddr, Option<PeripheralProp
 
             let rssi = Some(result.get_rssi()? as i16);
 
+            let connectable = Some(result.is_connectable()?);
+
             let manufacturer_specific_data_array = record.get_manufacturer_specific_data()?;
             let manufacturer_specific_data_obj: &JObject = &manufacturer_specific_data_array;
             let mut manufacturer_data = HashMap::new();
@@ -693,7 +890,7 @@ impl<'a: 'b, 'b> TryFrom<JScanResult<'a, 'b>> for (BDAddr, Option<PeripheralProp
 
                     let index = index as u16;
                     let data = jni_utils::arrays::byte_array_to_vec(result.env, data.into_inner())?;
-                    manufacturer_data.insert(index, data);
+                    manufacturer_data.insert(index, data.into());
                 }
             }
 
@@ -710,10 +907,13 @@ impl<'a: 'b, 'b> TryFrom<JScanResult<'a, 'b>> for (BDAddr, Option<PeripheralProp
                         .as_uuid()?;
                     let data =
                         jni_utils::arrays::byte_array_to_vec(result.env, value.into_inner())?;
-                    service_data.insert(uuid, data);
+                    service_data.insert(uuid, data.into());
                 }
             }
 
+            // The scan record is the advertisement and scan response, padded with zeros.
+            let scan_record_bytes = record.get_bytes()?;
+
             let services_list = record.get_service_uuids()?;
             let services_obj: &JObject = &services_list;
             let mut services = Vec::new();
@@ -731,7 +931,7 @@ impl<'a: 'b, 'b> TryFrom<JScanResult<'a, 'b>> for (BDAddr, Option<PeripheralProp
 
             Some(PeripheralProperties {
                 address: addr,
-                address_type: None,
+                address_type: device.get_address_type()?,
                 local_name: device_name,
                 tx_power_level,
                 manufacturer_data,
@@ -739,6 +939,12 @@ impl<'a: 'b, 'b> TryFrom<JScanResult<'a, 'b>> for (BDAddr, Option<PeripheralProp
                 services,
                 rssi,
                 class: None,
+                advertisement_type: None,
+                connectable,
+                last_seen: Some(SystemTime::now()),
+                appearance: appearance_from_scan_record(&scan_record_bytes).map(Into::into),
+                scan_response: Default::default(),
+                advertising_data: Some(scan_record_bytes.into()),
             })
         };
         Ok((addr, properties))
@@ -752,6 +958,7 @@ pub struct JScanRecord<'a: 'b, 'b> {
     get_manufacturer_specific_data: JMethodID<'a>,
     get_service_data: JMethodID<'a>,
     get_service_uuids: JMethodID<'a>,
+    get_bytes: JMethodID<'a>,
     env: &'b JNIEnv<'a>,
 }
 
@@ -783,6 +990,7 @@ impl<'a: 'b, 'b> JScanRecord<'a, 'b> {
         let get_service_data = env.get_method_id(&class, "getServiceData", "()Ljava/util/Map;")?;
         let get_service_uuids =
             env.get_method_id(&class, "getServiceUuids", "()Ljava/util/List;")?;
+        let get_bytes = env.get_method_id(&class, "getBytes", "()[B")?;
         Ok(Self {
             internal: obj,
             get_device_name,
@@ -790,6 +998,7 @@ impl<'a: 'b, 'b> JScanRecord<'a, 'b> {
             get_manufacturer_specific_data,
             get_service_data,
             get_service_uuids,
+            get_bytes,
             env,
         })
     }
@@ -856,6 +1065,41 @@ impl<'a: 'b, 'b> JScanRecord<'a, 'b> {
             .l()?;
         JList::from_env(self.env, obj)
     }
+
+    pub fn get_bytes(&self) -> Result<Vec<u8>> {
+        let obj = self
+            .env
+            .call_method_unchecked(
+                self.internal,
+                self.get_bytes,
+                JavaType::Array(Box::new(JavaType::Primitive(Primitive::Byte))),
+                &[],
+            )?
+            .l()?;
+        if self.env.is_same_object(obj, JObject::null())? {
+            return Ok(Vec::new());
+        }
+        jni_utils::arrays::byte_array_to_vec(self.env, obj.into_inner())
+    }
+}
+
+/// ScanRecord doesn't have a getter for the appearance, so pull it out of the raw advertising data
+/// structures.
+fn appearance_from_scan_record(bytes: &[u8]) -> Option<u16> {
+    const APPEARANCE: u8 = 0x19;
+    let mut remaining = bytes;
+    while let [len, rest @ ..] = remaining {
+        let len = *len as usize;
+        if len == 0 || len > rest.len() {
+            break;
+        }
+        let (structure, next) = rest.split_at(len);
+        if let [APPEARANCE, low, high] = structure {
+            return Some(u16::from_le_bytes([*low, *high]));
+        }
+        remaining = next;
+    }
+    None
 }
 
 #[derive(Clone)]
@@ -1,6 +1,10 @@
 use super::adapter::Adapter;
-use crate::{api, Result};
+use crate::api::observer::TraceHandler;
+use crate::{api, api::ManagerEvent, Result};
 use async_trait::async_trait;
+use futures::stream::{self, Stream, StreamExt};
+use std::pin::Pin;
+use std::sync::Arc;
 
 #[derive(Clone, Debug)]
 pub struct Manager;
@@ -18,4 +22,17 @@ impl api::Manager for Manager {
     async fn adapters(&self) -> Result<Vec<Adapter>> {
         Ok(vec![super::global_adapter().clone()])
     }
+
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = ManagerEvent<Adapter>> + Send>>> {
+        // There is only ever one adapter, and it can't be removed.
+        let adapters = api::Manager::adapters(self).await?;
+        Ok(Box::pin(
+            stream::iter(adapters.into_iter().map(ManagerEvent::AdapterAdded))
+                .chain(stream::pending()),
+        ))
+    }
+
+    fn set_trace_handler(&self, handler: Option<Arc<dyn TraceHandler>>) {
+        super::global_adapter().set_trace_handler(handler);
+    }
 }
@@ -1,14 +1,22 @@
 use crate::{
     api::{
-        self, BDAddr, Characteristic, Descriptor, PeripheralProperties, Service, ValueNotification,
-        WriteType,
+        self,
+        descriptor_cache::DescriptorCache,
+        link::{self, LinkSample},
+        operation::OperationContext,
+        rssi::RssiStats,
+        spans, BDAddr, CentralEvent, Characteristic, ConnectionPriority, Descriptor,
+        DisconnectReason, NativeError, PeripheralEvent, PeripheralProperties, Service,
+        SubscriptionType, Transport, ValueNotification, WriteType,
     },
+    common::adapter_manager::AdapterManager,
     Error, Result,
 };
 use async_trait::async_trait;
 use futures::stream::Stream;
 use jni::{
     objects::{GlobalRef, JList, JObject},
+    sys::jint,
     JNIEnv,
 };
 use jni_utils::{
@@ -20,11 +28,12 @@ use serde::{Deserialize, Serialize};
 #[cfg(feature = "serde")]
 use serde_cr as serde;
 use std::{
-    collections::BTreeSet,
+    collections::{BTreeSet, HashMap},
     convert::TryFrom,
     fmt::{self, Debug, Display, Formatter},
     pin::Pin,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
 };
 
 use super::jni::{
@@ -32,6 +41,7 @@ use super::jni::{
     objects::{JBluetoothGattCharacteristic, JBluetoothGattService, JPeripheral},
 };
 use jni::objects::JClass;
+use uuid::Uuid;
 #[cfg_attr(
     feature = "serde",
     derive(Serialize, Deserialize),
@@ -45,6 +55,25 @@ impl Display for PeripheralId {
     }
 }
 
+#[cfg(feature = "mock")]
+impl PeripheralId {
+    /// The ID of a peripheral of the [mock](crate::mock) backend.
+    pub(crate) fn synthetic(_adapter_id: &str, address: BDAddr) -> Self {
+        PeripheralId(address)
+    }
+}
+
+/// Converts a failed GATT status to an error. Android's GATT statuses are ATT error codes, apart
+/// from a few of its own.
+fn att_error(status: i32) -> Error {
+    // BluetoothGatt.GATT_ERROR, which the stack uses for its own failures.
+    const GATT_ERROR: i32 = 0x85;
+    match u8::try_from(status) {
+        Ok(code) if status != GATT_ERROR => Error::Att(code.into()),
+        _ => Error::Other(Box::new(NativeError::GattStatus(status))),
+    }
+}
+
 fn get_poll_result<'a: 'b, 'b>(
     env: &'b JNIEnv<'a>,
     result: JPollResult<'a, 'b>,
@@ -117,6 +146,18 @@ fn get_poll_result<'a: 'b, 'b>(
                     ),
                 )? {
                     Ok(Err(Error::NoSuchCharacteristic))
+                } else if env.is_instance_of(
+                    cause,
+                    JClass::from(
+                        jni_utils::classcache::get_class(
+                            "com/nonpolynomial/btleplug/android/impl/AttException",
+                        )
+                        .unwrap()
+                        .as_obj(),
+                    ),
+                )? {
+                    let status = env.call_method(cause, "getStatus", "()I", &[])?.i()?;
+                    Ok(Err(att_error(status)))
                 } else if env.is_instance_of(
                     cause,
                     "java/lang/RuntimeException",
@@ -142,6 +183,9 @@ struct PeripheralShared {
     services: BTreeSet<Service>,
     characteristics: BTreeSet<Characteristic>,
     properties: Option<PeripheralProperties>,
+    /// The type of subscription enabled for each characteristic, so notifications can say what
+    /// they are.
+    subscriptions: HashMap<Uuid, SubscriptionType>,
 }
 
 #[derive(Clone)]
@@ -149,10 +193,19 @@ pub struct Peripheral {
     addr: BDAddr,
     internal: GlobalRef,
     shared: Arc<Mutex<PeripheralShared>>,
+    descriptor_cache: DescriptorCache,
+    operation_context: OperationContext,
+    manager: Weak<AdapterManager<Peripheral>>,
 }
 
 impl Peripheral {
-    pub(crate) fn new(env: &JNIEnv, adapter: JObject, addr: BDAddr) -> Result<Self> {
+    pub(crate) fn new(
+        env: &JNIEnv,
+        adapter: JObject,
+        addr: BDAddr,
+        manager: Weak<AdapterManager<Peripheral>>,
+        operation_context: OperationContext,
+    ) -> Result<Self> {
         let obj = JPeripheral::new(env, adapter, addr)?;
         Ok(Self {
             addr,
@@ -161,16 +214,59 @@ impl Peripheral {
                 services: BTreeSet::new(),
                 characteristics: BTreeSet::new(),
                 properties: None,
+                subscriptions: HashMap::new(),
             })),
+            descriptor_cache: DescriptorCache::default(),
+            operation_context: operation_context.for_peripheral(PeripheralId(addr)),
+            manager,
         })
     }
 
-    pub(crate) fn report_properties(&self, properties: PeripheralProperties) {
+    /// Updates the properties from a scan result. Like the other platforms, what the scan result
+    /// doesn't have is kept from earlier ones.
+    pub(crate) fn report_properties(&self, mut properties: PeripheralProperties) {
         let mut guard = self.shared.lock().unwrap();
-
+        if let Some(old) = guard.properties.take() {
+            properties.address_type = properties.address_type.or(old.address_type);
+            properties.local_name = properties.local_name.or(old.local_name);
+            properties.tx_power_level = properties.tx_power_level.or(old.tx_power_level);
+            properties.rssi = properties.rssi.or(old.rssi);
+            properties.appearance = properties.appearance.or(old.appearance);
+            if properties.manufacturer_data.is_empty() {
+                properties.manufacturer_data = old.manufacturer_data;
+            }
+            if properties.service_data.is_empty() {
+                properties.service_data = old.service_data;
+            }
+            if properties.services.is_empty() {
+                properties.services = old.services;
+            }
+        }
         guard.properties = Some(properties);
     }
 
+    /// Connects with `BluetoothDevice.connectGatt`. With `auto_connect`, Android waits for the
+    /// device to be in range rather than giving up after about 30 seconds.
+    pub(super) async fn connect_gatt(
+        &self,
+        auto_connect: bool,
+        transport: Transport,
+    ) -> Result<()> {
+        // BluetoothDevice.TRANSPORT_AUTO, TRANSPORT_BREDR and TRANSPORT_LE.
+        let transport = match transport {
+            Transport::Auto => 0,
+            Transport::BrEdr => 1,
+            Transport::Le => 2,
+        };
+        let future = self
+            .with_obj(|_env, obj| JSendFuture::try_from(obj.connect(auto_connect, transport)?))?;
+        let result_ref = future.await?;
+        self.with_obj(|env, _obj| {
+            let result = JPollResult::from_env(env, result_ref.as_obj())?;
+            get_poll_result(env, result).map(|_| {})
+        })
+    }
+
     fn with_obj<T, E>(
         &self,
         f: impl FnOnce(&JNIEnv, JPeripheral) -> std::result::Result<T, E>,
@@ -183,20 +279,100 @@ impl Peripheral {
         f(&env, obj)
     }
 
+    /// Replaces the services we know about with a `List<BluetoothGattService>` from Java.
+    pub(crate) fn set_services(&self, env: &JNIEnv, services: JObject) -> Result<()> {
+        use std::iter::FromIterator;
+
+        let list = JList::from_env(env, services)?;
+        let mut peripheral_services = Vec::new();
+        let mut peripheral_characteristics = Vec::new();
+
+        for service in list.iter()? {
+            let service = JBluetoothGattService::from_env(env, service)?;
+            let mut characteristics = BTreeSet::<Characteristic>::new();
+            for characteristic in service.get_characteristics()? {
+                let mut descriptors = BTreeSet::new();
+                for descriptor in characteristic.get_descriptors()? {
+                    descriptors.insert(Descriptor {
+                        uuid: descriptor.get_uuid()?,
+                        service_uuid: service.get_uuid()?,
+                        characteristic_uuid: characteristic.get_uuid()?,
+                        handle: None,
+                        value: None,
+                    });
+                }
+                // Android uses the value handle as the characteristic's instance ID.
+                let value_handle = characteristic.get_instance_id()?;
+                let char = Characteristic {
+                    service_uuid: service.get_uuid()?,
+                    uuid: characteristic.get_uuid()?,
+                    properties: characteristic.get_properties()?,
+                    descriptors: descriptors.clone(),
+                    handle: value_handle.checked_sub(1),
+                    value_handle: Some(value_handle),
+                };
+                // Only consider the first characteristic of each UUID
+                // This "should" be unique, but of course it's not enforced
+                if characteristics
+                    .iter()
+                    .filter(|c| c.service_uuid == char.service_uuid && c.uuid == char.uuid)
+                    .count()
+                    == 0
+                {
+                    characteristics.insert(char.clone());
+                    peripheral_characteristics.push(char.clone());
+                }
+            }
+            // Android lists included services alongside the primary services, so they only need
+            // to be linked up here.
+            let mut included_services = BTreeSet::new();
+            for included in service.get_included_services()? {
+                included_services.insert(included.get_uuid()?);
+            }
+            peripheral_services.push(Service {
+                uuid: service.get_uuid()?,
+                primary: service.is_primary()?,
+                characteristics,
+                included_services,
+                handle: Some(service.get_instance_id()?),
+            })
+        }
+        let mut guard = self.shared.lock().map_err(Into::<Error>::into)?;
+        guard.services = BTreeSet::from_iter(peripheral_services.clone());
+        guard.characteristics = BTreeSet::from_iter(peripheral_characteristics.clone());
+        Ok(())
+    }
+
     async fn set_characteristic_notification(
         &self,
         characteristic: &Characteristic,
-        enable: bool,
+        subscription_type: Option<SubscriptionType>,
     ) -> Result<()> {
         let future = self.with_obj(|env, obj| {
             let uuid_obj = JUuid::new(env, characteristic.uuid)?;
-            JSendFuture::try_from(obj.set_characteristic_notification(uuid_obj, enable)?)
+            JSendFuture::try_from(obj.set_characteristic_notification(
+                uuid_obj,
+                subscription_type.is_some(),
+                subscription_type == Some(SubscriptionType::Indicate),
+            )?)
         })?;
         let result_ref = future.await?;
         self.with_obj(|env, _obj| {
             let result = JPollResult::from_env(env, result_ref.as_obj())?;
             get_poll_result(env, result).map(|_| {})
-        })
+        })?;
+        let mut guard = self.shared.lock().unwrap();
+        match subscription_type {
+            Some(subscription_type) => {
+                guard
+                    .subscriptions
+                    .insert(characteristic.uuid, subscription_type);
+            }
+            None => {
+                guard.subscriptions.remove(&characteristic.uuid);
+            }
+        }
+        Ok(())
     }
 }
 
@@ -224,7 +400,14 @@ impl api::Peripheral for Peripheral {
 
     fn characteristics(&self) -> BTreeSet<Characteristic> {
         let guard = self.shared.lock().unwrap();
-        (&guard.characteristics).clone()
+        guard
+            .characteristics
+            .iter()
+            .map(|characteristic| {
+                self.descriptor_cache
+                    .fill_characteristic(characteristic.clone())
+            })
+            .collect()
     }
 
     async fn is_connected(&self) -> Result<bool> {
@@ -232,15 +415,23 @@ impl api::Peripheral for Peripheral {
     }
 
     async fn connect(&self) -> Result<()> {
-        let future = self.with_obj(|_env, obj| JSendFuture::try_from(obj.connect()?))?;
-        let result_ref = future.await?;
-        self.with_obj(|env, _obj| {
-            let result = JPollResult::from_env(env, result_ref.as_obj())?;
-            get_poll_result(env, result).map(|_| {})
-        })
+        self.operation_context
+            .run("connect", self.connect_gatt(false, Transport::Auto))
+            .await
+    }
+
+    async fn connect_with_transport(&self, transport: Transport) -> Result<()> {
+        self.operation_context
+            .run("connect", self.connect_gatt(false, transport))
+            .await
     }
 
     async fn disconnect(&self) -> Result<()> {
+        if let Some(manager) = self.manager.upgrade() {
+            manager
+                .peripheral_events()
+                .set_disconnect_reason(&PeripheralId(self.addr), DisconnectReason::Local);
+        }
         let future = self.with_obj(|_env, obj| JSendFuture::try_from(obj.disconnect()?))?;
         let result_ref = future.await?;
         self.with_obj(|env, _obj| {
@@ -251,64 +442,147 @@ impl api::Peripheral for Peripheral {
 
     /// The set of services we've discovered for this device. This will be empty until
     /// `discover_services` is called.
+    fn rssi_stats(&self) -> Option<RssiStats> {
+        self.operation_context.rssi().stats(&self.id())
+    }
+
     fn services(&self) -> BTreeSet<Service> {
         let guard = self.shared.lock().unwrap();
-        (&guard.services).clone()
+        self.descriptor_cache.fill(guard.services.clone())
     }
 
-    async fn discover_services(&self) -> Result<()> {
-        let future = self.with_obj(|_env, obj| JSendFuture::try_from(obj.discover_services()?))?;
-        let result_ref = future.await?;
-        self.with_obj(|env, _obj| {
-            use std::iter::FromIterator;
+    async fn request_mtu(&self, mtu: u16) -> Result<u16> {
+        self.operation_context
+            .run("request MTU", async {
+                let future = self
+                    .with_obj(|_env, obj| JSendFuture::try_from(obj.request_mtu(mtu as jint)?))?;
+                let result_ref = future.await?;
+                self.with_obj(|env, _obj| {
+                    let result = JPollResult::from_env(env, result_ref.as_obj())?;
+                    let obj = get_poll_result(env, result)?;
+                    Ok(env.call_method(obj, "intValue", "()I", &[])?.i()? as u16)
+                })
+            })
+            .await
+    }
 
-            let result = JPollResult::from_env(env, result_ref.as_obj())?;
-            let obj = get_poll_result(env, result)?;
-            let list = JList::from_env(env, obj)?;
-            let mut peripheral_services = Vec::new();
-            let mut peripheral_characteristics = Vec::new();
-
-            for service in list.iter()? {
-                let service = JBluetoothGattService::from_env(env, service)?;
-                let mut characteristics = BTreeSet::<Characteristic>::new();
-                for characteristic in service.get_characteristics()? {
-                    let mut descriptors = BTreeSet::new();
-                    for descriptor in characteristic.get_descriptors()? {
-                        descriptors.insert(Descriptor {
-                            uuid: descriptor.get_uuid()?,
-                            service_uuid: service.get_uuid()?,
-                            characteristic_uuid: characteristic.get_uuid()?,
-                        });
-                    }
-                    let char = Characteristic {
-                        service_uuid: service.get_uuid()?,
-                        uuid: characteristic.get_uuid()?,
-                        properties: characteristic.get_properties()?,
-                        descriptors: descriptors.clone(),
-                    };
-                    // Only consider the first characteristic of each UUID
-                    // This "should" be unique, but of course it's not enforced
-                    if characteristics
-                        .iter()
-                        .filter(|c| c.service_uuid == char.service_uuid && c.uuid == char.uuid)
-                        .count()
-                        == 0
-                    {
-                        characteristics.insert(char.clone());
-                        peripheral_characteristics.push(char.clone());
-                    }
-                }
-                peripheral_services.push(Service {
-                    uuid: service.get_uuid()?,
-                    primary: service.is_primary()?,
-                    characteristics,
+    async fn refresh_properties(&self) -> Result<Option<PeripheralProperties>> {
+        if self.is_connected().await? {
+            let rssi = self
+                .operation_context
+                .run("read RSSI", async {
+                    let future =
+                        self.with_obj(|_env, obj| JSendFuture::try_from(obj.read_remote_rssi()?))?;
+                    let result_ref = future.await?;
+                    self.with_obj(|env, _obj| {
+                        let result = JPollResult::from_env(env, result_ref.as_obj())?;
+                        let obj = get_poll_result(env, result)?;
+                        Ok(env.call_method(obj, "intValue", "()I", &[])?.i()? as i16)
+                    })
                 })
+                .await?;
+            if let Some(properties) = &mut self.shared.lock().unwrap().properties {
+                properties.rssi = Some(rssi);
             }
-            let mut guard = self.shared.lock().map_err(Into::<Error>::into)?;
-            guard.services = BTreeSet::from_iter(peripheral_services.clone());
-            guard.characteristics = BTreeSet::from_iter(peripheral_characteristics.clone());
-            Ok(())
-        })
+        }
+        self.properties().await
+    }
+
+    async fn resolve_name(&self) -> Result<Option<String>> {
+        let local_name = self
+            .shared
+            .lock()
+            .unwrap()
+            .properties
+            .as_ref()
+            .and_then(|p| p.local_name.clone());
+        if local_name.is_some() {
+            return Ok(local_name);
+        }
+        let name = api::read_device_name(self).await?;
+        if let Some(name) = &name {
+            let properties = self
+                .shared
+                .lock()
+                .unwrap()
+                .properties
+                .as_mut()
+                .map(|properties| {
+                    properties.local_name = Some(name.clone());
+                    properties.clone()
+                });
+            if let Some(manager) = self.manager.upgrade() {
+                if let Some(properties) = properties {
+                    manager.peripheral_events().send_discovery(
+                        &PeripheralId(self.addr),
+                        properties,
+                        false,
+                    );
+                }
+                manager.emit(CentralEvent::DeviceUpdated(PeripheralId(self.addr)));
+            }
+        }
+        Ok(name)
+    }
+
+    async fn request_connection_priority(&self, priority: ConnectionPriority) -> Result<()> {
+        // The values of BluetoothGatt.CONNECTION_PRIORITY_*.
+        let priority = match priority {
+            ConnectionPriority::Balanced => 0,
+            ConnectionPriority::High => 1,
+            ConnectionPriority::LowPower => 2,
+        };
+        self.operation_context
+            .run("request connection priority", async {
+                let future = self.with_obj(|_env, obj| {
+                    JSendFuture::try_from(obj.request_connection_priority(priority)?)
+                })?;
+                let result_ref = future.await?;
+                self.with_obj(|env, _obj| {
+                    let result = JPollResult::from_env(env, result_ref.as_obj())?;
+                    get_poll_result(env, result).map(|_| {})
+                })
+            })
+            .await
+    }
+
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = PeripheralEvent> + Send>>> {
+        let manager = self.manager.upgrade().ok_or(Error::DeviceNotFound)?;
+        Ok(manager
+            .peripheral_events()
+            .subscribe(PeripheralId(self.addr)))
+    }
+
+    async fn link_events(
+        &self,
+        interval: Duration,
+    ) -> Result<Pin<Box<dyn Stream<Item = LinkSample> + Send>>> {
+        Ok(link::samples(
+            self.clone(),
+            self.operation_context.clone(),
+            interval,
+        ))
+    }
+
+    async fn discover_services(&self) -> Result<()> {
+        self.operation_context
+            .run("discover services", async {
+                let future =
+                    self.with_obj(|_env, obj| JSendFuture::try_from(obj.discover_services()?))?;
+                let result_ref = future.await?;
+                self.with_obj(|env, _obj| {
+                    let result = JPollResult::from_env(env, result_ref.as_obj())?;
+                    let obj = get_poll_result(env, result)?;
+                    self.set_services(env, obj)
+                })?;
+                if let Some(manager) = self.manager.upgrade() {
+                    manager
+                        .peripheral_events()
+                        .send(&PeripheralId(self.addr), PeripheralEvent::ServicesResolved);
+                }
+                Ok(())
+            })
+            .await
     }
 
     async fn write(
@@ -317,58 +591,120 @@ impl api::Peripheral for Peripheral {
         data: &[u8],
         write_type: WriteType,
     ) -> Result<()> {
-        let future = self.with_obj(|env, obj| {
-            let uuid = JUuid::new(env, characteristic.uuid)?;
-            let data_obj = jni_utils::arrays::slice_to_byte_array(env, data)?;
-            let write_type = match write_type {
-                WriteType::WithResponse => 2,
-                WriteType::WithoutResponse => 1,
-            };
-            JSendFuture::try_from(obj.write(uuid, data_obj.into(), write_type)?)
-        })?;
-        let result_ref = future.await?;
-        self.with_obj(|env, _obj| {
-            let result = JPollResult::from_env(env, result_ref.as_obj())?;
-            get_poll_result(env, result).map(|_| {})
-        })
+        self.operation_context
+            .run_write("write", characteristic.uuid, data, async {
+                write_type.check_signed(characteristic)?;
+                let future = self.with_obj(|env, obj| {
+                    let uuid = JUuid::new(env, characteristic.uuid)?;
+                    let data_obj = jni_utils::arrays::slice_to_byte_array(env, data)?;
+                    // BluetoothGattCharacteristic's WRITE_TYPE_* constants.
+                    let write_type = match write_type {
+                        WriteType::WithResponse => 2,
+                        WriteType::WithoutResponse => 1,
+                        WriteType::SignedWithoutResponse => 4,
+                    };
+                    JSendFuture::try_from(obj.write(uuid, data_obj.into(), write_type)?)
+                })?;
+                let result_ref = future.await?;
+                self.with_obj(|env, _obj| {
+                    let result = JPollResult::from_env(env, result_ref.as_obj())?;
+                    get_poll_result(env, result).map(|_| {})
+                })
+            })
+            .await
     }
 
     async fn read(&self, characteristic: &Characteristic) -> Result<Vec<u8>> {
-        let future = self.with_obj(|env, obj| {
-            let uuid = JUuid::new(env, characteristic.uuid)?;
-            JSendFuture::try_from(obj.read(uuid)?)
-        })?;
-        let result_ref = future.await?;
-        self.with_obj(|env, _obj| {
-            let result = JPollResult::from_env(env, result_ref.as_obj())?;
-            let bytes = get_poll_result(env, result)?;
-            Ok(byte_array_to_vec(env, bytes.into_inner())?)
-        })
+        self.operation_context
+            .run_read("read", characteristic.uuid, async {
+                let future = self.with_obj(|env, obj| {
+                    let uuid = JUuid::new(env, characteristic.uuid)?;
+                    JSendFuture::try_from(obj.read(uuid)?)
+                })?;
+                let result_ref = future.await?;
+                self.with_obj(|env, _obj| {
+                    let result = JPollResult::from_env(env, result_ref.as_obj())?;
+                    let bytes = get_poll_result(env, result)?;
+                    Ok(byte_array_to_vec(env, bytes.into_inner())?)
+                })
+            })
+            .await
     }
 
     async fn subscribe(&self, characteristic: &Characteristic) -> Result<()> {
-        self.set_characteristic_notification(characteristic, true)
+        self.operation_context
+            .run_on("subscribe", characteristic.uuid, async {
+                let subscription_type = SubscriptionType::preferred(characteristic.properties)
+                    .ok_or_else(|| Error::NotSupported("Can not subscribe to attribute".into()))?;
+                self.set_characteristic_notification(characteristic, Some(subscription_type))
+                    .await
+            })
+            .await
+    }
+
+    async fn subscribe_with(
+        &self,
+        characteristic: &Characteristic,
+        subscription_type: SubscriptionType,
+    ) -> Result<()> {
+        self.operation_context
+            .run_on("subscribe", characteristic.uuid, async {
+                if !characteristic
+                    .properties
+                    .contains(subscription_type.property())
+                {
+                    return Err(Error::NotSupported(format!(
+                        "Characteristic doesn't support {:?}",
+                        subscription_type
+                    )));
+                }
+                self.set_characteristic_notification(characteristic, Some(subscription_type))
+                    .await
+            })
             .await
     }
 
     async fn unsubscribe(&self, characteristic: &Characteristic) -> Result<()> {
-        self.set_characteristic_notification(characteristic, false)
+        self.operation_context
+            .run_on("unsubscribe", characteristic.uuid, async {
+                self.set_characteristic_notification(characteristic, None)
+                    .await
+            })
             .await
     }
 
     async fn notifications(&self) -> Result<Pin<Box<dyn Stream<Item = ValueNotification> + Send>>> {
         use futures::stream::StreamExt;
         let stream = self.with_obj(|_env, obj| JSendStream::try_from(obj.get_notifications()?))?;
+        let shared = self.shared.clone();
+        let id = self.id();
         let stream = stream
-            .map(|item| match item {
-                Ok(item) => {
+            .map(move |item| match item {
+                Ok(item) => spans::callback("notification", Some(&id)).in_scope(|| {
+                    let timestamp = Instant::now();
                     let env = global_jvm().get_env()?;
                     let item = item.as_obj();
                     let characteristic = JBluetoothGattCharacteristic::from_env(&env, item)?;
                     let uuid = characteristic.get_uuid()?;
+                    let service_uuid = characteristic.get_service()?.get_uuid()?;
+                    let handle = characteristic.get_instance_id()?;
                     let value = characteristic.get_value()?;
-                    Ok(ValueNotification { uuid, value })
-                }
+                    let subscription_type = shared
+                        .lock()
+                        .unwrap()
+                        .subscriptions
+                        .get(&uuid)
+                        .copied()
+                        .unwrap_or(SubscriptionType::Notify);
+                    Ok(ValueNotification {
+                        uuid,
+                        service_uuid,
+                        handle: Some(handle),
+                        subscription_type,
+                        value,
+                        timestamp,
+                    })
+                }),
                 Err(err) => Err(err),
             })
             .filter_map(|item| async { item.ok() });
@@ -376,30 +712,46 @@ impl api::Peripheral for Peripheral {
     }
 
     async fn write_descriptor(&self, descriptor: &Descriptor, data: &[u8]) -> Result<()> {
-        let future = self.with_obj(|env, obj| {
-            let characteristic = JUuid::new(env, descriptor.characteristic_uuid)?;
-            let uuid = JUuid::new(env, descriptor.uuid)?;
-            let data_obj = jni_utils::arrays::slice_to_byte_array(env, data)?;
-            JSendFuture::try_from(obj.write_descriptor(characteristic, uuid, data_obj.into())?)
-        })?;
-        let result_ref = future.await?;
-        self.with_obj(|env, _obj| {
-            let result = JPollResult::from_env(env, result_ref.as_obj())?;
-            get_poll_result(env, result).map(|_| {})
-        })
+        self.operation_context
+            .run_write("write descriptor", descriptor.uuid, data, async {
+                let future = self.with_obj(|env, obj| {
+                    let characteristic = JUuid::new(env, descriptor.characteristic_uuid)?;
+                    let uuid = JUuid::new(env, descriptor.uuid)?;
+                    let data_obj = jni_utils::arrays::slice_to_byte_array(env, data)?;
+                    JSendFuture::try_from(obj.write_descriptor(
+                        characteristic,
+                        uuid,
+                        data_obj.into(),
+                    )?)
+                })?;
+                let result_ref = future.await?;
+                self.with_obj::<_, Error>(|env, _obj| {
+                    let result = JPollResult::from_env(env, result_ref.as_obj())?;
+                    get_poll_result(env, result).map(|_| {})
+                })?;
+                self.descriptor_cache.insert(descriptor, data);
+                Ok(())
+            })
+            .await
     }
 
     async fn read_descriptor(&self, descriptor: &Descriptor) -> Result<Vec<u8>> {
-        let future = self.with_obj(|env, obj| {
-            let characteristic = JUuid::new(env, descriptor.characteristic_uuid)?;
-            let uuid = JUuid::new(env, descriptor.uuid)?;
-            JSendFuture::try_from(obj.read_descriptor(characteristic, uuid)?)
-        })?;
-        let result_ref = future.await?;
-        self.with_obj(|env, _obj| {
-            let result = JPollResult::from_env(env, result_ref.as_obj())?;
-            let bytes = get_poll_result(env, result)?;
-            Ok(byte_array_to_vec(env, bytes.into_inner())?)
-        })
+        self.operation_context
+            .run_read("read descriptor", descriptor.uuid, async {
+                let future = self.with_obj(|env, obj| {
+                    let characteristic = JUuid::new(env, descriptor.characteristic_uuid)?;
+                    let uuid = JUuid::new(env, descriptor.uuid)?;
+                    JSendFuture::try_from(obj.read_descriptor(characteristic, uuid)?)
+                })?;
+                let result_ref = future.await?;
+                let value = self.with_obj::<_, Error>(|env, _obj| {
+                    let result = JPollResult::from_env(env, result_ref.as_obj())?;
+                    let bytes = get_poll_result(env, result)?;
+                    Ok(byte_array_to_vec(env, bytes.into_inner())?)
+                })?;
+                self.descriptor_cache.insert(descriptor, &value);
+                Ok(value)
+            })
+            .await
     }
 }
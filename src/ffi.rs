@@ -0,0 +1,784 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! A C interface to btleplug, for applications in other languages such as C, C++, Swift, Kotlin and
+//! Python. It is declared in `include/btleplug.h`, and is built on the [`blocking`](crate::blocking)
+//! API, so every function blocks until the operation finishes.
+//!
+//! To link it into an application, build btleplug as a static or dynamic library with the `ffi`
+//! feature, e.g. `cargo rustc --lib --release --features ffi --crate-type staticlib`.
+//!
+//! ## Conventions
+//!
+//! - Functions which can fail return a [`Status`], and store a description of the error for the
+//!   calling thread which [`btleplug_last_error_message`] returns.
+//! - Managers, adapters, peripherals, lists and subscriptions are opaque handles, each of which
+//!   must be freed with the matching `_free` function. Handles may be used from any thread.
+//! - Strings are NUL-terminated UTF-8. Those returned by btleplug must be freed with
+//!   [`btleplug_string_free`], and byte buffers with [`btleplug_bytes_free`].
+//! - UUIDs are passed as pointers to their 16 bytes, most significant first.
+//! - Callbacks are called on a thread of btleplug's own, with the `user_data` they were registered
+//!   with, until the subscription is freed.
+//!
+//! ## Safety
+//!
+//! Every pointer passed in must be null or valid for the function's use of it, and handles must not
+//! be used after they are freed. A null pointer where one is required is reported as
+//! [`Status::InvalidArgument`].
+
+#![allow(clippy::missing_safety_doc)]
+
+use crate::api::{CentralEvent, CentralState, ScanFilter, WriteType};
+use crate::blocking::{Adapter, Manager, Peripheral};
+use crate::Error;
+use futures::channel::oneshot;
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_void, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+    thread::{self, JoinHandle},
+};
+use uuid::Uuid;
+
+/// The result of a call. The values are part of the C interface, so must not change.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Ok = 0,
+    /// A required pointer was null, or a string wasn't valid UTF-8.
+    InvalidArgument = 1,
+    PermissionDenied = 2,
+    DeviceNotFound = 3,
+    NotConnected = 4,
+    NoSuchCharacteristic = 5,
+    NotSupported = 6,
+    TimedOut = 7,
+    /// The device rejected the operation with an ATT error.
+    Att = 8,
+    /// btleplug panicked. This is a bug.
+    Panic = 9,
+    Other = 10,
+}
+
+impl From<&Error> for Status {
+    fn from(error: &Error) -> Self {
+        match error {
+            Error::PermissionDenied => Status::PermissionDenied,
+            Error::DeviceNotFound => Status::DeviceNotFound,
+            Error::NotConnected => Status::NotConnected,
+            Error::NoSuchCharacteristic => Status::NoSuchCharacteristic,
+            Error::NotSupported(_) => Status::NotSupported,
+            Error::TimedOut { .. } => Status::TimedOut,
+            Error::Att(_) => Status::Att,
+            Error::Uuid(_) | Error::InvalidBDAddr(_) => Status::InvalidArgument,
+            _ => Status::Other,
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message).unwrap_or_else(|_| c"Invalid error message".into());
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Why a call failed.
+enum Failure {
+    /// The named argument was null or invalid.
+    InvalidArgument(&'static str),
+    Error(Error),
+}
+
+impl From<Error> for Failure {
+    fn from(error: Error) -> Self {
+        Failure::Error(error)
+    }
+}
+
+type CallResult<T> = std::result::Result<T, Failure>;
+
+/// Runs the body of a call, turning its result or panic into a status.
+fn call(body: impl FnOnce() -> CallResult<()>) -> Status {
+    let (status, message) = match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(())) => return Status::Ok,
+        Ok(Err(Failure::InvalidArgument(name))) => (
+            Status::InvalidArgument,
+            format!("Invalid argument: {}", name),
+        ),
+        Ok(Err(Failure::Error(error))) => (Status::from(&error), error.to_string()),
+        Err(_) => (Status::Panic, "btleplug panicked".to_string()),
+    };
+    set_last_error(message);
+    status
+}
+
+unsafe fn reference<'a, T>(handle: *const T, name: &'static str) -> CallResult<&'a T> {
+    handle
+        .as_ref()
+        .ok_or_else(|| Failure::InvalidArgument(name))
+}
+
+unsafe fn write_out<T>(out: *mut T, value: T, name: &'static str) -> CallResult<()> {
+    if out.is_null() {
+        return Err(Failure::InvalidArgument(name));
+    }
+    out.write(value);
+    Ok(())
+}
+
+unsafe fn read_uuid(uuid: *const [u8; 16], name: &'static str) -> CallResult<Uuid> {
+    Ok(Uuid::from_bytes(*reference(uuid, name)?))
+}
+
+unsafe fn read_str<'a>(string: *const c_char, name: &'static str) -> CallResult<&'a str> {
+    if string.is_null() {
+        return Err(Failure::InvalidArgument(name));
+    }
+    CStr::from_ptr(string)
+        .to_str()
+        .map_err(|_| Failure::InvalidArgument(name))
+}
+
+fn into_c_string(string: String) -> *mut c_char {
+    // Rust strings can contain NULs, which C strings can't, so those are cut off.
+    let string = match CString::new(string) {
+        Ok(string) => string,
+        Err(error) => {
+            let end = error.nul_position();
+            CString::new(&error.into_vec()[..end]).unwrap()
+        }
+    };
+    string.into_raw()
+}
+
+fn into_handle<T>(value: T) -> *mut T {
+    Box::into_raw(Box::new(value))
+}
+
+unsafe fn free_handle<T>(handle: *mut T) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// The `user_data` given with a callback, which the application has promised can be used on
+/// btleplug's thread.
+struct UserData(*mut c_void);
+
+unsafe impl Send for UserData {}
+
+impl UserData {
+    fn get(&self) -> *mut c_void {
+        self.0
+    }
+}
+
+/// A list of handles returned by btleplug, such as the adapters of a manager.
+#[derive(Debug)]
+pub struct List<T>(Vec<T>);
+
+pub type AdapterList = List<Adapter>;
+pub type PeripheralList = List<Peripheral>;
+
+/// A registered callback, which is called until this is freed.
+#[derive(Debug)]
+pub struct Subscription {
+    /// Dropping this stops the callback thread, without waiting for the next event.
+    stop: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            // Freed from within the callback, the thread finishes once the callback returns.
+            if thread.thread().id() != thread::current().id() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+/// Calls `handle` with each item of `events` on a thread of its own, until the subscription is
+/// dropped.
+fn subscribe<T: Send + 'static>(
+    mut events: crate::blocking::Events<T>,
+    handle: impl Fn(T) + Send + 'static,
+) -> crate::Result<Subscription> {
+    let (stop, mut stopped) = oneshot::channel::<()>();
+    let thread = thread::Builder::new()
+        .name("btleplug-ffi-callback".to_string())
+        .spawn(move || {
+            while let Some(item) = events.next_until(&mut stopped) {
+                handle(item);
+            }
+        })
+        .map_err(|error| Error::Other(Box::new(error)))?;
+    Ok(Subscription {
+        stop: Some(stop),
+        thread: Some(thread),
+    })
+}
+
+/// The kind of a [`Event`]. The values are part of the C interface, so must not change.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    DeviceDiscovered = 0,
+    DeviceUpdated = 1,
+    DeviceConnected = 2,
+    DeviceDisconnected = 3,
+    ManufacturerDataAdvertisement = 4,
+    ServiceDataAdvertisement = 5,
+    ServicesAdvertisement = 6,
+    StateUpdate = 7,
+    ServicesChanged = 8,
+    MtuChanged = 9,
+    DirectedAdvertisement = 10,
+}
+
+/// An event from an adapter, as passed to an [`EventCallback`]. It is only valid during the call.
+#[repr(C)]
+#[derive(Debug)]
+pub struct Event {
+    pub kind: EventKind,
+    /// The ID of the peripheral the event is about, or null for [`EventKind::StateUpdate`].
+    pub id: *const c_char,
+    /// The adapter's state, for [`EventKind::StateUpdate`]: the value of a [`CentralState`].
+    pub state: i32,
+    /// The new MTU, for [`EventKind::MtuChanged`].
+    pub mtu: u16,
+}
+
+pub type EventCallback = extern "C" fn(event: *const Event, user_data: *mut c_void);
+
+/// Called with the service and characteristic UUIDs and the value of each notification. The
+/// pointers are only valid during the call.
+pub type NotificationCallback = extern "C" fn(
+    service_uuid: *const [u8; 16],
+    characteristic_uuid: *const [u8; 16],
+    value: *const u8,
+    length: usize,
+    user_data: *mut c_void,
+);
+
+fn send_event(event: CentralEvent, callback: EventCallback, user_data: &UserData) {
+    let (kind, id, state, mtu) = match event {
+        CentralEvent::DeviceDiscovered(id) => (EventKind::DeviceDiscovered, Some(id), None, 0),
+        CentralEvent::DeviceUpdated(id) => (EventKind::DeviceUpdated, Some(id), None, 0),
+        CentralEvent::DeviceConnected(id) => (EventKind::DeviceConnected, Some(id), None, 0),
+        CentralEvent::DeviceDisconnected(id) => (EventKind::DeviceDisconnected, Some(id), None, 0),
+        CentralEvent::ManufacturerDataAdvertisement { id, .. } => {
+            (EventKind::ManufacturerDataAdvertisement, Some(id), None, 0)
+        }
+        CentralEvent::ServiceDataAdvertisement { id, .. } => {
+            (EventKind::ServiceDataAdvertisement, Some(id), None, 0)
+        }
+        CentralEvent::ServicesAdvertisement { id, .. } => {
+            (EventKind::ServicesAdvertisement, Some(id), None, 0)
+        }
+        CentralEvent::StateUpdate(state) => (EventKind::StateUpdate, None, Some(state), 0),
+        CentralEvent::ServicesChanged(id) => (EventKind::ServicesChanged, Some(id), None, 0),
+        CentralEvent::MtuChanged { id, mtu } => (EventKind::MtuChanged, Some(id), None, mtu),
+        CentralEvent::DirectedAdvertisement(id) => {
+            (EventKind::DirectedAdvertisement, Some(id), None, 0)
+        }
+        // The C interface doesn't scan for Classic devices.
+        CentralEvent::ClassicDeviceDiscovered(_) => return,
+    };
+    let id = id.and_then(|id| CString::new(id.to_string()).ok());
+    let event = Event {
+        kind,
+        id: id.as_ref().map_or(ptr::null(), |id| id.as_ptr()),
+        state: state.unwrap_or(CentralState::Unknown) as i32,
+        mtu,
+    };
+    callback(&event, user_data.get());
+}
+
+/// Returns a description of the last error on the calling thread, or null if there hasn't been
+/// one. The string must be freed with [`btleplug_string_free`].
+#[no_mangle]
+pub extern "C" fn btleplug_last_error_message() -> *mut c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null_mut(), |message| message.clone().into_raw())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_bytes_free(data: *mut u8, length: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, length)));
+    }
+}
+
+/// Creates a manager, which runs the async runtime its adapters and peripherals share.
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_manager_new(out_manager: *mut *mut Manager) -> Status {
+    call(|| write_out(out_manager, into_handle(Manager::new()?), "out_manager"))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_manager_free(manager: *mut Manager) {
+    free_handle(manager)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_manager_adapters(
+    manager: *const Manager,
+    out_adapters: *mut *mut AdapterList,
+) -> Status {
+    call(|| {
+        let adapters = reference(manager, "manager")?.adapters()?;
+        write_out(out_adapters, into_handle(List(adapters)), "out_adapters")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_adapter_list_len(list: *const AdapterList) -> usize {
+    list.as_ref().map_or(0, |list| list.0.len())
+}
+
+/// Returns a new handle to the adapter at `index`, or null if there isn't one. The handle must be
+/// freed with [`btleplug_adapter_free`], and stays valid after the list is freed.
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_adapter_list_get(
+    list: *const AdapterList,
+    index: usize,
+) -> *mut Adapter {
+    list.as_ref()
+        .and_then(|list| list.0.get(index))
+        .map_or(ptr::null_mut(), |adapter| into_handle(adapter.clone()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_adapter_list_free(list: *mut AdapterList) {
+    free_handle(list)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_adapter_free(adapter: *mut Adapter) {
+    free_handle(adapter)
+}
+
+/// Starts scanning for peripherals which advertise any of the `count` services in `services`, or
+/// for all peripherals if `count` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_adapter_start_scan(
+    adapter: *const Adapter,
+    services: *const [u8; 16],
+    count: usize,
+) -> Status {
+    call(|| {
+        let adapter = reference(adapter, "adapter")?;
+        let services = if count == 0 {
+            Vec::new()
+        } else if services.is_null() {
+            return Err(Failure::InvalidArgument("services"));
+        } else {
+            slice::from_raw_parts(services, count)
+                .iter()
+                .map(|uuid| Uuid::from_bytes(*uuid))
+                .collect()
+        };
+        Ok(adapter.start_scan(ScanFilter {
+            services,
+            ..Default::default()
+        })?)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_adapter_stop_scan(adapter: *const Adapter) -> Status {
+    call(|| Ok(reference(adapter, "adapter")?.stop_scan()?))
+}
+
+/// Calls `callback` with each event from the adapter, until the subscription is freed.
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_adapter_on_event(
+    adapter: *const Adapter,
+    callback: Option<EventCallback>,
+    user_data: *mut c_void,
+    out_subscription: *mut *mut Subscription,
+) -> Status {
+    call(|| {
+        let callback = callback.ok_or_else(|| Failure::InvalidArgument("callback"))?;
+        if out_subscription.is_null() {
+            return Err(Failure::InvalidArgument("out_subscription"));
+        }
+        let events = reference(adapter, "adapter")?.events()?;
+        let user_data = UserData(user_data);
+        let subscription = subscribe(events, move |event| send_event(event, callback, &user_data))?;
+        write_out(
+            out_subscription,
+            into_handle(subscription),
+            "out_subscription",
+        )
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_adapter_peripherals(
+    adapter: *const Adapter,
+    out_peripherals: *mut *mut PeripheralList,
+) -> Status {
+    call(|| {
+        let peripherals = reference(adapter, "adapter")?.peripherals()?;
+        write_out(
+            out_peripherals,
+            into_handle(List(peripherals)),
+            "out_peripherals",
+        )
+    })
+}
+
+/// Looks up a peripheral by the ID from [`btleplug_peripheral_id`] or an [`Event`].
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_adapter_peripheral(
+    adapter: *const Adapter,
+    id: *const c_char,
+    out_peripheral: *mut *mut Peripheral,
+) -> Status {
+    call(|| {
+        let adapter = reference(adapter, "adapter")?;
+        let id = read_str(id, "id")?;
+        let peripheral = adapter
+            .peripherals()?
+            .into_iter()
+            .find(|peripheral| peripheral.id().to_string() == id)
+            .ok_or(Error::DeviceNotFound)?;
+        write_out(out_peripheral, into_handle(peripheral), "out_peripheral")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_peripheral_list_len(list: *const PeripheralList) -> usize {
+    list.as_ref().map_or(0, |list| list.0.len())
+}
+
+/// Returns a new handle to the peripheral at `index`, or null if there isn't one. The handle must
+/// be freed with [`btleplug_peripheral_free`], and stays valid after the list is freed.
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_peripheral_list_get(
+    list: *const PeripheralList,
+    index: usize,
+) -> *mut Peripheral {
+    list.as_ref()
+        .and_then(|list| list.0.get(index))
+        .map_or(ptr::null_mut(), |peripheral| {
+            into_handle(peripheral.clone())
+        })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_peripheral_list_free(list: *mut PeripheralList) {
+    free_handle(list)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_peripheral_free(peripheral: *mut Peripheral) {
+    free_handle(peripheral)
+}
+
+/// Returns the peripheral's ID, or null if `peripheral` is null. The string must be freed with
+/// [`btleplug_string_free`].
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_peripheral_id(peripheral: *const Peripheral) -> *mut c_char {
+    peripheral.as_ref().map_or(ptr::null_mut(), |peripheral| {
+        into_c_string(peripheral.id().to_string())
+    })
+}
+
+/// Writes the peripheral's address to `out_address`, most significant byte first. This is all
+/// zeros on platforms which don't expose addresses.
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_peripheral_address(
+    peripheral: *const Peripheral,
+    out_address: *mut [u8; 6],
+) -> Status {
+    call(|| {
+        let address = reference(peripheral, "peripheral")?.address();
+        write_out(out_address, address.into_inner(), "out_address")
+    })
+}
+
+/// Writes the peripheral's advertised name to `out_name`, or null if it hasn't advertised one.
+/// The string must be freed with [`btleplug_string_free`].
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_peripheral_local_name(
+    peripheral: *const Peripheral,
+    out_name: *mut *mut c_char,
+) -> Status {
+    call(|| {
+        let properties = reference(peripheral, "peripheral")?.properties()?;
+        let name = properties
+            .and_then(|properties| properties.local_name)
+            .map_or(ptr::null_mut(), into_c_string);
+        write_out(out_name, name, "out_name")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_peripheral_is_connected(
+    peripheral: *const Peripheral,
+    out_connected: *mut bool,
+) -> Status {
+    call(|| {
+        let connected = reference(peripheral, "peripheral")?.is_connected()?;
+        write_out(out_connected, connected, "out_connected")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_peripheral_connect(peripheral: *const Peripheral) -> Status {
+    call(|| Ok(reference(peripheral, "peripheral")?.connect()?))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_peripheral_disconnect(peripheral: *const Peripheral) -> Status {
+    call(|| Ok(reference(peripheral, "peripheral")?.disconnect()?))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_peripheral_discover_services(
+    peripheral: *const Peripheral,
+) -> Status {
+    call(|| Ok(reference(peripheral, "peripheral")?.discover_services()?))
+}
+
+/// Reads a characteristic, writing a buffer holding its value to `out_value` and its length to
+/// `out_length`. The buffer must be freed with [`btleplug_bytes_free`].
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_peripheral_read(
+    peripheral: *const Peripheral,
+    service_uuid: *const [u8; 16],
+    characteristic_uuid: *const [u8; 16],
+    out_value: *mut *mut u8,
+    out_length: *mut usize,
+) -> Status {
+    call(|| {
+        let peripheral = reference(peripheral, "peripheral")?;
+        if out_value.is_null() || out_length.is_null() {
+            return Err(Failure::InvalidArgument("out_value"));
+        }
+        let value = peripheral.read_by_uuid(
+            read_uuid(service_uuid, "service_uuid")?,
+            read_uuid(characteristic_uuid, "characteristic_uuid")?,
+        )?;
+        out_length.write(value.len());
+        out_value.write(Box::into_raw(value.into_boxed_slice()).cast());
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_peripheral_write(
+    peripheral: *const Peripheral,
+    service_uuid: *const [u8; 16],
+    characteristic_uuid: *const [u8; 16],
+    value: *const u8,
+    length: usize,
+    with_response: bool,
+) -> Status {
+    call(|| {
+        let peripheral = reference(peripheral, "peripheral")?;
+        let value = if length == 0 {
+            &[]
+        } else if value.is_null() {
+            return Err(Failure::InvalidArgument("value"));
+        } else {
+            slice::from_raw_parts(value, length)
+        };
+        let write_type = if with_response {
+            WriteType::WithResponse
+        } else {
+            WriteType::WithoutResponse
+        };
+        Ok(peripheral.write_by_uuid(
+            read_uuid(service_uuid, "service_uuid")?,
+            read_uuid(characteristic_uuid, "characteristic_uuid")?,
+            value,
+            write_type,
+        )?)
+    })
+}
+
+unsafe fn set_subscribed(
+    peripheral: *const Peripheral,
+    service_uuid: *const [u8; 16],
+    characteristic_uuid: *const [u8; 16],
+    subscribed: bool,
+) -> Status {
+    call(|| {
+        let peripheral = reference(peripheral, "peripheral")?;
+        let service_uuid = read_uuid(service_uuid, "service_uuid")?;
+        let uuid = read_uuid(characteristic_uuid, "characteristic_uuid")?;
+        let characteristic = peripheral
+            .characteristics()
+            .into_iter()
+            .find(|c| c.service_uuid == service_uuid && c.uuid == uuid)
+            .ok_or(Error::NoSuchCharacteristic)?;
+        if subscribed {
+            peripheral.subscribe(&characteristic)?;
+        } else {
+            peripheral.unsubscribe(&characteristic)?;
+        }
+        Ok(())
+    })
+}
+
+/// Subscribes to notifications or indications of a characteristic, which are passed to the
+/// callback registered with [`btleplug_peripheral_on_notification`].
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_peripheral_subscribe(
+    peripheral: *const Peripheral,
+    service_uuid: *const [u8; 16],
+    characteristic_uuid: *const [u8; 16],
+) -> Status {
+    set_subscribed(peripheral, service_uuid, characteristic_uuid, true)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_peripheral_unsubscribe(
+    peripheral: *const Peripheral,
+    service_uuid: *const [u8; 16],
+    characteristic_uuid: *const [u8; 16],
+) -> Status {
+    set_subscribed(peripheral, service_uuid, characteristic_uuid, false)
+}
+
+/// Calls `callback` with each notification from the peripheral, until the subscription is freed.
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_peripheral_on_notification(
+    peripheral: *const Peripheral,
+    callback: Option<NotificationCallback>,
+    user_data: *mut c_void,
+    out_subscription: *mut *mut Subscription,
+) -> Status {
+    call(|| {
+        let callback = callback.ok_or_else(|| Failure::InvalidArgument("callback"))?;
+        if out_subscription.is_null() {
+            return Err(Failure::InvalidArgument("out_subscription"));
+        }
+        let notifications = reference(peripheral, "peripheral")?.notifications()?;
+        let user_data = UserData(user_data);
+        let subscription = subscribe(notifications, move |notification| {
+            callback(
+                notification.service_uuid.as_bytes(),
+                notification.uuid.as_bytes(),
+                notification.value.as_ptr(),
+                notification.value.len(),
+                user_data.get(),
+            )
+        })?;
+        write_out(
+            out_subscription,
+            into_handle(subscription),
+            "out_subscription",
+        )
+    })
+}
+
+/// Stops calling a callback. Once this returns, the callback won't be called again, unless this is
+/// called from within the callback itself, in which case it isn't called again once it returns.
+#[no_mangle]
+pub unsafe extern "C" fn btleplug_subscription_free(subscription: *mut Subscription) {
+    free_handle(subscription)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocking::Events;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    fn last_error() -> Option<String> {
+        let message = btleplug_last_error_message();
+        if message.is_null() {
+            return None;
+        }
+        let string = unsafe { CStr::from_ptr(message) }
+            .to_str()
+            .unwrap()
+            .to_owned();
+        unsafe { btleplug_string_free(message) };
+        Some(string)
+    }
+
+    #[test]
+    fn null_arguments() {
+        let status = unsafe { btleplug_adapter_stop_scan(ptr::null()) };
+        assert_eq!(status, Status::InvalidArgument);
+        assert_eq!(last_error().as_deref(), Some("Invalid argument: adapter"));
+        assert!(unsafe { btleplug_peripheral_id(ptr::null()) }.is_null());
+        assert_eq!(unsafe { btleplug_adapter_list_len(ptr::null()) }, 0);
+        unsafe {
+            btleplug_manager_free(ptr::null_mut());
+            btleplug_string_free(ptr::null_mut());
+            btleplug_bytes_free(ptr::null_mut(), 0);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            call(|| Err(Error::NotConnected.into())),
+            Status::NotConnected
+        );
+        assert_eq!(last_error().as_deref(), Some("Not connected"));
+        assert_eq!(call(|| panic!("oops")), Status::Panic);
+        assert_eq!(call(|| Ok(())), Status::Ok);
+    }
+
+    #[test]
+    fn subscription_free() {
+        let events = Events::from_stream(futures::stream::pending::<()>());
+        // Freeing doesn't wait for an event that never comes.
+        drop(subscribe(events, |_| {}).unwrap());
+    }
+
+    #[test]
+    fn subscription_free_from_callback() {
+        let (sender, receiver) = futures::channel::mpsc::unbounded();
+        let subscription = Arc::new(Mutex::new(None));
+        let (called, was_called) = std::sync::mpsc::channel();
+        let events = Events::from_stream(receiver);
+        let freed = subscription.clone();
+        *subscription.lock().unwrap() = Some(
+            subscribe(events, move |()| {
+                freed.lock().unwrap().take();
+                called.send(()).unwrap();
+            })
+            .unwrap(),
+        );
+        sender.unbounded_send(()).unwrap();
+        was_called.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(subscription.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn strings() {
+        let string = into_c_string("a\0b".to_string());
+        assert_eq!(unsafe { CStr::from_ptr(string) }.to_str(), Ok("a"));
+        unsafe { btleplug_string_free(string) };
+    }
+}
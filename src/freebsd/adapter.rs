@@ -0,0 +1,496 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::controller::{Controller, MAX_EVENT};
+use super::peripheral::{Peripheral, PeripheralId};
+use super::sys::Socket;
+use crate::api::{
+    event_filter,
+    observer::{Observer, Tracer},
+    rssi::RssiTracking,
+    spans, AdapterCapabilities, AdapterInfo, AddressType, BDAddr, Central, CentralEvent,
+    CentralState, DataLength, DiscoveryEvent, EventFilter, ScanFilter, ScanOptions,
+};
+use crate::common::adapter_manager::AdapterManager;
+use crate::host::eir::Advertisement;
+use crate::rt::{self, Task};
+use crate::{Error, Result};
+use async_trait::async_trait;
+use futures::stream::Stream;
+use log::debug;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime};
+
+const EV_LE_META: u8 = 0x3e;
+const EV_LE_ADVERTISING_REPORT: u8 = 0x02;
+
+/// Advertisements which can be connected to: ADV_IND and ADV_DIRECT_IND. Scan responses, which
+/// are 0x04, don't say.
+const ADV_IND: u8 = 0x00;
+const ADV_DIRECT_IND: u8 = 0x01;
+const SCAN_RSP: u8 = 0x04;
+
+/// The interval and window to scan with when the options don't give them, in units of 0.625 ms,
+/// which are those Linux discovers with.
+const DEFAULT_SCAN_INTERVAL: u16 = 0x0060;
+const DEFAULT_SCAN_WINDOW: u16 = 0x0030;
+
+/// One report of an LE Advertising Report event.
+#[derive(Debug, PartialEq, Eq)]
+struct Report<'a> {
+    event_type: u8,
+    /// Public or random, as 0 or 1, or 2 or 3 for an identity address the controller resolved.
+    address_type: u8,
+    address: BDAddr,
+    data: &'a [u8],
+    rssi: i8,
+}
+
+/// Parses the reports of an LE Advertising Report event, given as received on a raw HCI socket,
+/// after the packet type. Reports are laid out one after the other, as every controller sends
+/// them.
+fn parse_reports(event: &[u8]) -> Vec<Report<'_>> {
+    let [EV_LE_META, _, EV_LE_ADVERTISING_REPORT, count, reports @ ..] = event else {
+        return Vec::new();
+    };
+    let mut rest = reports;
+    let mut reports = Vec::with_capacity(usize::from(*count));
+    for _ in 0..*count {
+        let [event_type, address_type, address @ .., length] = rest.get(..9).unwrap_or_default()
+        else {
+            break;
+        };
+        let Some((data, [rssi, after @ ..])) = rest[9..].split_at_checked(usize::from(*length))
+        else {
+            break;
+        };
+        let mut address: [u8; 6] = address.try_into().unwrap();
+        address.reverse();
+        reports.push(Report {
+            event_type: *event_type,
+            address_type: *address_type,
+            address: BDAddr::from(address),
+            data,
+            rssi: *rssi as i8,
+        });
+        rest = after;
+    }
+    reports
+}
+
+/// Converts an address type as HCI reports it to one of the API's.
+fn address_type(address_type: u8) -> AddressType {
+    if address_type & 1 == 0 {
+        AddressType::Public
+    } else {
+        AddressType::Random
+    }
+}
+
+/// Converts a scan interval or window to the controller's units of 0.625ms.
+fn scan_units(duration: Duration) -> u16 {
+    (duration.as_micros() / 625).try_into().unwrap_or(u16::MAX)
+}
+
+#[derive(Debug)]
+struct Shared {
+    node: String,
+    address: BDAddr,
+    scan_filter: Mutex<Option<ScanFilter>>,
+    /// Receives the advertising reports while scanning.
+    scanner: Mutex<Option<Task>>,
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        if let Some(scanner) = self.scanner.get_mut().unwrap().take() {
+            scanner.abort();
+        }
+    }
+}
+
+/// Implementation of [api::Central](crate::api::Central), for one HCI node of the netgraph stack.
+#[derive(Clone, Debug)]
+pub struct Adapter {
+    manager: Arc<AdapterManager<Peripheral>>,
+    shared: Arc<Shared>,
+}
+
+impl Adapter {
+    pub(crate) async fn new(node: String, tracer: Tracer) -> Result<Self> {
+        let address = Controller::open(&node)?.read_address().await?;
+        Ok(Adapter {
+            manager: Arc::new(AdapterManager::new(tracer)),
+            shared: Arc::new(Shared {
+                node,
+                address,
+                scan_filter: Mutex::new(None),
+                scanner: Mutex::new(None),
+            }),
+        })
+    }
+
+    /// Returns the peripheral with the given address, creating it if the adapter doesn't know it
+    /// yet, and whether it was created.
+    fn peripheral_for(&self, address: BDAddr) -> (Peripheral, bool) {
+        let id = PeripheralId(address);
+        if let Some(peripheral) = self.manager.peripheral(&id) {
+            return (peripheral, false);
+        }
+        let peripheral = Peripheral::new(
+            address,
+            self.shared.address,
+            Arc::downgrade(&self.manager),
+            self.manager.operation_context().clone(),
+        );
+        self.manager.add_peripheral(peripheral.clone());
+        (peripheral, true)
+    }
+
+    fn device_found(&self, report: &Report) {
+        let advertisement = Advertisement::parse(report.data);
+        let id = PeripheralId(report.address);
+        {
+            let filter = self.shared.scan_filter.lock().unwrap();
+            let Some(filter) = &*filter else {
+                return;
+            };
+            // Match the filter against everything the device has advertised, as the services may
+            // have been in the advertisement and this be the scan response.
+            if !(filter.services.is_empty()
+                || advertisement
+                    .services()
+                    .any(|uuid| filter.services.contains(&uuid))
+                || self
+                    .manager
+                    .peripheral(&id)
+                    .is_some_and(|peripheral| peripheral.advertised_any(&filter.services)))
+            {
+                return;
+            }
+        }
+        let (peripheral, new) = self.peripheral_for(report.address);
+
+        // Devices advertise many times a second, so only copy out of the properties what
+        // something is listening for.
+        let events = self.manager.peripheral_events();
+        let wants_discovery = events.has_discovery_subscribers();
+        let (discovery, manufacturer_data, service_data, services) =
+            peripheral.update_properties(|properties| {
+                advertisement.apply_to(properties);
+                properties.address_type = Some(address_type(report.address_type));
+                // An RSSI of 127 means it isn't available.
+                if report.rssi != 127 {
+                    properties.rssi = Some(i16::from(report.rssi));
+                }
+                if report.event_type != SCAN_RSP {
+                    properties.connectable =
+                        Some(matches!(report.event_type, ADV_IND | ADV_DIRECT_IND));
+                }
+                properties.last_seen = Some(SystemTime::now());
+                (
+                    wants_discovery.then(|| properties.clone()),
+                    advertisement
+                        .has_manufacturer_data()
+                        .then(|| properties.manufacturer_data.clone()),
+                    advertisement
+                        .has_service_data()
+                        .then(|| properties.service_data.clone()),
+                    advertisement
+                        .has_services()
+                        .then(|| properties.services.clone()),
+                )
+            });
+        if report.rssi != 127 {
+            self.manager
+                .operation_context()
+                .rssi()
+                .record(&id, i16::from(report.rssi));
+        }
+        if let Some(properties) = discovery {
+            events.send_discovery(&id, properties, new);
+        }
+        self.manager.emit(if new {
+            CentralEvent::DeviceDiscovered(id.clone())
+        } else {
+            CentralEvent::DeviceUpdated(id.clone())
+        });
+        if let Some(manufacturer_data) = manufacturer_data {
+            self.manager
+                .emit(CentralEvent::ManufacturerDataAdvertisement {
+                    id: id.clone(),
+                    manufacturer_data,
+                });
+        }
+        if let Some(service_data) = service_data {
+            self.manager.emit(CentralEvent::ServiceDataAdvertisement {
+                id: id.clone(),
+                service_data,
+            });
+        }
+        if let Some(services) = services {
+            self.manager
+                .emit(CentralEvent::ServicesAdvertisement { id, services });
+        }
+    }
+}
+
+/// Receives the advertising reports of a scan, until the adapter is dropped or the scan stopped.
+async fn scan(manager: Weak<AdapterManager<Peripheral>>, shared: Weak<Shared>, reports: Socket) {
+    loop {
+        let event = match reports.recv(MAX_EVENT).await {
+            Ok(event) => event,
+            Err(error) => {
+                debug!("Stopped receiving advertising reports: {}", error);
+                return;
+            }
+        };
+        let (Some(manager), Some(shared)) = (manager.upgrade(), shared.upgrade()) else {
+            return;
+        };
+        let adapter = Adapter { manager, shared };
+        for report in parse_reports(event.get(1..).unwrap_or_default()) {
+            let id = PeripheralId(report.address);
+            spans::callback("advertisement", Some(&id)).in_scope(|| adapter.device_found(&report));
+        }
+    }
+}
+
+#[async_trait]
+impl Central for Adapter {
+    type Peripheral = Peripheral;
+
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = CentralEvent> + Send>>> {
+        Ok(self.manager.event_stream())
+    }
+
+    async fn events_filtered(
+        &self,
+        filter: EventFilter,
+    ) -> Result<Pin<Box<dyn Stream<Item = CentralEvent> + Send>>> {
+        let events = self.manager.filtered_event_stream(&filter);
+        Ok(event_filter::by_services(self, events, filter.services))
+    }
+
+    async fn discovery_events(&self) -> Result<Pin<Box<dyn Stream<Item = DiscoveryEvent> + Send>>> {
+        Ok(self.manager.peripheral_events().subscribe_discoveries())
+    }
+
+    async fn start_scan(&self, filter: ScanFilter) -> Result<()> {
+        self.start_scan_with_options(filter, ScanOptions::default())
+            .await
+    }
+
+    /// Scans actively, with the options' interval and window, or else with those Linux uses.
+    async fn start_scan_with_options(
+        &self,
+        filter: ScanFilter,
+        options: ScanOptions,
+    ) -> Result<()> {
+        if self
+            .shared
+            .scan_filter
+            .lock()
+            .unwrap()
+            .replace(filter)
+            .is_some()
+        {
+            return Ok(());
+        }
+        let result = async {
+            let (interval, window) = options
+                .timing()
+                .map(|(interval, window)| (scan_units(interval), scan_units(window)))
+                .unwrap_or((DEFAULT_SCAN_INTERVAL, DEFAULT_SCAN_WINDOW));
+            let controller = Controller::open(&self.shared.node)?;
+            controller.enable_le().await?;
+            // The parameters can't be changed while the controller is scanning.
+            controller.set_scan_enable(false).await?;
+            controller
+                .set_scan_parameters(true, interval, window)
+                .await?;
+            // Listen for reports before the scan starts, so that none are missed.
+            let reports = Socket::hci_raw(&self.shared.node, &[EV_LE_META])?;
+            controller.set_scan_enable(true).await?;
+            let scanner = rt::spawn(scan(
+                Arc::downgrade(&self.manager),
+                Arc::downgrade(&self.shared),
+                reports,
+            ));
+            if let Some(old) = self.shared.scanner.lock().unwrap().replace(scanner) {
+                old.abort();
+            }
+            Ok(())
+        }
+        .await;
+        if result.is_err() {
+            self.shared.scan_filter.lock().unwrap().take();
+        }
+        result
+    }
+
+    async fn stop_scan(&self) -> Result<()> {
+        if self.shared.scan_filter.lock().unwrap().take().is_none() {
+            return Ok(());
+        }
+        if let Some(scanner) = self.shared.scanner.lock().unwrap().take() {
+            scanner.abort();
+        }
+        Controller::open(&self.shared.node)?
+            .set_scan_enable(false)
+            .await
+    }
+
+    async fn peripherals(&self) -> Result<Vec<Peripheral>> {
+        Ok(self.manager.peripherals())
+    }
+
+    async fn peripheral(&self, id: &PeripheralId) -> Result<Peripheral> {
+        self.manager.peripheral(id).ok_or(Error::DeviceNotFound)
+    }
+
+    async fn peripheral_by_address(&self, address: BDAddr) -> Result<Peripheral> {
+        self.peripheral(&PeripheralId(address)).await
+    }
+
+    async fn add_peripheral(&self, id: &PeripheralId) -> Result<Peripheral> {
+        Ok(self.peripheral_for(id.0).0)
+    }
+
+    async fn remove_peripheral(&self, id: &PeripheralId) -> Result<()> {
+        self.manager.disconnect_and_remove(id).await
+    }
+
+    async fn max_data_length(&self) -> Result<DataLength> {
+        Controller::open(&self.shared.node)?.max_data_length().await
+    }
+
+    async fn suggested_data_length(&self) -> Result<DataLength> {
+        Controller::open(&self.shared.node)?
+            .suggested_data_length()
+            .await
+    }
+
+    async fn set_suggested_data_length(&self, length: DataLength) -> Result<()> {
+        Controller::open(&self.shared.node)?
+            .set_suggested_data_length(length)
+            .await
+    }
+
+    async fn adapter_info(&self) -> Result<AdapterInfo> {
+        Ok(AdapterInfo {
+            id: self.shared.node.clone(),
+            description: format!("{} (netgraph)", self.shared.node),
+            address: Some(self.shared.address),
+            capabilities: AdapterCapabilities {
+                // The ATT client only uses the fixed ATT channel.
+                eatt: Some(false),
+                advertisement_monitor: Some(false),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+    }
+
+    /// The adapter is on once its HCI node has been initialized, which `/etc/rc.d/bluetooth` does
+    /// when it's attached.
+    async fn adapter_state(&self) -> Result<CentralState> {
+        Ok(if Controller::open(&self.shared.node)?.is_ready()? {
+            CentralState::PoweredOn
+        } else {
+            CentralState::PoweredOff
+        })
+    }
+
+    fn operation_timeout(&self) -> Option<Duration> {
+        self.manager.operation_context().timeout()
+    }
+
+    fn set_operation_timeout(&self, timeout: Option<Duration>) {
+        self.manager.operation_context().set_timeout(timeout);
+    }
+
+    fn set_observer(&self, observer: Option<Arc<dyn Observer>>) {
+        self.manager.operation_context().set_observer(observer);
+    }
+
+    fn set_peripheral_ttl(&self, ttl: Option<Duration>) {
+        self.manager.operation_context().expiry().set_ttl(ttl);
+    }
+
+    fn set_rssi_tracking(&self, tracking: Option<RssiTracking>) {
+        self.manager
+            .operation_context()
+            .rssi()
+            .set_tracking(tracking);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advertising_reports() {
+        let event = [
+            EV_LE_META,
+            25,
+            EV_LE_ADVERTISING_REPORT,
+            2,
+            // A connectable advertisement from a public address, with the Flags AD structure.
+            ADV_IND,
+            0,
+            0x55,
+            0x44,
+            0x33,
+            0x22,
+            0x11,
+            0x00,
+            3,
+            0x02,
+            0x01,
+            0x06,
+            0xc4,
+            // An empty scan response from a random address, without an RSSI.
+            SCAN_RSP,
+            1,
+            0x01,
+            0x02,
+            0x03,
+            0x04,
+            0x05,
+            0xc6,
+            0,
+            127,
+        ];
+        assert_eq!(
+            parse_reports(&event),
+            vec![
+                Report {
+                    event_type: ADV_IND,
+                    address_type: 0,
+                    address: BDAddr::from([0x00, 0x11, 0x22, 0x33, 0x44, 0x55]),
+                    data: &[0x02, 0x01, 0x06],
+                    rssi: -60,
+                },
+                Report {
+                    event_type: SCAN_RSP,
+                    address_type: 1,
+                    address: BDAddr::from([0xc6, 0x05, 0x04, 0x03, 0x02, 0x01]),
+                    data: &[],
+                    rssi: 127,
+                },
+            ]
+        );
+        assert_eq!(address_type(1), AddressType::Random);
+        assert_eq!(address_type(2), AddressType::Public);
+        // A truncated report is left out.
+        assert_eq!(parse_reports(&event[..20]).len(), 1);
+        assert!(parse_reports(&[EV_LE_META, 1, 0x0a]).is_empty());
+    }
+}
@@ -0,0 +1,193 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! HCI commands sent straight to an adapter's controller over a raw HCI socket. FreeBSD has no
+//! management interface like Linux's, so scanning is done this way too.
+
+use super::sys::Socket;
+use crate::api::{BDAddr, DataLength, NativeError};
+use crate::{Error, Result};
+
+const HCI_COMMAND_PKT: u8 = 0x01;
+const EV_CMD_COMPLETE: u8 = 0x0e;
+const EV_CMD_STATUS: u8 = 0x0f;
+
+const OP_SET_EVENT_MASK: u16 = 0x0c01;
+const OP_WRITE_LE_HOST_SUPPORTED: u16 = 0x0c6d;
+const OP_READ_BD_ADDR: u16 = 0x1009;
+const OP_LE_SET_SCAN_PARAMETERS: u16 = 0x200b;
+const OP_LE_SET_SCAN_ENABLE: u16 = 0x200c;
+const OP_LE_READ_SUGGESTED_DEFAULT_DATA_LENGTH: u16 = 0x2023;
+const OP_LE_WRITE_SUGGESTED_DEFAULT_DATA_LENGTH: u16 = 0x2024;
+const OP_LE_READ_MAXIMUM_DATA_LENGTH: u16 = 0x202f;
+
+/// The events a controller reports after a reset, and LE Meta, which it doesn't report until
+/// asked to, but which the HCI node needs to make LE connections and the scan to find devices.
+const EVENT_MASK: u64 = 0x2000_1fff_ffff_ffff;
+
+const STATUS_SUCCESS: u8 = 0x00;
+const STATUS_UNKNOWN_COMMAND: u8 = 0x01;
+const STATUS_UNKNOWN_CONNECTION: u8 = 0x02;
+const STATUS_COMMAND_DISALLOWED: u8 = 0x0c;
+const STATUS_UNSUPPORTED_FEATURE: u8 = 0x11;
+const STATUS_UNSUPPORTED_REMOTE_FEATURE: u8 = 0x1a;
+
+/// The longest event packet: a type, an event code, a length and up to 255 bytes of parameters.
+pub(crate) const MAX_EVENT: usize = 258;
+
+fn status_error(status: u8) -> Error {
+    match status {
+        STATUS_UNKNOWN_COMMAND | STATUS_UNSUPPORTED_FEATURE => {
+            Error::NotSupported("The adapter doesn't support this".to_string())
+        }
+        STATUS_UNSUPPORTED_REMOTE_FEATURE => {
+            Error::NotSupported("The device doesn't support this".to_string())
+        }
+        STATUS_UNKNOWN_CONNECTION => Error::NotConnected,
+        status => Error::Other(Box::new(NativeError::HciStatus(status))),
+    }
+}
+
+fn parse_data_length(params: &[u8]) -> Result<DataLength> {
+    match params {
+        [octets_low, octets_high, time_low, time_high, ..] => Ok(DataLength {
+            octets: u16::from_le_bytes([*octets_low, *octets_high]),
+            time: u16::from_le_bytes([*time_low, *time_high]),
+        }),
+        _ => Err(Error::RuntimeError("Invalid data length".to_string())),
+    }
+}
+
+/// The controller of one adapter.
+#[derive(Debug)]
+pub(crate) struct Controller {
+    socket: Socket,
+}
+
+impl Controller {
+    pub(crate) fn open(node: &str) -> Result<Self> {
+        Ok(Controller {
+            socket: Socket::hci_raw(node, &[EV_CMD_COMPLETE, EV_CMD_STATUS])?,
+        })
+    }
+
+    /// Returns whether the adapter's HCI node is attached and initialized.
+    pub(crate) fn is_ready(&self) -> Result<bool> {
+        self.socket.node_ready()
+    }
+
+    /// Sends a command and returns the return parameters of its Command Complete event, after the
+    /// status. A command which the controller finishes later is done once it's been accepted.
+    async fn command(&self, opcode: u16, params: &[u8]) -> Result<Vec<u8>> {
+        let mut packet = Vec::with_capacity(4 + params.len());
+        packet.push(HCI_COMMAND_PKT);
+        packet.extend_from_slice(&opcode.to_le_bytes());
+        packet.push(params.len() as u8);
+        packet.extend_from_slice(params);
+        self.socket.send(&packet).await?;
+        loop {
+            // The socket also receives the events of commands which others send.
+            let event = self.socket.recv(MAX_EVENT).await?;
+            let (status, rest) = match event.get(1..) {
+                Some([EV_CMD_COMPLETE, _, _, low, high, status, rest @ ..])
+                    if u16::from_le_bytes([*low, *high]) == opcode =>
+                {
+                    (*status, rest)
+                }
+                Some([EV_CMD_STATUS, _, status, _, low, high, ..])
+                    if u16::from_le_bytes([*low, *high]) == opcode =>
+                {
+                    (*status, &[][..])
+                }
+                _ => continue,
+            };
+            return match status {
+                STATUS_SUCCESS => Ok(rest.to_vec()),
+                status => Err(status_error(status)),
+            };
+        }
+    }
+
+    pub(crate) async fn read_address(&self) -> Result<BDAddr> {
+        let reply = self.command(OP_READ_BD_ADDR, &[]).await?;
+        let mut address: [u8; 6] = reply
+            .get(..6)
+            .and_then(|address| address.try_into().ok())
+            .ok_or_else(|| Error::RuntimeError("Invalid BD_ADDR".to_string()))?;
+        address.reverse();
+        Ok(address.into())
+    }
+
+    /// Has the controller report LE events, and, if it also does BR/EDR, use LE. FreeBSD only
+    /// does this when asked to with `hccontrol le_enable`.
+    pub(crate) async fn enable_le(&self) -> Result<()> {
+        self.command(OP_SET_EVENT_MASK, &EVENT_MASK.to_le_bytes())
+            .await?;
+        match self.command(OP_WRITE_LE_HOST_SUPPORTED, &[1, 0]).await {
+            // LE-only controllers don't have the command.
+            Ok(_) | Err(Error::NotSupported(_)) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sets how the controller scans: actively, asking devices for their scan responses, or
+    /// passively, with the interval and window in units of 0.625 ms.
+    pub(crate) async fn set_scan_parameters(
+        &self,
+        active: bool,
+        interval: u16,
+        window: u16,
+    ) -> Result<()> {
+        let mut params = [0; 7];
+        params[0] = u8::from(active);
+        params[1..3].copy_from_slice(&interval.to_le_bytes());
+        params[3..5].copy_from_slice(&window.to_le_bytes());
+        // The public address, and advertisements from any device.
+        self.command(OP_LE_SET_SCAN_PARAMETERS, &params).await?;
+        Ok(())
+    }
+
+    /// Starts or stops scanning. Every advertisement is reported, rather than the controller
+    /// leaving out those it has seen, so that the RSSI of devices keeps being updated.
+    pub(crate) async fn set_scan_enable(&self, enable: bool) -> Result<()> {
+        match self
+            .command(OP_LE_SET_SCAN_ENABLE, &[u8::from(enable), 0])
+            .await
+        {
+            // The controller was already scanning, or not.
+            Err(e)
+                if e.native_error() == Some(&NativeError::HciStatus(STATUS_COMMAND_DISALLOWED)) =>
+            {
+                Ok(())
+            }
+            result => result.map(|_| ()),
+        }
+    }
+
+    /// Returns the longest data length the controller can send with.
+    pub(crate) async fn max_data_length(&self) -> Result<DataLength> {
+        let reply = self.command(OP_LE_READ_MAXIMUM_DATA_LENGTH, &[]).await?;
+        parse_data_length(&reply)
+    }
+
+    /// Returns the data length the controller suggests for new connections.
+    pub(crate) async fn suggested_data_length(&self) -> Result<DataLength> {
+        let reply = self
+            .command(OP_LE_READ_SUGGESTED_DEFAULT_DATA_LENGTH, &[])
+            .await?;
+        parse_data_length(&reply)
+    }
+
+    pub(crate) async fn set_suggested_data_length(&self, length: DataLength) -> Result<()> {
+        let mut params = [0; 4];
+        params[..2].copy_from_slice(&length.octets.to_le_bytes());
+        params[2..].copy_from_slice(&length.time.to_le_bytes());
+        self.command(OP_LE_WRITE_SUGGESTED_DEFAULT_DATA_LENGTH, &params)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::adapter::Adapter;
+use super::sys::Socket;
+use crate::api::observer::{TraceHandler, Tracer};
+use crate::{api, api::ManagerEvent, Result};
+use async_trait::async_trait;
+use futures::stream::{self, Stream, StreamExt};
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+/// Implementation of [api::Manager], over the HCI nodes of the netgraph Bluetooth stack.
+#[derive(Clone, Debug)]
+pub struct Manager {
+    /// The adapters created so far, by node name, so that each adapter has one set of peripherals.
+    adapters: Arc<Mutex<BTreeMap<String, Adapter>>>,
+    tracer: Tracer,
+}
+
+impl Manager {
+    /// Creates the manager. Scanning sends HCI commands to the adapters, which needs root. It
+    /// must be called within a Tokio runtime with `runtime-tokio`.
+    pub async fn new() -> Result<Self> {
+        Ok(Self {
+            adapters: Arc::new(Mutex::new(BTreeMap::new())),
+            tracer: Tracer::default(),
+        })
+    }
+
+    /// Returns the adapter of the given node, creating it if there isn't one yet.
+    async fn adapter(&self, node: String) -> Result<Adapter> {
+        if let Some(adapter) = self.adapters.lock().unwrap().get(&node) {
+            return Ok(adapter.clone());
+        }
+        let adapter = Adapter::new(node.clone(), self.tracer.clone()).await?;
+        Ok(self
+            .adapters
+            .lock()
+            .unwrap()
+            .entry(node)
+            .or_insert(adapter)
+            .clone())
+    }
+}
+
+#[async_trait]
+impl api::Manager for Manager {
+    type Adapter = Adapter;
+
+    async fn adapters(&self) -> Result<Vec<Adapter>> {
+        let nodes = Socket::hci_nodes()?;
+        self.adapters
+            .lock()
+            .unwrap()
+            .retain(|node, _| nodes.contains(node));
+        let mut adapters = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            adapters.push(self.adapter(node).await?);
+        }
+        Ok(adapters)
+    }
+
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = ManagerEvent<Adapter>> + Send>>> {
+        // Netgraph doesn't report nodes coming and going to sockets, so only the adapters there
+        // are now are reported.
+        let adapters = self.adapters().await?;
+        Ok(Box::pin(
+            stream::iter(adapters.into_iter().map(ManagerEvent::AdapterAdded))
+                .chain(stream::pending()),
+        ))
+    }
+
+    fn set_trace_handler(&self, handler: Option<Arc<dyn TraceHandler>>) {
+        self.tracer.set(handler);
+    }
+}
@@ -0,0 +1,19 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! The backend for FreeBSD, over the Bluetooth sockets of its netgraph stack, `ng_btsocket`.
+//!
+//! Each adapter is an HCI node, such as `ubt0hci`, which `/etc/rc.d/bluetooth` creates and
+//! initializes when the adapter is attached. FreeBSD has no Bluetooth service to scan with, so
+//! scanning sends HCI commands over a raw HCI socket, which needs root. Connections are made by
+//! the HCI node when the ATT channel's L2CAP socket connects. Peripherals aren't paired.
+
+pub mod adapter;
+mod controller;
+pub mod manager;
+pub mod peripheral;
+mod sys;
@@ -0,0 +1,531 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::sys::Socket;
+use crate::api::{
+    self,
+    descriptor_cache::DescriptorCache,
+    link::{self, LinkSample},
+    operation::OperationContext,
+    rssi::RssiStats,
+    spans, AddressType, BDAddr, CentralEvent, Characteristic, Descriptor, DisconnectReason,
+    PeripheralEvent, PeripheralProperties, Service, SubscriptionType, ValueNotification, WriteType,
+    CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
+};
+use crate::common::adapter_manager::AdapterManager;
+use crate::host::{
+    att::{self, HandleValue},
+    gatt,
+};
+use crate::{rt, Error, Result};
+use async_trait::async_trait;
+use futures::channel::mpsc;
+use futures::stream::{Stream, StreamExt};
+use log::trace;
+#[cfg(feature = "serde")]
+use serde_cr::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct PeripheralId(pub(super) BDAddr);
+impl Display for PeripheralId {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl PeripheralId {
+    #[cfg(feature = "mock")]
+    pub(crate) fn synthetic(_adapter_id: &str, address: BDAddr) -> Self {
+        PeripheralId(address)
+    }
+}
+
+#[derive(Default)]
+struct State {
+    /// Copied on write, so that the properties can be cloned without holding the lock.
+    properties: Arc<PeripheralProperties>,
+    client: Option<att::Client>,
+    /// Counts connections, so that the end of an old one isn't taken for the end of the current
+    /// one.
+    connection: u64,
+    services: BTreeSet<Service>,
+}
+
+impl State {
+    fn characteristic(&self, characteristic: &Characteristic) -> Result<Characteristic> {
+        self.services
+            .iter()
+            .filter(|service| service.uuid == characteristic.service_uuid)
+            .flat_map(|service| service.characteristics.iter())
+            .find(|c| {
+                c.uuid == characteristic.uuid
+                    && (characteristic.value_handle.is_none()
+                        || c.value_handle == characteristic.value_handle)
+            })
+            .cloned()
+            .ok_or(Error::NoSuchCharacteristic)
+    }
+
+    fn descriptor_handle(&self, descriptor: &Descriptor) -> Result<u16> {
+        self.services
+            .iter()
+            .filter(|service| service.uuid == descriptor.service_uuid)
+            .flat_map(|service| service.characteristics.iter())
+            .filter(|characteristic| characteristic.uuid == descriptor.characteristic_uuid)
+            .flat_map(|characteristic| characteristic.descriptors.iter())
+            .find(|d| {
+                d.uuid == descriptor.uuid
+                    && (descriptor.handle.is_none() || d.handle == descriptor.handle)
+            })
+            .and_then(|d| d.handle)
+            .ok_or_else(|| Error::Other("Descriptor not found".into()))
+    }
+}
+
+struct Shared {
+    id: PeripheralId,
+    /// The address of the adapter to connect from.
+    adapter_address: BDAddr,
+    state: Mutex<State>,
+    descriptor_cache: DescriptorCache,
+    /// Held while connecting, so that connecting twice at once makes one connection.
+    connecting: tokio::sync::Mutex<()>,
+    notifications_channel: broadcast::Sender<ValueNotification>,
+    manager: Weak<AdapterManager<Peripheral>>,
+    operation_context: OperationContext,
+}
+
+/// Implementation of [api::Peripheral].
+#[derive(Clone)]
+pub struct Peripheral {
+    shared: Arc<Shared>,
+}
+
+impl Peripheral {
+    pub(crate) fn new(
+        address: BDAddr,
+        adapter_address: BDAddr,
+        manager: Weak<AdapterManager<Peripheral>>,
+        operation_context: OperationContext,
+    ) -> Self {
+        let (notifications_channel, _) = broadcast::channel(16);
+        let id = PeripheralId(address);
+        let operation_context = operation_context.for_peripheral(id.clone());
+        Peripheral {
+            shared: Arc::new(Shared {
+                id,
+                adapter_address,
+                state: Mutex::new(State {
+                    properties: Arc::new(PeripheralProperties {
+                        address,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                descriptor_cache: DescriptorCache::default(),
+                connecting: tokio::sync::Mutex::new(()),
+                notifications_channel,
+                manager,
+                operation_context,
+            }),
+        }
+    }
+
+    /// The type of the peripheral's address, as it last advertised, or public if it hasn't.
+    fn address_type(&self) -> AddressType {
+        self.shared
+            .state
+            .lock()
+            .unwrap()
+            .properties
+            .address_type
+            .unwrap_or_default()
+    }
+
+    /// Returns whether the peripheral has advertised any of the services.
+    pub(crate) fn advertised_any(&self, services: &[Uuid]) -> bool {
+        let state = self.shared.state.lock().unwrap();
+        services
+            .iter()
+            .any(|uuid| state.properties.services.contains(uuid))
+    }
+
+    /// Updates the peripheral's properties, as an advertising report was received from it, and
+    /// returns whatever `update` copies out of them.
+    pub(crate) fn update_properties<T>(
+        &self,
+        update: impl FnOnce(&mut PeripheralProperties) -> T,
+    ) -> T {
+        let mut state = self.shared.state.lock().unwrap();
+        update(Arc::make_mut(&mut state.properties))
+    }
+
+    fn emit(&self, event: CentralEvent) {
+        if let Some(manager) = self.shared.manager.upgrade() {
+            manager.emit(event);
+        }
+    }
+
+    fn client(&self) -> Result<att::Client> {
+        self.shared
+            .state
+            .lock()
+            .unwrap()
+            .client
+            .clone()
+            .ok_or(Error::NotConnected)
+    }
+
+    fn characteristic(&self, characteristic: &Characteristic) -> Result<Characteristic> {
+        self.shared
+            .state
+            .lock()
+            .unwrap()
+            .characteristic(characteristic)
+    }
+
+    fn value_handle(&self, characteristic: &Characteristic) -> Result<u16> {
+        self.characteristic(characteristic)?
+            .value_handle
+            .ok_or(Error::NoSuchCharacteristic)
+    }
+
+    fn descriptor_handle(&self, descriptor: &Descriptor) -> Result<u16> {
+        self.shared
+            .state
+            .lock()
+            .unwrap()
+            .descriptor_handle(descriptor)
+    }
+
+    /// Writes the characteristic's Client Characteristic Configuration Descriptor.
+    async fn write_client_configuration(
+        &self,
+        characteristic: &Characteristic,
+        value: u16,
+    ) -> Result<()> {
+        let client = self.client()?;
+        let handle = self
+            .characteristic(characteristic)?
+            .descriptors
+            .iter()
+            .find(|descriptor| descriptor.uuid == CLIENT_CHARACTERISTIC_CONFIGURATION_UUID)
+            .and_then(|descriptor| descriptor.handle)
+            .ok_or_else(|| {
+                Error::NotSupported(
+                    "Characteristic has no client characteristic configuration descriptor".into(),
+                )
+            })?;
+        client.write(handle, &value.to_le_bytes()).await
+    }
+}
+
+/// Delivers the notifications received on a connection, then reports the peripheral as
+/// disconnected once the connection is closed.
+async fn receive_notifications(
+    shared: Weak<Shared>,
+    connection: u64,
+    mut notifications: mpsc::UnboundedReceiver<HandleValue>,
+) {
+    while let Some(notification) = notifications.next().await {
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+        spans::callback("notification", Some(&shared.id)).in_scope(|| {
+            let characteristic = shared
+                .state
+                .lock()
+                .unwrap()
+                .services
+                .iter()
+                .flat_map(|service| service.characteristics.iter())
+                .find(|c| c.value_handle == Some(notification.handle))
+                .map(|c| (c.uuid, c.service_uuid));
+            let Some((uuid, service_uuid)) = characteristic else {
+                trace!(
+                    "Notification for unknown handle {:#06x}",
+                    notification.handle
+                );
+                return;
+            };
+            let _ = shared.notifications_channel.send(ValueNotification {
+                uuid,
+                service_uuid,
+                handle: Some(notification.handle),
+                subscription_type: notification.subscription_type,
+                value: notification.value,
+                timestamp: Instant::now(),
+            });
+        });
+    }
+    let Some(shared) = shared.upgrade() else {
+        return;
+    };
+    let peripheral = Peripheral { shared };
+    {
+        let mut state = peripheral.shared.state.lock().unwrap();
+        if state.connection == connection {
+            state.client = None;
+        }
+    }
+    peripheral.emit(CentralEvent::DeviceDisconnected(
+        peripheral.shared.id.clone(),
+    ));
+}
+
+impl Debug for Peripheral {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Peripheral")
+            .field("id", &self.shared.id)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl api::Peripheral for Peripheral {
+    fn id(&self) -> PeripheralId {
+        self.shared.id.clone()
+    }
+
+    fn address(&self) -> BDAddr {
+        self.shared.id.0
+    }
+
+    async fn properties(&self) -> Result<Option<PeripheralProperties>> {
+        let properties = self.shared.state.lock().unwrap().properties.clone();
+        Ok(Some(Arc::unwrap_or_clone(properties)))
+    }
+
+    async fn resolve_name(&self) -> Result<Option<String>> {
+        let local_name = self
+            .shared
+            .state
+            .lock()
+            .unwrap()
+            .properties
+            .local_name
+            .clone();
+        if local_name.is_some() {
+            return Ok(local_name);
+        }
+        let name = api::read_device_name(self).await?;
+        if let Some(name) = &name {
+            let properties = {
+                let mut state = self.shared.state.lock().unwrap();
+                Arc::make_mut(&mut state.properties).local_name = Some(name.clone());
+                state.properties.clone()
+            };
+            if let Some(manager) = self.shared.manager.upgrade() {
+                manager.peripheral_events().send_discovery(
+                    &self.shared.id,
+                    Arc::unwrap_or_clone(properties),
+                    false,
+                );
+            }
+            self.emit(CentralEvent::DeviceUpdated(self.id()));
+        }
+        Ok(name)
+    }
+
+    fn rssi_stats(&self) -> Option<RssiStats> {
+        self.shared.operation_context.rssi().stats(&self.id())
+    }
+
+    fn services(&self) -> BTreeSet<Service> {
+        let services = self.shared.state.lock().unwrap().services.clone();
+        self.shared.descriptor_cache.fill(services)
+    }
+
+    async fn is_connected(&self) -> Result<bool> {
+        Ok(self.shared.state.lock().unwrap().client.is_some())
+    }
+
+    async fn connect(&self) -> Result<()> {
+        self.shared
+            .operation_context
+            .run("connect", async {
+                let _connecting = self.shared.connecting.lock().await;
+                if self.client().is_ok() {
+                    return Ok(());
+                }
+                let address_type = self.address_type();
+                let socket = Socket::l2cap_le(
+                    self.shared.adapter_address,
+                    self.shared.id.0,
+                    address_type.num(),
+                )
+                .await?;
+                let (client, notifications) = att::Client::new(Arc::new(socket));
+                client.exchange_mtu().await?;
+                let connection = {
+                    let mut state = self.shared.state.lock().unwrap();
+                    state.connection += 1;
+                    state.client = Some(client);
+                    state.connection
+                };
+                rt::spawn(receive_notifications(
+                    Arc::downgrade(&self.shared),
+                    connection,
+                    notifications,
+                ));
+                self.emit(CentralEvent::DeviceConnected(self.shared.id.clone()));
+                Ok(())
+            })
+            .await
+    }
+
+    async fn disconnect(&self) -> Result<()> {
+        // The peripheral is reported as disconnected once the connection has closed.
+        if let Some(client) = self.shared.state.lock().unwrap().client.take() {
+            if let Some(manager) = self.shared.manager.upgrade() {
+                manager
+                    .peripheral_events()
+                    .set_disconnect_reason(&self.shared.id, DisconnectReason::Local);
+            }
+            client.close();
+        }
+        Ok(())
+    }
+
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = PeripheralEvent> + Send>>> {
+        let manager = self.shared.manager.upgrade().ok_or(Error::DeviceNotFound)?;
+        Ok(manager
+            .peripheral_events()
+            .subscribe(self.shared.id.clone()))
+    }
+
+    async fn link_events(
+        &self,
+        interval: Duration,
+    ) -> Result<Pin<Box<dyn Stream<Item = LinkSample> + Send>>> {
+        Ok(link::samples(
+            self.clone(),
+            self.shared.operation_context.clone(),
+            interval,
+        ))
+    }
+
+    async fn discover_services(&self) -> Result<()> {
+        self.shared
+            .operation_context
+            .run("discover services", async {
+                let services = gatt::discover(&self.client()?).await?;
+                self.shared.state.lock().unwrap().services = services;
+                if let Some(manager) = self.shared.manager.upgrade() {
+                    manager
+                        .peripheral_events()
+                        .send(&self.shared.id, PeripheralEvent::ServicesResolved);
+                }
+                Ok(())
+            })
+            .await
+    }
+
+    async fn write(
+        &self,
+        characteristic: &Characteristic,
+        data: &[u8],
+        write_type: WriteType,
+    ) -> Result<()> {
+        self.shared
+            .operation_context
+            .run_write("write", characteristic.uuid, data, async {
+                let client = self.client()?;
+                let handle = self.value_handle(characteristic)?;
+                match write_type {
+                    WriteType::WithResponse => client.write(handle, data).await,
+                    WriteType::WithoutResponse => client.write_command(handle, data).await,
+                    // Signing needs the CSRK from bonding, which this backend doesn't do.
+                    WriteType::SignedWithoutResponse => Err(Error::NotSupported(
+                        "Signed writes aren't supported on this platform".into(),
+                    )),
+                }
+            })
+            .await
+    }
+
+    async fn read(&self, characteristic: &Characteristic) -> Result<Vec<u8>> {
+        self.shared
+            .operation_context
+            .run_read("read", characteristic.uuid, async {
+                let client = self.client()?;
+                client.read(self.value_handle(characteristic)?).await
+            })
+            .await
+    }
+
+    async fn subscribe(&self, characteristic: &Characteristic) -> Result<()> {
+        self.shared
+            .operation_context
+            .run_on("subscribe", characteristic.uuid, async {
+                let properties = self.characteristic(characteristic)?.properties;
+                let value = match SubscriptionType::preferred(properties) {
+                    Some(SubscriptionType::Notify) => 0x0001,
+                    Some(SubscriptionType::Indicate) => 0x0002,
+                    None => {
+                        return Err(Error::NotSupported(
+                            "Characteristic doesn't support notify or indicate".to_string(),
+                        ))
+                    }
+                };
+                self.write_client_configuration(characteristic, value).await
+            })
+            .await
+    }
+
+    async fn unsubscribe(&self, characteristic: &Characteristic) -> Result<()> {
+        self.shared
+            .operation_context
+            .run_on("unsubscribe", characteristic.uuid, async {
+                self.write_client_configuration(characteristic, 0).await
+            })
+            .await
+    }
+
+    async fn notifications(&self) -> Result<Pin<Box<dyn Stream<Item = ValueNotification> + Send>>> {
+        let receiver = self.shared.notifications_channel.subscribe();
+        Ok(self.shared.operation_context.notifications_stream(receiver))
+    }
+
+    async fn write_descriptor(&self, descriptor: &Descriptor, data: &[u8]) -> Result<()> {
+        self.shared
+            .operation_context
+            .run_write("write descriptor", descriptor.uuid, data, async {
+                let client = self.client()?;
+                client
+                    .write(self.descriptor_handle(descriptor)?, data)
+                    .await?;
+                self.shared.descriptor_cache.insert(descriptor, data);
+                Ok(())
+            })
+            .await
+    }
+
+    async fn read_descriptor(&self, descriptor: &Descriptor) -> Result<Vec<u8>> {
+        self.shared
+            .operation_context
+            .run_read("read descriptor", descriptor.uuid, async {
+                let client = self.client()?;
+                let value = client.read(self.descriptor_handle(descriptor)?).await?;
+                self.shared.descriptor_cache.insert(descriptor, &value);
+                Ok(value)
+            })
+            .await
+    }
+}
@@ -0,0 +1,399 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! The Bluetooth sockets of FreeBSD's netgraph stack, from `ng_btsocket.h`, which libc doesn't
+//! have bindings for.
+
+use crate::api::BDAddr;
+use crate::host::att::{Bearer, MAX_MTU};
+use crate::{rt, Error, Result};
+use async_trait::async_trait;
+use std::ffi::CStr;
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use tokio::io::unix::AsyncFd;
+
+const BLUETOOTH_PROTO_HCI: libc::c_int = 134;
+const BLUETOOTH_PROTO_L2CAP: libc::c_int = 135;
+const SOL_HCI_RAW: libc::c_int = 0x0802;
+const SO_HCI_RAW_FILTER: libc::c_int = 1;
+const HCI_EVENT_PKT: u8 = 0x04;
+/// The fixed L2CAP channel of the Attribute Protocol on LE links.
+const ATT_CID: u16 = 4;
+const BDADDR_LE_PUBLIC: u8 = 1;
+/// The length of a netgraph node's name or type, with its terminating NUL.
+const NG_NODESIZ: usize = 32;
+/// The most HCI nodes listed. Each USB adapter has one, as do those attached with `ng_h4`.
+const MAX_NODES: usize = 32;
+/// The state of an HCI node which has been connected to its driver and initialized, which
+/// `/etc/rc.d/bluetooth` does when the adapter is attached.
+const NG_HCI_UNIT_READY: u16 = 0x03;
+
+/// `_IOWR('b', number, length)`, for the ioctls of raw HCI sockets.
+const fn iowr(number: u8, length: usize) -> libc::c_ulong {
+    const IOC_INOUT: libc::c_ulong = 0xc000_0000;
+    const IOCPARM_MASK: usize = 0x1fff;
+    IOC_INOUT
+        | (((length & IOCPARM_MASK) as libc::c_ulong) << 16)
+        | ((b'b' as libc::c_ulong) << 8)
+        | number as libc::c_ulong
+}
+
+/// `SIOC_HCI_RAW_NODE_GET_STATE`, for `NGM_HCI_NODE_GET_STATE`.
+const SIOC_HCI_RAW_NODE_GET_STATE: libc::c_ulong = iowr(100, mem::size_of::<u16>());
+/// `SIOC_HCI_RAW_NODE_LIST_NAMES`, for `NGM_HCI_NODE_LIST_NAMES`.
+const SIOC_HCI_RAW_NODE_LIST_NAMES: libc::c_ulong = iowr(200, mem::size_of::<NodeListNames>());
+
+#[repr(C)]
+struct SockaddrHci {
+    hci_len: u8,
+    hci_family: u8,
+    hci_node: [libc::c_char; NG_NODESIZ],
+}
+
+impl SockaddrHci {
+    fn new(node: &str) -> Result<Self> {
+        let mut hci_node = [0; NG_NODESIZ];
+        if node.len() >= NG_NODESIZ || node.contains('\0') {
+            return Err(Error::DeviceNotFound);
+        }
+        for (c, &byte) in hci_node.iter_mut().zip(node.as_bytes()) {
+            *c = byte as libc::c_char;
+        }
+        Ok(SockaddrHci {
+            hci_len: mem::size_of::<SockaddrHci>() as u8,
+            hci_family: libc::AF_BLUETOOTH as u8,
+            hci_node,
+        })
+    }
+}
+
+/// The number of `bitstr_t` words in a bit string of the given length.
+const fn words(bits: usize) -> usize {
+    bits.div_ceil(libc::c_ulong::BITS as usize)
+}
+
+/// Which packets a raw HCI socket receives, as `struct ng_btsocket_hci_raw_filter`. Each is a bit
+/// string indexed by the packet type or event code less one.
+#[repr(C)]
+struct HciRawFilter {
+    packet_mask: [libc::c_ulong; words(32)],
+    event_mask: [libc::c_ulong; words(64)],
+}
+
+fn set_bit(bits: &mut [libc::c_ulong], bit: u8) {
+    let bit = usize::from(bit) - 1;
+    let word_bits = libc::c_ulong::BITS as usize;
+    bits[bit / word_bits] |= 1 << (bit % word_bits);
+}
+
+/// `struct nodeinfo`, which describes a netgraph node.
+#[repr(C)]
+struct NodeInfo {
+    name: [libc::c_char; NG_NODESIZ],
+    node_type: [libc::c_char; NG_NODESIZ],
+    id: u32,
+    hooks: u32,
+}
+
+/// `struct ng_btsocket_hci_raw_node_list_names`.
+#[repr(C)]
+struct NodeListNames {
+    num_names: u32,
+    names: *mut NodeInfo,
+}
+
+#[repr(C)]
+struct SockaddrL2cap {
+    l2cap_len: u8,
+    l2cap_family: u8,
+    l2cap_psm: u16,
+    /// The address in little-endian byte order, i.e. the reverse of how it is written.
+    l2cap_bdaddr: [u8; 6],
+    l2cap_cid: u16,
+    l2cap_bdaddr_type: u8,
+}
+
+impl SockaddrL2cap {
+    fn le(address: BDAddr, address_type: u8) -> Self {
+        let mut l2cap_bdaddr = address.into_inner();
+        l2cap_bdaddr.reverse();
+        SockaddrL2cap {
+            l2cap_len: mem::size_of::<SockaddrL2cap>() as u8,
+            l2cap_family: libc::AF_BLUETOOTH as u8,
+            l2cap_psm: 0,
+            l2cap_bdaddr,
+            l2cap_cid: ATT_CID.to_le(),
+            l2cap_bdaddr_type: address_type,
+        }
+    }
+}
+
+/// Converts an error from a socket call, keeping the ones applications handle recognisable.
+pub(crate) fn io_error(error: io::Error) -> Error {
+    match error.raw_os_error() {
+        Some(
+            libc::ENOTCONN | libc::ECONNRESET | libc::EPIPE | libc::ESHUTDOWN | libc::ECONNABORTED,
+        ) => Error::NotConnected,
+        Some(libc::EACCES | libc::EPERM) => Error::PermissionDenied,
+        // The node named in the address doesn't exist.
+        Some(libc::EHOSTUNREACH | libc::ENETDOWN) => Error::DeviceNotFound,
+        _ => Error::Other(error.into()),
+    }
+}
+
+fn check(result: libc::c_int) -> Result<libc::c_int> {
+    if result < 0 {
+        Err(io_error(io::Error::last_os_error()))
+    } else {
+        Ok(result)
+    }
+}
+
+/// A non-blocking Bluetooth socket registered with the Tokio reactor.
+#[derive(Debug)]
+pub(crate) struct Socket(AsyncFd<OwnedFd>);
+
+impl Socket {
+    fn new(kind: libc::c_int, protocol: libc::c_int) -> Result<Self> {
+        // SAFETY: socket has no memory-safety preconditions, and on success returns a descriptor
+        // which nothing else owns.
+        let fd = check(unsafe {
+            libc::socket(
+                libc::PF_BLUETOOTH,
+                kind | libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK,
+                protocol,
+            )
+        })?;
+        // SAFETY: as above, the descriptor was just created and is owned by nothing else.
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let _context = rt::enter_io();
+        Ok(Socket(AsyncFd::new(fd).map_err(io_error)?))
+    }
+
+    fn bind<T>(&self, address: &T) -> Result<()> {
+        // SAFETY: the address is a valid sockaddr of the given length.
+        check(unsafe {
+            libc::bind(
+                self.0.as_raw_fd(),
+                address as *const T as *const libc::sockaddr,
+                mem::size_of::<T>() as libc::socklen_t,
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Returns the names of the HCI nodes, one for each adapter, such as `ubt0hci`.
+    pub(crate) fn hci_nodes() -> Result<Vec<String>> {
+        let socket = Self::new(libc::SOCK_RAW, BLUETOOTH_PROTO_HCI)?;
+        // SAFETY: NodeInfo is plain old data, for which all zeroes is valid.
+        let mut nodes: Vec<NodeInfo> = (0..MAX_NODES).map(|_| unsafe { mem::zeroed() }).collect();
+        let mut request = NodeListNames {
+            num_names: MAX_NODES as u32,
+            names: nodes.as_mut_ptr(),
+        };
+        // SAFETY: the argument is a node list pointing to room for the given number of nodes.
+        check(unsafe {
+            libc::ioctl(
+                socket.0.as_raw_fd(),
+                SIOC_HCI_RAW_NODE_LIST_NAMES,
+                &mut request as *mut NodeListNames,
+            )
+        })?;
+        nodes.truncate(request.num_names as usize);
+        Ok(nodes
+            .iter()
+            .filter_map(|node| {
+                // SAFETY: the kernel NUL-terminates the names, and the array was zeroed.
+                let name = unsafe { CStr::from_ptr(node.name.as_ptr()) };
+                name.to_str().ok().map(str::to_string)
+            })
+            .filter(|name| !name.is_empty())
+            .collect())
+    }
+
+    /// Opens a raw HCI socket on the HCI node with the given name, which receives the HCI events
+    /// with the given codes. Sending most commands on it needs root.
+    pub(crate) fn hci_raw(node: &str, events: &[u8]) -> Result<Self> {
+        let socket = Self::new(libc::SOCK_RAW, BLUETOOTH_PROTO_HCI)?;
+        let mut filter = HciRawFilter {
+            packet_mask: [0; words(32)],
+            event_mask: [0; words(64)],
+        };
+        set_bit(&mut filter.packet_mask, HCI_EVENT_PKT);
+        for &event in events {
+            set_bit(&mut filter.event_mask, event);
+        }
+        // SAFETY: the option value is an ng_btsocket_hci_raw_filter of the given length.
+        check(unsafe {
+            libc::setsockopt(
+                socket.0.as_raw_fd(),
+                SOL_HCI_RAW,
+                SO_HCI_RAW_FILTER,
+                &filter as *const HciRawFilter as *const libc::c_void,
+                mem::size_of::<HciRawFilter>() as libc::socklen_t,
+            )
+        })?;
+        let address = SockaddrHci::new(node)?;
+        socket.bind(&address)?;
+        // Connecting makes the node the destination of what is sent.
+        // SAFETY: the address is a valid sockaddr_hci.
+        check(unsafe {
+            libc::connect(
+                socket.0.as_raw_fd(),
+                &address as *const SockaddrHci as *const libc::sockaddr,
+                mem::size_of::<SockaddrHci>() as libc::socklen_t,
+            )
+        })?;
+        Ok(socket)
+    }
+
+    /// Returns whether the HCI node a raw HCI socket is bound to is attached to its adapter and
+    /// initialized.
+    pub(crate) fn node_ready(&self) -> Result<bool> {
+        let mut state: u16 = 0;
+        // SAFETY: the argument is an ng_btsocket_hci_raw_node_state, which is one u16.
+        check(unsafe {
+            libc::ioctl(
+                self.0.as_raw_fd(),
+                SIOC_HCI_RAW_NODE_GET_STATE,
+                &mut state as *mut u16,
+            )
+        })?;
+        Ok(state & NG_HCI_UNIT_READY == NG_HCI_UNIT_READY)
+    }
+
+    /// Connects the ATT channel of the LE device with the given address and address type
+    /// (1 for public, 2 for random), from the adapter with address `source`. The HCI node creates
+    /// the LE connection.
+    pub(crate) async fn l2cap_le(
+        source: BDAddr,
+        address: BDAddr,
+        address_type: u8,
+    ) -> Result<Self> {
+        let socket = Self::new(libc::SOCK_SEQPACKET, BLUETOOTH_PROTO_L2CAP)?;
+        socket.bind(&SockaddrL2cap::le(source, BDADDR_LE_PUBLIC))?;
+        let destination = SockaddrL2cap::le(address, address_type);
+        // SAFETY: the address is a valid sockaddr_l2cap.
+        let result = unsafe {
+            libc::connect(
+                socket.0.as_raw_fd(),
+                &destination as *const SockaddrL2cap as *const libc::sockaddr,
+                mem::size_of::<SockaddrL2cap>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            let error = io::Error::last_os_error();
+            if error.raw_os_error() != Some(libc::EINPROGRESS) {
+                return Err(io_error(error));
+            }
+            // The connection is made once the socket becomes writable, and whether it failed is
+            // then reported by SO_ERROR.
+            let mut guard = socket.0.writable().await.map_err(io_error)?;
+            guard.retain_ready();
+            let mut error: libc::c_int = 0;
+            let mut length = mem::size_of::<libc::c_int>() as libc::socklen_t;
+            // SAFETY: the option value is a c_int of the given length.
+            check(unsafe {
+                libc::getsockopt(
+                    socket.0.as_raw_fd(),
+                    libc::SOL_SOCKET,
+                    libc::SO_ERROR,
+                    &mut error as *mut libc::c_int as *mut libc::c_void,
+                    &mut length,
+                )
+            })?;
+            if error != 0 {
+                return Err(io_error(io::Error::from_raw_os_error(error)));
+            }
+        }
+        Ok(socket)
+    }
+
+    pub(crate) async fn send(&self, packet: &[u8]) -> Result<()> {
+        loop {
+            let mut guard = self.0.writable().await.map_err(io_error)?;
+            match guard.try_io(|fd| {
+                // SAFETY: the buffer is valid for reads of its length.
+                let sent = unsafe {
+                    libc::send(
+                        fd.as_raw_fd(),
+                        packet.as_ptr() as *const libc::c_void,
+                        packet.len(),
+                        libc::MSG_NOSIGNAL,
+                    )
+                };
+                if sent < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(())
+                }
+            }) {
+                Ok(result) => return result.map_err(io_error),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Receives one packet of at most `capacity` bytes. The peer closing the connection is
+    /// reported as [`Error::NotConnected`].
+    pub(crate) async fn recv(&self, capacity: usize) -> Result<Vec<u8>> {
+        let mut buffer = vec![0; capacity];
+        loop {
+            let mut guard = self.0.readable().await.map_err(io_error)?;
+            match guard.try_io(|fd| {
+                // SAFETY: the buffer is valid for writes of its length.
+                let received = unsafe {
+                    libc::recv(
+                        fd.as_raw_fd(),
+                        buffer.as_mut_ptr() as *mut libc::c_void,
+                        buffer.len(),
+                        0,
+                    )
+                };
+                if received < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(received as usize)
+                }
+            }) {
+                Ok(Ok(0)) => return Err(Error::NotConnected),
+                Ok(Ok(length)) => {
+                    buffer.truncate(length);
+                    return Ok(buffer);
+                }
+                Ok(Err(error)) => return Err(io_error(error)),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Shuts the connection down in both directions, which wakes up anything waiting to receive.
+    pub(crate) fn shutdown(&self) {
+        // SAFETY: shutdown has no memory-safety preconditions.
+        unsafe {
+            libc::shutdown(self.0.as_raw_fd(), libc::SHUT_RDWR);
+        }
+    }
+}
+
+#[async_trait]
+impl Bearer for Socket {
+    async fn send(&self, pdu: &[u8]) -> Result<()> {
+        Socket::send(self, pdu).await
+    }
+
+    async fn recv(&self) -> Result<Vec<u8>> {
+        // No PDU is longer than the largest MTU.
+        Socket::recv(self, MAX_MTU.into()).await
+    }
+
+    fn close(&self) {
+        self.shutdown();
+    }
+}
@@ -0,0 +1,592 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::peripheral::Peripheral;
+use crate::api::{
+    advertiser::{Advertiser, Advertising},
+    event_filter,
+    observer::{Observer, Tracer},
+    operation::OperationContext,
+    peripheral_events::PeripheralEventSender,
+    peripheral_store::PeripheralStore,
+    rssi::RssiTracking,
+    spans, AdapterCapabilities, AdapterInfo, AddressType, BDAddr, Central, CentralEvent,
+    CentralState, DataLength, DiscoveryEvent, EventFilter, Peripheral as _, ScanFilter,
+    ScanOptions,
+};
+use crate::host::eir::Advertisement;
+use crate::linux::controller::Controller;
+use crate::linux::mgmt::{self, Event, Info, Mgmt};
+use crate::platform::PeripheralId;
+use crate::rt::{self, Task};
+use crate::{Error, Result};
+use async_trait::async_trait;
+use futures::stream::{Stream, StreamExt};
+use log::{debug, trace};
+use std::collections::BTreeSet;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+
+#[derive(Debug)]
+struct State {
+    settings: u32,
+    scan_filter: Option<ScanFilter>,
+    /// The peripherals on the filter accept list, which are connected to when the controller
+    /// connects to them.
+    accept_list: BTreeSet<PeripheralId>,
+    /// The kernel's advertising instances which advertisements started by this adapter use.
+    advertising: BTreeSet<u8>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    mgmt: Mgmt,
+    index: u16,
+    id: String,
+    info: Info,
+    state: Mutex<State>,
+    /// Kept apart from the state, so that advertisements don't wait on each other to find their
+    /// peripheral.
+    peripherals: PeripheralStore<Peripheral>,
+    events_channel: broadcast::Sender<CentralEvent>,
+    peripheral_events: PeripheralEventSender,
+    operation_context: OperationContext,
+    monitor: Mutex<Option<Task>>,
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        if let Some(monitor) = self.monitor.get_mut().unwrap().take() {
+            monitor.abort();
+        }
+    }
+}
+
+/// Implementation of [api::Central](crate::api::Central).
+#[derive(Clone, Debug)]
+pub struct Adapter {
+    shared: Arc<Shared>,
+}
+
+fn central_state(settings: u32) -> CentralState {
+    if settings & mgmt::SETTING_POWERED != 0 {
+        CentralState::PoweredOn
+    } else {
+        CentralState::PoweredOff
+    }
+}
+
+impl Adapter {
+    pub(crate) async fn new(mgmt: Mgmt, index: u16, tracer: Tracer) -> Result<Self> {
+        // Subscribe before reading the settings, so that no change to them is missed.
+        let events = mgmt.events();
+        let info = mgmt.read_info(index).await?;
+        let (events_channel, _) = broadcast::channel(256);
+        let adapter = Adapter {
+            shared: Arc::new(Shared {
+                mgmt,
+                index,
+                id: format!("hci{}", index),
+                state: Mutex::new(State {
+                    settings: info.current_settings,
+                    scan_filter: None,
+                    accept_list: BTreeSet::new(),
+                    advertising: BTreeSet::new(),
+                }),
+                peripherals: PeripheralStore::default(),
+                info,
+                events_channel,
+                peripheral_events: PeripheralEventSender::default(),
+                operation_context: OperationContext::with_tracer(tracer),
+                monitor: Mutex::new(None),
+            }),
+        };
+        let monitor = rt::spawn(monitor(Arc::downgrade(&adapter.shared), events));
+        *adapter.shared.monitor.lock().unwrap() = Some(monitor);
+        Ok(adapter)
+    }
+
+    pub(crate) fn index(&self) -> u16 {
+        self.shared.index
+    }
+
+    fn emit(&self, event: CentralEvent) {
+        self.shared.operation_context.observe_event(&event);
+        self.shared.peripheral_events.send_central(&event);
+        if let Err(lost) = self.shared.events_channel.send(event) {
+            trace!("Lost central event, while nothing subscribed: {:?}", lost);
+        }
+    }
+
+    /// Removes the peripherals which haven't been seen for the peripheral TTL, if it's time to
+    /// check.
+    fn expire_peripherals(&self) {
+        if let Some(cutoff) = self.shared.operation_context.expiry().cutoff() {
+            self.shared
+                .peripherals
+                .remove_expired(|peripheral| peripheral.expired(cutoff));
+        }
+    }
+
+    /// Returns the peripheral with the given address, creating it if the adapter doesn't know it
+    /// yet, and whether it was created.
+    fn peripheral_for(&self, address: BDAddr) -> (Peripheral, bool) {
+        let id = PeripheralId::synthetic(&self.shared.id, address);
+        if let Some(peripheral) = self.shared.peripherals.get(&id) {
+            return (peripheral, false);
+        }
+        self.expire_peripherals();
+        self.shared.peripherals.get_or_insert_with(id.clone(), || {
+            Peripheral::new(
+                id,
+                address,
+                self.shared.info.address,
+                self.shared.index,
+                self.shared.events_channel.clone(),
+                self.shared.peripheral_events.clone(),
+                self.shared.operation_context.clone(),
+            )
+        })
+    }
+
+    fn device_found(&self, address: BDAddr, address_type: u8, rssi: i8, flags: u32, eir: &[u8]) {
+        let advertisement = Advertisement::parse(eir);
+        let state = self.shared.state.lock().unwrap();
+        let Some(filter) = &state.scan_filter else {
+            return;
+        };
+        // Match the filter against everything the device has advertised, as the services may
+        // have been in the advertisement and this be the scan response.
+        if !(filter.services.is_empty()
+            || advertisement
+                .services()
+                .any(|uuid| filter.services.contains(&uuid))
+            || self
+                .shared
+                .peripherals
+                .get(&PeripheralId::synthetic(&self.shared.id, address))
+                .is_some_and(|peripheral| peripheral.advertised_any(&filter.services)))
+        {
+            return;
+        }
+        drop(state);
+        let (peripheral, new) = self.peripheral_for(address);
+
+        // Devices advertise many times a second, so only copy out of the properties what
+        // something is listening for.
+        let events = &self.shared.peripheral_events;
+        let wants_discovery = events.has_discovery_subscribers();
+        let wants_data =
+            self.shared.events_channel.receiver_count() > 0 || events.has_filtered_subscribers();
+        let (discovery, manufacturer_data, service_data, services) =
+            peripheral.update_properties(|properties| {
+                advertisement.apply_to(properties);
+                properties.address_type = AddressType::from_u8(address_type);
+                // An RSSI of 127 means it isn't available.
+                if rssi != 127 {
+                    properties.rssi = Some(i16::from(rssi));
+                }
+                properties.connectable = Some(flags & mgmt::DEVICE_NOT_CONNECTABLE == 0);
+                properties.last_seen = Some(SystemTime::now());
+                (
+                    wants_discovery.then(|| properties.clone()),
+                    (wants_data && advertisement.has_manufacturer_data())
+                        .then(|| properties.manufacturer_data.clone()),
+                    (wants_data && advertisement.has_service_data())
+                        .then(|| properties.service_data.clone()),
+                    (wants_data && advertisement.has_services())
+                        .then(|| properties.services.clone()),
+                )
+            });
+        let id = peripheral.id_ref().clone();
+        if rssi != 127 {
+            self.shared
+                .operation_context
+                .rssi()
+                .record(&id, i16::from(rssi));
+        }
+        if let Some(properties) = discovery {
+            events.send_discovery(&id, properties, new);
+        }
+        self.emit(if new {
+            CentralEvent::DeviceDiscovered(id.clone())
+        } else {
+            CentralEvent::DeviceUpdated(id.clone())
+        });
+        if let Some(manufacturer_data) = manufacturer_data {
+            self.emit(CentralEvent::ManufacturerDataAdvertisement {
+                id: id.clone(),
+                manufacturer_data,
+            });
+        }
+        if let Some(service_data) = service_data {
+            self.emit(CentralEvent::ServiceDataAdvertisement {
+                id: id.clone(),
+                service_data,
+            });
+        }
+        if let Some(services) = services {
+            self.emit(CentralEvent::ServicesAdvertisement { id, services });
+        }
+    }
+
+    async fn handle_event(&self, event: Event) {
+        match event {
+            Event::DeviceFound {
+                address,
+                address_type,
+                rssi,
+                flags,
+                eir,
+            } => {
+                let id = PeripheralId::synthetic(&self.shared.id, address);
+                spans::callback("advertisement", Some(&id))
+                    .in_scope(|| self.device_found(address, address_type, rssi, flags, &eir));
+            }
+            Event::NewSettings(settings) => {
+                let mut state = self.shared.state.lock().unwrap();
+                let powered_changed = (state.settings ^ settings) & mgmt::SETTING_POWERED != 0;
+                state.settings = settings;
+                if settings & mgmt::SETTING_POWERED == 0 {
+                    state.scan_filter = None;
+                }
+                drop(state);
+                if powered_changed {
+                    self.emit(CentralEvent::StateUpdate(central_state(settings)));
+                }
+            }
+            // The kernel stops discovery after a while, so keep it going for as long as we are
+            // meant to be scanning.
+            Event::Discovering(false) => {
+                if self.shared.state.lock().unwrap().scan_filter.is_some() {
+                    if let Err(error) = self.shared.mgmt.start_discovery(self.shared.index).await {
+                        debug!("Failed to restart discovery: {}", error);
+                    }
+                }
+            }
+            // The controller connected to a device on the accept list, so open its ATT channel.
+            Event::DeviceConnected(address) => {
+                let id = PeripheralId::synthetic(&self.shared.id, address);
+                let accepted = self.shared.state.lock().unwrap().accept_list.contains(&id);
+                if let (true, Some(peripheral)) = (accepted, self.shared.peripherals.get(&id)) {
+                    let span = spans::callback("connection", Some(&id));
+                    rt::spawn(span.instrument(async move {
+                        if let Err(error) = peripheral.connect().await {
+                            debug!("Failed to connect to {}: {}", peripheral.id_ref(), error);
+                        }
+                    }));
+                }
+            }
+            Event::Discovering(true) | Event::IndexAdded | Event::IndexRemoved => {}
+        }
+    }
+}
+
+async fn monitor(adapter: Weak<Shared>, mut events: broadcast::Receiver<(u16, Event)>) {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                debug!("Missed {} management events", missed);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        let Some(shared) = adapter.upgrade() else {
+            return;
+        };
+        if event.0 == shared.index {
+            Adapter { shared }.handle_event(event.1).await;
+        }
+    }
+}
+
+/// Parses the address of a peripheral from its ID, which is of the form `hci0/dev_...`.
+fn address_of(id: &PeripheralId) -> Option<BDAddr> {
+    let id = id.to_string();
+    let (_, address) = id.rsplit_once("dev_")?;
+    address.replace('_', ":").parse().ok()
+}
+
+#[async_trait]
+impl Central for Adapter {
+    type Peripheral = Peripheral;
+
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = CentralEvent> + Send>>> {
+        let receiver = self.shared.events_channel.subscribe();
+        Ok(Box::pin(
+            BroadcastStream::new(receiver).filter_map(|x| async move { x.ok() }),
+        ))
+    }
+
+    async fn events_filtered(
+        &self,
+        filter: EventFilter,
+    ) -> Result<Pin<Box<dyn Stream<Item = CentralEvent> + Send>>> {
+        let events = self.shared.peripheral_events.subscribe_filtered(&filter);
+        Ok(event_filter::by_services(self, events, filter.services))
+    }
+
+    async fn discovery_events(&self) -> Result<Pin<Box<dyn Stream<Item = DiscoveryEvent> + Send>>> {
+        Ok(self.shared.peripheral_events.subscribe_discoveries())
+    }
+
+    async fn start_scan(&self, filter: ScanFilter) -> Result<()> {
+        let (scanning, settings) = {
+            let mut state = self.shared.state.lock().unwrap();
+            (state.scan_filter.replace(filter).is_some(), state.settings)
+        };
+        if scanning {
+            return Ok(());
+        }
+        let result = async {
+            if settings & mgmt::SETTING_LE == 0 {
+                self.shared.mgmt.set_le(self.shared.index, true).await?;
+            }
+            self.shared.mgmt.start_discovery(self.shared.index).await
+        }
+        .await;
+        if result.is_err() {
+            self.shared.state.lock().unwrap().scan_filter = None;
+        }
+        result
+    }
+
+    async fn start_scan_with_options(
+        &self,
+        filter: ScanFilter,
+        options: ScanOptions,
+    ) -> Result<()> {
+        if let Some((interval, window)) = options.timing() {
+            self.shared
+                .mgmt
+                .set_discovery_scan_timing(self.shared.index, interval, window)
+                .await?;
+        }
+        self.start_scan(filter).await
+    }
+
+    async fn stop_scan(&self) -> Result<()> {
+        if self
+            .shared
+            .state
+            .lock()
+            .unwrap()
+            .scan_filter
+            .take()
+            .is_none()
+        {
+            return Ok(());
+        }
+        self.shared.mgmt.stop_discovery(self.shared.index).await
+    }
+
+    async fn peripherals(&self) -> Result<Vec<Peripheral>> {
+        self.expire_peripherals();
+        Ok(self.shared.peripherals.peripherals())
+    }
+
+    async fn peripheral(&self, id: &PeripheralId) -> Result<Peripheral> {
+        self.shared.peripherals.get(id).ok_or(Error::DeviceNotFound)
+    }
+
+    async fn peripheral_by_address(&self, address: BDAddr) -> Result<Peripheral> {
+        self.shared
+            .peripherals
+            .by_address(address)
+            .ok_or(Error::DeviceNotFound)
+    }
+
+    async fn remove_peripheral(&self, id: &PeripheralId) -> Result<()> {
+        let peripheral = self.peripheral(id).await?;
+        if peripheral.is_connected().await? {
+            peripheral.disconnect().await?;
+        }
+        self.shared.peripherals.remove(id);
+        Ok(())
+    }
+
+    async fn add_peripheral(&self, id: &PeripheralId) -> Result<Peripheral> {
+        let address = address_of(id).ok_or(Error::DeviceNotFound)?;
+        Ok(self.peripheral_for(address).0)
+    }
+
+    async fn add_to_accept_list(&self, id: &PeripheralId) -> Result<()> {
+        let peripheral = self.peripheral(id).await?;
+        let address_type = peripheral.address_type();
+        self.shared
+            .mgmt
+            .add_device(self.shared.index, peripheral.address(), address_type)
+            .await?;
+        self.shared
+            .state
+            .lock()
+            .unwrap()
+            .accept_list
+            .insert(id.clone());
+        Ok(())
+    }
+
+    async fn remove_from_accept_list(&self, id: &PeripheralId) -> Result<()> {
+        let peripheral = self.peripheral(id).await?;
+        let address_type = peripheral.address_type();
+        self.shared.state.lock().unwrap().accept_list.remove(id);
+        self.shared
+            .mgmt
+            .remove_device(self.shared.index, peripheral.address(), address_type)
+            .await
+    }
+
+    async fn max_data_length(&self) -> Result<DataLength> {
+        Controller::open(self.shared.index)?.max_data_length().await
+    }
+
+    async fn suggested_data_length(&self) -> Result<DataLength> {
+        Controller::open(self.shared.index)?
+            .suggested_data_length()
+            .await
+    }
+
+    async fn set_suggested_data_length(&self, length: DataLength) -> Result<()> {
+        Controller::open(self.shared.index)?
+            .set_suggested_data_length(length)
+            .await
+    }
+
+    async fn adapter_info(&self) -> Result<AdapterInfo> {
+        let info = self.shared.mgmt.read_info(self.shared.index).await?;
+        Ok(AdapterInfo {
+            id: self.shared.id.clone(),
+            description: format!("{} (mgmt)", self.shared.id),
+            address: Some(info.address),
+            name: Some(info.name),
+            alias: None,
+            capabilities: AdapterCapabilities {
+                peripheral_role: Some(info.supported_settings & mgmt::SETTING_ADVERTISING != 0),
+                // The ATT client only uses the fixed ATT channel.
+                eatt: Some(false),
+                advertisement_monitor: Some(false),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+    }
+
+    async fn adapter_state(&self) -> Result<CentralState> {
+        Ok(central_state(self.shared.state.lock().unwrap().settings))
+    }
+
+    async fn set_powered(&self, powered: bool) -> Result<()> {
+        self.shared
+            .mgmt
+            .set_powered(self.shared.index, powered)
+            .await
+    }
+
+    /// Only a connectable adapter can be discoverable, so it's made connectable first.
+    async fn set_discoverable(&self, discoverable: bool, timeout: Option<Duration>) -> Result<()> {
+        let mgmt = &self.shared.mgmt;
+        if discoverable {
+            mgmt.set_connectable(self.shared.index, true).await?;
+        }
+        mgmt.set_discoverable(self.shared.index, discoverable, timeout)
+            .await
+    }
+
+    async fn set_alias(&self, alias: &str) -> Result<()> {
+        self.shared
+            .mgmt
+            .set_local_name(self.shared.index, alias)
+            .await
+    }
+
+    async fn set_pairable(&self, pairable: bool) -> Result<()> {
+        self.shared
+            .mgmt
+            .set_bondable(self.shared.index, pairable)
+            .await
+    }
+
+    async fn start_advertising(&self, advertiser: Advertiser) -> Result<Advertising> {
+        let data = advertiser.to_ad_structures()?;
+        // Instances are numbered from 1; the kernel rejects those beyond what the controller
+        // supports.
+        let instance = {
+            let mut state = self.shared.state.lock().unwrap();
+            let instance = (1..=u8::MAX)
+                .find(|instance| !state.advertising.contains(instance))
+                .ok_or_else(|| Error::RuntimeError("No advertising instances left".to_string()))?;
+            state.advertising.insert(instance);
+            instance
+        };
+        let index = self.shared.index;
+        if let Err(e) = self
+            .shared
+            .mgmt
+            .add_advertising(index, instance, &data)
+            .await
+        {
+            self.shared
+                .state
+                .lock()
+                .unwrap()
+                .advertising
+                .remove(&instance);
+            return Err(e);
+        }
+        let shared = Arc::downgrade(&self.shared);
+        Ok(Advertising::new(move || {
+            let Some(shared) = shared.upgrade() else {
+                return;
+            };
+            if !rt::can_spawn() {
+                return;
+            }
+            rt::spawn(async move {
+                if let Err(e) = shared.mgmt.remove_advertising(index, instance).await {
+                    debug!("Failed to stop advertising instance {}: {}", instance, e);
+                }
+                shared.state.lock().unwrap().advertising.remove(&instance);
+            });
+        }))
+    }
+
+    fn operation_timeout(&self) -> Option<Duration> {
+        self.shared.operation_context.timeout()
+    }
+
+    fn set_operation_timeout(&self, timeout: Option<Duration>) {
+        self.shared.operation_context.set_timeout(timeout);
+    }
+
+    fn set_observer(&self, observer: Option<Arc<dyn Observer>>) {
+        self.shared.operation_context.set_observer(observer);
+    }
+
+    fn set_peripheral_ttl(&self, ttl: Option<Duration>) {
+        self.shared.operation_context.expiry().set_ttl(ttl);
+    }
+
+    fn set_rssi_tracking(&self, tracking: Option<RssiTracking>) {
+        self.shared.operation_context.rssi().set_tracking(tracking);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address_from_id() {
+        let address = BDAddr::from([0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        let id = PeripheralId::synthetic("hci1", address);
+        assert_eq!(address_of(&id), Some(address));
+    }
+}
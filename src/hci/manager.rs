@@ -0,0 +1,121 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::adapter::Adapter;
+use crate::api::observer::{TraceHandler, Tracer};
+use crate::linux::mgmt::{Event, Mgmt};
+use crate::{api, api::ManagerEvent, Result};
+use async_trait::async_trait;
+use futures::stream::{self, Stream, StreamExt};
+use log::debug;
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// Implementation of [api::Manager], which talks to the kernel directly
+/// rather than through BlueZ.
+#[derive(Clone, Debug)]
+pub struct Manager {
+    mgmt: Mgmt,
+    /// The adapters created so far, by index, so that each adapter has one set of peripherals.
+    adapters: Arc<Mutex<BTreeMap<u16, Adapter>>>,
+    tracer: Tracer,
+}
+
+impl Manager {
+    /// Opens the kernel's Bluetooth management interface. This needs the `CAP_NET_ADMIN`
+    /// capability to scan, connect and power adapters on and off. It must be called within a
+    /// Tokio runtime.
+    pub async fn new() -> Result<Self> {
+        Ok(Self {
+            mgmt: Mgmt::open()?,
+            adapters: Arc::new(Mutex::new(BTreeMap::new())),
+            tracer: Tracer::default(),
+        })
+    }
+
+    /// Returns the adapter with the given index, creating it if there isn't one yet.
+    async fn adapter(&self, index: u16) -> Result<Adapter> {
+        if let Some(adapter) = self.adapters.lock().unwrap().get(&index) {
+            return Ok(adapter.clone());
+        }
+        let adapter = Adapter::new(self.mgmt.clone(), index, self.tracer.clone()).await?;
+        Ok(self
+            .adapters
+            .lock()
+            .unwrap()
+            .entry(index)
+            .or_insert(adapter)
+            .clone())
+    }
+}
+
+#[async_trait]
+impl api::Manager for Manager {
+    type Adapter = Adapter;
+
+    async fn adapters(&self) -> Result<Vec<Adapter>> {
+        let indices = self.mgmt.read_index_list().await?;
+        self.adapters
+            .lock()
+            .unwrap()
+            .retain(|index, _| indices.contains(index));
+        let mut adapters = Vec::with_capacity(indices.len());
+        for index in indices {
+            adapters.push(self.adapter(index).await?);
+        }
+        Ok(adapters)
+    }
+
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = ManagerEvent<Adapter>> + Send>>> {
+        // Subscribe before listing the current adapters so that we don't miss one being added in
+        // between.
+        let events = self.mgmt.events();
+        let adapters = self.adapters().await?;
+        let known: Vec<u16> = adapters.iter().map(Adapter::index).collect();
+        let initial_events = stream::iter(adapters.into_iter().map(ManagerEvent::AdapterAdded));
+        let changes = stream::unfold(
+            (self.clone(), events, known),
+            |(manager, mut events, mut known)| async move {
+                loop {
+                    let (index, event) = match events.recv().await {
+                        Ok(event) => event,
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    };
+                    match event {
+                        Event::IndexAdded if !known.contains(&index) => {
+                            match manager.adapter(index).await {
+                                Ok(adapter) => {
+                                    known.push(index);
+                                    let event = ManagerEvent::AdapterAdded(adapter);
+                                    return Some((event, (manager, events, known)));
+                                }
+                                Err(error) => debug!("Failed to add adapter {}: {}", index, error),
+                            }
+                        }
+                        Event::IndexRemoved if known.contains(&index) => {
+                            known.retain(|&i| i != index);
+                            let adapter = manager.adapters.lock().unwrap().remove(&index);
+                            if let Some(adapter) = adapter {
+                                let event = ManagerEvent::AdapterRemoved(adapter);
+                                return Some((event, (manager, events, known)));
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            },
+        );
+        Ok(Box::pin(initial_events.chain(changes)))
+    }
+
+    fn set_trace_handler(&self, handler: Option<Arc<dyn TraceHandler>>) {
+        self.tracer.set(handler);
+    }
+}
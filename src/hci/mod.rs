@@ -0,0 +1,40 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! A Linux backend which talks to the kernel directly through its Bluetooth management and L2CAP
+//! sockets, with a GATT client of its own, for systems which don't run bluetoothd and D-Bus, such
+//! as containers and minimal embedded images.
+//!
+//! The types in here implement the same [`api`](crate::api) traits as those in
+//! [`platform`](crate::platform), and peripheral IDs have the same form as BlueZ's. Use them in
+//! place of the platform ones where BlueZ isn't available, or turn off the default `bluez`
+//! feature, which makes them the platform ones and leaves out everything which needs D-Bus:
+//!
+//! ```no_run
+//! use btleplug::api::{Central, Manager as _, ScanFilter};
+//! use btleplug::hci::Manager;
+//!
+//! # async fn example() -> btleplug::Result<()> {
+//! let manager = Manager::new().await?;
+//! let adapter = manager.adapters().await?.remove(0);
+//! adapter.start_scan(ScanFilter::default()).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Scanning, connecting and powering adapters need the `CAP_NET_ADMIN` capability. This backend
+//! doesn't pair or bond, so attributes which need an encrypted link can't be used. It shouldn't be
+//! used while bluetoothd is running, as both would be controlling the same adapters.
+//!
+//! Only available on Linux with the `hci` feature, and like the BlueZ backend it needs a Tokio
+//! runtime.
+
+pub mod adapter;
+pub mod manager;
+pub mod peripheral;
+
+pub use self::{adapter::Adapter, manager::Manager, peripheral::Peripheral};
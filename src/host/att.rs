@@ -0,0 +1,494 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! A client of the Attribute Protocol, as defined in the Core Specification, Vol 3, Part F.
+
+use crate::api::{AttError, SubscriptionType};
+use crate::rt::{self, Task};
+use crate::{Error, Result};
+use async_trait::async_trait;
+use futures::channel::{mpsc, oneshot};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+pub(crate) const ERROR_RSP: u8 = 0x01;
+pub(crate) const EXCHANGE_MTU_REQ: u8 = 0x02;
+pub(crate) const EXCHANGE_MTU_RSP: u8 = 0x03;
+pub(crate) const FIND_INFORMATION_REQ: u8 = 0x04;
+pub(crate) const FIND_INFORMATION_RSP: u8 = 0x05;
+pub(crate) const READ_BY_TYPE_REQ: u8 = 0x08;
+pub(crate) const READ_BY_TYPE_RSP: u8 = 0x09;
+pub(crate) const READ_REQ: u8 = 0x0a;
+pub(crate) const READ_RSP: u8 = 0x0b;
+pub(crate) const READ_BLOB_REQ: u8 = 0x0c;
+pub(crate) const READ_BLOB_RSP: u8 = 0x0d;
+pub(crate) const READ_BY_GROUP_TYPE_REQ: u8 = 0x10;
+pub(crate) const READ_BY_GROUP_TYPE_RSP: u8 = 0x11;
+pub(crate) const WRITE_REQ: u8 = 0x12;
+pub(crate) const WRITE_RSP: u8 = 0x13;
+pub(crate) const PREPARE_WRITE_REQ: u8 = 0x16;
+pub(crate) const PREPARE_WRITE_RSP: u8 = 0x17;
+pub(crate) const EXECUTE_WRITE_REQ: u8 = 0x18;
+pub(crate) const EXECUTE_WRITE_RSP: u8 = 0x19;
+pub(crate) const HANDLE_VALUE_NTF: u8 = 0x1b;
+pub(crate) const HANDLE_VALUE_IND: u8 = 0x1d;
+pub(crate) const HANDLE_VALUE_CFM: u8 = 0x1e;
+const MULTIPLE_HANDLE_VALUE_NTF: u8 = 0x23;
+pub(crate) const WRITE_CMD: u8 = 0x52;
+/// Set in the opcode of commands, which have no response.
+const COMMAND_FLAG: u8 = 0x40;
+
+/// The ATT MTU every device supports.
+pub(crate) const DEFAULT_MTU: u16 = 23;
+/// The ATT MTU we ask for, which is the largest an attribute value can need.
+pub(crate) const MAX_MTU: u16 = 517;
+/// The longest an attribute value can be, from Vol 3, Part F, 3.2.9.
+const MAX_ATTRIBUTE_LENGTH: usize = 512;
+/// How long the device has to respond to a request before the bearer is considered to have failed.
+const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The channel ATT PDUs are exchanged over, which is an L2CAP socket outside of tests.
+#[async_trait]
+pub(crate) trait Bearer: Send + Sync + 'static {
+    async fn send(&self, pdu: &[u8]) -> Result<()>;
+    /// Receives the next PDU, failing once the bearer is closed.
+    async fn recv(&self) -> Result<Vec<u8>>;
+    /// Closes the bearer, so that `recv` fails.
+    fn close(&self);
+}
+
+/// A notification or indication received from the server.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct HandleValue {
+    pub(crate) handle: u16,
+    pub(crate) value: Vec<u8>,
+    pub(crate) subscription_type: SubscriptionType,
+}
+
+struct Shared {
+    bearer: Arc<dyn Bearer>,
+    mtu: AtomicU16,
+    /// Allows one request at a time. A permit is taken for each request, and given back when its
+    /// response arrives rather than when the request finishes, so that a cancelled request still
+    /// blocks the next one until the server has responded to it.
+    transaction: Semaphore,
+    response: Mutex<Option<oneshot::Sender<Vec<u8>>>>,
+}
+
+/// Aborts the task receiving PDUs when the last clone of the client is dropped.
+struct Reader(Task);
+
+impl Drop for Reader {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// An ATT client over one bearer. It stops working once the bearer is closed, which is when the
+/// stream of notifications returned with it ends.
+#[derive(Clone)]
+pub(crate) struct Client {
+    shared: Arc<Shared>,
+    _reader: Arc<Reader>,
+}
+
+impl Client {
+    /// Starts a client on the bearer. Notifications and indications received are sent to the
+    /// returned stream, indications being confirmed once they have been.
+    pub(crate) fn new(bearer: Arc<dyn Bearer>) -> (Self, mpsc::UnboundedReceiver<HandleValue>) {
+        let shared = Arc::new(Shared {
+            bearer,
+            mtu: AtomicU16::new(DEFAULT_MTU),
+            transaction: Semaphore::new(1),
+            response: Mutex::new(None),
+        });
+        let (sender, receiver) = mpsc::unbounded();
+        let reader = Reader(rt::spawn(receive(shared.clone(), sender)));
+        (
+            Client {
+                shared,
+                _reader: Arc::new(reader),
+            },
+            receiver,
+        )
+    }
+
+    pub(crate) fn mtu(&self) -> u16 {
+        self.shared.mtu.load(Ordering::Relaxed)
+    }
+
+    /// Closes the bearer, which ends the stream of notifications.
+    pub(crate) fn close(&self) {
+        self.shared.bearer.close();
+    }
+
+    /// Sends a request and waits for its response, which must have the given opcode. Error
+    /// responses are returned as [`Error::Att`].
+    pub(crate) async fn request(&self, pdu: &[u8], response_opcode: u8) -> Result<Vec<u8>> {
+        let shared = &self.shared;
+        match rt::timeout(TRANSACTION_TIMEOUT, shared.transaction.acquire()).await {
+            Ok(Ok(permit)) => permit.forget(),
+            // The semaphore is closed when the bearer is.
+            Ok(Err(_)) => return Err(Error::NotConnected),
+            Err(_) => {
+                self.close();
+                return Err(Error::NotConnected);
+            }
+        }
+        let (sender, receiver) = oneshot::channel();
+        *shared.response.lock().unwrap() = Some(sender);
+        let unsent = Unsent(Some(shared));
+        shared.bearer.send(pdu).await?;
+        unsent.sent();
+
+        let response = match rt::timeout(TRANSACTION_TIMEOUT, receiver).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(_)) => return Err(Error::NotConnected),
+            Err(_) => {
+                // No more requests can be sent on a bearer after a transaction times out.
+                self.close();
+                return Err(Error::NotConnected);
+            }
+        };
+        match response[..] {
+            [ERROR_RSP, request, _, _, code, ..] if request == pdu[0] => {
+                Err(Error::Att(AttError::from(code)))
+            }
+            [opcode, ..] if opcode == response_opcode => Ok(response),
+            _ => Err(Error::UnexpectedCallback),
+        }
+    }
+
+    /// Sends a command, which the server doesn't respond to.
+    pub(crate) async fn command(&self, pdu: &[u8]) -> Result<()> {
+        self.shared.bearer.send(pdu).await
+    }
+
+    /// Asks the server for the largest MTU we support, and returns the MTU agreed on. Servers which
+    /// refuse keep the default.
+    pub(crate) async fn exchange_mtu(&self) -> Result<u16> {
+        let mut request = vec![EXCHANGE_MTU_REQ];
+        request.extend_from_slice(&MAX_MTU.to_le_bytes());
+        match self.request(&request, EXCHANGE_MTU_RSP).await {
+            Ok(response) if response.len() >= 3 => {
+                let server_mtu = u16::from_le_bytes([response[1], response[2]]);
+                let mtu = server_mtu.clamp(DEFAULT_MTU, MAX_MTU);
+                self.shared.mtu.store(mtu, Ordering::Relaxed);
+                Ok(mtu)
+            }
+            Ok(_) => Err(Error::Att(AttError::InvalidPdu)),
+            Err(Error::Att(_)) => Ok(DEFAULT_MTU),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Reads an attribute's value, using Read Blob Requests for the rest of it if it doesn't fit
+    /// in one response. A server which sends more than the longest value an attribute can have
+    /// is sent no more requests, and the read fails.
+    pub(crate) async fn read(&self, handle: u16) -> Result<Vec<u8>> {
+        let mut request = vec![READ_REQ];
+        request.extend_from_slice(&handle.to_le_bytes());
+        let mut value = self.request(&request, READ_RSP).await?.split_off(1);
+        let part_length = usize::from(self.mtu()) - 1;
+        let mut last_part = value.len();
+        while last_part == part_length && value.len() < MAX_ATTRIBUTE_LENGTH {
+            let mut request = vec![READ_BLOB_REQ];
+            request.extend_from_slice(&handle.to_le_bytes());
+            // Shorter than the maximum length, so this fits.
+            request.extend_from_slice(&(value.len() as u16).to_le_bytes());
+            match self.request(&request, READ_BLOB_RSP).await {
+                Ok(response) => {
+                    last_part = response.len() - 1;
+                    value.extend_from_slice(&response[1..]);
+                }
+                // The value turned out to be exactly as long as a response.
+                Err(Error::Att(AttError::AttributeNotLong | AttError::InvalidOffset)) => break,
+                Err(error) => return Err(error),
+            }
+        }
+        if value.len() > MAX_ATTRIBUTE_LENGTH {
+            return Err(Error::Att(AttError::InvalidAttributeValueLength));
+        }
+        Ok(value)
+    }
+
+    /// Writes an attribute's value with a Write Request, or with a queue of Prepare Write Requests
+    /// if it is too long for one.
+    pub(crate) async fn write(&self, handle: u16, value: &[u8]) -> Result<()> {
+        let mtu = usize::from(self.mtu());
+        if value.len() <= mtu - 3 {
+            let mut request = vec![WRITE_REQ];
+            request.extend_from_slice(&handle.to_le_bytes());
+            request.extend_from_slice(value);
+            self.request(&request, WRITE_RSP).await?;
+            return Ok(());
+        }
+        let mut offset = 0;
+        for part in value.chunks(mtu - 5) {
+            let mut request = vec![PREPARE_WRITE_REQ];
+            request.extend_from_slice(&handle.to_le_bytes());
+            request.extend_from_slice(&(offset as u16).to_le_bytes());
+            request.extend_from_slice(part);
+            let result = self.request(&request, PREPARE_WRITE_RSP).await;
+            match result {
+                // The server echoes the part, so that we can check it arrived intact.
+                Ok(response) if response[1..] == request[1..] => {}
+                result => {
+                    let _ = self
+                        .request(&[EXECUTE_WRITE_REQ, 0x00], EXECUTE_WRITE_RSP)
+                        .await;
+                    return Err(result.err().unwrap_or(Error::Att(AttError::InvalidPdu)));
+                }
+            }
+            offset += part.len();
+        }
+        self.request(&[EXECUTE_WRITE_REQ, 0x01], EXECUTE_WRITE_RSP)
+            .await?;
+        Ok(())
+    }
+
+    /// Writes an attribute's value with a Write Command, which the server doesn't acknowledge.
+    pub(crate) async fn write_command(&self, handle: u16, value: &[u8]) -> Result<()> {
+        if value.len() > usize::from(self.mtu()) - 3 {
+            return Err(Error::Att(AttError::InvalidAttributeValueLength));
+        }
+        let mut command = vec![WRITE_CMD];
+        command.extend_from_slice(&handle.to_le_bytes());
+        command.extend_from_slice(value);
+        self.command(&command).await
+    }
+}
+
+/// Gives back the transaction permit if a request is cancelled before it is sent, as no response
+/// will arrive to give it back.
+struct Unsent<'a>(Option<&'a Shared>);
+
+impl Unsent<'_> {
+    fn sent(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for Unsent<'_> {
+    fn drop(&mut self) {
+        if let Some(shared) = self.0 {
+            shared.response.lock().unwrap().take();
+            shared.transaction.add_permits(1);
+        }
+    }
+}
+
+/// Whether a PDU from the server is the response to a request.
+fn is_response(opcode: u8) -> bool {
+    opcode & 1 == 1
+        && opcode & COMMAND_FLAG == 0
+        && !matches!(
+            opcode,
+            HANDLE_VALUE_NTF | HANDLE_VALUE_IND | MULTIPLE_HANDLE_VALUE_NTF
+        )
+}
+
+async fn receive(shared: Arc<Shared>, notifications: mpsc::UnboundedSender<HandleValue>) {
+    while let Ok(pdu) = shared.bearer.recv().await {
+        let Some(&opcode) = pdu.first() else {
+            continue;
+        };
+        match opcode {
+            HANDLE_VALUE_NTF | HANDLE_VALUE_IND if pdu.len() >= 3 => {
+                let _ = notifications.unbounded_send(HandleValue {
+                    handle: u16::from_le_bytes([pdu[1], pdu[2]]),
+                    value: pdu[3..].to_vec(),
+                    subscription_type: if opcode == HANDLE_VALUE_NTF {
+                        SubscriptionType::Notify
+                    } else {
+                        SubscriptionType::Indicate
+                    },
+                });
+                if opcode == HANDLE_VALUE_IND {
+                    let _ = shared.bearer.send(&[HANDLE_VALUE_CFM]).await;
+                }
+            }
+            EXCHANGE_MTU_REQ if pdu.len() >= 3 => {
+                let client_mtu = u16::from_le_bytes([pdu[1], pdu[2]]);
+                let mut response = vec![EXCHANGE_MTU_RSP];
+                response.extend_from_slice(&MAX_MTU.to_le_bytes());
+                if shared.bearer.send(&response).await.is_ok() {
+                    shared
+                        .mtu
+                        .store(client_mtu.clamp(DEFAULT_MTU, MAX_MTU), Ordering::Relaxed);
+                }
+            }
+            opcode if is_response(opcode) => {
+                if let Some(sender) = shared.response.lock().unwrap().take() {
+                    let _ = sender.send(pdu);
+                    shared.transaction.add_permits(1);
+                }
+            }
+            // Commands aren't answered, and nor are notifications we can't make sense of.
+            opcode if opcode & COMMAND_FLAG != 0 || opcode & 1 == 1 => {}
+            // We have no attributes of our own, so refuse anything else the server asks for.
+            opcode => {
+                let _ = shared
+                    .bearer
+                    .send(&[
+                        ERROR_RSP,
+                        opcode,
+                        0,
+                        0,
+                        AttError::RequestNotSupported.code(),
+                    ])
+                    .await;
+            }
+        }
+    }
+    shared.transaction.close();
+    shared.response.lock().unwrap().take();
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use std::collections::VecDeque;
+    use tokio::sync::Notify;
+
+    /// A bearer which hands what the client sends to a function standing in for the server, and
+    /// receives what it returns.
+    pub(crate) struct FakeBearer<F> {
+        server: Mutex<F>,
+        received: Mutex<VecDeque<Option<Vec<u8>>>>,
+        notify: Notify,
+        pub(crate) sent: Mutex<Vec<Vec<u8>>>,
+    }
+
+    impl<F: FnMut(&[u8]) -> Vec<Vec<u8>> + Send + 'static> FakeBearer<F> {
+        pub(crate) fn new(server: F) -> Arc<Self> {
+            Arc::new(FakeBearer {
+                server: Mutex::new(server),
+                received: Mutex::new(VecDeque::new()),
+                notify: Notify::new(),
+                sent: Mutex::new(Vec::new()),
+            })
+        }
+
+        /// Delivers a PDU from the server which isn't a response.
+        pub(crate) fn receive(&self, pdu: Vec<u8>) {
+            self.received.lock().unwrap().push_back(Some(pdu));
+            self.notify.notify_one();
+        }
+    }
+
+    #[async_trait]
+    impl<F: FnMut(&[u8]) -> Vec<Vec<u8>> + Send + 'static> Bearer for FakeBearer<F> {
+        async fn send(&self, pdu: &[u8]) -> Result<()> {
+            self.sent.lock().unwrap().push(pdu.to_vec());
+            let responses = (self.server.lock().unwrap())(pdu);
+            for response in responses {
+                self.receive(response);
+            }
+            Ok(())
+        }
+
+        async fn recv(&self) -> Result<Vec<u8>> {
+            loop {
+                let notified = self.notify.notified();
+                match self.received.lock().unwrap().pop_front() {
+                    Some(Some(pdu)) => return Ok(pdu),
+                    Some(None) => return Err(Error::NotConnected),
+                    None => {}
+                }
+                notified.await;
+            }
+        }
+
+        fn close(&self) {
+            self.received.lock().unwrap().push_back(None);
+            self.notify.notify_one();
+        }
+    }
+
+    #[tokio::test]
+    async fn long_read_and_write() -> Result<()> {
+        let mut value = Vec::new();
+        let stored: Vec<u8> = (0..40).collect();
+        let bearer = FakeBearer::new(move |pdu: &[u8]| match pdu[0] {
+            EXCHANGE_MTU_REQ => vec![vec![EXCHANGE_MTU_RSP, 23, 0]],
+            READ_REQ => vec![[&[READ_RSP][..], &stored[..22]].concat()],
+            READ_BLOB_REQ => {
+                let offset = usize::from(u16::from_le_bytes([pdu[3], pdu[4]]));
+                vec![[&[READ_BLOB_RSP][..], &stored[offset..(offset + 22).min(40)]].concat()]
+            }
+            PREPARE_WRITE_REQ => {
+                value.extend_from_slice(&pdu[5..]);
+                let mut response = pdu.to_vec();
+                response[0] = PREPARE_WRITE_RSP;
+                vec![response]
+            }
+            EXECUTE_WRITE_REQ => {
+                assert_eq!(value, (0..30).collect::<Vec<u8>>());
+                vec![vec![EXECUTE_WRITE_RSP]]
+            }
+            _ => vec![vec![ERROR_RSP, pdu[0], 0, 0, 0x06]],
+        });
+        let (client, _notifications) = Client::new(bearer.clone());
+        assert_eq!(client.exchange_mtu().await?, 23);
+        assert_eq!(client.read(3).await?, (0..40).collect::<Vec<u8>>());
+        client.write(3, &(0..30).collect::<Vec<u8>>()).await?;
+        let sent = bearer.sent.lock().unwrap();
+        assert_eq!(sent.last().unwrap(), &vec![EXECUTE_WRITE_REQ, 0x01]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn endless_long_read() {
+        // Every response is a full one, as if the value never ended.
+        let bearer = FakeBearer::new(|pdu: &[u8]| match pdu[0] {
+            READ_REQ => vec![[&[READ_RSP][..], &[0; 22]].concat()],
+            READ_BLOB_REQ => vec![[&[READ_BLOB_RSP][..], &[0; 22]].concat()],
+            _ => vec![vec![ERROR_RSP, pdu[0], 0, 0, 0x06]],
+        });
+        let (client, _notifications) = Client::new(bearer.clone());
+        assert!(matches!(
+            client.read(3).await,
+            Err(Error::Att(AttError::InvalidAttributeValueLength))
+        ));
+        assert_eq!(bearer.sent.lock().unwrap().len(), 24);
+    }
+
+    #[tokio::test]
+    async fn errors_and_notifications() -> Result<()> {
+        let bearer = FakeBearer::new(|pdu: &[u8]| match pdu[0] {
+            READ_REQ => vec![vec![ERROR_RSP, READ_REQ, pdu[1], pdu[2], 0x02]],
+            _ => Vec::new(),
+        });
+        let (client, mut notifications) = Client::new(bearer.clone());
+        assert!(matches!(
+            client.read(3).await,
+            Err(Error::Att(AttError::ReadNotPermitted))
+        ));
+
+        bearer.receive(vec![HANDLE_VALUE_IND, 3, 0, 42]);
+        assert_eq!(
+            notifications.next().await,
+            Some(HandleValue {
+                handle: 3,
+                value: vec![42],
+                subscription_type: SubscriptionType::Indicate,
+            })
+        );
+        client.close();
+        assert_eq!(notifications.next().await, None);
+        assert_eq!(
+            bearer.sent.lock().unwrap().last(),
+            Some(&vec![HANDLE_VALUE_CFM])
+        );
+        assert!(matches!(client.read(3).await, Err(Error::NotConnected)));
+        Ok(())
+    }
+}
@@ -0,0 +1,281 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Parsing of advertising data and scan responses, as defined in the Core Specification
+//! Supplement, Part A.
+
+use crate::api::{
+    ad_structures,
+    appearance::Appearance,
+    bleuuid::{uuid_from_u16, uuid_from_u32},
+    PeripheralProperties,
+};
+use std::{borrow::Cow, collections::HashMap, hash::Hash, sync::Arc};
+use uuid::Uuid;
+
+const INCOMPLETE_UUIDS_16: u8 = 0x02;
+const COMPLETE_UUIDS_16: u8 = 0x03;
+const INCOMPLETE_UUIDS_32: u8 = 0x04;
+const COMPLETE_UUIDS_32: u8 = 0x05;
+const INCOMPLETE_UUIDS_128: u8 = 0x06;
+const COMPLETE_UUIDS_128: u8 = 0x07;
+const SHORTENED_LOCAL_NAME: u8 = 0x08;
+const COMPLETE_LOCAL_NAME: u8 = 0x09;
+const TX_POWER_LEVEL: u8 = 0x0a;
+const CLASS_OF_DEVICE: u8 = 0x0d;
+const SERVICE_DATA_16: u8 = 0x16;
+const APPEARANCE: u8 = 0x19;
+const SERVICE_DATA_32: u8 = 0x20;
+const SERVICE_DATA_128: u8 = 0x21;
+const MANUFACTURER_DATA: u8 = 0xff;
+
+/// One AD structure of an advertising report, borrowing its data from the report.
+#[derive(Debug)]
+enum Structure<'a> {
+    /// Service UUIDs of the given length in bytes.
+    Services(&'a [u8], usize),
+    LocalName {
+        complete: bool,
+        name: &'a [u8],
+    },
+    TxPowerLevel(i16),
+    Class(u32),
+    Appearance(Appearance),
+    ServiceData(Uuid, &'a [u8]),
+    ManufacturerData(u16, &'a [u8]),
+}
+
+/// The AD structures of one advertising report. Nothing is copied out of the report until it's
+/// applied to a peripheral's properties, and then only what changed, into the buffers the
+/// properties already have, as devices mostly advertise the same thing over and over.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Advertisement<'a> {
+    data: &'a [u8],
+}
+
+fn parse_uuid(bytes: &[u8]) -> Uuid {
+    match *bytes {
+        [a, b] => uuid_from_u16(u16::from_le_bytes([a, b])),
+        [a, b, c, d] => uuid_from_u32(u32::from_le_bytes([a, b, c, d])),
+        _ => {
+            let mut bytes: [u8; 16] = bytes.try_into().unwrap();
+            bytes.reverse();
+            Uuid::from_bytes(bytes)
+        }
+    }
+}
+
+/// Replaces `shared` with a copy of `data`, unless they're the same already, so that repeated
+/// advertisements don't allocate.
+fn set_bytes(shared: &mut Option<Arc<[u8]>>, data: &[u8]) {
+    if shared.as_deref() != Some(data) {
+        *shared = Some(data.into());
+    }
+}
+
+/// Like [`set_bytes`], for a value of manufacturer or service data.
+fn set_value<K: Eq + Hash>(map: &mut HashMap<K, Arc<[u8]>>, key: K, data: &[u8]) {
+    if map.get(&key).map(|value| &**value) != Some(data) {
+        map.insert(key, data.into());
+    }
+}
+
+impl<'a> Advertisement<'a> {
+    pub(crate) fn parse(data: &'a [u8]) -> Self {
+        Advertisement { data }
+    }
+
+    /// Returns the structures of the report which btleplug understands. Malformed and unknown
+    /// structures are skipped, and a truncated one ends the report.
+    fn structures(&self) -> impl Iterator<Item = Structure<'a>> + 'a {
+        ad_structures(self.data).filter_map(|(ad_type, value)| {
+            let structure = match ad_type {
+                INCOMPLETE_UUIDS_16 | COMPLETE_UUIDS_16 => Structure::Services(value, 2),
+                INCOMPLETE_UUIDS_32 | COMPLETE_UUIDS_32 => Structure::Services(value, 4),
+                INCOMPLETE_UUIDS_128 | COMPLETE_UUIDS_128 => Structure::Services(value, 16),
+                SHORTENED_LOCAL_NAME | COMPLETE_LOCAL_NAME => Structure::LocalName {
+                    complete: ad_type == COMPLETE_LOCAL_NAME,
+                    name: value,
+                },
+                TX_POWER_LEVEL if value.len() == 1 => {
+                    Structure::TxPowerLevel(i16::from(value[0] as i8))
+                }
+                CLASS_OF_DEVICE if value.len() == 3 => {
+                    Structure::Class(u32::from_le_bytes([value[0], value[1], value[2], 0]))
+                }
+                APPEARANCE if value.len() == 2 => {
+                    Structure::Appearance(Appearance::from(u16::from_le_bytes([
+                        value[0], value[1],
+                    ])))
+                }
+                SERVICE_DATA_16 | SERVICE_DATA_32 | SERVICE_DATA_128 => {
+                    let uuid_length = match ad_type {
+                        SERVICE_DATA_16 => 2,
+                        SERVICE_DATA_32 => 4,
+                        _ => 16,
+                    };
+                    if value.len() < uuid_length {
+                        return None;
+                    }
+                    let (uuid, data) = value.split_at(uuid_length);
+                    Structure::ServiceData(parse_uuid(uuid), data)
+                }
+                MANUFACTURER_DATA if value.len() >= 2 => Structure::ManufacturerData(
+                    u16::from_le_bytes([value[0], value[1]]),
+                    &value[2..],
+                ),
+                _ => return None,
+            };
+            Some(structure)
+        })
+    }
+
+    /// Returns the service UUIDs advertised.
+    pub(crate) fn services(&self) -> impl Iterator<Item = Uuid> + 'a {
+        self.structures()
+            .flat_map(|structure| match structure {
+                Structure::Services(uuids, length) => uuids.chunks_exact(length),
+                _ => (&[] as &[u8]).chunks_exact(1),
+            })
+            .map(parse_uuid)
+    }
+
+    /// Returns the local name advertised. A shortened name doesn't replace the complete one if
+    /// both are there.
+    pub(crate) fn local_name(&self) -> Option<Cow<'a, str>> {
+        let mut local_name = None;
+        for structure in self.structures() {
+            if let Structure::LocalName { complete, name } = structure {
+                if complete || local_name.is_none() {
+                    local_name = Some(name);
+                }
+            }
+        }
+        local_name.map(String::from_utf8_lossy)
+    }
+
+    pub(crate) fn has_manufacturer_data(&self) -> bool {
+        self.structures()
+            .any(|structure| matches!(structure, Structure::ManufacturerData(..)))
+    }
+
+    pub(crate) fn has_service_data(&self) -> bool {
+        self.structures()
+            .any(|structure| matches!(structure, Structure::ServiceData(..)))
+    }
+
+    pub(crate) fn has_services(&self) -> bool {
+        self.services().next().is_some()
+    }
+
+    /// Updates a peripheral's properties with what was advertised. Like BlueZ, this keeps what
+    /// earlier reports said unless this one replaces it, so that an advertisement and its scan
+    /// response add up.
+    pub(crate) fn apply_to(&self, properties: &mut PeripheralProperties) {
+        // The advertising data itself is only ever that of the latest report, though.
+        set_bytes(&mut properties.advertising_data, self.data);
+        if let Some(local_name) = self.local_name() {
+            if properties.local_name.as_deref() != Some(&*local_name) {
+                properties.local_name = Some(local_name.into_owned());
+            }
+        }
+        for structure in self.structures() {
+            match structure {
+                Structure::Services(..) | Structure::LocalName { .. } => {}
+                Structure::TxPowerLevel(level) => properties.tx_power_level = Some(level),
+                Structure::Class(class) => properties.class = Some(class),
+                Structure::Appearance(appearance) => properties.appearance = Some(appearance),
+                Structure::ServiceData(uuid, data) => {
+                    set_value(&mut properties.service_data, uuid, data);
+                }
+                Structure::ManufacturerData(id, data) => {
+                    set_value(&mut properties.manufacturer_data, id, data);
+                }
+            }
+        }
+        for uuid in self.services() {
+            if !properties.services.contains(&uuid) {
+                properties.services.push(uuid);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let data = [
+            0x02, 0x01, 0x06, // Flags
+            0x05, 0x03, 0x0f, 0x18, 0x0a, 0x18, // Battery and Device Information services
+            0x04, 0x08, b'S', b'e', b'n', // Shortened name
+            0x05, 0x09, b'S', b'e', b'n', b's', // Complete name
+            0x02, 0x0a, 0xf4, // -12 dBm
+            0x05, 0xff, 0x4c, 0x00, 0x01, 0x02, // Apple manufacturer data
+            0x04, 0x16, 0x0f, 0x18, 0x64, // Battery service data
+            0x03, 0x19, 0xc1, 0x03, // Keyboard
+            0x09, 0xff, 0x01, // Truncated
+        ];
+        let advertisement = Advertisement::parse(&data);
+        assert_eq!(advertisement.local_name().as_deref(), Some("Sens"));
+        assert_eq!(
+            advertisement.services().collect::<Vec<_>>(),
+            vec![uuid_from_u16(0x180f), uuid_from_u16(0x180a)]
+        );
+        assert!(advertisement.has_manufacturer_data());
+        assert!(advertisement.has_service_data());
+
+        let mut properties = PeripheralProperties::default();
+        advertisement.apply_to(&mut properties);
+        assert_eq!(properties.tx_power_level, Some(-12));
+        assert_eq!(
+            properties.manufacturer_data,
+            [(0x004c, Arc::from([1, 2]))].into()
+        );
+        assert_eq!(
+            properties.service_data,
+            [(uuid_from_u16(0x180f), Arc::from([100]))].into()
+        );
+        assert_eq!(properties.class, None);
+        assert_eq!(properties.appearance, Some(Appearance::from(0x03c1)));
+        assert_eq!(properties.advertising_data.as_deref(), Some(&data[..]));
+        assert_eq!(
+            properties
+                .ad_structures()
+                .map(|(ad_type, _)| ad_type)
+                .collect::<Vec<_>>(),
+            vec![0x01, 0x03, 0x08, 0x09, 0x0a, 0xff, 0x16, 0x19]
+        );
+    }
+
+    #[test]
+    fn shortened_name_after_complete() {
+        let advertisement = Advertisement::parse(&[0x03, 0x09, b'A', b'B', 0x02, 0x08, b'A']);
+        assert_eq!(advertisement.local_name().as_deref(), Some("AB"));
+        assert!(!advertisement.has_services());
+    }
+
+    #[test]
+    fn scan_response_adds_to_advertisement() {
+        let mut properties = PeripheralProperties::default();
+        Advertisement::parse(&[0x03, 0x03, 0x0f, 0x18, 0x03, 0xff, 0x4c, 0x00])
+            .apply_to(&mut properties);
+        Advertisement::parse(&[0x04, 0x09, b'K', b'e', b'y']).apply_to(&mut properties);
+        assert_eq!(properties.local_name.as_deref(), Some("Key"));
+        assert_eq!(properties.services, vec![uuid_from_u16(0x180f)]);
+        assert_eq!(
+            properties.manufacturer_data,
+            [(0x004c, Arc::from([]))].into()
+        );
+        assert_eq!(
+            properties.advertising_data.as_deref(),
+            Some(&[0x04, 0x09, b'K', b'e', b'y'][..])
+        );
+    }
+}
@@ -0,0 +1,461 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Discovery of a server's GATT database over ATT, as defined in the Core Specification, Vol 3,
+//! Part G, 4.4 to 4.7.
+
+use super::att::{
+    Client, FIND_INFORMATION_REQ, FIND_INFORMATION_RSP, READ_BY_GROUP_TYPE_REQ,
+    READ_BY_GROUP_TYPE_RSP, READ_BY_TYPE_REQ, READ_BY_TYPE_RSP,
+};
+use crate::api::{
+    bleuuid::uuid_from_u16, AttError, CharPropFlags, Characteristic, Descriptor, Service,
+};
+use crate::{Error, Result};
+use std::collections::BTreeSet;
+use uuid::Uuid;
+
+const PRIMARY_SERVICE: u16 = 0x2800;
+const SECONDARY_SERVICE: u16 = 0x2801;
+const INCLUDE: u16 = 0x2802;
+const CHARACTERISTIC: u16 = 0x2803;
+
+/// Parses a UUID as sent over ATT, in little-endian byte order.
+pub(crate) fn parse_uuid(bytes: &[u8]) -> Option<Uuid> {
+    match *bytes {
+        [low, high] => Some(uuid_from_u16(u16::from_le_bytes([low, high]))),
+        _ => {
+            let mut bytes: [u8; 16] = bytes.try_into().ok()?;
+            bytes.reverse();
+            Some(Uuid::from_bytes(bytes))
+        }
+    }
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+/// Splits the attribute data list of a Read By Type or Read By Group Type Response into its
+/// entries, which all have the length given at the start of it.
+fn entries(response: &[u8]) -> Result<impl Iterator<Item = &[u8]>> {
+    match response {
+        [_, length, list @ ..] if *length > 0 && list.len() % usize::from(*length) == 0 => {
+            Ok(list.chunks(usize::from(*length)))
+        }
+        _ => Err(Error::Att(AttError::InvalidPdu)),
+    }
+}
+
+/// Runs a discovery procedure over a range of handles, calling `found` with each response until
+/// the server says there is nothing more to find. `found` returns the last handle the response
+/// covered.
+async fn discover_range(
+    client: &Client,
+    start: u16,
+    end: u16,
+    mut request: impl FnMut(u16, u16) -> (Vec<u8>, u8),
+    mut found: impl FnMut(&[u8]) -> Result<u16>,
+) -> Result<()> {
+    let mut start = start;
+    while start <= end && start != 0 {
+        let (pdu, response_opcode) = request(start, end);
+        let response = match client.request(&pdu, response_opcode).await {
+            Ok(response) => response,
+            Err(Error::Att(AttError::AttributeNotFound)) => return Ok(()),
+            Err(error) => return Err(error),
+        };
+        let last = found(&response)?;
+        if last < start {
+            return Err(Error::Att(AttError::InvalidPdu));
+        }
+        start = last.wrapping_add(1);
+    }
+    Ok(())
+}
+
+fn read_by_type(attribute_type: u16) -> impl FnMut(u16, u16) -> (Vec<u8>, u8) {
+    move |start, end| {
+        let mut pdu = vec![READ_BY_TYPE_REQ];
+        pdu.extend_from_slice(&start.to_le_bytes());
+        pdu.extend_from_slice(&end.to_le_bytes());
+        pdu.extend_from_slice(&attribute_type.to_le_bytes());
+        (pdu, READ_BY_TYPE_RSP)
+    }
+}
+
+/// A service found by discovery, with the range of handles it covers.
+struct ServiceRange {
+    uuid: Uuid,
+    primary: bool,
+    start: u16,
+    end: u16,
+}
+
+async fn discover_services(client: &Client, service_type: u16) -> Result<Vec<ServiceRange>> {
+    let mut services = Vec::new();
+    discover_range(
+        client,
+        0x0001,
+        0xffff,
+        |start, end| {
+            let mut pdu = vec![READ_BY_GROUP_TYPE_REQ];
+            pdu.extend_from_slice(&start.to_le_bytes());
+            pdu.extend_from_slice(&end.to_le_bytes());
+            pdu.extend_from_slice(&service_type.to_le_bytes());
+            (pdu, READ_BY_GROUP_TYPE_RSP)
+        },
+        |response| {
+            let mut last = 0;
+            for entry in entries(response)? {
+                let uuid = (entry.len() > 4)
+                    .then(|| parse_uuid(&entry[4..]))
+                    .flatten()
+                    .ok_or(Error::Att(AttError::InvalidPdu))?;
+                last = u16_at(entry, 2);
+                services.push(ServiceRange {
+                    uuid,
+                    primary: service_type == PRIMARY_SERVICE,
+                    start: u16_at(entry, 0),
+                    end: last,
+                });
+            }
+            Ok(last)
+        },
+    )
+    .await?;
+    Ok(services)
+}
+
+async fn discover_included_services(client: &Client, service: &ServiceRange) -> Result<Vec<Uuid>> {
+    let mut included = Vec::new();
+    // Included services with 128-bit UUIDs are listed without them, so they have to be read from
+    // the service declaration.
+    let mut unknown = Vec::new();
+    discover_range(
+        client,
+        service.start,
+        service.end,
+        read_by_type(INCLUDE),
+        |response| {
+            let mut last = 0;
+            for entry in entries(response)? {
+                if entry.len() < 6 {
+                    return Err(Error::Att(AttError::InvalidPdu));
+                }
+                last = u16_at(entry, 0);
+                match parse_uuid(&entry[6..]) {
+                    Some(uuid) => included.push(uuid),
+                    None => unknown.push(u16_at(entry, 2)),
+                }
+            }
+            Ok(last)
+        },
+    )
+    .await?;
+    for handle in unknown {
+        let declaration = client.read(handle).await?;
+        included.push(parse_uuid(&declaration).ok_or(Error::Att(AttError::InvalidPdu))?);
+    }
+    Ok(included)
+}
+
+/// A characteristic found by discovery, before its descriptors have been.
+struct CharacteristicDeclaration {
+    uuid: Uuid,
+    properties: CharPropFlags,
+    handle: u16,
+    value_handle: u16,
+}
+
+async fn discover_characteristics(
+    client: &Client,
+    service: &ServiceRange,
+) -> Result<Vec<CharacteristicDeclaration>> {
+    let mut characteristics: Vec<CharacteristicDeclaration> = Vec::new();
+    discover_range(
+        client,
+        service.start,
+        service.end,
+        read_by_type(CHARACTERISTIC),
+        |response| {
+            let mut last = 0;
+            for entry in entries(response)? {
+                let uuid = (entry.len() > 5)
+                    .then(|| parse_uuid(&entry[5..]))
+                    .flatten()
+                    .ok_or(Error::Att(AttError::InvalidPdu))?;
+                last = u16_at(entry, 0);
+                let value_handle = u16_at(entry, 3);
+                // The declarations have to be in order and in the service, with their values
+                // after them, as the handles between them are searched for descriptors.
+                let previous = characteristics.last().map_or(0, |c| c.handle);
+                if last <= previous
+                    || last < service.start
+                    || value_handle <= last
+                    || value_handle > service.end
+                {
+                    return Err(Error::Att(AttError::InvalidPdu));
+                }
+                characteristics.push(CharacteristicDeclaration {
+                    uuid,
+                    properties: CharPropFlags::from_bits_truncate(entry[2]),
+                    handle: last,
+                    value_handle,
+                });
+            }
+            Ok(last)
+        },
+    )
+    .await?;
+    Ok(characteristics)
+}
+
+/// Finds the descriptors in a range of handles, with their handles.
+async fn discover_descriptors(client: &Client, start: u16, end: u16) -> Result<Vec<(Uuid, u16)>> {
+    let mut descriptors = Vec::new();
+    discover_range(
+        client,
+        start,
+        end,
+        |start, end| {
+            let mut pdu = vec![FIND_INFORMATION_REQ];
+            pdu.extend_from_slice(&start.to_le_bytes());
+            pdu.extend_from_slice(&end.to_le_bytes());
+            (pdu, FIND_INFORMATION_RSP)
+        },
+        |response| {
+            let uuid_length = match response.get(1) {
+                Some(1) => 2,
+                Some(2) => 16,
+                _ => return Err(Error::Att(AttError::InvalidPdu)),
+            };
+            // Like `entries`, the list has to be made up of whole entries.
+            let list = response[2..].chunks_exact(2 + uuid_length);
+            if !list.remainder().is_empty() {
+                return Err(Error::Att(AttError::InvalidPdu));
+            }
+            let mut last = 0;
+            for entry in list {
+                let uuid = parse_uuid(&entry[2..]).ok_or(Error::Att(AttError::InvalidPdu))?;
+                last = u16_at(entry, 0);
+                descriptors.push((uuid, last));
+            }
+            Ok(last)
+        },
+    )
+    .await?;
+    Ok(descriptors)
+}
+
+/// Discovers all services on the server, with their characteristics and descriptors.
+pub(crate) async fn discover(client: &Client) -> Result<BTreeSet<Service>> {
+    let mut ranges = discover_services(client, PRIMARY_SERVICE).await?;
+    ranges.extend(discover_services(client, SECONDARY_SERVICE).await?);
+    let mut services = BTreeSet::new();
+    for range in ranges {
+        let included_services = discover_included_services(client, &range).await?;
+        let declarations = discover_characteristics(client, &range).await?;
+        let mut characteristics = BTreeSet::new();
+        for (index, declaration) in declarations.iter().enumerate() {
+            // A characteristic's descriptors are between its value and the next characteristic.
+            let end = declarations
+                .get(index + 1)
+                .map_or(Some(range.end), |next| next.handle.checked_sub(1))
+                .ok_or(Error::Att(AttError::InvalidPdu))?;
+            let descriptors = if declaration.value_handle < end {
+                discover_descriptors(client, declaration.value_handle + 1, end).await?
+            } else {
+                Vec::new()
+            };
+            characteristics.insert(Characteristic {
+                uuid: declaration.uuid,
+                service_uuid: range.uuid,
+                properties: declaration.properties,
+                descriptors: descriptors
+                    .into_iter()
+                    .map(|(uuid, handle)| Descriptor {
+                        uuid,
+                        service_uuid: range.uuid,
+                        characteristic_uuid: declaration.uuid,
+                        handle: Some(handle),
+                        value: None,
+                    })
+                    .collect(),
+                handle: Some(declaration.handle),
+                value_handle: Some(declaration.value_handle),
+            });
+        }
+        services.insert(Service {
+            uuid: range.uuid,
+            primary: range.primary,
+            characteristics,
+            included_services: included_services.into_iter().collect(),
+            handle: Some(range.start),
+        });
+    }
+    Ok(services)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::att::{tests::FakeBearer, ERROR_RSP};
+    use super::*;
+    use crate::api::CLIENT_CHARACTERISTIC_CONFIGURATION_UUID;
+
+    /// The attributes of a server with a Battery service with a notifiable Battery Level, and a
+    /// custom service with a 128-bit UUID which includes it.
+    fn attributes() -> Vec<(u16, u16, Vec<u8>)> {
+        let custom = Uuid::from_u128(0x12345678_9abc_def0_1234_56789abcdef0);
+        let mut custom_le = custom.as_bytes().to_vec();
+        custom_le.reverse();
+        vec![
+            (0x0001, PRIMARY_SERVICE, vec![0x0f, 0x18]),
+            (0x0002, CHARACTERISTIC, vec![0x12, 0x03, 0x00, 0x19, 0x2a]),
+            (0x0003, 0x2a19, vec![100]),
+            (0x0004, 0x2902, vec![0, 0]),
+            (0x0005, PRIMARY_SERVICE, custom_le.clone()),
+            (0x0006, INCLUDE, vec![0x01, 0x00, 0x04, 0x00, 0x0f, 0x18]),
+            (
+                0x0007,
+                CHARACTERISTIC,
+                [&[0x02, 0x08, 0x00][..], &custom_le].concat(),
+            ),
+            (0x0008, 0x0000, vec![1, 2, 3]),
+        ]
+    }
+
+    /// A minimal ATT server for the discovery procedures, over the given attributes.
+    fn server(pdu: &[u8]) -> Vec<Vec<u8>> {
+        let attributes = attributes();
+        let not_found = vec![vec![ERROR_RSP, pdu[0], pdu[1], pdu[2], 0x0a]];
+        let start = u16_at(pdu, 1);
+        let end = pdu.get(3..5).map_or(0, |_| u16_at(pdu, 3));
+        let in_range = attributes
+            .iter()
+            .filter(|(handle, _, _)| *handle >= start && *handle <= end);
+        match pdu[0] {
+            READ_BY_GROUP_TYPE_REQ => {
+                let group_type = u16_at(pdu, 5);
+                let Some((handle, _, value)) = in_range.clone().find(|a| a.1 == group_type) else {
+                    return not_found;
+                };
+                let group_end = attributes
+                    .iter()
+                    .find(|a| a.0 > *handle && a.1 == PRIMARY_SERVICE)
+                    .map_or(0xffff, |a| a.0 - 1);
+                let mut response = vec![READ_BY_GROUP_TYPE_RSP, 4 + value.len() as u8];
+                response.extend_from_slice(&handle.to_le_bytes());
+                response.extend_from_slice(&group_end.to_le_bytes());
+                response.extend_from_slice(value);
+                vec![response]
+            }
+            READ_BY_TYPE_REQ => {
+                let attribute_type = u16_at(pdu, 5);
+                let Some((handle, _, value)) = in_range.clone().find(|a| a.1 == attribute_type)
+                else {
+                    return not_found;
+                };
+                let mut response = vec![READ_BY_TYPE_RSP, 2 + value.len() as u8];
+                response.extend_from_slice(&handle.to_le_bytes());
+                response.extend_from_slice(value);
+                vec![response]
+            }
+            FIND_INFORMATION_REQ => {
+                let Some((handle, attribute_type, _)) = in_range.clone().next() else {
+                    return not_found;
+                };
+                let mut response = vec![FIND_INFORMATION_RSP, 1];
+                response.extend_from_slice(&handle.to_le_bytes());
+                response.extend_from_slice(&attribute_type.to_le_bytes());
+                vec![response]
+            }
+            super::super::att::READ_REQ => {
+                let Some((_, _, value)) = attributes.iter().find(|a| a.0 == start) else {
+                    return not_found;
+                };
+                vec![[&[super::super::att::READ_RSP][..], value].concat()]
+            }
+            _ => vec![vec![ERROR_RSP, pdu[0], 0, 0, 0x06]],
+        }
+    }
+
+    #[tokio::test]
+    async fn discover_database() -> Result<()> {
+        let (client, _notifications) = Client::new(FakeBearer::new(server));
+        let services = discover(&client).await?;
+        assert_eq!(services.len(), 2);
+        let battery = services
+            .iter()
+            .find(|service| service.uuid == uuid_from_u16(0x180f))
+            .unwrap();
+        assert_eq!(battery.handle, Some(1));
+        let level = battery.characteristics.iter().next().unwrap();
+        assert_eq!(level.uuid, uuid_from_u16(0x2a19));
+        assert_eq!(
+            level.properties,
+            CharPropFlags::READ | CharPropFlags::NOTIFY
+        );
+        assert_eq!(level.value_handle, Some(3));
+        assert_eq!(
+            level.descriptors.iter().next().map(|d| (d.uuid, d.handle)),
+            Some((CLIENT_CHARACTERISTIC_CONFIGURATION_UUID, Some(4)))
+        );
+
+        let custom = services
+            .iter()
+            .find(|service| service.uuid != uuid_from_u16(0x180f))
+            .unwrap();
+        assert_eq!(
+            custom.uuid,
+            Uuid::from_u128(0x12345678_9abc_def0_1234_56789abcdef0)
+        );
+        assert_eq!(custom.included_services, [uuid_from_u16(0x180f)].into());
+        let characteristic = custom.characteristics.iter().next().unwrap();
+        assert_eq!(characteristic.uuid, custom.uuid);
+        assert!(characteristic.descriptors.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn truncated_find_information_response() {
+        let (client, _notifications) = Client::new(FakeBearer::new(|pdu: &[u8]| match pdu[0] {
+            FIND_INFORMATION_REQ => {
+                vec![vec![FIND_INFORMATION_RSP, 1, 0x04, 0x00, 0x02, 0x29, 0x05]]
+            }
+            _ => server(pdu),
+        }));
+        assert!(matches!(
+            discover_descriptors(&client, 4, 5).await,
+            Err(Error::Att(AttError::InvalidPdu))
+        ));
+    }
+
+    #[tokio::test]
+    async fn characteristic_outside_service() {
+        // A declaration at handle 0 would otherwise make the previous characteristic's
+        // descriptors run to the end of the handle space.
+        let (client, _notifications) = Client::new(FakeBearer::new(|pdu: &[u8]| match pdu[0] {
+            READ_BY_TYPE_REQ if u16_at(pdu, 5) == CHARACTERISTIC => vec![vec![
+                READ_BY_TYPE_RSP,
+                7,
+                0x00,
+                0x00,
+                0x02,
+                0x01,
+                0x00,
+                0x19,
+                0x2a,
+            ]],
+            _ => server(pdu),
+        }));
+        assert!(matches!(
+            discover(&client).await,
+            Err(Error::Att(AttError::InvalidPdu))
+        ));
+    }
+}
@@ -0,0 +1,14 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! The parts of a Bluetooth host which the backends that talk to the kernel's Bluetooth sockets,
+//! rather than to a Bluetooth service, implement themselves: the `hci` backend on Linux and the
+//! FreeBSD backend.
+
+pub(crate) mod att;
+pub(crate) mod eir;
+pub(crate) mod gatt;
@@ -37,7 +37,7 @@
 //!
 //!     // get the first bluetooth adapter
 //!     let adapters = manager.adapters().await?;
-//!     let central = adapters.into_iter().nth(0).unwrap();
+//!     let central = adapters.into_iter().next().unwrap();
 //!
 //!     // start scanning for devices
 //!     central.start_scan(ScanFilter::default()).await?;
@@ -85,12 +85,14 @@
 //! }
 //! ```
 
-use crate::api::ParseBDAddrError;
+use crate::api::{AttError, NativeError, ParseBDAddrError, SecurityLevel};
 use std::result;
 use std::time::Duration;
 
 pub mod api;
-#[cfg(target_os = "linux")]
+#[cfg(not(target_arch = "wasm32"))]
+pub mod blocking;
+#[cfg(all(target_os = "linux", feature = "bluez"))]
 mod bluez;
 #[cfg(not(target_os = "linux"))]
 mod common;
@@ -98,9 +100,27 @@ mod common;
 mod corebluetooth;
 #[cfg(target_os = "android")]
 mod droidplug;
+#[cfg(all(feature = "ffi", not(target_arch = "wasm32")))]
+pub mod ffi;
+#[cfg(target_os = "freebsd")]
+mod freebsd;
+#[cfg(all(target_os = "linux", feature = "hci"))]
+pub mod hci;
+#[cfg(any(all(target_os = "linux", feature = "hci"), target_os = "freebsd"))]
+mod host;
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(feature = "mock")]
+pub mod mock;
 pub mod platform;
+#[cfg(feature = "record")]
+pub mod record;
+mod rt;
 #[cfg(feature = "serde")]
 pub mod serde;
+pub mod time;
+#[cfg(target_arch = "wasm32")]
+mod wasm;
 #[cfg(target_os = "windows")]
 mod winrtble;
 
@@ -113,6 +133,11 @@ pub enum Error {
     #[error("Device not found")]
     DeviceNotFound,
 
+    /// The platform doesn't expose the Bluetooth addresses of peripherals, so they can't be looked
+    /// up by address. This is the case for CoreBluetooth.
+    #[error("Peripheral addresses are not available on this platform")]
+    AddressNotAvailable,
+
     #[error("Not connected")]
     NotConnected,
 
@@ -125,11 +150,34 @@ pub enum Error {
     #[error("No such characteristic")]
     NoSuchCharacteristic,
 
+    #[error("No such descriptor")]
+    NoSuchDescriptor,
+
     #[error("The operation is not supported: {}", _0)]
     NotSupported(String),
 
-    #[error("Timed out after {:?}", _0)]
-    TimedOut(Duration),
+    /// An operation took longer than the timeout set with
+    /// [`Central::set_operation_timeout`](api::Central::set_operation_timeout).
+    #[error("{operation} timed out after {timeout:?}")]
+    TimedOut {
+        /// The operation which timed out, e.g. `"connect"` or `"read"`.
+        operation: &'static str,
+        timeout: Duration,
+    },
+
+    /// The device rejected a GATT operation with an ATT error. Not every platform reports these;
+    /// where one doesn't, the failure is reported as [`Error::Other`] or [`Error::RuntimeError`].
+    #[error("ATT error: {0}")]
+    Att(#[from] AttError),
+
+    /// The link to a peripheral is less secure than
+    /// [`Peripheral::require_security`](api::Peripheral::require_security) was asked for, even
+    /// after pairing.
+    #[error("Security level {required:?} is required, but the link is {actual:?}")]
+    InsufficientSecurity {
+        required: SecurityLevel,
+        actual: SecurityLevel,
+    },
 
     #[error("Error parsing UUID: {0}")]
     Uuid(#[from] uuid::Error),
@@ -140,11 +188,23 @@ pub enum Error {
     #[error("Runtime Error: {}", _0)]
     RuntimeError(String),
 
+    /// Any other error. Where the platform reported an error code, this holds a [`NativeError`],
+    /// which [`Error::native_error`] returns.
     #[error("{}", _0)]
     Other(Box<dyn std::error::Error + Send + Sync>),
 }
 
-/// Convert [`PoisonError`] to [`Error`] for replace `unwrap` to `map_err`
+impl Error {
+    /// Returns the error as reported by the platform's Bluetooth stack, if there is one.
+    pub fn native_error(&self) -> Option<&NativeError> {
+        match self {
+            Error::Other(error) => error.downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// Convert [`PoisonError`](std::sync::PoisonError) to [`Error`] for replace `unwrap` to `map_err`
 impl<T: std::fmt::Debug> From<std::sync::PoisonError<T>> for Error {
     fn from(e: std::sync::PoisonError<T>) -> Self {
         Self::Other(format!("{:?}", e).into())
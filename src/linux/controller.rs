@@ -0,0 +1,135 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! HCI commands sent straight to an adapter's controller over a raw HCI socket, for the few
+//! things which neither the management interface nor bluetoothd expose.
+
+use super::sys::Socket;
+use crate::api::{BDAddr, DataLength, NativeError};
+use crate::{Error, Result};
+
+const HCI_COMMAND_PKT: u8 = 0x01;
+const EV_CMD_COMPLETE: u8 = 0x0e;
+const EV_CMD_STATUS: u8 = 0x0f;
+
+const OP_LE_SET_DATA_LENGTH: u16 = 0x2022;
+const OP_LE_READ_SUGGESTED_DEFAULT_DATA_LENGTH: u16 = 0x2023;
+const OP_LE_WRITE_SUGGESTED_DEFAULT_DATA_LENGTH: u16 = 0x2024;
+const OP_LE_READ_MAXIMUM_DATA_LENGTH: u16 = 0x202f;
+
+const STATUS_SUCCESS: u8 = 0x00;
+const STATUS_UNKNOWN_COMMAND: u8 = 0x01;
+const STATUS_UNKNOWN_CONNECTION: u8 = 0x02;
+const STATUS_UNSUPPORTED_FEATURE: u8 = 0x11;
+const STATUS_UNSUPPORTED_REMOTE_FEATURE: u8 = 0x1a;
+
+/// The longest event packet: a type, an event code, a length and up to 255 bytes of parameters.
+const MAX_EVENT: usize = 258;
+
+fn status_error(status: u8) -> Error {
+    match status {
+        STATUS_UNKNOWN_COMMAND | STATUS_UNSUPPORTED_FEATURE => {
+            Error::NotSupported("The adapter doesn't support this".to_string())
+        }
+        STATUS_UNSUPPORTED_REMOTE_FEATURE => {
+            Error::NotSupported("The device doesn't support this".to_string())
+        }
+        STATUS_UNKNOWN_CONNECTION => Error::NotConnected,
+        status => Error::Other(Box::new(NativeError::HciStatus(status))),
+    }
+}
+
+fn parse_data_length(params: &[u8]) -> Result<DataLength> {
+    match params {
+        [octets_low, octets_high, time_low, time_high, ..] => Ok(DataLength {
+            octets: u16::from_le_bytes([*octets_low, *octets_high]),
+            time: u16::from_le_bytes([*time_low, *time_high]),
+        }),
+        _ => Err(Error::RuntimeError("Invalid data length".to_string())),
+    }
+}
+
+/// The controller of one adapter.
+#[derive(Debug)]
+pub(crate) struct Controller {
+    socket: Socket,
+}
+
+impl Controller {
+    pub(crate) fn open(index: u16) -> Result<Self> {
+        Ok(Controller {
+            socket: Socket::hci_raw(index, &[EV_CMD_COMPLETE, EV_CMD_STATUS])?,
+        })
+    }
+
+    /// Sends a command and returns the return parameters of its Command Complete event, after the
+    /// status. A command which the controller finishes later is done once it's been accepted.
+    async fn command(&self, opcode: u16, params: &[u8]) -> Result<Vec<u8>> {
+        let mut packet = Vec::with_capacity(4 + params.len());
+        packet.push(HCI_COMMAND_PKT);
+        packet.extend_from_slice(&opcode.to_le_bytes());
+        packet.push(params.len() as u8);
+        packet.extend_from_slice(params);
+        self.socket.send(&packet).await?;
+        loop {
+            // The socket also receives the events of commands which others send.
+            let event = self.socket.recv(MAX_EVENT).await?;
+            let (status, rest) = match event.get(1..) {
+                Some([EV_CMD_COMPLETE, _, _, low, high, status, rest @ ..])
+                    if u16::from_le_bytes([*low, *high]) == opcode =>
+                {
+                    (*status, rest)
+                }
+                Some([EV_CMD_STATUS, _, status, _, low, high, ..])
+                    if u16::from_le_bytes([*low, *high]) == opcode =>
+                {
+                    (*status, &[][..])
+                }
+                _ => continue,
+            };
+            return match status {
+                STATUS_SUCCESS => Ok(rest.to_vec()),
+                status => Err(status_error(status)),
+            };
+        }
+    }
+
+    /// Returns the longest data length the controller can send with.
+    pub(crate) async fn max_data_length(&self) -> Result<DataLength> {
+        let reply = self.command(OP_LE_READ_MAXIMUM_DATA_LENGTH, &[]).await?;
+        parse_data_length(&reply)
+    }
+
+    /// Returns the data length the controller suggests for new connections.
+    pub(crate) async fn suggested_data_length(&self) -> Result<DataLength> {
+        let reply = self
+            .command(OP_LE_READ_SUGGESTED_DEFAULT_DATA_LENGTH, &[])
+            .await?;
+        parse_data_length(&reply)
+    }
+
+    pub(crate) async fn set_suggested_data_length(&self, length: DataLength) -> Result<()> {
+        let mut params = [0; 4];
+        params[..2].copy_from_slice(&length.octets.to_le_bytes());
+        params[2..].copy_from_slice(&length.time.to_le_bytes());
+        self.command(OP_LE_WRITE_SUGGESTED_DEFAULT_DATA_LENGTH, &params)
+            .await?;
+        Ok(())
+    }
+
+    /// Asks the controller to negotiate the given data length on the LE connection to the device
+    /// with the given address.
+    pub(crate) async fn set_data_length(&self, address: BDAddr, length: DataLength) -> Result<()> {
+        let handle = self.socket.le_connection_handle(address)?;
+        let mut params = [0; 6];
+        params[..2].copy_from_slice(&handle.to_le_bytes());
+        params[2..4].copy_from_slice(&length.octets.to_le_bytes());
+        params[4..].copy_from_slice(&length.time.to_le_bytes());
+        self.command(OP_LE_SET_DATA_LENGTH, &params).await?;
+        Ok(())
+    }
+}
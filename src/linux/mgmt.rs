@@ -0,0 +1,467 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! A client of the kernel's Bluetooth management interface, which is documented in BlueZ's
+//! `doc/mgmt-api.txt`. This is what bluetoothd itself uses to control adapters.
+
+use super::sys::Socket;
+use crate::api::{pairing::OobData, AddressType, BDAddr, NativeError};
+use crate::rt::{self, Task};
+use crate::{Error, Result};
+use futures::channel::oneshot;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// The index commands which aren't about one adapter are sent to.
+const NO_INDEX: u16 = 0xffff;
+
+const OP_READ_INDEX_LIST: u16 = 0x0003;
+const OP_READ_INFO: u16 = 0x0004;
+const OP_SET_POWERED: u16 = 0x0005;
+const OP_SET_DISCOVERABLE: u16 = 0x0006;
+const OP_SET_CONNECTABLE: u16 = 0x0007;
+const OP_SET_BONDABLE: u16 = 0x0009;
+const OP_SET_LE: u16 = 0x000d;
+const OP_SET_LOCAL_NAME: u16 = 0x000f;
+const OP_ADD_REMOTE_OOB_DATA: u16 = 0x0021;
+const OP_START_DISCOVERY: u16 = 0x0023;
+const OP_STOP_DISCOVERY: u16 = 0x0024;
+const OP_ADD_DEVICE: u16 = 0x0033;
+const OP_REMOVE_DEVICE: u16 = 0x0034;
+const OP_ADD_ADVERTISING: u16 = 0x003e;
+const OP_REMOVE_ADVERTISING: u16 = 0x003f;
+const OP_SET_DEFAULT_SYSTEM_CONFIG: u16 = 0x004c;
+
+const EV_CMD_COMPLETE: u16 = 0x0001;
+const EV_CMD_STATUS: u16 = 0x0002;
+const EV_INDEX_ADDED: u16 = 0x0004;
+const EV_INDEX_REMOVED: u16 = 0x0005;
+const EV_NEW_SETTINGS: u16 = 0x0006;
+const EV_DEVICE_CONNECTED: u16 = 0x000b;
+const EV_DEVICE_FOUND: u16 = 0x0012;
+const EV_DISCOVERING: u16 = 0x0013;
+
+const STATUS_SUCCESS: u8 = 0x00;
+const STATUS_NOT_SUPPORTED: u8 = 0x0c;
+const STATUS_PERMISSION_DENIED: u8 = 0x14;
+
+pub(crate) const SETTING_POWERED: u32 = 1 << 0;
+pub(crate) const SETTING_LE: u32 = 1 << 9;
+pub(crate) const SETTING_ADVERTISING: u32 = 1 << 10;
+
+/// Set in the flags of a found device which can't be connected to.
+pub(crate) const DEVICE_NOT_CONNECTABLE: u32 = 0x04;
+
+/// The action of Add Device which has the controller connect to the device whenever it
+/// advertises, using the filter accept list.
+const ACTION_AUTO_CONNECT: u8 = 2;
+
+/// The flag of Add Advertising which has the kernel add flags for a general discoverable device to
+/// the advertisement.
+const ADVERTISING_DISCOVERABLE: u32 = 1 << 1;
+
+/// The types of the default system configuration parameters for the scan interval and window of
+/// discovery.
+const CONFIG_LE_SCAN_INTERVAL_DISCOVERY: u16 = 0x0011;
+const CONFIG_LE_SCAN_WINDOW_DISCOVERY: u16 = 0x0012;
+
+/// Discovery of LE devices, with both public and random addresses.
+const DISCOVERY_LE: u8 = (1 << 1) | (1 << 2);
+
+/// The longest packet the management interface can send: a header and up to 65535 bytes of
+/// parameters.
+const MAX_PACKET: usize = 6 + 65535;
+
+fn parse_address(bytes: &[u8]) -> BDAddr {
+    let mut address: [u8; 6] = bytes[..6].try_into().unwrap();
+    address.reverse();
+    BDAddr::from(address)
+}
+
+/// Encodes the address of an LE device as commands take it, followed by its type. The types
+/// are numbered as [`AddressType::num`] numbers them.
+fn le_address(address: BDAddr, address_type: AddressType) -> [u8; 7] {
+    let mut bytes = [0; 7];
+    bytes[..6].copy_from_slice(&address.into_inner());
+    bytes[..6].reverse();
+    bytes[6] = address_type.num();
+    bytes
+}
+
+fn status_error(status: u8) -> Error {
+    match status {
+        STATUS_PERMISSION_DENIED => Error::PermissionDenied,
+        STATUS_NOT_SUPPORTED => Error::NotSupported("The adapter doesn't support this".to_string()),
+        status => Error::Other(Box::new(NativeError::MgmtStatus(status))),
+    }
+}
+
+/// An event about an adapter, from the management interface.
+#[derive(Clone, Debug)]
+pub(crate) enum Event {
+    IndexAdded,
+    IndexRemoved,
+    NewSettings(u32),
+    DeviceConnected(BDAddr),
+    DeviceFound {
+        address: BDAddr,
+        address_type: u8,
+        rssi: i8,
+        flags: u32,
+        eir: Vec<u8>,
+    },
+    Discovering(bool),
+}
+
+impl Event {
+    fn parse(code: u16, params: &[u8]) -> Option<Self> {
+        Some(match code {
+            EV_INDEX_ADDED => Event::IndexAdded,
+            EV_INDEX_REMOVED => Event::IndexRemoved,
+            EV_NEW_SETTINGS if params.len() >= 4 => {
+                Event::NewSettings(u32::from_le_bytes(params[..4].try_into().unwrap()))
+            }
+            EV_DEVICE_CONNECTED if params.len() >= 7 => {
+                Event::DeviceConnected(parse_address(params))
+            }
+            EV_DEVICE_FOUND if params.len() >= 14 => Event::DeviceFound {
+                address: parse_address(params),
+                address_type: params[6],
+                rssi: params[7] as i8,
+                flags: u32::from_le_bytes(params[8..12].try_into().unwrap()),
+                eir: params[14..].to_vec(),
+            },
+            EV_DISCOVERING if params.len() >= 2 => Event::Discovering(params[1] != 0),
+            _ => return None,
+        })
+    }
+}
+
+/// What Read Controller Information returns about an adapter.
+#[derive(Clone, Debug)]
+pub(crate) struct Info {
+    pub(crate) address: BDAddr,
+    pub(crate) supported_settings: u32,
+    pub(crate) current_settings: u32,
+    pub(crate) name: String,
+}
+
+impl Info {
+    fn parse(params: &[u8]) -> Option<Self> {
+        if params.len() < 20 {
+            return None;
+        }
+        let name = &params[20..params.len().min(20 + 249)];
+        let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+        Some(Info {
+            address: parse_address(params),
+            supported_settings: u32::from_le_bytes(params[9..13].try_into().unwrap()),
+            current_settings: u32::from_le_bytes(params[13..17].try_into().unwrap()),
+            name: String::from_utf8_lossy(name).into_owned(),
+        })
+    }
+}
+
+struct Pending {
+    opcode: u16,
+    index: u16,
+    reply: oneshot::Sender<Result<Vec<u8>>>,
+}
+
+struct Shared {
+    socket: Socket,
+    /// Commands are sent one at a time, which keeps matching replies to them simple.
+    command: tokio::sync::Mutex<()>,
+    pending: Mutex<Option<Pending>>,
+    events: broadcast::Sender<(u16, Event)>,
+}
+
+/// Aborts the task receiving from the socket when the last clone of the client is dropped.
+struct Reader(Task);
+
+impl Drop for Reader {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Cuts `name` down to at most `len` bytes, at a character boundary.
+fn truncate(name: &str, len: usize) -> &str {
+    let mut end = name.len().min(len);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    &name[..end]
+}
+
+/// A connection to the management interface, shared by a manager and its adapters.
+#[derive(Clone)]
+pub(crate) struct Mgmt {
+    shared: Arc<Shared>,
+    _reader: Arc<Reader>,
+}
+
+impl std::fmt::Debug for Mgmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Mgmt").finish_non_exhaustive()
+    }
+}
+
+impl Mgmt {
+    pub(crate) fn open() -> Result<Self> {
+        let (events, _) = broadcast::channel(256);
+        let shared = Arc::new(Shared {
+            socket: Socket::mgmt()?,
+            command: tokio::sync::Mutex::new(()),
+            pending: Mutex::new(None),
+            events,
+        });
+        let reader = Reader(rt::spawn(receive(shared.clone())));
+        Ok(Mgmt {
+            shared,
+            _reader: Arc::new(reader),
+        })
+    }
+
+    /// Returns the stream of events, with the index of the adapter each is about.
+    pub(crate) fn events(&self) -> broadcast::Receiver<(u16, Event)> {
+        self.shared.events.subscribe()
+    }
+
+    /// Sends a command and returns the parameters of its reply.
+    async fn command(&self, opcode: u16, index: u16, params: &[u8]) -> Result<Vec<u8>> {
+        let _command = self.shared.command.lock().await;
+        let (reply, receiver) = oneshot::channel();
+        *self.shared.pending.lock().unwrap() = Some(Pending {
+            opcode,
+            index,
+            reply,
+        });
+        let mut packet = Vec::with_capacity(6 + params.len());
+        packet.extend_from_slice(&opcode.to_le_bytes());
+        packet.extend_from_slice(&index.to_le_bytes());
+        packet.extend_from_slice(&(params.len() as u16).to_le_bytes());
+        packet.extend_from_slice(params);
+        if let Err(error) = self.shared.socket.send(&packet).await {
+            self.shared.pending.lock().unwrap().take();
+            return Err(error);
+        }
+        receiver
+            .await
+            .unwrap_or_else(|_| Err(Error::RuntimeError("Management socket closed".to_string())))
+    }
+
+    /// Returns the indices of the adapters.
+    pub(crate) async fn read_index_list(&self) -> Result<Vec<u16>> {
+        let reply = self.command(OP_READ_INDEX_LIST, NO_INDEX, &[]).await?;
+        Ok(reply
+            .get(2..)
+            .unwrap_or_default()
+            .chunks_exact(2)
+            .map(|index| u16::from_le_bytes([index[0], index[1]]))
+            .collect())
+    }
+
+    pub(crate) async fn read_info(&self, index: u16) -> Result<Info> {
+        let reply = self.command(OP_READ_INFO, index, &[]).await?;
+        Info::parse(&reply).ok_or_else(|| Error::RuntimeError("Invalid controller info".into()))
+    }
+
+    pub(crate) async fn set_powered(&self, index: u16, powered: bool) -> Result<()> {
+        self.command(OP_SET_POWERED, index, &[u8::from(powered)])
+            .await?;
+        Ok(())
+    }
+
+    /// Makes the adapter discoverable, until the kernel stops it after `timeout` if given. Only a
+    /// connectable adapter can be discoverable.
+    pub(crate) async fn set_discoverable(
+        &self,
+        index: u16,
+        discoverable: bool,
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        let seconds = timeout.filter(|_| discoverable).map_or(0, |timeout| {
+            timeout.as_secs().clamp(1, u16::MAX.into()) as u16
+        });
+        let mut params = vec![u8::from(discoverable)];
+        params.extend_from_slice(&seconds.to_le_bytes());
+        self.command(OP_SET_DISCOVERABLE, index, &params).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn set_connectable(&self, index: u16, connectable: bool) -> Result<()> {
+        self.command(OP_SET_CONNECTABLE, index, &[u8::from(connectable)])
+            .await?;
+        Ok(())
+    }
+
+    pub(crate) async fn set_bondable(&self, index: u16, bondable: bool) -> Result<()> {
+        self.command(OP_SET_BONDABLE, index, &[u8::from(bondable)])
+            .await?;
+        Ok(())
+    }
+
+    /// Sets the name the controller presents to other devices, along with the short name
+    /// advertisements fall back to when the whole name doesn't fit.
+    pub(crate) async fn set_local_name(&self, index: u16, name: &str) -> Result<()> {
+        // Both are null terminated.
+        let mut params = [0; 249 + 11];
+        let long = truncate(name, 248);
+        params[..long.len()].copy_from_slice(long.as_bytes());
+        let short = truncate(name, 10);
+        params[249..249 + short.len()].copy_from_slice(short.as_bytes());
+        self.command(OP_SET_LOCAL_NAME, index, &params).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn set_le(&self, index: u16, enabled: bool) -> Result<()> {
+        self.command(OP_SET_LE, index, &[u8::from(enabled)]).await?;
+        Ok(())
+    }
+
+    /// Starts discovering LE devices, which the kernel stops again after a while.
+    pub(crate) async fn start_discovery(&self, index: u16) -> Result<()> {
+        self.command(OP_START_DISCOVERY, index, &[DISCOVERY_LE])
+            .await?;
+        Ok(())
+    }
+
+    /// Gives the kernel the out-of-band data an LE device shared, which it then pairs with.
+    pub(crate) async fn add_remote_oob_data(
+        &self,
+        index: u16,
+        address: BDAddr,
+        address_type: AddressType,
+        data: &OobData,
+    ) -> Result<()> {
+        let mut params = Vec::with_capacity(7 + 64);
+        params.extend_from_slice(&le_address(address, address_type));
+        // The P-192 values are only for BR/EDR, and must be zero for LE.
+        params.extend_from_slice(&[0; 32]);
+        params.extend_from_slice(&data.confirm);
+        params.extend_from_slice(&data.random);
+        self.command(OP_ADD_REMOTE_OOB_DATA, index, &params).await?;
+        Ok(())
+    }
+
+    /// Adds an LE device to the filter accept list, for the controller to connect to it whenever
+    /// it advertises.
+    pub(crate) async fn add_device(
+        &self,
+        index: u16,
+        address: BDAddr,
+        address_type: AddressType,
+    ) -> Result<()> {
+        let mut params = le_address(address, address_type).to_vec();
+        params.push(ACTION_AUTO_CONNECT);
+        self.command(OP_ADD_DEVICE, index, &params).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn remove_device(
+        &self,
+        index: u16,
+        address: BDAddr,
+        address_type: AddressType,
+    ) -> Result<()> {
+        self.command(OP_REMOVE_DEVICE, index, &le_address(address, address_type))
+            .await?;
+        Ok(())
+    }
+
+    /// Has the kernel broadcast `data`, AD structures without the flags, as the given advertising
+    /// instance, until it is removed. It isn't connectable, and has no scan response.
+    pub(crate) async fn add_advertising(
+        &self,
+        index: u16,
+        instance: u8,
+        data: &[u8],
+    ) -> Result<()> {
+        let mut params = Vec::with_capacity(11 + data.len());
+        params.push(instance);
+        params.extend_from_slice(&ADVERTISING_DISCOVERABLE.to_le_bytes());
+        // No duration or timeout.
+        params.extend_from_slice(&[0; 4]);
+        params.extend_from_slice(&[data.len() as u8, 0]);
+        params.extend_from_slice(data);
+        self.command(OP_ADD_ADVERTISING, index, &params).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn remove_advertising(&self, index: u16, instance: u8) -> Result<()> {
+        self.command(OP_REMOVE_ADVERTISING, index, &[instance])
+            .await?;
+        Ok(())
+    }
+
+    pub(crate) async fn stop_discovery(&self, index: u16) -> Result<()> {
+        self.command(OP_STOP_DISCOVERY, index, &[DISCOVERY_LE])
+            .await?;
+        Ok(())
+    }
+
+    /// Sets the LE scan interval and window the kernel discovers with, which bluetoothd otherwise
+    /// sets from its configuration. They take effect the next time discovery starts.
+    pub(crate) async fn set_discovery_scan_timing(
+        &self,
+        index: u16,
+        interval: Duration,
+        window: Duration,
+    ) -> Result<()> {
+        let mut params = Vec::with_capacity(10);
+        for (kind, value) in [
+            (CONFIG_LE_SCAN_INTERVAL_DISCOVERY, interval),
+            (CONFIG_LE_SCAN_WINDOW_DISCOVERY, window),
+        ] {
+            params.extend_from_slice(&kind.to_le_bytes());
+            params.push(2);
+            params.extend_from_slice(&scan_units(value).to_le_bytes());
+        }
+        self.command(OP_SET_DEFAULT_SYSTEM_CONFIG, index, &params)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Converts a scan interval or window to the controller's units of 0.625ms.
+fn scan_units(duration: Duration) -> u16 {
+    (duration.as_micros() / 625).try_into().unwrap_or(u16::MAX)
+}
+
+async fn receive(shared: Arc<Shared>) {
+    while let Ok(packet) = shared.socket.recv(MAX_PACKET).await {
+        if packet.len() < 6 {
+            continue;
+        }
+        let code = u16::from_le_bytes([packet[0], packet[1]]);
+        let index = u16::from_le_bytes([packet[2], packet[3]]);
+        let params = &packet[6..];
+        match code {
+            EV_CMD_COMPLETE | EV_CMD_STATUS if params.len() >= 3 => {
+                let opcode = u16::from_le_bytes([params[0], params[1]]);
+                let status = params[2];
+                let mut pending = shared.pending.lock().unwrap();
+                if !matches!(&*pending, Some(p) if p.opcode == opcode && p.index == index) {
+                    continue;
+                }
+                let reply = match status {
+                    STATUS_SUCCESS => Ok(params[3..].to_vec()),
+                    status => Err(status_error(status)),
+                };
+                let _ = pending.take().unwrap().reply.send(reply);
+            }
+            code => {
+                if let Some(event) = Event::parse(code, params) {
+                    let _ = shared.events.send((index, event));
+                }
+            }
+        }
+    }
+    shared.pending.lock().unwrap().take();
+}
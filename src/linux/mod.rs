@@ -0,0 +1,15 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! The kernel's Bluetooth sockets and management interface, which the `hci` backend is built on
+//! and the BlueZ backend uses for what bluetoothd doesn't expose over D-Bus.
+
+pub(crate) mod controller;
+// Most of the management interface is only used by the hci backend, and some only by BlueZ's.
+#[cfg_attr(not(all(feature = "hci", feature = "bluez")), allow(dead_code))]
+pub(crate) mod mgmt;
+pub(crate) mod sys;
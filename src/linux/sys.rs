@@ -0,0 +1,360 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! The kernel's Bluetooth sockets, which libc doesn't have bindings for.
+
+use crate::api::BDAddr;
+#[cfg(feature = "hci")]
+use crate::host::att::{Bearer, MAX_MTU};
+use crate::{rt, Error, Result};
+#[cfg(feature = "hci")]
+use async_trait::async_trait;
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use tokio::io::unix::AsyncFd;
+
+const AF_BLUETOOTH: libc::c_int = 31;
+#[cfg(feature = "hci")]
+const BTPROTO_L2CAP: libc::c_int = 0;
+const BTPROTO_HCI: libc::c_int = 1;
+const HCI_DEV_NONE: u16 = 0xffff;
+const HCI_CHANNEL_RAW: u16 = 0;
+const HCI_CHANNEL_CONTROL: u16 = 3;
+const SOL_HCI: libc::c_int = 0;
+const HCI_FILTER: libc::c_int = 2;
+/// `_IOR('H', 213, int)`
+const HCIGETCONNINFO: libc::c_ulong = 0x800448d5;
+const HCI_EVENT_PKT: u8 = 0x04;
+const LE_LINK: u8 = 0x80;
+/// The fixed L2CAP channel of the Attribute Protocol on LE links.
+#[cfg(feature = "hci")]
+const ATT_CID: u16 = 4;
+#[cfg(feature = "hci")]
+const BDADDR_LE_PUBLIC: u8 = 1;
+
+#[repr(C)]
+struct SockaddrHci {
+    hci_family: libc::sa_family_t,
+    hci_dev: u16,
+    hci_channel: u16,
+}
+
+/// Which packets a raw HCI socket receives, as `struct hci_ufilter`.
+#[repr(C)]
+struct HciFilter {
+    type_mask: u32,
+    event_mask: [u32; 2],
+    opcode: u16,
+}
+
+/// `struct hci_conn_info_req`, followed by the `struct hci_conn_info` the kernel fills in.
+#[repr(C)]
+#[derive(Default)]
+struct ConnInfoRequest {
+    bdaddr: [u8; 6],
+    link_type: u8,
+    _padding: u8,
+    handle: u16,
+    info_bdaddr: [u8; 6],
+    info_link_type: u8,
+    out: u8,
+    state: u16,
+    link_mode: u32,
+}
+
+#[cfg(feature = "hci")]
+#[repr(C)]
+struct SockaddrL2 {
+    l2_family: libc::sa_family_t,
+    l2_psm: u16,
+    /// The address in little-endian byte order, i.e. the reverse of how it is written.
+    l2_bdaddr: [u8; 6],
+    l2_cid: u16,
+    l2_bdaddr_type: u8,
+}
+
+#[cfg(feature = "hci")]
+impl SockaddrL2 {
+    fn le(address: BDAddr, address_type: u8) -> Self {
+        let mut l2_bdaddr = address.into_inner();
+        l2_bdaddr.reverse();
+        SockaddrL2 {
+            l2_family: AF_BLUETOOTH as libc::sa_family_t,
+            l2_psm: 0,
+            l2_bdaddr,
+            l2_cid: ATT_CID.to_le(),
+            l2_bdaddr_type: address_type,
+        }
+    }
+}
+
+/// Converts an error from a socket call, keeping the ones applications handle recognisable.
+pub(crate) fn io_error(error: io::Error) -> Error {
+    match error.raw_os_error() {
+        Some(
+            libc::ENOTCONN | libc::ECONNRESET | libc::EPIPE | libc::ESHUTDOWN | libc::ECONNABORTED,
+        ) => Error::NotConnected,
+        Some(libc::EACCES | libc::EPERM) => Error::PermissionDenied,
+        _ => Error::Other(error.into()),
+    }
+}
+
+fn check(result: libc::c_int) -> Result<libc::c_int> {
+    if result < 0 {
+        Err(io_error(io::Error::last_os_error()))
+    } else {
+        Ok(result)
+    }
+}
+
+/// A non-blocking Bluetooth socket registered with the Tokio reactor.
+#[derive(Debug)]
+pub(crate) struct Socket(AsyncFd<OwnedFd>);
+
+impl Socket {
+    fn new(kind: libc::c_int, protocol: libc::c_int) -> Result<Self> {
+        // SAFETY: socket has no memory-safety preconditions, and on success returns a descriptor
+        // which nothing else owns.
+        let fd = check(unsafe {
+            libc::socket(
+                AF_BLUETOOTH,
+                kind | libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK,
+                protocol,
+            )
+        })?;
+        // SAFETY: as above, the descriptor was just created and is owned by nothing else.
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let _context = rt::enter_io();
+        Ok(Socket(AsyncFd::new(fd).map_err(io_error)?))
+    }
+
+    /// Takes over a socket opened by someone else, such as the one bluetoothd hands out for
+    /// `AcquireNotify`, making it non-blocking.
+    #[cfg(feature = "bluez")]
+    pub(crate) fn from_fd(fd: OwnedFd) -> Result<Self> {
+        // SAFETY: fcntl has no memory-safety preconditions.
+        let flags = check(unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_GETFL) })?;
+        // SAFETY: as above.
+        check(unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK) })?;
+        let _context = rt::enter_io();
+        Ok(Socket(AsyncFd::new(fd).map_err(io_error)?))
+    }
+
+    fn bind<T>(&self, address: &T) -> Result<()> {
+        // SAFETY: the address is a valid sockaddr of the given length.
+        check(unsafe {
+            libc::bind(
+                self.0.as_raw_fd(),
+                address as *const T as *const libc::sockaddr,
+                mem::size_of::<T>() as libc::socklen_t,
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Opens the kernel's Bluetooth management interface, which controls all adapters.
+    pub(crate) fn mgmt() -> Result<Self> {
+        let socket = Self::new(libc::SOCK_RAW, BTPROTO_HCI)?;
+        socket.bind(&SockaddrHci {
+            hci_family: AF_BLUETOOTH as libc::sa_family_t,
+            hci_dev: HCI_DEV_NONE,
+            hci_channel: HCI_CHANNEL_CONTROL,
+        })?;
+        Ok(socket)
+    }
+
+    /// Opens a raw HCI socket on the adapter with the given index, which receives the HCI events
+    /// with the given codes. Sending commands on it needs the `CAP_NET_RAW` capability.
+    pub(crate) fn hci_raw(index: u16, events: &[u8]) -> Result<Self> {
+        let socket = Self::new(libc::SOCK_RAW, BTPROTO_HCI)?;
+        let mut filter = HciFilter {
+            type_mask: 1 << HCI_EVENT_PKT,
+            event_mask: [0; 2],
+            opcode: 0,
+        };
+        for &event in events {
+            filter.event_mask[usize::from(event / 32)] |= 1 << (event % 32);
+        }
+        // SAFETY: the option value is an hci_ufilter of the given length.
+        check(unsafe {
+            libc::setsockopt(
+                socket.0.as_raw_fd(),
+                SOL_HCI,
+                HCI_FILTER,
+                &filter as *const HciFilter as *const libc::c_void,
+                mem::size_of::<HciFilter>() as libc::socklen_t,
+            )
+        })?;
+        socket.bind(&SockaddrHci {
+            hci_family: AF_BLUETOOTH as libc::sa_family_t,
+            hci_dev: index,
+            hci_channel: HCI_CHANNEL_RAW,
+        })?;
+        Ok(socket)
+    }
+
+    /// Returns the handle of the LE connection to the device with the given address, on the
+    /// adapter a raw HCI socket is bound to.
+    pub(crate) fn le_connection_handle(&self, address: BDAddr) -> Result<u16> {
+        let mut request = ConnInfoRequest {
+            bdaddr: address.into_inner(),
+            link_type: LE_LINK,
+            ..Default::default()
+        };
+        request.bdaddr.reverse();
+        // SAFETY: the argument is an hci_conn_info_req with room for the hci_conn_info after it.
+        let result = unsafe {
+            libc::ioctl(
+                self.0.as_raw_fd(),
+                HCIGETCONNINFO as _,
+                &mut request as *mut ConnInfoRequest,
+            )
+        };
+        if result < 0 {
+            let error = io::Error::last_os_error();
+            return Err(if error.raw_os_error() == Some(libc::ENOENT) {
+                Error::NotConnected
+            } else {
+                io_error(error)
+            });
+        }
+        Ok(request.handle)
+    }
+
+    /// Connects the ATT channel of the LE device with the given address and address type
+    /// (1 for public, 2 for random), from the adapter with address `source`. The kernel creates
+    /// the LE connection.
+    #[cfg(feature = "hci")]
+    pub(crate) async fn l2cap_le(
+        source: BDAddr,
+        address: BDAddr,
+        address_type: u8,
+    ) -> Result<Self> {
+        let socket = Self::new(libc::SOCK_SEQPACKET, BTPROTO_L2CAP)?;
+        socket.bind(&SockaddrL2::le(source, BDADDR_LE_PUBLIC))?;
+        let destination = SockaddrL2::le(address, address_type);
+        // SAFETY: the address is a valid sockaddr_l2.
+        let result = unsafe {
+            libc::connect(
+                socket.0.as_raw_fd(),
+                &destination as *const SockaddrL2 as *const libc::sockaddr,
+                mem::size_of::<SockaddrL2>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            let error = io::Error::last_os_error();
+            if error.raw_os_error() != Some(libc::EINPROGRESS) {
+                return Err(io_error(error));
+            }
+            // The connection is made once the socket becomes writable, and whether it failed is
+            // then reported by SO_ERROR.
+            let mut guard = socket.0.writable().await.map_err(io_error)?;
+            guard.retain_ready();
+            let mut error: libc::c_int = 0;
+            let mut length = mem::size_of::<libc::c_int>() as libc::socklen_t;
+            // SAFETY: the option value is a c_int of the given length.
+            check(unsafe {
+                libc::getsockopt(
+                    socket.0.as_raw_fd(),
+                    libc::SOL_SOCKET,
+                    libc::SO_ERROR,
+                    &mut error as *mut libc::c_int as *mut libc::c_void,
+                    &mut length,
+                )
+            })?;
+            if error != 0 {
+                return Err(io_error(io::Error::from_raw_os_error(error)));
+            }
+        }
+        Ok(socket)
+    }
+
+    pub(crate) async fn send(&self, packet: &[u8]) -> Result<()> {
+        loop {
+            let mut guard = self.0.writable().await.map_err(io_error)?;
+            match guard.try_io(|fd| {
+                // SAFETY: the buffer is valid for reads of its length.
+                let sent = unsafe {
+                    libc::send(
+                        fd.as_raw_fd(),
+                        packet.as_ptr() as *const libc::c_void,
+                        packet.len(),
+                        libc::MSG_NOSIGNAL,
+                    )
+                };
+                if sent < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(())
+                }
+            }) {
+                Ok(result) => return result.map_err(io_error),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Receives one packet of at most `capacity` bytes. The peer closing the connection is
+    /// reported as [`Error::NotConnected`].
+    pub(crate) async fn recv(&self, capacity: usize) -> Result<Vec<u8>> {
+        let mut buffer = vec![0; capacity];
+        loop {
+            let mut guard = self.0.readable().await.map_err(io_error)?;
+            match guard.try_io(|fd| {
+                // SAFETY: the buffer is valid for writes of its length.
+                let received = unsafe {
+                    libc::recv(
+                        fd.as_raw_fd(),
+                        buffer.as_mut_ptr() as *mut libc::c_void,
+                        buffer.len(),
+                        0,
+                    )
+                };
+                if received < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(received as usize)
+                }
+            }) {
+                Ok(Ok(0)) => return Err(Error::NotConnected),
+                Ok(Ok(length)) => {
+                    buffer.truncate(length);
+                    return Ok(buffer);
+                }
+                Ok(Err(error)) => return Err(io_error(error)),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Shuts the connection down in both directions, which wakes up anything waiting to receive.
+    #[cfg(feature = "hci")]
+    pub(crate) fn shutdown(&self) {
+        // SAFETY: shutdown has no memory-safety preconditions.
+        unsafe {
+            libc::shutdown(self.0.as_raw_fd(), libc::SHUT_RDWR);
+        }
+    }
+}
+
+#[cfg(feature = "hci")]
+#[async_trait]
+impl Bearer for Socket {
+    async fn send(&self, pdu: &[u8]) -> Result<()> {
+        Socket::send(self, pdu).await
+    }
+
+    async fn recv(&self) -> Result<Vec<u8>> {
+        // No PDU is longer than the largest MTU.
+        Socket::recv(self, MAX_MTU.into()).await
+    }
+
+    fn close(&self) {
+        self.shutdown();
+    }
+}
@@ -0,0 +1,551 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::peripheral::Peripheral;
+use super::script::{Operation, Script};
+use crate::api::{
+    advertiser::{Advertiser, Advertising},
+    event_filter,
+    observer::{Observer, Tracer},
+    operation::OperationContext,
+    peripheral_events::PeripheralEventSender,
+    rssi::RssiTracking,
+    scan_response::{AdvertisingEvent, ScanResponses},
+    AdapterInfo, BDAddr, Central, CentralEvent, CentralState, ClassicDevice, DataLength,
+    DiscoveryEvent, EventFilter, Peripheral as _, PeripheralProperties, ScanFilter,
+    ScanResponseParts,
+};
+use crate::platform::PeripheralId;
+use crate::time::{Instant, SystemTime};
+use crate::{Error, Result};
+use async_trait::async_trait;
+use futures::stream::{Stream, StreamExt};
+use log::trace;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use uuid::Uuid;
+
+#[derive(Debug)]
+struct State {
+    info: AdapterInfo,
+    central_state: CentralState,
+    scan_filter: Option<ScanFilter>,
+    peripherals: BTreeMap<PeripheralId, Peripheral>,
+    suggested_data_length: DataLength,
+    /// The peripherals which are connected to as soon as they advertise.
+    accept_list: BTreeSet<PeripheralId>,
+    /// Results scripted for peripherals which haven't been created yet, by address.
+    scripts: HashMap<BDAddr, Arc<Script>>,
+    /// Whether the adapter is discoverable, and until when if it stops by itself.
+    discoverable: Option<Option<Instant>>,
+    pairable: bool,
+    /// The advertisements started with `start_advertising` which haven't been stopped, by the
+    /// order they were started in.
+    advertisements: BTreeMap<u64, Advertiser>,
+    next_advertisement: u64,
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<State>,
+    events_channel: broadcast::Sender<CentralEvent>,
+    peripheral_events: PeripheralEventSender,
+    scan_responses: ScanResponses<PeripheralProperties>,
+    operation_context: OperationContext,
+    script: Script,
+}
+
+/// Implementation of [api::Central](crate::api::Central), which is created with
+/// [`Manager::add_adapter`](super::Manager::add_adapter).
+#[derive(Clone, Debug)]
+pub struct Adapter {
+    shared: Arc<Shared>,
+}
+
+impl Adapter {
+    pub(crate) fn new(id: String, tracer: Tracer) -> Self {
+        let (events_channel, _) = broadcast::channel(16);
+        let info = AdapterInfo {
+            id,
+            description: "Mock".to_string(),
+            ..Default::default()
+        };
+        Adapter {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    info,
+                    central_state: CentralState::PoweredOn,
+                    scan_filter: None,
+                    peripherals: BTreeMap::new(),
+                    suggested_data_length: DataLength::MIN,
+                    accept_list: BTreeSet::new(),
+                    scripts: HashMap::new(),
+                    discoverable: None,
+                    pairable: true,
+                    advertisements: BTreeMap::new(),
+                    next_advertisement: 0,
+                }),
+                events_channel,
+                peripheral_events: PeripheralEventSender::default(),
+                scan_responses: ScanResponses::default(),
+                operation_context: OperationContext::with_tracer(tracer),
+                script: Script::default(),
+            }),
+        }
+    }
+
+    pub(crate) fn same_as(&self, other: &Adapter) -> bool {
+        Arc::ptr_eq(&self.shared, &other.shared)
+    }
+
+    #[cfg(feature = "record")]
+    pub(crate) fn script(&self) -> &Script {
+        &self.shared.script
+    }
+
+    /// Returns the script of the peripheral with the given address, which can be used before the
+    /// peripheral has been created.
+    #[cfg(feature = "record")]
+    pub(crate) fn peripheral_script(&self, address: BDAddr) -> Arc<Script> {
+        let mut state = self.shared.state.lock().unwrap();
+        let id = PeripheralId::synthetic(&state.info.id, address);
+        match state.peripherals.get(&id) {
+            Some(peripheral) => peripheral.script().clone(),
+            None => state.scripts.entry(address).or_default().clone(),
+        }
+    }
+
+    fn emit(&self, event: CentralEvent) {
+        self.shared.operation_context.observe_event(&event);
+        self.shared.peripheral_events.send_central(&event);
+        if let Err(lost) = self.shared.events_channel.send(event) {
+            trace!("Lost central event, while nothing subscribed: {:?}", lost);
+        }
+    }
+
+    /// Removes the peripherals which haven't been seen for the peripheral TTL, if it's time to
+    /// check.
+    fn expire_peripherals(&self, state: &mut State) {
+        if let Some(cutoff) = self.shared.operation_context.expiry().cutoff() {
+            state
+                .peripherals
+                .retain(|_, peripheral| !peripheral.expired(cutoff));
+        }
+    }
+
+    /// Returns the peripheral with the given address, creating it if the adapter doesn't know it
+    /// yet, and whether it was created.
+    fn peripheral_for(&self, state: &mut State, address: BDAddr) -> (Peripheral, bool) {
+        let id = PeripheralId::synthetic(&state.info.id, address);
+        if let Some(peripheral) = state.peripherals.get(&id) {
+            return (peripheral.clone(), false);
+        }
+        self.expire_peripherals(state);
+        let peripheral = Peripheral::new(
+            id.clone(),
+            address,
+            self.shared.events_channel.clone(),
+            self.shared.peripheral_events.clone(),
+            self.shared.operation_context.clone(),
+            state.scripts.remove(&address).unwrap_or_default(),
+        );
+        state.peripherals.insert(id, peripheral.clone());
+        (peripheral, true)
+    }
+
+    /// Simulates the adapter receiving an advertisement from the device with the given properties,
+    /// which is identified by their [`address`](PeripheralProperties::address). The peripheral is
+    /// created the first time, and its properties are replaced after that.
+    ///
+    /// If the properties' [`advertisement_type`](PeripheralProperties::advertisement_type) is
+    /// scannable, the advertisement is held back until the device's next advertisement, or its scan
+    /// response, which is advertised with
+    /// [`AdvertisementType::ScanResponse`](crate::api::AdvertisementType::ScanResponse) and only
+    /// needs the values it adds. The scan response's values are then added to the advertisement's,
+    /// and reported with it. A directed advertisement is also reported as
+    /// [`CentralEvent::DirectedAdvertisement`].
+    ///
+    /// Like a real adapter, this only reports advertisements while scanning, and only those which
+    /// match the scan filter. Returns the peripheral if the advertisement was reported. A peripheral
+    /// on the accept list is connected to whether or not the adapter is scanning.
+    pub fn advertise(&self, mut properties: PeripheralProperties) -> Option<Peripheral> {
+        let mut state = self.shared.state.lock().unwrap();
+        let id = PeripheralId::synthetic(&state.info.id, properties.address);
+        if state.central_state == CentralState::PoweredOn && state.accept_list.contains(&id) {
+            if let Some(peripheral) = state.peripherals.get(&id).cloned() {
+                drop(state);
+                peripheral.simulate_connect();
+                state = self.shared.state.lock().unwrap();
+            }
+        }
+        if state.scan_filter.is_none() || state.central_state != CentralState::PoweredOn {
+            return None;
+        }
+        drop(state);
+        properties.last_seen.get_or_insert_with(SystemTime::now);
+        let events = self.shared.scan_responses.receive(
+            properties.address,
+            properties.advertisement_type,
+            properties,
+        );
+        let mut reported = None;
+        for event in events {
+            reported = self.report(event);
+        }
+        reported
+    }
+
+    /// Reports an advertising event, if it matches the scan filter.
+    fn report(&self, event: AdvertisingEvent<PeripheralProperties>) -> Option<Peripheral> {
+        let mut state = self.shared.state.lock().unwrap();
+        let mut properties = match (event.advertisement, &event.scan_response) {
+            (Some(advertisement), _) => advertisement,
+            (None, Some(response)) => {
+                let id = PeripheralId::synthetic(&state.info.id, response.address);
+                match state.peripherals.get(&id) {
+                    Some(peripheral) => peripheral.current_properties(),
+                    None => PeripheralProperties {
+                        address: response.address,
+                        ..Default::default()
+                    },
+                }
+            }
+            (None, None) => return None,
+        };
+        match event.scan_response {
+            Some(response) => properties.apply_scan_response(response),
+            None => {
+                properties.scan_response = ScanResponseParts {
+                    received: Some(false),
+                    ..Default::default()
+                }
+            }
+        }
+        let filter = state.scan_filter.as_ref()?;
+        if !(filter.services.is_empty()
+            || filter
+                .services
+                .iter()
+                .any(|uuid| properties.services.contains(uuid)))
+        {
+            return None;
+        }
+        let (peripheral, new) = self.peripheral_for(&mut state, properties.address);
+        drop(state);
+
+        let id = peripheral.id_ref().clone();
+        peripheral.set_properties(properties.clone());
+        if let Some(rssi) = properties.rssi {
+            self.shared.operation_context.rssi().record(&id, rssi);
+        }
+        self.shared
+            .peripheral_events
+            .send_discovery(&id, properties.clone(), new);
+        self.emit(if new {
+            CentralEvent::DeviceDiscovered(id.clone())
+        } else {
+            CentralEvent::DeviceUpdated(id.clone())
+        });
+        if properties
+            .advertisement_type
+            .is_some_and(|kind| kind.is_directed())
+        {
+            self.emit(CentralEvent::DirectedAdvertisement(id.clone()));
+        }
+        if !properties.manufacturer_data.is_empty() {
+            self.emit(CentralEvent::ManufacturerDataAdvertisement {
+                id: id.clone(),
+                manufacturer_data: properties.manufacturer_data,
+            });
+        }
+        if !properties.service_data.is_empty() {
+            self.emit(CentralEvent::ServiceDataAdvertisement {
+                id: id.clone(),
+                service_data: properties.service_data,
+            });
+        }
+        if !properties.services.is_empty() {
+            self.emit(CentralEvent::ServicesAdvertisement {
+                id,
+                services: properties.services,
+            });
+        }
+        Some(peripheral)
+    }
+
+    /// Simulates the adapter finding a Bluetooth Classic device, which is reported while scanning
+    /// for Classic devices. Returns whether it was reported.
+    pub fn inquiry_response(&self, device: ClassicDevice) -> bool {
+        let state = self.shared.state.lock().unwrap();
+        if state.central_state != CentralState::PoweredOn
+            || !state
+                .scan_filter
+                .as_ref()
+                .is_some_and(|filter| filter.classic)
+        {
+            return false;
+        }
+        drop(state);
+        self.emit(CentralEvent::ClassicDeviceDiscovered(device));
+        true
+    }
+
+    /// Adds a peripheral which the adapter knows about without having seen it advertise, like a
+    /// device the system has bonded with. No event is emitted for it.
+    pub fn add_known_peripheral(&self, properties: PeripheralProperties) -> Peripheral {
+        let mut state = self.shared.state.lock().unwrap();
+        let (peripheral, _) = self.peripheral_for(&mut state, properties.address);
+        peripheral.set_properties(properties);
+        peripheral
+    }
+
+    /// Returns the peripheral with the given address, adding it as a known peripheral if the adapter
+    /// doesn't know it yet.
+    #[cfg(feature = "record")]
+    pub(crate) fn peripheral_at(&self, address: BDAddr) -> Peripheral {
+        let mut state = self.shared.state.lock().unwrap();
+        let (peripheral, new) = self.peripheral_for(&mut state, address);
+        if new {
+            peripheral.set_properties(PeripheralProperties::default());
+        }
+        peripheral
+    }
+
+    /// Changes the state of the adapter, emitting [`CentralEvent::StateUpdate`]. Turning it off
+    /// stops any scan and disconnects all peripherals.
+    pub fn set_state(&self, central_state: CentralState) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.central_state = central_state.clone();
+        let peripherals: Vec<_> = if central_state == CentralState::PoweredOn {
+            Vec::new()
+        } else {
+            state.scan_filter = None;
+            state.peripherals.values().cloned().collect()
+        };
+        drop(state);
+        for peripheral in peripherals {
+            peripheral.simulate_disconnect();
+        }
+        self.emit(CentralEvent::StateUpdate(central_state));
+    }
+
+    /// Returns whether the adapter is discoverable, as set with
+    /// [`set_discoverable`](Central::set_discoverable) and until its timeout has passed.
+    pub fn is_discoverable(&self) -> bool {
+        self.shared
+            .state
+            .lock()
+            .unwrap()
+            .discoverable
+            .is_some_and(|until| until.is_none_or(|until| Instant::now() < until))
+    }
+
+    /// Returns whether the adapter is pairable, as set with
+    /// [`set_pairable`](Central::set_pairable). It is to begin with.
+    pub fn is_pairable(&self) -> bool {
+        self.shared.state.lock().unwrap().pairable
+    }
+
+    /// Returns what the adapter is advertising, as started with
+    /// [`start_advertising`](Central::start_advertising), in the order it was started in.
+    pub fn advertisements(&self) -> Vec<Advertiser> {
+        let state = self.shared.state.lock().unwrap();
+        state.advertisements.values().cloned().collect()
+    }
+
+    /// Returns the filter of the current scan, or `None` if the adapter isn't scanning.
+    pub fn scan_filter(&self) -> Option<ScanFilter> {
+        self.shared.state.lock().unwrap().scan_filter.clone()
+    }
+}
+
+#[async_trait]
+impl Central for Adapter {
+    type Peripheral = Peripheral;
+
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = CentralEvent> + Send>>> {
+        let receiver = self.shared.events_channel.subscribe();
+        Ok(Box::pin(
+            BroadcastStream::new(receiver).filter_map(|x| async move { x.ok() }),
+        ))
+    }
+
+    async fn events_filtered(
+        &self,
+        filter: EventFilter,
+    ) -> Result<Pin<Box<dyn Stream<Item = CentralEvent> + Send>>> {
+        let events = self.shared.peripheral_events.subscribe_filtered(&filter);
+        Ok(event_filter::by_services(self, events, filter.services))
+    }
+
+    async fn discovery_events(&self) -> Result<Pin<Box<dyn Stream<Item = DiscoveryEvent> + Send>>> {
+        Ok(self.shared.peripheral_events.subscribe_discoveries())
+    }
+
+    async fn start_scan(&self, filter: ScanFilter) -> Result<()> {
+        let _performed = match self.shared.script.take(&Operation::StartScan) {
+            Some((Err(error), _)) => return Err(error),
+            scripted => scripted,
+        };
+        let mut state = self.shared.state.lock().unwrap();
+        if state.central_state != CentralState::PoweredOn {
+            return Err(Error::RuntimeError("Adapter is not powered on".to_string()));
+        }
+        state.scan_filter = Some(filter);
+        Ok(())
+    }
+
+    async fn stop_scan(&self) -> Result<()> {
+        self.shared.state.lock().unwrap().scan_filter = None;
+        Ok(())
+    }
+
+    async fn peripherals(&self) -> Result<Vec<Peripheral>> {
+        let mut state = self.shared.state.lock().unwrap();
+        self.expire_peripherals(&mut state);
+        Ok(state.peripherals.values().cloned().collect())
+    }
+
+    async fn peripheral(&self, id: &PeripheralId) -> Result<Peripheral> {
+        self.shared
+            .state
+            .lock()
+            .unwrap()
+            .peripherals
+            .get(id)
+            .cloned()
+            .ok_or(Error::DeviceNotFound)
+    }
+
+    async fn remove_peripheral(&self, id: &PeripheralId) -> Result<()> {
+        let peripheral = self.peripheral(id).await?;
+        if peripheral.is_connected().await? {
+            peripheral.disconnect().await?;
+        }
+        self.shared.state.lock().unwrap().peripherals.remove(id);
+        Ok(())
+    }
+
+    async fn add_peripheral(&self, id: &PeripheralId) -> Result<Peripheral> {
+        self.peripheral(id).await
+    }
+
+    async fn connected_peripherals(&self, services: &[Uuid]) -> Result<Vec<Peripheral>> {
+        let peripherals: Vec<_> = self
+            .shared
+            .state
+            .lock()
+            .unwrap()
+            .peripherals
+            .values()
+            .cloned()
+            .collect();
+        Ok(peripherals
+            .into_iter()
+            .filter(|peripheral| peripheral.is_connected_with(services))
+            .collect())
+    }
+
+    async fn add_to_accept_list(&self, id: &PeripheralId) -> Result<()> {
+        let mut state = self.shared.state.lock().unwrap();
+        if !state.peripherals.contains_key(id) {
+            return Err(Error::DeviceNotFound);
+        }
+        state.accept_list.insert(id.clone());
+        Ok(())
+    }
+
+    async fn remove_from_accept_list(&self, id: &PeripheralId) -> Result<()> {
+        self.shared.state.lock().unwrap().accept_list.remove(id);
+        Ok(())
+    }
+
+    async fn max_data_length(&self) -> Result<DataLength> {
+        Ok(DataLength::with_octets(DataLength::MAX.octets))
+    }
+
+    async fn suggested_data_length(&self) -> Result<DataLength> {
+        Ok(self.shared.state.lock().unwrap().suggested_data_length)
+    }
+
+    async fn set_suggested_data_length(&self, length: DataLength) -> Result<()> {
+        self.shared.state.lock().unwrap().suggested_data_length = length;
+        Ok(())
+    }
+
+    async fn adapter_info(&self) -> Result<AdapterInfo> {
+        Ok(self.shared.state.lock().unwrap().info.clone())
+    }
+
+    async fn adapter_state(&self) -> Result<CentralState> {
+        Ok(self.shared.state.lock().unwrap().central_state.clone())
+    }
+
+    async fn set_powered(&self, powered: bool) -> Result<()> {
+        self.set_state(if powered {
+            CentralState::PoweredOn
+        } else {
+            CentralState::PoweredOff
+        });
+        Ok(())
+    }
+
+    async fn set_discoverable(&self, discoverable: bool, timeout: Option<Duration>) -> Result<()> {
+        self.shared.state.lock().unwrap().discoverable =
+            discoverable.then(|| timeout.map(|timeout| Instant::now() + timeout));
+        Ok(())
+    }
+
+    async fn set_alias(&self, alias: &str) -> Result<()> {
+        self.shared.state.lock().unwrap().info.alias =
+            Some(alias.to_string()).filter(|alias| !alias.is_empty());
+        Ok(())
+    }
+
+    async fn set_pairable(&self, pairable: bool) -> Result<()> {
+        self.shared.state.lock().unwrap().pairable = pairable;
+        Ok(())
+    }
+
+    async fn start_advertising(&self, advertiser: Advertiser) -> Result<Advertising> {
+        advertiser.to_ad_structures()?;
+        let mut state = self.shared.state.lock().unwrap();
+        let key = state.next_advertisement;
+        state.next_advertisement += 1;
+        state.advertisements.insert(key, advertiser);
+        let shared = Arc::downgrade(&self.shared);
+        Ok(Advertising::new(move || {
+            if let Some(shared) = shared.upgrade() {
+                shared.state.lock().unwrap().advertisements.remove(&key);
+            }
+        }))
+    }
+
+    fn operation_timeout(&self) -> Option<Duration> {
+        self.shared.operation_context.timeout()
+    }
+
+    fn set_operation_timeout(&self, timeout: Option<Duration>) {
+        self.shared.operation_context.set_timeout(timeout);
+    }
+
+    fn set_observer(&self, observer: Option<Arc<dyn Observer>>) {
+        self.shared.operation_context.set_observer(observer);
+    }
+
+    fn set_peripheral_ttl(&self, ttl: Option<Duration>) {
+        self.shared.operation_context.expiry().set_ttl(ttl);
+    }
+
+    fn set_rssi_tracking(&self, tracking: Option<RssiTracking>) {
+        self.shared.operation_context.rssi().set_tracking(tracking);
+    }
+}
@@ -0,0 +1,91 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::adapter::Adapter;
+use crate::api::observer::{TraceHandler, Tracer};
+use crate::{api, api::ManagerEvent, Result};
+use async_trait::async_trait;
+use futures::{
+    channel::mpsc,
+    stream::{self, Stream, StreamExt},
+};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Default)]
+struct State {
+    adapters: Vec<Adapter>,
+    /// How many adapters have been added, so that each gets a different ID.
+    added: usize,
+    event_senders: Vec<mpsc::UnboundedSender<ManagerEvent<Adapter>>>,
+}
+
+impl State {
+    fn emit(&mut self, event: ManagerEvent<Adapter>) {
+        self.event_senders
+            .retain(|sender| sender.unbounded_send(event.clone()).is_ok());
+    }
+}
+
+/// Implementation of [api::Manager], which starts out with no adapters.
+#[derive(Clone, Debug, Default)]
+pub struct Manager {
+    state: Arc<Mutex<State>>,
+    tracer: Tracer,
+}
+
+impl Manager {
+    pub async fn new() -> Result<Self> {
+        Ok(Self::default())
+    }
+
+    /// Adds a virtual adapter which is powered on, as if one had been plugged in. Its ID is `mock`
+    /// followed by a number, e.g. `mock0`.
+    pub fn add_adapter(&self) -> Adapter {
+        let mut state = self.state.lock().unwrap();
+        let adapter = Adapter::new(format!("mock{}", state.added), self.tracer.clone());
+        state.added += 1;
+        state.adapters.push(adapter.clone());
+        state.emit(ManagerEvent::AdapterAdded(adapter.clone()));
+        adapter
+    }
+
+    /// Removes an adapter, as if it had been unplugged.
+    pub fn remove_adapter(&self, adapter: &Adapter) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(index) = state.adapters.iter().position(|a| a.same_as(adapter)) {
+            let adapter = state.adapters.remove(index);
+            state.emit(ManagerEvent::AdapterRemoved(adapter));
+        }
+    }
+}
+
+#[async_trait]
+impl api::Manager for Manager {
+    type Adapter = Adapter;
+
+    async fn adapters(&self) -> Result<Vec<Adapter>> {
+        Ok(self.state.lock().unwrap().adapters.clone())
+    }
+
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = ManagerEvent<Adapter>> + Send>>> {
+        let mut state = self.state.lock().unwrap();
+        let (sender, receiver) = mpsc::unbounded();
+        let existing = state
+            .adapters
+            .iter()
+            .cloned()
+            .map(ManagerEvent::AdapterAdded)
+            .collect::<Vec<_>>();
+        state.event_senders.push(sender);
+        Ok(Box::pin(stream::iter(existing).chain(receiver)))
+    }
+
+    fn set_trace_handler(&self, handler: Option<Arc<dyn TraceHandler>>) {
+        self.tracer.set(handler);
+    }
+}
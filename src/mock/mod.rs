@@ -0,0 +1,1132 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! A simulated backend, for testing code built on btleplug without Bluetooth hardware.
+//!
+//! The types in here implement the same [`api`](crate::api) traits as those in
+//! [`platform`](crate::platform), so code which is generic over [`Manager`](crate::api::Manager),
+//! [`Central`](crate::api::Central) or [`Peripheral`](crate::api::Peripheral) can be run against
+//! them. Tests script what the virtual devices do: [`Manager::add_adapter`] adds an adapter,
+//! [`Adapter::advertise`] delivers an advertisement to it while it is scanning, and the methods
+//! on [`Peripheral`] define a device's GATT database, send notifications, add latency, inject
+//! errors and simulate the device disconnecting.
+//!
+//! Only available with the `mock` feature.
+//!
+//! ```
+//! use btleplug::api::{
+//!     Central, CharPropFlags, Characteristic, Manager as _, Peripheral as _, PeripheralProperties,
+//!     ScanFilter, Service,
+//! };
+//! use btleplug::mock::Manager;
+//! use uuid::Uuid;
+//!
+//! # async fn example() -> btleplug::Result<()> {
+//! let manager = Manager::new().await?;
+//! let adapter = manager.add_adapter();
+//! let service_uuid = Uuid::from_u128(0x1234);
+//! let characteristic = Characteristic {
+//!     uuid: Uuid::from_u128(0x5678),
+//!     service_uuid,
+//!     properties: CharPropFlags::READ,
+//!     descriptors: Default::default(),
+//!     handle: None,
+//!     value_handle: None,
+//! };
+//!
+//! adapter.start_scan(ScanFilter::default()).await?;
+//! let device = adapter
+//!     .advertise(PeripheralProperties {
+//!         local_name: Some("Sensor".to_string()),
+//!         ..Default::default()
+//!     })
+//!     .unwrap();
+//! device.set_services([Service {
+//!     uuid: service_uuid,
+//!     primary: true,
+//!     characteristics: [characteristic.clone()].into(),
+//!     included_services: Default::default(),
+//!     handle: None,
+//! }]);
+//! device.set_value(&characteristic, [42]);
+//!
+//! // The code under test only sees the `api` traits.
+//! let peripheral = &manager.adapters().await?[0].peripherals().await?[0];
+//! peripheral.connect().await?;
+//! peripheral.discover_services().await?;
+//! assert_eq!(peripheral.read(&characteristic).await?, vec![42]);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod adapter;
+pub mod manager;
+pub mod peripheral;
+pub(crate) mod script;
+
+pub use self::{adapter::Adapter, manager::Manager, peripheral::Peripheral};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{
+        advertiser::Advertiser,
+        pairing::{OobData, PairingAgent, PairingRequest, PairingResponse},
+        AdvertisementType, Central, CentralEvent, CentralEventKind, CentralState, CharPropFlags,
+        Characteristic, ClassicDevice, ConnectTarget, DataLength, Descriptor, DisconnectReason,
+        EventFilter, Manager as _, ManagerEvent, Peripheral as _, PeripheralEvent,
+        PeripheralProperties, ScanFilter, SecurityLevel, Service, Transport, WriteType,
+        CLIENT_CHARACTERISTIC_CONFIGURATION_UUID, DEVICE_NAME_CHARACTERISTIC_UUID,
+        GENERIC_ACCESS_SERVICE_UUID,
+    };
+    use crate::platform::PeripheralId;
+    use crate::{Error, Result};
+    use futures::StreamExt;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+    use uuid::Uuid;
+
+    const SERVICE_UUID: Uuid = Uuid::from_u128(0x1234);
+
+    fn characteristic(properties: CharPropFlags) -> Characteristic {
+        let uuid = Uuid::from_u128(0x5678);
+        Characteristic {
+            uuid,
+            service_uuid: SERVICE_UUID,
+            properties,
+            descriptors: [Descriptor {
+                uuid: CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
+                service_uuid: SERVICE_UUID,
+                characteristic_uuid: uuid,
+                handle: None,
+                value: None,
+            }]
+            .into(),
+            handle: None,
+            value_handle: None,
+        }
+    }
+
+    fn service(characteristic: &Characteristic) -> Service {
+        Service {
+            uuid: SERVICE_UUID,
+            primary: true,
+            characteristics: [characteristic.clone()].into(),
+            included_services: Default::default(),
+            handle: None,
+        }
+    }
+
+    async fn scanning_adapter() -> Result<(Manager, Adapter)> {
+        let manager = Manager::new().await?;
+        let adapter = manager.add_adapter();
+        adapter.start_scan(ScanFilter::default()).await?;
+        Ok((manager, adapter))
+    }
+
+    #[tokio::test]
+    async fn advertisements() -> Result<()> {
+        let manager = Manager::new().await?;
+        let adapter = manager.add_adapter();
+        let mut events = adapter.events().await?;
+        let properties = PeripheralProperties {
+            address: [1, 2, 3, 4, 5, 6].into(),
+            services: vec![SERVICE_UUID],
+            ..Default::default()
+        };
+
+        // Nothing is found without scanning, or with a filter which doesn't match.
+        assert!(adapter.advertise(properties.clone()).is_none());
+        adapter
+            .start_scan(ScanFilter {
+                services: vec![Uuid::from_u128(1)],
+                ..Default::default()
+            })
+            .await?;
+        assert!(adapter.advertise(properties.clone()).is_none());
+
+        adapter.start_scan(ScanFilter::default()).await?;
+        let peripheral = adapter.advertise(properties.clone()).unwrap();
+        adapter.advertise(properties).unwrap();
+        let id = peripheral.id();
+        assert!(matches!(events.next().await, Some(CentralEvent::DeviceDiscovered(i)) if i == id));
+        assert!(matches!(
+            events.next().await,
+            Some(CentralEvent::ServicesAdvertisement { .. })
+        ));
+        assert!(matches!(events.next().await, Some(CentralEvent::DeviceUpdated(i)) if i == id));
+        assert_eq!(adapter.peripherals().await?.len(), 1);
+        assert_eq!(
+            adapter.peripheral(&id).await?.address(),
+            [1, 2, 3, 4, 5, 6].into()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn events_filtered() -> Result<()> {
+        let (_manager, adapter) = scanning_adapter().await?;
+        let advertised = |address: [u8; 6], services: Vec<Uuid>| PeripheralProperties {
+            address: address.into(),
+            services,
+            ..Default::default()
+        };
+        let wanted = adapter
+            .advertise(advertised([1, 1, 1, 1, 1, 1], vec![]))
+            .unwrap();
+        let mut discovered = adapter
+            .events_filtered(EventFilter {
+                kinds: vec![CentralEventKind::DeviceDiscovered],
+                ..Default::default()
+            })
+            .await?;
+        let mut by_peripheral = adapter
+            .events_filtered(EventFilter {
+                peripherals: vec![wanted.id()],
+                ..Default::default()
+            })
+            .await?;
+        let mut by_service = adapter
+            .events_filtered(EventFilter {
+                services: vec![SERVICE_UUID],
+                ..Default::default()
+            })
+            .await?;
+
+        adapter.advertise(advertised([1, 1, 1, 1, 1, 1], vec![]));
+        let other = adapter
+            .advertise(advertised([2, 2, 2, 2, 2, 2], vec![SERVICE_UUID]))
+            .unwrap();
+        adapter.set_state(CentralState::PoweredOff);
+
+        assert!(
+            matches!(discovered.next().await, Some(CentralEvent::DeviceDiscovered(id)) if id == other.id())
+        );
+        // Events which aren't about a peripheral pass the filter.
+        assert!(
+            matches!(by_peripheral.next().await, Some(CentralEvent::DeviceUpdated(id)) if id == wanted.id())
+        );
+        assert!(matches!(
+            by_peripheral.next().await,
+            Some(CentralEvent::StateUpdate(CentralState::PoweredOff))
+        ));
+        assert!(
+            matches!(by_service.next().await, Some(CentralEvent::DeviceDiscovered(id)) if id == other.id())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn scan_response() -> Result<()> {
+        let (_manager, adapter) = scanning_adapter().await?;
+        let mut events = adapter.events().await?;
+        let advertisement = PeripheralProperties {
+            services: vec![SERVICE_UUID],
+            advertisement_type: Some(AdvertisementType::ConnectableUndirected),
+            ..Default::default()
+        };
+        assert!(adapter.advertise(advertisement).is_none());
+        let device = adapter
+            .advertise(PeripheralProperties {
+                local_name: Some("Sensor".to_string()),
+                advertisement_type: Some(AdvertisementType::ScanResponse),
+                ..Default::default()
+            })
+            .unwrap();
+
+        // The device is only discovered once it's complete.
+        assert!(
+            matches!(events.next().await, Some(CentralEvent::DeviceDiscovered(id)) if id == device.id())
+        );
+        let properties = device.properties().await?.unwrap();
+        assert_eq!(properties.local_name.as_deref(), Some("Sensor"));
+        assert_eq!(properties.services, vec![SERVICE_UUID]);
+        assert_eq!(properties.scan_response.received, Some(true));
+        assert!(properties.scan_response.local_name);
+        assert!(properties.scan_response.services.is_empty());
+
+        // Without a scan response, nothing is attributed to one.
+        adapter.advertise(PeripheralProperties {
+            advertisement_type: Some(AdvertisementType::NonConnectableUndirected),
+            ..Default::default()
+        });
+        let properties = device.properties().await?.unwrap();
+        assert_eq!(properties.scan_response.received, Some(false));
+        assert!(properties.scan_response.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn directed_advertisement() -> Result<()> {
+        let (_manager, adapter) = scanning_adapter().await?;
+        let mut events = adapter.events().await?;
+        let device = adapter.advertise(Default::default()).unwrap();
+        let mut device_events = device.events().await?;
+        adapter.advertise(PeripheralProperties {
+            advertisement_type: Some(AdvertisementType::ConnectableDirected),
+            ..Default::default()
+        });
+
+        assert!(
+            matches!(events.next().await, Some(CentralEvent::DeviceDiscovered(id)) if id == device.id())
+        );
+        assert!(
+            matches!(events.next().await, Some(CentralEvent::DeviceUpdated(id)) if id == device.id())
+        );
+        assert!(
+            matches!(events.next().await, Some(CentralEvent::DirectedAdvertisement(id)) if id == device.id())
+        );
+        assert_eq!(
+            device_events.next().await,
+            Some(PeripheralEvent::PropertiesUpdated)
+        );
+        assert_eq!(
+            device_events.next().await,
+            Some(PeripheralEvent::DirectedAdvertisement)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn discovery_events() -> Result<()> {
+        let (_manager, adapter) = scanning_adapter().await?;
+        let mut events = adapter.discovery_events().await?;
+        let mut properties = PeripheralProperties {
+            rssi: Some(-60),
+            ..Default::default()
+        };
+        adapter.advertise(properties.clone()).unwrap();
+        properties.rssi = Some(-70);
+        let device = adapter.advertise(properties).unwrap();
+        adapter.remove_peripheral(&device.id()).await?;
+
+        // Each event has the properties it was emitted with, though the device is gone.
+        let event = events.next().await.unwrap();
+        assert!(event.new);
+        assert_eq!(event.id, device.id());
+        assert_eq!(event.properties.rssi, Some(-60));
+        let event = events.next().await.unwrap();
+        assert!(!event.new);
+        assert_eq!(event.properties.rssi, Some(-70));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn discover() -> Result<()> {
+        let (_manager, adapter) = scanning_adapter().await?;
+        let advertised = |address: [u8; 6]| PeripheralProperties {
+            address: address.into(),
+            services: vec![SERVICE_UUID],
+            ..Default::default()
+        };
+        let before = adapter.advertise(advertised([1, 1, 1, 1, 1, 1])).unwrap();
+
+        let mut found = adapter
+            .discover(ScanFilter {
+                services: vec![SERVICE_UUID],
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(found.next().await.map(|p| p.id()), Some(before.id()));
+
+        // Peripherals are only returned once, however often they advertise.
+        adapter.advertise(advertised([1, 1, 1, 1, 1, 1])).unwrap();
+        let after = adapter.advertise(advertised([2, 2, 2, 2, 2, 2])).unwrap();
+        assert_eq!(found.next().await.map(|p| p.id()), Some(after.id()));
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), found.next())
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn properties_with_age() -> Result<()> {
+        let (_manager, adapter) = scanning_adapter().await?;
+        let seen = adapter
+            .advertise(PeripheralProperties {
+                address: [1, 1, 1, 1, 1, 1].into(),
+                last_seen: Some(SystemTime::now() - Duration::from_secs(5)),
+                ..Default::default()
+            })
+            .unwrap();
+        let (properties, age) = seen.properties_with_age().await?.unwrap();
+        assert_eq!(properties.address, seen.address());
+        assert!(age.unwrap() >= Duration::from_secs(5));
+        let refreshed = seen.refresh_properties().await?.unwrap();
+        assert_eq!(refreshed.last_seen, properties.last_seen);
+
+        let known = adapter.add_known_peripheral(PeripheralProperties {
+            address: [2, 2, 2, 2, 2, 2].into(),
+            ..Default::default()
+        });
+        assert_eq!(known.properties_with_age().await?.unwrap().1, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn remove_peripheral() -> Result<()> {
+        let (_manager, adapter) = scanning_adapter().await?;
+        let first = adapter
+            .advertise(PeripheralProperties {
+                address: [1, 1, 1, 1, 1, 1].into(),
+                ..Default::default()
+            })
+            .unwrap();
+        first.connect().await?;
+        adapter.remove_peripheral(&first.id()).await?;
+        assert!(!first.is_connected().await?);
+        assert!(adapter.peripherals().await?.is_empty());
+        assert!(matches!(
+            adapter.remove_peripheral(&first.id()).await,
+            Err(Error::DeviceNotFound)
+        ));
+
+        // Peripherals which haven't been seen for the TTL are removed when another is found,
+        // unless they are connected.
+        adapter.set_peripheral_ttl(Some(Duration::from_millis(10)));
+        let advertise = |address: [u8; 6]| {
+            adapter
+                .advertise(PeripheralProperties {
+                    address: address.into(),
+                    ..Default::default()
+                })
+                .unwrap()
+        };
+        let stale = advertise([2, 2, 2, 2, 2, 2]);
+        let connected = advertise([3, 3, 3, 3, 3, 3]);
+        connected.connect().await?;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let fresh = advertise([4, 4, 4, 4, 4, 4]);
+        let mut ids: Vec<_> = adapter
+            .peripherals()
+            .await?
+            .iter()
+            .map(|p| p.id())
+            .collect();
+        ids.sort();
+        assert_eq!(ids, [connected.id(), fresh.id()]);
+        assert!(adapter.peripheral(&stale.id()).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn gatt() -> Result<()> {
+        let (_manager, adapter) = scanning_adapter().await?;
+        let characteristic = characteristic(CharPropFlags::READ | CharPropFlags::WRITE);
+        let device = adapter.advertise(Default::default()).unwrap();
+        device.set_services([service(&characteristic)]);
+        device.set_value(&characteristic, [1]);
+
+        assert!(matches!(
+            device.read(&characteristic).await,
+            Err(Error::NotConnected)
+        ));
+        device.connect().await?;
+        assert!(device.services().is_empty());
+        device.discover_services().await?;
+        assert_eq!(device.characteristics(), [characteristic.clone()].into());
+        assert_eq!(device.read(&characteristic).await?, vec![1]);
+        device
+            .write(&characteristic, &[2, 3], WriteType::WithResponse)
+            .await?;
+        assert_eq!(device.value(&characteristic), Some(vec![2, 3]));
+        assert!(matches!(
+            device
+                .write(&characteristic, &[4], WriteType::WithoutResponse)
+                .await,
+            Err(Error::Att(crate::api::AttError::WriteNotPermitted))
+        ));
+
+        device.fail_next(Error::NotConnected);
+        assert!(device.read(&characteristic).await.is_err());
+        assert_eq!(device.read(&characteristic).await?, vec![2, 3]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn descriptor_values() -> Result<()> {
+        let (_manager, adapter) = scanning_adapter().await?;
+        let characteristic = characteristic(CharPropFlags::READ);
+        let cccd = characteristic
+            .descriptor(CLIENT_CHARACTERISTIC_CONFIGURATION_UUID)
+            .unwrap()
+            .clone();
+        let device = adapter.advertise(Default::default()).unwrap();
+        device.set_services([service(&characteristic)]);
+        device.set_descriptor_value(&cccd, [0, 0]);
+        device.connect().await?;
+        device.discover_services().await?;
+
+        let cached = |device: &Peripheral| {
+            device
+                .characteristics()
+                .into_iter()
+                .next()
+                .unwrap()
+                .descriptors
+                .into_iter()
+                .next()
+                .unwrap()
+                .value
+        };
+        assert_eq!(cached(&device), None);
+        assert_eq!(
+            device
+                .read_descriptor_by_uuid(&characteristic, CLIENT_CHARACTERISTIC_CONFIGURATION_UUID)
+                .await?,
+            vec![0, 0]
+        );
+        assert_eq!(cached(&device), Some(vec![0, 0]));
+        assert!(matches!(
+            device
+                .read_descriptor_by_uuid(&characteristic, Uuid::from_u128(0x2901))
+                .await,
+            Err(Error::NoSuchDescriptor)
+        ));
+
+        // Only reading again sees the device's new value.
+        device.set_descriptor_value(&cccd, [1, 0]);
+        assert_eq!(device.read_descriptor_cached(&cccd).await?, vec![0, 0]);
+        assert_eq!(device.read_descriptor(&cccd).await?, vec![1, 0]);
+        device.write_descriptor(&cccd, &[2, 0]).await?;
+        assert_eq!(cached(&device), Some(vec![2, 0]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn signed_writes() -> Result<()> {
+        let (_manager, adapter) = scanning_adapter().await?;
+        let signed = characteristic(CharPropFlags::AUTHENTICATED_SIGNED_WRITES);
+        let device = adapter.advertise(Default::default()).unwrap();
+        device.set_services([service(&signed)]);
+        device.connect().await?;
+        device.discover_services().await?;
+
+        device
+            .write(&signed, &[1], WriteType::SignedWithoutResponse)
+            .await?;
+        assert_eq!(device.value(&signed), Some(vec![1]));
+        assert!(matches!(
+            device
+                .write(&signed, &[2], WriteType::WithoutResponse)
+                .await,
+            Err(Error::Att(crate::api::AttError::WriteNotPermitted))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn require_security() -> Result<()> {
+        let (_manager, adapter) = scanning_adapter().await?;
+        let device = adapter.advertise(Default::default()).unwrap();
+        device.set_pairing_security(SecurityLevel::AuthenticatedLESC);
+        device.connect().await?;
+        assert_eq!(device.security_level().await?, SecurityLevel::None);
+        device.require_security(SecurityLevel::None).await?;
+
+        // Just Works pairing isn't enough for a device which wants the PIN compared.
+        assert!(matches!(
+            device.require_security(SecurityLevel::Encrypted).await,
+            Err(Error::PermissionDenied)
+        ));
+        #[derive(Debug)]
+        struct ComparePin;
+        #[async_trait::async_trait]
+        impl PairingAgent for ComparePin {
+            async fn request(
+                &self,
+                _id: &PeripheralId,
+                request: PairingRequest,
+            ) -> PairingResponse {
+                match request {
+                    PairingRequest::ConfirmPin(_) => PairingResponse::Accept,
+                    _ => PairingResponse::Reject,
+                }
+            }
+        }
+        device
+            .require_security_with_agent(SecurityLevel::AuthenticatedLESC, Arc::new(ComparePin))
+            .await?;
+        assert_eq!(
+            device.security_level().await?,
+            SecurityLevel::AuthenticatedLESC
+        );
+
+        let other = adapter
+            .advertise(PeripheralProperties {
+                address: [1, 1, 1, 1, 1, 1].into(),
+                ..Default::default()
+            })
+            .unwrap();
+        other.connect().await?;
+        assert!(matches!(
+            other.require_security(SecurityLevel::Authenticated).await,
+            Err(Error::InsufficientSecurity {
+                required: SecurityLevel::Authenticated,
+                actual: SecurityLevel::Encrypted,
+            })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pair_with_oob() -> Result<()> {
+        let (_manager, adapter) = scanning_adapter().await?;
+        let device = adapter.advertise(Default::default()).unwrap();
+        device.set_oob_data(OobData::new([1; 16], [2; 16]));
+        device.connect().await?;
+
+        assert!(matches!(
+            device.pair_with_oob(OobData::new([1; 16], [3; 16])).await,
+            Err(Error::PermissionDenied)
+        ));
+        device.pair_with_oob(OobData::new([1; 16], [2; 16])).await?;
+        assert_eq!(
+            device.security_level().await?,
+            SecurityLevel::AuthenticatedLESC
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn resolve_name() -> Result<()> {
+        let (_manager, adapter) = scanning_adapter().await?;
+        let name = Characteristic {
+            uuid: DEVICE_NAME_CHARACTERISTIC_UUID,
+            service_uuid: GENERIC_ACCESS_SERVICE_UUID,
+            properties: CharPropFlags::READ,
+            descriptors: Default::default(),
+            handle: None,
+            value_handle: None,
+        };
+        let device = adapter.advertise(Default::default()).unwrap();
+        device.set_services([Service {
+            uuid: GENERIC_ACCESS_SERVICE_UUID,
+            primary: true,
+            characteristics: [name.clone()].into(),
+            included_services: Default::default(),
+            handle: None,
+        }]);
+        device.set_value(&name, *b"Thermometer\0");
+        assert_eq!(device.resolve_name().await?, None);
+
+        let mut events = adapter.events().await?;
+        device.connect().await?;
+        device.discover_services().await?;
+        assert_eq!(device.resolve_name().await?.as_deref(), Some("Thermometer"));
+        assert_eq!(
+            device.properties().await?.unwrap().local_name.as_deref(),
+            Some("Thermometer")
+        );
+        assert!(matches!(
+            events.next().await,
+            Some(CentralEvent::DeviceConnected(_))
+        ));
+        assert!(matches!(
+            events.next().await,
+            Some(CentralEvent::DeviceUpdated(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn notifications_and_disconnect() -> Result<()> {
+        let (_manager, adapter) = scanning_adapter().await?;
+        let characteristic = characteristic(CharPropFlags::NOTIFY);
+        let device = adapter.advertise(Default::default()).unwrap();
+        device.set_services([service(&characteristic)]);
+        let mut events = adapter.events().await?;
+        device.connect().await?;
+        device.discover_services().await?;
+
+        let mut notifications = device.subscribe_stream(&characteristic).await?;
+        assert!(device.is_subscribed(&characteristic).await?);
+        assert!(device.notify(&characteristic, [7]));
+        assert_eq!(notifications.next().await, Some(vec![7]));
+
+        device.simulate_disconnect();
+        assert!(!device.notify(&characteristic, [8]));
+        assert!(!device.is_connected().await?);
+        assert!(matches!(
+            events.next().await,
+            Some(CentralEvent::DeviceConnected(_))
+        ));
+        assert!(matches!(
+            events.next().await,
+            Some(CentralEvent::DeviceDisconnected(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn request_mtu() -> Result<()> {
+        let (_manager, adapter) = scanning_adapter().await?;
+        let device = adapter.advertise(Default::default()).unwrap();
+        assert!(matches!(
+            device.request_mtu(247).await,
+            Err(Error::NotConnected)
+        ));
+        let mut events = adapter.events().await?;
+        device.set_max_mtu(185);
+        device.connect().await?;
+        assert_eq!(device.request_mtu(247).await?, 185);
+        assert_eq!(device.request_mtu(185).await?, 185);
+        assert!(matches!(
+            events.next().await,
+            Some(CentralEvent::DeviceConnected(_))
+        ));
+        assert!(matches!(
+            events.next().await,
+            Some(CentralEvent::MtuChanged { mtu: 185, .. })
+        ));
+        device.simulate_disconnect();
+        assert!(matches!(
+            events.next().await,
+            Some(CentralEvent::DeviceDisconnected(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn peripheral_events() -> Result<()> {
+        let (_manager, adapter) = scanning_adapter().await?;
+        let advertised = |address: [u8; 6]| PeripheralProperties {
+            address: address.into(),
+            ..Default::default()
+        };
+        let device = adapter.advertise(advertised([1; 6])).unwrap();
+        let other = adapter.advertise(advertised([2; 6])).unwrap();
+        let mut events = device.events().await?;
+        device.set_max_mtu(185);
+        device.set_pairing_security(SecurityLevel::Encrypted);
+        other.connect().await?;
+        device.connect().await?;
+        device.discover_services().await?;
+        device.request_mtu(247).await?;
+        device.pair().await?;
+        adapter.advertise(advertised([1; 6])).unwrap();
+        device.disconnect().await?;
+        device.connect().await?;
+        device.simulate_disconnect();
+
+        let expected = [
+            PeripheralEvent::Connected,
+            PeripheralEvent::ServicesResolved,
+            PeripheralEvent::MtuChanged(185),
+            PeripheralEvent::PairingStateChanged(true),
+            PeripheralEvent::PropertiesUpdated,
+            PeripheralEvent::Disconnected(DisconnectReason::Local),
+            PeripheralEvent::Connected,
+            PeripheralEvent::Disconnected(DisconnectReason::Remote),
+        ];
+        for event in expected {
+            assert_eq!(events.next().await, Some(event));
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn observer() -> Result<()> {
+        use crate::api::observer::{Observer, OperationRecord};
+        use crate::platform::PeripheralId;
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Debug, Default)]
+        struct Recorded(Mutex<Vec<String>>);
+
+        impl Observer for Recorded {
+            fn advertisement(&self, _id: &PeripheralId) {
+                self.0.lock().unwrap().push("advertisement".to_string());
+            }
+
+            fn operation(&self, record: &OperationRecord) {
+                let outcome = if record.error.is_some() {
+                    "failed"
+                } else {
+                    "ok"
+                };
+                let entry = format!("{} {}", record.operation, outcome);
+                self.0.lock().unwrap().push(entry);
+            }
+
+            fn reconnect(&self, _id: &PeripheralId) {
+                self.0.lock().unwrap().push("reconnect".to_string());
+            }
+        }
+
+        let (_manager, adapter) = scanning_adapter().await?;
+        let observer = Arc::new(Recorded::default());
+        adapter.set_observer(Some(observer.clone()));
+        let device = adapter.advertise(Default::default()).unwrap();
+        device.fail_next(Error::DeviceNotFound);
+        assert!(device.connect().await.is_err());
+        device.connect().await?;
+        device.simulate_disconnect();
+        device.connect().await?;
+        adapter.set_observer(None);
+        device.discover_services().await?;
+        assert_eq!(
+            *observer.0.lock().unwrap(),
+            [
+                "advertisement",
+                "connect failed",
+                "connect ok",
+                "connect ok",
+                "reconnect"
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn trace_handler() -> Result<()> {
+        use crate::api::{observer::OperationRecord, Manager as _};
+        use std::sync::{Arc, Mutex};
+
+        let (manager, adapter) = scanning_adapter().await?;
+        let traced = Arc::new(Mutex::new(vec![]));
+        let sink = traced.clone();
+        manager.set_trace_handler(Some(Arc::new(move |record: &OperationRecord| {
+            sink.lock().unwrap().push((
+                record.operation,
+                record.attribute,
+                record.payload_len,
+                record.error.is_some(),
+            ));
+        })));
+        let characteristic = characteristic(CharPropFlags::READ | CharPropFlags::WRITE);
+        let device = adapter.advertise(Default::default()).unwrap();
+        device.set_services([service(&characteristic)]);
+        device.set_value(&characteristic, [1, 2, 3]);
+        device.connect().await?;
+        device.discover_services().await?;
+        device.read(&characteristic).await?;
+        device
+            .write(&characteristic, &[4], WriteType::WithResponse)
+            .await?;
+        // Adapters added later are traced too.
+        let later = manager.add_adapter();
+        later.start_scan(ScanFilter::default()).await?;
+        let other = later.advertise(Default::default()).unwrap();
+        other.fail_next(Error::DeviceNotFound);
+        assert!(other.connect().await.is_err());
+        manager.set_trace_handler(None);
+        device.read(&characteristic).await?;
+
+        let uuid = Some(characteristic.uuid);
+        assert_eq!(
+            *traced.lock().unwrap(),
+            [
+                ("connect", None, None, false),
+                ("discover services", None, None, false),
+                ("read", uuid, Some(3), false),
+                ("write", uuid, Some(1), false),
+                ("connect", None, None, true),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn latency() -> Result<()> {
+        let (_manager, adapter) = scanning_adapter().await?;
+        let device = adapter.advertise(Default::default()).unwrap();
+        device.set_latency(Duration::from_millis(100));
+        adapter.set_operation_timeout(Some(Duration::from_millis(10)));
+        assert!(matches!(
+            device.connect().await,
+            Err(Error::TimedOut {
+                operation: "connect",
+                ..
+            })
+        ));
+        adapter.set_operation_timeout(None);
+        device.connect().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn link_events() -> Result<()> {
+        let (_manager, adapter) = scanning_adapter().await?;
+        let device = adapter
+            .advertise(PeripheralProperties {
+                rssi: Some(-70),
+                ..Default::default()
+            })
+            .unwrap();
+        let read = characteristic(CharPropFlags::READ);
+        device.set_services([service(&read)]);
+        device.connect().await?;
+        let mut samples = device.link_events(Duration::from_millis(10)).await?;
+        let sample = samples.next().await.unwrap();
+        assert_eq!(sample.rssi, Some(-70));
+        assert_eq!(sample.latency, None);
+
+        device.set_latency(Duration::from_millis(20));
+        device.discover_services().await?;
+        device.read(&read).await?;
+        let sample = samples.next().await.unwrap();
+        assert!(sample.latency.unwrap() >= Duration::from_millis(20));
+        assert!(sample.since_last_response.is_some());
+
+        // The stream ends once the device disconnects.
+        device.disconnect().await?;
+        assert!(samples.next().await.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn data_length() -> Result<()> {
+        let (_manager, adapter) = scanning_adapter().await?;
+        assert_eq!(
+            adapter.max_data_length().await?,
+            DataLength::with_octets(251)
+        );
+        assert_eq!(adapter.suggested_data_length().await?, DataLength::MIN);
+        adapter
+            .set_suggested_data_length(DataLength::with_octets(251))
+            .await?;
+        assert_eq!(adapter.suggested_data_length().await?.time, 2120);
+
+        let device = adapter.advertise(Default::default()).unwrap();
+        assert!(matches!(
+            device.request_data_length(DataLength::MAX).await,
+            Err(Error::NotConnected)
+        ));
+        device.connect().await?;
+        device
+            .request_data_length(DataLength::with_octets(300))
+            .await?;
+        assert_eq!(device.data_length().octets, 251);
+        device.disconnect().await?;
+        assert_eq!(device.data_length(), DataLength::default());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn classic_discovery() -> Result<()> {
+        let (_manager, adapter) = scanning_adapter().await?;
+        let mut headset = ClassicDevice::new([1, 2, 3, 4, 5, 6].into());
+        headset.class = Some(0x240404);
+        assert_eq!(headset.major_class(), Some(0x04));
+        // A scan only for LE devices doesn't find it.
+        assert!(!adapter.inquiry_response(headset.clone()));
+
+        adapter
+            .start_scan(ScanFilter {
+                classic: true,
+                ..Default::default()
+            })
+            .await?;
+        let mut events = adapter.events().await?;
+        assert!(adapter.inquiry_response(headset.clone()));
+        assert!(matches!(
+            events.next().await,
+            Some(CentralEvent::ClassicDeviceDiscovered(device)) if device == headset
+        ));
+        assert!(adapter.peripherals().await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn connect_with_transport() -> Result<()> {
+        let (_manager, adapter) = scanning_adapter().await?;
+        let device = adapter.advertise(Default::default()).unwrap();
+        assert!(matches!(
+            device.connect_with_transport(Transport::BrEdr).await,
+            Err(Error::NotSupported(_))
+        ));
+        assert!(!device.is_connected().await?);
+        device.connect_with_transport(Transport::Le).await?;
+        assert!(device.is_connected().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn connected_peripherals() -> Result<()> {
+        let manager = Manager::new().await?;
+        let adapter = manager.add_adapter();
+        let watch = adapter.add_known_peripheral(PeripheralProperties {
+            address: [1, 2, 3, 4, 5, 6].into(),
+            ..Default::default()
+        });
+        watch.set_services([service(&characteristic(CharPropFlags::READ))]);
+        let other = adapter.add_known_peripheral(PeripheralProperties {
+            address: [6, 5, 4, 3, 2, 1].into(),
+            ..Default::default()
+        });
+        assert!(adapter.connected_peripherals(&[]).await?.is_empty());
+
+        watch.simulate_system_connect();
+        other.simulate_system_connect();
+        assert_eq!(adapter.connected_peripherals(&[]).await?.len(), 2);
+        let found = adapter.connected_peripherals(&[SERVICE_UUID]).await?;
+        assert_eq!(
+            found.iter().map(|p| p.id()).collect::<Vec<_>>(),
+            [watch.id()]
+        );
+        assert!(found[0].is_connected().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn accept_list() -> Result<()> {
+        let manager = Manager::new().await?;
+        let adapter = manager.add_adapter();
+        let properties = PeripheralProperties {
+            address: [1, 2, 3, 4, 5, 6].into(),
+            ..Default::default()
+        };
+        let sensor = adapter.add_known_peripheral(properties.clone());
+        let unknown = PeripheralId::synthetic("mock0", [6, 5, 4, 3, 2, 1].into());
+        assert!(matches!(
+            adapter.add_to_accept_list(&unknown).await,
+            Err(Error::DeviceNotFound)
+        ));
+
+        adapter.add_to_accept_list(&sensor.id()).await?;
+        let mut events = adapter.events().await?;
+        // The advertisement isn't reported without a scan, but the sensor is still connected to.
+        assert!(adapter.advertise(properties.clone()).is_none());
+        assert!(sensor.is_connected().await?);
+        assert!(matches!(
+            events.next().await,
+            Some(CentralEvent::DeviceConnected(id)) if id == sensor.id()
+        ));
+
+        sensor.simulate_disconnect();
+        adapter.remove_from_accept_list(&sensor.id()).await?;
+        adapter.advertise(properties);
+        assert!(!sensor.is_connected().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn discoverable_and_pairable() -> Result<()> {
+        let manager = Manager::new().await?;
+        let adapter = manager.add_adapter();
+        assert!(!adapter.is_discoverable());
+        adapter.set_discoverable(true, None).await?;
+        assert!(adapter.is_discoverable());
+        adapter
+            .set_discoverable(true, Some(Duration::from_millis(10)))
+            .await?;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!adapter.is_discoverable());
+
+        assert!(adapter.is_pairable());
+        adapter.set_pairable(false).await?;
+        assert!(!adapter.is_pairable());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn set_alias() -> Result<()> {
+        let manager = Manager::new().await?;
+        let adapter = manager.add_adapter();
+        adapter.set_alias("Kiosk").await?;
+        assert_eq!(
+            adapter.adapter_info().await?.alias.as_deref(),
+            Some("Kiosk")
+        );
+        adapter.set_alias("").await?;
+        assert_eq!(adapter.adapter_info().await?.alias, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn advertising() -> Result<()> {
+        let manager = Manager::new().await?;
+        let adapter = manager.add_adapter();
+        let ibeacon = Advertiser::ibeacon(Uuid::nil(), 1, 2, -59);
+        let advertising = adapter.start_advertising(ibeacon.clone()).await?;
+        assert_eq!(adapter.advertisements(), vec![ibeacon]);
+        drop(advertising);
+        assert!(adapter.advertisements().is_empty());
+
+        let too_long = Advertiser::new().with_local_name("A name far too long to advertise");
+        assert!(adapter.start_advertising(too_long).await.is_err());
+        assert!(adapter.advertisements().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn connect_when_available() -> Result<()> {
+        let manager = Manager::new().await?;
+        let adapter = manager.add_adapter();
+        let properties = PeripheralProperties {
+            address: [1, 2, 3, 4, 5, 6].into(),
+            ..Default::default()
+        };
+        let tag = adapter.add_known_peripheral(properties.clone());
+        tag.fail_next(Error::RuntimeError("Connection failed".to_string()));
+        let connecting = tokio::spawn({
+            let adapter = adapter.clone();
+            let target = ConnectTarget::Peripheral(tag.id());
+            async move { adapter.connect_when_available(target).await }
+        });
+        while adapter.scan_filter().is_none() {
+            tokio::task::yield_now().await;
+        }
+        // The first attempt fails, and the next advertisement is tried again.
+        adapter.advertise(properties.clone());
+        adapter.advertise(properties);
+        let connected = connecting.await.unwrap()?;
+        assert_eq!(connected.id(), tag.id());
+        assert!(tag.is_connected().await?);
+
+        // A filter connects to the first peripheral advertising one of its services.
+        let filter = ScanFilter {
+            services: vec![SERVICE_UUID],
+            ..Default::default()
+        };
+        let connecting = tokio::spawn({
+            let adapter = adapter.clone();
+            async move { adapter.connect_when_available(filter.into()).await }
+        });
+        tokio::task::yield_now().await;
+        adapter.advertise(PeripheralProperties {
+            address: [2, 2, 2, 2, 2, 2].into(),
+            ..Default::default()
+        });
+        adapter.advertise(PeripheralProperties {
+            address: [3, 3, 3, 3, 3, 3].into(),
+            services: vec![SERVICE_UUID],
+            ..Default::default()
+        });
+        let connected = connecting.await.unwrap()?;
+        assert_eq!(connected.address(), [3, 3, 3, 3, 3, 3].into());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn adapters() -> Result<()> {
+        let manager = Manager::new().await?;
+        let first = manager.add_adapter();
+        let mut events = manager.events().await?;
+        let second = manager.add_adapter();
+        manager.remove_adapter(&first);
+        assert_eq!(manager.adapters().await?.len(), 1);
+        assert_eq!(second.adapter_info().await?.id, "mock1");
+
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            ids.push(match events.next().await.unwrap() {
+                ManagerEvent::AdapterAdded(adapter) => {
+                    format!("+{}", adapter.adapter_info().await?.id)
+                }
+                ManagerEvent::AdapterRemoved(adapter) => {
+                    format!("-{}", adapter.adapter_info().await?.id)
+                }
+            });
+        }
+        assert_eq!(ids, ["+mock0", "+mock1", "-mock0"]);
+        Ok(())
+    }
+}
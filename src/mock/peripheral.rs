@@ -0,0 +1,810 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::script::{Operation, Performed, Reply, Script};
+use crate::api::{
+    self,
+    descriptor_cache::DescriptorCache,
+    link::{self, LinkSample},
+    operation::OperationContext,
+    pairing::{OobData, PairingAgent, PairingRequest, PairingResponse},
+    peripheral_events::PeripheralEventSender,
+    rssi::RssiStats,
+    AttError, BDAddr, CentralEvent, CharPropFlags, Characteristic, DataLength, Descriptor,
+    DisconnectReason, PeripheralEvent, PeripheralProperties, SecurityLevel, Service,
+    SubscriptionType, ValueNotification, WriteType, CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
+};
+use crate::platform::PeripheralId;
+use crate::time::{Instant, SystemTime};
+use crate::{rt, Error, Result};
+use async_trait::async_trait;
+use futures::stream::Stream;
+use log::trace;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{self, Debug, Formatter};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// The ATT MTU before any exchange, and the largest one the device accepts by default.
+const DEFAULT_MTU: u16 = 23;
+const DEFAULT_MAX_MTU: u16 = 517;
+/// The PIN the device shows for numeric comparison.
+const PAIRING_PIN: &str = "123456";
+
+/// Identifies a characteristic within a device's GATT database. The handle tells apart
+/// characteristics with the same UUID in the same service.
+type CharacteristicKey = (Uuid, Uuid, Option<u16>);
+/// Identifies a descriptor within a device's GATT database.
+type DescriptorKey = (Uuid, Uuid, Uuid, Option<u16>);
+
+fn characteristic_key(characteristic: &Characteristic) -> CharacteristicKey {
+    (
+        characteristic.service_uuid,
+        characteristic.uuid,
+        characteristic.value_handle,
+    )
+}
+
+fn descriptor_key(descriptor: &Descriptor) -> DescriptorKey {
+    (
+        descriptor.service_uuid,
+        descriptor.characteristic_uuid,
+        descriptor.uuid,
+        descriptor.handle,
+    )
+}
+
+#[derive(Debug)]
+struct State {
+    properties: PeripheralProperties,
+    connected: bool,
+    /// The device's GATT database.
+    services: BTreeSet<Service>,
+    /// Whether `discover_services` has been called since connecting.
+    discovered: bool,
+    values: HashMap<CharacteristicKey, Vec<u8>>,
+    descriptor_values: HashMap<DescriptorKey, Vec<u8>>,
+    subscriptions: HashMap<CharacteristicKey, SubscriptionType>,
+    latency: Duration,
+    next_error: Option<Error>,
+    mtu: u16,
+    max_mtu: u16,
+    data_length: DataLength,
+    /// How well the bond from pairing protects the link, which is `None` if not paired.
+    bond: SecurityLevel,
+    /// What pairing with the device achieves.
+    pairing_security: SecurityLevel,
+    /// The out-of-band pairing data the device shares, if any.
+    oob_data: Option<OobData>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            properties: Default::default(),
+            connected: false,
+            services: Default::default(),
+            discovered: false,
+            values: Default::default(),
+            descriptor_values: Default::default(),
+            subscriptions: Default::default(),
+            latency: Duration::ZERO,
+            next_error: None,
+            mtu: DEFAULT_MTU,
+            max_mtu: DEFAULT_MAX_MTU,
+            data_length: DataLength::MIN,
+            bond: SecurityLevel::None,
+            pairing_security: SecurityLevel::Encrypted,
+            oob_data: None,
+        }
+    }
+}
+
+impl State {
+    fn characteristic(&self, characteristic: &Characteristic) -> Result<Characteristic> {
+        self.services
+            .iter()
+            .filter(|service| service.uuid == characteristic.service_uuid)
+            .flat_map(|service| service.characteristics.iter())
+            .find(|c| characteristic_key(c) == characteristic_key(characteristic))
+            .cloned()
+            .ok_or(Error::NoSuchCharacteristic)
+    }
+
+    fn has_descriptor(&self, descriptor: &Descriptor) -> bool {
+        self.services
+            .iter()
+            .flat_map(|service| service.characteristics.iter())
+            .flat_map(|characteristic| characteristic.descriptors.iter())
+            .any(|d| descriptor_key(d) == descriptor_key(descriptor))
+    }
+
+    /// Sets the characteristic's Client Characteristic Configuration Descriptor, if it has one.
+    fn set_client_configuration(&mut self, characteristic: &Characteristic, value: u16) {
+        if let Some(descriptor) = characteristic
+            .descriptors
+            .iter()
+            .find(|descriptor| descriptor.uuid == CLIENT_CHARACTERISTIC_CONFIGURATION_UUID)
+        {
+            self.descriptor_values
+                .insert(descriptor_key(descriptor), value.to_le_bytes().to_vec());
+        }
+    }
+}
+
+struct Shared {
+    id: PeripheralId,
+    address: BDAddr,
+    state: Mutex<State>,
+    /// The descriptor values as the central has seen them, rather than as the device has them.
+    descriptor_cache: DescriptorCache,
+    notifications_channel: broadcast::Sender<ValueNotification>,
+    adapter_events: broadcast::Sender<CentralEvent>,
+    peripheral_events: PeripheralEventSender,
+    operation_context: OperationContext,
+    script: Arc<Script>,
+}
+
+/// Implementation of [api::Peripheral], which is created with
+/// [`Adapter::advertise`](super::Adapter::advertise) or
+/// [`Adapter::add_known_peripheral`](super::Adapter::add_known_peripheral).
+///
+/// Its other methods script the behaviour of the virtual device. Until
+/// [`set_services`](Self::set_services) is called it has no services.
+#[derive(Clone)]
+pub struct Peripheral {
+    shared: Arc<Shared>,
+}
+
+impl Peripheral {
+    pub(crate) fn new(
+        id: PeripheralId,
+        address: BDAddr,
+        adapter_events: broadcast::Sender<CentralEvent>,
+        peripheral_events: PeripheralEventSender,
+        operation_context: OperationContext,
+        script: Arc<Script>,
+    ) -> Self {
+        let (notifications_channel, _) = broadcast::channel(16);
+        let operation_context = operation_context.for_peripheral(id.clone());
+        Peripheral {
+            shared: Arc::new(Shared {
+                id,
+                address,
+                state: Mutex::new(State::default()),
+                descriptor_cache: DescriptorCache::default(),
+                notifications_channel,
+                adapter_events,
+                peripheral_events,
+                operation_context,
+                script,
+            }),
+        }
+    }
+
+    pub(crate) fn id_ref(&self) -> &PeripheralId {
+        &self.shared.id
+    }
+
+    #[cfg(feature = "record")]
+    pub(crate) fn script(&self) -> &Arc<Script> {
+        &self.shared.script
+    }
+
+    pub(crate) fn current_properties(&self) -> PeripheralProperties {
+        self.shared.state.lock().unwrap().properties.clone()
+    }
+
+    pub(crate) fn set_properties(&self, mut properties: PeripheralProperties) {
+        properties.address = self.shared.address;
+        self.shared.state.lock().unwrap().properties = properties;
+    }
+
+    fn emit(&self, event: CentralEvent) {
+        self.shared.peripheral_events.send_central(&event);
+        if let Err(lost) = self.shared.adapter_events.send(event) {
+            trace!("Lost central event, while nothing subscribed: {:?}", lost);
+        }
+    }
+
+    /// Waits for the latency, then fails with the injected error if there is one, or if the
+    /// operation needs a connection and there isn't one. If a result was scripted for the
+    /// operation, returns that instead, with a guard to hold until the operation is done.
+    async fn simulate(
+        &self,
+        operation: Operation,
+        needs_connection: bool,
+    ) -> Result<Option<(Reply, Performed)>> {
+        let latency = self.shared.state.lock().unwrap().latency;
+        if !latency.is_zero() {
+            rt::sleep(latency).await;
+        }
+        if let Some(error) = self.shared.state.lock().unwrap().next_error.take() {
+            return Err(error);
+        }
+        match self.shared.script.take(&operation) {
+            Some((Ok(reply), performed)) => Ok(Some((reply, performed))),
+            Some((Err(error), _)) => Err(error),
+            None if needs_connection && !self.shared.state.lock().unwrap().connected => {
+                Err(Error::NotConnected)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Sets the device's GATT database, which is what
+    /// [`discover_services`](api::Peripheral::discover_services) finds.
+    pub fn set_services(&self, services: impl IntoIterator<Item = Service>) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.services = services.into_iter().collect();
+        if state.discovered {
+            drop(state);
+            self.emit(CentralEvent::ServicesChanged(self.shared.id.clone()));
+        }
+    }
+
+    /// Sets the value which reading the characteristic returns.
+    pub fn set_value(&self, characteristic: &Characteristic, value: impl Into<Vec<u8>>) {
+        self.shared
+            .state
+            .lock()
+            .unwrap()
+            .values
+            .insert(characteristic_key(characteristic), value.into());
+    }
+
+    /// Returns the characteristic's current value, which is the last one set or written.
+    pub fn value(&self, characteristic: &Characteristic) -> Option<Vec<u8>> {
+        self.shared
+            .state
+            .lock()
+            .unwrap()
+            .values
+            .get(&characteristic_key(characteristic))
+            .cloned()
+    }
+
+    /// Sets the value which reading the descriptor returns.
+    pub fn set_descriptor_value(&self, descriptor: &Descriptor, value: impl Into<Vec<u8>>) {
+        self.shared
+            .state
+            .lock()
+            .unwrap()
+            .descriptor_values
+            .insert(descriptor_key(descriptor), value.into());
+    }
+
+    /// Returns the descriptor's current value, which is the last one set or written.
+    pub fn descriptor_value(&self, descriptor: &Descriptor) -> Option<Vec<u8>> {
+        self.shared
+            .state
+            .lock()
+            .unwrap()
+            .descriptor_values
+            .get(&descriptor_key(descriptor))
+            .cloned()
+    }
+
+    /// Sets the characteristic's value and, if it is connected and subscribed to, sends a
+    /// notification of it. Returns whether a notification was sent.
+    pub fn notify(&self, characteristic: &Characteristic, value: impl Into<Vec<u8>>) -> bool {
+        let value = value.into();
+        let key = characteristic_key(characteristic);
+        let mut state = self.shared.state.lock().unwrap();
+        state.values.insert(key, value.clone());
+        let Some(&subscription_type) = state.subscriptions.get(&key) else {
+            return false;
+        };
+        if !state.connected {
+            return false;
+        }
+        drop(state);
+        let _ = self.shared.notifications_channel.send(ValueNotification {
+            uuid: characteristic.uuid,
+            service_uuid: characteristic.service_uuid,
+            handle: characteristic.value_handle,
+            subscription_type,
+            value,
+            timestamp: Instant::now(),
+        });
+        true
+    }
+
+    /// Sets how long each connection and GATT operation takes.
+    pub fn set_latency(&self, latency: Duration) {
+        self.shared.state.lock().unwrap().latency = latency;
+    }
+
+    /// Sets the largest ATT MTU the device accepts in an MTU exchange. The default is 517.
+    pub fn set_max_mtu(&self, max_mtu: u16) {
+        self.shared.state.lock().unwrap().max_mtu = max_mtu;
+    }
+
+    /// Sets how well pairing with the device protects the link, which decides what the pairing
+    /// agent is asked: [`SecurityLevel::Encrypted`] pairs with "Just Works", the default,
+    /// [`SecurityLevel::Authenticated`] asks for a PIN and [`SecurityLevel::AuthenticatedLESC`]
+    /// for a PIN to be compared. [`SecurityLevel::None`] makes the device refuse to pair.
+    pub fn set_pairing_security(&self, level: SecurityLevel) {
+        self.shared.state.lock().unwrap().pairing_security = level;
+    }
+
+    /// Sets the out-of-band pairing data the device shares, which
+    /// [`pair_with_oob`](api::Peripheral::pair_with_oob) has to be given to pair with it.
+    pub fn set_oob_data(&self, data: OobData) {
+        self.shared.state.lock().unwrap().oob_data = Some(data);
+    }
+
+    /// Returns the data length of the connection, as last requested with
+    /// [`request_data_length`](api::Peripheral::request_data_length).
+    pub fn data_length(&self) -> DataLength {
+        self.shared.state.lock().unwrap().data_length
+    }
+
+    /// Makes the next connection or GATT operation fail with the given error.
+    pub fn fail_next(&self, error: Error) {
+        self.shared.state.lock().unwrap().next_error = Some(error);
+    }
+
+    /// Simulates the device being connected by the system or another app, so that
+    /// [`connected_peripherals`](crate::api::Central::connected_peripherals) finds it. No event is emitted, as it
+    /// wasn't this app which connected.
+    pub fn simulate_system_connect(&self) {
+        self.shared.state.lock().unwrap().connected = true;
+    }
+
+    /// Connects to the device, emitting [`CentralEvent::DeviceConnected`] if it wasn't connected.
+    pub(crate) fn simulate_connect(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        if !state.connected {
+            state.connected = true;
+            drop(state);
+            self.emit(CentralEvent::DeviceConnected(self.shared.id.clone()));
+        }
+    }
+
+    /// Returns whether the device is connected and has any of the given services, advertised or in
+    /// its GATT database, or any services if `services` is empty.
+    pub(crate) fn is_connected_with(&self, services: &[Uuid]) -> bool {
+        let state = self.shared.state.lock().unwrap();
+        state.connected
+            && (services.is_empty()
+                || services.iter().any(|uuid| {
+                    state.properties.services.contains(uuid)
+                        || state.services.iter().any(|service| service.uuid == *uuid)
+                }))
+    }
+
+    /// Returns whether the device is disconnected and was last seen before `cutoff`.
+    pub(crate) fn expired(&self, cutoff: SystemTime) -> bool {
+        let state = self.shared.state.lock().unwrap();
+        !state.connected
+            && state
+                .properties
+                .last_seen
+                .is_some_and(|last_seen| last_seen < cutoff)
+    }
+
+    /// Simulates the device disconnecting, for example by going out of range. This emits
+    /// [`CentralEvent::DeviceDisconnected`] if it was connected.
+    pub fn simulate_disconnect(&self) {
+        self.drop_connection(DisconnectReason::Remote);
+    }
+
+    fn drop_connection(&self, reason: DisconnectReason) {
+        let mut state = self.shared.state.lock().unwrap();
+        if !state.connected {
+            return;
+        }
+        self.shared
+            .peripheral_events
+            .set_disconnect_reason(&self.shared.id, reason);
+        state.connected = false;
+        state.discovered = false;
+        state.mtu = DEFAULT_MTU;
+        state.data_length = DataLength::MIN;
+        let subscriptions: Vec<_> = state.subscriptions.drain().map(|(key, _)| key).collect();
+        let subscribed: Vec<Characteristic> = state
+            .services
+            .iter()
+            .flat_map(|service| service.characteristics.iter())
+            .filter(|characteristic| subscriptions.contains(&characteristic_key(characteristic)))
+            .cloned()
+            .collect();
+        for characteristic in &subscribed {
+            state.set_client_configuration(characteristic, 0);
+        }
+        drop(state);
+        self.emit(CentralEvent::DeviceDisconnected(self.shared.id.clone()));
+    }
+}
+
+impl Debug for Peripheral {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Peripheral")
+            .field("id", &self.shared.id)
+            .field("address", &self.shared.address)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl api::Peripheral for Peripheral {
+    fn id(&self) -> PeripheralId {
+        self.shared.id.clone()
+    }
+
+    fn address(&self) -> BDAddr {
+        self.shared.address
+    }
+
+    async fn properties(&self) -> Result<Option<PeripheralProperties>> {
+        Ok(Some(self.shared.state.lock().unwrap().properties.clone()))
+    }
+
+    async fn resolve_name(&self) -> Result<Option<String>> {
+        let local_name = self
+            .shared
+            .state
+            .lock()
+            .unwrap()
+            .properties
+            .local_name
+            .clone();
+        if local_name.is_some() {
+            return Ok(local_name);
+        }
+        let name = api::read_device_name(self).await?;
+        if let Some(name) = &name {
+            let properties = {
+                let mut state = self.shared.state.lock().unwrap();
+                state.properties.local_name = Some(name.clone());
+                state.properties.clone()
+            };
+            self.shared
+                .peripheral_events
+                .send_discovery(&self.shared.id, properties, false);
+            self.emit(CentralEvent::DeviceUpdated(self.id()));
+        }
+        Ok(name)
+    }
+
+    fn rssi_stats(&self) -> Option<RssiStats> {
+        self.shared.operation_context.rssi().stats(&self.id())
+    }
+
+    fn services(&self) -> BTreeSet<Service> {
+        let state = self.shared.state.lock().unwrap();
+        if state.discovered {
+            self.shared.descriptor_cache.fill(state.services.clone())
+        } else {
+            BTreeSet::new()
+        }
+    }
+
+    async fn is_connected(&self) -> Result<bool> {
+        Ok(self.shared.state.lock().unwrap().connected)
+    }
+
+    async fn connect(&self) -> Result<()> {
+        self.shared
+            .operation_context
+            .run("connect", async {
+                let _scripted = self.simulate(Operation::Connect, false).await?;
+                self.simulate_connect();
+                Ok(())
+            })
+            .await
+    }
+
+    async fn disconnect(&self) -> Result<()> {
+        let _performed = match self.shared.script.take(&Operation::Disconnect) {
+            Some((Err(error), _)) => return Err(error),
+            scripted => scripted,
+        };
+        self.drop_connection(DisconnectReason::Local);
+        Ok(())
+    }
+
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = PeripheralEvent> + Send>>> {
+        Ok(self
+            .shared
+            .peripheral_events
+            .subscribe(self.shared.id.clone()))
+    }
+
+    async fn pair_with_agent(&self, agent: Arc<dyn PairingAgent>) -> Result<()> {
+        let request = {
+            let state = self.shared.state.lock().unwrap();
+            if !state.connected {
+                return Err(Error::NotConnected);
+            }
+            if state.bond != SecurityLevel::None {
+                return Ok(());
+            }
+            match state.pairing_security {
+                SecurityLevel::None => return Err(Error::PermissionDenied),
+                SecurityLevel::Encrypted => PairingRequest::Confirm,
+                SecurityLevel::Authenticated => PairingRequest::ProvidePin,
+                SecurityLevel::AuthenticatedLESC => {
+                    PairingRequest::ConfirmPin(PAIRING_PIN.to_string())
+                }
+            }
+        };
+        match agent.request(&self.shared.id, request).await {
+            PairingResponse::Reject => Err(Error::PermissionDenied),
+            PairingResponse::Accept | PairingResponse::AcceptWithPin(_) => {
+                let mut state = self.shared.state.lock().unwrap();
+                state.bond = state.pairing_security;
+                self.shared
+                    .peripheral_events
+                    .send(&self.shared.id, PeripheralEvent::PairingStateChanged(true));
+                Ok(())
+            }
+        }
+    }
+
+    async fn pair_with_oob(&self, data: OobData) -> Result<()> {
+        let mut state = self.shared.state.lock().unwrap();
+        if !state.connected {
+            return Err(Error::NotConnected);
+        }
+        if state.bond != SecurityLevel::None {
+            return Ok(());
+        }
+        // The values have to match what the device shared, but the address is optional.
+        match &state.oob_data {
+            Some(expected)
+                if expected.confirm == data.confirm && expected.random == data.random =>
+            {
+                state.bond = SecurityLevel::AuthenticatedLESC;
+                self.shared
+                    .peripheral_events
+                    .send(&self.shared.id, PeripheralEvent::PairingStateChanged(true));
+                Ok(())
+            }
+            _ => Err(Error::PermissionDenied),
+        }
+    }
+
+    async fn security_level(&self) -> Result<SecurityLevel> {
+        let state = self.shared.state.lock().unwrap();
+        if state.connected {
+            Ok(state.bond)
+        } else {
+            Err(Error::NotConnected)
+        }
+    }
+
+    async fn request_mtu(&self, mtu: u16) -> Result<u16> {
+        self.shared
+            .operation_context
+            .run("request MTU", async {
+                let _scripted = self.simulate(Operation::RequestMtu, true).await?;
+                let mut state = self.shared.state.lock().unwrap();
+                let mtu = mtu.min(state.max_mtu).max(DEFAULT_MTU);
+                if mtu != state.mtu {
+                    state.mtu = mtu;
+                    drop(state);
+                    self.emit(CentralEvent::MtuChanged {
+                        id: self.shared.id.clone(),
+                        mtu,
+                    });
+                }
+                Ok(mtu)
+            })
+            .await
+    }
+
+    async fn request_data_length(&self, length: DataLength) -> Result<()> {
+        self.shared
+            .operation_context
+            .run("request data length", async {
+                let _scripted = self.simulate(Operation::RequestDataLength, true).await?;
+                self.shared.state.lock().unwrap().data_length = DataLength {
+                    octets: length
+                        .octets
+                        .clamp(DataLength::MIN.octets, DataLength::MAX.octets),
+                    time: length
+                        .time
+                        .clamp(DataLength::MIN.time, DataLength::MAX.time),
+                };
+                Ok(())
+            })
+            .await
+    }
+
+    async fn link_events(
+        &self,
+        interval: Duration,
+    ) -> Result<Pin<Box<dyn Stream<Item = LinkSample> + Send>>> {
+        Ok(link::samples(
+            self.clone(),
+            self.shared.operation_context.clone(),
+            interval,
+        ))
+    }
+
+    async fn discover_services(&self) -> Result<()> {
+        self.shared
+            .operation_context
+            .run("discover services", async {
+                let scripted = self.simulate(Operation::DiscoverServices, true).await?;
+                let mut state = self.shared.state.lock().unwrap();
+                if let Some((reply, _performed)) = scripted {
+                    if let Some(services) = reply.into_services() {
+                        state.services = services;
+                    }
+                }
+                state.discovered = true;
+                self.shared
+                    .peripheral_events
+                    .send(&self.shared.id, PeripheralEvent::ServicesResolved);
+                Ok(())
+            })
+            .await
+    }
+
+    async fn write(
+        &self,
+        characteristic: &Characteristic,
+        data: &[u8],
+        write_type: WriteType,
+    ) -> Result<()> {
+        self.shared
+            .operation_context
+            .run_write("write", characteristic.uuid, data, async {
+                let scripted = self
+                    .simulate(Operation::write(characteristic), true)
+                    .await?;
+                let mut state = self.shared.state.lock().unwrap();
+                if let Some((_, _performed)) = scripted {
+                    state
+                        .values
+                        .insert(characteristic_key(characteristic), data.to_vec());
+                    return Ok(());
+                }
+                let characteristic = state.characteristic(characteristic)?;
+                if !characteristic.properties.contains(write_type.property()) {
+                    return Err(AttError::WriteNotPermitted.into());
+                }
+                state
+                    .values
+                    .insert(characteristic_key(&characteristic), data.to_vec());
+                Ok(())
+            })
+            .await
+    }
+
+    async fn read(&self, characteristic: &Characteristic) -> Result<Vec<u8>> {
+        self.shared
+            .operation_context
+            .run_read("read", characteristic.uuid, async {
+                let scripted = self.simulate(Operation::read(characteristic), true).await?;
+                if let Some((reply, _performed)) = scripted {
+                    return Ok(reply.into_value());
+                }
+                let state = self.shared.state.lock().unwrap();
+                let characteristic = state.characteristic(characteristic)?;
+                if !characteristic.properties.contains(CharPropFlags::READ) {
+                    return Err(AttError::ReadNotPermitted.into());
+                }
+                Ok(state
+                    .values
+                    .get(&characteristic_key(&characteristic))
+                    .cloned()
+                    .unwrap_or_default())
+            })
+            .await
+    }
+
+    async fn subscribe(&self, characteristic: &Characteristic) -> Result<()> {
+        self.shared
+            .operation_context
+            .run_on("subscribe", characteristic.uuid, async {
+                let scripted = self
+                    .simulate(Operation::subscribe(characteristic), true)
+                    .await?;
+                let mut state = self.shared.state.lock().unwrap();
+                let characteristic = match scripted {
+                    Some(_) => characteristic.clone(),
+                    None => state.characteristic(characteristic)?,
+                };
+                let subscription_type = SubscriptionType::preferred(characteristic.properties)
+                    .ok_or_else(|| {
+                        Error::NotSupported(
+                            "Characteristic doesn't support notify or indicate".to_string(),
+                        )
+                    })?;
+                let value = match subscription_type {
+                    SubscriptionType::Notify => 0x0001,
+                    SubscriptionType::Indicate => 0x0002,
+                };
+                state
+                    .subscriptions
+                    .insert(characteristic_key(&characteristic), subscription_type);
+                state.set_client_configuration(&characteristic, value);
+                Ok(())
+            })
+            .await
+    }
+
+    async fn unsubscribe(&self, characteristic: &Characteristic) -> Result<()> {
+        self.shared
+            .operation_context
+            .run_on("unsubscribe", characteristic.uuid, async {
+                let scripted = self
+                    .simulate(Operation::unsubscribe(characteristic), true)
+                    .await?;
+                let mut state = self.shared.state.lock().unwrap();
+                let characteristic = match scripted {
+                    Some(_) => characteristic.clone(),
+                    None => state.characteristic(characteristic)?,
+                };
+                state
+                    .subscriptions
+                    .remove(&characteristic_key(&characteristic));
+                state.set_client_configuration(&characteristic, 0);
+                Ok(())
+            })
+            .await
+    }
+
+    async fn notifications(&self) -> Result<Pin<Box<dyn Stream<Item = ValueNotification> + Send>>> {
+        let receiver = self.shared.notifications_channel.subscribe();
+        Ok(self.shared.operation_context.notifications_stream(receiver))
+    }
+
+    async fn write_descriptor(&self, descriptor: &Descriptor, data: &[u8]) -> Result<()> {
+        self.shared
+            .operation_context
+            .run_write("write descriptor", descriptor.uuid, data, async {
+                let scripted = self
+                    .simulate(Operation::write_descriptor(descriptor), true)
+                    .await?;
+                let mut state = self.shared.state.lock().unwrap();
+                if scripted.is_none() && !state.has_descriptor(descriptor) {
+                    return Err(AttError::AttributeNotFound.into());
+                }
+                state
+                    .descriptor_values
+                    .insert(descriptor_key(descriptor), data.to_vec());
+                self.shared.descriptor_cache.insert(descriptor, data);
+                Ok(())
+            })
+            .await
+    }
+
+    async fn read_descriptor(&self, descriptor: &Descriptor) -> Result<Vec<u8>> {
+        self.shared
+            .operation_context
+            .run_read("read descriptor", descriptor.uuid, async {
+                let scripted = self
+                    .simulate(Operation::read_descriptor(descriptor), true)
+                    .await?;
+                let value = match scripted {
+                    Some((reply, _performed)) => reply.into_value(),
+                    None => {
+                        let state = self.shared.state.lock().unwrap();
+                        if !state.has_descriptor(descriptor) {
+                            return Err(AttError::AttributeNotFound.into());
+                        }
+                        state
+                            .descriptor_values
+                            .get(&descriptor_key(descriptor))
+                            .cloned()
+                            .unwrap_or_default()
+                    }
+                };
+                self.shared.descriptor_cache.insert(descriptor, &value);
+                Ok(value)
+            })
+            .await
+    }
+}
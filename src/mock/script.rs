@@ -0,0 +1,154 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use crate::api::{Characteristic, Descriptor, Service};
+use crate::Result;
+use futures::channel::oneshot;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// An operation which the application can perform on a mock adapter or peripheral, and which the
+/// result can be scripted for.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Operation {
+    StartScan,
+    Connect,
+    Disconnect,
+    DiscoverServices,
+    RequestMtu,
+    RequestDataLength,
+    Read(Uuid, Uuid),
+    Write(Uuid, Uuid),
+    Subscribe(Uuid, Uuid),
+    Unsubscribe(Uuid, Uuid),
+    ReadDescriptor(Uuid, Uuid, Uuid),
+    WriteDescriptor(Uuid, Uuid, Uuid),
+}
+
+impl Operation {
+    pub(crate) fn read(characteristic: &Characteristic) -> Self {
+        Operation::Read(characteristic.service_uuid, characteristic.uuid)
+    }
+
+    pub(crate) fn write(characteristic: &Characteristic) -> Self {
+        Operation::Write(characteristic.service_uuid, characteristic.uuid)
+    }
+
+    pub(crate) fn subscribe(characteristic: &Characteristic) -> Self {
+        Operation::Subscribe(characteristic.service_uuid, characteristic.uuid)
+    }
+
+    pub(crate) fn unsubscribe(characteristic: &Characteristic) -> Self {
+        Operation::Unsubscribe(characteristic.service_uuid, characteristic.uuid)
+    }
+
+    pub(crate) fn read_descriptor(descriptor: &Descriptor) -> Self {
+        Operation::ReadDescriptor(
+            descriptor.service_uuid,
+            descriptor.characteristic_uuid,
+            descriptor.uuid,
+        )
+    }
+
+    pub(crate) fn write_descriptor(descriptor: &Descriptor) -> Self {
+        Operation::WriteDescriptor(
+            descriptor.service_uuid,
+            descriptor.characteristic_uuid,
+            descriptor.uuid,
+        )
+    }
+}
+
+/// What a scripted operation returns when it succeeds. Results are only scripted by replaying a
+/// recording, so without the `record` feature there are none.
+#[derive(Debug)]
+pub(crate) enum Reply {
+    /// The operation is simulated as usual.
+    #[cfg(feature = "record")]
+    Done,
+    /// The value read.
+    #[cfg(feature = "record")]
+    Value(Vec<u8>),
+    /// The services found by service discovery, which become the device's GATT database.
+    #[cfg(feature = "record")]
+    Services(BTreeSet<Service>),
+}
+
+impl Reply {
+    pub(crate) fn into_value(self) -> Vec<u8> {
+        #[cfg(feature = "record")]
+        if let Reply::Value(value) = self {
+            return value;
+        }
+        Vec::new()
+    }
+
+    pub(crate) fn into_services(self) -> Option<BTreeSet<Service>> {
+        #[cfg(feature = "record")]
+        if let Reply::Services(services) = self {
+            return Some(services);
+        }
+        None
+    }
+}
+
+#[derive(Debug)]
+struct Expectation {
+    result: Result<Reply>,
+    performed: oneshot::Sender<()>,
+}
+
+/// Tells whoever queued a result that the operation has been performed, when dropped at the end
+/// of the operation.
+#[derive(Debug)]
+pub(crate) struct Performed(Option<oneshot::Sender<()>>);
+
+impl Drop for Performed {
+    fn drop(&mut self) {
+        if let Some(sender) = self.0.take() {
+            let _ = sender.send(());
+        }
+    }
+}
+
+/// Results queued for operations, which are used instead of the simulated behaviour until they run
+/// out.
+#[derive(Debug, Default)]
+pub(crate) struct Script(Mutex<HashMap<Operation, VecDeque<Expectation>>>);
+
+impl Script {
+    /// Queues the result of the next time the operation is performed, after any already queued
+    /// for it. An error is returned as is. The returned receiver completes once the application
+    /// has performed the operation.
+    #[cfg(feature = "record")]
+    pub(crate) fn expect(
+        &self,
+        operation: Operation,
+        result: Result<Reply>,
+    ) -> oneshot::Receiver<()> {
+        let (performed, receiver) = oneshot::channel();
+        self.0
+            .lock()
+            .unwrap()
+            .entry(operation)
+            .or_default()
+            .push_back(Expectation { result, performed });
+        receiver
+    }
+
+    /// Takes the queued result for the operation, if there is one.
+    pub(crate) fn take(&self, operation: &Operation) -> Option<(Result<Reply>, Performed)> {
+        let mut expectations = self.0.lock().unwrap();
+        let queue = expectations.get_mut(operation)?;
+        let expectation = queue.pop_front()?;
+        if queue.is_empty() {
+            expectations.remove(operation);
+        }
+        Some((expectation.result, Performed(Some(expectation.performed))))
+    }
+}
@@ -1,23 +1,54 @@
 //! The `platform` module contains the platform-specific implementations of the various [`api`]
 //! traits. Refer for the `api` module for how to use them.
 
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", feature = "bluez"))]
 pub use crate::bluez::{
-    adapter::Adapter, manager::Manager, peripheral::Peripheral, peripheral::PeripheralId,
+    adapter::Adapter, agent::AgentCapability, agent::AgentRegistration, manager::Manager,
+    peripheral::Peripheral, peripheral::PeripheralId,
 };
 #[cfg(target_vendor = "apple")]
 pub use crate::corebluetooth::{
-    adapter::Adapter, manager::Manager, peripheral::Peripheral, peripheral::PeripheralId,
+    adapter::Adapter, adapter::RestoredPeripheral, adapter::RestoredState, manager::Authorization,
+    manager::Manager, peripheral::ConnectOptions, peripheral::Peripheral, peripheral::PeripheralId,
 };
 #[cfg(target_os = "android")]
 pub use crate::droidplug::{
-    adapter::Adapter, init, manager::Manager, peripheral::Peripheral, peripheral::PeripheralId,
+    adapter::Adapter, adapter::Permission, init, manager::Manager, peripheral::Peripheral,
+    peripheral::PeripheralId,
+};
+#[cfg(target_os = "freebsd")]
+pub use crate::freebsd::{
+    adapter::Adapter, manager::Manager, peripheral::Peripheral, peripheral::PeripheralId,
+};
+#[cfg(all(target_os = "linux", not(feature = "bluez"), feature = "hci"))]
+pub use crate::hci::{
+    adapter::Adapter, manager::Manager, peripheral::Peripheral, peripheral::PeripheralId,
+};
+#[cfg(target_arch = "wasm32")]
+pub use crate::wasm::{
+    adapter::Adapter, manager::Manager, peripheral::Peripheral, peripheral::PeripheralId,
 };
 #[cfg(target_os = "windows")]
 pub use crate::winrtble::{
     adapter::Adapter, manager::Manager, peripheral::Peripheral, peripheral::PeripheralId,
 };
 
+// Say which platforms have no backend yet, rather than failing with unresolved imports.
+#[cfg(all(target_os = "linux", not(any(feature = "bluez", feature = "hci"))))]
+compile_error!("btleplug needs the `bluez` or `hci` feature on Linux");
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "freebsd",
+    target_vendor = "apple",
+    target_os = "android",
+    target_os = "windows",
+    target_arch = "wasm32"
+)))]
+compile_error!(
+    "btleplug has no backend for this platform; it supports Linux, FreeBSD, Windows, macOS, iOS, \
+     Android and WebAssembly"
+);
+
 use crate::api::{self, Central};
 use static_assertions::assert_impl_all;
 use std::{
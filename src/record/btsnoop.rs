@@ -0,0 +1,627 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Writing recordings as btsnoop files of HCI packets with the H4 packet type in front.
+//!
+//! Advertisements are written as LE extended advertising reports. Service discovery isn't written,
+//! but the handles it finds are used in the ATT packets which come after it. Errors other than ATT
+//! errors have no response packet.
+
+use super::{Entry, Outcome, RecordedError};
+use crate::api::{
+    bleuuid::BleUuid, AddressType, AdvertisementType, BDAddr, CharPropFlags, Characteristic,
+    PeripheralProperties, CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
+};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// Microseconds from midnight on January 1st, 0 AD, which btsnoop timestamps count from, to the
+/// Unix epoch.
+const EPOCH_OFFSET: u64 = 0x00dc_ddb3_0f2f_8000;
+/// HCI packets preceded by their UART (H4) packet type.
+const DATALINK_H4: u32 = 1002;
+
+const H4_COMMAND: u8 = 0x01;
+const H4_ACL: u8 = 0x02;
+const H4_EVENT: u8 = 0x04;
+
+const EVENT_DISCONNECTION_COMPLETE: u8 = 0x05;
+const EVENT_COMMAND_COMPLETE: u8 = 0x0e;
+const EVENT_LE_META: u8 = 0x3e;
+const SUBEVENT_CONNECTION_COMPLETE: u8 = 0x01;
+const SUBEVENT_EXTENDED_ADVERTISING_REPORT: u8 = 0x0d;
+
+const COMMAND_DISCONNECT: u16 = 0x0406;
+const COMMAND_LE_SET_SCAN_ENABLE: u16 = 0x200c;
+
+const STATUS_SUCCESS: u8 = 0x00;
+const STATUS_UNSPECIFIED: u8 = 0x1f;
+const STATUS_CONNECTION_FAILED: u8 = 0x3e;
+const REASON_REMOTE_USER: u8 = 0x13;
+const REASON_LOCAL_HOST: u8 = 0x16;
+
+const L2CAP_CID_ATT: u16 = 0x0004;
+const ATT_ERROR_RESPONSE: u8 = 0x01;
+const ATT_READ_REQUEST: u8 = 0x0a;
+const ATT_READ_RESPONSE: u8 = 0x0b;
+const ATT_WRITE_REQUEST: u8 = 0x12;
+const ATT_WRITE_RESPONSE: u8 = 0x13;
+const ATT_HANDLE_VALUE_NOTIFICATION: u8 = 0x1b;
+const ATT_WRITE_COMMAND: u8 = 0x52;
+
+/// The most advertising data one extended advertising report can hold.
+const MAX_REPORT_DATA: usize = 229;
+
+/// Whether a packet went to or came from the controller.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Direction {
+    Sent,
+    Received,
+}
+
+#[derive(Debug)]
+struct Packet {
+    direction: Direction,
+    /// Whether it's a command or event rather than data.
+    control: bool,
+    bytes: Vec<u8>,
+}
+
+fn command(opcode: u16, parameters: &[u8]) -> Packet {
+    let mut bytes = vec![H4_COMMAND];
+    bytes.extend_from_slice(&opcode.to_le_bytes());
+    bytes.push(parameters.len() as u8);
+    bytes.extend_from_slice(parameters);
+    Packet {
+        direction: Direction::Sent,
+        control: true,
+        bytes,
+    }
+}
+
+fn event(code: u8, parameters: &[u8]) -> Packet {
+    let mut bytes = vec![H4_EVENT, code, parameters.len() as u8];
+    bytes.extend_from_slice(parameters);
+    Packet {
+        direction: Direction::Received,
+        control: true,
+        bytes,
+    }
+}
+
+fn command_complete(opcode: u16, status: u8) -> Packet {
+    let mut parameters = vec![1];
+    parameters.extend_from_slice(&opcode.to_le_bytes());
+    parameters.push(status);
+    event(EVENT_COMMAND_COMPLETE, &parameters)
+}
+
+/// An ATT PDU in an L2CAP frame on the given connection.
+fn att(connection: u16, direction: Direction, pdu: &[u8]) -> Packet {
+    let mut bytes = vec![H4_ACL];
+    // The first packet of a flushable L2CAP frame.
+    bytes.extend_from_slice(&(connection | 0x2000).to_le_bytes());
+    bytes.extend_from_slice(&(pdu.len() as u16 + 4).to_le_bytes());
+    bytes.extend_from_slice(&(pdu.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(&L2CAP_CID_ATT.to_le_bytes());
+    bytes.extend_from_slice(pdu);
+    Packet {
+        direction,
+        control: false,
+        bytes,
+    }
+}
+
+fn att_pdu(opcode: u8, handle: u16, value: &[u8]) -> Vec<u8> {
+    let mut pdu = vec![opcode];
+    pdu.extend_from_slice(&handle.to_le_bytes());
+    pdu.extend_from_slice(value);
+    pdu
+}
+
+/// The request for an operation on an attribute, followed by its response if the operation got
+/// one.
+fn att_exchange<T>(
+    connection: u16,
+    request: u8,
+    handle: u16,
+    value: &[u8],
+    result: &Outcome<T>,
+    response: impl FnOnce(&T) -> Vec<u8>,
+) -> Vec<Packet> {
+    let mut packets = vec![att(
+        connection,
+        Direction::Sent,
+        &att_pdu(request, handle, value),
+    )];
+    match result {
+        Ok(value) => packets.push(att(connection, Direction::Received, &response(value))),
+        Err(RecordedError::Att(code)) => {
+            let mut pdu = vec![ATT_ERROR_RESPONSE, request];
+            pdu.extend_from_slice(&handle.to_le_bytes());
+            pdu.push(*code);
+            packets.push(att(connection, Direction::Received, &pdu));
+        }
+        Err(_) => {}
+    }
+    packets
+}
+
+/// The address as sent over HCI, least significant byte first.
+fn address_bytes(address: BDAddr) -> [u8; 6] {
+    let mut bytes = address.into_inner();
+    bytes.reverse();
+    bytes
+}
+
+fn address_type_byte(address_type: Option<AddressType>) -> u8 {
+    match address_type {
+        Some(AddressType::Random) => 0x01,
+        _ => 0x00,
+    }
+}
+
+/// Puts the advertising data the properties were parsed from back together, as far as it can be.
+fn advertising_data(properties: &PeripheralProperties) -> Vec<u8> {
+    let mut data = vec![];
+    let mut push = |ad_type: u8, content: &[u8]| {
+        data.push(content.len() as u8 + 1);
+        data.push(ad_type);
+        data.extend_from_slice(content);
+    };
+    let uuid_bytes = |uuid: &Uuid| -> Vec<u8> {
+        match uuid.to_ble_u16() {
+            Some(short) => short.to_le_bytes().to_vec(),
+            None => uuid.as_bytes().iter().rev().copied().collect(),
+        }
+    };
+    let (short, long): (Vec<&Uuid>, Vec<&Uuid>) = properties
+        .services
+        .iter()
+        .partition(|uuid| uuid.to_ble_u16().is_some());
+    if !short.is_empty() {
+        push(
+            0x03,
+            &short.into_iter().flat_map(uuid_bytes).collect::<Vec<_>>(),
+        );
+    }
+    if !long.is_empty() {
+        push(
+            0x07,
+            &long.into_iter().flat_map(uuid_bytes).collect::<Vec<_>>(),
+        );
+    }
+    if let Some(name) = &properties.local_name {
+        push(0x09, name.as_bytes());
+    }
+    if let Some(level) = properties.tx_power_level {
+        push(0x0a, &[level as u8]);
+    }
+    if let Some(appearance) = properties.appearance {
+        push(0x19, &appearance.raw().to_le_bytes());
+    }
+    let mut service_data: Vec<_> = properties.service_data.iter().collect();
+    service_data.sort();
+    for (uuid, value) in service_data {
+        let ad_type = if uuid.to_ble_u16().is_some() {
+            0x16
+        } else {
+            0x21
+        };
+        push(ad_type, &[&uuid_bytes(uuid)[..], value].concat());
+    }
+    let mut manufacturer_data: Vec<_> = properties.manufacturer_data.iter().collect();
+    manufacturer_data.sort();
+    for (company_id, value) in manufacturer_data {
+        push(0xff, &[&company_id.to_le_bytes()[..], value].concat());
+    }
+    data
+}
+
+fn advertising_report(properties: &PeripheralProperties) -> Packet {
+    // Legacy PDUs, as an extended report describes them.
+    let event_type: u16 = match properties.advertisement_type {
+        Some(AdvertisementType::ConnectableUndirected) => 0x13,
+        Some(AdvertisementType::ConnectableDirected) => 0x15,
+        Some(AdvertisementType::ScannableUndirected) => 0x12,
+        Some(AdvertisementType::NonConnectableUndirected) => 0x10,
+        Some(AdvertisementType::ScanResponse) => 0x1b,
+        None if properties.connectable == Some(true) => 0x13,
+        None => 0x10,
+    };
+    let mut data = advertising_data(properties);
+    data.truncate(MAX_REPORT_DATA);
+    let mut parameters = vec![SUBEVENT_EXTENDED_ADVERTISING_REPORT, 1];
+    parameters.extend_from_slice(&event_type.to_le_bytes());
+    parameters.push(address_type_byte(properties.address_type));
+    parameters.extend_from_slice(&address_bytes(properties.address));
+    // The LE 1M PHY, and no secondary PHY or advertising set.
+    parameters.extend_from_slice(&[0x01, 0x00, 0xff]);
+    parameters.push(properties.tx_power_level.map_or(0x7f, |level| level as u8));
+    parameters.push(properties.rssi.map_or(0x7f, |rssi| rssi as u8));
+    // No periodic advertising or direct address.
+    parameters.extend_from_slice(&[0; 9]);
+    parameters.push(data.len() as u8);
+    parameters.extend_from_slice(&data);
+    event(EVENT_LE_META, &parameters)
+}
+
+/// Writes the entries of a recording as HCI packets to a btsnoop file.
+pub(super) struct Btsnoop<W> {
+    writer: W,
+    /// The connection handle made up for each peripheral, by its recorded ID.
+    connections: HashMap<String, u16>,
+    /// The address each peripheral last advertised with.
+    addresses: HashMap<String, (BDAddr, Option<AddressType>)>,
+    /// The value handles of the characteristics discovered on each peripheral, for the
+    /// notifications, which are recorded without them.
+    handles: HashMap<(String, Uuid, Uuid), u16>,
+}
+
+impl<W: Write> Btsnoop<W> {
+    /// Writes the file header.
+    pub(super) fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(b"btsnoop\0")?;
+        writer.write_all(&1u32.to_be_bytes())?;
+        writer.write_all(&DATALINK_H4.to_be_bytes())?;
+        writer.flush()?;
+        Ok(Btsnoop {
+            writer,
+            connections: HashMap::new(),
+            addresses: HashMap::new(),
+            handles: HashMap::new(),
+        })
+    }
+
+    /// Writes the packets for an entry recorded at `time`, and flushes them.
+    pub(super) fn write(&mut self, entry: &Entry, time: SystemTime) -> io::Result<()> {
+        let micros = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_micros() as u64);
+        let timestamp = micros + EPOCH_OFFSET;
+        for packet in self.packets(entry) {
+            let length = packet.bytes.len() as u32;
+            let flags = u32::from(packet.direction == Direction::Received)
+                | (u32::from(packet.control) << 1);
+            self.writer.write_all(&length.to_be_bytes())?;
+            self.writer.write_all(&length.to_be_bytes())?;
+            self.writer.write_all(&flags.to_be_bytes())?;
+            // No packets dropped.
+            self.writer.write_all(&0u32.to_be_bytes())?;
+            self.writer.write_all(&timestamp.to_be_bytes())?;
+            self.writer.write_all(&packet.bytes)?;
+        }
+        self.writer.flush()
+    }
+
+    fn connection(&mut self, peripheral: &str) -> u16 {
+        let next = self.connections.len() as u16 + 1;
+        *self
+            .connections
+            .entry(peripheral.to_string())
+            .or_insert(next)
+    }
+
+    fn learn(&mut self, peripheral: &str, characteristic: &Characteristic) {
+        if let Some(handle) = characteristic.value_handle {
+            self.handles.insert(
+                (
+                    peripheral.to_string(),
+                    characteristic.service_uuid,
+                    characteristic.uuid,
+                ),
+                handle,
+            );
+        }
+    }
+
+    fn value_handle(&mut self, peripheral: &str, characteristic: &Characteristic) -> u16 {
+        self.learn(peripheral, characteristic);
+        characteristic.value_handle.unwrap_or_default()
+    }
+
+    /// The write to the characteristic's Client Characteristic Configuration descriptor which
+    /// subscribing or unsubscribing takes.
+    fn client_configuration(
+        &mut self,
+        peripheral: &str,
+        characteristic: &Characteristic,
+        value: u16,
+        result: &Outcome<()>,
+    ) -> Vec<Packet> {
+        self.learn(peripheral, characteristic);
+        let handle = characteristic
+            .descriptors
+            .iter()
+            .find(|descriptor| descriptor.uuid == CLIENT_CHARACTERISTIC_CONFIGURATION_UUID)
+            .and_then(|descriptor| descriptor.handle)
+            .unwrap_or_default();
+        let connection = self.connection(peripheral);
+        att_exchange(
+            connection,
+            ATT_WRITE_REQUEST,
+            handle,
+            &value.to_le_bytes(),
+            result,
+            |_| vec![ATT_WRITE_RESPONSE],
+        )
+    }
+
+    fn packets(&mut self, entry: &Entry) -> Vec<Packet> {
+        match entry {
+            Entry::Advertisement {
+                peripheral,
+                properties,
+            } => {
+                self.addresses.insert(
+                    peripheral.clone(),
+                    (properties.address, properties.address_type),
+                );
+                vec![advertising_report(properties)]
+            }
+            Entry::Disconnected { peripheral } => {
+                let mut parameters = vec![STATUS_SUCCESS];
+                parameters.extend_from_slice(&self.connection(peripheral).to_le_bytes());
+                parameters.push(REASON_REMOTE_USER);
+                vec![event(EVENT_DISCONNECTION_COMPLETE, &parameters)]
+            }
+            Entry::StateUpdate { .. } => vec![],
+            Entry::Notification {
+                peripheral,
+                service_uuid,
+                uuid,
+                value,
+            } => {
+                let handle = self
+                    .handles
+                    .get(&(peripheral.clone(), *service_uuid, *uuid))
+                    .copied()
+                    .unwrap_or_default();
+                let connection = self.connection(peripheral);
+                vec![att(
+                    connection,
+                    Direction::Received,
+                    &att_pdu(ATT_HANDLE_VALUE_NOTIFICATION, handle, value),
+                )]
+            }
+            Entry::StartScan { result, .. } => vec![
+                command(COMMAND_LE_SET_SCAN_ENABLE, &[0x01, 0x00]),
+                command_complete(
+                    COMMAND_LE_SET_SCAN_ENABLE,
+                    if result.is_ok() {
+                        STATUS_SUCCESS
+                    } else {
+                        STATUS_UNSPECIFIED
+                    },
+                ),
+            ],
+            Entry::Connect { peripheral, result } => {
+                let (address, address_type) =
+                    self.addresses.get(peripheral).copied().unwrap_or_default();
+                let mut parameters = vec![
+                    SUBEVENT_CONNECTION_COMPLETE,
+                    if result.is_ok() {
+                        STATUS_SUCCESS
+                    } else {
+                        STATUS_CONNECTION_FAILED
+                    },
+                ];
+                parameters.extend_from_slice(&self.connection(peripheral).to_le_bytes());
+                // As central.
+                parameters.push(0x00);
+                parameters.push(address_type_byte(address_type));
+                parameters.extend_from_slice(&address_bytes(address));
+                // A 30 ms interval, no latency, a 5 s supervision timeout and the clock accuracy.
+                parameters.extend_from_slice(&[0x18, 0x00, 0x00, 0x00, 0xf4, 0x01, 0x00]);
+                vec![event(EVENT_LE_META, &parameters)]
+            }
+            Entry::Disconnect { peripheral, result } => {
+                let connection = self.connection(peripheral).to_le_bytes();
+                let mut packets = vec![command(
+                    COMMAND_DISCONNECT,
+                    &[connection[0], connection[1], REASON_REMOTE_USER],
+                )];
+                if result.is_ok() {
+                    packets.push(event(
+                        EVENT_DISCONNECTION_COMPLETE,
+                        &[
+                            STATUS_SUCCESS,
+                            connection[0],
+                            connection[1],
+                            REASON_LOCAL_HOST,
+                        ],
+                    ));
+                }
+                packets
+            }
+            Entry::DiscoverServices { peripheral, result } => {
+                for service in result.iter().flatten() {
+                    for characteristic in &service.characteristics {
+                        self.learn(peripheral, characteristic);
+                    }
+                }
+                vec![]
+            }
+            Entry::Read {
+                peripheral,
+                characteristic,
+                result,
+            } => {
+                let handle = self.value_handle(peripheral, characteristic);
+                att_exchange(
+                    self.connection(peripheral),
+                    ATT_READ_REQUEST,
+                    handle,
+                    &[],
+                    result,
+                    |value| [&[ATT_READ_RESPONSE][..], value].concat(),
+                )
+            }
+            Entry::Write {
+                peripheral,
+                characteristic,
+                data,
+                without_response,
+                result,
+            } => {
+                let handle = self.value_handle(peripheral, characteristic);
+                let connection = self.connection(peripheral);
+                if *without_response {
+                    vec![att(
+                        connection,
+                        Direction::Sent,
+                        &att_pdu(ATT_WRITE_COMMAND, handle, data),
+                    )]
+                } else {
+                    att_exchange(connection, ATT_WRITE_REQUEST, handle, data, result, |_| {
+                        vec![ATT_WRITE_RESPONSE]
+                    })
+                }
+            }
+            Entry::Subscribe {
+                peripheral,
+                characteristic,
+                result,
+            } => {
+                let value = if characteristic.properties.contains(CharPropFlags::NOTIFY) {
+                    0x0001
+                } else {
+                    0x0002
+                };
+                self.client_configuration(peripheral, characteristic, value, result)
+            }
+            Entry::Unsubscribe {
+                peripheral,
+                characteristic,
+                result,
+            } => self.client_configuration(peripheral, characteristic, 0x0000, result),
+            Entry::ReadDescriptor {
+                peripheral,
+                descriptor,
+                result,
+            } => att_exchange(
+                self.connection(peripheral),
+                ATT_READ_REQUEST,
+                descriptor.handle.unwrap_or_default(),
+                &[],
+                result,
+                |value| [&[ATT_READ_RESPONSE][..], value].concat(),
+            ),
+            Entry::WriteDescriptor {
+                peripheral,
+                descriptor,
+                data,
+                result,
+            } => att_exchange(
+                self.connection(peripheral),
+                ATT_WRITE_REQUEST,
+                descriptor.handle.unwrap_or_default(),
+                data,
+                result,
+                |_| vec![ATT_WRITE_RESPONSE],
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::bleuuid::uuid_from_u16;
+    use std::collections::BTreeSet;
+    use std::time::Duration;
+
+    /// Splits a btsnoop file into its header and the flags and bytes of each packet.
+    fn parse(file: &[u8]) -> (&[u8], Vec<(u32, &[u8])>) {
+        let (header, mut rest) = file.split_at(16);
+        let mut packets = vec![];
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes(rest[0..4].try_into().unwrap()) as usize;
+            let flags = u32::from_be_bytes(rest[8..12].try_into().unwrap());
+            packets.push((flags, &rest[24..24 + length]));
+            rest = &rest[24 + length..];
+        }
+        (header, packets)
+    }
+
+    #[test]
+    fn write() {
+        let mut btsnoop = Btsnoop::new(vec![]).unwrap();
+        let peripheral = "hci0/dev_01_02_03_04_05_06".to_string();
+        let properties = PeripheralProperties {
+            address: [1, 2, 3, 4, 5, 6].into(),
+            local_name: Some("Tag".to_string()),
+            services: vec![uuid_from_u16(0x180f)],
+            rssi: Some(-60),
+            ..Default::default()
+        };
+        let characteristic = Characteristic {
+            uuid: uuid_from_u16(0x2a19),
+            service_uuid: uuid_from_u16(0x180f),
+            properties: CharPropFlags::READ | CharPropFlags::NOTIFY,
+            descriptors: BTreeSet::new(),
+            handle: Some(0x0010),
+            value_handle: Some(0x0011),
+        };
+        let time = UNIX_EPOCH + Duration::from_secs(1);
+        for entry in [
+            Entry::Advertisement {
+                peripheral: peripheral.clone(),
+                properties,
+            },
+            Entry::Read {
+                peripheral: peripheral.clone(),
+                characteristic: characteristic.clone(),
+                result: Ok(vec![0x64]),
+            },
+            Entry::Read {
+                peripheral: peripheral.clone(),
+                characteristic: characteristic.clone(),
+                result: Err(RecordedError::Att(0x05)),
+            },
+            Entry::Notification {
+                peripheral,
+                service_uuid: characteristic.service_uuid,
+                uuid: characteristic.uuid,
+                value: vec![0x63],
+            },
+        ] {
+            btsnoop.write(&entry, time).unwrap();
+        }
+
+        let (header, packets) = parse(&btsnoop.writer);
+        assert_eq!(header, b"btsnoop\0\0\0\0\x01\0\0\x03\xea");
+        assert_eq!(
+            btsnoop.writer[32..40],
+            (EPOCH_OFFSET + 1_000_000).to_be_bytes()
+        );
+        assert_eq!(packets.len(), 6);
+
+        let (flags, report) = packets[0];
+        assert_eq!(flags, 0b11);
+        assert_eq!(report[..6], [H4_EVENT, EVENT_LE_META, 35, 0x0d, 1, 0x10]);
+        assert_eq!(report[8..14], [6, 5, 4, 3, 2, 1]);
+        assert_eq!(report[18], -60i8 as u8);
+        assert_eq!(
+            report[28..],
+            [9, 3, 0x03, 0x0f, 0x18, 4, 0x09, b'T', b'a', b'g']
+        );
+
+        assert_eq!(packets[1].1[..9], [H4_ACL, 1, 0x20, 7, 0, 3, 0, 4, 0]);
+        let attribute = |packet: (u32, &[u8])| (packet.0, packet.1[9..].to_vec());
+        assert_eq!(attribute(packets[1]), (0b00, vec![0x0a, 0x11, 0x00]));
+        assert_eq!(attribute(packets[2]), (0b01, vec![0x0b, 0x64]));
+        assert_eq!(attribute(packets[3]), (0b00, vec![0x0a, 0x11, 0x00]));
+        assert_eq!(
+            attribute(packets[4]),
+            (0b01, vec![0x01, 0x0a, 0x11, 0x00, 0x05])
+        );
+        assert_eq!(attribute(packets[5]), (0b01, vec![0x1b, 0x11, 0x00, 0x63]));
+    }
+}
@@ -0,0 +1,364 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Recording a live session with real devices to a file, and replaying it through the
+//! [`mock`](crate::mock) backend, to reproduce bugs with flaky devices deterministically.
+//!
+//! A [`Recorder`] wraps an adapter in a [`RecordingAdapter`], which works like the adapter while
+//! logging its advertisements, connection changes and notifications, and every operation the
+//! application performs along with its result. A [`Replay`] reads the log back and drives a
+//! [`mock::Manager`](crate::mock::Manager) with it, so the same application code sees the same
+//! devices, values and errors.
+//!
+//! Replays are driven by the application rather than the clock: each operation returns its
+//! recorded result once the application performs it, and what happened after it in the recording
+//! isn't replayed until then. The application must therefore perform the same operations in the
+//! same order as when it was recorded; if it does something else, the mock's usual simulated
+//! behaviour is used and the replay waits.
+//!
+//! The log is a file of JSON lines, one per [`Entry`], so it can be inspected or edited by hand.
+//! Alternatively, [`Recorder::create_btsnoop`] writes it as a btsnoop file of the HCI packets the
+//! entries correspond to, which Wireshark can open to debug interoperability problems with a
+//! device reported from the field. Those can't be replayed. As few platforms let applications see
+//! the HCI traffic itself, the packets are put back together from what the application sees:
+//! advertising reports for advertisements, HCI events for connections and disconnections, and ATT
+//! requests and responses for operations on characteristics and descriptors and for notifications.
+//!
+//! Only available with the `record` feature.
+//!
+//! ```no_run
+//! use btleplug::api::{Central, Manager as _};
+//! use btleplug::platform::Manager;
+//! use btleplug::record::{Recorder, Replay};
+//!
+//! async fn run_app(adapter: impl Central) -> btleplug::Result<()> {
+//!     // ...
+//! # Ok(())
+//! }
+//!
+//! # async fn example() -> btleplug::Result<()> {
+//! // In the field:
+//! let adapter = Manager::new().await?.adapters().await?.remove(0);
+//! let recorder = Recorder::create("session.jsonl")?;
+//! run_app(recorder.record(adapter).await?).await?;
+//!
+//! // In CI:
+//! let replay = Replay::open("session.jsonl")?;
+//! run_app(replay.adapter().clone()).await?;
+//! replay.finished().await;
+//! # Ok(())
+//! # }
+//! ```
+
+mod btsnoop;
+mod recorder;
+mod replay;
+
+pub use self::recorder::{Recorder, RecordingAdapter, RecordingPeripheral};
+pub use self::replay::Replay;
+
+use crate::api::{
+    AttError, CentralState, Characteristic, Descriptor, PeripheralProperties, ScanFilter, Service,
+};
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use serde_cr as serde;
+use std::collections::BTreeSet;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// One line of a recording. Peripherals are identified by their ID as a string, as IDs are
+/// platform-specific.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "serde_cr", tag = "type", rename_all = "snake_case")]
+pub enum Entry {
+    /// An advertisement was received, and the peripheral's properties became these.
+    Advertisement {
+        peripheral: String,
+        properties: PeripheralProperties,
+    },
+    /// The peripheral disconnected.
+    Disconnected { peripheral: String },
+    /// The adapter changed state.
+    StateUpdate { state: CentralState },
+    /// A notification or indication was received.
+    Notification {
+        peripheral: String,
+        service_uuid: Uuid,
+        uuid: Uuid,
+        value: Vec<u8>,
+    },
+    /// The application started a scan.
+    StartScan {
+        filter: ScanFilter,
+        result: Outcome<()>,
+    },
+    /// The application connected to the peripheral.
+    Connect {
+        peripheral: String,
+        result: Outcome<()>,
+    },
+    /// The application disconnected from the peripheral.
+    Disconnect {
+        peripheral: String,
+        result: Outcome<()>,
+    },
+    /// The application discovered the peripheral's services, which were found to be these.
+    DiscoverServices {
+        peripheral: String,
+        result: Outcome<BTreeSet<Service>>,
+    },
+    /// The application read a characteristic.
+    Read {
+        peripheral: String,
+        characteristic: Characteristic,
+        result: Outcome<Vec<u8>>,
+    },
+    /// The application wrote a characteristic.
+    Write {
+        peripheral: String,
+        characteristic: Characteristic,
+        data: Vec<u8>,
+        without_response: bool,
+        result: Outcome<()>,
+    },
+    /// The application subscribed to a characteristic.
+    Subscribe {
+        peripheral: String,
+        characteristic: Characteristic,
+        result: Outcome<()>,
+    },
+    /// The application unsubscribed from a characteristic.
+    Unsubscribe {
+        peripheral: String,
+        characteristic: Characteristic,
+        result: Outcome<()>,
+    },
+    /// The application read a descriptor.
+    ReadDescriptor {
+        peripheral: String,
+        descriptor: Descriptor,
+        result: Outcome<Vec<u8>>,
+    },
+    /// The application wrote a descriptor.
+    WriteDescriptor {
+        peripheral: String,
+        descriptor: Descriptor,
+        data: Vec<u8>,
+        result: Outcome<()>,
+    },
+}
+
+/// The recorded result of an operation.
+pub type Outcome<T> = std::result::Result<T, RecordedError>;
+
+/// An [`Error`] as recorded. The errors which applications commonly handle keep their type; the
+/// rest are replayed as [`Error::RuntimeError`] with the original message.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "serde_cr", rename_all = "snake_case")]
+pub enum RecordedError {
+    NotConnected,
+    DeviceNotFound,
+    NoSuchCharacteristic,
+    PermissionDenied,
+    TimedOut {
+        operation: String,
+        timeout: Duration,
+    },
+    /// An ATT error, by its code.
+    Att(u8),
+    Other(String),
+}
+
+/// The operations which [`Error::TimedOut`] is reported for, so that a recorded timeout can be
+/// given back its `&'static str`.
+const TIMED_OUT_OPERATIONS: &[&str] = &[
+    "connect",
+    "discover services",
+    "read",
+    "write",
+    "subscribe",
+    "unsubscribe",
+    "read descriptor",
+    "write descriptor",
+];
+
+impl From<&Error> for RecordedError {
+    fn from(error: &Error) -> Self {
+        match error {
+            Error::NotConnected => RecordedError::NotConnected,
+            Error::DeviceNotFound => RecordedError::DeviceNotFound,
+            Error::NoSuchCharacteristic => RecordedError::NoSuchCharacteristic,
+            Error::PermissionDenied => RecordedError::PermissionDenied,
+            Error::TimedOut { operation, timeout } => RecordedError::TimedOut {
+                operation: operation.to_string(),
+                timeout: *timeout,
+            },
+            Error::Att(error) => RecordedError::Att(error.code()),
+            error => RecordedError::Other(error.to_string()),
+        }
+    }
+}
+
+impl From<RecordedError> for Error {
+    fn from(error: RecordedError) -> Self {
+        match error {
+            RecordedError::NotConnected => Error::NotConnected,
+            RecordedError::DeviceNotFound => Error::DeviceNotFound,
+            RecordedError::NoSuchCharacteristic => Error::NoSuchCharacteristic,
+            RecordedError::PermissionDenied => Error::PermissionDenied,
+            RecordedError::TimedOut { operation, timeout } => {
+                match TIMED_OUT_OPERATIONS.iter().find(|&&o| o == operation) {
+                    Some(operation) => Error::TimedOut { operation, timeout },
+                    None => {
+                        Error::RuntimeError(format!("{} timed out after {:?}", operation, timeout))
+                    }
+                }
+            }
+            RecordedError::Att(code) => Error::Att(AttError::from(code)),
+            RecordedError::Other(message) => Error::RuntimeError(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{
+        Central, CharPropFlags, Peripheral as _, WriteType,
+        CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
+    };
+    use futures::StreamExt;
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+
+    /// A writer which keeps what is written to it, shared with the test.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn characteristic() -> Characteristic {
+        let uuid = Uuid::from_u128(2);
+        let service_uuid = Uuid::from_u128(1);
+        Characteristic {
+            uuid,
+            service_uuid,
+            properties: CharPropFlags::READ | CharPropFlags::WRITE | CharPropFlags::NOTIFY,
+            descriptors: [Descriptor {
+                uuid: CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
+                service_uuid,
+                characteristic_uuid: uuid,
+                handle: None,
+                value: None,
+            }]
+            .into(),
+            handle: None,
+            value_handle: None,
+        }
+    }
+
+    /// The application under test: connects to the first device it finds, writes, reads and waits
+    /// for a notification.
+    async fn app(
+        adapter: impl Central,
+    ) -> crate::Result<(Vec<u8>, Vec<u8>, crate::Result<Vec<u8>>)> {
+        let mut events = adapter.events().await?;
+        adapter.start_scan(ScanFilter::default()).await?;
+        let id = loop {
+            if let Some(crate::api::CentralEvent::DeviceDiscovered(id)) = events.next().await {
+                break id;
+            }
+        };
+        let peripheral = adapter.peripheral(&id).await?;
+        peripheral.connect().await?;
+        peripheral.discover_services().await?;
+        let characteristic = peripheral.characteristics().into_iter().next().unwrap();
+        peripheral
+            .write(&characteristic, &[1], WriteType::WithResponse)
+            .await?;
+        let read = peripheral.read(&characteristic).await?;
+        let mut notifications = peripheral.subscribe_stream(&characteristic).await?;
+        let notification = notifications.next().await.unwrap();
+        let failed = peripheral.read(&characteristic).await;
+        Ok((read, notification, failed))
+    }
+
+    #[tokio::test]
+    async fn record_and_replay() -> crate::Result<()> {
+        // Record a session with a mock device standing in for a real one.
+        let manager = crate::mock::Manager::new().await?;
+        let adapter = manager.add_adapter();
+        let buffer = Buffer::default();
+        let recorder = Recorder::new(buffer.clone());
+        let recording = recorder.record(adapter.clone()).await?;
+        let session = tokio::spawn(app(recording));
+
+        while adapter.scan_filter().is_none() {
+            tokio::task::yield_now().await;
+        }
+        let device = adapter
+            .advertise(PeripheralProperties {
+                address: [1, 2, 3, 4, 5, 6].into(),
+                local_name: Some("Flaky".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        device.set_services([Service {
+            uuid: characteristic().service_uuid,
+            primary: true,
+            characteristics: [characteristic()].into(),
+            included_services: BTreeSet::new(),
+            handle: None,
+        }]);
+        while !device
+            .is_subscribed(&characteristic())
+            .await
+            .unwrap_or(false)
+        {
+            tokio::task::yield_now().await;
+        }
+        device.notify(&characteristic(), [9]);
+        device.fail_next(Error::Att(AttError::InsufficientEncryption));
+        let recorded = session.await.unwrap()?;
+        assert_eq!(recorded.0, vec![1]);
+        assert_eq!(recorded.1, vec![9]);
+        drop(recorder);
+
+        // Replay it, and check the application sees the same thing.
+        let log = buffer.0.lock().unwrap().clone();
+        let replay = Replay::from_reader(&log[..])?;
+        let replayed = app(replay.adapter().clone()).await?;
+        replay.finished().await;
+        assert_eq!(replayed.0, recorded.0);
+        assert_eq!(replayed.1, recorded.1);
+        assert!(matches!(
+            replayed.2,
+            Err(Error::Att(AttError::InsufficientEncryption))
+        ));
+        let peripheral = &replay.adapter().peripherals().await?[0];
+        assert_eq!(
+            peripheral
+                .properties()
+                .await?
+                .unwrap()
+                .local_name
+                .as_deref(),
+            Some("Flaky")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,718 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::btsnoop::Btsnoop;
+use super::{Entry, Outcome, RecordedError};
+use crate::api::{
+    advertiser::{Advertiser, Advertising},
+    link::LinkSample,
+    observer::Observer,
+    pairing::{OobData, PairingAgent},
+    rpa,
+    rssi::{RssiStats, RssiTracking},
+    AdapterInfo, BDAddr, Central, CentralEvent, CentralState, Characteristic, ClientConfiguration,
+    ConnectionPriority, DataLength, Descriptor, Peripheral, PeripheralEvent, PeripheralProperties,
+    ScanFilter, ScanOptions, SecurityLevel, Service, SubscriptionType, Transport,
+    ValueNotification, WriteType, CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
+};
+use crate::platform::PeripheralId;
+use crate::{rt, Error, Result};
+use async_trait::async_trait;
+use futures::stream::{Stream, StreamExt};
+use log::warn;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::{self, Debug, Formatter};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use uuid::Uuid;
+
+/// How many events or notifications are kept for an application which is slow to read them, before
+/// the oldest are dropped.
+const CHANNEL_CAPACITY: usize = 256;
+
+/// What a recording is written as.
+enum Output {
+    /// JSON lines, which can be replayed.
+    Json(Box<dyn Write + Send>),
+    Btsnoop(Btsnoop<Box<dyn Write + Send>>),
+}
+
+struct Inner {
+    output: Mutex<Output>,
+    /// The peripherals which an advertisement has been recorded for.
+    advertised: Mutex<HashSet<String>>,
+    /// The channels which each peripheral's recorded notifications are passed on to the
+    /// application through.
+    notifications: Mutex<HashMap<String, broadcast::Sender<ValueNotification>>>,
+    tasks: Mutex<Vec<rt::Task>>,
+    /// Held while a scan is being started, so that advertisements received meanwhile are
+    /// recorded after it, as a replay can't deliver them before.
+    starting_scan: tokio::sync::Mutex<()>,
+}
+
+impl Inner {
+    fn write(&self, entry: &Entry) {
+        let result = match &mut *self.output.lock().unwrap() {
+            Output::Json(writer) => {
+                let mut line = match serde_json::to_vec(entry) {
+                    Ok(line) => line,
+                    Err(e) => {
+                        warn!("Failed to serialize {:?}: {}", entry, e);
+                        return;
+                    }
+                };
+                line.push(b'\n');
+                writer.write_all(&line).and_then(|()| writer.flush())
+            }
+            Output::Btsnoop(btsnoop) => btsnoop.write(entry, SystemTime::now()),
+        };
+        if let Err(e) = result {
+            warn!("Failed to write recording: {}", e);
+        }
+    }
+
+    fn advertisement(&self, peripheral: String, properties: PeripheralProperties) {
+        self.advertised.lock().unwrap().insert(peripheral.clone());
+        self.write(&Entry::Advertisement {
+            peripheral,
+            properties,
+        });
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        for task in self.tasks.get_mut().unwrap().drain(..) {
+            task.abort();
+        }
+    }
+}
+
+/// Records a session to a file of JSON lines, which [`Replay`](super::Replay) can play back.
+///
+/// Recording stops once the recorder and all the adapters and peripherals it returned have been
+/// dropped. Each entry is flushed as it is written, so a recording survives the application
+/// crashing.
+#[derive(Clone)]
+pub struct Recorder {
+    inner: Arc<Inner>,
+}
+
+impl Recorder {
+    /// Creates a recorder which writes to `writer`.
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self::with_output(Output::Json(Box::new(writer)))
+    }
+
+    /// Creates a recorder which writes to `writer` in the btsnoop format, for opening in Wireshark
+    /// to debug problems with devices, rather than replaying. See the [`record`](super) module
+    /// for what is written.
+    pub fn new_btsnoop(writer: impl Write + Send + 'static) -> Result<Self> {
+        let btsnoop = Btsnoop::new(Box::new(writer) as Box<dyn Write + Send>)
+            .map_err(|e| Error::Other(e.into()))?;
+        Ok(Self::with_output(Output::Btsnoop(btsnoop)))
+    }
+
+    fn with_output(output: Output) -> Self {
+        Recorder {
+            inner: Arc::new(Inner {
+                output: Mutex::new(output),
+                advertised: Mutex::new(HashSet::new()),
+                notifications: Mutex::new(HashMap::new()),
+                tasks: Mutex::new(Vec::new()),
+                starting_scan: tokio::sync::Mutex::new(()),
+            }),
+        }
+    }
+
+    /// Creates a recorder which writes to the file at `path`, replacing it if it exists.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::create(path).map_err(|e| Error::Other(e.into()))?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+
+    /// Creates a recorder which writes to the btsnoop file at `path`, replacing it if it exists.
+    pub fn create_btsnoop(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::create(path).map_err(|e| Error::Other(e.into()))?;
+        Self::new_btsnoop(BufWriter::new(file))
+    }
+
+    /// Starts recording `adapter`. The returned adapter should be used in its place; only what is
+    /// done through it, and the events and notifications it passes on, is recorded.
+    ///
+    /// A replay has a single adapter, so only one adapter should be recorded to each file.
+    pub async fn record<A: Central + 'static>(&self, adapter: A) -> Result<RecordingAdapter<A>> {
+        let mut events = adapter.events().await?;
+        let (events_channel, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let recorder = Arc::downgrade(&self.inner);
+        let sender = events_channel.clone();
+        let inner_adapter = adapter.clone();
+        // Events are recorded before the application sees them, so that anything it does in
+        // response comes after them in the recording.
+        let task = rt::spawn(async move {
+            while let Some(event) = events.next().await {
+                let Some(recorder) = recorder.upgrade() else {
+                    break;
+                };
+                let _starting_scan = recorder.starting_scan.lock().await;
+                record_event(&recorder, &inner_adapter, &event).await;
+                let _ = sender.send(event);
+            }
+        });
+        self.inner.tasks.lock().unwrap().push(task);
+        Ok(RecordingAdapter {
+            adapter,
+            recorder: self.clone(),
+            events_channel,
+        })
+    }
+
+    fn write(&self, entry: Entry) {
+        self.inner.write(&entry);
+    }
+
+    /// Records the peripheral's current properties, if no advertisement has been recorded for it
+    /// yet, so that a replay knows about it before the application uses it.
+    async fn introduce<P: Peripheral>(&self, peripheral: &P) {
+        let id = peripheral.id().to_string();
+        if self.inner.advertised.lock().unwrap().contains(&id) {
+            return;
+        }
+        if let Ok(Some(properties)) = peripheral.properties().await {
+            self.inner.advertisement(id, properties);
+        }
+    }
+}
+
+impl Debug for Recorder {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Recorder").finish_non_exhaustive()
+    }
+}
+
+async fn record_event<A: Central>(recorder: &Inner, adapter: &A, event: &CentralEvent) {
+    match event {
+        CentralEvent::DeviceDiscovered(id) | CentralEvent::DeviceUpdated(id) => {
+            let Ok(peripheral) = adapter.peripheral(id).await else {
+                return;
+            };
+            if let Ok(Some(properties)) = peripheral.properties().await {
+                recorder.advertisement(id.to_string(), properties);
+            }
+        }
+        CentralEvent::DeviceDisconnected(id) => recorder.write(&Entry::Disconnected {
+            peripheral: id.to_string(),
+        }),
+        CentralEvent::StateUpdate(state) => recorder.write(&Entry::StateUpdate {
+            state: state.clone(),
+        }),
+        _ => {}
+    }
+}
+
+fn outcome<T, U>(result: &Result<T>, value: impl FnOnce(&T) -> U) -> Outcome<U> {
+    result.as_ref().map(value).map_err(RecordedError::from)
+}
+
+/// An adapter which records what happens through it, returned by [`Recorder::record`].
+#[derive(Clone, Debug)]
+pub struct RecordingAdapter<A> {
+    adapter: A,
+    recorder: Recorder,
+    events_channel: broadcast::Sender<CentralEvent>,
+}
+
+impl<A: Central> RecordingAdapter<A> {
+    /// Returns the adapter being recorded, for calls which shouldn't be recorded.
+    pub fn inner(&self) -> &A {
+        &self.adapter
+    }
+
+    async fn wrap(&self, peripheral: A::Peripheral) -> RecordingPeripheral<A::Peripheral> {
+        self.recorder.introduce(&peripheral).await;
+        RecordingPeripheral {
+            id: peripheral.id().to_string(),
+            peripheral,
+            recorder: self.recorder.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl<A: Central> Central for RecordingAdapter<A> {
+    type Peripheral = RecordingPeripheral<A::Peripheral>;
+
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = CentralEvent> + Send>>> {
+        let receiver = self.events_channel.subscribe();
+        Ok(Box::pin(
+            BroadcastStream::new(receiver).filter_map(|x| async move { x.ok() }),
+        ))
+    }
+
+    async fn start_scan(&self, filter: ScanFilter) -> Result<()> {
+        let _starting_scan = self.recorder.inner.starting_scan.lock().await;
+        let result = self.adapter.start_scan(filter.clone()).await;
+        self.recorder.write(Entry::StartScan {
+            filter,
+            result: outcome(&result, |_| ()),
+        });
+        result
+    }
+
+    async fn start_scan_with_options(
+        &self,
+        filter: ScanFilter,
+        options: ScanOptions,
+    ) -> Result<()> {
+        let _starting_scan = self.recorder.inner.starting_scan.lock().await;
+        let result = self
+            .adapter
+            .start_scan_with_options(filter.clone(), options)
+            .await;
+        self.recorder.write(Entry::StartScan {
+            filter,
+            result: outcome(&result, |_| ()),
+        });
+        result
+    }
+
+    async fn stop_scan(&self) -> Result<()> {
+        self.adapter.stop_scan().await
+    }
+
+    async fn peripherals(&self) -> Result<Vec<Self::Peripheral>> {
+        let mut peripherals = Vec::new();
+        for peripheral in self.adapter.peripherals().await? {
+            peripherals.push(self.wrap(peripheral).await);
+        }
+        Ok(peripherals)
+    }
+
+    async fn peripheral(&self, id: &PeripheralId) -> Result<Self::Peripheral> {
+        let peripheral = self.adapter.peripheral(id).await?;
+        Ok(self.wrap(peripheral).await)
+    }
+
+    async fn connected_peripherals(&self, services: &[Uuid]) -> Result<Vec<Self::Peripheral>> {
+        let mut peripherals = Vec::new();
+        for peripheral in self.adapter.connected_peripherals(services).await? {
+            peripherals.push(self.wrap(peripheral).await);
+        }
+        Ok(peripherals)
+    }
+
+    async fn peripheral_by_address(&self, address: BDAddr) -> Result<Self::Peripheral> {
+        let peripheral = self.adapter.peripheral_by_address(address).await?;
+        Ok(self.wrap(peripheral).await)
+    }
+
+    async fn add_peripheral(&self, address: &PeripheralId) -> Result<Self::Peripheral> {
+        let peripheral = self.adapter.add_peripheral(address).await?;
+        Ok(self.wrap(peripheral).await)
+    }
+
+    async fn remove_peripheral(&self, id: &PeripheralId) -> Result<()> {
+        self.adapter.remove_peripheral(id).await
+    }
+
+    async fn adapter_info(&self) -> Result<AdapterInfo> {
+        self.adapter.adapter_info().await
+    }
+
+    async fn adapter_state(&self) -> Result<CentralState> {
+        self.adapter.adapter_state().await
+    }
+
+    async fn is_powered(&self) -> Result<bool> {
+        self.adapter.is_powered().await
+    }
+
+    async fn add_to_accept_list(&self, id: &PeripheralId) -> Result<()> {
+        self.adapter.add_to_accept_list(id).await
+    }
+
+    async fn remove_from_accept_list(&self, id: &PeripheralId) -> Result<()> {
+        self.adapter.remove_from_accept_list(id).await
+    }
+
+    async fn max_data_length(&self) -> Result<DataLength> {
+        self.adapter.max_data_length().await
+    }
+
+    async fn suggested_data_length(&self) -> Result<DataLength> {
+        self.adapter.suggested_data_length().await
+    }
+
+    async fn set_suggested_data_length(&self, length: DataLength) -> Result<()> {
+        self.adapter.set_suggested_data_length(length).await
+    }
+
+    async fn set_powered(&self, powered: bool) -> Result<()> {
+        self.adapter.set_powered(powered).await
+    }
+
+    async fn set_discoverable(&self, discoverable: bool, timeout: Option<Duration>) -> Result<()> {
+        self.adapter.set_discoverable(discoverable, timeout).await
+    }
+
+    async fn set_alias(&self, alias: &str) -> Result<()> {
+        self.adapter.set_alias(alias).await
+    }
+
+    async fn set_pairable(&self, pairable: bool) -> Result<()> {
+        self.adapter.set_pairable(pairable).await
+    }
+
+    async fn start_advertising(&self, advertiser: Advertiser) -> Result<Advertising> {
+        self.adapter.start_advertising(advertiser).await
+    }
+
+    fn operation_timeout(&self) -> Option<Duration> {
+        self.adapter.operation_timeout()
+    }
+
+    fn set_operation_timeout(&self, timeout: Option<Duration>) {
+        self.adapter.set_operation_timeout(timeout);
+    }
+
+    fn set_observer(&self, observer: Option<Arc<dyn Observer>>) {
+        self.adapter.set_observer(observer);
+    }
+
+    fn set_peripheral_ttl(&self, ttl: Option<Duration>) {
+        self.adapter.set_peripheral_ttl(ttl);
+    }
+
+    fn set_rssi_tracking(&self, tracking: Option<RssiTracking>) {
+        self.adapter.set_rssi_tracking(tracking);
+    }
+}
+
+/// A peripheral which records the operations performed on it and the notifications received from
+/// it, returned by a [`RecordingAdapter`].
+#[derive(Clone, Debug)]
+pub struct RecordingPeripheral<P> {
+    peripheral: P,
+    /// The peripheral's ID as recorded.
+    id: String,
+    recorder: Recorder,
+}
+
+impl<P: Peripheral> RecordingPeripheral<P> {
+    /// Returns the peripheral being recorded, for calls which shouldn't be recorded.
+    pub fn inner(&self) -> &P {
+        &self.peripheral
+    }
+
+    fn record_services(&self, result: &Result<()>) {
+        self.recorder.write(Entry::DiscoverServices {
+            peripheral: self.id.clone(),
+            result: outcome(result, |_| self.peripheral.services()),
+        });
+    }
+
+    fn record_subscribe(&self, characteristic: &Characteristic, result: &Result<()>) {
+        self.recorder.write(Entry::Subscribe {
+            peripheral: self.id.clone(),
+            characteristic: characteristic.clone(),
+            result: outcome(result, |_| ()),
+        });
+    }
+
+    /// Returns the channel which the peripheral's notifications are recorded and passed on
+    /// through, starting to record them if this is the first time.
+    async fn notifications_channel(&self) -> Result<broadcast::Sender<ValueNotification>> {
+        let inner = &self.recorder.inner;
+        if let Some(channel) = inner.notifications.lock().unwrap().get(&self.id) {
+            return Ok(channel.clone());
+        }
+        let mut notifications = self.peripheral.notifications().await?;
+        let mut channels = inner.notifications.lock().unwrap();
+        if let Some(channel) = channels.get(&self.id) {
+            // Another call got there first while this one was waiting.
+            return Ok(channel.clone());
+        }
+        let (channel, _) = broadcast::channel(CHANNEL_CAPACITY);
+        channels.insert(self.id.clone(), channel.clone());
+        drop(channels);
+
+        let recorder: Weak<Inner> = Arc::downgrade(inner);
+        let peripheral = self.id.clone();
+        let sender = channel.clone();
+        let task = rt::spawn(async move {
+            while let Some(notification) = notifications.next().await {
+                let Some(recorder) = recorder.upgrade() else {
+                    break;
+                };
+                recorder.write(&Entry::Notification {
+                    peripheral: peripheral.clone(),
+                    service_uuid: notification.service_uuid,
+                    uuid: notification.uuid,
+                    value: notification.value.clone(),
+                });
+                let _ = sender.send(notification);
+            }
+        });
+        inner.tasks.lock().unwrap().push(task);
+        Ok(channel)
+    }
+}
+
+#[async_trait]
+impl<P: Peripheral> Peripheral for RecordingPeripheral<P> {
+    fn id(&self) -> PeripheralId {
+        self.peripheral.id()
+    }
+
+    fn address(&self) -> BDAddr {
+        self.peripheral.address()
+    }
+
+    async fn identity_address(&self, resolver: &rpa::Resolver) -> Result<Option<BDAddr>> {
+        self.peripheral.identity_address(resolver).await
+    }
+
+    async fn properties(&self) -> Result<Option<PeripheralProperties>> {
+        self.peripheral.properties().await
+    }
+
+    async fn refresh_properties(&self) -> Result<Option<PeripheralProperties>> {
+        self.peripheral.refresh_properties().await
+    }
+
+    async fn resolve_name(&self) -> Result<Option<String>> {
+        self.peripheral.resolve_name().await
+    }
+
+    fn services(&self) -> BTreeSet<Service> {
+        self.peripheral.services()
+    }
+
+    fn rssi_stats(&self) -> Option<RssiStats> {
+        self.peripheral.rssi_stats()
+    }
+
+    async fn is_connected(&self) -> Result<bool> {
+        self.peripheral.is_connected().await
+    }
+
+    async fn connect(&self) -> Result<()> {
+        let result = self.peripheral.connect().await;
+        self.recorder.write(Entry::Connect {
+            peripheral: self.id.clone(),
+            result: outcome(&result, |_| ()),
+        });
+        result
+    }
+
+    async fn connect_with_transport(&self, transport: Transport) -> Result<()> {
+        let result = self.peripheral.connect_with_transport(transport).await;
+        self.recorder.write(Entry::Connect {
+            peripheral: self.id.clone(),
+            result: outcome(&result, |_| ()),
+        });
+        result
+    }
+
+    async fn disconnect(&self) -> Result<()> {
+        let result = self.peripheral.disconnect().await;
+        self.recorder.write(Entry::Disconnect {
+            peripheral: self.id.clone(),
+            result: outcome(&result, |_| ()),
+        });
+        result
+    }
+
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = PeripheralEvent> + Send>>> {
+        self.peripheral.events().await
+    }
+
+    async fn pair(&self) -> Result<()> {
+        self.peripheral.pair().await
+    }
+
+    async fn pair_with_agent(&self, agent: Arc<dyn PairingAgent>) -> Result<()> {
+        self.peripheral.pair_with_agent(agent).await
+    }
+
+    async fn pair_with_oob(&self, data: OobData) -> Result<()> {
+        self.peripheral.pair_with_oob(data).await
+    }
+
+    async fn security_level(&self) -> Result<SecurityLevel> {
+        self.peripheral.security_level().await
+    }
+
+    async fn require_security(&self, level: SecurityLevel) -> Result<()> {
+        self.peripheral.require_security(level).await
+    }
+
+    async fn require_security_with_agent(
+        &self,
+        level: SecurityLevel,
+        agent: Arc<dyn PairingAgent>,
+    ) -> Result<()> {
+        self.peripheral
+            .require_security_with_agent(level, agent)
+            .await
+    }
+
+    async fn request_mtu(&self, mtu: u16) -> Result<u16> {
+        self.peripheral.request_mtu(mtu).await
+    }
+
+    async fn request_connection_priority(&self, priority: ConnectionPriority) -> Result<()> {
+        self.peripheral.request_connection_priority(priority).await
+    }
+
+    async fn link_events(
+        &self,
+        interval: Duration,
+    ) -> Result<Pin<Box<dyn Stream<Item = LinkSample> + Send>>> {
+        self.peripheral.link_events(interval).await
+    }
+
+    async fn request_data_length(&self, length: DataLength) -> Result<()> {
+        self.peripheral.request_data_length(length).await
+    }
+
+    async fn discover_services(&self) -> Result<()> {
+        let result = self.peripheral.discover_services().await;
+        self.record_services(&result);
+        result
+    }
+
+    async fn discover_services_filtered(&self, uuids: &[Uuid]) -> Result<()> {
+        let result = self.peripheral.discover_services_filtered(uuids).await;
+        self.record_services(&result);
+        result
+    }
+
+    async fn write(
+        &self,
+        characteristic: &Characteristic,
+        data: &[u8],
+        write_type: WriteType,
+    ) -> Result<()> {
+        let result = self
+            .peripheral
+            .write(characteristic, data, write_type)
+            .await;
+        self.recorder.write(Entry::Write {
+            peripheral: self.id.clone(),
+            characteristic: characteristic.clone(),
+            data: data.to_vec(),
+            without_response: write_type != WriteType::WithResponse,
+            result: outcome(&result, |_| ()),
+        });
+        result
+    }
+
+    async fn read(&self, characteristic: &Characteristic) -> Result<Vec<u8>> {
+        let result = self.peripheral.read(characteristic).await;
+        self.recorder.write(Entry::Read {
+            peripheral: self.id.clone(),
+            characteristic: characteristic.clone(),
+            result: outcome(&result, Clone::clone),
+        });
+        result
+    }
+
+    async fn subscribe(&self, characteristic: &Characteristic) -> Result<()> {
+        let result = self.peripheral.subscribe(characteristic).await;
+        self.record_subscribe(characteristic, &result);
+        result
+    }
+
+    async fn subscribe_with(
+        &self,
+        characteristic: &Characteristic,
+        subscription_type: SubscriptionType,
+    ) -> Result<()> {
+        let result = self
+            .peripheral
+            .subscribe_with(characteristic, subscription_type)
+            .await;
+        // The characteristic not supporting the type is found out before anything is sent, and a
+        // replay finds it out the same way.
+        if !matches!(result, Err(Error::NotSupported(_))) {
+            self.record_subscribe(characteristic, &result);
+        }
+        result
+    }
+
+    async fn unsubscribe(&self, characteristic: &Characteristic) -> Result<()> {
+        let result = self.peripheral.unsubscribe(characteristic).await;
+        self.recorder.write(Entry::Unsubscribe {
+            peripheral: self.id.clone(),
+            characteristic: characteristic.clone(),
+            result: outcome(&result, |_| ()),
+        });
+        result
+    }
+
+    async fn read_client_configuration(
+        &self,
+        characteristic: &Characteristic,
+    ) -> Result<ClientConfiguration> {
+        let result = self
+            .peripheral
+            .read_client_configuration(characteristic)
+            .await;
+        // Recorded as the descriptor read which a replay does for it.
+        if !matches!(result, Err(Error::NotSupported(_))) {
+            self.recorder.write(Entry::ReadDescriptor {
+                peripheral: self.id.clone(),
+                descriptor: Descriptor {
+                    uuid: CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
+                    service_uuid: characteristic.service_uuid,
+                    characteristic_uuid: characteristic.uuid,
+                    handle: None,
+                    value: None,
+                },
+                result: outcome(&result, |configuration| {
+                    configuration.bits().to_le_bytes().to_vec()
+                }),
+            });
+        }
+        result
+    }
+
+    async fn notifications(&self) -> Result<Pin<Box<dyn Stream<Item = ValueNotification> + Send>>> {
+        let receiver = self.notifications_channel().await?.subscribe();
+        Ok(Box::pin(
+            BroadcastStream::new(receiver).filter_map(|x| async move { x.ok() }),
+        ))
+    }
+
+    async fn write_descriptor(&self, descriptor: &Descriptor, data: &[u8]) -> Result<()> {
+        let result = self.peripheral.write_descriptor(descriptor, data).await;
+        self.recorder.write(Entry::WriteDescriptor {
+            peripheral: self.id.clone(),
+            descriptor: descriptor.clone(),
+            data: data.to_vec(),
+            result: outcome(&result, |_| ()),
+        });
+        result
+    }
+
+    async fn read_descriptor(&self, descriptor: &Descriptor) -> Result<Vec<u8>> {
+        let result = self.peripheral.read_descriptor(descriptor).await;
+        self.recorder.write(Entry::ReadDescriptor {
+            peripheral: self.id.clone(),
+            descriptor: descriptor.clone(),
+            result: outcome(&result, Clone::clone),
+        });
+        result
+    }
+}
@@ -0,0 +1,299 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::{Entry, Outcome};
+use crate::api::{BDAddr, CharPropFlags, Characteristic, Peripheral as _};
+use crate::mock::script::{Operation, Reply};
+use crate::mock::{Adapter, Manager, Peripheral};
+use crate::{rt, Error, Result};
+use futures::channel::oneshot;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{self, Debug, Formatter};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use tokio::sync::watch;
+
+/// Plays back a recording made with a [`Recorder`](super::Recorder) through a mock adapter.
+///
+/// The replay starts as soon as it is created, and stops if it is dropped. It must be created
+/// within a Tokio runtime if the `runtime-tokio` feature is enabled.
+pub struct Replay {
+    manager: Manager,
+    adapter: Adapter,
+    finished: watch::Receiver<bool>,
+    task: rt::Task,
+}
+
+impl Replay {
+    /// Starts replaying the recording read from `reader`, which is read in full first. Fails if it
+    /// can't be read or isn't a recording.
+    pub fn from_reader(reader: impl BufRead) -> Result<Self> {
+        let mut entries = Vec::new();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line.map_err(|e| Error::Other(e.into()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(serde_json::from_str(&line).map_err(|e| {
+                Error::RuntimeError(format!("Invalid recording on line {}: {}", number + 1, e))
+            })?);
+        }
+
+        let manager = Manager::default();
+        let adapter = manager.add_adapter();
+        let mut driver = Driver {
+            adapter: adapter.clone(),
+            addresses: HashMap::new(),
+        };
+        // Everything the application will do is scripted before it gets the chance to.
+        let steps: Vec<_> = entries
+            .into_iter()
+            .map(|entry| {
+                let performed = driver.expect(&entry);
+                (entry, performed)
+            })
+            .collect();
+        let (sender, finished) = watch::channel(false);
+        let task = rt::spawn(async move {
+            for (entry, performed) in steps {
+                match performed {
+                    Some(performed) => {
+                        let _ = performed.await;
+                    }
+                    None => driver.apply(entry),
+                }
+            }
+            let _ = sender.send(true);
+        });
+        Ok(Replay {
+            manager,
+            adapter,
+            finished,
+            task,
+        })
+    }
+
+    /// Starts replaying the recording in the file at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path).map_err(|e| Error::Other(e.into()))?;
+        Self::from_reader(BufReader::new(file))
+    }
+
+    /// Returns the manager, which has the replay's adapter as its only adapter.
+    pub fn manager(&self) -> &Manager {
+        &self.manager
+    }
+
+    /// Returns the adapter the recording is played back through.
+    pub fn adapter(&self) -> &Adapter {
+        &self.adapter
+    }
+
+    /// Waits until everything in the recording has been played back, which means the application
+    /// has performed all the operations it performed when it was recorded.
+    pub async fn finished(&self) {
+        let mut finished = self.finished.clone();
+        let _ = finished.wait_for(|&finished| finished).await;
+    }
+}
+
+impl Drop for Replay {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl Debug for Replay {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Replay")
+            .field("adapter", &self.adapter)
+            .field("finished", &*self.finished.borrow())
+            .finish()
+    }
+}
+
+fn scripted<T>(result: Outcome<T>, reply: impl FnOnce(T) -> Reply) -> Result<Reply> {
+    result.map(reply).map_err(Error::from)
+}
+
+fn done(result: Outcome<()>) -> Result<Reply> {
+    scripted(result, |()| Reply::Done)
+}
+
+/// Plays back a recording through a mock adapter.
+struct Driver {
+    adapter: Adapter,
+    /// The addresses of the mock peripherals standing in for the recorded ones, by recorded ID.
+    addresses: HashMap<String, BDAddr>,
+}
+
+impl Driver {
+    /// Returns the address of the mock peripheral standing in for a recorded one. This is the
+    /// recorded address, unless the platform doesn't report addresses or another peripheral
+    /// already has it.
+    fn address(&mut self, id: &str, recorded: BDAddr) -> BDAddr {
+        if let Some(&address) = self.addresses.get(id) {
+            return address;
+        }
+        let taken = |address: &BDAddr| self.addresses.values().any(|a| a == address);
+        let address = if recorded != BDAddr::default() && !taken(&recorded) {
+            recorded
+        } else {
+            (1..)
+                .map(|n: u32| {
+                    let [_, a, b, c] = n.to_be_bytes();
+                    BDAddr::from([0xde, 0xad, 0xbe, a, b, c])
+                })
+                .find(|address| !taken(address))
+                .unwrap()
+        };
+        self.addresses.insert(id.to_string(), address);
+        address
+    }
+
+    /// Scripts the result of the operation the application performed, if the entry is one, and
+    /// returns the receiver which completes once the application performs it again.
+    fn expect(&mut self, entry: &Entry) -> Option<oneshot::Receiver<()>> {
+        let (peripheral, operation, result) = match entry.clone() {
+            Entry::Advertisement {
+                peripheral,
+                properties,
+            } => {
+                self.address(&peripheral, properties.address);
+                return None;
+            }
+            Entry::Disconnected { .. } | Entry::StateUpdate { .. } | Entry::Notification { .. } => {
+                return None;
+            }
+            Entry::StartScan { result, .. } => {
+                return Some(
+                    self.adapter
+                        .script()
+                        .expect(Operation::StartScan, done(result)),
+                );
+            }
+            Entry::Connect { peripheral, result } => (peripheral, Operation::Connect, done(result)),
+            Entry::Disconnect { peripheral, result } => {
+                (peripheral, Operation::Disconnect, done(result))
+            }
+            Entry::DiscoverServices { peripheral, result } => (
+                peripheral,
+                Operation::DiscoverServices,
+                scripted(result, Reply::Services),
+            ),
+            Entry::Read {
+                peripheral,
+                characteristic,
+                result,
+            } => (
+                peripheral,
+                Operation::read(&characteristic),
+                scripted(result, Reply::Value),
+            ),
+            Entry::Write {
+                peripheral,
+                characteristic,
+                result,
+                ..
+            } => (peripheral, Operation::write(&characteristic), done(result)),
+            Entry::Subscribe {
+                peripheral,
+                characteristic,
+                result,
+            } => (
+                peripheral,
+                Operation::subscribe(&characteristic),
+                done(result),
+            ),
+            Entry::Unsubscribe {
+                peripheral,
+                characteristic,
+                result,
+            } => (
+                peripheral,
+                Operation::unsubscribe(&characteristic),
+                done(result),
+            ),
+            Entry::ReadDescriptor {
+                peripheral,
+                descriptor,
+                result,
+            } => (
+                peripheral,
+                Operation::read_descriptor(&descriptor),
+                scripted(result, Reply::Value),
+            ),
+            Entry::WriteDescriptor {
+                peripheral,
+                descriptor,
+                result,
+                ..
+            } => (
+                peripheral,
+                Operation::write_descriptor(&descriptor),
+                done(result),
+            ),
+        };
+        let address = self.address(&peripheral, BDAddr::default());
+        Some(
+            self.adapter
+                .peripheral_script(address)
+                .expect(operation, result),
+        )
+    }
+
+    /// Returns the mock peripheral standing in for a recorded one, adding it if it hasn't been
+    /// advertised.
+    fn peripheral(&mut self, id: &str) -> Peripheral {
+        let address = self.address(id, BDAddr::default());
+        self.adapter.peripheral_at(address)
+    }
+
+    /// Plays back something which happened without the application asking for it.
+    fn apply(&mut self, entry: Entry) {
+        match entry {
+            Entry::Advertisement {
+                peripheral,
+                mut properties,
+            } => {
+                properties.address = self.address(&peripheral, properties.address);
+                // When not scanning, the peripheral was found some other way, such as by being
+                // bonded.
+                if self.adapter.advertise(properties.clone()).is_none() {
+                    self.adapter.add_known_peripheral(properties);
+                }
+            }
+            Entry::Disconnected { peripheral } => {
+                self.peripheral(&peripheral).simulate_disconnect()
+            }
+            Entry::StateUpdate { state } => self.adapter.set_state(state),
+            Entry::Notification {
+                peripheral,
+                service_uuid,
+                uuid,
+                value,
+            } => {
+                let peripheral = self.peripheral(&peripheral);
+                let characteristic = peripheral
+                    .characteristics()
+                    .into_iter()
+                    .find(|c| c.uuid == uuid && c.service_uuid == service_uuid)
+                    .unwrap_or(Characteristic {
+                        uuid,
+                        service_uuid,
+                        properties: CharPropFlags::empty(),
+                        descriptors: BTreeSet::new(),
+                        handle: None,
+                        value_handle: None,
+                    });
+                peripheral.notify(&characteristic, value);
+            }
+            _ => {}
+        }
+    }
+}
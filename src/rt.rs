@@ -0,0 +1,592 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! The async runtime which btleplug runs its own background tasks and timers on.
+//!
+//! With the `runtime-tokio` feature, which is on by default, these use the application's Tokio
+//! runtime, so must be used from within one. With `runtime-async-std` instead, they use async-std's.
+//! Without either, tasks run on a thread pool and timers on a thread of btleplug's own, so that
+//! btleplug can be used from any executor, such as smol.
+//!
+//! The Linux and FreeBSD backends' D-Bus connections and sockets are driven by Tokio's reactor
+//! whichever runtime is used. Without `runtime-tokio`, btleplug runs a Tokio runtime of its own on
+//! a thread for them, and [`Io`] enters it around the futures and streams which need it.
+//!
+//! On WebAssembly, whichever runtime feature is on, tasks run on the browser's event loop and
+//! timers use `setTimeout`.
+
+#[cfg(all(
+    feature = "runtime-async-std",
+    not(feature = "runtime-tokio"),
+    not(target_arch = "wasm32")
+))]
+pub(crate) use self::async_std_runtime::*;
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+pub(crate) use self::io::*;
+#[cfg(not(any(
+    feature = "runtime-tokio",
+    feature = "runtime-async-std",
+    target_arch = "wasm32"
+)))]
+pub(crate) use self::standalone::*;
+#[cfg(all(feature = "runtime-tokio", not(target_arch = "wasm32")))]
+pub(crate) use self::tokio_runtime::*;
+#[cfg(target_arch = "wasm32")]
+pub(crate) use self::wasm_runtime::*;
+
+/// The error returned by `timeout` when the future didn't finish in time.
+#[derive(Debug)]
+pub(crate) struct Elapsed;
+
+#[cfg(all(feature = "runtime-tokio", not(target_arch = "wasm32")))]
+mod tokio_runtime {
+    use super::Elapsed;
+    use std::{future::Future, panic, time::Duration};
+
+    /// A handle to a spawned task. Dropping it leaves the task running.
+    #[derive(Debug)]
+    pub(crate) struct Task(tokio::task::JoinHandle<()>);
+
+    impl Task {
+        pub(crate) fn abort(&self) {
+            self.0.abort();
+        }
+
+        // Only used by some backends, so turn off deadcode so we don't get warnings on the other platforms.
+        #[allow(dead_code)]
+        pub(crate) fn is_finished(&self) -> bool {
+            self.0.is_finished()
+        }
+    }
+
+    pub(crate) fn spawn(future: impl Future<Output = ()> + Send + 'static) -> Task {
+        Task(tokio::spawn(future))
+    }
+
+    /// Returns whether tasks can be spawned, which they can't outside of a Tokio runtime, such as
+    /// while dropping something after the runtime has shut down.
+    #[allow(dead_code)]
+    pub(crate) fn can_spawn() -> bool {
+        tokio::runtime::Handle::try_current().is_ok()
+    }
+
+    /// Runs `function` on a thread where it may block, and returns what it returns.
+    #[allow(dead_code)]
+    pub(crate) async fn spawn_blocking<T: Send + 'static>(
+        function: impl FnOnce() -> T + Send + 'static,
+    ) -> T {
+        match tokio::task::spawn_blocking(function).await {
+            Ok(output) => output,
+            Err(error) => panic::resume_unwind(error.into_panic()),
+        }
+    }
+
+    pub(crate) async fn sleep(duration: Duration) {
+        tokio::time::sleep(duration).await;
+    }
+
+    pub(crate) async fn timeout<F: Future>(
+        duration: Duration,
+        future: F,
+    ) -> Result<F::Output, Elapsed> {
+        tokio::time::timeout(duration, future)
+            .await
+            .map_err(|_| Elapsed)
+    }
+}
+
+/// Tasks on executors which have no way to abort them, which are instead wrapped so that they stop
+/// at their next poll.
+#[cfg(any(test, not(feature = "runtime-tokio"), target_arch = "wasm32"))]
+mod abortable {
+    use futures::future::{AbortHandle, Abortable};
+    use std::{
+        future::Future,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+
+    /// A handle to a spawned task. Dropping it leaves the task running.
+    #[derive(Debug)]
+    pub(crate) struct Task {
+        handle: AbortHandle,
+        finished: Arc<AtomicBool>,
+    }
+
+    impl Task {
+        pub(crate) fn abort(&self) {
+            self.handle.abort();
+        }
+
+        // Only used by some backends, so turn off deadcode so we don't get warnings on the other platforms.
+        #[allow(dead_code)]
+        pub(crate) fn is_finished(&self) -> bool {
+            self.finished.load(Ordering::Acquire) || self.handle.is_aborted()
+        }
+    }
+
+    /// Returns the handle to the task, and the future to run for it.
+    pub(super) fn wrap(
+        future: impl Future<Output = ()> + Send + 'static,
+    ) -> (Task, impl Future<Output = ()> + Send + 'static) {
+        let (handle, registration) = AbortHandle::new_pair();
+        let finished = Arc::new(AtomicBool::new(false));
+        let task = Task {
+            handle,
+            finished: finished.clone(),
+        };
+        let future = async move {
+            let _ = Abortable::new(future, registration).await;
+            finished.store(true, Ordering::Release);
+        };
+        (task, future)
+    }
+}
+
+#[cfg(all(
+    feature = "runtime-async-std",
+    not(feature = "runtime-tokio"),
+    not(target_arch = "wasm32")
+))]
+mod async_std_runtime {
+    use super::{abortable, Elapsed};
+    use std::{future::Future, time::Duration};
+
+    pub(crate) use super::abortable::Task;
+
+    pub(crate) fn spawn(future: impl Future<Output = ()> + Send + 'static) -> Task {
+        let (task, future) = abortable::wrap(future);
+        async_std::task::spawn(future);
+        task
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn can_spawn() -> bool {
+        true
+    }
+
+    /// Runs `function` on a thread where it may block, and returns what it returns.
+    #[allow(dead_code)]
+    pub(crate) async fn spawn_blocking<T: Send + 'static>(
+        function: impl FnOnce() -> T + Send + 'static,
+    ) -> T {
+        async_std::task::spawn_blocking(function).await
+    }
+
+    pub(crate) async fn sleep(duration: Duration) {
+        async_std::task::sleep(duration).await;
+    }
+
+    pub(crate) async fn timeout<F: Future>(
+        duration: Duration,
+        future: F,
+    ) -> Result<F::Output, Elapsed> {
+        async_std::future::timeout(duration, future)
+            .await
+            .map_err(|_| Elapsed)
+    }
+}
+
+#[cfg(all(
+    not(target_arch = "wasm32"),
+    any(
+        test,
+        not(any(feature = "runtime-tokio", feature = "runtime-async-std"))
+    )
+))]
+mod standalone {
+    use super::{abortable, Elapsed};
+    use futures::{
+        channel::oneshot,
+        executor::ThreadPool,
+        future::{self, Either},
+    };
+    use std::{
+        cmp::{Ordering, Reverse},
+        collections::BinaryHeap,
+        future::Future,
+        panic,
+        pin::pin,
+        sync::{mpsc, Mutex, OnceLock},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    pub(crate) use super::abortable::Task;
+
+    pub(crate) fn spawn(future: impl Future<Output = ()> + Send + 'static) -> Task {
+        static POOL: OnceLock<ThreadPool> = OnceLock::new();
+        let pool = POOL.get_or_init(|| {
+            ThreadPool::builder()
+                .name_prefix("btleplug-")
+                .create()
+                .expect("Failed to start btleplug's thread pool")
+        });
+        let (task, future) = abortable::wrap(future);
+        pool.spawn_ok(future);
+        task
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn can_spawn() -> bool {
+        true
+    }
+
+    /// Runs `function` on a thread of its own, and returns what it returns.
+    #[allow(dead_code)]
+    pub(crate) async fn spawn_blocking<T: Send + 'static>(
+        function: impl FnOnce() -> T + Send + 'static,
+    ) -> T {
+        let (sender, receiver) = oneshot::channel();
+        let thread = thread::Builder::new()
+            .name("btleplug-blocking".to_string())
+            .spawn(move || {
+                let _ = sender.send(panic::catch_unwind(panic::AssertUnwindSafe(function)));
+            })
+            .expect("Failed to start a btleplug thread");
+        drop(thread);
+        match receiver.await.expect("btleplug's blocking thread stopped") {
+            Ok(output) => output,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+
+    struct Timer {
+        deadline: Instant,
+        sender: oneshot::Sender<()>,
+    }
+
+    impl PartialEq for Timer {
+        fn eq(&self, other: &Self) -> bool {
+            self.deadline == other.deadline
+        }
+    }
+
+    impl Eq for Timer {}
+
+    impl PartialOrd for Timer {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Timer {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.deadline.cmp(&other.deadline)
+        }
+    }
+
+    /// Returns the sender for a thread which fires each timer sent to it at its deadline.
+    fn timers() -> &'static Mutex<mpsc::Sender<Timer>> {
+        static TIMERS: OnceLock<Mutex<mpsc::Sender<Timer>>> = OnceLock::new();
+        TIMERS.get_or_init(|| {
+            let (sender, receiver) = mpsc::channel::<Timer>();
+            thread::Builder::new()
+                .name("btleplug-timer".to_string())
+                .spawn(move || {
+                    let mut timers = BinaryHeap::<Reverse<Timer>>::new();
+                    loop {
+                        let now = Instant::now();
+                        while let Some(Reverse(timer)) = timers.peek() {
+                            if timer.deadline > now {
+                                break;
+                            }
+                            let Reverse(timer) = timers.pop().unwrap();
+                            // The sleep may have been dropped already.
+                            let _ = timer.sender.send(());
+                        }
+                        let next = match timers.peek() {
+                            Some(Reverse(timer)) => receiver.recv_timeout(timer.deadline - now),
+                            None => receiver
+                                .recv()
+                                .map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+                        };
+                        match next {
+                            Ok(timer) => timers.push(Reverse(timer)),
+                            Err(mpsc::RecvTimeoutError::Timeout) => {}
+                            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                        }
+                    }
+                })
+                .expect("Failed to start btleplug's timer thread");
+            Mutex::new(sender)
+        })
+    }
+
+    pub(crate) async fn sleep(duration: Duration) {
+        let Some(deadline) = Instant::now().checked_add(duration) else {
+            // Too far in the future to ever happen.
+            return future::pending().await;
+        };
+        let (sender, receiver) = oneshot::channel();
+        timers()
+            .lock()
+            .unwrap()
+            .send(Timer { deadline, sender })
+            .expect("btleplug's timer thread has stopped");
+        let _ = receiver.await;
+    }
+
+    pub(crate) async fn timeout<F: Future>(
+        duration: Duration,
+        future: F,
+    ) -> Result<F::Output, Elapsed> {
+        match future::select(pin!(future), pin!(sleep(duration))).await {
+            Either::Left((output, _)) => Ok(output),
+            Either::Right(_) => Err(Elapsed),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use futures::executor::block_on;
+        use std::sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        };
+
+        #[test]
+        fn timers_fire_in_order() {
+            block_on(async {
+                let start = Instant::now();
+                let long = sleep(Duration::from_millis(50));
+                let short = sleep(Duration::from_millis(10));
+                match future::select(pin!(long), pin!(short)).await {
+                    Either::Right((_, long)) => long.await,
+                    Either::Left(_) => panic!("Long timer fired first"),
+                }
+                assert!(start.elapsed() >= Duration::from_millis(50));
+
+                assert!(timeout(Duration::from_millis(10), future::pending::<()>())
+                    .await
+                    .is_err());
+                assert_eq!(
+                    timeout(Duration::from_secs(10), async { 1 }).await.unwrap(),
+                    1
+                );
+            });
+        }
+
+        #[test]
+        fn abort_task() {
+            let finished = Arc::new(AtomicBool::new(false));
+            let task_finished = finished.clone();
+            let task = spawn(async move {
+                sleep(Duration::from_millis(50)).await;
+                task_finished.store(true, Ordering::Relaxed);
+            });
+            task.abort();
+            assert!(task.is_finished());
+            thread::sleep(Duration::from_millis(100));
+            assert!(!finished.load(Ordering::Relaxed));
+        }
+
+        #[test]
+        fn blocking() {
+            assert_eq!(block_on(spawn_blocking(|| 1)), 1);
+            let task = spawn(async {});
+            thread::sleep(Duration::from_millis(50));
+            assert!(task.is_finished());
+        }
+    }
+}
+
+/// The browser's event loop and timers, which WebAssembly has instead of threads.
+#[cfg(target_arch = "wasm32")]
+mod wasm_runtime {
+    use super::{abortable, Elapsed};
+    use futures::future::{self, Either};
+    use js_sys::{Function, Promise};
+    use send_wrapper::SendWrapper;
+    use std::{future::Future, pin::pin, time::Duration};
+    use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
+    use wasm_bindgen_futures::JsFuture;
+
+    pub(crate) use super::abortable::Task;
+
+    #[wasm_bindgen]
+    extern "C" {
+        #[wasm_bindgen(js_name = setTimeout)]
+        fn set_timeout(handler: &Function, timeout: f64) -> JsValue;
+    }
+
+    pub(crate) fn spawn(future: impl Future<Output = ()> + Send + 'static) -> Task {
+        let (task, future) = abortable::wrap(future);
+        wasm_bindgen_futures::spawn_local(future);
+        task
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn can_spawn() -> bool {
+        true
+    }
+
+    /// Runs `function` straight away, as there are no other threads to run it on.
+    #[allow(dead_code)]
+    pub(crate) async fn spawn_blocking<T: Send + 'static>(
+        function: impl FnOnce() -> T + Send + 'static,
+    ) -> T {
+        function()
+    }
+
+    pub(crate) async fn sleep(duration: Duration) {
+        // setTimeout fires straight away when the delay doesn't fit in 32 bits, so longer sleeps
+        // are taken in parts.
+        const LONGEST: Duration = Duration::from_millis(i32::MAX as u64);
+        let mut remaining = duration;
+        loop {
+            let part = remaining.min(LONGEST);
+            let promise = Promise::new(&mut |resolve, _reject| {
+                set_timeout(&resolve, part.as_secs_f64() * 1000.0);
+            });
+            let _ = SendWrapper::new(JsFuture::from(promise)).await;
+            remaining -= part;
+            if remaining.is_zero() {
+                break;
+            }
+        }
+    }
+
+    pub(crate) async fn timeout<F: Future>(
+        duration: Duration,
+        future: F,
+    ) -> Result<F::Output, Elapsed> {
+        match future::select(pin!(future), pin!(sleep(duration))).await {
+            Either::Left((output, _)) => Ok(output),
+            Either::Right(_) => Err(Elapsed),
+        }
+    }
+}
+
+/// The Tokio context the Linux and FreeBSD backends need for their D-Bus connections and sockets.
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+mod io {
+    #[cfg(all(target_os = "linux", feature = "bluez"))]
+    use {
+        super::Task,
+        futures::stream::Stream,
+        std::{
+            future::Future,
+            pin::Pin,
+            task::{Context, Poll},
+        },
+    };
+
+    /// While this is held, the thread is in the context of the Tokio runtime which drives the
+    /// Linux and FreeBSD backends' I/O.
+    pub(crate) struct IoContext {
+        #[cfg(not(feature = "runtime-tokio"))]
+        _guard: tokio::runtime::EnterGuard<'static>,
+    }
+
+    /// Enters the context of the Tokio runtime which drives I/O: the application's with
+    /// `runtime-tokio`, which it's already in, or otherwise one which btleplug runs on a thread of
+    /// its own, started the first time it's needed.
+    pub(crate) fn enter_io() -> IoContext {
+        #[cfg(feature = "runtime-tokio")]
+        {
+            IoContext {}
+        }
+        #[cfg(not(feature = "runtime-tokio"))]
+        {
+            use std::sync::{mpsc, OnceLock};
+            static HANDLE: OnceLock<tokio::runtime::Handle> = OnceLock::new();
+            let handle = HANDLE.get_or_init(|| {
+                let (sender, receiver) = mpsc::channel();
+                std::thread::Builder::new()
+                    .name("btleplug-io".to_string())
+                    .spawn(move || {
+                        let runtime = tokio::runtime::Builder::new_current_thread()
+                            .enable_all()
+                            .build()
+                            .expect("Failed to start btleplug's Tokio runtime");
+                        sender.send(runtime.handle().clone()).unwrap();
+                        runtime.block_on(futures::future::pending::<()>());
+                    })
+                    .expect("Failed to start btleplug's I/O thread");
+                receiver.recv().expect("btleplug's I/O thread stopped")
+            });
+            IoContext {
+                _guard: handle.enter(),
+            }
+        }
+    }
+
+    /// A future or stream which is polled and dropped in the I/O context, for those of bluez_async
+    /// and dbus_tokio, which use Tokio's reactor, timers and tasks.
+    #[cfg(all(target_os = "linux", feature = "bluez"))]
+    pub(crate) struct Io<T>(Option<Pin<Box<T>>>);
+
+    #[cfg(all(target_os = "linux", feature = "bluez"))]
+    impl<T> Io<T> {
+        pub(crate) fn new(inner: T) -> Self {
+            Io(Some(Box::pin(inner)))
+        }
+    }
+
+    #[cfg(all(target_os = "linux", feature = "bluez"))]
+    impl<F: Future> Future for Io<F> {
+        type Output = F::Output;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+            let _context = enter_io();
+            self.0.as_mut().unwrap().as_mut().poll(cx)
+        }
+    }
+
+    #[cfg(all(target_os = "linux", feature = "bluez"))]
+    impl<S: Stream> Stream for Io<S> {
+        type Item = S::Item;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+            let _context = enter_io();
+            self.0.as_mut().unwrap().as_mut().poll_next(cx)
+        }
+    }
+
+    #[cfg(all(target_os = "linux", feature = "bluez"))]
+    impl<T> Drop for Io<T> {
+        fn drop(&mut self) {
+            let _context = enter_io();
+            self.0.take();
+        }
+    }
+
+    #[cfg(all(target_os = "linux", feature = "bluez"))]
+    /// Spawns a task which runs in the I/O context.
+    pub(crate) fn spawn_io(future: impl Future<Output = ()> + Send + 'static) -> Task {
+        super::spawn(Io::new(future))
+    }
+
+    #[cfg(all(
+        test,
+        target_os = "linux",
+        feature = "bluez",
+        not(feature = "runtime-tokio")
+    ))]
+    mod tests {
+        use super::*;
+        use futures::executor::block_on;
+        use std::time::Duration;
+
+        #[test]
+        fn tokio_timers_outside_tokio() {
+            block_on(Io::new(async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }));
+            let (sender, receiver) = futures::channel::oneshot::channel();
+            spawn_io(async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                let _ = sender.send(());
+            });
+            block_on(receiver).unwrap();
+        }
+    }
+}
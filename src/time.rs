@@ -0,0 +1,7 @@
+//! The clocks in btleplug's API. They are std's, except on WebAssembly, where std's panic and
+//! `web_time`'s, which read the browser's clocks, are used instead.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use std::time::{Instant, SystemTime};
+#[cfg(target_arch = "wasm32")]
+pub use web_time::{Instant, SystemTime};
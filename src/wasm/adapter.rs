@@ -0,0 +1,474 @@
+use super::{
+    bindings::{
+        to_vec, Bluetooth, BluetoothAdvertisingEvent, BluetoothDevice, BluetoothLeScan, ValueEvent,
+    },
+    call, error_name, js_error, local,
+    peripheral::{Peripheral, PeripheralId},
+    Listener,
+};
+use crate::{
+    api::{
+        appearance::Appearance,
+        connect_when_advertising, event_filter,
+        observer::{Observer, TraceHandler},
+        rssi::RssiTracking,
+        spans, AdapterCapabilities, AdapterInfo, BDAddr, Central, CentralEvent, CentralState,
+        ConnectTarget, DiscoveryEvent, EventFilter, Peripheral as _, PeripheralProperties,
+        ScanFilter, ScanMode, ScanOptions,
+    },
+    common::adapter_manager::AdapterManager,
+    time::SystemTime,
+    Error, Result,
+};
+use async_trait::async_trait;
+use futures::stream::Stream;
+use js_sys::{Array, Object, Reflect};
+use log::debug;
+use send_wrapper::SendWrapper;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::{self, Debug, Formatter},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use uuid::Uuid;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+
+struct Shared {
+    bluetooth: SendWrapper<Bluetooth>,
+    optional_services: Mutex<BTreeSet<Uuid>>,
+    /// The scan for all devices' advertisements, in browsers which can.
+    scan: Mutex<Option<SendWrapper<BluetoothLeScan>>>,
+    _listeners: SendWrapper<[Listener; 2]>,
+}
+
+/// The browser's adapter. Browsers only let pages use the devices which the user picks in the
+/// browser's device chooser, which is shown by scanning with [`ScanMode::Chooser`].
+#[derive(Clone)]
+pub struct Adapter {
+    manager: Arc<AdapterManager<Peripheral>>,
+    shared: Arc<Shared>,
+}
+
+impl Debug for Adapter {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Adapter")
+            .field("manager", &self.manager)
+            .finish()
+    }
+}
+
+fn get_central_state(available: bool) -> CentralState {
+    // Browsers don't say whether the adapter is off, or missing, or the user has blocked it.
+    if available {
+        CentralState::PoweredOn
+    } else {
+        CentralState::PoweredOff
+    }
+}
+
+/// Sets a property of an object built to pass to the browser.
+fn set(object: &Object, key: &str, value: impl Into<JsValue>) {
+    Reflect::set(object, &key.into(), &value.into()).expect("Setting a property of an object");
+}
+
+/// The filters of the chooser and scan options which match the devices advertising any of the
+/// services.
+fn service_filters(services: &[Uuid]) -> Array {
+    services
+        .iter()
+        .map(|uuid| {
+            let filter = Object::new();
+            let services: Array = [JsValue::from(uuid.to_string())].into_iter().collect();
+            set(&filter, "services", services);
+            JsValue::from(filter)
+        })
+        .collect()
+}
+
+/// Reports a device found by scanning, watching its advertisements or the device chooser, adding
+/// a peripheral for it if it's new.
+fn report(
+    manager: &Arc<AdapterManager<Peripheral>>,
+    device: BluetoothDevice,
+    properties: PeripheralProperties,
+) -> Peripheral {
+    let id = PeripheralId(device.id());
+    let (peripheral, new) = match manager.peripheral(&id) {
+        Some(peripheral) => (peripheral, false),
+        None => {
+            let peripheral = Peripheral::new(
+                device,
+                Arc::downgrade(manager),
+                manager.operation_context().clone(),
+            );
+            manager.add_peripheral(peripheral.clone());
+            (peripheral, true)
+        }
+    };
+    peripheral.report_properties(properties.clone());
+    if let Some(rssi) = properties.rssi {
+        manager.operation_context().rssi().record(&id, rssi);
+    }
+    manager
+        .peripheral_events()
+        .send_discovery(&id, properties.clone(), new);
+    manager.emit(if new {
+        CentralEvent::DeviceDiscovered(id.clone())
+    } else {
+        CentralEvent::DeviceUpdated(id.clone())
+    });
+    if !properties.manufacturer_data.is_empty() {
+        manager.emit(CentralEvent::ManufacturerDataAdvertisement {
+            id: id.clone(),
+            manufacturer_data: properties.manufacturer_data,
+        });
+    }
+    if !properties.service_data.is_empty() {
+        manager.emit(CentralEvent::ServiceDataAdvertisement {
+            id: id.clone(),
+            service_data: properties.service_data,
+        });
+    }
+    if !properties.services.is_empty() {
+        manager.emit(CentralEvent::ServicesAdvertisement {
+            id,
+            services: properties.services,
+        });
+    }
+    peripheral
+}
+
+/// Reports a device the user has let the page use, which the browser hasn't seen advertise yet.
+fn report_device(manager: &Arc<AdapterManager<Peripheral>>, device: BluetoothDevice) -> Peripheral {
+    let properties = PeripheralProperties {
+        local_name: device.name(),
+        ..Default::default()
+    };
+    report(manager, device, properties)
+}
+
+/// Reports an advertisement, from the scan or from watching a device's advertisements.
+pub(super) fn on_advertisement(
+    manager: &Arc<AdapterManager<Peripheral>>,
+    event: BluetoothAdvertisingEvent,
+) {
+    let device = event.device();
+    let id = PeripheralId(device.id());
+    spans::callback("advertisement", Some(&id)).in_scope(|| {
+        let mut manufacturer_data = HashMap::new();
+        event.manufacturer_data().for_each(&mut |data, company_id| {
+            if let Some(company_id) = company_id.as_f64() {
+                manufacturer_data.insert(company_id as u16, to_vec(data.unchecked_ref()).into());
+            }
+        });
+        let mut service_data = HashMap::new();
+        event.service_data().for_each(&mut |data, uuid| {
+            if let Some(uuid) = uuid.as_string().and_then(|uuid| uuid.parse().ok()) {
+                service_data.insert(uuid, to_vec(data.unchecked_ref()).into());
+            }
+        });
+        let services = event
+            .uuids()
+            .iter()
+            .filter_map(|uuid| uuid.as_string()?.parse().ok())
+            .collect();
+        let properties = PeripheralProperties {
+            local_name: event.name(),
+            tx_power_level: event.tx_power().map(i16::from),
+            rssi: event.rssi(),
+            manufacturer_data,
+            service_data,
+            services,
+            last_seen: Some(SystemTime::now()),
+            appearance: event.appearance().map(Appearance::from),
+            ..Default::default()
+        };
+        report(manager, device, properties);
+    })
+}
+
+/// Has the browser report the device's advertisements, where it can, so that its RSSI and
+/// advertised data are kept up to date.
+async fn watch_advertisements(device: &BluetoothDevice) {
+    let Ok(promise) = device.watch_advertisements() else {
+        return;
+    };
+    if let Err(e) = call(promise).await {
+        debug!(
+            "Couldn't watch the advertisements of {}: {}",
+            device.id(),
+            e
+        );
+    }
+}
+
+impl Adapter {
+    pub(super) fn new(bluetooth: Bluetooth) -> Self {
+        let manager = Arc::new(AdapterManager::default());
+        let advertisements = Listener::new(&bluetooth, "advertisementreceived", {
+            let manager = Arc::downgrade(&manager);
+            move |event| {
+                if let Some(manager) = manager.upgrade() {
+                    on_advertisement(&manager, event.unchecked_into());
+                }
+            }
+        });
+        let availability = Listener::new(&bluetooth, "availabilitychanged", {
+            let manager = Arc::downgrade(&manager);
+            move |event| {
+                let available = event.unchecked_into::<ValueEvent>().value().as_bool();
+                if let Some(manager) = manager.upgrade() {
+                    manager.emit(CentralEvent::StateUpdate(get_central_state(
+                        available.unwrap_or(false),
+                    )));
+                }
+            }
+        });
+        Adapter {
+            manager,
+            shared: Arc::new(Shared {
+                bluetooth: SendWrapper::new(bluetooth),
+                optional_services: Mutex::new(BTreeSet::new()),
+                scan: Mutex::new(None),
+                _listeners: SendWrapper::new([advertisements, availability]),
+            }),
+        }
+    }
+
+    /// There is only one adapter, so the manager's trace handler is set on it directly.
+    pub(crate) fn set_trace_handler(&self, handler: Option<Arc<dyn TraceHandler>>) {
+        self.manager.operation_context().tracer().set(handler);
+    }
+
+    /// Sets the services which the device chooser asks the user to let the page use, besides
+    /// those of the scan filter. Browsers only let a page discover and use the services it asked
+    /// for when the device was picked.
+    pub fn set_optional_services(&self, services: impl IntoIterator<Item = Uuid>) {
+        *self.shared.optional_services.lock().unwrap() = services.into_iter().collect();
+    }
+
+    /// Shows the browser's device chooser, and adds the device the user picks.
+    async fn choose(&self, filter: &ScanFilter) -> Result<()> {
+        let options = Object::new();
+        if filter.services.is_empty() {
+            set(&options, "acceptAllDevices", true);
+        } else {
+            set(&options, "filters", service_filters(&filter.services));
+        }
+        let optional_services: Array = filter
+            .services
+            .iter()
+            .chain(self.shared.optional_services.lock().unwrap().iter())
+            .map(|uuid| JsValue::from(uuid.to_string()))
+            .collect();
+        set(&options, "optionalServices", optional_services);
+        let device: BluetoothDevice =
+            match JsFuture::from(self.shared.bluetooth.request_device(&options)).await {
+                Ok(device) => device.unchecked_into(),
+                // The user closed the chooser without picking a device.
+                Err(e) if error_name(&e).as_deref() == Some("NotFoundError") => {
+                    return Err(Error::DeviceNotFound)
+                }
+                Err(e) => return Err(js_error(e)),
+            };
+        report_device(&self.manager, device.clone());
+        watch_advertisements(&device).await;
+        Ok(())
+    }
+
+    /// Adds the devices the user has already let the page use, and scans for the advertisements
+    /// of all devices, in the browsers which can.
+    async fn scan(&self, filter: &ScanFilter) -> Result<()> {
+        let devices = self.shared.bluetooth.get_devices();
+        if let Ok(devices) = &devices {
+            let devices: Array = call(devices.clone()).await?.unchecked_into();
+            for device in devices.iter() {
+                let device: BluetoothDevice = device.unchecked_into();
+                report_device(&self.manager, device.clone());
+                watch_advertisements(&device).await;
+            }
+        }
+        let options = Object::new();
+        if filter.services.is_empty() {
+            set(&options, "acceptAllAdvertisements", true);
+        } else {
+            set(&options, "filters", service_filters(&filter.services));
+        }
+        // So that each advertisement is reported, rather than only the first from each device.
+        set(&options, "keepRepeatedDevices", true);
+        match self.shared.bluetooth.request_le_scan(&options) {
+            Ok(scan) => {
+                let scan: BluetoothLeScan = call(scan).await?.unchecked_into();
+                if let Some(old) = self
+                    .shared
+                    .scan
+                    .lock()
+                    .unwrap()
+                    .replace(SendWrapper::new(scan))
+                {
+                    old.stop();
+                }
+                Ok(())
+            }
+            Err(_) if devices.is_ok() => Ok(()),
+            Err(_) => Err(Error::NotSupported(
+                "This browser can only find devices with its device chooser, which scanning with \
+                 ScanMode::Chooser shows"
+                    .to_string(),
+            )),
+        }
+    }
+
+    /// Finds the device with the ID among those the user has already let the page use.
+    async fn find_device(&self, id: &PeripheralId) -> Result<Peripheral> {
+        let devices = self.shared.bluetooth.get_devices().map_err(|_| {
+            Error::NotSupported(
+                "This browser can only add devices with its device chooser".to_string(),
+            )
+        })?;
+        let devices: Array = call(devices).await?.unchecked_into();
+        let device = devices
+            .iter()
+            .map(|device| device.unchecked_into::<BluetoothDevice>())
+            .find(|device| device.id() == id.0)
+            .ok_or(Error::DeviceNotFound)?;
+        Ok(report_device(&self.manager, device))
+    }
+}
+
+#[async_trait]
+impl Central for Adapter {
+    type Peripheral = Peripheral;
+
+    async fn adapter_info(&self) -> Result<AdapterInfo> {
+        Ok(AdapterInfo {
+            // Browsers only ever have the default adapter.
+            id: "default".to_string(),
+            description: "Web Bluetooth".to_string(),
+            address: None,
+            name: None,
+            alias: None,
+            capabilities: AdapterCapabilities {
+                extended_advertising: None,
+                le_2m_phy: None,
+                le_coded_phy: None,
+                peripheral_role: Some(false),
+                eatt: None,
+                advertisement_monitor: Some(false),
+            },
+            stack_version: None,
+            experimental_features: Vec::new(),
+        })
+    }
+
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = CentralEvent> + Send>>> {
+        Ok(self.manager.event_stream())
+    }
+
+    async fn events_filtered(
+        &self,
+        filter: EventFilter,
+    ) -> Result<Pin<Box<dyn Stream<Item = CentralEvent> + Send>>> {
+        let events = self.manager.filtered_event_stream(&filter);
+        Ok(event_filter::by_services(self, events, filter.services))
+    }
+
+    async fn discovery_events(&self) -> Result<Pin<Box<dyn Stream<Item = DiscoveryEvent> + Send>>> {
+        Ok(self.manager.peripheral_events().subscribe_discoveries())
+    }
+
+    /// Adds the devices the user has already let the page use, and scans for the advertisements
+    /// of all devices in browsers which can, which Chrome only does with its experimental web
+    /// platform features turned on. Elsewhere, this returns
+    /// [`Error::NotSupported`](crate::Error::NotSupported), and devices can only be found with
+    /// the device chooser, which scanning with [`ScanMode::Chooser`] shows.
+    async fn start_scan(&self, filter: ScanFilter) -> Result<()> {
+        local(self.scan(&filter)).await
+    }
+
+    async fn start_scan_with_options(
+        &self,
+        filter: ScanFilter,
+        options: ScanOptions,
+    ) -> Result<()> {
+        if options.mode == Some(ScanMode::Chooser) {
+            local(self.choose(&filter)).await
+        } else {
+            self.start_scan(filter).await
+        }
+    }
+
+    async fn stop_scan(&self) -> Result<()> {
+        if let Some(scan) = self.shared.scan.lock().unwrap().take() {
+            scan.stop();
+        }
+        Ok(())
+    }
+
+    async fn peripherals(&self) -> Result<Vec<Peripheral>> {
+        Ok(self.manager.peripherals())
+    }
+
+    async fn peripheral(&self, id: &PeripheralId) -> Result<Peripheral> {
+        self.manager.peripheral(id).ok_or(Error::DeviceNotFound)
+    }
+
+    async fn peripheral_by_address(&self, _address: BDAddr) -> Result<Peripheral> {
+        Err(Error::AddressNotAvailable)
+    }
+
+    /// Adds a device which the user has already let the page use, in browsers which can find
+    /// them.
+    async fn add_peripheral(&self, id: &PeripheralId) -> Result<Peripheral> {
+        match self.manager.peripheral(id) {
+            Some(peripheral) => Ok(peripheral),
+            None => local(self.find_device(id)).await,
+        }
+    }
+
+    async fn remove_peripheral(&self, id: &PeripheralId) -> Result<()> {
+        self.manager.disconnect_and_remove(id).await
+    }
+
+    async fn connect_when_available(&self, target: ConnectTarget) -> Result<Peripheral> {
+        match target {
+            ConnectTarget::Peripheral(id) => {
+                let peripheral = self.add_peripheral(&id).await?;
+                peripheral.connect().await?;
+                Ok(peripheral)
+            }
+            target => connect_when_advertising(self, target).await,
+        }
+    }
+
+    async fn adapter_state(&self) -> Result<CentralState> {
+        let available = local(call(self.shared.bluetooth.get_availability())).await?;
+        Ok(get_central_state(available.as_bool().unwrap_or(false)))
+    }
+
+    fn operation_timeout(&self) -> Option<Duration> {
+        self.manager.operation_context().timeout()
+    }
+
+    fn set_operation_timeout(&self, timeout: Option<Duration>) {
+        self.manager.operation_context().set_timeout(timeout);
+    }
+
+    fn set_observer(&self, observer: Option<Arc<dyn Observer>>) {
+        self.manager.operation_context().set_observer(observer);
+    }
+
+    fn set_peripheral_ttl(&self, ttl: Option<Duration>) {
+        self.manager.operation_context().expiry().set_ttl(ttl);
+    }
+
+    fn set_rssi_tracking(&self, tracking: Option<RssiTracking>) {
+        self.manager
+            .operation_context()
+            .rssi()
+            .set_tracking(tracking);
+    }
+}
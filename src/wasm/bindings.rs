@@ -0,0 +1,237 @@
+//! Bindings for the parts of the Web Bluetooth API which the backend uses. web-sys only has them
+//! with `--cfg=web_sys_unstable_apis`, which every application would then have to build with.
+
+use js_sys::{Array, DataView, Function, Map, Object, Promise, Reflect, Uint8Array};
+use wasm_bindgen::{prelude::wasm_bindgen, JsCast, JsValue};
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(extends = Object)]
+    #[derive(Clone, Debug)]
+    pub(super) type EventTarget;
+
+    #[wasm_bindgen(method, js_name = addEventListener)]
+    pub(super) fn add_event_listener(this: &EventTarget, kind: &str, listener: &Function);
+
+    #[wasm_bindgen(method, js_name = removeEventListener)]
+    pub(super) fn remove_event_listener(this: &EventTarget, kind: &str, listener: &Function);
+
+    /// `navigator.bluetooth`.
+    #[wasm_bindgen(extends = EventTarget, extends = Object)]
+    #[derive(Clone, Debug)]
+    pub(super) type Bluetooth;
+
+    #[wasm_bindgen(method, js_name = getAvailability)]
+    pub(super) fn get_availability(this: &Bluetooth) -> Promise;
+
+    #[wasm_bindgen(method, js_name = requestDevice)]
+    pub(super) fn request_device(this: &Bluetooth, options: &Object) -> Promise;
+
+    /// The devices the user has already let the page use. Only some browsers have it.
+    #[wasm_bindgen(method, catch, js_name = getDevices)]
+    pub(super) fn get_devices(this: &Bluetooth) -> Result<Promise, JsValue>;
+
+    /// Scans for the advertisements of all devices. Only some browsers have it, and Chrome only
+    /// with its experimental web platform features turned on.
+    #[wasm_bindgen(method, catch, js_name = requestLEScan)]
+    pub(super) fn request_le_scan(this: &Bluetooth, options: &Object) -> Result<Promise, JsValue>;
+
+    #[wasm_bindgen(extends = Object)]
+    #[derive(Clone, Debug)]
+    pub(super) type BluetoothLeScan;
+
+    #[wasm_bindgen(method)]
+    pub(super) fn stop(this: &BluetoothLeScan);
+
+    #[wasm_bindgen(extends = Object)]
+    #[derive(Clone, Debug)]
+    pub(super) type ValueEvent;
+
+    #[wasm_bindgen(method, getter)]
+    pub(super) fn value(this: &ValueEvent) -> JsValue;
+
+    #[wasm_bindgen(extends = EventTarget, extends = Object)]
+    #[derive(Clone, Debug)]
+    pub(super) type BluetoothDevice;
+
+    #[wasm_bindgen(method, getter)]
+    pub(super) fn id(this: &BluetoothDevice) -> String;
+
+    #[wasm_bindgen(method, getter)]
+    pub(super) fn name(this: &BluetoothDevice) -> Option<String>;
+
+    #[wasm_bindgen(method, getter)]
+    pub(super) fn gatt(this: &BluetoothDevice) -> Option<BluetoothRemoteGattServer>;
+
+    /// Has the device's advertisements reported with `advertisementreceived` events. Only some
+    /// browsers have it.
+    #[wasm_bindgen(method, catch, js_name = watchAdvertisements)]
+    pub(super) fn watch_advertisements(this: &BluetoothDevice) -> Result<Promise, JsValue>;
+
+    #[wasm_bindgen(extends = Object)]
+    #[derive(Clone, Debug)]
+    pub(super) type BluetoothAdvertisingEvent;
+
+    #[wasm_bindgen(method, getter)]
+    pub(super) fn bubbles(this: &BluetoothAdvertisingEvent) -> bool;
+
+    #[wasm_bindgen(method, getter)]
+    pub(super) fn device(this: &BluetoothAdvertisingEvent) -> BluetoothDevice;
+
+    #[wasm_bindgen(method, getter)]
+    pub(super) fn uuids(this: &BluetoothAdvertisingEvent) -> Array;
+
+    #[wasm_bindgen(method, getter)]
+    pub(super) fn name(this: &BluetoothAdvertisingEvent) -> Option<String>;
+
+    #[wasm_bindgen(method, getter)]
+    pub(super) fn appearance(this: &BluetoothAdvertisingEvent) -> Option<u16>;
+
+    #[wasm_bindgen(method, getter, js_name = txPower)]
+    pub(super) fn tx_power(this: &BluetoothAdvertisingEvent) -> Option<i8>;
+
+    #[wasm_bindgen(method, getter)]
+    pub(super) fn rssi(this: &BluetoothAdvertisingEvent) -> Option<i16>;
+
+    #[wasm_bindgen(method, getter, js_name = manufacturerData)]
+    pub(super) fn manufacturer_data(this: &BluetoothAdvertisingEvent) -> Map;
+
+    #[wasm_bindgen(method, getter, js_name = serviceData)]
+    pub(super) fn service_data(this: &BluetoothAdvertisingEvent) -> Map;
+
+    #[wasm_bindgen(extends = Object)]
+    #[derive(Clone, Debug)]
+    pub(super) type BluetoothRemoteGattServer;
+
+    #[wasm_bindgen(method, getter)]
+    pub(super) fn connected(this: &BluetoothRemoteGattServer) -> bool;
+
+    #[wasm_bindgen(method)]
+    pub(super) fn connect(this: &BluetoothRemoteGattServer) -> Promise;
+
+    #[wasm_bindgen(method)]
+    pub(super) fn disconnect(this: &BluetoothRemoteGattServer);
+
+    #[wasm_bindgen(method, js_name = getPrimaryServices)]
+    pub(super) fn get_primary_services(this: &BluetoothRemoteGattServer) -> Promise;
+
+    #[wasm_bindgen(extends = EventTarget, extends = Object)]
+    #[derive(Clone, Debug)]
+    pub(super) type BluetoothRemoteGattService;
+
+    #[wasm_bindgen(method, getter)]
+    pub(super) fn uuid(this: &BluetoothRemoteGattService) -> String;
+
+    #[wasm_bindgen(method, getter, js_name = isPrimary)]
+    pub(super) fn is_primary(this: &BluetoothRemoteGattService) -> bool;
+
+    #[wasm_bindgen(method, js_name = getCharacteristics)]
+    pub(super) fn get_characteristics(this: &BluetoothRemoteGattService) -> Promise;
+
+    #[wasm_bindgen(method, js_name = getIncludedServices)]
+    pub(super) fn get_included_services(this: &BluetoothRemoteGattService) -> Promise;
+
+    #[wasm_bindgen(extends = EventTarget, extends = Object)]
+    #[derive(Clone, Debug)]
+    pub(super) type BluetoothRemoteGattCharacteristic;
+
+    #[wasm_bindgen(method, getter)]
+    pub(super) fn uuid(this: &BluetoothRemoteGattCharacteristic) -> String;
+
+    #[wasm_bindgen(method, getter)]
+    pub(super) fn properties(
+        this: &BluetoothRemoteGattCharacteristic,
+    ) -> BluetoothCharacteristicProperties;
+
+    #[wasm_bindgen(method, getter)]
+    pub(super) fn value(this: &BluetoothRemoteGattCharacteristic) -> Option<DataView>;
+
+    #[wasm_bindgen(method, js_name = getDescriptors)]
+    pub(super) fn get_descriptors(this: &BluetoothRemoteGattCharacteristic) -> Promise;
+
+    #[wasm_bindgen(method, js_name = readValue)]
+    pub(super) fn read_value(this: &BluetoothRemoteGattCharacteristic) -> Promise;
+
+    #[wasm_bindgen(method, js_name = writeValueWithResponse)]
+    pub(super) fn write_value_with_response(
+        this: &BluetoothRemoteGattCharacteristic,
+        value: &Uint8Array,
+    ) -> Promise;
+
+    #[wasm_bindgen(method, js_name = writeValueWithoutResponse)]
+    pub(super) fn write_value_without_response(
+        this: &BluetoothRemoteGattCharacteristic,
+        value: &Uint8Array,
+    ) -> Promise;
+
+    #[wasm_bindgen(method, js_name = startNotifications)]
+    pub(super) fn start_notifications(this: &BluetoothRemoteGattCharacteristic) -> Promise;
+
+    #[wasm_bindgen(method, js_name = stopNotifications)]
+    pub(super) fn stop_notifications(this: &BluetoothRemoteGattCharacteristic) -> Promise;
+
+    #[wasm_bindgen(extends = Object)]
+    #[derive(Clone, Debug)]
+    pub(super) type BluetoothCharacteristicProperties;
+
+    #[wasm_bindgen(method, getter)]
+    pub(super) fn broadcast(this: &BluetoothCharacteristicProperties) -> bool;
+
+    #[wasm_bindgen(method, getter)]
+    pub(super) fn read(this: &BluetoothCharacteristicProperties) -> bool;
+
+    #[wasm_bindgen(method, getter, js_name = writeWithoutResponse)]
+    pub(super) fn write_without_response(this: &BluetoothCharacteristicProperties) -> bool;
+
+    #[wasm_bindgen(method, getter)]
+    pub(super) fn write(this: &BluetoothCharacteristicProperties) -> bool;
+
+    #[wasm_bindgen(method, getter)]
+    pub(super) fn notify(this: &BluetoothCharacteristicProperties) -> bool;
+
+    #[wasm_bindgen(method, getter)]
+    pub(super) fn indicate(this: &BluetoothCharacteristicProperties) -> bool;
+
+    #[wasm_bindgen(method, getter, js_name = authenticatedSignedWrites)]
+    pub(super) fn authenticated_signed_writes(this: &BluetoothCharacteristicProperties) -> bool;
+
+    #[wasm_bindgen(method, getter, js_name = reliableWrite)]
+    pub(super) fn reliable_write(this: &BluetoothCharacteristicProperties) -> bool;
+
+    #[wasm_bindgen(method, getter, js_name = writableAuxiliaries)]
+    pub(super) fn writable_auxiliaries(this: &BluetoothCharacteristicProperties) -> bool;
+
+    #[wasm_bindgen(extends = Object)]
+    #[derive(Clone, Debug)]
+    pub(super) type BluetoothRemoteGattDescriptor;
+
+    #[wasm_bindgen(method, getter)]
+    pub(super) fn uuid(this: &BluetoothRemoteGattDescriptor) -> String;
+
+    #[wasm_bindgen(method, js_name = readValue)]
+    pub(super) fn read_value(this: &BluetoothRemoteGattDescriptor) -> Promise;
+
+    #[wasm_bindgen(method, js_name = writeValue)]
+    pub(super) fn write_value(this: &BluetoothRemoteGattDescriptor, value: &Uint8Array) -> Promise;
+}
+
+/// Returns `navigator.bluetooth`, or `None` if the browser doesn't have Web Bluetooth, or only has
+/// it in secure contexts and the page isn't in one.
+pub(super) fn bluetooth() -> Option<Bluetooth> {
+    let navigator = Reflect::get(&js_sys::global(), &"navigator".into()).ok()?;
+    let bluetooth = Reflect::get(&navigator, &"bluetooth".into()).ok()?;
+    if bluetooth.is_undefined() || bluetooth.is_null() {
+        return None;
+    }
+    Some(bluetooth.unchecked_into())
+}
+
+/// Copies the bytes a `DataView` sees.
+pub(super) fn to_vec(view: &DataView) -> Vec<u8> {
+    Uint8Array::new_with_byte_offset_and_length(
+        &view.buffer(),
+        view.byte_offset() as u32,
+        view.byte_length() as u32,
+    )
+    .to_vec()
+}
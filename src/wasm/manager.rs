@@ -0,0 +1,42 @@
+use super::adapter::Adapter;
+use crate::api::observer::TraceHandler;
+use crate::{api, api::ManagerEvent, Result};
+use async_trait::async_trait;
+use futures::stream::{self, Stream, StreamExt};
+use std::pin::Pin;
+use std::sync::Arc;
+
+#[derive(Clone, Debug)]
+pub struct Manager;
+
+impl Manager {
+    pub async fn new() -> Result<Manager> {
+        Ok(Manager)
+    }
+}
+
+#[async_trait]
+impl api::Manager for Manager {
+    type Adapter = Adapter;
+
+    /// Returns the browser's adapter, or none if the browser doesn't have Web Bluetooth, or the
+    /// page isn't a secure context.
+    async fn adapters(&self) -> Result<Vec<Adapter>> {
+        Ok(super::global_adapter().into_iter().cloned().collect())
+    }
+
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = ManagerEvent<Adapter>> + Send>>> {
+        // There is at most one adapter, and it can't be removed.
+        let adapters = api::Manager::adapters(self).await?;
+        Ok(Box::pin(
+            stream::iter(adapters.into_iter().map(ManagerEvent::AdapterAdded))
+                .chain(stream::pending()),
+        ))
+    }
+
+    fn set_trace_handler(&self, handler: Option<Arc<dyn TraceHandler>>) {
+        if let Some(adapter) = super::global_adapter() {
+            adapter.set_trace_handler(handler);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! The backend for WebAssembly in browsers, over the Web Bluetooth API in `navigator.bluetooth`.
+//!
+//! Browsers only let a page use the devices which the user picks in the browser's device chooser,
+//! which has to be shown while handling a user gesture such as a click. It is shown by scanning
+//! with [`ScanMode::Chooser`](crate::api::ScanMode::Chooser).
+
+pub mod adapter;
+mod bindings;
+pub mod manager;
+pub mod peripheral;
+
+use self::bindings::EventTarget;
+use crate::{api::NativeError, Error, Result};
+use js_sys::{Array, Promise, Reflect};
+use send_wrapper::SendWrapper;
+use std::{future::Future, sync::OnceLock};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+
+static GLOBAL_ADAPTER: OnceLock<Option<adapter::Adapter>> = OnceLock::new();
+
+/// The only adapter, which is the one the browser uses, or `None` if the browser doesn't have Web
+/// Bluetooth.
+fn global_adapter() -> Option<&'static adapter::Adapter> {
+    GLOBAL_ADAPTER
+        .get_or_init(|| bindings::bluetooth().map(adapter::Adapter::new))
+        .as_ref()
+}
+
+/// Lets a future which holds the browser's objects, which aren't `Send`, be awaited by btleplug's
+/// futures, which must be. There is only the browser's thread for it to run on.
+fn local<F: Future>(future: F) -> SendWrapper<F> {
+    SendWrapper::new(future)
+}
+
+/// Waits for the promise, and converts the exception it's rejected with to an error.
+async fn call(promise: Promise) -> Result<JsValue> {
+    JsFuture::from(promise).await.map_err(js_error)
+}
+
+/// Waits for a promise of attributes, which is rejected with `NotFoundError` when there are none.
+async fn found(promise: Promise) -> Result<Array> {
+    match JsFuture::from(promise).await {
+        Ok(attributes) => Ok(attributes.unchecked_into()),
+        Err(e) if error_name(&e).as_deref() == Some("NotFoundError") => Ok(Array::new()),
+        Err(e) => Err(js_error(e)),
+    }
+}
+
+fn error_name(error: &JsValue) -> Option<String> {
+    Reflect::get(error, &"name".into()).ok()?.as_string()
+}
+
+/// Converts an exception from the browser, which is usually a `DOMException`, to an error.
+fn js_error(error: JsValue) -> Error {
+    let message = Reflect::get(&error, &"message".into())
+        .ok()
+        .and_then(|message| message.as_string())
+        .unwrap_or_default();
+    match error_name(&error) {
+        Some(name) if name == "SecurityError" || name == "NotAllowedError" => {
+            Error::PermissionDenied
+        }
+        Some(name) if name == "NotSupportedError" => Error::NotSupported(message),
+        Some(name) => Error::Other(Box::new(NativeError::DomException { name, message })),
+        None => Error::RuntimeError(error.as_string().unwrap_or_else(|| format!("{:?}", error))),
+    }
+}
+
+/// An event listener, which is removed once this is dropped. Listeners which may drop themselves
+/// must leave it to a spawned task, as a closure can't be dropped while it runs.
+struct Listener {
+    target: EventTarget,
+    kind: &'static str,
+    closure: Closure<dyn FnMut(JsValue)>,
+}
+
+impl Listener {
+    fn new(
+        target: &EventTarget,
+        kind: &'static str,
+        listener: impl FnMut(JsValue) + 'static,
+    ) -> Self {
+        let closure = Closure::<dyn FnMut(JsValue)>::new(listener);
+        target.add_event_listener(kind, closure.as_ref().unchecked_ref());
+        Listener {
+            target: target.clone(),
+            kind,
+            closure,
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.target
+            .remove_event_listener(self.kind, self.closure.as_ref().unchecked_ref());
+    }
+}
@@ -0,0 +1,574 @@
+use super::{
+    adapter::on_advertisement,
+    bindings::{
+        to_vec, BluetoothAdvertisingEvent, BluetoothCharacteristicProperties, BluetoothDevice,
+        BluetoothRemoteGattCharacteristic, BluetoothRemoteGattDescriptor,
+        BluetoothRemoteGattServer, BluetoothRemoteGattService,
+    },
+    call, found, local, Listener,
+};
+use crate::{
+    api::{
+        self,
+        descriptor_cache::DescriptorCache,
+        link::{self, LinkSample},
+        operation::OperationContext,
+        rssi::RssiStats,
+        spans, BDAddr, CentralEvent, CharPropFlags, Characteristic, Descriptor, DisconnectReason,
+        PeripheralEvent, PeripheralProperties, Service, SubscriptionType, ValueNotification,
+        WriteType,
+    },
+    common::adapter_manager::AdapterManager,
+    rt,
+    time::Instant,
+    Error, Result,
+};
+use async_trait::async_trait;
+use futures::stream::Stream;
+use js_sys::{DataView, Uint8Array};
+use send_wrapper::SendWrapper;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use serde_cr as serde;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::{self, Debug, Display, Formatter},
+    pin::Pin,
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+use wasm_bindgen::JsCast;
+
+/// The ID the browser gives the device, which is the same each time the page uses it, but differs
+/// between sites, and isn't its address.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_cr")
+)]
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct PeripheralId(pub(super) String);
+impl Display for PeripheralId {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+#[cfg(feature = "mock")]
+impl PeripheralId {
+    /// The ID of a peripheral of the [mock](crate::mock) backend. Browsers don't tell pages the
+    /// addresses of devices, so this is made from the address.
+    pub(crate) fn synthetic(_adapter_id: &str, address: BDAddr) -> Self {
+        PeripheralId(address.to_string())
+    }
+}
+
+/// The browser's objects for the attributes found by discovering the services, which operations
+/// on them are made through.
+#[derive(Default)]
+struct Attributes {
+    characteristics: HashMap<(Uuid, Uuid), BluetoothRemoteGattCharacteristic>,
+    descriptors: HashMap<(Uuid, Uuid, Uuid), BluetoothRemoteGattDescriptor>,
+    /// The listeners for the values of the characteristics which are subscribed to.
+    subscriptions: HashMap<(Uuid, Uuid), Listener>,
+}
+
+struct Shared {
+    id: PeripheralId,
+    device: SendWrapper<BluetoothDevice>,
+    properties: Mutex<Option<PeripheralProperties>>,
+    services: Mutex<BTreeSet<Service>>,
+    attributes: Mutex<SendWrapper<Attributes>>,
+    notifications: broadcast::Sender<ValueNotification>,
+    _listeners: SendWrapper<[Listener; 2]>,
+}
+
+#[derive(Clone)]
+pub struct Peripheral {
+    shared: Arc<Shared>,
+    descriptor_cache: DescriptorCache,
+    operation_context: OperationContext,
+    manager: Weak<AdapterManager<Peripheral>>,
+}
+
+impl Peripheral {
+    pub(super) fn new(
+        device: BluetoothDevice,
+        manager: Weak<AdapterManager<Peripheral>>,
+        operation_context: OperationContext,
+    ) -> Self {
+        let id = PeripheralId(device.id());
+        let shared = Arc::new_cyclic(|shared: &Weak<Shared>| {
+            let disconnected = Listener::new(&device, "gattserverdisconnected", {
+                let shared = shared.clone();
+                let manager = manager.clone();
+                let id = id.clone();
+                move |_event| {
+                    let shared = shared.clone();
+                    let manager = manager.clone();
+                    let id = id.clone();
+                    // Emitting the event removes the peripheral, which may drop this listener.
+                    rt::spawn(async move {
+                        spans::callback("connection", Some(&id)).in_scope(|| {
+                            // The browser's objects for the attributes can't be used once it has
+                            // disconnected, and the services are discovered again on
+                            // reconnecting.
+                            if let Some(shared) = shared.upgrade() {
+                                **shared.attributes.lock().unwrap() = Attributes::default();
+                            }
+                            if let Some(manager) = manager.upgrade() {
+                                manager.emit(CentralEvent::DeviceDisconnected(id.clone()));
+                            }
+                        })
+                    });
+                }
+            });
+            // The advertisements the browser reports once they are watched for. Those which bubble
+            // up to navigator.bluetooth are reported there, with the scan's.
+            let advertisements = Listener::new(&device, "advertisementreceived", {
+                let manager = manager.clone();
+                move |event| {
+                    let event: BluetoothAdvertisingEvent = event.unchecked_into();
+                    if let (false, Some(manager)) = (event.bubbles(), manager.upgrade()) {
+                        on_advertisement(&manager, event);
+                    }
+                }
+            });
+            let (notifications, _) = broadcast::channel(16);
+            Shared {
+                id: id.clone(),
+                device: SendWrapper::new(device),
+                properties: Mutex::new(None),
+                services: Mutex::new(BTreeSet::new()),
+                attributes: Mutex::new(SendWrapper::new(Attributes::default())),
+                notifications,
+                _listeners: SendWrapper::new([disconnected, advertisements]),
+            }
+        });
+        Peripheral {
+            shared,
+            descriptor_cache: DescriptorCache::default(),
+            operation_context: operation_context.for_peripheral(id),
+            manager,
+        }
+    }
+
+    /// The browser's object for the device.
+    pub(super) fn device(&self) -> &BluetoothDevice {
+        &self.shared.device
+    }
+
+    /// Updates the properties from an advertisement, or the device chooser. Like the other
+    /// platforms, what it doesn't have is kept from earlier ones.
+    pub(super) fn report_properties(&self, mut properties: PeripheralProperties) {
+        let mut guard = self.shared.properties.lock().unwrap();
+        if let Some(old) = guard.take() {
+            properties.local_name = properties.local_name.or(old.local_name);
+            properties.tx_power_level = properties.tx_power_level.or(old.tx_power_level);
+            properties.rssi = properties.rssi.or(old.rssi);
+            properties.appearance = properties.appearance.or(old.appearance);
+            properties.last_seen = properties.last_seen.or(old.last_seen);
+            if properties.manufacturer_data.is_empty() {
+                properties.manufacturer_data = old.manufacturer_data;
+            }
+            if properties.service_data.is_empty() {
+                properties.service_data = old.service_data;
+            }
+            if properties.services.is_empty() {
+                properties.services = old.services;
+            }
+        }
+        *guard = Some(properties);
+    }
+
+    fn server(&self) -> Result<BluetoothRemoteGattServer> {
+        self.device()
+            .gatt()
+            .ok_or_else(|| Error::NotSupported("The device has no GATT server".to_string()))
+    }
+
+    fn connected_server(&self) -> Result<BluetoothRemoteGattServer> {
+        let server = self.server()?;
+        if !server.connected() {
+            return Err(Error::NotConnected);
+        }
+        Ok(server)
+    }
+
+    fn characteristic(
+        &self,
+        characteristic: &Characteristic,
+    ) -> Result<BluetoothRemoteGattCharacteristic> {
+        self.connected_server()?;
+        self.shared
+            .attributes
+            .lock()
+            .unwrap()
+            .characteristics
+            .get(&(characteristic.service_uuid, characteristic.uuid))
+            .cloned()
+            .ok_or(Error::NoSuchCharacteristic)
+    }
+
+    fn descriptor(&self, descriptor: &Descriptor) -> Result<BluetoothRemoteGattDescriptor> {
+        self.connected_server()?;
+        self.shared
+            .attributes
+            .lock()
+            .unwrap()
+            .descriptors
+            .get(&(
+                descriptor.service_uuid,
+                descriptor.characteristic_uuid,
+                descriptor.uuid,
+            ))
+            .cloned()
+            .ok_or(Error::NoSuchDescriptor)
+    }
+
+    /// Discovers the services, their characteristics and their descriptors. The browser only
+    /// finds the services which the device chooser asked for.
+    async fn discover(&self) -> Result<()> {
+        let server = self.connected_server()?;
+        let mut services = BTreeSet::new();
+        let mut attributes = Attributes::default();
+        for service in found(server.get_primary_services()).await?.iter() {
+            let service: BluetoothRemoteGattService = service.unchecked_into();
+            let service_uuid = Uuid::parse_str(&service.uuid())?;
+            let mut characteristics = BTreeSet::new();
+            for characteristic in found(service.get_characteristics()).await?.iter() {
+                let characteristic: BluetoothRemoteGattCharacteristic =
+                    characteristic.unchecked_into();
+                let uuid = Uuid::parse_str(&characteristic.uuid())?;
+                // Only consider the first characteristic of each UUID, as it's all that can be
+                // told apart without handles.
+                if attributes
+                    .characteristics
+                    .contains_key(&(service_uuid, uuid))
+                {
+                    continue;
+                }
+                let mut descriptors = BTreeSet::new();
+                for descriptor in found(characteristic.get_descriptors()).await?.iter() {
+                    let descriptor: BluetoothRemoteGattDescriptor = descriptor.unchecked_into();
+                    let descriptor_uuid = Uuid::parse_str(&descriptor.uuid())?;
+                    descriptors.insert(Descriptor {
+                        uuid: descriptor_uuid,
+                        service_uuid,
+                        characteristic_uuid: uuid,
+                        handle: None,
+                        value: None,
+                    });
+                    attributes
+                        .descriptors
+                        .insert((service_uuid, uuid, descriptor_uuid), descriptor);
+                }
+                characteristics.insert(Characteristic {
+                    service_uuid,
+                    uuid,
+                    properties: char_prop_flags(&characteristic.properties()),
+                    descriptors,
+                    handle: None,
+                    value_handle: None,
+                });
+                attributes
+                    .characteristics
+                    .insert((service_uuid, uuid), characteristic);
+            }
+            let mut included_services = BTreeSet::new();
+            for included in found(service.get_included_services()).await?.iter() {
+                let included: BluetoothRemoteGattService = included.unchecked_into();
+                included_services.insert(Uuid::parse_str(&included.uuid())?);
+            }
+            services.insert(Service {
+                uuid: service_uuid,
+                primary: service.is_primary(),
+                characteristics,
+                included_services,
+                handle: None,
+            });
+        }
+        {
+            // Subscriptions are kept, as the browser keeps the same objects while connected.
+            let mut guard = self.shared.attributes.lock().unwrap();
+            attributes.subscriptions = std::mem::take(&mut guard.subscriptions);
+            **guard = attributes;
+        }
+        *self.shared.services.lock().unwrap() = services;
+        if let Some(manager) = self.manager.upgrade() {
+            manager
+                .peripheral_events()
+                .send(&self.shared.id, PeripheralEvent::ServicesResolved);
+        }
+        Ok(())
+    }
+
+    async fn set_subscribed(&self, characteristic: &Characteristic, subscribe: bool) -> Result<()> {
+        let target = self.characteristic(characteristic)?;
+        let key = (characteristic.service_uuid, characteristic.uuid);
+        if !subscribe {
+            call(target.stop_notifications()).await?;
+            self.shared
+                .attributes
+                .lock()
+                .unwrap()
+                .subscriptions
+                .remove(&key);
+            return Ok(());
+        }
+        // The browser uses notifications if the characteristic has them, and otherwise
+        // indications.
+        let subscription_type = SubscriptionType::preferred(characteristic.properties)
+            .ok_or_else(|| Error::NotSupported("Can not subscribe to attribute".into()))?;
+        call(target.start_notifications()).await?;
+        let listener = Listener::new(&target, "characteristicvaluechanged", {
+            let id = self.shared.id.clone();
+            let sender = self.shared.notifications.clone();
+            let target = target.clone();
+            let (service_uuid, uuid) = key;
+            move |_event| {
+                spans::callback("notification", Some(&id)).in_scope(|| {
+                    let timestamp = Instant::now();
+                    let Some(value) = target.value() else {
+                        return;
+                    };
+                    // Note: we ignore send errors here which may happen while there are no
+                    // receivers...
+                    let _ = sender.send(ValueNotification {
+                        uuid,
+                        service_uuid,
+                        handle: None,
+                        subscription_type,
+                        value: to_vec(&value),
+                        timestamp,
+                    });
+                })
+            }
+        });
+        self.shared
+            .attributes
+            .lock()
+            .unwrap()
+            .subscriptions
+            .insert(key, listener);
+        Ok(())
+    }
+}
+
+fn char_prop_flags(properties: &BluetoothCharacteristicProperties) -> CharPropFlags {
+    let mut flags = CharPropFlags::empty();
+    flags.set(CharPropFlags::BROADCAST, properties.broadcast());
+    flags.set(CharPropFlags::READ, properties.read());
+    flags.set(
+        CharPropFlags::WRITE_WITHOUT_RESPONSE,
+        properties.write_without_response(),
+    );
+    flags.set(CharPropFlags::WRITE, properties.write());
+    flags.set(CharPropFlags::NOTIFY, properties.notify());
+    flags.set(CharPropFlags::INDICATE, properties.indicate());
+    flags.set(
+        CharPropFlags::AUTHENTICATED_SIGNED_WRITES,
+        properties.authenticated_signed_writes(),
+    );
+    // The browser reports the extended properties themselves rather than the flag for them.
+    flags.set(
+        CharPropFlags::EXTENDED_PROPERTIES,
+        properties.reliable_write() || properties.writable_auxiliaries(),
+    );
+    flags
+}
+
+impl Debug for Peripheral {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Peripheral")
+            .field("id", &self.shared.id)
+            .field("properties", &self.shared.properties.lock().unwrap())
+            .finish()
+    }
+}
+
+#[async_trait]
+impl api::Peripheral for Peripheral {
+    fn id(&self) -> PeripheralId {
+        self.shared.id.clone()
+    }
+
+    /// Browsers don't tell pages the addresses of devices, so this is always zero.
+    fn address(&self) -> BDAddr {
+        BDAddr::default()
+    }
+
+    async fn properties(&self) -> Result<Option<PeripheralProperties>> {
+        Ok(self.shared.properties.lock().unwrap().clone())
+    }
+
+    fn rssi_stats(&self) -> Option<RssiStats> {
+        self.operation_context.rssi().stats(&self.id())
+    }
+
+    fn services(&self) -> BTreeSet<Service> {
+        self.descriptor_cache
+            .fill(self.shared.services.lock().unwrap().clone())
+    }
+
+    async fn is_connected(&self) -> Result<bool> {
+        Ok(self
+            .device()
+            .gatt()
+            .is_some_and(|server| server.connected()))
+    }
+
+    async fn connect(&self) -> Result<()> {
+        self.operation_context
+            .run(
+                "connect",
+                local(async {
+                    call(self.server()?.connect()).await?;
+                    if let Some(manager) = self.manager.upgrade() {
+                        manager.emit(CentralEvent::DeviceConnected(self.id()));
+                    }
+                    Ok(())
+                }),
+            )
+            .await
+    }
+
+    async fn disconnect(&self) -> Result<()> {
+        if let Some(manager) = self.manager.upgrade() {
+            manager
+                .peripheral_events()
+                .set_disconnect_reason(&self.shared.id, DisconnectReason::Local);
+        }
+        // The browser reports the disconnection with gattserverdisconnected.
+        self.server()?.disconnect();
+        Ok(())
+    }
+
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = PeripheralEvent> + Send>>> {
+        let manager = self.manager.upgrade().ok_or(Error::DeviceNotFound)?;
+        Ok(manager.peripheral_events().subscribe(self.id()))
+    }
+
+    /// Samples the link like the other platforms, though the RSSI is the last advertised one, as
+    /// browsers can't read it while connected.
+    async fn link_events(
+        &self,
+        interval: Duration,
+    ) -> Result<Pin<Box<dyn Stream<Item = LinkSample> + Send>>> {
+        Ok(link::samples(
+            self.clone(),
+            self.operation_context.clone(),
+            interval,
+        ))
+    }
+
+    async fn discover_services(&self) -> Result<()> {
+        self.operation_context
+            .run("discover services", local(self.discover()))
+            .await
+    }
+
+    async fn write(
+        &self,
+        characteristic: &Characteristic,
+        data: &[u8],
+        write_type: WriteType,
+    ) -> Result<()> {
+        self.operation_context
+            .run_write(
+                "write",
+                characteristic.uuid,
+                data,
+                local(async {
+                    let target = self.characteristic(characteristic)?;
+                    let value = Uint8Array::from(data);
+                    let promise = match write_type {
+                        WriteType::WithResponse => target.write_value_with_response(&value),
+                        WriteType::WithoutResponse => target.write_value_without_response(&value),
+                        WriteType::SignedWithoutResponse => {
+                            return Err(Error::NotSupported(
+                                "Web Bluetooth can't sign writes".to_string(),
+                            ))
+                        }
+                    };
+                    call(promise).await?;
+                    Ok(())
+                }),
+            )
+            .await
+    }
+
+    async fn read(&self, characteristic: &Characteristic) -> Result<Vec<u8>> {
+        self.operation_context
+            .run_read(
+                "read",
+                characteristic.uuid,
+                local(async {
+                    let target = self.characteristic(characteristic)?;
+                    let value: DataView = call(target.read_value()).await?.unchecked_into();
+                    Ok(to_vec(&value))
+                }),
+            )
+            .await
+    }
+
+    async fn subscribe(&self, characteristic: &Characteristic) -> Result<()> {
+        self.operation_context
+            .run_on(
+                "subscribe",
+                characteristic.uuid,
+                local(self.set_subscribed(characteristic, true)),
+            )
+            .await
+    }
+
+    async fn unsubscribe(&self, characteristic: &Characteristic) -> Result<()> {
+        self.operation_context
+            .run_on(
+                "unsubscribe",
+                characteristic.uuid,
+                local(self.set_subscribed(characteristic, false)),
+            )
+            .await
+    }
+
+    async fn notifications(&self) -> Result<Pin<Box<dyn Stream<Item = ValueNotification> + Send>>> {
+        let receiver = self.shared.notifications.subscribe();
+        Ok(self.operation_context.notifications_stream(receiver))
+    }
+
+    async fn write_descriptor(&self, descriptor: &Descriptor, data: &[u8]) -> Result<()> {
+        self.operation_context
+            .run_write(
+                "write descriptor",
+                descriptor.uuid,
+                data,
+                local(async {
+                    let target = self.descriptor(descriptor)?;
+                    call(target.write_value(&Uint8Array::from(data))).await?;
+                    self.descriptor_cache.insert(descriptor, data);
+                    Ok(())
+                }),
+            )
+            .await
+    }
+
+    async fn read_descriptor(&self, descriptor: &Descriptor) -> Result<Vec<u8>> {
+        self.operation_context
+            .run_read(
+                "read descriptor",
+                descriptor.uuid,
+                local(async {
+                    let target = self.descriptor(descriptor)?;
+                    let value: DataView = call(target.read_value()).await?.unchecked_into();
+                    let value = to_vec(&value);
+                    self.descriptor_cache.insert(descriptor, &value);
+                    Ok(value)
+                }),
+            )
+            .await
+    }
+}
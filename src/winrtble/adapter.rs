@@ -11,20 +11,51 @@
 //
 // Copyright (c) 2014 The Rust Project Developers
 
-use super::{ble::watcher::BLEWatcher, peripheral::Peripheral, peripheral::PeripheralId};
+use super::{
+    ble::device,
+    ble::inquiry::ClassicWatcher,
+    ble::paired::PairedWatcher,
+    ble::watcher::BLEWatcher,
+    peripheral::Peripheral,
+    peripheral::{to_advertisement_type, PeripheralId},
+    utils,
+};
 use crate::{
-    api::{BDAddr, Central, CentralEvent, CentralState, ScanFilter},
+    api::{
+        advertiser::{Advertiser, Advertising},
+        bleuuid::BleUuid,
+        event_filter,
+        observer::{Observer, Tracer},
+        rssi::RssiTracking,
+        scan_response::ScanResponses,
+        spans, AdapterCapabilities, AdapterInfo, BDAddr, Central, CentralEvent, CentralState,
+        EventFilter, ScanFilter,
+    },
     common::adapter_manager::AdapterManager,
     Error, Result,
 };
 use async_trait::async_trait;
 use futures::stream::Stream;
+use log::{debug, trace};
 use std::convert::TryInto;
 use std::fmt::{self, Debug, Formatter};
+use std::future::IntoFuture;
 use std::pin::Pin;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
 use windows::{
-    Devices::Radios::{Radio, RadioState},
+    core::Ref,
+    Devices::{
+        Bluetooth::{
+            Advertisement::{
+                BluetoothLEAdvertisementDataSection, BluetoothLEAdvertisementPublisher,
+                BluetoothLEManufacturerData,
+            },
+            BluetoothAdapter,
+        },
+        Radios::{Radio, RadioAccessStatus, RadioState},
+    },
     Foundation::TypedEventHandler,
 };
 
@@ -32,8 +63,26 @@ use windows::{
 #[derive(Clone)]
 pub struct Adapter {
     watcher: Arc<Mutex<BLEWatcher>>,
+    classic_watcher: Arc<Mutex<Option<ClassicWatcher>>>,
+    _paired_watcher: Arc<PairedWatcher>,
     manager: Arc<AdapterManager<Peripheral>>,
     radio: Radio,
+    _state_handler: Arc<StateHandler>,
+}
+
+/// Removes the handler for changes to the radio's state when the last clone of the adapter is
+/// dropped.
+struct StateHandler {
+    radio: Radio,
+    token: i64,
+}
+
+impl Drop for StateHandler {
+    fn drop(&mut self) {
+        if let Err(err) = self.radio.RemoveStateChanged(self.token) {
+            debug!("Drop:remove_state_changed {:?}", err);
+        }
+    }
 }
 
 // https://github.com/microsoft/windows-rs/blob/master/crates/libs/windows/src/Windows/Devices/Radios/mod.rs
@@ -41,33 +90,110 @@ fn get_central_state(radio: &Radio) -> CentralState {
     let state = radio.State().unwrap_or(RadioState::Unknown);
     match state {
         RadioState::On => CentralState::PoweredOn,
-        RadioState::Off => CentralState::PoweredOff,
+        // A disabled radio is switched off by firmware or a hardware switch, not by us.
+        RadioState::Off | RadioState::Disabled => CentralState::PoweredOff,
         _ => CentralState::Unknown,
     }
 }
 
+fn to_result(status: RadioAccessStatus) -> Result<()> {
+    match status {
+        RadioAccessStatus::Allowed => Ok(()),
+        RadioAccessStatus::DeniedByUser | RadioAccessStatus::DeniedBySystem => {
+            Err(Error::PermissionDenied)
+        }
+        status => Err(Error::Other(
+            format!("Failed to set radio state: {:?}", status).into(),
+        )),
+    }
+}
+
 impl Adapter {
-    pub(crate) fn new(radio: Radio) -> Result<Self> {
+    pub(crate) fn new(radio: Radio, tracer: Tracer) -> Result<Self> {
         let watcher = Arc::new(Mutex::new(BLEWatcher::new()?));
-        let manager = Arc::new(AdapterManager::default());
+        let manager = Arc::new(AdapterManager::<Peripheral>::new(tracer));
 
-        let radio_clone = radio.clone();
-        let manager_clone = manager.clone();
-        let handler = TypedEventHandler::new(move |_sender, _args| {
-            let state = get_central_state(&radio_clone);
-            manager_clone.emit(CentralEvent::StateUpdate(state.into()));
+        // Windows raises StateChanged more than once for one change, and for changes to other
+        // properties of the radio, so only pass on actual changes of state.
+        let last_state = Mutex::new(get_central_state(&radio));
+        let manager_weak = Arc::downgrade(&manager);
+        let handler = TypedEventHandler::new(move |radio: Ref<Radio>, _| {
+            let (Ok(radio), Some(manager)) = (radio.ok(), manager_weak.upgrade()) else {
+                return Ok(());
+            };
+            let state = get_central_state(radio);
+            let mut last_state = last_state.lock().unwrap();
+            if *last_state != state {
+                *last_state = state.clone();
+                trace!("radio state {:?}", state);
+                manager.emit(CentralEvent::StateUpdate(state));
+            }
             Ok(())
         });
-        if let Err(err) = radio.StateChanged(&handler) {
-            eprintln!("radio.StateChanged error: {}", err);
-        }
+        let token = radio.StateChanged(&handler)?;
+
+        // Paired devices which aren't advertising are never seen by the advertisement watcher,
+        // so list them from the system's device enumeration, which also reports connections to
+        // them made by other apps.
+        let manager_weak = Arc::downgrade(&manager);
+        let paired_watcher = PairedWatcher::start(Box::new(move |device| {
+            let Some(manager) = manager_weak.upgrade() else {
+                return;
+            };
+            let id = device.address.into();
+            let (changed, discovered) = match manager.peripheral(&id) {
+                Some(peripheral) => (peripheral.update_paired(&device), false),
+                None => {
+                    let peripheral = Peripheral::new(
+                        Arc::downgrade(&manager),
+                        device.address,
+                        manager.operation_context().clone(),
+                    );
+                    let changed = peripheral.update_paired(&device);
+                    manager.add_peripheral(peripheral);
+                    (changed, true)
+                }
+            };
+            if discovered {
+                manager.emit(CentralEvent::DeviceDiscovered(id.clone()));
+            }
+            if changed {
+                trace!(
+                    "paired device {} connected {}",
+                    device.address,
+                    device.connected
+                );
+                manager.emit(if device.connected {
+                    CentralEvent::DeviceConnected(id)
+                } else {
+                    CentralEvent::DeviceDisconnected(id)
+                });
+            }
+        }))?;
 
         Ok(Adapter {
             watcher,
+            classic_watcher: Arc::new(Mutex::new(None)),
+            _paired_watcher: Arc::new(paired_watcher),
             manager,
+            _state_handler: Arc::new(StateHandler {
+                radio: radio.clone(),
+                token,
+            }),
             radio,
         })
     }
+
+    /// Returns an error if the radio is off, as Windows doesn't report one when scanning with it
+    /// off but just never finds anything.
+    fn check_powered(&self) -> Result<()> {
+        match get_central_state(&self.radio) {
+            CentralState::PoweredOff => {
+                Err(Error::RuntimeError("Bluetooth is turned off".to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 impl Debug for Adapter {
@@ -86,23 +212,70 @@ impl Central for Adapter {
         Ok(self.manager.event_stream())
     }
 
+    async fn events_filtered(
+        &self,
+        filter: EventFilter,
+    ) -> Result<Pin<Box<dyn Stream<Item = CentralEvent> + Send>>> {
+        let events = self.manager.filtered_event_stream(&filter);
+        Ok(event_filter::by_services(self, events, filter.services))
+    }
+
     async fn start_scan(&self, filter: ScanFilter) -> Result<()> {
+        self.check_powered()?;
+        if filter.classic {
+            let mut classic_watcher = self.classic_watcher.lock().map_err(Into::<Error>::into)?;
+            if classic_watcher.is_none() {
+                let manager = self.manager.clone();
+                *classic_watcher = Some(ClassicWatcher::start(Box::new(move |device| {
+                    manager.emit(CentralEvent::ClassicDeviceDiscovered(device));
+                }))?);
+            }
+        }
         let watcher = self.watcher.lock().map_err(Into::<Error>::into)?;
         let manager = self.manager.clone();
+        // Windows reports scan responses separately from the advertisements they respond to.
+        let scan_responses = ScanResponses::default();
         watcher.start(
             filter,
             Box::new(move |args| {
                 let bluetooth_address = args.BluetoothAddress()?;
                 let address: BDAddr = bluetooth_address.try_into().unwrap();
-                if let Some(mut entry) = manager.peripheral_mut(&address.into()) {
-                    entry.value_mut().update_properties(args);
-                    manager.emit(CentralEvent::DeviceUpdated(address.into()));
-                } else {
-                    let peripheral = Peripheral::new(Arc::downgrade(&manager), address);
-                    peripheral.update_properties(args);
-                    manager.add_peripheral(peripheral);
-                    manager.emit(CentralEvent::DeviceDiscovered(address.into()));
-                }
+                let id = PeripheralId::from(address);
+                let span = spans::callback("advertisement", Some(&id));
+                span.in_scope(|| {
+                    let events =
+                        scan_responses.receive(address, to_advertisement_type(args), args.clone());
+                    for event in events {
+                        // The controller only passes on directed advertisements addressed to it.
+                        let directed = event
+                            .advertisement
+                            .as_ref()
+                            .and_then(to_advertisement_type)
+                            .is_some_and(|kind| kind.is_directed());
+                        let reports = event.advertisement.iter().chain(&event.scan_response);
+                        if let Some(mut entry) = manager.peripheral_mut(&address.into()) {
+                            for args in reports {
+                                entry.value_mut().update_properties(args);
+                            }
+                            drop(entry);
+                            manager.emit(CentralEvent::DeviceUpdated(address.into()));
+                        } else {
+                            let peripheral = Peripheral::new(
+                                Arc::downgrade(&manager),
+                                address,
+                                manager.operation_context().clone(),
+                            );
+                            for args in reports {
+                                peripheral.update_properties(args);
+                            }
+                            manager.add_peripheral(peripheral);
+                            manager.emit(CentralEvent::DeviceDiscovered(address.into()));
+                        }
+                        if directed {
+                            manager.emit(CentralEvent::DirectedAdvertisement(address.into()));
+                        }
+                    }
+                });
                 Ok(())
             }),
         )
@@ -111,6 +284,14 @@ impl Central for Adapter {
     async fn stop_scan(&self) -> Result<()> {
         let watcher = self.watcher.lock().map_err(Into::<Error>::into)?;
         watcher.stop()?;
+        let classic_watcher = self
+            .classic_watcher
+            .lock()
+            .map_err(Into::<Error>::into)?
+            .take();
+        if let Some(classic_watcher) = classic_watcher {
+            classic_watcher.stop()?;
+        }
         Ok(())
     }
 
@@ -122,18 +303,146 @@ impl Central for Adapter {
         self.manager.peripheral(id).ok_or(Error::DeviceNotFound)
     }
 
+    async fn peripheral_by_address(&self, address: BDAddr) -> Result<Peripheral> {
+        self.manager
+            .peripheral(&address.into())
+            .ok_or(Error::DeviceNotFound)
+    }
+
+    async fn connected_peripherals(&self, services: &[Uuid]) -> Result<Vec<Peripheral>> {
+        let addresses = device::connected_devices(services).await?;
+        Ok(addresses
+            .into_iter()
+            .map(|address| {
+                self.manager.peripheral(&address.into()).unwrap_or_else(|| {
+                    let peripheral = Peripheral::new(
+                        Arc::downgrade(&self.manager),
+                        address,
+                        self.manager.operation_context().clone(),
+                    );
+                    self.manager.add_peripheral(peripheral.clone());
+                    self.manager
+                        .emit(CentralEvent::DeviceDiscovered(address.into()));
+                    peripheral
+                })
+            })
+            .collect())
+    }
+
     async fn add_peripheral(&self, _address: &PeripheralId) -> Result<Peripheral> {
         Err(Error::NotSupported(
             "Can't add a Peripheral from a BDAddr".to_string(),
         ))
     }
 
-    async fn adapter_info(&self) -> Result<String> {
-        // TODO: Get information about the adapter.
-        Ok("WinRT".to_string())
+    async fn remove_peripheral(&self, id: &PeripheralId) -> Result<()> {
+        self.manager.disconnect_and_remove(id).await
+    }
+
+    async fn adapter_info(&self) -> Result<AdapterInfo> {
+        let mut info = AdapterInfo {
+            description: "WinRT".to_string(),
+            name: self.radio.Name().ok().map(|name| name.to_string()),
+            ..Default::default()
+        };
+        // Windows only uses one Bluetooth adapter at a time, which is the default one.
+        let adapter = BluetoothAdapter::GetDefaultAsync()?.into_future().await?;
+        info.id = adapter.DeviceId()?.to_string();
+        info.address = adapter
+            .BluetoothAddress()
+            .ok()
+            .and_then(|address| address.try_into().ok());
+        info.capabilities = AdapterCapabilities {
+            extended_advertising: adapter.IsExtendedAdvertisingSupported().ok(),
+            // WinRT doesn't report which PHYs are supported.
+            le_2m_phy: None,
+            le_coded_phy: None,
+            peripheral_role: adapter.IsPeripheralRoleSupported().ok(),
+            eatt: None,
+            advertisement_monitor: Some(false),
+        };
+        Ok(info)
     }
 
     async fn adapter_state(&self) -> Result<CentralState> {
         Ok(get_central_state(&self.radio))
     }
+
+    async fn set_powered(&self, powered: bool) -> Result<()> {
+        let state = if powered {
+            RadioState::On
+        } else {
+            RadioState::Off
+        };
+        // Apps have to be allowed to control radios before setting their state; until they are,
+        // SetStateAsync is denied.
+        to_result(Radio::RequestAccessAsync()?.into_future().await?)?;
+        to_result(self.radio.SetStateAsync(state)?.into_future().await?)
+    }
+
+    /// The publisher adds the flags itself, and doesn't let applications advertise a name.
+    async fn start_advertising(&self, advertiser: Advertiser) -> Result<Advertising> {
+        if advertiser.local_name.is_some() {
+            return Err(Error::NotSupported(
+                "Windows doesn't let applications advertise a local name".to_string(),
+            ));
+        }
+        advertiser.to_ad_structures()?;
+        let publisher = BluetoothLEAdvertisementPublisher::new()?;
+        let advertisement = publisher.Advertisement()?;
+        for uuid in &advertiser.services {
+            advertisement.ServiceUuids()?.Append(utils::to_guid(uuid))?;
+        }
+        for (uuid, data) in &advertiser.service_data {
+            // Service Data - 16-bit UUID, or Service Data - 128-bit UUID.
+            let (data_type, mut section) = match uuid.to_ble_u16() {
+                Some(short) => (0x16, short.to_le_bytes().to_vec()),
+                None => (0x21, uuid.as_u128().to_le_bytes().to_vec()),
+            };
+            section.extend_from_slice(data);
+            advertisement
+                .DataSections()?
+                .Append(&BluetoothLEAdvertisementDataSection::Create(
+                    data_type,
+                    &utils::to_buffer(&section)?,
+                )?)?;
+        }
+        for (&company_id, data) in &advertiser.manufacturer_data {
+            advertisement
+                .ManufacturerData()?
+                .Append(&BluetoothLEManufacturerData::Create(
+                    company_id,
+                    &utils::to_buffer(data)?,
+                )?)?;
+        }
+        publisher.Start()?;
+        Ok(Advertising::new(move || {
+            if let Err(err) = publisher.Stop() {
+                debug!("Failed to stop advertising: {:?}", err);
+            }
+        }))
+    }
+
+    fn operation_timeout(&self) -> Option<Duration> {
+        self.manager.operation_context().timeout()
+    }
+
+    fn set_operation_timeout(&self, timeout: Option<Duration>) {
+        self.manager.operation_context().set_timeout(timeout);
+    }
+
+    fn set_observer(&self, observer: Option<Arc<dyn Observer>>) {
+        self.manager.operation_context().set_observer(observer);
+    }
+
+    fn set_peripheral_ttl(&self, ttl: Option<Duration>) {
+        self.manager.operation_context().expiry().set_ttl(ttl);
+    }
+
+    fn set_rssi_tracking(&self, tracking: Option<RssiTracking>) {
+        self.manager
+            .operation_context()
+            .rssi()
+            .set_tracking(tracking);
+    }
 }
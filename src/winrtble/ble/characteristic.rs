@@ -13,7 +13,7 @@
 
 use super::{super::utils::to_descriptor_value, descriptor::BLEDescriptor};
 use crate::{
-    api::{Characteristic, WriteType},
+    api::{Characteristic, ClientConfiguration, SubscriptionType, WriteType},
     winrtble::utils,
     Error, Result,
 };
@@ -36,11 +36,17 @@ use windows::{
 
 pub type NotifiyEventHandler = Box<dyn Fn(Vec<u8>) + Send>;
 
-impl From<WriteType> for GattWriteOption {
-    fn from(val: WriteType) -> Self {
+impl TryFrom<WriteType> for GattWriteOption {
+    type Error = Error;
+
+    fn try_from(val: WriteType) -> Result<Self> {
         match val {
-            WriteType::WithoutResponse => GattWriteOption::WriteWithoutResponse,
-            WriteType::WithResponse => GattWriteOption::WriteWithResponse,
+            WriteType::WithoutResponse => Ok(GattWriteOption::WriteWithoutResponse),
+            WriteType::WithResponse => Ok(GattWriteOption::WriteWithResponse),
+            // Windows doesn't expose signed writes.
+            WriteType::SignedWithoutResponse => Err(Error::NotSupported(
+                "Signed writes aren't supported on this platform".into(),
+            )),
         }
     }
 }
@@ -65,19 +71,14 @@ impl BLECharacteristic {
     }
 
     pub async fn write_value(&self, data: &[u8], write_type: WriteType) -> Result<()> {
+        let option = GattWriteOption::try_from(write_type)?;
         let writer = DataWriter::new()?;
         writer.WriteBytes(data)?;
         let operation = self
             .characteristic
-            .WriteValueWithOptionAsync(&writer.DetachBuffer()?, write_type.into())?;
+            .WriteValueWithResultAndOptionAsync(&writer.DetachBuffer()?, option)?;
         let result = operation.into_future().await?;
-        if result == GattCommunicationStatus::Success {
-            Ok(())
-        } else {
-            Err(Error::Other(
-                format!("Windows UWP threw error on write: {:?}", result).into(),
-            ))
-        }
+        utils::to_gatt_result("write", result.Status()?, result.ProtocolError())
     }
 
     pub async fn read_value(&self) -> Result<Vec<u8>> {
@@ -86,21 +87,51 @@ impl BLECharacteristic {
             .ReadValueWithCacheModeAsync(BluetoothCacheMode::Uncached)?
             .into_future()
             .await?;
-        if result.Status()? == GattCommunicationStatus::Success {
-            let value = result.Value()?;
-            let reader = DataReader::FromBuffer(&value)?;
-            let len = reader.UnconsumedBufferLength()? as usize;
-            let mut input = vec![0u8; len];
-            reader.ReadBytes(&mut input[0..len])?;
-            Ok(input)
-        } else {
-            Err(Error::Other(
-                format!("Windows UWP threw error on read: {:?}", result).into(),
-            ))
+        utils::to_gatt_result("read", result.Status()?, result.ProtocolError())?;
+        let value = result.Value()?;
+        let reader = DataReader::FromBuffer(&value)?;
+        let len = reader.UnconsumedBufferLength()? as usize;
+        let mut input = vec![0u8; len];
+        reader.ReadBytes(&mut input[0..len])?;
+        Ok(input)
+    }
+
+    pub fn attribute_handle(&self) -> Option<u16> {
+        self.characteristic.AttributeHandle().ok()
+    }
+
+    /// The handle of the value attribute, which directly follows the characteristic declaration
+    /// that `AttributeHandle` refers to.
+    pub fn value_handle(&self) -> Option<u16> {
+        self.attribute_handle()?.checked_add(1)
+    }
+
+    /// The type of subscription to use when none is asked for. Windows prefers indications when
+    /// the characteristic supports both.
+    pub fn default_subscription_type(&self) -> Result<SubscriptionType> {
+        match to_descriptor_value(self.characteristic.CharacteristicProperties()?) {
+            GattClientCharacteristicConfigurationDescriptorValue::Indicate => {
+                Ok(SubscriptionType::Indicate)
+            }
+            GattClientCharacteristicConfigurationDescriptorValue::Notify => {
+                Ok(SubscriptionType::Notify)
+            }
+            _ => Err(Error::NotSupported("Can not subscribe to attribute".into())),
         }
     }
 
-    pub async fn subscribe(&mut self, on_value_changed: NotifiyEventHandler) -> Result<()> {
+    pub async fn subscribe(
+        &mut self,
+        subscription_type: SubscriptionType,
+        on_value_changed: NotifiyEventHandler,
+    ) -> Result<()> {
+        let properties = utils::to_char_props(&self.characteristic.CharacteristicProperties()?);
+        if !properties.contains(subscription_type.property()) {
+            return Err(Error::NotSupported(format!(
+                "Characteristic doesn't support {:?}",
+                subscription_type
+            )));
+        }
         {
             let value_handler = TypedEventHandler::new(
                 move |_: Ref<GattCharacteristic>, args: Ref<GattValueChangedEventArgs>| {
@@ -119,24 +150,22 @@ impl BLECharacteristic {
             let token = self.characteristic.ValueChanged(&value_handler)?;
             self.notify_token = Some(token);
         }
-        let config = to_descriptor_value(self.characteristic.CharacteristicProperties()?);
-        if config == GattClientCharacteristicConfigurationDescriptorValue::None {
-            return Err(Error::NotSupported("Can not subscribe to attribute".into()));
-        }
-
-        let status = self
+        let config = match subscription_type {
+            SubscriptionType::Notify => {
+                GattClientCharacteristicConfigurationDescriptorValue::Notify
+            }
+            SubscriptionType::Indicate => {
+                GattClientCharacteristicConfigurationDescriptorValue::Indicate
+            }
+        };
+        let result = self
             .characteristic
-            .WriteClientCharacteristicConfigurationDescriptorAsync(config)?
+            .WriteClientCharacteristicConfigurationDescriptorWithResultAsync(config)?
             .into_future()
             .await?;
+        let status = result.Status()?;
         trace!("subscribe {:?}", status);
-        if status == GattCommunicationStatus::Success {
-            Ok(())
-        } else {
-            Err(Error::Other(
-                format!("Windows UWP threw error on subscribe: {:?}", status).into(),
-            ))
-        }
+        utils::to_gatt_result("subscribe", status, result.ProtocolError())
     }
 
     pub async fn unsubscribe(&mut self) -> Result<()> {
@@ -145,17 +174,32 @@ impl BLECharacteristic {
         }
         self.notify_token = None;
         let config = GattClientCharacteristicConfigurationDescriptorValue::None;
-        let status = self
+        let result = self
             .characteristic
-            .WriteClientCharacteristicConfigurationDescriptorAsync(config)?
+            .WriteClientCharacteristicConfigurationDescriptorWithResultAsync(config)?
             .into_future()
             .await?;
+        let status = result.Status()?;
         trace!("unsubscribe {:?}", status);
-        if status == GattCommunicationStatus::Success {
-            Ok(())
+        utils::to_gatt_result("unsubscribe", status, result.ProtocolError())
+    }
+
+    pub async fn read_client_configuration(&self) -> Result<ClientConfiguration> {
+        let result = self
+            .characteristic
+            .ReadClientCharacteristicConfigurationDescriptorAsync()?
+            .into_future()
+            .await?;
+        if result.Status()? == GattCommunicationStatus::Success {
+            let value = result.ClientCharacteristicConfigurationDescriptor()?;
+            Ok(ClientConfiguration::from_bits_truncate(value.0 as u16))
         } else {
             Err(Error::Other(
-                format!("Windows UWP threw error on unsubscribe: {:?}", status).into(),
+                format!(
+                    "Windows UWP threw error on CCCD read: {:?}",
+                    result.Status()?
+                )
+                .into(),
             ))
         }
     }
@@ -178,6 +222,8 @@ impl BLECharacteristic {
             service_uuid,
             descriptors,
             properties,
+            handle: self.attribute_handle(),
+            value_handle: self.value_handle(),
         }
     }
 }
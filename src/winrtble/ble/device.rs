@@ -14,8 +14,9 @@
 use crate::{api::BDAddr, winrtble::utils, Error, Result};
 use log::{debug, trace};
 use std::future::IntoFuture;
+use uuid::Uuid;
 use windows::{
-    core::Ref,
+    core::{IInspectable, Ref, HSTRING},
     Devices::Bluetooth::{
         BluetoothCacheMode, BluetoothConnectionStatus, BluetoothLEDevice,
         GenericAttributeProfile::{
@@ -23,14 +24,17 @@ use windows::{
             GattDeviceServicesResult,
         },
     },
+    Devices::Enumeration::DeviceInformation,
     Foundation::TypedEventHandler,
 };
 
 pub type ConnectedEventHandler = Box<dyn Fn(bool) + Send>;
+pub type ServicesChangedEventHandler = Box<dyn Fn() + Send>;
 
 pub struct BLEDevice {
     device: BluetoothLEDevice,
     connection_token: i64,
+    services_changed_token: i64,
     services: Vec<GattDeviceService>,
 }
 
@@ -38,6 +42,7 @@ impl BLEDevice {
     pub async fn new(
         address: BDAddr,
         connection_status_changed: ConnectedEventHandler,
+        services_changed: ServicesChangedEventHandler,
     ) -> Result<Self> {
         let async_op = BluetoothLEDevice::FromBluetoothAddressAsync(address.into())
             .map_err(|_| Error::DeviceNotFound)?;
@@ -61,10 +66,20 @@ impl BLEDevice {
         let connection_token = device
             .ConnectionStatusChanged(&connection_status_handler)
             .map_err(|_| Error::Other("Could not add connection status handler".into()))?;
+        let services_changed_handler =
+            TypedEventHandler::new(move |_: Ref<BluetoothLEDevice>, _: Ref<IInspectable>| {
+                trace!("services changed");
+                services_changed();
+                Ok(())
+            });
+        let services_changed_token = device
+            .GattServicesChanged(&services_changed_handler)
+            .map_err(|_| Error::Other("Could not add services changed handler".into()))?;
 
         Ok(BLEDevice {
             device,
             connection_token,
+            services_changed_token,
             services: vec![],
         })
     }
@@ -92,6 +107,13 @@ impl BLEDevice {
         utils::to_error(status)
     }
 
+    /// The name Windows has for the device, read from its Generic Access service when it was
+    /// last connected if it didn't advertise one.
+    pub fn name(&self) -> Result<String> {
+        let winrt_error = |e| Error::Other(format!("{:?}", e).into());
+        Ok(self.device.Name().map_err(winrt_error)?.to_string())
+    }
+
     async fn is_connected(&self) -> Result<bool> {
         let winrt_error = |e| Error::Other(format!("{:?}", e).into());
         let status = self.device.ConnectionStatus().map_err(winrt_error)?;
@@ -153,6 +175,62 @@ impl BLEDevice {
         }
     }
 
+    pub async fn discover_services_for_uuids(
+        &mut self,
+        uuids: &[Uuid],
+    ) -> Result<&[GattDeviceService]> {
+        let winrt_error = |e| Error::Other(format!("{:?}", e).into());
+        let mut services = vec![];
+        for uuid in uuids {
+            let service_result = self
+                .device
+                .GetGattServicesForUuidWithCacheModeAsync(
+                    utils::to_guid(uuid),
+                    BluetoothCacheMode::Uncached,
+                )
+                .map_err(winrt_error)?
+                .into_future()
+                .await
+                .map_err(winrt_error)?;
+            let status = service_result.Status().map_err(winrt_error)?;
+            if status == GattCommunicationStatus::Success {
+                services.extend(service_result.Services().map_err(winrt_error)?);
+            }
+        }
+        debug!("services {:?}", services.len());
+        // Keep the services we already had, so they're still closed when we're dropped.
+        let start = self.services.len();
+        self.services.extend(services);
+        Ok(&self.services[start..])
+    }
+
+    /// Gets the services included by `service`. These are kept along with the device's own
+    /// services, so they're closed when we're dropped.
+    pub async fn get_included_services(
+        &mut self,
+        service: &GattDeviceService,
+    ) -> Result<Vec<GattDeviceService>> {
+        let winrt_error = |e| Error::Other(format!("{:?}", e).into());
+        let service_result = service
+            .GetIncludedServicesWithCacheModeAsync(BluetoothCacheMode::Uncached)
+            .map_err(winrt_error)?
+            .into_future()
+            .await
+            .map_err(winrt_error)?;
+        let status = service_result.Status().map_err(winrt_error)?;
+        if status != GattCommunicationStatus::Success {
+            debug!("included services for {:?} failed: {:?}", service, status);
+            return Ok(vec![]);
+        }
+        let services: Vec<_> = service_result
+            .Services()
+            .map_err(winrt_error)?
+            .into_iter()
+            .collect();
+        self.services.extend(services.iter().cloned());
+        Ok(services)
+    }
+
     pub async fn discover_services(&mut self) -> Result<&[GattDeviceService]> {
         let winrt_error = |e| Error::Other(format!("{:?}", e).into());
         let service_result = self.get_gatt_services(BluetoothCacheMode::Uncached).await?;
@@ -172,6 +250,53 @@ impl BLEDevice {
     }
 }
 
+/// Returns the addresses of the LE devices connected to the system which have any of the given
+/// services, or any services if `services` is empty. Only services Windows has cached are
+/// considered, so as not to send requests to devices another app is using.
+pub async fn connected_devices(services: &[Uuid]) -> Result<Vec<BDAddr>> {
+    let selector = BluetoothLEDevice::GetDeviceSelectorFromConnectionStatus(
+        BluetoothConnectionStatus::Connected,
+    )?;
+    // The collection isn't Send, so take the IDs out of it before awaiting anything else.
+    let ids: Vec<HSTRING> = DeviceInformation::FindAllAsyncAqsFilter(&selector)?
+        .into_future()
+        .await?
+        .into_iter()
+        .map(|info| info.Id())
+        .collect::<windows::core::Result<_>>()?;
+    let mut addresses = vec![];
+    for id in ids {
+        let device = BluetoothLEDevice::FromIdAsync(&id)?.into_future().await?;
+        let mut matches = services.is_empty();
+        for uuid in services {
+            if matches {
+                break;
+            }
+            let result = device
+                .GetGattServicesForUuidWithCacheModeAsync(
+                    utils::to_guid(uuid),
+                    BluetoothCacheMode::Cached,
+                )?
+                .into_future()
+                .await?;
+            let services: Vec<_> = result.Services()?.into_iter().collect();
+            matches = result.Status()? == GattCommunicationStatus::Success && !services.is_empty();
+            for service in services {
+                let _ = service.Close();
+            }
+        }
+        if matches {
+            if let Ok(address) = device.BluetoothAddress()?.try_into() {
+                addresses.push(address);
+            }
+        }
+        if let Err(err) = device.Close() {
+            debug!("connected_devices:close {:?}", err);
+        }
+    }
+    Ok(addresses)
+}
+
 impl Drop for BLEDevice {
     fn drop(&mut self) {
         let result = self
@@ -181,6 +306,13 @@ impl Drop for BLEDevice {
             debug!("Drop:remove_connection_status_changed {:?}", err);
         }
 
+        let result = self
+            .device
+            .RemoveGattServicesChanged(self.services_changed_token);
+        if let Err(err) = result {
+            debug!("Drop:remove_gatt_services_changed {:?}", err);
+        }
+
         self.services.iter().for_each(|service| {
             if let Err(err) = service.Close() {
                 debug!("Drop:remove_gatt_Service {:?}", err);
@@ -0,0 +1,92 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use crate::{
+    api::{BDAddr, ClassicDevice},
+    Result,
+};
+use log::debug;
+use windows::{
+    core::{Interface, Ref, HSTRING},
+    Devices::{
+        Bluetooth::BluetoothDevice,
+        Enumeration::{
+            DeviceInformation, DeviceInformationKind, DeviceInformationUpdate, DeviceWatcher,
+        },
+    },
+    Foundation::{IReference, TypedEventHandler},
+};
+use windows_collections::IIterable;
+
+/// Selects the association endpoints of devices found by a Bluetooth Classic inquiry.
+const BREDR_PROTOCOL_FILTER: &str =
+    r#"System.Devices.Aep.ProtocolId:="{e0cbf06c-cd8b-4647-bb8a-263b43f0f974}""#;
+const SIGNAL_STRENGTH: &str = "System.Devices.Aep.SignalStrength";
+
+pub type ClassicDeviceHandler = Box<dyn Fn(ClassicDevice) + Send>;
+
+/// Looks for Bluetooth Classic devices, which the advertisement watcher doesn't find.
+#[derive(Debug)]
+pub struct ClassicWatcher {
+    watcher: DeviceWatcher,
+}
+
+impl ClassicWatcher {
+    pub fn start(on_found: ClassicDeviceHandler) -> Result<Self> {
+        let properties = IIterable::<HSTRING>::from(vec![HSTRING::from(SIGNAL_STRENGTH)]);
+        let watcher = DeviceInformation::CreateWatcherWithKindAqsFilterAndAdditionalProperties(
+            &HSTRING::from(BREDR_PROTOCOL_FILTER),
+            &properties,
+            DeviceInformationKind::AssociationEndpoint,
+        )?;
+        watcher.Added(&TypedEventHandler::new(
+            move |_sender, info: Ref<DeviceInformation>| {
+                if let Ok(info) = info.ok() {
+                    match classic_device(info) {
+                        Ok(device) => on_found(device),
+                        Err(err) => debug!("classic_device {:?}", err),
+                    }
+                }
+                Ok(())
+            },
+        ))?;
+        // The watcher only reports devices found after the initial enumeration to apps which
+        // handle updates and removals too.
+        let ignore =
+            TypedEventHandler::new(|_sender, _update: Ref<DeviceInformationUpdate>| Ok(()));
+        watcher.Updated(&ignore)?;
+        watcher.Removed(&ignore)?;
+        watcher.Start()?;
+        Ok(ClassicWatcher { watcher })
+    }
+
+    pub fn stop(&self) -> Result<()> {
+        self.watcher.Stop()?;
+        Ok(())
+    }
+}
+
+fn classic_device(info: &DeviceInformation) -> windows::core::Result<ClassicDevice> {
+    // Handlers run on a thread pool thread, which may wait for the device.
+    let device = BluetoothDevice::FromIdAsync(&info.Id()?)?.get()?;
+    let address: BDAddr = device.BluetoothAddress()?.try_into().unwrap();
+    let mut classic = ClassicDevice::new(address);
+    classic.class = device.ClassOfDevice()?.RawValue().ok();
+    classic.name = device
+        .Name()
+        .ok()
+        .map(|name| name.to_string())
+        .filter(|name| !name.is_empty());
+    classic.rssi = info
+        .Properties()?
+        .Lookup(&HSTRING::from(SIGNAL_STRENGTH))
+        .and_then(|value| value.cast::<IReference<i32>>()?.Value())
+        .ok()
+        .and_then(|rssi| rssi.try_into().ok());
+    let _ = device.Close();
+    Ok(classic)
+}
@@ -14,5 +14,8 @@
 pub mod characteristic;
 pub mod descriptor;
 pub mod device;
+pub mod inquiry;
+pub mod paired;
+pub mod pairing;
 pub mod service;
 pub mod watcher;
@@ -0,0 +1,147 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use crate::{api::BDAddr, Result};
+use log::debug;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use windows::{
+    core::{IInspectable, Interface, Ref, HSTRING},
+    Devices::{
+        Bluetooth::BluetoothLEDevice,
+        Enumeration::{
+            DeviceInformation, DeviceInformationKind, DeviceInformationUpdate, DeviceWatcher,
+        },
+    },
+    Foundation::{IReference, TypedEventHandler},
+};
+use windows_collections::{IIterable, IMapView};
+
+const DEVICE_ADDRESS: &str = "System.Devices.Aep.DeviceAddress";
+const IS_CONNECTED: &str = "System.Devices.Aep.IsConnected";
+
+/// A paired LE device, as the system knows it whether or not it's advertising.
+#[derive(Debug, Clone)]
+pub struct PairedDevice {
+    pub address: BDAddr,
+    pub name: Option<String>,
+    pub connected: bool,
+}
+
+pub type PairedDeviceHandler = Box<dyn Fn(PairedDevice) + Send>;
+
+/// Watches the LE devices paired with the system, reporting each one as it's enumerated and again
+/// whenever its connection status changes, so that devices which aren't advertising are known too.
+#[derive(Debug)]
+pub struct PairedWatcher {
+    watcher: DeviceWatcher,
+}
+
+impl PairedWatcher {
+    pub fn start(on_changed: PairedDeviceHandler) -> Result<Self> {
+        let selector = BluetoothLEDevice::GetDeviceSelectorFromPairingState(true)?;
+        let properties = IIterable::<HSTRING>::from(vec![
+            HSTRING::from(DEVICE_ADDRESS),
+            HSTRING::from(IS_CONNECTED),
+        ]);
+        let watcher = DeviceInformation::CreateWatcherWithKindAqsFilterAndAdditionalProperties(
+            &selector,
+            &properties,
+            DeviceInformationKind::AssociationEndpoint,
+        )?;
+        // Updates only carry the device's ID and the properties which changed.
+        let known: Arc<Mutex<HashMap<HSTRING, PairedDevice>>> = Default::default();
+        let on_changed = Arc::new(Mutex::new(on_changed));
+
+        let added_known = known.clone();
+        let added_on_changed = on_changed.clone();
+        watcher.Added(&TypedEventHandler::new(
+            move |_sender, info: Ref<DeviceInformation>| {
+                if let Ok(info) = info.ok() {
+                    match paired_device(info) {
+                        Ok(Some(device)) => {
+                            added_known
+                                .lock()
+                                .unwrap()
+                                .insert(info.Id()?, device.clone());
+                            (added_on_changed.lock().unwrap())(device);
+                        }
+                        Ok(None) => {}
+                        Err(err) => debug!("paired_device {:?}", err),
+                    }
+                }
+                Ok(())
+            },
+        ))?;
+        let updated_known = known.clone();
+        watcher.Updated(&TypedEventHandler::new(
+            move |_sender, update: Ref<DeviceInformationUpdate>| {
+                let Ok(update) = update.ok() else {
+                    return Ok(());
+                };
+                let Some(connected) = lookup_bool(&update.Properties()?, IS_CONNECTED) else {
+                    return Ok(());
+                };
+                let device = match updated_known.lock().unwrap().get_mut(&update.Id()?) {
+                    Some(device) if device.connected != connected => {
+                        device.connected = connected;
+                        device.clone()
+                    }
+                    _ => return Ok(()),
+                };
+                (on_changed.lock().unwrap())(device);
+                Ok(())
+            },
+        ))?;
+        watcher.Removed(&TypedEventHandler::new(
+            move |_sender, update: Ref<DeviceInformationUpdate>| {
+                if let Ok(update) = update.ok() {
+                    known.lock().unwrap().remove(&update.Id()?);
+                }
+                Ok(())
+            },
+        ))?;
+        watcher.Start()?;
+        Ok(PairedWatcher { watcher })
+    }
+}
+
+impl Drop for PairedWatcher {
+    fn drop(&mut self) {
+        if let Err(err) = self.watcher.Stop() {
+            debug!("Drop:stop {:?}", err);
+        }
+    }
+}
+
+fn paired_device(info: &DeviceInformation) -> windows::core::Result<Option<PairedDevice>> {
+    let properties = info.Properties()?;
+    let Some(address) = properties
+        .Lookup(&HSTRING::from(DEVICE_ADDRESS))
+        .and_then(|value| value.cast::<IReference<HSTRING>>()?.Value())
+        .ok()
+        .and_then(|address| address.to_string().parse::<BDAddr>().ok())
+    else {
+        return Ok(None);
+    };
+    Ok(Some(PairedDevice {
+        address,
+        name: info
+            .Name()
+            .ok()
+            .map(|name| name.to_string())
+            .filter(|name| !name.is_empty()),
+        connected: lookup_bool(&properties, IS_CONNECTED).unwrap_or(false),
+    }))
+}
+
+fn lookup_bool(properties: &IMapView<HSTRING, IInspectable>, key: &str) -> Option<bool> {
+    properties
+        .Lookup(&HSTRING::from(key))
+        .and_then(|value| value.cast::<IReference<bool>>()?.Value())
+        .ok()
+}
@@ -0,0 +1,166 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use crate::{
+    api::{
+        pairing::{PairingAgent, PairingRequest, PairingResponse},
+        BDAddr, NativeError, SecurityLevel,
+    },
+    winrtble::peripheral::PeripheralId,
+    Error, Result,
+};
+use log::{debug, trace};
+use std::{future::IntoFuture, sync::Arc};
+use windows::{
+    core::{Ref, HSTRING},
+    Devices::{
+        Bluetooth::BluetoothLEDevice,
+        Enumeration::{
+            DeviceInformationCustomPairing, DeviceInformationPairing, DevicePairingKinds,
+            DevicePairingProtectionLevel, DevicePairingRequestedEventArgs,
+            DevicePairingResultStatus,
+        },
+    },
+    Foundation::TypedEventHandler,
+};
+
+/// The ceremonies we can handle, by passing them on to the agent.
+const SUPPORTED_KINDS: DevicePairingKinds = DevicePairingKinds(
+    DevicePairingKinds::ConfirmOnly.0
+        | DevicePairingKinds::DisplayPin.0
+        | DevicePairingKinds::ProvidePin.0
+        | DevicePairingKinds::ConfirmPinMatch.0,
+);
+
+fn to_request(args: &DevicePairingRequestedEventArgs) -> windows::core::Result<PairingRequest> {
+    let kind = args.PairingKind()?;
+    Ok(if kind == DevicePairingKinds::ConfirmPinMatch {
+        PairingRequest::ConfirmPin(args.Pin()?.to_string())
+    } else if kind == DevicePairingKinds::DisplayPin {
+        PairingRequest::DisplayPin(args.Pin()?.to_string())
+    } else if kind == DevicePairingKinds::ProvidePin {
+        PairingRequest::ProvidePin
+    } else {
+        PairingRequest::Confirm
+    })
+}
+
+/// Answers a pairing request with the agent's response. Windows waits for the deferral to be
+/// completed, so the agent can take as long as it needs.
+fn handle_request(
+    id: PeripheralId,
+    agent: Arc<dyn PairingAgent>,
+    args: DevicePairingRequestedEventArgs,
+) {
+    let result = (|| {
+        let request = to_request(&args)?;
+        trace!("Pairing request for {}: {:?}", id, request);
+        let deferral = args.GetDeferral()?;
+        // This is called on a Windows thread outside of any async runtime, and the agent may take
+        // a while to answer, e.g. if it asks the user.
+        std::thread::spawn(move || {
+            let response = futures::executor::block_on(agent.request(&id, request));
+            let result = match response {
+                PairingResponse::Accept => args.Accept(),
+                PairingResponse::AcceptWithPin(pin) => args.AcceptWithPin(&HSTRING::from(pin)),
+                // Not accepting before completing the deferral rejects the request.
+                PairingResponse::Reject => Ok(()),
+            };
+            if let Err(e) = result.and_then(|_| deferral.Complete()) {
+                debug!("Failed to answer pairing request: {:?}", e);
+            }
+        });
+        windows::core::Result::Ok(())
+    })();
+    if let Err(e) = result {
+        debug!("Failed to handle pairing request: {:?}", e);
+    }
+}
+
+fn to_result(status: DevicePairingResultStatus) -> Result<()> {
+    if status == DevicePairingResultStatus::Paired
+        || status == DevicePairingResultStatus::AlreadyPaired
+    {
+        Ok(())
+    } else if status == DevicePairingResultStatus::AccessDenied
+        || status == DevicePairingResultStatus::RejectedByHandler
+    {
+        Err(Error::PermissionDenied)
+    } else {
+        debug!("Pairing failed with {:?}", status);
+        Err(Error::Other(Box::new(NativeError::PairingStatus(status.0))))
+    }
+}
+
+async fn device_pairing(address: BDAddr) -> Result<DeviceInformationPairing> {
+    let device = BluetoothLEDevice::FromBluetoothAddressAsync(address.into())
+        .map_err(|_| Error::DeviceNotFound)?
+        .into_future()
+        .await
+        .map_err(|_| Error::DeviceNotFound)?;
+    Ok(device.DeviceInformation()?.Pairing()?)
+}
+
+/// Returns how well the bond with the device with the given address protects the link. Windows
+/// doesn't say whether LE Secure Connections were used, so this is at most
+/// [`SecurityLevel::Authenticated`].
+pub async fn security_level(address: BDAddr) -> Result<SecurityLevel> {
+    let pairing = device_pairing(address).await?;
+    if !pairing.IsPaired()? {
+        return Ok(SecurityLevel::None);
+    }
+    let level = pairing.ProtectionLevel()?;
+    Ok(
+        if level == DevicePairingProtectionLevel::EncryptionAndAuthentication {
+            SecurityLevel::Authenticated
+        } else if level == DevicePairingProtectionLevel::Encryption {
+            SecurityLevel::Encrypted
+        } else {
+            SecurityLevel::None
+        },
+    )
+}
+
+/// Pairs with the device with the given address, using custom pairing so that the agent handles
+/// the ceremony rather than Windows showing its own dialog. Windows refuses pairing which would
+/// protect the link less well than `minimum`. Returns whether the device wasn't paired already.
+pub async fn pair(
+    address: BDAddr,
+    agent: Arc<dyn PairingAgent>,
+    minimum: SecurityLevel,
+) -> Result<bool> {
+    let pairing = device_pairing(address).await?;
+    if pairing.IsPaired()? {
+        return Ok(false);
+    }
+    let custom = pairing.Custom()?;
+    let id = PeripheralId::from(address);
+    // The handler isn't Send, so it mustn't be kept past the await below.
+    let token = custom.PairingRequested(&TypedEventHandler::new(
+        move |_: Ref<DeviceInformationCustomPairing>,
+              args: Ref<DevicePairingRequestedEventArgs>| {
+            if let Ok(args) = args.ok() {
+                handle_request(id.clone(), agent.clone(), args.clone());
+            }
+            Ok(())
+        },
+    ))?;
+    let protection_level = match minimum {
+        SecurityLevel::None => DevicePairingProtectionLevel::Default,
+        SecurityLevel::Encrypted => DevicePairingProtectionLevel::Encryption,
+        _ => DevicePairingProtectionLevel::EncryptionAndAuthentication,
+    };
+    let result = match custom.PairWithProtectionLevelAsync(SUPPORTED_KINDS, protection_level) {
+        Ok(operation) => operation.into_future().await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = custom.RemovePairingRequested(token) {
+        debug!("Failed to remove pairing handler: {:?}", e);
+    }
+    to_result(result?.Status()?)?;
+    Ok(true)
+}
@@ -1,11 +1,14 @@
 use super::characteristic::BLECharacteristic;
 use crate::api::Service;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use uuid::Uuid;
 
 #[derive(Debug)]
 pub struct BLEService {
     pub uuid: Uuid,
+    pub primary: bool,
+    pub handle: Option<u16>,
+    pub included_services: BTreeSet<Uuid>,
     pub characteristics: HashMap<Uuid, BLECharacteristic>,
 }
 
@@ -18,8 +21,10 @@ impl BLEService {
             .collect();
         Service {
             uuid: self.uuid,
-            primary: true,
+            primary: self.primary,
             characteristics,
+            included_services: self.included_services.clone(),
+            handle: self.handle,
         }
     }
 }
@@ -11,7 +11,10 @@
 //
 // Copyright (c) 2014 The Rust Project Developers
 
-use crate::{api::ScanFilter, Error, Result};
+use crate::{
+    api::{NativeError, ScanFilter},
+    Error, Result,
+};
 use windows::{core::Ref, Devices::Bluetooth::Advertisement::*, Foundation::TypedEventHandler};
 
 pub type AdvertisementEventHandler =
@@ -24,7 +27,10 @@ pub struct BLEWatcher {
 
 impl From<windows::core::Error> for Error {
     fn from(err: windows::core::Error) -> Error {
-        Error::Other(format!("{:?}", err).into())
+        Error::Other(Box::new(NativeError::HResult {
+            code: err.code().0,
+            message: err.message(),
+        }))
     }
 }
 
@@ -36,7 +42,7 @@ impl BLEWatcher {
     }
 
     pub fn start(&self, filter: ScanFilter, on_received: AdvertisementEventHandler) -> Result<()> {
-        let ScanFilter { services } = filter;
+        let ScanFilter { services, .. } = filter;
         let ad = self.watcher.AdvertisementFilter()?.Advertisement()?;
         let ad_services = ad.ServiceUuids()?;
         ad_services.Clear()?;
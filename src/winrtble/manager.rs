@@ -12,21 +12,66 @@
 // Copyright (c) 2014 The Rust Project Developers
 
 use super::adapter::Adapter;
-use crate::{api, Result};
+use crate::api::observer::{TraceHandler, Tracer};
+use crate::{api, api::ManagerEvent, Result};
 use async_trait::async_trait;
+use futures::{
+    channel::mpsc,
+    stream::{self, Stream, StreamExt},
+};
+use std::collections::HashMap;
 use std::future::IntoFuture;
-use windows::Devices::Radios::{Radio, RadioKind};
+use std::pin::Pin;
+use std::sync::Arc;
+use windows::{
+    core::Ref,
+    Devices::{
+        Enumeration::{DeviceInformation, DeviceInformationUpdate, DeviceWatcher},
+        Radios::{Radio, RadioKind},
+    },
+    Foundation::TypedEventHandler,
+};
 
 /// Implementation of [api::Manager](crate::api::Manager).
 #[derive(Clone, Debug)]
-pub struct Manager {}
+pub struct Manager {
+    tracer: Tracer,
+}
 
 impl Manager {
     pub async fn new() -> Result<Self> {
-        Ok(Self {})
+        Ok(Self {
+            tracer: Tracer::default(),
+        })
+    }
+}
+
+enum RadioChange {
+    Added(String),
+    Removed(String),
+}
+
+/// Stops the device watcher when the adapter event stream is dropped.
+struct WatcherGuard(DeviceWatcher);
+
+impl Drop for WatcherGuard {
+    fn drop(&mut self) {
+        let _ = self.0.Stop();
     }
 }
 
+async fn bluetooth_adapter(id: &str, tracer: &Tracer) -> Option<Adapter> {
+    let radio = Radio::FromIdAsync(&id.into())
+        .ok()?
+        .into_future()
+        .await
+        .ok()?;
+    if radio.Kind() != Ok(RadioKind::Bluetooth) {
+        return None;
+    }
+    Adapter::new(radio, tracer.clone()).ok()
+}
+
 #[async_trait]
 impl api::Manager for Manager {
     type Adapter = Adapter;
@@ -36,7 +81,66 @@ impl api::Manager for Manager {
         radios
             .into_iter()
             .filter(|radio| radio.Kind() == Ok(RadioKind::Bluetooth))
-            .map(|radio| Adapter::new(radio))
+            .map(|radio| Adapter::new(radio, self.tracer.clone()))
             .collect()
     }
+
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = ManagerEvent<Adapter>> + Send>>> {
+        let (sender, receiver) = mpsc::unbounded();
+        let watcher = DeviceInformation::CreateWatcherAqsFilter(&Radio::GetDeviceSelector()?)?;
+        let added_sender = sender.clone();
+        watcher.Added(&TypedEventHandler::new(
+            move |_sender, info: Ref<DeviceInformation>| {
+                if let Ok(info) = info.ok() {
+                    let _ = added_sender.unbounded_send(RadioChange::Added(info.Id()?.to_string()));
+                }
+                Ok(())
+            },
+        ))?;
+        watcher.Removed(&TypedEventHandler::new(
+            move |_sender, update: Ref<DeviceInformationUpdate>| {
+                if let Ok(update) = update.ok() {
+                    let _ = sender.unbounded_send(RadioChange::Removed(update.Id()?.to_string()));
+                }
+                Ok(())
+            },
+        ))?;
+        // The watcher reports all existing radios as added once it starts.
+        watcher.Start()?;
+
+        let tracer = self.tracer.clone();
+        let state = (receiver, HashMap::new(), WatcherGuard(watcher));
+        Ok(Box::pin(stream::unfold(
+            state,
+            move |(mut receiver, mut adapters, guard)| {
+                let tracer = tracer.clone();
+                async move {
+                    loop {
+                        match receiver.next().await? {
+                            RadioChange::Added(id) => {
+                                if adapters.contains_key(&id) {
+                                    continue;
+                                }
+                                if let Some(adapter) = bluetooth_adapter(&id, &tracer).await {
+                                    adapters.insert(id, adapter.clone());
+                                    let event = ManagerEvent::AdapterAdded(adapter);
+                                    return Some((event, (receiver, adapters, guard)));
+                                }
+                            }
+                            RadioChange::Removed(id) => {
+                                if let Some(adapter) = adapters.remove(&id) {
+                                    let event = ManagerEvent::AdapterRemoved(adapter);
+                                    return Some((event, (receiver, adapters, guard)));
+                                }
+                            }
+                        }
+                    }
+                }
+            },
+        )))
+    }
+
+    fn set_trace_handler(&self, handler: Option<Arc<dyn TraceHandler>>) {
+        self.tracer.set(handler);
+    }
 }
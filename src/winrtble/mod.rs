@@ -19,6 +19,7 @@ mod utils;
 
 /// Only some of the assigned numbers are populated here as needed from https://www.bluetooth.com/specifications/assigned-numbers/generic-access-profile/
 mod advertisement_data_type {
+    pub const APPEARANCE: u8 = 0x19;
     pub const SERVICE_DATA_16_BIT_UUID: u8 = 0x16;
     pub const SERVICE_DATA_32_BIT_UUID: u8 = 0x20;
     pub const SERVICE_DATA_128_BIT_UUID: u8 = 0x21;
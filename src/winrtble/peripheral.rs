@@ -13,15 +13,25 @@
 
 use super::{
     advertisement_data_type, ble::characteristic::BLECharacteristic,
-    ble::descriptor::BLEDescriptor, ble::device::BLEDevice, ble::service::BLEService, utils,
+    ble::descriptor::BLEDescriptor, ble::device::BLEDevice, ble::paired::PairedDevice,
+    ble::pairing, ble::service::BLEService, utils,
 };
 use crate::{
     api::{
+        appearance::Appearance,
         bleuuid::{uuid_from_u16, uuid_from_u32},
-        AddressType, BDAddr, CentralEvent, Characteristic, Descriptor, Peripheral as ApiPeripheral,
-        PeripheralProperties, Service, ValueNotification, WriteType,
+        check_security_level,
+        descriptor_cache::DescriptorCache,
+        link::{self, LinkSample},
+        operation::OperationContext,
+        pairing::PairingAgent,
+        rssi::RssiStats,
+        spans, AddressType, AdvertisementType, BDAddr, CentralEvent, Characteristic,
+        ClientConfiguration, Descriptor, DisconnectReason, Peripheral as ApiPeripheral,
+        PeripheralEvent, PeripheralProperties, ScanResponseParts, SecurityLevel, Service,
+        SubscriptionType, ValueNotification, WriteType, CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
     },
-    common::{adapter_manager::AdapterManager, util::notifications_stream_from_broadcast_receiver},
+    common::adapter_manager::AdapterManager,
     Error, Result,
 };
 use async_trait::async_trait;
@@ -33,19 +43,20 @@ use serde::{Deserialize, Serialize};
 #[cfg(feature = "serde")]
 use serde_cr as serde;
 use std::{
-    collections::{BTreeSet, HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
     convert::TryInto,
     fmt::{self, Debug, Display, Formatter},
     pin::Pin,
     sync::atomic::{AtomicBool, Ordering},
     sync::{Arc, RwLock},
+    time::{Duration, Instant, SystemTime},
 };
 use tokio::sync::broadcast;
 use uuid::Uuid;
 
 use std::sync::Weak;
 use windows::core::GUID;
-use windows::Devices::Bluetooth::GenericAttributeProfile::GattCharacteristic;
+use windows::Devices::Bluetooth::GenericAttributeProfile::{GattCharacteristic, GattDeviceService};
 use windows::Devices::Bluetooth::{Advertisement::*, BluetoothAddressType};
 
 #[cfg_attr(
@@ -62,6 +73,14 @@ impl Display for PeripheralId {
     }
 }
 
+#[cfg(feature = "mock")]
+impl PeripheralId {
+    /// The ID of a peripheral of the [mock](crate::mock) backend.
+    pub(crate) fn synthetic(_adapter_id: &str, address: BDAddr) -> Self {
+        PeripheralId(address)
+    }
+}
+
 /// Implementation of [api::Peripheral](crate::api::Peripheral).
 #[derive(Clone)]
 pub struct Peripheral {
@@ -73,30 +92,47 @@ struct Shared {
     adapter: Weak<AdapterManager<Peripheral>>,
     address: BDAddr,
     connected: AtomicBool,
+    /// Whether the system has a connection to the device, which may be another app's.
+    system_connected: AtomicBool,
     ble_services: DashMap<Uuid, BLEService>,
+    descriptor_cache: DescriptorCache,
     notifications_channel: broadcast::Sender<ValueNotification>,
+    operation_context: OperationContext,
 
     // Mutable, advertised, state...
     address_type: RwLock<Option<AddressType>>,
     local_name: RwLock<Option<String>>,
     last_tx_power_level: RwLock<Option<i16>>, // XXX: would be nice to avoid lock here!
     last_rssi: RwLock<Option<i16>>,           // XXX: would be nice to avoid lock here!
-    latest_manufacturer_data: RwLock<HashMap<u16, Vec<u8>>>,
-    latest_service_data: RwLock<HashMap<Uuid, Vec<u8>>>,
+    latest_manufacturer_data: RwLock<HashMap<u16, Arc<[u8]>>>,
+    latest_service_data: RwLock<HashMap<Uuid, Arc<[u8]>>>,
     services: RwLock<HashSet<Uuid>>,
     class: RwLock<Option<u32>>,
+    advertisement_type: RwLock<Option<AdvertisementType>>,
+    connectable: RwLock<Option<bool>>,
+    last_seen: RwLock<Option<SystemTime>>,
+    appearance: RwLock<Option<Appearance>>,
+    scan_response: RwLock<ScanResponseParts>,
+    advertising_data: RwLock<Option<Arc<[u8]>>>,
 }
 
 impl Peripheral {
-    pub(crate) fn new(adapter: Weak<AdapterManager<Self>>, address: BDAddr) -> Self {
+    pub(crate) fn new(
+        adapter: Weak<AdapterManager<Self>>,
+        address: BDAddr,
+        operation_context: OperationContext,
+    ) -> Self {
         let (broadcast_sender, _) = broadcast::channel(16);
         Peripheral {
             shared: Arc::new(Shared {
                 adapter,
+                operation_context: operation_context.for_peripheral(PeripheralId(address)),
                 device: tokio::sync::Mutex::new(None),
                 address,
                 connected: AtomicBool::new(false),
+                system_connected: AtomicBool::new(false),
                 ble_services: DashMap::new(),
+                descriptor_cache: DescriptorCache::default(),
                 notifications_channel: broadcast_sender,
                 address_type: RwLock::new(None),
                 local_name: RwLock::new(None),
@@ -106,6 +142,12 @@ impl Peripheral {
                 latest_service_data: RwLock::new(HashMap::new()),
                 services: RwLock::new(HashSet::new()),
                 class: RwLock::new(None),
+                advertisement_type: RwLock::new(None),
+                connectable: RwLock::new(None),
+                last_seen: RwLock::new(None),
+                appearance: RwLock::new(None),
+                scan_response: RwLock::new(ScanResponseParts::default()),
+                advertising_data: RwLock::new(None),
             }),
         }
     }
@@ -130,11 +172,21 @@ impl Peripheral {
                 .copied()
                 .collect(),
             class: *self.shared.class.read().unwrap(),
+            advertisement_type: *self.shared.advertisement_type.read().unwrap(),
+            connectable: *self.shared.connectable.read().unwrap(),
+            last_seen: *self.shared.last_seen.read().unwrap(),
+            appearance: *self.shared.appearance.read().unwrap(),
+            scan_response: self.shared.scan_response.read().unwrap().clone(),
+            advertising_data: self.shared.advertising_data.read().unwrap().clone(),
         }
     }
 
     pub(crate) fn update_properties(&self, args: &BluetoothLEAdvertisementReceivedEventArgs) {
+        *self.shared.last_seen.write().unwrap() = Some(SystemTime::now());
+
         let advertisement = args.Advertisement().unwrap();
+        // What this report contains, in case it's a scan response.
+        let mut parts = ScanResponseParts::default();
 
         // Advertisements are cumulative: set/replace data only if it's set
         if let Ok(name) = advertisement.LocalName() {
@@ -145,6 +197,7 @@ impl Peripheral {
 
                 let mut local_name_guard = self.shared.local_name.write().unwrap();
                 *local_name_guard = Some(name.to_string());
+                parts.local_name = true;
             }
         }
         if let Ok(manufacturer_data) = advertisement.ManufacturerData() {
@@ -157,9 +210,10 @@ impl Peripheral {
                         let manufacturer_id = d.CompanyId().unwrap();
                         let data = utils::to_vec(&d.Data().unwrap());
 
-                        (manufacturer_id, data)
+                        (manufacturer_id, data.into())
                     })
                     .collect();
+                parts.manufacturer_data = manufacturer_data_guard.keys().copied().collect();
 
                 // Emit event of newly received advertisement
                 self.emit_event(CentralEvent::ManufacturerDataAdvertisement {
@@ -176,6 +230,14 @@ impl Peripheral {
             let mut found_service_data = false;
             for section in &data_sections {
                 match section.DataType().unwrap() {
+                    advertisement_data_type::APPEARANCE => {
+                        let data = utils::to_vec(&section.Data().unwrap());
+                        if let Ok(appearance) = data.as_slice().try_into() {
+                            *self.shared.appearance.write().unwrap() =
+                                Some(u16::from_le_bytes(appearance).into());
+                            parts.appearance = true;
+                        }
+                    }
                     advertisement_data_type::SERVICE_DATA_16_BIT_UUID
                     | advertisement_data_type::SERVICE_DATA_32_BIT_UUID
                     | advertisement_data_type::SERVICE_DATA_128_BIT_UUID => {
@@ -198,23 +260,24 @@ impl Peripheral {
                                 let (uuid, data) = data.split_at(2);
                                 let uuid =
                                     uuid_from_u16(u16::from_le_bytes(uuid.try_into().unwrap()));
-                                Some((uuid, data.to_owned()))
+                                Some((uuid, data.into()))
                             }
                             advertisement_data_type::SERVICE_DATA_32_BIT_UUID => {
                                 let (uuid, data) = data.split_at(4);
                                 let uuid =
                                     uuid_from_u32(u32::from_le_bytes(uuid.try_into().unwrap()));
-                                Some((uuid, data.to_owned()))
+                                Some((uuid, data.into()))
                             }
                             advertisement_data_type::SERVICE_DATA_128_BIT_UUID => {
                                 let (uuid, data) = data.split_at(16);
                                 let uuid = Uuid::from_slice(uuid).unwrap();
-                                Some((uuid, data.to_owned()))
+                                Some((uuid, data.into()))
                             }
                             _ => None,
                         }
                     })
                     .collect();
+                parts.service_data = service_data_guard.keys().copied().collect();
 
                 // Emit event of newly received advertisement
                 self.emit_event(CentralEvent::ServiceDataAdvertisement {
@@ -225,6 +288,10 @@ impl Peripheral {
         }
 
         if let Ok(services) = advertisement.ServiceUuids() {
+            parts.services = (&services)
+                .into_iter()
+                .map(|uuid| utils::to_uuid(&uuid))
+                .collect();
             let mut found_new_service = false;
 
             // Limited scope for read-only lock...
@@ -277,14 +344,219 @@ impl Peripheral {
             if let Ok(tx) = tx_reference.Value() {
                 let mut tx_power_level_guard = self.shared.last_tx_power_level.write().unwrap();
                 *tx_power_level_guard = Some(tx);
+                parts.tx_power_level = true;
             }
         }
         if let Ok(rssi) = args.RawSignalStrengthInDBm() {
             let mut rssi_guard = self.shared.last_rssi.write().unwrap();
             *rssi_guard = Some(rssi);
+            self.shared
+                .operation_context
+                .rssi()
+                .record(&PeripheralId(self.shared.address), rssi);
+        }
+
+        let advertisement_type = to_advertisement_type(args);
+        // Windows splits the advertising data up into its AD structures, so put it back together.
+        if let Ok(data_sections) = advertisement.DataSections() {
+            let mut data = Vec::new();
+            for section in &data_sections {
+                let section_data = utils::to_vec(&section.Data().unwrap());
+                data.push(section_data.len() as u8 + 1);
+                data.push(section.DataType().unwrap());
+                data.extend(section_data);
+            }
+            let mut advertising_data = self.shared.advertising_data.write().unwrap();
+            *advertising_data = Some(match advertising_data.take() {
+                Some(advertisement)
+                    if advertisement_type == Some(AdvertisementType::ScanResponse) =>
+                {
+                    [&advertisement[..], &data[..]].concat().into()
+                }
+                _ => data.into(),
+            });
+        }
+        // The adapter applies a scan response right after the advertisement it responded to.
+        *self.shared.scan_response.write().unwrap() =
+            if advertisement_type == Some(AdvertisementType::ScanResponse) {
+                ScanResponseParts {
+                    received: Some(true),
+                    ..parts
+                }
+            } else {
+                ScanResponseParts {
+                    received: Some(false),
+                    ..Default::default()
+                }
+            };
+        if let Some(advertisement_type) = advertisement_type {
+            *self.shared.advertisement_type.write().unwrap() = Some(advertisement_type);
+            // A scan response says nothing about connectability, so keep whatever the preceding
+            // advertisement told us.
+            if advertisement_type != AdvertisementType::ScanResponse {
+                *self.shared.connectable.write().unwrap() =
+                    Some(advertisement_type.is_connectable());
+            }
         }
     }
 
+    /// Applies what the system knows of the device as a paired device, returning whether its
+    /// connection status changed without us connecting to or disconnecting from it, in which case
+    /// the adapter reports the change.
+    pub(crate) fn update_paired(&self, device: &PairedDevice) -> bool {
+        if let Some(name) = &device.name {
+            let mut local_name = self.shared.local_name.write().unwrap();
+            if local_name.is_none() {
+                *local_name = Some(name.clone());
+            }
+        }
+        let was_connected = self
+            .shared
+            .system_connected
+            .swap(device.connected, Ordering::Relaxed);
+        was_connected != device.connected
+            && self.shared.connected.load(Ordering::Relaxed) != device.connected
+    }
+
+    /// Discovers the services with the given UUIDs, or all services, and the services they
+    /// include, along with their characteristics and descriptors.
+    async fn discover_services_matching(&self, uuids: Option<&[Uuid]>) -> Result<()> {
+        let mut device = self.shared.device.lock().await;
+        if let Some(ref mut device) = *device {
+            let gatt_services = match uuids {
+                Some(uuids) => device.discover_services_for_uuids(uuids).await?,
+                None => device.discover_services().await?,
+            };
+            // Windows only lists primary services, so follow their included services too.
+            let mut pending: VecDeque<(GattDeviceService, bool)> = gatt_services
+                .iter()
+                .map(|service| (service.clone(), true))
+                .collect();
+            let mut visited = HashSet::new();
+            while let Some((service, primary)) = pending.pop_front() {
+                let uuid = utils::to_uuid(&service.Uuid().unwrap());
+                if !visited.insert(uuid) {
+                    continue;
+                }
+                let handle = service.AttributeHandle().ok();
+                let included = device.get_included_services(&service).await?;
+                let included_services = included
+                    .iter()
+                    .filter_map(|service| service.Uuid().ok())
+                    .map(|guid| utils::to_uuid(&guid))
+                    .collect();
+                pending.extend(included.into_iter().map(|service| (service, false)));
+                if !self.shared.ble_services.contains_key(&uuid) {
+                    match BLEDevice::get_characteristics(&service).await {
+                        Ok(characteristics) => {
+                            let characteristics = characteristics
+                                .into_iter()
+                                .fold(
+                                    // Only consider the first characteristic of each UUID
+                                    // This "should" be unique, but of course it's not enforced
+                                    HashMap::<GUID, GattCharacteristic>::new(),
+                                    |mut map, gatt_characteristic| {
+                                        let uuid = gatt_characteristic.Uuid().unwrap_or_default();
+                                        if !map.contains_key(&uuid) {
+                                            map.insert(uuid, gatt_characteristic);
+                                        }
+                                        map
+                                    },
+                                )
+                                .into_iter()
+                                .map(|(_, characteristic)| async {
+                                    let c = characteristic.clone();
+                                    (
+                                        characteristic,
+                                        BLEDevice::get_characteristic_descriptors(&c)
+                                            .await
+                                            .unwrap_or(Vec::new())
+                                            .into_iter()
+                                            .map(|descriptor| {
+                                                let descriptor = BLEDescriptor::new(descriptor);
+                                                (descriptor.uuid(), descriptor)
+                                            })
+                                            .collect(),
+                                    )
+                                });
+
+                            let characteristics = futures::future::join_all(characteristics)
+                                .await
+                                .into_iter()
+                                .map(|(characteristic, descriptors)| {
+                                    let characteristic =
+                                        BLECharacteristic::new(characteristic, descriptors);
+                                    (characteristic.uuid(), characteristic)
+                                })
+                                .collect();
+
+                            self.shared.ble_services.insert(
+                                uuid,
+                                BLEService {
+                                    uuid,
+                                    primary,
+                                    handle,
+                                    included_services,
+                                    characteristics,
+                                },
+                            );
+                        }
+                        Err(e) => {
+                            warn!("get_characteristics_async {:?}", e);
+                        }
+                    }
+                }
+            }
+            return Ok(());
+        }
+        Err(Error::NotConnected)
+    }
+
+    async fn subscribe_characteristic(
+        &self,
+        characteristic: &Characteristic,
+        subscription_type: Option<SubscriptionType>,
+    ) -> Result<()> {
+        let ble_service = &mut *self
+            .shared
+            .ble_services
+            .get_mut(&characteristic.service_uuid)
+            .ok_or_else(|| Error::NotSupported("Service not found for subscribe".into()))?;
+        let ble_characteristic = ble_service
+            .characteristics
+            .get_mut(&characteristic.uuid)
+            .ok_or_else(|| Error::NotSupported("Characteristic not found for subscribe".into()))?;
+        let notifications_sender = self.shared.notifications_channel.clone();
+        let uuid = characteristic.uuid;
+        let service_uuid = characteristic.service_uuid;
+        let handle = ble_characteristic.value_handle();
+        let id = self.id();
+        let subscription_type = match subscription_type {
+            Some(subscription_type) => subscription_type,
+            None => ble_characteristic.default_subscription_type()?,
+        };
+        ble_characteristic
+            .subscribe(
+                subscription_type,
+                Box::new(move |value| {
+                    spans::callback("notification", Some(&id)).in_scope(|| {
+                        let notification = ValueNotification {
+                            uuid,
+                            service_uuid,
+                            handle,
+                            subscription_type,
+                            value,
+                            timestamp: Instant::now(),
+                        };
+                        // Note: we ignore send errors here which may happen while there are no
+                        // receivers...
+                        let _ = notifications_sender.send(notification);
+                    })
+                }),
+            )
+            .await
+    }
+
     fn emit_event(&self, event: CentralEvent) {
         if let Some(manager) = self.shared.adapter.upgrade() {
             manager.emit(event);
@@ -292,6 +564,52 @@ impl Peripheral {
             trace!("Could not emit an event. AdapterManager has been dropped");
         }
     }
+
+    fn send_peripheral_event(&self, event: PeripheralEvent) {
+        if let Some(manager) = self.shared.adapter.upgrade() {
+            manager
+                .peripheral_events()
+                .send(&self.shared.address.into(), event);
+        }
+    }
+}
+
+pub(crate) fn to_advertisement_type(
+    args: &BluetoothLEAdvertisementReceivedEventArgs,
+) -> Option<AdvertisementType> {
+    match args.AdvertisementType().ok()? {
+        BluetoothLEAdvertisementType::ConnectableUndirected => {
+            Some(AdvertisementType::ConnectableUndirected)
+        }
+        BluetoothLEAdvertisementType::ConnectableDirected => {
+            Some(AdvertisementType::ConnectableDirected)
+        }
+        BluetoothLEAdvertisementType::ScannableUndirected => {
+            Some(AdvertisementType::ScannableUndirected)
+        }
+        BluetoothLEAdvertisementType::NonConnectableUndirected => {
+            Some(AdvertisementType::NonConnectableUndirected)
+        }
+        BluetoothLEAdvertisementType::ScanResponse => Some(AdvertisementType::ScanResponse),
+        // Extended advertisements carry the same information as separate flags, which are only
+        // available on Windows 10 2004 and later.
+        BluetoothLEAdvertisementType::Extended => {
+            if args.IsScanResponse().ok()? {
+                Some(AdvertisementType::ScanResponse)
+            } else if args.IsConnectable().ok()? {
+                if args.IsDirected().ok()? {
+                    Some(AdvertisementType::ConnectableDirected)
+                } else {
+                    Some(AdvertisementType::ConnectableUndirected)
+                }
+            } else if args.IsScannable().ok()? {
+                Some(AdvertisementType::ScannableUndirected)
+            } else {
+                Some(AdvertisementType::NonConnectableUndirected)
+            }
+        }
+        _ => None,
+    }
 }
 
 impl Display for Peripheral {
@@ -349,12 +667,35 @@ impl ApiPeripheral for Peripheral {
         Ok(Some(self.derive_properties()))
     }
 
+    async fn resolve_name(&self) -> Result<Option<String>> {
+        let local_name = self.shared.local_name.read().unwrap().clone();
+        if local_name.is_some() {
+            return Ok(local_name);
+        }
+        let name = match &*self.shared.device.lock().await {
+            Some(device) => device.name()?,
+            None => return Ok(None),
+        };
+        if name.is_empty() {
+            return Ok(None);
+        }
+        *self.shared.local_name.write().unwrap() = Some(name.clone());
+        self.emit_event(CentralEvent::DeviceUpdated(self.id()));
+        Ok(Some(name))
+    }
+
+    fn rssi_stats(&self) -> Option<RssiStats> {
+        self.shared.operation_context.rssi().stats(&self.id())
+    }
+
     fn services(&self) -> BTreeSet<Service> {
-        self.shared
+        let services = self
+            .shared
             .ble_services
             .iter()
             .map(|item| item.value().to_service())
-            .collect()
+            .collect();
+        self.shared.descriptor_cache.fill(services)
     }
 
     /// Returns true iff we are currently connected to the device.
@@ -366,31 +707,70 @@ impl ApiPeripheral for Peripheral {
     /// Ok there has been successful connection. Note that peripherals allow only one connection at
     /// a time. Operations that attempt to communicate with a device will fail until it is connected.
     async fn connect(&self) -> Result<()> {
-        let shared_clone = Arc::downgrade(&self.shared);
-        let adapter_clone = self.shared.adapter.clone();
-        let address = self.shared.address;
-        let device = BLEDevice::new(
-            self.shared.address,
-            Box::new(move |is_connected| {
-                if let Some(shared) = shared_clone.upgrade() {
-                    shared.connected.store(is_connected, Ordering::Relaxed);
-                }
+        self.shared
+            .operation_context
+            .run("connect", async {
+                let shared_clone = Arc::downgrade(&self.shared);
+                let services_shared = Arc::downgrade(&self.shared);
+                let adapter_clone = self.shared.adapter.clone();
+                let address = self.shared.address;
+                let device = BLEDevice::new(
+                    self.shared.address,
+                    Box::new(move |is_connected| {
+                        let id = PeripheralId::from(address);
+                        spans::callback("connection status", Some(&id)).in_scope(|| {
+                            if let Some(shared) = shared_clone.upgrade() {
+                                shared.connected.store(is_connected, Ordering::Relaxed);
+                            }
 
-                if !is_connected {
-                    if let Some(adapter) = adapter_clone.upgrade() {
-                        adapter.emit(CentralEvent::DeviceDisconnected(address.into()));
-                    }
-                }
-            }),
-        )
-        .await?;
+                            if !is_connected {
+                                if let Some(adapter) = adapter_clone.upgrade() {
+                                    adapter.emit(CentralEvent::DeviceDisconnected(id.clone()));
+                                }
+                            }
+                        })
+                    }),
+                    Box::new(move || {
+                        let Some(shared) = services_shared.upgrade() else {
+                            return;
+                        };
+                        // Windows also reports its first look at the services after connecting, before
+                        // we've discovered anything, which isn't a change.
+                        if shared.ble_services.is_empty() {
+                            return;
+                        }
+                        let peripheral = Peripheral { shared };
+                        // This is called on a Windows thread outside of any async runtime, and
+                        // discovery doesn't need one.
+                        std::thread::spawn(move || {
+                            futures::executor::block_on(async {
+                                peripheral.shared.ble_services.clear();
+                                // Not with `discover_services`, which would report the services
+                                // resolved before `ServicesChanged` does too.
+                                let rediscovered = peripheral.shared.operation_context.run(
+                                    "discover services",
+                                    peripheral.discover_services_matching(None),
+                                );
+                                if let Err(e) = rediscovered.await {
+                                    warn!("Error rediscovering services: {:?}", e);
+                                }
+                                peripheral.emit_event(CentralEvent::ServicesChanged(
+                                    peripheral.shared.address.into(),
+                                ));
+                            })
+                        });
+                    }),
+                )
+                .await?;
 
-        device.connect().await?;
-        let mut d = self.shared.device.lock().await;
-        *d = Some(device);
-        self.shared.connected.store(true, Ordering::Relaxed);
-        self.emit_event(CentralEvent::DeviceConnected(self.shared.address.into()));
-        Ok(())
+                device.connect().await?;
+                let mut d = self.shared.device.lock().await;
+                *d = Some(device);
+                self.shared.connected.store(true, Ordering::Relaxed);
+                self.emit_event(CentralEvent::DeviceConnected(self.shared.address.into()));
+                Ok(())
+            })
+            .await
     }
 
     /// Terminates a connection to the device. This is a synchronous operation.
@@ -401,78 +781,86 @@ impl ApiPeripheral for Peripheral {
         let mut device = self.shared.device.lock().await;
         *device = None;
         self.shared.connected.store(false, Ordering::Relaxed);
+        if let Some(manager) = self.shared.adapter.upgrade() {
+            manager
+                .peripheral_events()
+                .set_disconnect_reason(&self.shared.address.into(), DisconnectReason::Local);
+        }
         self.emit_event(CentralEvent::DeviceDisconnected(self.shared.address.into()));
         Ok(())
     }
 
-    /// Discovers all characteristics for the device. This is a synchronous operation.
-    async fn discover_services(&self) -> Result<()> {
-        let mut device = self.shared.device.lock().await;
-        if let Some(ref mut device) = *device {
-            let gatt_services = device.discover_services().await?;
-            for service in gatt_services {
-                let uuid = utils::to_uuid(&service.Uuid().unwrap());
-                if !self.shared.ble_services.contains_key(&uuid) {
-                    match BLEDevice::get_characteristics(service).await {
-                        Ok(characteristics) => {
-                            let characteristics = characteristics
-                                .into_iter()
-                                .fold(
-                                    // Only consider the first characteristic of each UUID
-                                    // This "should" be unique, but of course it's not enforced
-                                    HashMap::<GUID, GattCharacteristic>::new(),
-                                    |mut map, gatt_characteristic| {
-                                        let uuid = gatt_characteristic.Uuid().unwrap_or_default();
-                                        if !map.contains_key(&uuid) {
-                                            map.insert(uuid, gatt_characteristic);
-                                        }
-                                        map
-                                    },
-                                )
-                                .into_iter()
-                                .map(|(_, characteristic)| async {
-                                    let c = characteristic.clone();
-                                    (
-                                        characteristic,
-                                        BLEDevice::get_characteristic_descriptors(&c)
-                                            .await
-                                            .unwrap_or(Vec::new())
-                                            .into_iter()
-                                            .map(|descriptor| {
-                                                let descriptor = BLEDescriptor::new(descriptor);
-                                                (descriptor.uuid(), descriptor)
-                                            })
-                                            .collect(),
-                                    )
-                                });
+    /// Pairs with the device, if it isn't paired already. This isn't subject to the operation
+    /// timeout, as the agent may be waiting for the user.
+    async fn pair_with_agent(&self, agent: Arc<dyn PairingAgent>) -> Result<()> {
+        if pairing::pair(self.shared.address, agent, SecurityLevel::None).await? {
+            self.send_peripheral_event(PeripheralEvent::PairingStateChanged(true));
+        }
+        Ok(())
+    }
 
-                            let characteristics = futures::future::join_all(characteristics)
-                                .await
-                                .into_iter()
-                                .map(|(characteristic, descriptors)| {
-                                    let characteristic =
-                                        BLECharacteristic::new(characteristic, descriptors);
-                                    (characteristic.uuid(), characteristic)
-                                })
-                                .collect();
+    async fn security_level(&self) -> Result<SecurityLevel> {
+        if !self.shared.connected.load(Ordering::Relaxed) {
+            return Err(Error::NotConnected);
+        }
+        pairing::security_level(self.shared.address).await
+    }
 
-                            self.shared.ble_services.insert(
-                                uuid,
-                                BLEService {
-                                    uuid,
-                                    characteristics,
-                                },
-                            );
-                        }
-                        Err(e) => {
-                            warn!("get_characteristics_async {:?}", e);
-                        }
-                    }
-                }
-            }
+    /// Pairs with the device at the protection level needed, so that Windows refuses a ceremony
+    /// which wouldn't meet it.
+    async fn require_security_with_agent(
+        &self,
+        level: SecurityLevel,
+        agent: Arc<dyn PairingAgent>,
+    ) -> Result<()> {
+        if level == SecurityLevel::None || self.security_level().await? >= level {
             return Ok(());
         }
-        Err(Error::NotConnected)
+        if pairing::pair(self.shared.address, agent, level).await? {
+            self.send_peripheral_event(PeripheralEvent::PairingStateChanged(true));
+        }
+        check_security_level(level, self.security_level().await?)
+    }
+
+    async fn events(&self) -> Result<Pin<Box<dyn Stream<Item = PeripheralEvent> + Send>>> {
+        let manager = self.shared.adapter.upgrade().ok_or(Error::DeviceNotFound)?;
+        Ok(manager
+            .peripheral_events()
+            .subscribe(self.shared.address.into()))
+    }
+
+    /// Discovers all characteristics for the device. This is a synchronous operation.
+    async fn link_events(
+        &self,
+        interval: Duration,
+    ) -> Result<Pin<Box<dyn Stream<Item = LinkSample> + Send>>> {
+        Ok(link::samples(
+            self.clone(),
+            self.shared.operation_context.clone(),
+            interval,
+        ))
+    }
+
+    async fn discover_services(&self) -> Result<()> {
+        self.shared
+            .operation_context
+            .run("discover services", async {
+                self.discover_services_matching(None).await
+            })
+            .await?;
+        self.send_peripheral_event(PeripheralEvent::ServicesResolved);
+        Ok(())
+    }
+
+    async fn discover_services_filtered(&self, uuids: &[Uuid]) -> Result<()> {
+        self.shared
+            .operation_context
+            .run("discover services", async {
+                self.discover_services_matching(Some(uuids)).await
+            })
+            .await?;
+        self.send_peripheral_event(PeripheralEvent::ServicesResolved);
+        Ok(())
     }
 
     /// Write some data to the characteristic. Returns an error if the write couldn't be send or (in
@@ -483,109 +871,179 @@ impl ApiPeripheral for Peripheral {
         data: &[u8],
         write_type: WriteType,
     ) -> Result<()> {
-        let ble_service = &*self
-            .shared
-            .ble_services
-            .get(&characteristic.service_uuid)
-            .ok_or_else(|| Error::NotSupported("Service not found for write".into()))?;
-        let ble_characteristic = ble_service
-            .characteristics
-            .get(&characteristic.uuid)
-            .ok_or_else(|| Error::NotSupported("Characteristic not found for write".into()))?;
-        ble_characteristic.write_value(data, write_type).await
+        self.shared
+            .operation_context
+            .run_write("write", characteristic.uuid, data, async {
+                let ble_service = &*self
+                    .shared
+                    .ble_services
+                    .get(&characteristic.service_uuid)
+                    .ok_or_else(|| Error::NotSupported("Service not found for write".into()))?;
+                let ble_characteristic = ble_service
+                    .characteristics
+                    .get(&characteristic.uuid)
+                    .ok_or_else(|| {
+                        Error::NotSupported("Characteristic not found for write".into())
+                    })?;
+                ble_characteristic.write_value(data, write_type).await
+            })
+            .await
     }
 
     /// Enables either notify or indicate (depending on support) for the specified characteristic.
     /// This is a synchronous call.
     async fn subscribe(&self, characteristic: &Characteristic) -> Result<()> {
-        let ble_service = &mut *self
-            .shared
-            .ble_services
-            .get_mut(&characteristic.service_uuid)
-            .ok_or_else(|| Error::NotSupported("Service not found for subscribe".into()))?;
-        let ble_characteristic = ble_service
-            .characteristics
-            .get_mut(&characteristic.uuid)
-            .ok_or_else(|| Error::NotSupported("Characteristic not found for subscribe".into()))?;
-        let notifications_sender = self.shared.notifications_channel.clone();
-        let uuid = characteristic.uuid;
-        ble_characteristic
-            .subscribe(Box::new(move |value| {
-                let notification = ValueNotification { uuid, value };
-                // Note: we ignore send errors here which may happen while there are no
-                // receivers...
-                let _ = notifications_sender.send(notification);
-            }))
+        self.shared
+            .operation_context
+            .run_on("subscribe", characteristic.uuid, async {
+                self.subscribe_characteristic(characteristic, None).await
+            })
+            .await
+    }
+
+    async fn subscribe_with(
+        &self,
+        characteristic: &Characteristic,
+        subscription_type: SubscriptionType,
+    ) -> Result<()> {
+        self.shared
+            .operation_context
+            .run_on("subscribe", characteristic.uuid, async {
+                self.subscribe_characteristic(characteristic, Some(subscription_type))
+                    .await
+            })
+            .await
+    }
+
+    async fn read_client_configuration(
+        &self,
+        characteristic: &Characteristic,
+    ) -> Result<ClientConfiguration> {
+        self.shared
+            .operation_context
+            .run_on(
+                "read descriptor",
+                CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
+                async {
+                    // Windows doesn't allow reading the CCCD as an ordinary descriptor.
+                    let ble_service = self
+                        .shared
+                        .ble_services
+                        .get(&characteristic.service_uuid)
+                        .ok_or_else(|| {
+                            Error::NotSupported("Service not found for CCCD read".into())
+                        })?;
+                    let ble_characteristic = ble_service
+                        .characteristics
+                        .get(&characteristic.uuid)
+                        .ok_or_else(|| {
+                            Error::NotSupported("Characteristic not found for CCCD read".into())
+                        })?;
+                    ble_characteristic.read_client_configuration().await
+                },
+            )
             .await
     }
 
     /// Disables either notify or indicate (depending on support) for the specified characteristic.
     /// This is a synchronous call.
     async fn unsubscribe(&self, characteristic: &Characteristic) -> Result<()> {
-        let ble_service = &mut *self
-            .shared
-            .ble_services
-            .get_mut(&characteristic.service_uuid)
-            .ok_or_else(|| Error::NotSupported("Service not found for unsubscribe".into()))?;
-        let ble_characteristic = ble_service
-            .characteristics
-            .get_mut(&characteristic.uuid)
-            .ok_or_else(|| {
-                Error::NotSupported("Characteristic not found for unsubscribe".into())
-            })?;
-        ble_characteristic.unsubscribe().await
+        self.shared
+            .operation_context
+            .run_on("unsubscribe", characteristic.uuid, async {
+                let ble_service = &mut *self
+                    .shared
+                    .ble_services
+                    .get_mut(&characteristic.service_uuid)
+                    .ok_or_else(|| {
+                        Error::NotSupported("Service not found for unsubscribe".into())
+                    })?;
+                let ble_characteristic = ble_service
+                    .characteristics
+                    .get_mut(&characteristic.uuid)
+                    .ok_or_else(|| {
+                        Error::NotSupported("Characteristic not found for unsubscribe".into())
+                    })?;
+                ble_characteristic.unsubscribe().await
+            })
+            .await
     }
 
     async fn read(&self, characteristic: &Characteristic) -> Result<Vec<u8>> {
-        let ble_service = &*self
-            .shared
-            .ble_services
-            .get(&characteristic.service_uuid)
-            .ok_or_else(|| Error::NotSupported("Service not found for read".into()))?;
-        let ble_characteristic = ble_service
-            .characteristics
-            .get(&characteristic.uuid)
-            .ok_or_else(|| Error::NotSupported("Characteristic not found for read".into()))?;
-        ble_characteristic.read_value().await
+        self.shared
+            .operation_context
+            .run_read("read", characteristic.uuid, async {
+                let ble_service = &*self
+                    .shared
+                    .ble_services
+                    .get(&characteristic.service_uuid)
+                    .ok_or_else(|| Error::NotSupported("Service not found for read".into()))?;
+                let ble_characteristic = ble_service
+                    .characteristics
+                    .get(&characteristic.uuid)
+                    .ok_or_else(|| {
+                        Error::NotSupported("Characteristic not found for read".into())
+                    })?;
+                ble_characteristic.read_value().await
+            })
+            .await
     }
 
     async fn notifications(&self) -> Result<Pin<Box<dyn Stream<Item = ValueNotification> + Send>>> {
         let receiver = self.shared.notifications_channel.subscribe();
-        Ok(notifications_stream_from_broadcast_receiver(receiver))
+        Ok(self.shared.operation_context.notifications_stream(receiver))
     }
 
     async fn write_descriptor(&self, descriptor: &Descriptor, data: &[u8]) -> Result<()> {
-        let ble_service = &*self
-            .shared
-            .ble_services
-            .get(&descriptor.service_uuid)
-            .ok_or_else(|| Error::NotSupported("Service not found for write".into()))?;
-        let ble_characteristic = ble_service
-            .characteristics
-            .get(&descriptor.characteristic_uuid)
-            .ok_or_else(|| Error::NotSupported("Characteristic not found for write".into()))?;
-        let ble_descriptor = ble_characteristic
-            .descriptors
-            .get(&descriptor.uuid)
-            .ok_or_else(|| Error::NotSupported("Descriptor not found for write".into()))?;
-        ble_descriptor.write_value(data).await
+        self.shared
+            .operation_context
+            .run_write("write descriptor", descriptor.uuid, data, async {
+                let ble_service = &*self
+                    .shared
+                    .ble_services
+                    .get(&descriptor.service_uuid)
+                    .ok_or_else(|| Error::NotSupported("Service not found for write".into()))?;
+                let ble_characteristic = ble_service
+                    .characteristics
+                    .get(&descriptor.characteristic_uuid)
+                    .ok_or_else(|| {
+                        Error::NotSupported("Characteristic not found for write".into())
+                    })?;
+                let ble_descriptor = ble_characteristic
+                    .descriptors
+                    .get(&descriptor.uuid)
+                    .ok_or_else(|| Error::NotSupported("Descriptor not found for write".into()))?;
+                ble_descriptor.write_value(data).await?;
+                self.shared.descriptor_cache.insert(descriptor, data);
+                Ok(())
+            })
+            .await
     }
 
     async fn read_descriptor(&self, descriptor: &Descriptor) -> Result<Vec<u8>> {
-        let ble_service = &*self
-            .shared
-            .ble_services
-            .get(&descriptor.service_uuid)
-            .ok_or_else(|| Error::NotSupported("Service not found for read".into()))?;
-        let ble_characteristic = ble_service
-            .characteristics
-            .get(&descriptor.characteristic_uuid)
-            .ok_or_else(|| Error::NotSupported("Characteristic not found for read".into()))?;
-        let ble_descriptor = ble_characteristic
-            .descriptors
-            .get(&descriptor.uuid)
-            .ok_or_else(|| Error::NotSupported("Descriptor not found for write".into()))?;
-        ble_descriptor.read_value().await
+        self.shared
+            .operation_context
+            .run_read("read descriptor", descriptor.uuid, async {
+                let ble_service = &*self
+                    .shared
+                    .ble_services
+                    .get(&descriptor.service_uuid)
+                    .ok_or_else(|| Error::NotSupported("Service not found for read".into()))?;
+                let ble_characteristic = ble_service
+                    .characteristics
+                    .get(&descriptor.characteristic_uuid)
+                    .ok_or_else(|| {
+                        Error::NotSupported("Characteristic not found for read".into())
+                    })?;
+                let ble_descriptor = ble_characteristic
+                    .descriptors
+                    .get(&descriptor.uuid)
+                    .ok_or_else(|| Error::NotSupported("Descriptor not found for write".into()))?;
+                let value = ble_descriptor.read_value().await?;
+                self.shared.descriptor_cache.insert(descriptor, &value);
+                Ok(value)
+            })
+            .await
     }
 }
 
@@ -11,7 +11,11 @@
 //
 // Copyright (c) 2014 The Rust Project Developers
 
-use crate::{api::CharPropFlags, Error, Result};
+use crate::{
+    api::{CharPropFlags, NativeError},
+    Error, Result,
+};
+use log::debug;
 use std::str::FromStr;
 use uuid::Uuid;
 use windows::core::GUID;
@@ -20,7 +24,8 @@ use windows::{
         GattCharacteristicProperties, GattClientCharacteristicConfigurationDescriptorValue,
         GattCommunicationStatus,
     },
-    Storage::Streams::{DataReader, IBuffer},
+    Foundation::IReference,
+    Storage::Streams::{DataReader, DataWriter, IBuffer},
 };
 
 pub fn to_error(status: GattCommunicationStatus) -> Result<()> {
@@ -37,6 +42,31 @@ pub fn to_error(status: GattCommunicationStatus) -> Result<()> {
     }
 }
 
+/// Converts the status of a GATT operation to a result, using the ATT error the device returned if
+/// there was a protocol error.
+pub fn to_gatt_result(
+    operation: &str,
+    status: GattCommunicationStatus,
+    protocol_error: windows::core::Result<IReference<u8>>,
+) -> Result<()> {
+    if status == GattCommunicationStatus::Success {
+        return Ok(());
+    }
+    let protocol_error = protocol_error.and_then(|error| error.Value()).ok();
+    if status == GattCommunicationStatus::ProtocolError {
+        if let Some(code) = protocol_error {
+            return Err(Error::Att(code.into()));
+        }
+    }
+    debug!("Windows UWP threw error on {}: {:?}", operation, status);
+    Err(Error::Other(Box::new(
+        NativeError::GattCommunicationStatus {
+            status: status.0,
+            protocol_error,
+        },
+    )))
+}
+
 pub fn to_descriptor_value(
     properties: GattCharacteristicProperties,
 ) -> GattClientCharacteristicConfigurationDescriptorValue {
@@ -64,7 +94,12 @@ pub fn to_vec(buffer: &IBuffer) -> Vec<u8> {
     data
 }
 
-#[allow(dead_code)]
+pub fn to_buffer(data: &[u8]) -> windows::core::Result<IBuffer> {
+    let writer = DataWriter::new()?;
+    writer.WriteBytes(data)?;
+    writer.DetachBuffer()
+}
+
 pub fn to_guid(uuid: &Uuid) -> GUID {
     let (data1, data2, data3, data4) = uuid.as_fields();
     GUID::from_values(data1, data2, data3, data4.to_owned())